@@ -0,0 +1,136 @@
+//! Attaching to the terminal of a run started with `--tty`
+//!
+//! The local terminal is put in raw mode, so every keystroke, Ctrl+C included,
+//! goes to the process; Ctrl+] detaches and leaves it running. Resizes are
+//! passed on as they happen, and the terminal is restored however the
+//! session ends.
+
+use crate::Subscription;
+use anyhow::Result;
+use base64::Engine;
+use proj_common::{IpcRequest, IpcResponse, ProcessInfo, TerminalSize};
+use ratatui::crossterm::terminal;
+use std::io::{IsTerminal, Read, Write};
+
+/// Ctrl+], as in telnet
+const DETACH_KEY: u8 = 0x1d;
+
+/// How an attached session ended
+pub enum Ended {
+    /// The process exited, with its code unless a signal killed it
+    Exited(Option<i32>),
+    /// The user detached; the process keeps running
+    Detached,
+}
+
+/// Size of this terminal
+pub fn size() -> TerminalSize {
+    terminal::size()
+        .map(|(cols, rows)| TerminalSize { cols, rows })
+        .unwrap_or_default()
+}
+
+/// Fail unless stdin and stdout are a terminal to attach from
+pub fn check_terminal() -> Result<()> {
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        anyhow::bail!("A terminal run needs an interactive terminal to attach from");
+    }
+    Ok(())
+}
+
+/// Raw mode for as long as it's held
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Connect this terminal to a process's until it exits or the user detaches
+pub async fn attach(process: &ProcessInfo) -> Result<Ended> {
+    let mut session = Subscription::open(IpcRequest::AttachTty {
+        process_id: process.id,
+        size: size(),
+    })
+    .await?;
+    let _raw = RawMode::enable()?;
+
+    // Reads block, so keys come from a thread of their own
+    let (keys_tx, mut keys) = tokio::sync::mpsc::channel::<Vec<u8>>(16);
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin();
+        let mut buf = [0; 1024];
+        loop {
+            match stdin.read(&mut buf) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => {
+                    if keys_tx.blocking_send(buf[..n].to_vec()).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+    let mut resizes = Resizes::new()?;
+    let mut stdout = std::io::stdout();
+    let base64 = base64::engine::general_purpose::STANDARD;
+
+    loop {
+        tokio::select! {
+            response = session.next() => match response? {
+                Some(IpcResponse::TtyOutput { data }) => {
+                    stdout.write_all(&base64.decode(data)?)?;
+                    stdout.flush()?;
+                }
+                Some(IpcResponse::TtyExited { exit_code }) => return Ok(Ended::Exited(exit_code)),
+                Some(_) => {}
+                None => anyhow::bail!("Daemon closed the connection"),
+            },
+            Some(bytes) = keys.recv() => {
+                let detach = bytes.iter().position(|&b| b == DETACH_KEY);
+                let typed = &bytes[..detach.unwrap_or(bytes.len())];
+                if !typed.is_empty() {
+                    let data = base64.encode(typed);
+                    session.send(&IpcRequest::TtyInput { data }).await?;
+                }
+                if detach.is_some() {
+                    return Ok(Ended::Detached);
+                }
+            }
+            () = resizes.next() => {
+                session.send(&IpcRequest::TtyResize { size: size() }).await?;
+            }
+        }
+    }
+}
+
+/// Resizes of this terminal: SIGWINCH on Unix. Windows consoles keep the
+/// size they attached with.
+struct Resizes {
+    #[cfg(unix)]
+    signal: tokio::signal::unix::Signal,
+}
+
+impl Resizes {
+    fn new() -> Result<Self> {
+        Ok(Self {
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::window_change())?,
+        })
+    }
+
+    async fn next(&mut self) {
+        #[cfg(unix)]
+        self.signal.recv().await;
+        #[cfg(not(unix))]
+        std::future::pending::<()>().await;
+    }
+}
@@ -0,0 +1,547 @@
+//! Browsers `proj open` launches with a project's isolated profile
+//!
+//! Each browser keeps its profile in its own directory under
+//! `~/.proj/projects/<name>/`, since their profile formats don't mix. A custom
+//! browser is a command template: `{url}` and `{profile}` are replaced in each
+//! word, and the URL is appended if the template doesn't place it. Words are
+//! split on whitespace; there is no shell quoting.
+//!
+//! When the browser can't be started, the URL goes to the system's default
+//! browser instead, which has no profile of the project's own.
+//!
+//! Before a profile is reset or its project deleted, a browser still running
+//! with it is found by its lock files and command line, and closed (or
+//! force-quit if it hangs) so it isn't left writing to a profile that's gone.
+
+use anyhow::{Context, Result};
+use proj_common::{project_dir, Capability, CapabilityStatus};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Names accepted for the `browser` setting besides custom commands
+pub const NAMES: &[&str] = &["chrome", "chromium", "firefox", "brave", "edge"];
+
+/// How a URL was opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opened {
+    /// In the chosen browser, with the project's profile
+    Isolated,
+    /// In the system's default browser, sharing its cookies and storage,
+    /// because the chosen one couldn't be started
+    Default,
+}
+
+/// One way to start a browser
+struct Launch {
+    command: Vec<String>,
+    /// The program hands off to the browser and exits, failing if it can't
+    /// find it, so its exit status tells whether the browser started
+    waits: bool,
+}
+
+impl Launch {
+    fn spawn(words: Vec<String>) -> Self {
+        Self {
+            command: words,
+            waits: false,
+        }
+    }
+
+    /// Whether the browser started
+    fn run(&self) -> bool {
+        let Some((program, args)) = self.command.split_first() else {
+            return false;
+        };
+        let mut command = std::process::Command::new(program);
+        command.args(args);
+        if self.waits {
+            command
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null());
+            command.status().is_ok_and(|status| status.success())
+        } else {
+            command.spawn().is_ok()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Browser {
+    Chrome,
+    Chromium,
+    Firefox,
+    Brave,
+    Edge,
+    /// Command template
+    Custom(String),
+}
+
+impl std::str::FromStr for Browser {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.trim() {
+            "" => anyhow::bail!("Empty browser (expected {} or a command)", NAMES.join(", ")),
+            "chrome" => Self::Chrome,
+            "chromium" => Self::Chromium,
+            "firefox" => Self::Firefox,
+            "brave" => Self::Brave,
+            "edge" => Self::Edge,
+            command => Self::Custom(command.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for Browser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Chrome => write!(f, "Chrome"),
+            Self::Chromium => write!(f, "Chromium"),
+            Self::Firefox => write!(f, "Firefox"),
+            Self::Brave => write!(f, "Brave"),
+            Self::Edge => write!(f, "Edge"),
+            Self::Custom(command) => {
+                write!(
+                    f,
+                    "{}",
+                    command.split_whitespace().next().unwrap_or_default()
+                )
+            }
+        }
+    }
+}
+
+impl Browser {
+    /// Directory under the project's data directory holding this browser's
+    /// profile. Chrome's is `chrome`, where every profile used to live.
+    fn profile_name(&self) -> &'static str {
+        match self {
+            Self::Chrome => "chrome",
+            Self::Chromium => "chromium",
+            Self::Firefox => "firefox",
+            Self::Brave => "brave",
+            Self::Edge => "edge",
+            Self::Custom(_) => "custom",
+        }
+    }
+
+    /// Isolated profile directory of a project for this browser
+    pub fn profile_dir(&self, project_name: &str) -> Result<PathBuf> {
+        Ok(project_dir(project_name)?.join(self.profile_name()))
+    }
+
+    /// Name of the browser's application bundle on macOS
+    #[cfg(target_os = "macos")]
+    fn app_name(&self) -> &'static str {
+        match self {
+            Self::Chrome => "Google Chrome",
+            Self::Chromium => "Chromium",
+            Self::Firefox => "Firefox",
+            Self::Brave => "Brave Browser",
+            Self::Edge => "Microsoft Edge",
+            Self::Custom(_) => unreachable!(),
+        }
+    }
+
+    /// Where the browser is installed, if `open` can find it
+    fn installed(&self) -> Option<PathBuf> {
+        #[cfg(target_os = "macos")]
+        if !matches!(self, Self::Custom(_)) {
+            let bundle = format!("{}.app", self.app_name());
+            let home = dirs::home_dir().unwrap_or_default();
+            return [Path::new("/Applications"), &home.join("Applications")]
+                .iter()
+                .map(|dir| dir.join(&bundle))
+                .find(|app| app.is_dir());
+        }
+        self.commands("", Path::new(""))
+            .iter()
+            .filter_map(|launch| launch.command.first())
+            .find_map(|program| locate(program))
+    }
+
+    /// Whether `open` can start the browser here, for `proj doctor`
+    pub fn capability(&self) -> Capability {
+        const NAME: &str = "browser launch";
+        match self.installed() {
+            Some(path) => Capability::new(
+                NAME,
+                CapabilityStatus::Ok,
+                format!("{} at {}, with a profile per project", self, path.display()),
+            ),
+            None if default_available() => Capability::new(
+                NAME,
+                CapabilityStatus::Degraded,
+                format!(
+                    "{} not found; `open` uses the default browser, without a profile per project",
+                    self
+                ),
+            ),
+            None => Capability::new(
+                NAME,
+                CapabilityStatus::Unavailable,
+                format!("{} not found, and no default browser to open instead", self),
+            ),
+        }
+    }
+
+    /// Arguments that open `url` in `profile`, after the executable
+    fn args(&self, url: &str, profile: &Path) -> Vec<String> {
+        match self {
+            Self::Firefox => vec![
+                "-profile".to_string(),
+                profile.display().to_string(),
+                "-no-remote".to_string(),
+                url.to_string(),
+            ],
+            _ => vec![
+                format!("--user-data-dir={}", profile.display()),
+                url.to_string(),
+            ],
+        }
+    }
+
+    /// Commands to try in order until one starts
+    fn commands(&self, url: &str, profile: &Path) -> Vec<Launch> {
+        if let Self::Custom(template) = self {
+            let profile = profile.display().to_string();
+            let mut words: Vec<String> = template
+                .split_whitespace()
+                .map(|word| word.replace("{url}", url).replace("{profile}", &profile))
+                .collect();
+            if !template.contains("{url}") {
+                words.push(url.to_string());
+            }
+            return vec![Launch::spawn(words)];
+        }
+
+        let args = self.args(url, profile);
+
+        #[cfg(target_os = "macos")]
+        let commands = {
+            let app = self.app_name();
+            let mut command: Vec<String> =
+                ["open", "-na", app, "--args"].map(str::to_string).to_vec();
+            command.extend(args);
+            vec![Launch {
+                command,
+                waits: true,
+            }]
+        };
+
+        #[cfg(windows)]
+        let commands = {
+            // Where installers put them, per machine or per user, then the
+            // PATH. `start` would find them too, but shows a dialog when it
+            // can't and succeeds either way.
+            let (dir, exe) = match self {
+                Self::Chrome => (r"Google\Chrome\Application", "chrome.exe"),
+                Self::Chromium => (r"Chromium\Application", "chrome.exe"),
+                Self::Firefox => ("Mozilla Firefox", "firefox.exe"),
+                Self::Brave => (r"BraveSoftware\Brave-Browser\Application", "brave.exe"),
+                Self::Edge => (r"Microsoft\Edge\Application", "msedge.exe"),
+                Self::Custom(_) => unreachable!(),
+            };
+            let installed = ["ProgramFiles", "ProgramFiles(x86)", "LOCALAPPDATA"]
+                .iter()
+                .filter_map(std::env::var_os)
+                .map(|base| PathBuf::from(base).join(dir).join(exe))
+                .filter(|path| path.is_file())
+                .map(|path| path.display().to_string());
+            installed
+                .chain(std::iter::once(exe.to_string()))
+                .map(|program| {
+                    let mut command = vec![program];
+                    command.extend(args.iter().cloned());
+                    Launch::spawn(command)
+                })
+                .collect()
+        };
+
+        #[cfg(not(any(target_os = "macos", windows)))]
+        let commands = {
+            // Executable names differ between distributions
+            let executables: &[&str] = match self {
+                Self::Chrome => &["google-chrome", "chromium", "chromium-browser"],
+                Self::Chromium => &["chromium", "chromium-browser"],
+                Self::Firefox => &["firefox"],
+                Self::Brave => &["brave-browser", "brave"],
+                Self::Edge => &["microsoft-edge", "microsoft-edge-stable"],
+                Self::Custom(_) => unreachable!(),
+            };
+            executables
+                .iter()
+                .map(|exe| {
+                    let mut command = vec![exe.to_string()];
+                    command.extend(args.iter().cloned());
+                    Launch::spawn(command)
+                })
+                .collect()
+        };
+
+        commands
+    }
+
+    /// Launch the browser on `url` with the project's isolated profile, or
+    /// failing that the default browser
+    pub fn open(&self, project_name: &str, url: &str) -> Result<Opened> {
+        let profile = self.profile_dir(project_name)?;
+        std::fs::create_dir_all(&profile)
+            .with_context(|| format!("Failed to create {}", profile.display()))?;
+
+        if self.commands(url, &profile).iter().any(Launch::run) {
+            return Ok(Opened::Isolated);
+        }
+        if default_commands(url).iter().any(Launch::run) {
+            return Ok(Opened::Default);
+        }
+        anyhow::bail!(
+            "Failed to open {}, or a default browser. Is it installed?",
+            self
+        )
+    }
+
+    /// What to tell the user when `open` fell back to the default browser
+    pub fn fallback_notice(&self) -> String {
+        format!(
+            "{0} couldn't be started, so this opened in your default browser, which shares cookies and logins with the rest of your browsing. Install {0} or set `browser` to one you have for an isolated profile",
+            self
+        )
+    }
+}
+
+/// Where `program` is: a path as given, or a name's first match on the PATH
+fn locate(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    let exe = match path.extension() {
+        Some(_) => program.to_string(),
+        None => format!("{}{}", program, std::env::consts::EXE_SUFFIX),
+    };
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(&exe))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Whether there's a default browser to fall back to. macOS and Windows
+/// always have a handler for URLs.
+fn default_available() -> bool {
+    cfg!(any(target_os = "macos", windows))
+        || default_commands("")
+            .iter()
+            .filter_map(|launch| launch.command.first())
+            .any(|program| locate(program).is_some())
+}
+
+/// Commands that open `url` in the system's default browser
+fn default_commands(url: &str) -> Vec<Launch> {
+    let url = url.to_string();
+
+    #[cfg(target_os = "macos")]
+    let commands = vec![Launch {
+        command: vec!["open".to_string(), url],
+        waits: true,
+    }];
+
+    // Not `start`, which would need the URL quoted for cmd
+    #[cfg(windows)]
+    let commands = vec![Launch {
+        command: vec![
+            "rundll32".to_string(),
+            "url.dll,FileProtocolHandler".to_string(),
+            url,
+        ],
+        waits: true,
+    }];
+
+    // xdg-open may run the browser itself rather than hand off, so it isn't
+    // waited for
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let commands = vec![
+        Launch::spawn(vec!["xdg-open".to_string(), url.clone()]),
+        Launch {
+            command: vec!["gio".to_string(), "open".to_string(), url],
+            waits: true,
+        },
+    ];
+
+    commands
+}
+
+/// Files browsers create in a profile directory while they have it open:
+/// Chromium's (`SingletonLock` a symlink to `<host>-<pid>`, `lockfile` on
+/// Windows) and Firefox's (`lock` a symlink to `<ip>:+<pid>` on Linux,
+/// `.parentlock` on macOS, `parent.lock` on Windows)
+const PROFILE_LOCKS: &[&str] = &[
+    "SingletonLock",
+    "SingletonSocket",
+    "SingletonCookie",
+    "lockfile",
+    "lock",
+    ".parentlock",
+    "parent.lock",
+];
+
+/// How long a browser asked to close gets before it counts as hung
+pub const CLOSE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How long a force-quit browser gets to go away
+const KILL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// A project's browser profiles that exist, with the browser each is for
+pub fn profiles(project_name: &str) -> Result<Vec<(String, PathBuf)>> {
+    let dir = project_dir(project_name)?;
+    let mut found = Vec::new();
+    for name in NAMES.iter().copied().chain(["custom"]) {
+        let browser = name.parse::<Browser>()?;
+        let profile = dir.join(browser.profile_name());
+        if profile.is_dir() {
+            let label = match browser {
+                Browser::Custom(_) => "custom browser".to_string(),
+                browser => browser.to_string(),
+            };
+            found.push((label, profile));
+        }
+    }
+    Ok(found)
+}
+
+/// Processes of a browser running with `profile`: any whose command line has
+/// the profile's path, and the one its lock file names if the lock was taken
+/// on this machine. A lock left behind by a browser that crashed can name a
+/// PID that now belongs to something else, so only a lock from this host is
+/// believed without the command line to back it.
+pub fn profile_users(profile: &Path) -> Vec<u32> {
+    let mut pids = processes_mentioning(profile);
+    let host = this_host();
+    let locked = ["SingletonLock", "lock"]
+        .iter()
+        .filter_map(|lock| std::fs::read_link(profile.join(lock)).ok())
+        .filter_map(|target| lock_owner(&target.to_string_lossy()))
+        .filter(|(lock_host, pid)| {
+            pids.contains(pid) || (host.as_deref() == Some(lock_host.as_str()) && is_running(*pid))
+        })
+        .map(|(_, pid)| pid)
+        .collect::<Vec<_>>();
+    pids.extend(locked);
+    pids.sort_unstable();
+    pids.dedup();
+    pids
+}
+
+/// The host and PID a lock symlink's target names: `<host>-<pid>` for
+/// Chromium's `SingletonLock`, `<address>:+<pid>` for Firefox's `lock`
+fn lock_owner(target: &str) -> Option<(String, u32)> {
+    let (host, pid) = target.rsplit_once(['-', '+'])?;
+    Some((host.to_string(), pid.parse().ok()?))
+}
+
+/// This machine's host name, as Chromium writes it into its lock
+fn this_host() -> Option<String> {
+    let output = std::process::Command::new("hostname")
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    let host = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !host.is_empty()).then_some(host)
+}
+
+/// Whether a process is running, as far as `kill -0` can tell
+fn is_running(pid: u32) -> bool {
+    if cfg!(windows) {
+        return false;
+    }
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Processes whose command line has `path` as a whole path
+fn processes_mentioning(path: &Path) -> Vec<u32> {
+    let path = path.display().to_string();
+    let listing = if cfg!(windows) {
+        std::process::Command::new("powershell")
+            .args([
+                "-NoProfile",
+                "-Command",
+                "Get-CimInstance Win32_Process | ForEach-Object { \"$($_.ProcessId) $($_.CommandLine)\" }",
+            ])
+            .output()
+    } else {
+        std::process::Command::new("ps")
+            .args(["-A", "-ww", "-o", "pid=,args="])
+            .output()
+    };
+    let Ok(listing) = listing else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&listing.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (pid, args) = line.trim_start().split_once(' ')?;
+            if !mentions(args, &path) {
+                return None;
+            }
+            pid.parse().ok()
+        })
+        .filter(|pid| *pid != std::process::id())
+        .collect()
+}
+
+/// Whether `path` appears in `args` not as the start of a longer name, so
+/// `.../chrome` isn't found in `.../chromium`
+fn mentions(args: &str, path: &str) -> bool {
+    args.match_indices(path).any(|(start, _)| {
+        args[start + path.len()..]
+            .chars()
+            .next()
+            .is_none_or(|next| next.is_whitespace() || matches!(next, '/' | '\\' | '"' | '\''))
+    })
+}
+
+/// Ask the browser processes to quit, or with `force` kill them, and wait for
+/// `profile` to be let go of; returns whether it was. `pids` should come from
+/// [`profile_users`], which only names processes known to use the profile.
+pub async fn close(profile: &Path, pids: &[u32], force: bool) -> bool {
+    for pid in pids {
+        let pid = pid.to_string();
+        let mut command = if cfg!(windows) {
+            let mut command = std::process::Command::new("taskkill");
+            if force {
+                command.arg("/F");
+            }
+            command.args(["/PID", &pid]);
+            command
+        } else {
+            let mut command = std::process::Command::new("kill");
+            command.args([if force { "-KILL" } else { "-TERM" }, &pid]);
+            command
+        };
+        // One of a browser's processes may end with another
+        let _ = command
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status();
+    }
+
+    let timeout = if force { KILL_TIMEOUT } else { CLOSE_TIMEOUT };
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !profile_users(profile).is_empty() {
+        if tokio::time::Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    }
+    if force {
+        // A killed browser leaves its locks behind, and they would make the
+        // next one started on a restored profile think it's still open
+        for lock in PROFILE_LOCKS {
+            let _ = std::fs::remove_file(profile.join(lock));
+        }
+    }
+    true
+}
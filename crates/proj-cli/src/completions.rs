@@ -0,0 +1,173 @@
+//! Shell completions for `proj completions <shell>`
+//!
+//! The static part comes from clap. Project names can't be known up front, so
+//! each script also asks `proj __complete-projects` for them at completion
+//! time, and offers project actions after a project name.
+
+use anyhow::Result;
+use clap_complete::Shell;
+use proj_common::store::Store;
+use proj_common::transport::{Ipc, Transport};
+use proj_common::{socket_path, tr, IpcRequest, IpcResponse, ProjectFilter};
+use std::io::Write;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::time::{timeout, Duration};
+
+/// How long completion waits for the daemon before reading the project store
+/// instead
+const DAEMON_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// Actions offered after `proj <name>`, and suggested for a command that
+/// looks like a typo of one
+pub const PROJECT_ACTIONS: &[&str] = &[
+    "run",
+    "open",
+    "profile",
+    "stop",
+    "restart",
+    "info",
+    "up",
+    "down",
+    "ps",
+    "primary",
+    "history",
+    "rerun",
+    "logs",
+    "stats",
+    "crash",
+    "watch",
+    "env",
+    "service",
+    "debug",
+    "deps",
+    "renice",
+    "sandbox",
+    "hooks",
+    "copy-config",
+    "queue",
+    "schedule",
+    "requests",
+    "route",
+    "override",
+    "rewrite",
+    "auth",
+    "alias",
+    "tag",
+    "graph",
+    "as",
+    "csp",
+    "cors",
+    "passthrough",
+    "cache",
+    "sw",
+    "host-header",
+    "redirects",
+    "trailing-slash",
+    "upstream",
+    "attach",
+    "idle",
+    "lazy",
+    "icon",
+    "color",
+    "max-in-flight",
+    "adopt",
+    "api-drift",
+    "budget",
+    "gql",
+    "assets",
+    "smoke",
+];
+
+/// Write the completion script for `shell` to stdout
+pub fn print(shell: Shell, command: &mut clap::Command) -> Result<()> {
+    let mut stdout = std::io::stdout();
+    clap_complete::generate(shell, command, "proj", &mut stdout);
+
+    let actions = PROJECT_ACTIONS.join(" ");
+    let dynamic = match shell {
+        Shell::Bash => format!(
+            r#"
+_proj_with_projects() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    if [[ ${{COMP_CWORD}} -eq 1 ]]; then
+        _proj "$@"
+        COMPREPLY+=( $(compgen -W "$(proj __complete-projects 2>/dev/null)" -- "${{cur}}") )
+    elif [[ ${{COMP_CWORD}} -eq 2 ]] && proj __complete-projects 2>/dev/null | grep -qxF -- "${{COMP_WORDS[1]}}"; then
+        COMPREPLY=( $(compgen -W "{actions}" -- "${{cur}}") )
+    else
+        _proj "$@"
+    fi
+}}
+complete -F _proj_with_projects -o bashdefault -o default proj
+"#
+        ),
+        Shell::Zsh => format!(
+            r#"
+_proj_with_projects() {{
+    local -a projects
+    projects=(${{(f)"$(proj __complete-projects 2>/dev/null)"}})
+    if (( CURRENT == 3 )) && (( ${{projects[(Ie)$words[2]]}} )); then
+        local -a actions
+        actions=({actions})
+        _describe 'action' actions
+        return
+    fi
+    if (( CURRENT == 2 )); then
+        _describe 'project' projects
+    fi
+    _proj "$@"
+}}
+compdef _proj_with_projects proj
+"#
+        ),
+        Shell::Fish => format!(
+            r#"
+function __proj_after_project
+    set -l tokens (commandline -opc)
+    test (count $tokens) -eq 2; and contains -- $tokens[2] (proj __complete-projects 2>/dev/null)
+end
+complete -c proj -n __fish_use_subcommand -f -a "(proj __complete-projects 2>/dev/null)" -d project
+complete -c proj -n __proj_after_project -f -a "{actions}"
+"#
+        ),
+        _ => String::new(),
+    };
+    stdout.write_all(dynamic.as_bytes())?;
+    Ok(())
+}
+
+/// Print every project name, one per line. Asks a running daemon, but never
+/// starts one; without it, reads the project store.
+pub async fn print_projects() -> Result<()> {
+    let mut names = match timeout(DAEMON_TIMEOUT, names_from_daemon()).await {
+        Ok(Ok(names)) => names,
+        _ => names_from_store()?,
+    };
+    names.sort();
+    for name in names {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+async fn names_from_daemon() -> Result<Vec<String>> {
+    let stream = Ipc::connect(&socket_path()?).await?;
+    let (reader, mut writer) = tokio::io::split(stream);
+    let json = serde_json::to_string(&IpcRequest::ListProjects {
+        filter: ProjectFilter::default(),
+    })?;
+    writer.write_all(json.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+    match serde_json::from_str(&line)? {
+        IpcResponse::Projects(projects) => Ok(projects.into_iter().map(|p| p.name).collect()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    }
+}
+
+fn names_from_store() -> Result<Vec<String>> {
+    let projects = Store::open_default()?.projects()?;
+    Ok(projects.into_iter().map(|(name, _)| name).collect())
+}
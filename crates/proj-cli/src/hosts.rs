@@ -0,0 +1,123 @@
+//! Entries proj manages in the system hosts file (`alias add --write-hosts`)
+//!
+//! Aliases outside `.localhost` don't resolve to this machine on their own, so
+//! each gets an `<address> <alias>` line tagged with a trailing `# proj`
+//! comment; only tagged lines are ever changed or removed. The file is
+//! usually only writable by root, in which case the new contents are written
+//! through `sudo tee`, which asks for a password.
+
+use anyhow::{Context, Result};
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+/// Comment marking the lines proj wrote
+const MARKER: &str = "# proj";
+
+/// The system hosts file
+fn hosts_path() -> PathBuf {
+    #[cfg(windows)]
+    {
+        let root = std::env::var_os("SystemRoot").unwrap_or_else(|| "C:\\Windows".into());
+        PathBuf::from(root).join("System32\\drivers\\etc\\hosts")
+    }
+    #[cfg(not(windows))]
+    {
+        PathBuf::from("/etc/hosts")
+    }
+}
+
+/// The host a line proj wrote is for
+fn managed_host(line: &str) -> Option<&str> {
+    let entry = line.trim_end().strip_suffix(MARKER)?;
+    let mut fields = entry.split_whitespace();
+    let _address = fields.next()?;
+    let host = fields.next()?;
+    fields.next().is_none().then_some(host)
+}
+
+/// Point `hosts` at `address`, returning those that weren't already
+pub fn add(hosts: &[String], address: IpAddr) -> Result<Vec<String>> {
+    let path = hosts_path();
+    let contents = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+    let mut added = Vec::new();
+    for host in hosts {
+        let line = format!("{} {} {}", address, host, MARKER);
+        match lines.iter_mut().find(|l| managed_host(l) == Some(host)) {
+            Some(existing) if *existing == line => continue,
+            Some(existing) => *existing = line,
+            None => lines.push(line),
+        }
+        added.push(host.clone());
+    }
+    if !added.is_empty() {
+        write(&path, &lines)?;
+    }
+    Ok(added)
+}
+
+/// Remove the lines proj wrote for `hosts`, returning those it had
+pub fn remove(hosts: &[String]) -> Result<Vec<String>> {
+    let path = hosts_path();
+    let contents = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let mut removed = Vec::new();
+    let lines: Vec<String> = contents
+        .lines()
+        .filter(|line| match managed_host(line) {
+            Some(host) if hosts.iter().any(|h| h == host) => {
+                removed.push(host.to_string());
+                false
+            }
+            _ => true,
+        })
+        .map(str::to_string)
+        .collect();
+    if !removed.is_empty() {
+        write(&path, &lines)?;
+    }
+    Ok(removed)
+}
+
+fn write(path: &std::path::Path, lines: &[String]) -> Result<()> {
+    let mut contents = lines.join("\n");
+    contents.push('\n');
+
+    match std::fs::write(path, &contents) {
+        Ok(()) => Ok(()),
+        #[cfg(unix)]
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => sudo_write(path, &contents),
+        Err(e) => Err(e).with_context(|| {
+            format!(
+                "Failed to write {} (run as Administrator to change it)",
+                path.display()
+            )
+        }),
+    }
+}
+
+#[cfg(unix)]
+fn sudo_write(path: &std::path::Path, contents: &str) -> Result<()> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    println!("Updating {} needs root; asking sudo", path.display());
+    let mut tee = Command::new("sudo")
+        .arg("tee")
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .context("Failed to run sudo")?;
+    tee.stdin
+        .take()
+        .context("Failed to open sudo's input")?
+        .write_all(contents.as_bytes())?;
+    if !tee.wait()?.success() {
+        anyhow::bail!("Failed to write {}", path.display());
+    }
+    Ok(())
+}
@@ -9,15 +9,50 @@
 //!   proj ls                    - List all projects
 //!   proj                       - Show overview
 
+mod attach;
+mod browser;
+mod completions;
+mod hosts;
+mod plugins;
+mod selftest;
+mod service;
+mod templates;
+mod top;
+
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use browser::{Browser, Opened};
+use chrono::{DateTime, Datelike, Local, NaiveTime, Utc, Weekday};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use proj_common::crash::{self, CrashAction};
+use proj_common::naming::{self, normalize_alias, normalize_tag, sanitize_label};
+use proj_common::notifications::DndMode;
+use proj_common::profile::ProfileKind;
+use proj_common::schema::ProjectFile;
+use proj_common::store::Store;
+use proj_common::transport::{Connection, Ipc, Transport};
+use proj_common::{
+    ca_cert_path, config_path, env_profiles_path, format_size, load_env_profiles, normalize_color,
+    normalize_icon, normalize_override_target, normalize_route_prefix, parse_size, pid_file_path,
+    processes_path, project_dir, projects_dir, socket_path, Appearance, AutoRunVerdict,
+    BudgetMetric, BudgetStatus, Capability, CapabilityStatus, CapturedRequest, Config,
+    DaemonExitPolicy, EnvSource, EventFilters, EventKind, HealthCheck, Hooks, IoClass, IpcError,
+    IpcRequest, IpcResponse, JobStage, LogLine, LogQuery, OnConflict, Persona, PortPolicy,
+    Priority, ProcessInfo, ProcessStatus, Project, ProjectFilter, ProjectSummary, ProxyTraffic,
+    QueueJobStatus, ResourceLimits, RestartPolicy, Rewrite, RunRecord, Sandbox, SandboxMode,
+    Schedule, ScheduleOverride, ScheduleWindow, Service, SmokeCheck, TrailingSlash, UpstreamAuth,
+    WatchOptions, CLEAR_SITE_DATA_PATH, DEFAULT_MAX_RESTARTS, PROTOCOL_VERSION, VERSION,
+};
 use proj_common::{
-    pid_file_path, project_dir, projects_dir, socket_path, validate_project_name, IpcRequest,
-    IpcResponse,
+    debug, deps, dotenv, graph, i18n, layers, lint, migrate, schema, secrets, tr, trash,
 };
+use std::collections::{BTreeMap, VecDeque};
+use std::io::IsTerminal;
 use std::path::PathBuf;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::UnixStream;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
 
 #[derive(Parser)]
 #[command(name = "proj")]
@@ -30,36 +65,298 @@ use tokio::net::UnixStream;
     proj my-app stop             Stop project's processes
     proj my-app                  Show project info
     proj ls                      List all projects with status
+    proj rm my-app               Delete a project
+    proj up --all                Start every project with its last run command
+    proj ports                   What's listening, and on which project's port
+    proj trust                   Trust the local HTTPS certificate authority
     proj                         Show daemon status overview")]
 struct Cli {
+    /// Override a setting for this command, e.g. -c up_concurrency=8 (repeatable)
+    #[arg(short = 'c', long = "config", value_name = "SETTING=VALUE")]
+    config: Vec<String>,
+
+    /// Control the daemon on another machine through its `listen_tcp`
+    /// address, authenticating with `tcp_token` (or set PROJ_HOST)
+    #[arg(long, global = true, value_name = "HOST:PORT")]
+    host: Option<String>,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
+/// `--config` flags given on the command line, the top config layer
+static CONFIG_FLAGS: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Address of the remote daemon requests go to, from `--host` or `PROJ_HOST`
+static REMOTE_HOST: OnceLock<Option<String>> = OnceLock::new();
+
 #[derive(Subcommand)]
 enum Commands {
+    /// Register the repository the current directory is in as a project
+    Init {
+        /// Project name (defaults to the one in proj.toml, then the
+        /// repository's directory name)
+        name: Option<String>,
+    },
+
     /// Create a new project (proj new <name>)
     New {
         /// Project name
         name: String,
-        /// Project root directory (defaults to current directory)
+        /// Project root directory (defaults to current directory, or
+        /// ./<name> with --template)
         #[arg(short, long)]
         dir: Option<PathBuf>,
+        /// Scaffold the directory from a template: vite-react, fastapi, or
+        /// one under ~/.proj/templates/
+        #[arg(short, long)]
+        template: Option<String>,
+    },
+
+    /// Delete a project, stopping its processes (proj rm <name>)
+    #[command(alias = "remove")]
+    Rm {
+        /// Project name
+        name: String,
+        /// Keep the project's data directory (Chrome profile, logs)
+        #[arg(long)]
+        keep_data: bool,
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Restore what the last rm or profile reset deleted, or a given trash entry
+    Undo {
+        /// Entry to restore, from `proj trash ls`
+        id: Option<String>,
+    },
+
+    /// Deleted projects and browser profiles kept for `proj undo` (proj trash [ls|empty])
+    Trash {
+        #[command(subcommand)]
+        action: Option<TrashAction>,
     },
 
     /// List all projects (alias: ls)
     #[command(alias = "ls")]
-    List,
+    List {
+        /// Redraw whenever a project is created, changed, or removed
+        #[arg(short, long)]
+        watch: bool,
+        /// Only projects with this tag (repeat to require several)
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        /// Only projects with a running process
+        #[arg(long)]
+        running: bool,
+        /// Order by name, last-used or created
+        #[arg(long, default_value = "name")]
+        sort: String,
+        /// List projects under each of their tags
+        #[arg(long)]
+        by_tag: bool,
+        /// Fixed columns without colors, e.g. for `watch -n1 proj ls --plain`
+        #[arg(long, conflicts_with = "by_tag")]
+        plain: bool,
+        /// Fail rather than start the daemon when it isn't running
+        #[arg(long)]
+        no_autostart: bool,
+    },
+
+    /// Start projects' services, or the command they were last run with, after
+    /// the projects they depend on (proj up --all)
+    Up {
+        /// Projects or groups (config.json `groups`) to start
+        projects: Vec<String>,
+        /// Start every project that has been run before
+        #[arg(long, conflicts_with = "projects")]
+        all: bool,
+        /// How many projects to start at once (defaults to up_concurrency in config.json)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+    },
+
+    /// Stop projects, each before the projects it depends on (proj down --all)
+    Down {
+        /// Projects or groups (config.json `groups`) to stop
+        projects: Vec<String>,
+        /// Stop every running project
+        #[arg(long, conflicts_with = "projects")]
+        all: bool,
+    },
+
+    /// Copy projects from another machine (proj migrate from ssh://old-laptop)
+    Migrate {
+        #[command(subcommand)]
+        action: MigrateAction,
+    },
+
+    /// Write projects, their stored env and proj.toml to a bundle for another machine
+    Export {
+        /// Projects to export
+        projects: Vec<String>,
+        /// Export every project
+        #[arg(long, conflicts_with = "projects")]
+        all: bool,
+        /// File to write (defaults to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Recreate the projects in a bundle written by `proj export`
+    Import {
+        /// Bundle to read, or - for stdin
+        file: PathBuf,
+        /// Rewrite paths starting with FROM to start with TO (repeatable); the
+        /// exporting machine's home directory maps to this one by default
+        #[arg(long = "map", value_name = "FROM=TO")]
+        maps: Vec<String>,
+        /// Don't ask where projects are or for confirmation
+        #[arg(short = 'y', long)]
+        yes: bool,
+    },
+
+    /// Register a copy of a project under a new name, with its settings,
+    /// stored env and aliases but a browser profile of its own
+    Duplicate {
+        /// Project to copy
+        source: String,
+        /// Name of the copy
+        name: String,
+        /// Root directory of the copy; defaults to the source's, or with
+        /// --copy or --clone to a sibling of it named after the copy
+        #[arg(long)]
+        dir: Option<PathBuf>,
+        /// Copy the source's working directory to the new root
+        #[arg(long, conflicts_with = "clone")]
+        copy: bool,
+        /// Clone the source's git repository into the new root
+        #[arg(long)]
+        clone: bool,
+    },
+
+    /// Bring project files written by older versions up to the current format
+    MigrateData {
+        /// Show what would change without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
 
-    /// Start the background daemon
+    /// Start the background daemon (proj daemon [stop|restart|reload|install|uninstall|status])
     Daemon {
+        #[command(subcommand)]
+        action: Option<DaemonAction>,
         /// Run in foreground (don't daemonize)
         #[arg(short, long)]
         foreground: bool,
     },
 
     /// Show daemon status
-    Status,
+    Status {
+        /// Also show the daemon's health: panics in its tasks within the last hour
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Spawn processes and proxy traffic in bulk, then check the daemon leaked
+    /// nothing (proj selftest --stress)
+    Selftest {
+        /// Hundreds of processes and thousands of requests instead of a quick round
+        #[arg(long)]
+        stress: bool,
+    },
+
+    /// Check what works on this machine: the binaries' builds, process
+    /// control, port detection, sandboxing, resource limits and browser launch
+    Doctor,
+
+    /// Install the local HTTPS certificate authority into the system trust store
+    Trust,
+
+    /// List listening ports and flag conflicts with project ports
+    Ports,
+
+    /// Full-screen dashboard of projects, processes and live output
+    Top,
+
+    /// Stream daemon events as they happen (proj watch [project] --json)
+    Watch {
+        /// Only events for this project
+        project: Option<String>,
+        /// Print raw JSON lines instead of a readable log
+        #[arg(long)]
+        json: bool,
+        /// Only these events (repeatable), e.g. --event port_detected
+        #[arg(long = "event", value_name = "EVENT")]
+        events: Vec<String>,
+    },
+
+    /// Hold back notifications other than crashes and panics (proj dnd [on|off|auto])
+    Dnd {
+        #[command(subcommand)]
+        action: Option<DndAction>,
+    },
+
+    /// Delete the log files and records of exited processes (proj prune [name])
+    Prune {
+        /// Only this project's
+        project: Option<String>,
+    },
+
+    /// CPU, memory and uptime of every running process (proj ps --sort cpu|mem|uptime)
+    Ps {
+        /// Sort by cpu, mem or uptime
+        #[arg(long, default_value = "cpu")]
+        sort: String,
+    },
+
+    /// Show maintenance windows for scheduled projects (proj schedule show)
+    Schedule {
+        /// Action (show)
+        action: Option<String>,
+    },
+
+    /// Print the cross-project service, dependency and route graph (proj graph --format dot|mermaid)
+    Graph {
+        /// Output format: dot or mermaid
+        #[arg(long, default_value = "mermaid")]
+        format: String,
+    },
+
+    /// Print a shell completion script (proj completions bash > /etc/bash_completion.d/proj)
+    Completions {
+        /// bash, zsh, fish, elvish or powershell
+        shell: clap_complete::Shell,
+    },
+
+    /// Project names, one per line, for completion scripts
+    #[command(name = "__complete-projects", hide = true)]
+    CompleteProjects,
+
+    /// Show the effective configuration (proj config show --origin)
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Shared env profiles projects opt into (proj profile-env ls|edit)
+    ProfileEnv {
+        #[command(subcommand)]
+        action: ProfileEnvAction,
+    },
+
+    /// List plugins: `proj-<name>` executables on PATH run as `proj <name>`
+    Plugins,
+
+    /// Print JSON Schemas for IPC messages, config files and the HTTP API
+    Schema {
+        /// Schema to print; lists them when omitted
+        name: Option<String>,
+        /// Write every schema to <DIR>/<name>.schema.json instead
+        #[arg(long, value_name = "DIR", conflicts_with = "name")]
+        out: Option<PathBuf>,
+    },
 
     /// Run a command in project context (proj <project> run <cmd>)
     #[command(hide = true)]
@@ -70,39 +367,414 @@ enum Commands {
 
     /// Open browser for project (proj <project> open)
     #[command(hide = true)]
-    Open,
+    Open {
+        /// Browser to use instead of the configured one
+        #[arg(long)]
+        browser: Option<String>,
+    },
 
     /// Stop project's processes (proj <project> stop)
     #[command(hide = true)]
-    Stop,
+    Stop {
+        /// ID (or its start) of the one process to stop, as shown by `ps`
+        id: Option<String>,
+        /// Stop every process, the default
+        #[arg(long, conflicts_with = "id")]
+        all: bool,
+    },
 
     /// Project-specific commands (proj <project> [action])
     #[command(external_subcommand)]
     Project(Vec<String>),
 }
 
+#[derive(Subcommand)]
+enum DaemonAction {
+    /// Stop the daemon and its managed processes
+    Stop,
+    /// Stop the daemon, then start it again in the background
+    Restart,
+    /// Apply changes to ~/.proj/config.json without restarting (same as SIGHUP)
+    Reload,
+    /// Show or override the daemon's log level per subsystem, e.g. `proxy=debug`
+    LogLevel {
+        /// `<subsystem>=<level>` overrides; `<subsystem>=default` drops one
+        levels: Vec<String>,
+        /// Drop every override, going back to RUST_LOG
+        #[arg(long)]
+        reset: bool,
+    },
+    /// Run the daemon under systemd (Linux) or launchd (macOS), starting it at login
+    Install,
+    /// Remove the daemon from the service manager
+    Uninstall,
+    /// Show whether the daemon runs under the service manager or was started by hand
+    Status,
+    /// Prune exited processes, trim browser caches and compact the database
+    /// now, as the daemon does at `maintenance_at`
+    Maintenance,
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print every setting's effective value
+    Show {
+        /// Also show which layer each value came from
+        #[arg(long)]
+        origin: bool,
+    },
+    /// List unknown and deprecated keys in config files and every project's files
+    Lint,
+}
+
+#[derive(Subcommand)]
+enum ProfileEnvAction {
+    /// List profiles, their variables and the projects using them
+    Ls,
+    /// Edit ~/.proj/env-profiles.json in $EDITOR, creating it with examples
+    Edit,
+}
+
+#[derive(Subcommand)]
+enum TrashAction {
+    /// List entries, newest first
+    Ls,
+    /// Delete every entry for good
+    Empty {
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum DndAction {
+    /// Hold them until `proj dnd off` or `proj dnd auto`
+    On,
+    /// Send them even while a terminal is attached
+    Off,
+    /// Hold them while a terminal is attached to a process, the default
+    Auto,
+}
+
+#[derive(Subcommand)]
+enum MigrateAction {
+    /// Read projects from a machine over SSH and recreate them here
+    From {
+        /// ssh://[user@]host[:port], or a host from ~/.ssh/config
+        source: String,
+        /// Projects to import (defaults to all)
+        projects: Vec<String>,
+        /// Rewrite paths starting with FROM to start with TO (repeatable);
+        /// the remote home directory maps to this one by default
+        #[arg(long = "map", value_name = "FROM=TO")]
+        maps: Vec<String>,
+        /// Don't ask for confirmation
+        #[arg(short = 'y', long)]
+        yes: bool,
+    },
+}
+
 #[tokio::main]
-async fn main() -> Result<()> {
-    let cli = Cli::parse();
+async fn main() -> ExitCode {
+    let Err(e) = run().await else {
+        return ExitCode::SUCCESS;
+    };
+    let error = e.downcast_ref::<IpcError>();
+    // Daemon errors are translated; context added around them is not
+    let message = match error {
+        Some(error) if error.to_string() == e.to_string() => i18n::error(error),
+        _ => e.to_string(),
+    };
+    eprintln!("{} {}", tr!("error-label"), message);
+    let causes: Vec<String> = e.chain().skip(1).map(|cause| cause.to_string()).collect();
+    match causes.as_slice() {
+        [] => {}
+        [cause] => eprintln!("\n{}\n    {}", tr!("caused-by-label"), cause),
+        causes => {
+            eprintln!("\n{}", tr!("caused-by-label"));
+            for (i, cause) in causes.iter().enumerate() {
+                eprintln!("    {}: {}", i, cause);
+            }
+        }
+    }
+    if let Some(hint) = error.and_then(hint) {
+        eprintln!("\n\x1b[90m{} {}\x1b[0m", tr!("hint-label"), hint);
+    }
+    ExitCode::from(error.map_or(1, exit_code))
+}
+
+/// Exit status for a daemon error, so scripts can tell failures apart
+/// (clap uses 2 for usage errors)
+fn exit_code(error: &IpcError) -> u8 {
+    match error {
+        IpcError::ProjectNotFound { .. } => 3,
+        IpcError::ProjectExists { .. } => 4,
+        IpcError::ServiceNotFound { .. }
+        | IpcError::ProcessNotFound { .. }
+        | IpcError::RequestNotFound { .. } => 5,
+        IpcError::ServiceRunning { .. } | IpcError::AlreadyRunning { .. } => 6,
+        IpcError::SpawnFailed { .. } => 7,
+        IpcError::PortConflict { .. } => 8,
+        IpcError::InvalidRequest { .. } => 9,
+        IpcError::Unauthorized => 10,
+        IpcError::Failed { .. } => 1,
+    }
+}
+
+/// What to try next after a daemon error
+fn hint(error: &IpcError) -> Option<String> {
+    match error {
+        IpcError::ProjectNotFound { name } => Some(tr!("hint-project-not-found", name = name)),
+        IpcError::ProjectExists { name } => Some(tr!("hint-project-exists", name = name)),
+        IpcError::ServiceNotFound { project, .. } => {
+            Some(tr!("hint-service-not-found", project = project))
+        }
+        IpcError::ServiceRunning { .. } => Some(tr!("hint-service-running")),
+        IpcError::AlreadyRunning { .. } => Some(tr!("hint-already-running")),
+        IpcError::ProcessNotFound { .. } => Some(tr!("hint-process-not-found")),
+        IpcError::RequestNotFound { project, .. } => {
+            Some(tr!("hint-request-not-found", project = project))
+        }
+        IpcError::SpawnFailed { suggestions, .. } if !suggestions.is_empty() => {
+            Some(suggestions.join("\n"))
+        }
+        IpcError::SpawnFailed { command, .. } => Some(tr!("hint-spawn-failed", command = command)),
+        IpcError::Unauthorized => Some(tr!("hint-unauthorized")),
+        IpcError::PortConflict { port, .. } => Some(tr!("hint-port-conflict", port = port)),
+        IpcError::InvalidRequest { .. } | IpcError::Failed { .. } => None,
+    }
+}
+
+/// The command line parser, with its help translated when the locale has
+/// a translation (the English is the doc comments above)
+fn localized_command() -> clap::Command {
+    let mut command = Cli::command();
+    if let Some(about) = i18n::translated("help-about") {
+        command = command.about(about);
+    }
+    if let Some(examples) = i18n::translated("help-examples") {
+        command = command.after_help(examples);
+    }
+    if let (Some(usage), Some(commands), Some(options)) = (
+        i18n::translated("help-usage"),
+        i18n::translated("help-commands"),
+        i18n::translated("help-options"),
+    ) {
+        command = command.help_template(format!(
+            "{{about-with-newline}}\n{}: {{usage}}\n\n{}:\n{{subcommands}}\n\n{}:\n{{options}}{{after-help}}",
+            usage, commands, options
+        ));
+    }
+    for (arg, id) in [("config", "help-arg-config"), ("host", "help-arg-host")] {
+        if let Some(help) = i18n::translated(id) {
+            command = command.mut_arg(arg, |a| a.help(help));
+        }
+    }
+    // clap adds these itself, so they're replaced rather than changed; the
+    // help flag is disabled for every subcommand, so the new one is global
+    if let Some(help) = i18n::translated("help-arg-help") {
+        command = command.disable_help_flag(true).arg(
+            clap::Arg::new("help")
+                .short('h')
+                .long("help")
+                .global(true)
+                .action(clap::ArgAction::Help)
+                .help(help),
+        );
+    }
+    if let Some(help) = i18n::translated("help-arg-version") {
+        command = command.disable_version_flag(true).arg(
+            clap::Arg::new("version")
+                .short('V')
+                .long("version")
+                .action(clap::ArgAction::Version)
+                .help(help),
+        );
+    }
+    let names: Vec<String> = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect();
+    for name in names {
+        if let Some(about) = i18n::translated(&format!("help-cmd-{}", name)) {
+            command = command.mut_subcommand(&name, |subcommand| subcommand.about(about));
+        }
+    }
+    command
+}
+
+/// A daemon error with its cause, for printing alongside other output
+fn error_message(error: IpcError) -> String {
+    format!("{:#}", anyhow::Error::from(error))
+}
+
+async fn run() -> Result<()> {
+    // The user's config and PROJ_LOCALE pick the language before there's
+    // a project or --config flags to layer on
+    i18n::init(
+        layers::load(None, &[])
+            .ok()
+            .and_then(|resolved| resolved.config.locale)
+            .as_deref(),
+    );
+    let cli =
+        Cli::from_arg_matches(&localized_command().get_matches()).unwrap_or_else(|e| e.exit());
+    CONFIG_FLAGS.get_or_init(|| cli.config.clone());
+    REMOTE_HOST.get_or_init(|| {
+        cli.host
+            .clone()
+            .or_else(|| std::env::var("PROJ_HOST").ok())
+            .filter(|host| !host.is_empty())
+    });
+    // Catch malformed --config flags before anything reads the config
+    if !cli.config.is_empty() {
+        resolve_config()?;
+    }
 
     match cli.command {
-        None => cmd_status().await,
-        Some(Commands::New { name, dir }) => cmd_new(name, dir).await,
-        Some(Commands::List) => cmd_list().await,
-        Some(Commands::Daemon { foreground }) => cmd_daemon(foreground).await,
-        Some(Commands::Status) => cmd_status().await,
+        None => cmd_status(false).await,
+        Some(Commands::Init { name }) => cmd_init(name).await,
+        Some(Commands::New {
+            name,
+            dir,
+            template,
+        }) => cmd_new(name, dir, template).await,
+        Some(Commands::Rm {
+            name,
+            keep_data,
+            yes,
+        }) => cmd_rm(name, keep_data, yes).await,
+        Some(Commands::Undo { id }) => cmd_undo(id).await,
+        Some(Commands::Trash { action }) => match action {
+            None | Some(TrashAction::Ls) => cmd_trash_ls(),
+            Some(TrashAction::Empty { yes }) => cmd_trash_empty(yes),
+        },
+        Some(Commands::List {
+            watch,
+            tags,
+            running,
+            sort,
+            by_tag,
+            plain,
+            no_autostart,
+        }) => {
+            if no_autostart {
+                NO_AUTOSTART.store(true, Ordering::SeqCst);
+            }
+            let filter = ProjectFilter {
+                tags: tags
+                    .iter()
+                    .map(|tag| normalize_tag(tag))
+                    .collect::<Result<_>>()?,
+                running,
+                sort: sort.parse()?,
+            };
+            cmd_list(filter, by_tag, plain, watch).await
+        }
+        Some(Commands::Up {
+            projects,
+            all,
+            jobs,
+        }) => cmd_up(projects, all, jobs).await,
+        Some(Commands::Down { projects, all }) => cmd_down(projects, all).await,
+        Some(Commands::Migrate {
+            action:
+                MigrateAction::From {
+                    source,
+                    projects,
+                    maps,
+                    yes,
+                },
+        }) => cmd_migrate_from(&source, projects, maps, yes).await,
+        Some(Commands::Export {
+            projects,
+            all,
+            output,
+        }) => cmd_export(projects, all, output).await,
+        Some(Commands::Import { file, maps, yes }) => cmd_import(file, maps, yes).await,
+        Some(Commands::Duplicate {
+            source,
+            name,
+            dir,
+            copy,
+            clone,
+        }) => cmd_duplicate(source, name, dir, copy, clone).await,
+        Some(Commands::MigrateData { dry_run }) => cmd_migrate_data(dry_run),
+        // Managing the daemon works whatever version it is
+        Some(Commands::Daemon { action, foreground }) => match skip_handshake(action) {
+            None => cmd_daemon(foreground).await,
+            Some(DaemonAction::Stop) => cmd_daemon_stop().await,
+            Some(DaemonAction::Restart) => {
+                cmd_daemon_stop().await?;
+                cmd_daemon(foreground).await
+            }
+            Some(DaemonAction::Reload) => cmd_daemon_reload().await,
+            Some(DaemonAction::LogLevel { levels, reset }) => {
+                cmd_daemon_log_level(levels, reset).await
+            }
+            Some(DaemonAction::Install) => cmd_daemon_install().await,
+            Some(DaemonAction::Uninstall) => cmd_daemon_uninstall(),
+            Some(DaemonAction::Status) => cmd_daemon_status().await,
+            Some(DaemonAction::Maintenance) => cmd_daemon_maintenance().await,
+        },
+        Some(Commands::Status { verbose }) => cmd_status(verbose).await,
+        Some(Commands::Selftest { stress }) => selftest::run(stress).await,
+        Some(Commands::Doctor) => cmd_doctor().await,
+        Some(Commands::Trust) => cmd_trust().await,
+        Some(Commands::Ports) => cmd_ports().await,
+        Some(Commands::Top) => top::run().await,
+        Some(Commands::Watch {
+            project,
+            json,
+            events,
+        }) => cmd_watch(project, events, json).await,
+        Some(Commands::Dnd { action }) => {
+            cmd_dnd(action.map(|action| match action {
+                DndAction::On => DndMode::On,
+                DndAction::Off => DndMode::Off,
+                DndAction::Auto => DndMode::Auto,
+            }))
+            .await
+        }
+        Some(Commands::Prune { project }) => cmd_prune(project).await,
+        Some(Commands::Ps { sort }) => cmd_ps(None, &sort).await,
+        Some(Commands::Graph { format }) => cmd_graph(None, &format).await,
+        Some(Commands::Completions { shell }) => completions::print(shell, &mut Cli::command()),
+        Some(Commands::CompleteProjects) => completions::print_projects().await,
+        Some(Commands::Config {
+            action: ConfigAction::Show { origin },
+        }) => cmd_config_show(origin),
+        Some(Commands::Config {
+            action: ConfigAction::Lint,
+        }) => cmd_config_lint(),
+        Some(Commands::ProfileEnv {
+            action: ProfileEnvAction::Ls,
+        }) => cmd_profile_env_ls().await,
+        Some(Commands::ProfileEnv {
+            action: ProfileEnvAction::Edit,
+        }) => cmd_profile_env_edit(),
+        Some(Commands::Plugins) => cmd_plugins(),
+        Some(Commands::Schema { name, out }) => cmd_schema(name, out),
+        Some(Commands::Schedule { action }) => match action.as_deref() {
+            None | Some("show") => cmd_schedule_show().await,
+            Some(other) => anyhow::bail!(
+                "Unknown schedule action '{}'. Usage: proj schedule show",
+                other
+            ),
+        },
         Some(Commands::Run { command }) => {
             // This shouldn't be reached directly, but handle it
             let project = detect_project_from_cwd()?;
             cmd_run(project, command).await
         }
-        Some(Commands::Open) => {
+        Some(Commands::Open { browser }) => {
             let project = detect_project_from_cwd()?;
-            cmd_open(project).await
+            cmd_open(project, browser).await
         }
-        Some(Commands::Stop) => {
+        Some(Commands::Stop { id, all: _ }) => {
             let project = detect_project_from_cwd()?;
-            cmd_stop(project).await
+            cmd_stop(project, id.as_deref()).await
         }
         Some(Commands::Project(args)) => handle_project_command(args).await,
     }
@@ -111,11 +783,21 @@ async fn main() -> Result<()> {
 /// Handle project-specific commands: proj <project> [action] [args...]
 async fn handle_project_command(args: Vec<String>) -> Result<()> {
     if args.is_empty() {
-        return cmd_status().await;
+        return cmd_status(false).await;
     }
 
     let project_name = &args[0];
 
+    // A plugin runs unless a project has its name
+    if let Some(plugin) = plugins::find(project_name) {
+        match get_project(project_name).await {
+            Err(e) if matches!(e.downcast_ref(), Some(IpcError::ProjectNotFound { .. })) => {
+                return run_plugin(&plugin, &args[1..]);
+            }
+            _ => {}
+        }
+    }
+
     // Check if this might be a project name
     if args.len() == 1 {
         // Just "proj <name>" - show project info
@@ -132,429 +814,7741 @@ async fn handle_project_command(args: Vec<String>) -> Result<()> {
             }
             cmd_run(project_name.clone(), rest).await
         }
-        "open" => cmd_open(project_name.clone()).await,
-        "stop" => cmd_stop(project_name.clone()).await,
-        "info" => cmd_project_info(project_name).await,
+        "adopt" => cmd_adopt(project_name, rest).await,
+        "env" => cmd_env(project_name, rest).await,
+        "service" | "services" => cmd_service(project_name, rest).await,
+        "debug" => cmd_debug(project_name, &rest).await,
+        "queue" => cmd_queue(project_name, rest).await,
+        "open" => match rest.as_slice() {
+            [] => cmd_open(project_name.clone(), None).await,
+            [flag, browser] if flag == "--browser" => {
+                cmd_open(project_name.clone(), Some(browser.clone())).await
+            }
+            _ => anyhow::bail!("Usage: proj {} open [--browser <browser>]", project_name),
+        },
+        "profile" => match rest.as_slice() {
+            [action, flags @ ..] if action == "reset" => {
+                cmd_profile_reset(project_name, flags).await
+            }
+            _ => anyhow::bail!(
+                "Usage: proj {} profile reset [--browser <browser>] [-y]",
+                project_name
+            ),
+        },
+        "stop" => match rest.as_slice() {
+            [] => cmd_stop(project_name.clone(), None).await,
+            [all] if all == "--all" => cmd_stop(project_name.clone(), None).await,
+            [id] if !id.starts_with('-') => cmd_stop(project_name.clone(), Some(id)).await,
+            _ => anyhow::bail!("Usage: proj {} stop [<id>|--all]", project_name),
+        },
+        "primary" => cmd_primary(project_name, rest).await,
+        "restart" => match rest.as_slice() {
+            [] => cmd_restart(project_name, None).await,
+            [service] => cmd_restart(project_name, Some(service)).await,
+            _ => anyhow::bail!("Usage: proj {} restart [service]", project_name),
+        },
+        "up" => cmd_up(vec![project_name.clone()], false, None).await,
+        "down" => cmd_down(vec![project_name.clone()], false).await,
+        "deps" | "depends-on" => cmd_deps(project_name, rest).await,
+        "renice" => cmd_renice(project_name, rest).await,
+        "sandbox" => cmd_sandbox(project_name, rest).await,
+        "alias" => cmd_alias(project_name, rest).await,
+        "tag" => cmd_tag(project_name, rest).await,
+        "icon" => cmd_icon(project_name, rest.first().map(String::as_str)).await,
+        "color" => cmd_color(project_name, rest.first().map(String::as_str)).await,
+        "info" => match rest.as_slice() {
+            [] => cmd_project_info(project_name).await,
+            [flag] if flag == "--watch" || flag == "-w" => cmd_info_watch(project_name).await,
+            [flag] if flag == "--json" => cmd_project_info_json(project_name).await,
+            _ => anyhow::bail!("Usage: proj {} info [--watch|--json]", project_name),
+        },
+        "watch" => {
+            let mut json = false;
+            let mut events = Vec::new();
+            let mut flags = rest.iter();
+            while let Some(flag) = flags.next() {
+                match flag.as_str() {
+                    "--json" => json = true,
+                    "--event" => {
+                        events.push(flags.next().context("--event expects a name")?.clone())
+                    }
+                    other => anyhow::bail!("Unknown option '{}'", other),
+                }
+            }
+            cmd_watch(Some(project_name.clone()), events, json).await
+        }
+        "ps" => {
+            let sort = match rest.as_slice() {
+                [] => "cpu",
+                [flag, sort] if flag == "--sort" => sort.as_str(),
+                _ => anyhow::bail!("Usage: proj {} ps [--sort cpu|mem|uptime]", project_name),
+            };
+            cmd_ps(Some(project_name), sort).await
+        }
+        "csp" => cmd_csp(project_name, rest.first().map(String::as_str)).await,
+        "cors" => cmd_cors(project_name, rest.first().map(String::as_str)).await,
+        "passthrough" => cmd_passthrough(project_name, rest.first().map(String::as_str)).await,
+        "cache" => cmd_cache(project_name, rest.first().map(String::as_str)).await,
+        "sw" => cmd_sw(project_name, rest.first().map(String::as_str)).await,
+        "host-header" => cmd_host_header(project_name, rest.first().map(String::as_str)).await,
+        "redirects" => cmd_redirects(project_name, rest.first().map(String::as_str)).await,
+        "trailing-slash" => {
+            cmd_trailing_slash(project_name, rest.first().map(String::as_str)).await
+        }
+        "upstream" => cmd_upstream(project_name, rest.first().map(String::as_str)).await,
+        "idle" => cmd_idle(project_name, rest.first().map(String::as_str)).await,
+        "lazy" => cmd_lazy(project_name, rest.first().map(String::as_str)).await,
+        "max-in-flight" => cmd_max_in_flight(project_name, rest.first().map(String::as_str)).await,
+        "attach" => cmd_attach(project_name, rest.first().map(String::as_str)).await,
+        "route" | "routes" => cmd_route(project_name, rest).await,
+        "override" | "overrides" => cmd_override(project_name, rest).await,
+        "rewrite" | "rewrites" => cmd_rewrite(project_name, rest).await,
+        "auth" => cmd_auth(project_name, rest).await,
+        "smoke" => cmd_smoke(project_name, rest).await,
+        "as" => cmd_as(project_name, rest).await,
+        "schedule" => cmd_schedule(project_name, rest).await,
+        "hooks" | "hook" => cmd_hooks(project_name, rest).await,
+        "copy-config" => cmd_copy_config(project_name, rest).await,
+        "graph" => {
+            let format = match rest.as_slice() {
+                [] => "mermaid",
+                [flag, format] if flag == "--format" => format.as_str(),
+                _ => anyhow::bail!("Usage: proj {} graph [--format dot|mermaid]", project_name),
+            };
+            cmd_graph(Some(project_name), format).await
+        }
+        "history" => {
+            let limit = match rest.as_slice() {
+                [] => HISTORY_LIMIT,
+                [flag, n] if flag == "-n" => n
+                    .parse()
+                    .with_context(|| format!("Invalid count '{}'", n))?,
+                _ => anyhow::bail!("Usage: proj {} history [-n <count>]", project_name),
+            };
+            cmd_history(project_name, limit).await
+        }
+        "logs" | "log" => cmd_logs(project_name, rest).await,
+        "stats" => cmd_stats(project_name, rest).await,
+        "crash" | "crashes" => cmd_crash(project_name, rest),
+        "rerun" => {
+            if !rest.is_empty() {
+                anyhow::bail!("Usage: proj {} rerun", project_name);
+            }
+            cmd_rerun(project_name).await
+        }
+        "requests" => cmd_requests(project_name, rest).await,
+        "api-drift" => cmd_api_drift(project_name, rest).await,
+        "budget" | "budgets" => cmd_budget(project_name, rest).await,
+        "gql" => cmd_gql(project_name, rest).await,
+        "assets" => cmd_assets(project_name, rest).await,
         _ => {
-            // Assume it's a command to run: proj <project> npm run dev
+            // A command to run: proj <project> npm run dev, if the user lets
+            // it run that way; `proj <project> -- <cmd>` always does
             let mut command = vec![action.clone()];
             command.extend(rest);
+            let (_, words) = parse_run_args(command.clone())?;
+            let explicit = command[..command.len() - words.len()].contains(&"--".to_string());
+            if !explicit && !words.is_empty() && !confirm_auto_run(project_name, &words)? {
+                println!("{}", tr!("aborted"));
+                return Ok(());
+            }
             cmd_run(project_name.clone(), command).await
         }
     }
 }
 
-/// Show info about a specific project
-async fn cmd_project_info(name: &str) -> Result<()> {
+/// Whether `proj <name> <cmd>` should run `command`, which isn't one of
+/// proj's actions, by the `auto_run` setting: commands it allows run, those
+/// it denies don't, and others are confirmed at a terminal
+fn confirm_auto_run(project_name: &str, command: &[String]) -> Result<bool> {
+    let program = &command[0];
+    let explicit = format!(
+        "proj {} run -- {}",
+        project_name,
+        shell_words::join(command)
+    );
+    let verdict = load_config().auto_run.verdict(program);
+    if verdict == AutoRunVerdict::Run {
+        return Ok(true);
+    }
+    if verdict == AutoRunVerdict::Refuse {
+        anyhow::bail!(
+            "Not running `{}` without `run`, as auto_run.deny lists it. To run it: {}",
+            program,
+            explicit
+        );
+    }
+    let typo = lint::did_you_mean(program, completions::PROJECT_ACTIONS.iter().copied())
+        .map(|action| format!(" (did you mean `proj {} {}`?)", project_name, action))
+        .unwrap_or_default();
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!(
+            "`{}` isn't a proj action or in auto_run.allow{}. To run it: {}",
+            program,
+            typo,
+            explicit
+        );
+    }
+
+    print!(
+        "`{}` isn't a proj action{}. Run `{}` in {}? [y/N] ",
+        program,
+        typo,
+        shell_words::join(command),
+        project_name
+    );
+    std::io::Write::flush(&mut std::io::stdout())?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Fetch the projects passing `filter` from the daemon
+async fn list_projects(filter: ProjectFilter) -> Result<Vec<Project>> {
+    match send_request(IpcRequest::ListProjects { filter }).await? {
+        IpcResponse::Projects(projects) => Ok(projects),
+        IpcResponse::Error(error) => Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    }
+}
+
+/// Fetch a project from the daemon
+async fn get_project(name: &str) -> Result<Project> {
     let response = send_request(IpcRequest::GetProject {
         name: name.to_string(),
     })
     .await?;
 
-    let project = match response {
-        IpcResponse::Project(p) => p,
-        IpcResponse::Error { message } => {
-            anyhow::bail!("{}", message);
+    match response {
+        IpcResponse::Project(p) => Ok(p),
+        IpcResponse::Error(error) => Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    }
+}
+
+/// Read-modify-write a project: `edit` builds an update request from a fresh
+/// copy, and runs again on a re-fetched copy if another client changed the
+/// project in between
+async fn update_project(
+    name: &str,
+    mut edit: impl FnMut(Project) -> Result<IpcRequest>,
+) -> Result<Project> {
+    for _ in 0..UPDATE_ATTEMPTS {
+        let request = edit(get_project(name).await?)?;
+        match send_request(request).await? {
+            IpcResponse::Project(project) => return Ok(project),
+            IpcResponse::Conflict { .. } => continue,
+            IpcResponse::Error(error) => return Err(error.into()),
+            _ => anyhow::bail!(tr!("unexpected-response")),
         }
-        _ => anyhow::bail!("Unexpected response"),
-    };
+    }
+    anyhow::bail!("'{}' kept changing while being updated; try again", name)
+}
 
-    // Get processes for this project
-    let proc_response = send_request(IpcRequest::ListProcesses {
-        project_name: Some(name.to_string()),
-    })
-    .await?;
+/// How many times `update_project` retries after a revision conflict
+const UPDATE_ATTEMPTS: usize = 3;
 
-    let processes = match proc_response {
-        IpcResponse::Processes(p) => p,
-        _ => vec![],
+/// Switch the dev auth persona injected into proxied requests
+///
+/// `proj <name> as admin [--user-id ID] [--email E] [--groups a,b] [--header K=V]`
+/// defines or updates a persona and activates it; `proj <name> as off` disables.
+async fn cmd_as(project_name: &str, args: Vec<String>) -> Result<()> {
+    let Some((persona_name, flags)) = args.split_first() else {
+        let project = get_project(project_name).await?;
+        if project.proxy.personas.is_empty() {
+            println!("No personas for {}", project.name);
+            println!("  Create one with: proj {} as <persona>", project.name);
+        }
+        for (name, persona) in &project.proxy.personas {
+            let marker = if project.proxy.persona.as_ref() == Some(name) {
+                "\x1b[32m●\x1b[0m"
+            } else {
+                "○"
+            };
+            println!("{} {}", marker, name);
+            for (header, value) in persona.identity_headers() {
+                println!("    {}: {}", header, value);
+            }
+        }
+        return Ok(());
     };
 
-    let running: Vec<_> = processes
-        .iter()
-        .filter(|p| p.status == proj_common::ProcessStatus::Running)
-        .collect();
+    let project = update_project(project_name, |mut project| {
+        if persona_name == "off" || persona_name == "none" {
+            project.proxy.persona = None;
+        } else {
+            let persona = project
+                .proxy
+                .personas
+                .entry(persona_name.clone())
+                .or_insert_with(|| Persona {
+                    user_id: persona_name.clone(),
+                    email: Some(format!("{}@{}", persona_name, project_host(project_name))),
+                    groups: vec![persona_name.clone()],
+                    headers: Default::default(),
+                });
 
-    println!("Project: {}", project.name);
-    println!("  Root:    {}", project.root_dir.display());
-    println!("  Created: {}", project.created_at.format("%Y-%m-%d %H:%M"));
+            let mut flags = flags.iter();
+            while let Some(flag) = flags.next() {
+                let mut value = || {
+                    flags
+                        .next()
+                        .with_context(|| format!("{} expects a value", flag))
+                };
+                match flag.as_str() {
+                    "--user-id" => persona.user_id = value()?.clone(),
+                    "--email" => persona.email = Some(value()?.clone()),
+                    "--groups" => {
+                        persona.groups = value()?
+                            .split(',')
+                            .map(|g| g.trim().to_string())
+                            .filter(|g| !g.is_empty())
+                            .collect()
+                    }
+                    "--header" => {
+                        let (name, header_value) = value()?
+                            .split_once('=')
+                            .context("--header expects NAME=VALUE")?;
+                        persona
+                            .headers
+                            .insert(name.to_string(), header_value.to_string());
+                    }
+                    other => anyhow::bail!("Unknown option '{}'", other),
+                }
+            }
+            project.proxy.persona = Some(persona_name.clone());
+        }
 
-    if let Some(proc) = running.first() {
-        println!("  Status:  \x1b[32mrunning\x1b[0m");
-        if let Some(port) = proc.port {
-            println!("  Port:    {}", port);
-            println!("  URL:     http://{}.localhost:8080", project.name);
+        Ok(IpcRequest::SetProxyOptions {
+            name: project.name.clone(),
+            options: project.proxy,
+            expected_revision: Some(project.revision),
+        })
+    })
+    .await?;
+
+    match project.proxy.active_persona() {
+        Some(persona) => {
+            println!(
+                "\x1b[32m✓\x1b[0m Requests to {} now act as \x1b[1m{}\x1b[0m",
+                project.name,
+                project.proxy.persona.as_deref().unwrap_or_default()
+            );
+            for (header, value) in persona.identity_headers() {
+                println!("    {}: {}", header, value);
+            }
         }
-        println!("  PID:     {}", proc.pid);
-        println!("  Command: {}", proc.command);
-    } else {
-        println!("  Status:  \x1b[90mstopped\x1b[0m");
+        None => println!("\x1b[32m✓\x1b[0m Dev auth disabled for {}", project.name),
     }
 
-    println!();
-    println!("Commands:");
-    println!("  proj {} run <cmd>   Run a command", project.name);
-    println!("  proj {} open        Open in browser", project.name);
-    println!("  proj {} stop        Stop processes", project.name);
-
     Ok(())
 }
 
-/// Send a request to the daemon and get a response
-async fn send_request(request: IpcRequest) -> Result<IpcResponse> {
-    let socket = socket_path()?;
+/// Toggle Content-Security-Policy relaxation for a project
+/// Path routes: `route [ls]`, `route add <path> <port>`, `route rm <path>`
+async fn cmd_route(project_name: &str, args: Vec<String>) -> Result<()> {
+    let usage = || {
+        anyhow::anyhow!(
+            "Usage: proj {} route [ls | add <path> <port> | rm <path> | disable | enable]",
+            project_name
+        )
+    };
+    let project = match args.split_first() {
+        None => get_project(project_name).await?,
+        Some((action, rest)) => match (action.as_str(), rest) {
+            ("ls", []) => get_project(project_name).await?,
+            ("add", [prefix, port]) => {
+                let prefix = normalize_route_prefix(prefix)?;
+                let port: u16 = port
+                    .parse()
+                    .ok()
+                    .filter(|port| *port != 0)
+                    .with_context(|| format!("Invalid port '{}'", port))?;
+                update_project(project_name, |mut project| {
+                    project.proxy.routes.insert(prefix.clone(), port);
+                    Ok(IpcRequest::SetProxyOptions {
+                        name: project.name.clone(),
+                        options: project.proxy,
+                        expected_revision: Some(project.revision),
+                    })
+                })
+                .await?
+            }
+            ("rm", [prefix]) => {
+                let prefix = normalize_route_prefix(prefix)?;
+                update_project(project_name, |mut project| {
+                    if project.proxy.routes.remove(&prefix).is_none() {
+                        let in_file = ProjectFile::load(&project.root_dir)
+                            .ok()
+                            .flatten()
+                            .is_some_and(|file| file.routes.contains_key(&prefix));
+                        if in_file {
+                            anyhow::bail!(
+                                "Route {} is declared in {}; remove it there",
+                                prefix,
+                                schema::PROJECT_FILE
+                            );
+                        }
+                        anyhow::bail!("{} has no route for {}", project.name, prefix);
+                    }
+                    Ok(IpcRequest::SetProxyOptions {
+                        name: project.name.clone(),
+                        options: project.proxy,
+                        expected_revision: Some(project.revision),
+                    })
+                })
+                .await?
+            }
+            ("disable", []) => return cmd_route_direct(project_name, true).await,
+            ("enable", []) => return cmd_route_direct(project_name, false).await,
+            _ => return Err(usage()),
+        },
+    };
 
-    // Auto-start daemon if not running
-    if !socket.exists() {
-        auto_start_daemon().await?;
+    if project.is_direct() {
+        println!(
+            "{} is in direct mode: the proxy doesn't route it (proj {} route enable)",
+            project.name, project.name
+        );
+        return Ok(());
     }
 
-    let stream = UnixStream::connect(&socket)
-        .await
-        .context("Failed to connect to daemon. Try: proj daemon -f")?;
+    // proj.toml routes are overridden by the project's own, as in the daemon
+    let mut routes: BTreeMap<String, (u16, &str)> = BTreeMap::new();
+    match ProjectFile::load(&project.root_dir) {
+        Ok(file) => {
+            for (prefix, port) in file.map(|file| file.routes).unwrap_or_default() {
+                if let Ok(prefix) = normalize_route_prefix(&prefix) {
+                    routes.insert(prefix, (port, schema::PROJECT_FILE));
+                }
+            }
+        }
+        Err(e) => eprintln!("\x1b[33m!\x1b[0m {:#}", e),
+    }
+    for (prefix, port) in &project.proxy.routes {
+        routes.insert(prefix.clone(), (*port, ""));
+    }
 
-    let (reader, mut writer) = stream.into_split();
+    let host = project_host(&project.name);
+    if routes.is_empty() {
+        println!("All of {} goes to its running process", host);
+        println!(
+            "Send a path elsewhere with: proj {} route add /api <port>",
+            project.name
+        );
+        return Ok(());
+    }
+    let width = routes.keys().map(|prefix| prefix.len()).max().unwrap_or(0);
+    for (prefix, (port, source)) in &routes {
+        let source = if source.is_empty() {
+            String::new()
+        } else {
+            format!("  \x1b[90m({})\x1b[0m", source)
+        };
+        println!(
+            "{}{:<width$}  → {}{}",
+            host,
+            prefix,
+            port,
+            source,
+            width = width
+        );
+    }
+    if !routes.contains_key("/") {
+        println!("{}{:<width$}  → running process", host, "/*", width = width);
+    }
+    Ok(())
+}
 
-    // Send request
-    let json = serde_json::to_string(&request)?;
-    writer.write_all(json.as_bytes()).await?;
-    writer.write_all(b"\n").await?;
+/// Take a project out of the proxy (`direct`), or put it back. Its processes
+/// run as before; only its hostname stops being routed.
+async fn cmd_route_direct(project_name: &str, direct: bool) -> Result<()> {
+    let project = update_project(project_name, |mut project| {
+        let in_file = ProjectFile::load(&project.root_dir)
+            .ok()
+            .flatten()
+            .is_some_and(|file| file.proxy == Some(false));
+        if !direct && in_file {
+            anyhow::bail!(
+                "{} sets proxy = false; remove it there to route {} again",
+                schema::PROJECT_FILE,
+                project.name
+            );
+        }
+        project.proxy.direct = direct;
+        Ok(IpcRequest::SetProxyOptions {
+            name: project.name.clone(),
+            options: project.proxy,
+            expected_revision: Some(project.revision),
+        })
+    })
+    .await?;
 
-    // Read response
-    let mut reader = BufReader::new(reader);
-    let mut line = String::new();
-    reader.read_line(&mut line).await?;
+    if direct {
+        let url = load_config()
+            .project_url(&project)
+            .unwrap_or_else(|| "its own port once it runs".to_string());
+        println!(
+            "\x1b[32m✓\x1b[0m {} is in direct mode: the proxy leaves it alone, and it's at {}",
+            project.name, url
+        );
+    } else {
+        println!(
+            "\x1b[32m✓\x1b[0m Routing {} through the proxy again at \x1b[4m{}\x1b[0m",
+            project.name,
+            project_url(&project.name)
+        );
+    }
+    Ok(())
+}
 
-    let response: IpcResponse =
-        serde_json::from_str(&line).context("Invalid response from daemon")?;
+/// Show or change where a project's processes and browsers reach other
+/// hostnames: `override [ls]`, `override add <host> <port|host:port>`,
+/// `override rm <host>`
+async fn cmd_override(project_name: &str, args: Vec<String>) -> Result<()> {
+    let usage = || {
+        anyhow::anyhow!(
+            "Usage: proj {} override [ls | add <host> <port|host:port> | rm <host>]",
+            project_name
+        )
+    };
+    let previous = get_project(project_name).await?;
+    let project = match args.split_first() {
+        None => previous.clone(),
+        Some((action, rest)) => match (action.as_str(), rest) {
+            ("ls", []) => previous.clone(),
+            ("add", [host, target]) => {
+                let host = normalize_alias(host)?;
+                let target = normalize_override_target(target)?;
+                update_project(project_name, |mut project| {
+                    project.proxy.overrides.insert(host.clone(), target.clone());
+                    Ok(IpcRequest::SetProxyOptions {
+                        name: project.name.clone(),
+                        options: project.proxy,
+                        expected_revision: Some(project.revision),
+                    })
+                })
+                .await?
+            }
+            ("rm", [host]) => {
+                let host = normalize_alias(host)?;
+                update_project(project_name, |mut project| {
+                    if project.proxy.overrides.remove(&host).is_none() {
+                        anyhow::bail!("{} doesn't override {}", project.name, host);
+                    }
+                    Ok(IpcRequest::SetProxyOptions {
+                        name: project.name.clone(),
+                        options: project.proxy,
+                        expected_revision: Some(project.revision),
+                    })
+                })
+                .await?
+            }
+            _ => return Err(usage()),
+        },
+    };
+
+    if project.proxy.overrides.is_empty() {
+        println!("{} reaches every hostname through DNS", project.name);
+        println!(
+            "Point one at a local server with: proj {} override add api.example.com 4000",
+            project.name
+        );
+        return Ok(());
+    }
+    let width = project
+        .proxy
+        .overrides
+        .keys()
+        .map(|host| host.len())
+        .max()
+        .unwrap_or(0);
+    for (host, target) in &project.proxy.overrides {
+        println!("{:<width$}  → {}", host, target, width = width);
+    }
 
-    Ok(response)
+    // Processes only get the proxy settings when they start
+    let newly_proxied = previous.proxy.overrides.is_empty();
+    if newly_proxied && !running_processes(&project.name).await?.is_empty() {
+        println!(
+            "[33m![0m Restart {} so its processes send requests through the proxy",
+            project.name
+        );
+    }
+    Ok(())
 }
 
-/// Auto-start the daemon in the background
-async fn auto_start_daemon() -> Result<()> {
-    let daemon_path = std::env::current_exe()?
-        .parent()
-        .context("No parent directory")?
-        .join("proj-daemon");
+async fn cmd_rewrite(project_name: &str, args: Vec<String>) -> Result<()> {
+    let usage = || {
+        anyhow::anyhow!(
+            "Usage: proj {} rewrite [ls | add <find> <replace> [--regex] | rm <n>]",
+            project_name
+        )
+    };
+    let project = match args.split_first() {
+        None => get_project(project_name).await?,
+        Some((action, rest)) => match (action.as_str(), rest) {
+            ("ls", []) => get_project(project_name).await?,
+            ("add", [find, replace, flags @ ..]) => {
+                let regex = match flags {
+                    [] => false,
+                    [flag] if flag == "--regex" => true,
+                    _ => return Err(usage()),
+                };
+                let rewrite = Rewrite {
+                    find: find.clone(),
+                    replace: replace.clone(),
+                    regex,
+                };
+                rewrite.pattern()?;
+                update_project(project_name, |mut project| {
+                    project.proxy.rewrites.push(rewrite.clone());
+                    Ok(IpcRequest::SetProxyOptions {
+                        name: project.name.clone(),
+                        options: project.proxy,
+                        expected_revision: Some(project.revision),
+                    })
+                })
+                .await?
+            }
+            ("rm", [n]) => {
+                let n: usize = n.parse().map_err(|_| usage())?;
+                update_project(project_name, |mut project| {
+                    if n == 0 || n > project.proxy.rewrites.len() {
+                        anyhow::bail!("{} has no rewrite {}", project.name, n);
+                    }
+                    project.proxy.rewrites.remove(n - 1);
+                    Ok(IpcRequest::SetProxyOptions {
+                        name: project.name.clone(),
+                        options: project.proxy,
+                        expected_revision: Some(project.revision),
+                    })
+                })
+                .await?
+            }
+            _ => return Err(usage()),
+        },
+    };
 
-    if !daemon_path.exists() {
-        anyhow::bail!(
-            "Daemon binary not found. Please reinstall proj or run: cargo build --release"
+    if project.proxy.rewrites.is_empty() {
+        println!(
+            "{} serves responses as its backend sends them",
+            project.name
+        );
+        println!(
+            "Rewrite one with: proj {} rewrite add https://cdn.example.com /assets",
+            project.name
+        );
+        return Ok(());
+    }
+    for (i, rewrite) in project.proxy.rewrites.iter().enumerate() {
+        let kind = if rewrite.regex { " (regex)" } else { "" };
+        println!(
+            "{:>2}. {:?} → {:?}{}",
+            i + 1,
+            rewrite.find,
+            rewrite.replace,
+            kind
         );
     }
+    Ok(())
+}
 
-    // Spawn detached
-    std::process::Command::new(&daemon_path)
-        .stdin(std::process::Stdio::null())
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null())
-        .spawn()
-        .context("Failed to start daemon")?;
+/// Run a project's smoke checks, or manage them:
+/// `smoke [ls | add <path> [--status <code>] [--contains <text>] | rm <n>]`
+async fn cmd_smoke(project_name: &str, args: Vec<String>) -> Result<()> {
+    let usage = || {
+        anyhow::anyhow!(
+            "Usage: proj {} smoke [ls | add <path> [--status <code>] [--contains <text>] | rm <n>]",
+            project_name
+        )
+    };
+    let project = match args.split_first() {
+        None => return run_smoke_checks(project_name).await,
+        Some((action, rest)) => match (action.as_str(), rest) {
+            ("ls", []) => get_project(project_name).await?,
+            ("add", [path, flags @ ..]) => {
+                let mut check = SmokeCheck {
+                    path: path.clone(),
+                    status: 200,
+                    contains: None,
+                };
+                let mut flags = flags.iter();
+                while let Some(flag) = flags.next() {
+                    let value = flags.next().ok_or_else(usage)?;
+                    match flag.as_str() {
+                        "--status" => {
+                            check.status = value
+                                .parse()
+                                .with_context(|| format!("Invalid status '{}'", value))?
+                        }
+                        "--contains" => check.contains = Some(value.clone()),
+                        _ => return Err(usage()),
+                    }
+                }
+                update_project(project_name, |mut project| {
+                    project.smoke.push(check.clone());
+                    Ok(IpcRequest::SetSmokeChecks {
+                        name: project.name.clone(),
+                        checks: project.smoke,
+                        expected_revision: Some(project.revision),
+                    })
+                })
+                .await?
+            }
+            ("rm", [n]) => {
+                let n: usize = n.parse().map_err(|_| usage())?;
+                update_project(project_name, |mut project| {
+                    if n == 0 || n > project.smoke.len() {
+                        anyhow::bail!("{} has no smoke check {}", project.name, n);
+                    }
+                    project.smoke.remove(n - 1);
+                    Ok(IpcRequest::SetSmokeChecks {
+                        name: project.name.clone(),
+                        checks: project.smoke,
+                        expected_revision: Some(project.revision),
+                    })
+                })
+                .await?
+            }
+            _ => return Err(usage()),
+        },
+    };
 
-    // Wait for daemon to be ready
-    let socket = socket_path()?;
-    for _ in 0..20 {
-        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-        if socket.exists() {
-            return Ok(());
+    if project.smoke.is_empty() {
+        println!("{} has no smoke checks", project.name);
+        println!(
+            "Add one with: proj {} smoke add /api/health --contains ok",
+            project.name
+        );
+        return Ok(());
+    }
+    for (i, check) in project.smoke.iter().enumerate() {
+        println!("{:>2}. {}", i + 1, check);
+    }
+    Ok(())
+}
+
+/// Send a project's smoke checks through the proxy and print how each went,
+/// failing if any did
+async fn run_smoke_checks(project_name: &str) -> Result<()> {
+    let request = IpcRequest::RunSmokeChecks {
+        project_name: project_name.to_string(),
+    };
+    let results = match send_request(request).await? {
+        IpcResponse::SmokeResults(results) => results,
+        IpcResponse::UnsupportedRequest { .. } => {
+            anyhow::bail!("The daemon predates smoke checks; restart it with `proj daemon restart`")
         }
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    };
+    if results.is_empty() {
+        println!("{} has no smoke checks", project_name);
+        println!(
+            "Add one with: proj {} smoke add /api/health --contains ok",
+            project_name
+        );
+        return Ok(());
     }
 
-    anyhow::bail!("Daemon failed to start. Try: proj daemon -f")
+    let width = results
+        .iter()
+        .map(|result| result.check.path.len())
+        .max()
+        .unwrap_or(0);
+    for result in &results {
+        let mark = if result.passed() {
+            "\x1b[32m✓\x1b[0m"
+        } else {
+            "\x1b[31m✗\x1b[0m"
+        };
+        let status = result
+            .status
+            .map_or_else(|| "---".to_string(), |status| status.to_string());
+        print!(
+            "  {} GET {:<width$}  {}  {:>5}ms",
+            mark,
+            result.check.path,
+            status,
+            result.duration_ms,
+            width = width
+        );
+        match &result.failure {
+            Some(failure) => println!("  \x1b[31m{}\x1b[0m", failure),
+            None => println!(),
+        }
+    }
+    let failed = results.iter().filter(|result| !result.passed()).count();
+    if failed > 0 {
+        anyhow::bail!("{} of {} smoke checks failed", failed, results.len());
+    }
+    println!("\x1b[32m✓\x1b[0m All {} smoke checks passed", results.len());
+    Ok(())
 }
 
-/// Create a new project
-async fn cmd_new(name: String, dir: Option<PathBuf>) -> Result<()> {
-    validate_project_name(&name)?;
+async fn cmd_auth(project_name: &str, args: Vec<String>) -> Result<()> {
+    let usage = || {
+        anyhow::anyhow!(
+            "Usage: proj {} auth [ls | add <path> --bearer | add <path> --basic <user> | rm <path>]",
+            project_name
+        )
+    };
+    let project = match args.split_first() {
+        None => get_project(project_name).await?,
+        Some((action, rest)) => match (action.as_str(), rest) {
+            ("ls", []) => get_project(project_name).await?,
+            ("add", [prefix, flags @ ..]) => {
+                let prefix = normalize_route_prefix(prefix)?;
+                let scheme = match flags {
+                    [flag] if flag == "--bearer" => UpstreamAuth::Bearer,
+                    [flag, username] if flag == "--basic" => UpstreamAuth::Basic {
+                        username: username.clone(),
+                    },
+                    _ => return Err(usage()),
+                };
+                if let Some(host) = remote_host() {
+                    anyhow::bail!(
+                        "The daemon at {} reads credentials from its own keychain; add them there",
+                        host
+                    );
+                }
+                let previous = get_project(project_name).await?;
+                let prompt = match &scheme {
+                    UpstreamAuth::Basic { username } => format!("Password for {}", username),
+                    UpstreamAuth::Bearer => format!("Token for {}", prefix),
+                };
+                let secret = read_secret(&prompt)?;
+                if secret.is_empty() {
+                    anyhow::bail!("No secret given");
+                }
+                let account = secrets::auth_account(&previous.name, &prefix);
+                let backend = secrets::set(&account, &secret)?;
+                let project = update_project(project_name, |mut project| {
+                    project.proxy.auth.insert(prefix.clone(), scheme.clone());
+                    Ok(IpcRequest::SetProxyOptions {
+                        name: project.name.clone(),
+                        options: project.proxy,
+                        expected_revision: Some(project.revision),
+                    })
+                })
+                .await?;
+                println!("\x1b[32m✓\x1b[0m Stored the secret in {}", backend);
+                project
+            }
+            ("rm", [prefix]) => {
+                let prefix = normalize_route_prefix(prefix)?;
+                let project = update_project(project_name, |mut project| {
+                    if project.proxy.auth.remove(&prefix).is_none() {
+                        anyhow::bail!("{} has no credentials for {}", project.name, prefix);
+                    }
+                    Ok(IpcRequest::SetProxyOptions {
+                        name: project.name.clone(),
+                        options: project.proxy,
+                        expected_revision: Some(project.revision),
+                    })
+                })
+                .await?;
+                secrets::delete(&secrets::auth_account(&project.name, &prefix))?;
+                project
+            }
+            _ => return Err(usage()),
+        },
+    };
+
+    if project.proxy.auth.is_empty() {
+        println!(
+            "{}'s requests go upstream without added credentials",
+            project.name
+        );
+        println!(
+            "Attach a token to requests under /api with: proj {} auth add /api --bearer",
+            project.name
+        );
+        return Ok(());
+    }
+    let width = project
+        .proxy
+        .auth
+        .keys()
+        .map(|prefix| prefix.len())
+        .max()
+        .unwrap_or(0);
+    for (prefix, scheme) in &project.proxy.auth {
+        println!("{:<width$}  {}", prefix, scheme, width = width);
+    }
+    Ok(())
+}
+
+/// Read a secret: typed without echo at a terminal, else piped to stdin
+fn read_secret(prompt: &str) -> Result<String> {
+    use std::io::Read;
+
+    let stdin = std::io::stdin();
+    if !stdin.is_terminal() {
+        let mut secret = String::new();
+        stdin.lock().read_to_string(&mut secret)?;
+        return Ok(secret.trim_end_matches(['\r', '\n']).to_string());
+    }
+
+    eprint!("{}: ", prompt);
+    std::io::Write::flush(&mut std::io::stderr())?;
+    ratatui::crossterm::terminal::enable_raw_mode()?;
+    let mut secret = Vec::new();
+    let mut read = || -> Result<()> {
+        for byte in stdin.lock().bytes() {
+            match byte? {
+                b'\r' | b'\n' => return Ok(()),
+                // Ctrl+C and Ctrl+D
+                3 | 4 => anyhow::bail!("Cancelled"),
+                // Backspace and Delete
+                8 | 0x7f => {
+                    secret.pop();
+                }
+                byte => secret.push(byte),
+            }
+        }
+        Ok(())
+    };
+    let result = read();
+    let _ = ratatui::crossterm::terminal::disable_raw_mode();
+    eprintln!();
+    result?;
+    String::from_utf8(secret).context("The secret isn't valid UTF-8")
+}
 
-    let root_dir = match dir {
-        Some(d) => d.canonicalize().context("Invalid directory path")?,
-        None => std::env::current_dir()?,
+async fn cmd_csp(project_name: &str, mode: Option<&str>) -> Result<()> {
+    let relax = match mode {
+        Some("relax") | Some("off") => true,
+        Some("enforce") | Some("on") => false,
+        None => {
+            let project = get_project(project_name).await?;
+            let state = if project.proxy.relax_csp {
+                "relaxed"
+            } else {
+                "enforced"
+            };
+            println!("CSP for {}: {}", project.name, state);
+            return Ok(());
+        }
+        Some(other) => anyhow::bail!(
+            "Unknown CSP mode '{}'. Usage: proj {} csp [relax|enforce]",
+            other,
+            project_name
+        ),
     };
 
-    let response = send_request(IpcRequest::CreateProject {
-        name: name.clone(),
-        root_dir: root_dir.clone(),
+    let project = update_project(project_name, |mut project| {
+        project.proxy.relax_csp = relax;
+        Ok(IpcRequest::SetProxyOptions {
+            name: project.name.clone(),
+            options: project.proxy,
+            expected_revision: Some(project.revision),
+        })
     })
     .await?;
 
-    match response {
-        IpcResponse::Project(project) => {
-            println!(
-                "\x1b[32m✓\x1b[0m Created project \x1b[1m{}\x1b[0m",
-                project.name
-            );
-            println!("  Root: {}", project.root_dir.display());
-            println!();
-            println!("Next steps:");
-            println!("  proj {} run <cmd>   Start a dev server", project.name);
-            println!(
-                "  proj {} open        Open in isolated browser",
-                project.name
-            );
-        }
-        IpcResponse::Error { message } => {
-            anyhow::bail!("{}", message);
-        }
-        _ => {
-            anyhow::bail!("Unexpected response from daemon");
-        }
+    if project.proxy.relax_csp {
+        println!(
+            "\x1b[33m⚠\x1b[0m CSP and X-Frame-Options \x1b[1mstripped\x1b[0m for {} (dev only, do not rely on this in production)",
+            project.name
+        );
+    } else {
+        println!("\x1b[32m✓\x1b[0m CSP enforced for {}", project.name);
     }
 
     Ok(())
 }
 
-/// Run a command in project context
-async fn cmd_run(project_name: String, command: Vec<String>) -> Result<()> {
-    if command.is_empty() {
-        anyhow::bail!("No command specified");
+/// Show or toggle permissive CORS on a project's responses: `cors [on|off]`
+async fn cmd_cors(project_name: &str, mode: Option<&str>) -> Result<()> {
+    let cors = match mode {
+        Some("on") => true,
+        Some("off") => false,
+        None => {
+            let project = get_project(project_name).await?;
+            let state = if project.proxy.cors {
+                "any origin allowed"
+            } else {
+                "off"
+            };
+            println!("CORS for {}: {}", project.name, state);
+            return Ok(());
+        }
+        Some(other) => anyhow::bail!(
+            "Unknown CORS mode '{}'. Usage: proj {} cors [on|off]",
+            other,
+            project_name
+        ),
+    };
+
+    let project = update_project(project_name, |mut project| {
+        project.proxy.cors = cors;
+        Ok(IpcRequest::SetProxyOptions {
+            name: project.name.clone(),
+            options: project.proxy,
+            expected_revision: Some(project.revision),
+        })
+    })
+    .await?;
+
+    if project.proxy.cors {
+        println!(
+            "\x1b[33m⚠\x1b[0m {} now allows requests from \x1b[1many origin\x1b[0m, with credentials (dev only)",
+            project.name
+        );
+    } else {
+        println!("\x1b[32m✓\x1b[0m CORS headers left to {}", project.name);
     }
+    Ok(())
+}
 
-    let cmd = command[0].clone();
-    let args = command[1..].to_vec();
+/// Show or set whether the daemon echoes a project's output in its own log:
+/// `passthrough [on|off|default]`
+async fn cmd_passthrough(project_name: &str, mode: Option<&str>) -> Result<()> {
+    let passthrough = match mode {
+        Some("on") => Some(true),
+        Some("off") => Some(false),
+        Some("default") => None,
+        None => {
+            let project = get_project(project_name).await?;
+            let state = match (project.output_passthrough, load_config().output_passthrough) {
+                (Some(true), _) => "on",
+                (Some(false), _) => "off",
+                (None, true) => "on (default)",
+                (None, false) => "off (default)",
+            };
+            println!("Output passthrough for {}: {}", project.name, state);
+            return Ok(());
+        }
+        Some(other) => anyhow::bail!(
+            "Unknown passthrough mode '{}'. Usage: proj {} passthrough [on|off|default]",
+            other,
+            project_name
+        ),
+    };
 
-    println!(
-        "\x1b[36m▶\x1b[0m Running in \x1b[1m{}\x1b[0m: {} {}",
-        project_name,
-        cmd,
-        args.join(" ")
-    );
+    let project = update_project(project_name, |project| {
+        Ok(IpcRequest::SetOutputPassthrough {
+            name: project.name.clone(),
+            passthrough,
+            expected_revision: Some(project.revision),
+        })
+    })
+    .await?;
 
-    let response = send_request(IpcRequest::RunCommand {
-        project_name: project_name.clone(),
-        command: cmd,
-        args,
+    match project.output_passthrough {
+        Some(true) => println!(
+            "\x1b[32m✓\x1b[0m The daemon's log now shows {}'s output",
+            project.name
+        ),
+        Some(false) => println!(
+            "\x1b[32m✓\x1b[0m The daemon's log no longer shows {}'s output",
+            project.name
+        ),
+        None => println!(
+            "\x1b[32m✓\x1b[0m {} follows output_passthrough in the config",
+            project.name
+        ),
+    }
+    Ok(())
+}
+
+/// Show or toggle browser caching of a project's responses: `cache [on|off]`
+async fn cmd_cache(project_name: &str, mode: Option<&str>) -> Result<()> {
+    let no_cache = match mode {
+        Some("off") => true,
+        Some("on") => false,
+        None => {
+            let project = get_project(project_name).await?;
+            let state = if project.proxy.no_cache {
+                "off"
+            } else {
+                "as the server says"
+            };
+            println!("Browser caching for {}: {}", project.name, state);
+            return Ok(());
+        }
+        Some(other) => anyhow::bail!(
+            "Unknown cache mode '{}'. Usage: proj {} cache [on|off]",
+            other,
+            project_name
+        ),
+    };
+
+    let project = update_project(project_name, |mut project| {
+        project.proxy.no_cache = no_cache;
+        Ok(IpcRequest::SetProxyOptions {
+            name: project.name.clone(),
+            options: project.proxy,
+            expected_revision: Some(project.revision),
+        })
     })
     .await?;
 
-    match response {
-        IpcResponse::ProcessStarted { process } => {
-            println!("  PID: {}", process.pid);
-            println!();
+    if project.proxy.no_cache {
+        println!(
+            "\x1b[33m⚠\x1b[0m Browsers now fetch every response from {} afresh (dev only)",
+            project.name
+        );
+        println!("  A service worker already installed may still answer from its own cache;");
+        println!("  it picks up a new script on the next load");
+    } else {
+        println!("\x1b[32m✓\x1b[0m Caching headers left to {}", project.name);
+    }
+    Ok(())
+}
+
+/// Escape a broken service worker: `sw [kill|allow|clear]`
+async fn cmd_sw(project_name: &str, action: Option<&str>) -> Result<()> {
+    let kill = match action {
+        Some("kill") => true,
+        Some("allow") => false,
+        Some("clear") => {
+            let project = get_project(project_name).await?;
+            let url = format!("{}{}", project_url(&project.name), CLEAR_SITE_DATA_PATH);
+            let browser = project_browser(&project, None)?;
+            match browser.open(&project.name, &url)? {
+                Opened::Isolated => println!(
+                    "\x1b[32m✓\x1b[0m Cleared {}'s cache and storage in its browser profile",
+                    project.name
+                ),
+                Opened::Default => println!(
+                    "\x1b[33m⚠\x1b[0m Cleared {}'s cache and storage in your default browser. {}",
+                    project.name,
+                    browser.fallback_notice()
+                ),
+            }
+            return Ok(());
+        }
+        None => {
+            let project = get_project(project_name).await?;
+            let state = if project.proxy.kill_service_workers {
+                "killed on their next update check"
+            } else {
+                "served by the project"
+            };
+            println!("Service workers for {}: {}", project.name, state);
             println!(
-                "\x1b[32m✓\x1b[0m Access at: \x1b[4mhttp://{}.localhost:8080\x1b[0m",
-                project_name
+                "Clear site data in any browser: {}{}",
+                project_url(&project.name),
+                CLEAR_SITE_DATA_PATH
             );
-            println!("  Stop with: proj {} stop", project_name);
-        }
-        IpcResponse::Error { message } => {
-            anyhow::bail!("{}", message);
-        }
-        _ => {
-            anyhow::bail!("Unexpected response from daemon");
+            return Ok(());
         }
-    }
+        Some(other) => anyhow::bail!(
+            "Unknown sw action '{}'. Usage: proj {} sw [kill|allow|clear]",
+            other,
+            project_name
+        ),
+    };
+
+    let project = update_project(project_name, |mut project| {
+        project.proxy.kill_service_workers = kill;
+        Ok(IpcRequest::SetProxyOptions {
+            name: project.name.clone(),
+            options: project.proxy,
+            expected_revision: Some(project.revision),
+        })
+    })
+    .await?;
 
+    if project.proxy.kill_service_workers {
+        println!(
+            "\x1b[33m⚠\x1b[0m Service worker scripts from {} are replaced by one that clears",
+            project.name
+        );
+        println!("  its caches and unregisters itself; reload the page to pick it up (dev only)");
+    } else {
+        println!(
+            "\x1b[32m✓\x1b[0m Service worker scripts left to {}",
+            project.name
+        );
+    }
     Ok(())
 }
 
-/// Open browser for a project
-async fn cmd_open(project_name: String) -> Result<()> {
-    // Get project info to verify it exists
-    let response = send_request(IpcRequest::GetProject {
-        name: project_name.clone(),
+/// Show or change the Host header a project's backend gets:
+/// `host-header [rewrite|keep]`
+async fn cmd_host_header(project_name: &str, mode: Option<&str>) -> Result<()> {
+    let rewrite = match mode {
+        Some("rewrite") => true,
+        Some("keep") => false,
+        None => {
+            let project = get_project(project_name).await?;
+            println!(
+                "Host header for {}: {}",
+                project.name,
+                host_header(&project)
+            );
+            return Ok(());
+        }
+        Some(other) => anyhow::bail!(
+            "Unknown Host header mode '{}'. Usage: proj {} host-header [rewrite|keep]",
+            other,
+            project_name
+        ),
+    };
+
+    let project = update_project(project_name, |mut project| {
+        project.proxy.rewrite_host = rewrite;
+        Ok(IpcRequest::SetProxyOptions {
+            name: project.name.clone(),
+            options: project.proxy,
+            expected_revision: Some(project.revision),
+        })
     })
     .await?;
 
-    let project = match response {
-        IpcResponse::Project(p) => p,
-        IpcResponse::Error { message } => {
-            anyhow::bail!("{}", message);
-        }
-        _ => {
-            anyhow::bail!("Unexpected response from daemon");
+    println!(
+        "\x1b[32m✓\x1b[0m {} now gets Host: {}",
+        project.name,
+        host_header(&project)
+    );
+    Ok(())
+}
+
+async fn cmd_redirects(project_name: &str, mode: Option<&str>) -> Result<()> {
+    let keep = match mode {
+        Some("rewrite") => false,
+        Some("keep") => true,
+        None => {
+            let project = get_project(project_name).await?;
+            println!(
+                "Redirects to {}'s own address: {}",
+                project.name,
+                redirect_mode(&project)
+            );
+            return Ok(());
         }
+        Some(other) => anyhow::bail!(
+            "Unknown redirect mode '{}'. Usage: proj {} redirects [rewrite|keep]",
+            other,
+            project_name
+        ),
     };
 
-    // Chrome profile directory
-    let chrome_dir = project_dir(&project.name)?.join("chrome");
-
-    // URL to open
-    let url = format!("http://{}.localhost:8080", project.name);
+    let project = update_project(project_name, |mut project| {
+        project.proxy.keep_redirects = keep;
+        Ok(IpcRequest::SetProxyOptions {
+            name: project.name.clone(),
+            options: project.proxy,
+            expected_revision: Some(project.revision),
+        })
+    })
+    .await?;
 
     println!(
-        "\x1b[36m▶\x1b[0m Opening \x1b[4m{}\x1b[0m with isolated Chrome profile",
-        url
+        "\x1b[32m✓\x1b[0m Redirects to {}'s own address are now {}",
+        project.name,
+        redirect_mode(&project)
     );
+    Ok(())
+}
 
-    // Open Chrome with isolated profile
-    #[cfg(target_os = "macos")]
-    {
-        std::process::Command::new("open")
-            .args([
-                "-na",
-                "Google Chrome",
-                "--args",
-                &format!("--user-data-dir={}", chrome_dir.display()),
-                &url,
-            ])
-            .spawn()
-            .context("Failed to open Chrome. Is it installed?")?;
+/// What happens to a backend's redirects to itself, for display
+fn redirect_mode(project: &Project) -> String {
+    if project.proxy.keep_redirects {
+        "passed on as they are".to_string()
+    } else {
+        format!("pointed at {}", project_host(&project.name))
+    }
+}
+
+async fn cmd_trailing_slash(project_name: &str, mode: Option<&str>) -> Result<()> {
+    let mode: TrailingSlash = match mode {
+        Some(mode) => mode.parse()?,
+        None => {
+            let project = get_project(project_name).await?;
+            println!(
+                "Trailing slashes for {}: {}",
+                project.name, project.proxy.trailing_slash
+            );
+            return Ok(());
+        }
+    };
+
+    let project = update_project(project_name, |mut project| {
+        project.proxy.trailing_slash = mode;
+        Ok(IpcRequest::SetProxyOptions {
+            name: project.name.clone(),
+            options: project.proxy,
+            expected_revision: Some(project.revision),
+        })
+    })
+    .await?;
+
+    let effect = match mode {
+        TrailingSlash::Keep => "passed on as they are",
+        TrailingSlash::Add => "redirected to end with a slash",
+        TrailingSlash::Remove => "redirected to drop a trailing slash",
+    };
+    println!("\x1b[32m✓\x1b[0m {} paths are now {}", project.name, effect);
+    Ok(())
+}
+
+/// The Host header a project's backend gets, for display
+fn host_header(project: &Project) -> String {
+    if project.proxy.rewrite_host {
+        format!("{}:<port>", project.proxy.upstream_authority())
+    } else {
+        project_host(&project.name)
+    }
+}
+
+/// `upstream [<host>|local]`: the machine the proxy sends a project's
+/// requests to, for projects running on another box
+async fn cmd_upstream(project_name: &str, host: Option<&str>) -> Result<()> {
+    let upstream_host = match host {
+        None => {
+            let project = get_project(project_name).await?;
+            let host = project.proxy.upstream_host.as_deref();
+            println!(
+                "{} runs on {}",
+                project.name,
+                host.unwrap_or("this machine")
+            );
+            return Ok(());
+        }
+        Some("local") => None,
+        Some(host) => Some(naming::normalize_upstream_host(host)?),
+    };
+
+    let project = update_project(project_name, |mut project| {
+        project.proxy.upstream_host = upstream_host.clone();
+        Ok(IpcRequest::SetProxyOptions {
+            name: project.name.clone(),
+            options: project.proxy,
+            expected_revision: Some(project.revision),
+        })
+    })
+    .await?;
+
+    match &project.proxy.upstream_host {
+        Some(host) => println!(
+            "\x1b[32m✓\x1b[0m {} now proxies to {} (add routes for the ports it serves)",
+            project_host(&project.name),
+            host
+        ),
+        None => println!(
+            "\x1b[32m✓\x1b[0m {} now proxies to this machine",
+            project_host(&project.name)
+        ),
+    }
+    Ok(())
+}
+
+/// Show or set how long a project may go without proxied requests before its
+/// processes are stopped: `idle [<minutes>|off]`
+async fn cmd_idle(project_name: &str, mins: Option<&str>) -> Result<()> {
+    let idle_stop_mins = match mins {
+        None => {
+            let project = get_project(project_name).await?;
+            match project.proxy.idle_stop_mins {
+                Some(mins) => {
+                    println!("{} stops after {} min without requests", project.name, mins)
+                }
+                None => println!("{} keeps running while idle", project.name),
+            }
+            return Ok(());
+        }
+        Some("off") => None,
+        Some(mins) => match mins.parse::<u64>() {
+            Ok(mins) if mins > 0 => Some(mins),
+            _ => anyhow::bail!(
+                "'{}' isn't a number of minutes. Usage: proj {} idle [<minutes>|off]",
+                mins,
+                project_name
+            ),
+        },
+    };
+
+    let project = update_project(project_name, |mut project| {
+        project.proxy.idle_stop_mins = idle_stop_mins;
+        Ok(IpcRequest::SetProxyOptions {
+            name: project.name.clone(),
+            options: project.proxy,
+            expected_revision: Some(project.revision),
+        })
+    })
+    .await?;
+
+    match project.proxy.idle_stop_mins {
+        Some(mins) => println!(
+            "\x1b[32m✓\x1b[0m {} stops after {} min without requests to {}",
+            project.name,
+            mins,
+            project_host(&project.name)
+        ),
+        None => println!("\x1b[32m✓\x1b[0m {} keeps running while idle", project.name),
+    }
+    Ok(())
+}
+
+/// Show or set how many requests the proxy forwards to a project at once:
+/// `max-in-flight [<n>|off]`
+async fn cmd_max_in_flight(project_name: &str, limit: Option<&str>) -> Result<()> {
+    let max_in_flight = match limit {
+        None => {
+            let project = get_project(project_name).await?;
+            match project.proxy.max_in_flight {
+                Some(limit) => println!("{} takes {} requests at a time", project.name, limit),
+                None => println!("{} takes any number of requests at a time", project.name),
+            }
+            return Ok(());
+        }
+        Some("off") => None,
+        Some(limit) => match limit.parse::<usize>() {
+            Ok(limit) if limit > 0 => Some(limit),
+            _ => anyhow::bail!(
+                "'{}' isn't a number of requests. Usage: proj {} max-in-flight [<n>|off]",
+                limit,
+                project_name
+            ),
+        },
+    };
+
+    let project = update_project(project_name, |mut project| {
+        project.proxy.max_in_flight = max_in_flight;
+        Ok(IpcRequest::SetProxyOptions {
+            name: project.name.clone(),
+            options: project.proxy,
+            expected_revision: Some(project.revision),
+        })
+    })
+    .await?;
+
+    match project.proxy.max_in_flight {
+        Some(limit) => println!(
+            "\x1b[32m✓\x1b[0m {} takes {} requests at a time; the rest queue, and browsers see a page that retries",
+            project_host(&project.name),
+            limit
+        ),
+        None => println!(
+            "\x1b[32m✓\x1b[0m {} takes any number of requests at a time",
+            project.name
+        ),
+    }
+    Ok(())
+}
+
+/// Show or set whether a request starts the project when nothing runs:
+/// `lazy [on|off]`
+async fn cmd_lazy(project_name: &str, mode: Option<&str>) -> Result<()> {
+    let lazy_start = match mode {
+        Some("on") => true,
+        Some("off") => false,
+        None => {
+            let project = get_project(project_name).await?;
+            let state = if project.proxy.lazy_start {
+                "on"
+            } else {
+                "off"
+            };
+            println!("Lazy start for {}: {}", project.name, state);
+            return Ok(());
+        }
+        Some(other) => anyhow::bail!(
+            "Unknown lazy start mode '{}'. Usage: proj {} lazy [on|off]",
+            other,
+            project_name
+        ),
+    };
+
+    let project = update_project(project_name, |mut project| {
+        project.proxy.lazy_start = lazy_start;
+        Ok(IpcRequest::SetProxyOptions {
+            name: project.name.clone(),
+            options: project.proxy,
+            expected_revision: Some(project.revision),
+        })
+    })
+    .await?;
+
+    if !project.proxy.lazy_start {
+        println!("\x1b[32m✓\x1b[0m {} only runs when started", project.name);
+    } else if project.services.is_empty() && project.run_command.is_none() {
+        println!(
+            "\x1b[33m⚠\x1b[0m Lazy start on, but {} has no services or run command to start yet",
+            project.name
+        );
+    } else {
+        println!(
+            "\x1b[32m✓\x1b[0m Requests to {} start {} when it isn't running",
+            project_host(&project.name),
+            project.name
+        );
+    }
+    Ok(())
+}
+
+/// Inspect captured proxy traffic: `requests ls` / `requests as-curl <id>`
+async fn cmd_requests(project_name: &str, args: Vec<String>) -> Result<()> {
+    let usage = format!(
+        "Usage: proj {} requests ls [--limit N] | as-curl <id> [--httpie] [--backend]",
+        project_name
+    );
+    let (action, rest) = args.split_first().context(usage.clone())?;
+
+    match action.as_str() {
+        "ls" => {
+            let limit = match rest {
+                [flag, n] if flag == "--limit" => n.parse().context("--limit expects a number")?,
+                [] => 20,
+                _ => anyhow::bail!(usage),
+            };
+            let response = send_request(IpcRequest::ListRequests {
+                project_name: project_name.to_string(),
+                limit,
+            })
+            .await?;
+            let requests = match response {
+                IpcResponse::Requests(r) => r,
+                IpcResponse::Error(error) => return Err(error.into()),
+                _ => anyhow::bail!(tr!("unexpected-response")),
+            };
+
+            if requests.is_empty() {
+                println!("No captured requests for '{}'", project_name);
+                return Ok(());
+            }
+            for r in requests {
+                let status = r
+                    .status
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| "ERR".to_string());
+                println!(
+                    "{:>5}  {}  {:<6} {:<3} {:>5}ms  {}",
+                    r.id,
+                    r.timestamp.format("%H:%M:%S"),
+                    r.method,
+                    status,
+                    r.duration_ms.unwrap_or(0),
+                    r.path
+                );
+            }
+        }
+        "as-curl" | "as-httpie" => {
+            let id: u64 = rest
+                .first()
+                .context(usage.clone())?
+                .parse()
+                .context("Request ID must be a number")?;
+            let httpie = action == "as-httpie" || rest.iter().any(|a| a == "--httpie");
+            let backend = rest.iter().any(|a| a == "--backend");
+
+            let response = send_request(IpcRequest::GetRequest {
+                project_name: project_name.to_string(),
+                id,
+            })
+            .await?;
+            let request = match response {
+                IpcResponse::Request(r) => r,
+                IpcResponse::Error(error) => return Err(error.into()),
+                _ => anyhow::bail!(tr!("unexpected-response")),
+            };
+
+            let base = if backend {
+                format!("http://127.0.0.1:{}", request.upstream_port)
+            } else {
+                project_url(project_name)
+            };
+            println!("{}", request_snippet(&request, &base, httpie));
+        }
+        _ => anyhow::bail!(usage),
+    }
+
+    Ok(())
+}
+
+/// Show or configure OpenAPI drift detection: `api-drift [spec <path>|clear]`
+async fn cmd_api_drift(project_name: &str, args: Vec<String>) -> Result<()> {
+    match args.first().map(String::as_str) {
+        Some("spec") => {
+            let project = update_project(project_name, |mut project| {
+                project.proxy.openapi_spec = match args.get(1).map(String::as_str) {
+                    Some("none") => None,
+                    Some(path) => {
+                        let path = project.root_dir.join(path);
+                        Some(path.canonicalize().context("OpenAPI spec not found")?)
+                    }
+                    None => {
+                        anyhow::bail!("Usage: proj {} api-drift spec <path|none>", project_name)
+                    }
+                };
+                Ok(IpcRequest::SetProxyOptions {
+                    name: project.name.clone(),
+                    options: project.proxy,
+                    expected_revision: Some(project.revision),
+                })
+            })
+            .await?;
+            match project.proxy.openapi_spec {
+                Some(spec) => println!(
+                    "\x1b[32m✓\x1b[0m Validating {} responses against {}",
+                    project.name,
+                    spec.display()
+                ),
+                None => println!("Drift detection disabled for {}", project.name),
+            }
+        }
+        Some("clear") => {
+            send_request(IpcRequest::ClearApiDrift {
+                project_name: project_name.to_string(),
+            })
+            .await?;
+            println!("Cleared API drift reports for {}", project_name);
+        }
+        None => {
+            let response = send_request(IpcRequest::GetApiDrift {
+                project_name: project_name.to_string(),
+            })
+            .await?;
+            let reports = match response {
+                IpcResponse::ApiDrift(r) => r,
+                IpcResponse::Error(error) => return Err(error.into()),
+                _ => anyhow::bail!(tr!("unexpected-response")),
+            };
+            if reports.is_empty() {
+                println!(
+                    "\x1b[32m✓\x1b[0m No API drift observed for {}",
+                    project_name
+                );
+                return Ok(());
+            }
+            for r in reports {
+                println!(
+                    "{}  {:<6} {} {}  \x1b[33m{}\x1b[0m",
+                    r.timestamp.format("%H:%M:%S"),
+                    r.method,
+                    r.path,
+                    r.status,
+                    r.message
+                );
+            }
+        }
+        Some(other) => anyhow::bail!("Unknown api-drift action: {}", other),
+    }
+
+    Ok(())
+}
+
+/// Show or set a project's performance budgets:
+/// `budget [set p95 <ms>|set document <size>|clear [p95|document]]`
+async fn cmd_budget(project_name: &str, args: Vec<String>) -> Result<()> {
+    let usage = || {
+        format!(
+            "Usage: proj {} budget [set p95 <ms>|set document <size>|clear [p95|document]]",
+            project_name
+        )
+    };
+    let changes: Vec<(BudgetMetric, Option<u64>)> = match args.as_slice() {
+        [] => {
+            let statuses = fetch_budgets(project_name).await?;
+            if statuses.iter().all(|s| s.limit.is_none()) {
+                println!("No performance budgets for {}", project_name);
+                println!("  Set one with: proj {} budget set p95 200ms", project_name);
+                return Ok(());
+            }
+            for status in statuses {
+                print_budget(&status);
+            }
+            return Ok(());
+        }
+        [action, metric, limit] if action == "set" => {
+            let metric: BudgetMetric = metric.parse()?;
+            let limit = match metric {
+                BudgetMetric::P95Latency => {
+                    let ms = limit.strip_suffix("ms").unwrap_or(limit);
+                    ms.trim()
+                        .parse()
+                        .with_context(|| format!("Invalid latency '{}' (e.g. 200ms)", limit))?
+                }
+                BudgetMetric::DocumentSize => parse_size(limit)?,
+            };
+            if limit == 0 {
+                anyhow::bail!("A budget must be above zero");
+            }
+            vec![(metric, Some(limit))]
+        }
+        [action] if action == "clear" => BudgetMetric::ALL.map(|m| (m, None)).into(),
+        [action, metric] if action == "clear" => vec![(metric.parse()?, None)],
+        _ => anyhow::bail!("{}", usage()),
+    };
+
+    let project = update_project(project_name, |mut project| {
+        for (metric, limit) in &changes {
+            project.proxy.budgets.set(*metric, *limit);
+        }
+        Ok(IpcRequest::SetProxyOptions {
+            name: project.name.clone(),
+            options: project.proxy,
+            expected_revision: Some(project.revision),
+        })
+    })
+    .await?;
+
+    for (metric, limit) in changes {
+        match limit {
+            Some(limit) => println!(
+                "\x1b[32m✓\x1b[0m {} budget for {} set to {}",
+                metric,
+                project.name,
+                metric.format(limit)
+            ),
+            None => println!("Cleared the {} budget for {}", metric, project.name),
+        }
+    }
+    Ok(())
+}
+
+/// Where a project's proxied traffic stands against its budgets
+async fn fetch_budgets(project_name: &str) -> Result<Vec<BudgetStatus>> {
+    match send_request(IpcRequest::GetBudgets {
+        project_name: project_name.to_string(),
+    })
+    .await?
+    {
+        IpcResponse::Budgets(statuses) => Ok(statuses),
+        IpcResponse::Error(error) => Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    }
+}
+
+fn print_budget(status: &BudgetStatus) {
+    let Some(limit) = status.limit else {
+        return;
+    };
+    let value = status
+        .value
+        .map_or("-".to_string(), |value| status.metric.format(value));
+    let state = if status.exceeded {
+        "\x1b[31mover\x1b[0m"
+    } else if status.value.is_some() {
+        "\x1b[32mok\x1b[0m"
+    } else {
+        "\x1b[90mnot measured yet\x1b[0m"
+    };
+    println!(
+        "  {:<14} {:>10} / {:<10} {} \x1b[90m({} samples)\x1b[0m",
+        status.metric.to_string(),
+        value,
+        status.metric.format(limit),
+        state,
+        status.samples
+    );
+}
+
+/// Manage a project's maintenance windows:
+/// `schedule set <HH:MM-HH:MM>... [--days mon-fri] [-- <cmd>]`, `schedule clear`,
+/// `schedule on|off [--for 2h]` to override, `schedule auto` to follow the windows again
+async fn cmd_schedule(project_name: &str, args: Vec<String>) -> Result<()> {
+    if args.is_empty() {
+        let project = get_project(project_name).await?;
+        print_schedules(&[project], Local::now());
+        return Ok(());
+    }
+
+    let project = update_project(project_name, |project| {
+        Ok(IpcRequest::SetSchedule {
+            name: project.name.clone(),
+            schedule: edit_schedule(&project, &args)?,
+            expected_revision: Some(project.revision),
+        })
+    })
+    .await?;
+
+    if project.schedule.is_some() {
+        print_schedules(&[project], Local::now());
+    } else {
+        println!("\x1b[32m✓\x1b[0m Schedule cleared for {}", project.name);
+    }
+
+    Ok(())
+}
+
+/// Manage lifecycle hooks: `hooks [ls]`, `hooks set <hook> <cmd>`, `hooks unset <hook>`
+async fn cmd_hooks(project_name: &str, args: Vec<String>) -> Result<()> {
+    let usage = || {
+        anyhow::anyhow!(
+            "Usage: proj {} hooks [ls | set <hook> <cmd> | unset <hook>] (hooks: {})",
+            project_name,
+            Hooks::NAMES.join(", ")
+        )
+    };
+    let edit: Option<(&String, Option<String>)> = match args.split_first() {
+        None => None,
+        Some((action, rest)) => match (action.as_str(), rest) {
+            ("ls", []) => None,
+            ("set", [hook, command @ ..]) if !command.is_empty() => {
+                let command = match command.split_first() {
+                    Some((dashes, command)) if dashes == "--" => command,
+                    _ => command,
+                };
+                Some((hook, Some(command.join(" "))))
+            }
+            ("unset", [hook]) => Some((hook, None)),
+            _ => return Err(usage()),
+        },
+    };
+
+    let project = match edit {
+        None => get_project(project_name).await?,
+        Some((hook, command)) => {
+            if !Hooks::NAMES.contains(&hook.as_str()) {
+                return Err(usage());
+            }
+            update_project(project_name, |project| {
+                let mut hooks = project.hooks.clone();
+                *hooks.get_mut(hook).expect("checked above") = command.clone();
+                Ok(IpcRequest::SetHooks {
+                    name: project.name.clone(),
+                    hooks,
+                    expected_revision: Some(project.revision),
+                })
+            })
+            .await?
+        }
+    };
+
+    let hooks = [
+        ("pre_run", &project.hooks.pre_run),
+        ("post_stop", &project.hooks.post_stop),
+        ("on_crash", &project.hooks.on_crash),
+    ];
+    if hooks.iter().all(|(_, command)| command.is_none()) {
+        println!(
+            "No hooks (set one with: proj {} hooks set pre_run <cmd>)",
+            project.name
+        );
+        return Ok(());
+    }
+    for (name, command) in hooks {
+        match command {
+            Some(command) => println!("{:<10} {}", name, command),
+            None => println!("{:<10} \x1b[90m-\x1b[0m", name),
+        }
+    }
+    Ok(())
+}
+
+/// Copy sections of another project's configuration into this one:
+/// `copy-config --from <project> [--env] [--routes] [--hooks]`, all three
+/// when none is picked. Copied entries replace ones of the same name.
+async fn cmd_copy_config(project_name: &str, args: Vec<String>) -> Result<()> {
+    let usage = || {
+        anyhow::anyhow!(
+            "Usage: proj {} copy-config --from <project> [--env] [--routes] [--hooks]",
+            project_name
+        )
+    };
+    let (mut from, mut env, mut routes, mut hooks) = (None, false, false, false);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" => from = Some(args.next().ok_or_else(usage)?.clone()),
+            "--env" => env = true,
+            "--routes" => routes = true,
+            "--hooks" => hooks = true,
+            _ => return Err(usage()),
+        }
+    }
+    let from = from.ok_or_else(usage)?;
+    if !(env || routes || hooks) {
+        (env, routes, hooks) = (true, true, true);
+    }
+    let source = get_project(&from).await?;
+    if source.name == project_name {
+        anyhow::bail!("Can't copy {}'s configuration into itself", project_name);
+    }
+
+    let mut copied = Vec::new();
+    if env {
+        let vars: BTreeMap<String, String> = match send_request(IpcRequest::GetEnv {
+            project_name: source.name.clone(),
+        })
+        .await?
+        {
+            IpcResponse::Env(vars) => vars
+                .into_iter()
+                .filter(|var| var.source == EnvSource::Project)
+                .map(|var| (var.key, var.value))
+                .collect(),
+            IpcResponse::Error(error) => return Err(error.into()),
+            _ => anyhow::bail!(tr!("unexpected-response")),
+        };
+        if !vars.is_empty() {
+            copied.push(format!("{} variable(s)", vars.len()));
+            match send_request(IpcRequest::SetEnv {
+                project_name: project_name.to_string(),
+                vars,
+            })
+            .await?
+            {
+                IpcResponse::Env(_) => {}
+                IpcResponse::Error(error) => return Err(error.into()),
+                _ => anyhow::bail!(tr!("unexpected-response")),
+            }
+        }
+        if !source.env_profiles.is_empty() {
+            copied.push(format!("env profiles {}", source.env_profiles.join(", ")));
+            update_project(project_name, |project| {
+                let mut profiles = project.env_profiles.clone();
+                profiles.extend(
+                    source
+                        .env_profiles
+                        .iter()
+                        .filter(|profile| !project.env_profiles.contains(profile))
+                        .cloned(),
+                );
+                Ok(IpcRequest::SetEnvProfiles {
+                    project_name: project.name.clone(),
+                    profiles,
+                    expected_revision: Some(project.revision),
+                })
+            })
+            .await?;
+        }
+    }
+    if routes && !source.proxy.routes.is_empty() {
+        copied.push(format!("{} route(s)", source.proxy.routes.len()));
+        update_project(project_name, |mut project| {
+            project.proxy.routes.extend(source.proxy.routes.clone());
+            Ok(IpcRequest::SetProxyOptions {
+                name: project.name.clone(),
+                options: project.proxy,
+                expected_revision: Some(project.revision),
+            })
+        })
+        .await?;
+    }
+    if hooks {
+        let set: Vec<&str> = Hooks::NAMES
+            .iter()
+            .copied()
+            .filter(|&hook| source.hooks.get(hook).is_some())
+            .collect();
+        if !set.is_empty() {
+            copied.push(format!("hooks {}", set.join(", ")));
+            update_project(project_name, |project| {
+                let mut hooks = project.hooks.clone();
+                for hook in &set {
+                    let command = source.hooks.get(hook).map(str::to_string);
+                    *hooks.get_mut(hook).expect("a hook name") = command;
+                }
+                Ok(IpcRequest::SetHooks {
+                    name: project.name.clone(),
+                    hooks,
+                    expected_revision: Some(project.revision),
+                })
+            })
+            .await?;
+        }
+    }
+
+    if copied.is_empty() {
+        println!("{} has nothing of that to copy", source.name);
+        return Ok(());
+    }
+    println!(
+        "\x1b[32m✓\x1b[0m Copied from {} to {}:",
+        source.name, project_name
+    );
+    for section in copied {
+        println!("  {}", section);
+    }
+    if env {
+        println!("\x1b[90mEnv changes apply to processes started from now on\x1b[0m");
+    }
+    Ok(())
+}
+
+/// Manage dependencies: `deps [ls]`, `deps add <project>...`, `deps rm <project>...`
+async fn cmd_deps(project_name: &str, args: Vec<String>) -> Result<()> {
+    let project = match args.split_first() {
+        None => get_project(project_name).await?,
+        Some((action, [])) if action == "ls" => get_project(project_name).await?,
+        Some((action, names)) if !names.is_empty() && (action == "add" || action == "rm") => {
+            update_project(project_name, |project| {
+                let mut depends_on = project.depends_on.clone();
+                for name in names {
+                    if action == "add" && !depends_on.contains(name) {
+                        depends_on.push(name.clone());
+                    } else if action == "rm" {
+                        depends_on.retain(|d| d != name);
+                    }
+                }
+                Ok(IpcRequest::SetDependencies {
+                    name: project.name.clone(),
+                    depends_on,
+                    expected_revision: Some(project.revision),
+                })
+            })
+            .await?
+        }
+        _ => anyhow::bail!(
+            "Usage: proj {} deps [ls | add <project>... | rm <project>...]",
+            project_name
+        ),
+    };
+
+    if project.depends_on.is_empty() {
+        println!(
+            "{} has no dependencies (add one with: proj {} deps add <project>)",
+            project.name, project.name
+        );
+        return Ok(());
+    }
+    println!("{} depends on:", project.name);
+    for name in &project.depends_on {
+        println!("  {}", name);
+    }
+    Ok(())
+}
+
+/// Show or change the priority a project's processes run at:
+/// `renice [<nice>] [--io idle|best-effort]`, `renice --reset`
+async fn cmd_renice(project_name: &str, args: Vec<String>) -> Result<()> {
+    let usage = || {
+        anyhow::anyhow!(
+            "Usage: proj {} renice [<nice>] [--io idle|best-effort] | --reset",
+            project_name
+        )
+    };
+    let mut nice = None;
+    let mut io = None;
+    let mut reset = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--reset" => reset = true,
+            "--io" => io = Some(args.next().ok_or_else(usage)?.parse::<IoClass>()?),
+            value => {
+                nice = Some(
+                    value
+                        .parse::<i32>()
+                        .map_err(|_| anyhow::anyhow!("Invalid niceness '{}'", value))?,
+                )
+            }
+        }
+    }
+    if reset && (nice.is_some() || io.is_some()) {
+        return Err(usage());
+    }
+
+    let project = if reset || nice.is_some() || io.is_some() {
+        update_project(project_name, |project| {
+            let priority = if reset {
+                // Explicit, so running processes are put back too
+                Priority {
+                    nice: Some(0),
+                    io: Some(IoClass::BestEffort),
+                }
+            } else {
+                Priority {
+                    nice: nice.or(project.priority.nice),
+                    io: io.or(project.priority.io),
+                }
+            };
+            priority.validate()?;
+            Ok(IpcRequest::SetPriority {
+                name: project.name.clone(),
+                priority,
+                expected_revision: Some(project.revision),
+            })
+        })
+        .await?
+    } else {
+        get_project(project_name).await?
+    };
+
+    if project.priority.is_default() {
+        println!("{} runs at normal priority", project.name);
+    } else {
+        println!("{} runs at {}", project.name, project.priority);
+    }
+    Ok(())
+}
+
+/// Show or change the extra hostnames a project is served at:
+/// `alias [ls]`, `alias add <host>... [--write-hosts]`, `alias rm <host>... [--write-hosts]`
+async fn cmd_alias(project_name: &str, args: Vec<String>) -> Result<()> {
+    let usage = || {
+        anyhow::anyhow!(
+            "Usage: proj {} alias [ls | add <host>... [--write-hosts] | rm <host>... [--write-hosts]]",
+            project_name
+        )
+    };
+    let write_hosts = args.iter().any(|arg| arg == "--write-hosts");
+    let args: Vec<&String> = args.iter().filter(|arg| *arg != "--write-hosts").collect();
+    let (action, hosts) = match args.split_first() {
+        None => ("ls", Vec::new()),
+        Some((action, hosts)) => {
+            let hosts = hosts
+                .iter()
+                .map(|host| normalize_alias(host))
+                .collect::<Result<Vec<_>>>()?;
+            (action.as_str(), hosts)
+        }
+    };
+
+    let project = match action {
+        "ls" if hosts.is_empty() && !write_hosts => get_project(project_name).await?,
+        "add" if !hosts.is_empty() => {
+            update_project(project_name, |project| {
+                let mut aliases = project.aliases.clone();
+                aliases.extend(
+                    hosts
+                        .iter()
+                        .filter(|h| !project.aliases.contains(h))
+                        .cloned(),
+                );
+                Ok(IpcRequest::SetAliases {
+                    name: project.name.clone(),
+                    aliases,
+                    expected_revision: Some(project.revision),
+                })
+            })
+            .await?
+        }
+        "rm" if !hosts.is_empty() => {
+            update_project(project_name, |project| {
+                if let Some(host) = hosts.iter().find(|h| !project.aliases.contains(h)) {
+                    anyhow::bail!("{} is not an alias of {}", host, project.name);
+                }
+                Ok(IpcRequest::SetAliases {
+                    name: project.name.clone(),
+                    aliases: project
+                        .aliases
+                        .iter()
+                        .filter(|alias| !hosts.contains(alias))
+                        .cloned()
+                        .collect(),
+                    expected_revision: Some(project.revision),
+                })
+            })
+            .await?
+        }
+        _ => return Err(usage()),
+    };
+
+    let config = load_config();
+    if write_hosts {
+        let changed = if action == "add" {
+            hosts::add(&hosts, config.proxy_addr().ip())?
+        } else {
+            hosts::remove(&hosts)?
+        };
+        let done = if action == "add" { "Added" } else { "Removed" };
+        let place = if action == "add" { "to" } else { "from" };
+        for host in changed {
+            println!(
+                "\x1b[32m✓\x1b[0m {} {} {} the hosts file",
+                done, host, place
+            );
+        }
+    }
+
+    if project.aliases.is_empty() {
+        println!("{} has no aliases", project.name);
+        println!(
+            "Add one with: proj {} alias add app.dev.local",
+            project.name
+        );
+        return Ok(());
+    }
+    for alias in &project.aliases {
+        println!("{}", config.host_url(alias));
+    }
+    // Only .localhost resolves to this machine everywhere without help
+    if action == "add" && !write_hosts {
+        for host in hosts.iter().filter(|host| !resolves_locally(host)) {
+            println!(
+                "\x1b[33m!\x1b[0m {} doesn't resolve to this machine; add --write-hosts to put it in the hosts file",
+                host
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Show or change the labels `proj ls` filters and groups a project by
+async fn cmd_tag(project_name: &str, args: Vec<String>) -> Result<()> {
+    let usage = || {
+        anyhow::anyhow!(
+            "Usage: proj {} tag [ls | add <tag>... | rm <tag>...]",
+            project_name
+        )
+    };
+    let (action, tags) = match args.split_first() {
+        None => ("ls", Vec::new()),
+        Some((action, tags)) => {
+            let tags = tags
+                .iter()
+                .map(|tag| normalize_tag(tag))
+                .collect::<Result<Vec<_>>>()?;
+            (action.as_str(), tags)
+        }
+    };
+
+    let project = match action {
+        "ls" if tags.is_empty() => get_project(project_name).await?,
+        "add" if !tags.is_empty() => {
+            update_project(project_name, |project| {
+                let mut all = project.tags.clone();
+                all.extend(tags.iter().filter(|t| !project.tags.contains(t)).cloned());
+                Ok(IpcRequest::SetTags {
+                    name: project.name.clone(),
+                    tags: all,
+                    expected_revision: Some(project.revision),
+                })
+            })
+            .await?
+        }
+        "rm" if !tags.is_empty() => {
+            update_project(project_name, |project| {
+                if let Some(tag) = tags.iter().find(|t| !project.tags.contains(t)) {
+                    anyhow::bail!("{} isn't tagged {}", project.name, tag);
+                }
+                Ok(IpcRequest::SetTags {
+                    name: project.name.clone(),
+                    tags: project
+                        .tags
+                        .iter()
+                        .filter(|tag| !tags.contains(tag))
+                        .cloned()
+                        .collect(),
+                    expected_revision: Some(project.revision),
+                })
+            })
+            .await?
+        }
+        _ => return Err(usage()),
+    };
+
+    if project.tags.is_empty() {
+        println!("{} has no tags", project.name);
+        println!(
+            "Add some with: proj {} tag add client-x backend",
+            project.name
+        );
+        return Ok(());
+    }
+    for tag in &project.tags {
+        println!("{}", tag);
+    }
+    Ok(())
+}
+
+/// Show or set the emoji a project is shown with: `icon [<emoji>|off]`
+async fn cmd_icon(project_name: &str, icon: Option<&str>) -> Result<()> {
+    let icon = match icon {
+        None => {
+            let project = get_project(project_name).await?;
+            match &project.appearance.icon {
+                Some(icon) => println!("{}", icon),
+                None => println!("{} has no icon", project.name),
+            }
+            return Ok(());
+        }
+        Some("off") => None,
+        Some(icon) => Some(normalize_icon(icon)?),
+    };
+
+    let project = update_project(project_name, |project| {
+        Ok(IpcRequest::SetAppearance {
+            name: project.name.clone(),
+            appearance: Appearance {
+                icon: icon.clone(),
+                ..project.appearance.clone()
+            },
+            expected_revision: Some(project.revision),
+        })
+    })
+    .await?;
+    match &project.appearance.icon {
+        Some(_) => println!("\x1b[32m✓\x1b[0m {}", styled_name(&project)),
+        None => println!("\x1b[32m✓\x1b[0m {} has no icon", project.name),
+    }
+    Ok(())
+}
+
+/// Show or set the color a project's name is shown in: `color [<color>|off]`
+async fn cmd_color(project_name: &str, color: Option<&str>) -> Result<()> {
+    let color = match color {
+        None => {
+            let project = get_project(project_name).await?;
+            match &project.appearance.color {
+                Some(color) => println!("{}", color),
+                None => println!("{} has no color", project.name),
+            }
+            return Ok(());
+        }
+        Some("off") => None,
+        Some(color) => Some(normalize_color(color)?),
+    };
+
+    let project = update_project(project_name, |project| {
+        Ok(IpcRequest::SetAppearance {
+            name: project.name.clone(),
+            appearance: Appearance {
+                color: color.clone(),
+                ..project.appearance.clone()
+            },
+            expected_revision: Some(project.revision),
+        })
+    })
+    .await?;
+    match &project.appearance.color {
+        Some(color) => println!("\x1b[32m✓\x1b[0m {} ({})", styled_name(&project), color),
+        None => println!("\x1b[32m✓\x1b[0m {} has no color", project.name),
+    }
+    Ok(())
+}
+
+/// Whether `host` resolves to a loopback address
+fn resolves_locally(host: &str) -> bool {
+    use std::net::ToSocketAddrs;
+    (host, 80)
+        .to_socket_addrs()
+        .is_ok_and(|mut addrs| addrs.any(|addr| addr.ip().is_loopback()))
+}
+
+/// Show or change how a project's processes are confined:
+/// `sandbox [off|files|offline|read-only] [--allow <dir>]... [--disallow <dir>]...`, `sandbox --reset`
+async fn cmd_sandbox(project_name: &str, args: Vec<String>) -> Result<()> {
+    let usage = || {
+        anyhow::anyhow!(
+            "Usage: proj {} sandbox [off|files|offline|read-only] [--allow <dir>]... [--disallow <dir>]... | --reset",
+            project_name
+        )
+    };
+    let mut mode = None;
+    let mut allow = Vec::new();
+    let mut disallow = Vec::new();
+    let mut reset = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--reset" => reset = true,
+            // The daemon runs elsewhere, so resolve against this shell's directory
+            "--allow" => {
+                let dir = std::env::current_dir()?.join(args.next().ok_or_else(usage)?);
+                if !dir.is_dir() {
+                    anyhow::bail!("Not a directory: {}", dir.display());
+                }
+                allow.push(dir.canonicalize()?);
+            }
+            "--disallow" => {
+                let dir = std::env::current_dir()?.join(args.next().ok_or_else(usage)?);
+                disallow.push(dir.canonicalize().unwrap_or(dir));
+            }
+            value if mode.is_none() => mode = Some(value.parse::<SandboxMode>()?),
+            _ => return Err(usage()),
+        }
+    }
+    if reset && (mode.is_some() || !allow.is_empty() || !disallow.is_empty()) {
+        return Err(usage());
+    }
+
+    let project = if reset || mode.is_some() || !allow.is_empty() || !disallow.is_empty() {
+        update_project(project_name, |project| {
+            let sandbox = if reset {
+                Sandbox::default()
+            } else {
+                let mut sandbox = project.sandbox.clone();
+                if let Some(mode) = mode {
+                    sandbox.mode = mode;
+                }
+                sandbox.writable.retain(|dir| !disallow.contains(dir));
+                for dir in &allow {
+                    if !sandbox.writable.contains(dir) {
+                        sandbox.writable.push(dir.clone());
+                    }
+                }
+                sandbox
+            };
+            Ok(IpcRequest::SetSandbox {
+                name: project.name.clone(),
+                sandbox,
+                expected_revision: Some(project.revision),
+            })
+        })
+        .await?
+    } else {
+        get_project(project_name).await?
+    };
+
+    if project.sandbox.mode == SandboxMode::Off {
+        println!("{} runs unconfined", project.name);
+    } else {
+        println!("{} runs sandboxed: {}", project.name, project.sandbox);
+        println!("  Takes effect as processes (re)start");
+    }
+    Ok(())
+}
+
+/// A project's schedule after applying a `schedule` subcommand to it
+fn edit_schedule(project: &Project, args: &[String]) -> Result<Option<Schedule>> {
+    let project_name = &project.name;
+    let schedule = match args.first().map(String::as_str) {
+        Some("set") => {
+            let (ranges, command) = match args[1..].iter().position(|a| a == "--") {
+                Some(i) => (&args[1..i + 1], args[i + 2..].to_vec()),
+                None => (&args[1..], Vec::new()),
+            };
+
+            let mut days = Vec::new();
+            let mut windows = Vec::new();
+            let mut iter = ranges.iter();
+            while let Some(arg) = iter.next() {
+                if arg == "--days" {
+                    days = parse_weekdays(iter.next().context("--days expects a value")?)?;
+                } else {
+                    let (start, end) = parse_time_range(arg)?;
+                    windows.push((start, end));
+                }
+            }
+            if windows.is_empty() {
+                anyhow::bail!(
+                    "Usage: proj {} schedule set <HH:MM-HH:MM>... [--days mon-fri] [-- <cmd>]",
+                    project_name
+                );
+            }
+
+            let previous = project.schedule.clone().unwrap_or_default();
+            let command = if command.is_empty() {
+                previous.command
+            } else {
+                command
+            };
+            if command.is_empty() {
+                anyhow::bail!("No command to schedule. Add one after `--`");
+            }
+
+            Some(Schedule {
+                windows: windows
+                    .into_iter()
+                    .map(|(start, end)| ScheduleWindow {
+                        days: days.clone(),
+                        start,
+                        end,
+                    })
+                    .collect(),
+                command,
+                forced: previous.forced,
+            })
+        }
+        Some("clear") => None,
+        Some(mode @ ("on" | "off" | "auto")) => {
+            let mut schedule = project
+                .schedule
+                .clone()
+                .with_context(|| format!("{} has no schedule", project_name))?;
+            schedule.forced = match mode {
+                "auto" => None,
+                _ => {
+                    let until = match args.get(1).map(String::as_str) {
+                        Some("--for") => {
+                            let duration = parse_duration(
+                                args.get(2).context("--for expects a duration, e.g. 2h")?,
+                            )?;
+                            Some(Utc::now() + duration)
+                        }
+                        Some(other) => anyhow::bail!("Unknown option '{}'", other),
+                        None => None,
+                    };
+                    Some(ScheduleOverride {
+                        run: mode == "on",
+                        until,
+                    })
+                }
+            };
+            Some(schedule)
+        }
+        Some(other) => anyhow::bail!(
+            "Unknown schedule action '{}'. Usage: proj {} schedule [set|clear|on|off|auto]",
+            other,
+            project_name
+        ),
+        None => project.schedule.clone(),
+    };
+    Ok(schedule)
+}
+
+/// Calendar-style view of every scheduled project
+async fn cmd_schedule_show() -> Result<()> {
+    let projects = list_projects(ProjectFilter::default()).await?;
+
+    let scheduled: Vec<_> = projects
+        .into_iter()
+        .filter(|p| p.schedule.is_some())
+        .collect();
+    if scheduled.is_empty() {
+        println!("No scheduled projects");
+        println!("  Create one with: proj <name> schedule set 12:00-13:00 -- <cmd>");
+        return Ok(());
+    }
+
+    print_schedules(&scheduled, Local::now());
+    Ok(())
+}
+
+/// Print a week grid (one cell per half hour) for each project's schedule
+fn print_schedules(projects: &[Project], now: DateTime<Local>) {
+    let today = now.date_naive();
+    let monday = today - chrono::Duration::days(today.weekday().num_days_from_monday() as i64);
+
+    println!(
+        "{:7}{}",
+        "",
+        (0..24)
+            .step_by(3)
+            .map(|h| format!("{:<6}", format!("{:02}", h)))
+            .collect::<String>()
+    );
+
+    for project in projects {
+        let Some(schedule) = &project.schedule else {
+            continue;
+        };
+
+        let state = if schedule.should_run(now) {
+            "\x1b[32mwindow open\x1b[0m"
+        } else {
+            "\x1b[90mwindow closed\x1b[0m"
+        };
+        let forced = match &schedule.forced {
+            Some(o) if o.until.is_none_or(|until| until > now) => format!(
+                " (forced {}{})",
+                if o.run { "on" } else { "off" },
+                o.until
+                    .map(|u| format!(" until {}", u.with_timezone(&Local).format("%a %H:%M")))
+                    .unwrap_or_default()
+            ),
+            _ => String::new(),
+        };
+        println!();
+        println!(
+            "\x1b[1m{}\x1b[0m {}{}  \x1b[90m{}\x1b[0m",
+            project.name,
+            state,
+            forced,
+            schedule.command.join(" ")
+        );
+
+        for offset in 0..7 {
+            let date = monday + chrono::Duration::days(offset);
+            let cells: String = (0..48)
+                .map(|slot| {
+                    let at = date.and_time(NaiveTime::MIN) + chrono::Duration::minutes(slot * 30);
+                    if schedule.windows.iter().any(|w| w.contains(at)) {
+                        '█'
+                    } else {
+                        '·'
+                    }
+                })
+                .collect();
+            let marker = if date == today { "›" } else { " " };
+            println!("{} {:4} {}", marker, date.format("%a"), cells);
+        }
+    }
+}
+
+/// Parse `HH:MM-HH:MM`
+fn parse_time_range(s: &str) -> Result<(NaiveTime, NaiveTime)> {
+    let (start, end) = s
+        .split_once('-')
+        .with_context(|| format!("Invalid time range '{}', expected HH:MM-HH:MM", s))?;
+    let parse = |t: &str| {
+        NaiveTime::parse_from_str(t, "%H:%M")
+            .with_context(|| format!("Invalid time '{}', expected HH:MM", t))
+    };
+    Ok((parse(start)?, parse(end)?))
+}
+
+/// Parse `mon,wed,fri` or `mon-fri`
+fn parse_weekdays(s: &str) -> Result<Vec<Weekday>> {
+    let parse = |d: &str| {
+        d.parse::<Weekday>()
+            .map_err(|_| anyhow::anyhow!("Invalid weekday '{}'", d))
+    };
+    let mut days = Vec::new();
+    for part in s.split(',') {
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let (mut day, to) = (parse(from)?, parse(to)?);
+                days.push(day);
+                while day != to {
+                    day = day.succ();
+                    days.push(day);
+                }
+            }
+            None => days.push(parse(part)?),
+        }
+    }
+    Ok(days)
+}
+
+/// Parse durations like `90s`, `30m`, `2h` or `1d`
+fn parse_duration(s: &str) -> Result<chrono::Duration> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (amount, unit) = s.split_at(split);
+    let amount: i64 = amount
+        .parse()
+        .with_context(|| format!("Invalid duration '{}'", s))?;
+    match unit {
+        "s" => Ok(chrono::Duration::seconds(amount)),
+        "m" => Ok(chrono::Duration::minutes(amount)),
+        "h" | "" => Ok(chrono::Duration::hours(amount)),
+        "d" => Ok(chrono::Duration::days(amount)),
+        _ => anyhow::bail!("Invalid duration unit in '{}' (use s, m, h or d)", s),
+    }
+}
+
+/// Check every hand-editable file for unknown and deprecated keys: the user
+/// config, and each project's .proj/config.json and proj.toml
+fn cmd_config_lint() -> Result<()> {
+    let json = |content: &str| -> Result<serde_json::Value> { Ok(serde_json::from_str(content)?) };
+    let toml = |content: &str| -> Result<serde_json::Value> { Ok(toml::from_str(content)?) };
+
+    let mut files = Vec::new();
+    let user = config_path()?;
+    if user.exists() {
+        let result = lint_file(&user, json, lint::check::<Config>);
+        files.push((user, result));
+    }
+    for (_, project) in open_store()?.projects()? {
+        let Ok(Project { root_dir: root, .. }) = project else {
+            continue;
+        };
+        let config = root.join(layers::PROJECT_CONFIG);
+        if config.exists() {
+            files.push((
+                config.clone(),
+                lint_file(&config, json, lint::check::<Config>),
+            ));
+        }
+        let project_file = root.join(schema::PROJECT_FILE);
+        if project_file.exists() {
+            let result = lint_file(&project_file, toml, lint::check::<schema::ProjectFile>);
+            files.push((project_file, result));
+        }
+    }
+
+    let mut problems = 0;
+    let mut files_with_problems = 0;
+    for (path, result) in &files {
+        let messages: Vec<String> = match result {
+            Ok(findings) => findings.iter().map(|f| f.to_string()).collect(),
+            Err(e) => vec![format!("{:#}", e)],
+        };
+        if messages.is_empty() {
+            continue;
+        }
+        problems += messages.len();
+        files_with_problems += 1;
+        println!("\x1b[1m{}\x1b[0m", path.display());
+        for message in messages {
+            println!("  \x1b[31m✗\x1b[0m {}", message);
+        }
+    }
+
+    if problems > 0 {
+        anyhow::bail!(
+            "{} problem(s) in {} of {} file(s)",
+            problems,
+            files_with_problems,
+            files.len()
+        );
+    }
+    println!(
+        "\x1b[32m✓\x1b[0m No unknown or deprecated keys in {} file(s)",
+        files.len()
+    );
+    Ok(())
+}
+
+/// Import files left by an older proj into the store and migrate stored
+/// projects to the current format, keeping the originals as backups. The
+/// daemon does the same when it starts; this shows what it would do.
+fn cmd_migrate_data(dry_run: bool) -> Result<()> {
+    let store = Store::open_default()?;
+    if dry_run {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(projects_dir()?)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|e| e.path().join("project.json"))
+                    .filter(|path| path.exists())
+                    .collect()
+            })
+            .unwrap_or_default();
+        files.sort();
+        files.extend(Some(processes_path()?).filter(|path| path.exists()));
+        for path in &files {
+            println!("\x1b[1m{}\x1b[0m: would be imported", path.display());
+        }
+    } else {
+        let imported = store.import_files(&projects_dir()?, &processes_path()?)?;
+        for name in &imported.projects {
+            println!("\x1b[1m{}\x1b[0m: imported project.json", name);
+        }
+        if imported.processes > 0 {
+            println!("Imported {} process record(s)", imported.processes);
+        }
+    }
+
+    let upgrades = store.upgrade_projects(!dry_run)?;
+    let mut migrated = 0;
+    let mut newer = 0;
+    for upgrade in &upgrades {
+        match &upgrade.result {
+            Ok(result) => {
+                println!(
+                    "\x1b[1m{}\x1b[0m: version {} → {}",
+                    upgrade.name, result.from, result.to
+                );
+                for change in &result.changes {
+                    println!("  {}", change);
+                }
+                migrated += 1;
+            }
+            Err(e) => {
+                println!("\x1b[33m!\x1b[0m {}: {}", upgrade.name, e);
+                newer += 1;
+            }
+        }
+    }
+
+    if dry_run {
+        println!(
+            "{} project(s) would be migrated to version {} (dry run, nothing written)",
+            migrated,
+            migrate::PROJECT.version()
+        );
+    } else {
+        println!(
+            "\x1b[32m✓\x1b[0m {} project(s) migrated; all others are at version {}",
+            migrated,
+            migrate::PROJECT.version()
+        );
+    }
+    if newer > 0 {
+        anyhow::bail!(
+            "{} project(s) are from a newer proj and were left alone",
+            newer
+        );
+    }
+    Ok(())
+}
+
+/// Parse a file and check its keys against the type it is read as
+fn lint_file(
+    path: &std::path::Path,
+    parse: impl Fn(&str) -> Result<serde_json::Value>,
+    check: fn(&serde_json::Value) -> Vec<lint::Finding>,
+) -> Result<Vec<lint::Finding>> {
+    let content = std::fs::read_to_string(path)?;
+    let value = parse(&content).context("Failed to parse")?;
+    Ok(check(&value))
+}
+
+/// Print the effective config, optionally with where each value came from
+fn cmd_config_show(origin: bool) -> Result<()> {
+    let resolved = resolve_config()?;
+    let settings = resolved.settings();
+    let width = settings
+        .iter()
+        .map(|(name, _, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    let values: Vec<String> = settings
+        .iter()
+        .map(|(_, value, _)| value.to_string())
+        .collect();
+    let value_width = values.iter().map(String::len).max().unwrap_or(0);
+    for ((name, _, from), value) in settings.iter().zip(&values) {
+        if origin {
+            let from = match from {
+                layers::ConfigOrigin::Default => format!("\x1b[90m{}\x1b[0m", from),
+                _ => from.to_string(),
+            };
+            println!(
+                "{:<width$}  {:<value_width$}  {}",
+                name,
+                value,
+                from,
+                width = width,
+                value_width = value_width
+            );
+        } else {
+            println!("{:<width$}  {}", name, value, width = width);
+        }
+    }
+    Ok(())
+}
+
+/// List the plugins on PATH
+fn cmd_plugins() -> Result<()> {
+    let plugins = plugins::discover();
+    if plugins.is_empty() {
+        println!("No plugins found. A `proj-<name>` executable on PATH runs as `proj <name>`.");
+        return Ok(());
+    }
+    let width = plugins.keys().map(String::len).max().unwrap_or(0);
+    for (name, path) in plugins {
+        println!(
+            "{:<width$}  \x1b[90m{}\x1b[0m",
+            name,
+            path.display(),
+            width = width
+        );
+    }
+    Ok(())
+}
+
+/// Hand the command line over to a plugin, telling it where the daemon is
+fn run_plugin(plugin: &std::path::Path, args: &[String]) -> Result<()> {
+    let mut env = vec![("PROJ_SOCKET", socket_path()?.display().to_string())];
+    if let Some(project) = project_from_cwd() {
+        env.push(("PROJ_PROJECT", project.name));
+    }
+    if let Some(host) = remote_host() {
+        env.push(("PROJ_HOST", host.to_string()));
+    }
+    if let Ok(exe) = std::env::current_exe() {
+        env.push(("PROJ_BIN", exe.display().to_string()));
+    }
+    plugins::run(plugin, args, env)
+}
+
+/// Print one JSON Schema, list them, or write them all into a directory
+fn cmd_schema(name: Option<String>, out: Option<PathBuf>) -> Result<()> {
+    if let Some(dir) = out {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        for (name, _) in schema::DOCUMENTS {
+            let schema = schema::generate(name).context("Unknown schema")?;
+            let path = dir.join(format!("{}.schema.json", name));
+            std::fs::write(&path, serde_json::to_string_pretty(&schema)? + "\n")
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            println!("{}", path.display());
+        }
+        return Ok(());
+    }
+
+    let Some(name) = name else {
+        for (name, description) in schema::DOCUMENTS {
+            println!("{:<14} {}", name, description);
+        }
+        println!();
+        println!("Print one with: proj schema <name>");
+        return Ok(());
+    };
+    let schema = schema::generate(&name).with_context(|| {
+        let names: Vec<_> = schema::DOCUMENTS.iter().map(|(n, _)| *n).collect();
+        format!("Unknown schema '{}'. Schemas: {}", name, names.join(", "))
+    })?;
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+    Ok(())
+}
+
+/// Emit the service, dependency and route graph (see [`graph`]) for one
+/// project, or all of them
+async fn cmd_graph(project_name: Option<&str>, format: &str) -> Result<()> {
+    let projects = match project_name {
+        Some(name) => vec![get_project(name).await?],
+        None => list_projects(ProjectFilter::default()).await?,
+    };
+    let processes = match send_request(IpcRequest::ListProcesses {
+        project_name: project_name.map(str::to_string),
+    })
+    .await?
+    {
+        IpcResponse::Processes(processes) => processes,
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    };
+
+    let graph = graph::build(&projects, &processes, project_host);
+    match format {
+        "dot" => print!("{}", graph.to_dot()),
+        "mermaid" => print!("{}", graph.to_mermaid()),
+        other => anyhow::bail!("Unknown graph format '{}'. Use dot or mermaid", other),
+    }
+    Ok(())
+}
+
+/// Show GraphQL operation timings: `gql ops`
+async fn cmd_gql(project_name: &str, args: Vec<String>) -> Result<()> {
+    if args.first().map(String::as_str) != Some("ops") {
+        anyhow::bail!("Usage: proj {} gql ops", project_name);
+    }
+
+    let response = send_request(IpcRequest::GetGraphqlOps {
+        project_name: project_name.to_string(),
+    })
+    .await?;
+    let ops = match response {
+        IpcResponse::GraphqlOps(ops) => ops,
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    };
+
+    if ops.is_empty() {
+        println!("No GraphQL operations observed for '{}'", project_name);
+        return Ok(());
+    }
+
+    println!(
+        "{:<32} {:<12} {:>6} {:>6} {:>8} {:>8} {:>8}",
+        "OPERATION", "KIND", "COUNT", "ERRORS", "AVG", "P95", "MAX"
+    );
+    for op in ops {
+        println!(
+            "{:<32} {:<12} {:>6} {:>6} {:>6}ms {:>6}ms {:>6}ms",
+            op.name, op.kind, op.count, op.errors, op.avg_ms, op.p95_ms, op.max_ms
+        );
+    }
+
+    Ok(())
+}
+
+/// Largest responses the proxy served a project: `assets [-n <count>|clear]`
+async fn cmd_assets(project_name: &str, args: Vec<String>) -> Result<()> {
+    let limit = match args.as_slice() {
+        [] => ASSETS_LIMIT,
+        [flag, n] if flag == "-n" => n
+            .parse()
+            .with_context(|| format!("Invalid count '{}'", n))?,
+        [action] if action == "clear" => {
+            match send_request(IpcRequest::ClearAssets {
+                project_name: project_name.to_string(),
+            })
+            .await?
+            {
+                IpcResponse::Success { .. } => {
+                    println!("Cleared response sizes for {}", project_name);
+                    return Ok(());
+                }
+                IpcResponse::Error(error) => return Err(error.into()),
+                _ => anyhow::bail!(tr!("unexpected-response")),
+            }
+        }
+        _ => anyhow::bail!("Usage: proj {} assets [-n <count>|clear]", project_name),
+    };
+
+    let report = match send_request(IpcRequest::GetAssets {
+        project_name: project_name.to_string(),
+    })
+    .await?
+    {
+        IpcResponse::Assets(report) => report,
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    };
+    if report.assets.is_empty() {
+        println!("No responses served to {} yet", project_name);
+        return Ok(());
+    }
+
+    let total: u64 = report.assets.iter().map(|a| a.total_bytes).sum();
+    println!(
+        "Served {} in {} paths since {}",
+        format_size(total),
+        report.assets.len(),
+        report.since.with_timezone(&Local).format("%H:%M:%S")
+    );
+    println!();
+    println!(
+        "\x1b[1m{:<48} {:<10} {:>5} {:>10} {:>10}\x1b[0m",
+        "PATH", "TYPE", "REQS", "SIZE", "TOTAL"
+    );
+    for asset in report.assets.iter().take(limit) {
+        let path = if asset.path.chars().count() > 48 {
+            let tail: String = asset.path.chars().rev().take(47).collect();
+            format!("…{}", tail.chars().rev().collect::<String>())
+        } else {
+            asset.path.clone()
+        };
+        // Sourcemaps are for devtools, and rarely meant to ship on every load
+        let highlight = if asset.kind() == "sourcemap" {
+            "\x1b[33m"
+        } else {
+            ""
+        };
+        println!(
+            "{}{:<48} {:<10} {:>5} {:>10} {:>10}\x1b[0m",
+            highlight,
+            path,
+            asset.kind(),
+            asset.requests,
+            format_size(asset.max_bytes),
+            format_size(asset.total_bytes)
+        );
+    }
+    if report.assets.len() > limit {
+        println!(
+            "\x1b[90m… {} more (proj {} assets -n <count>)\x1b[0m",
+            report.assets.len() - limit,
+            project_name
+        );
+    }
+
+    let mut kinds: BTreeMap<&str, u64> = BTreeMap::new();
+    for asset in &report.assets {
+        *kinds.entry(asset.kind()).or_default() += asset.total_bytes;
+    }
+    let mut kinds: Vec<(&str, u64)> = kinds.into_iter().collect();
+    kinds.sort_by_key(|(_, bytes)| std::cmp::Reverse(*bytes));
+    let kinds: Vec<String> = kinds
+        .iter()
+        .map(|(kind, bytes)| format!("{} {}", kind, format_size(*bytes)))
+        .collect();
+    println!();
+    println!("By type: {}", kinds.join(", "));
+
+    Ok(())
+}
+
+/// Paths shown by `assets` by default
+const ASSETS_LIMIT: usize = 20;
+
+/// Headers that curl/HTTPie derive themselves and should not be replayed
+const DERIVED_HEADERS: [&str; 4] = ["host", "content-length", "connection", "transfer-encoding"];
+
+/// Reconstruct a runnable curl or HTTPie command for a captured request
+fn request_snippet(request: &CapturedRequest, base_url: &str, httpie: bool) -> String {
+    let url = format!("{}{}", base_url, request.path);
+    let headers = request
+        .headers
+        .iter()
+        .filter(|(name, _)| !DERIVED_HEADERS.contains(&name.to_ascii_lowercase().as_str()));
+
+    let mut parts = Vec::new();
+    if httpie {
+        parts.push(format!("http {} {}", request.method, shell_quote(&url)));
+        for (name, value) in headers {
+            parts.push(shell_quote(&format!("{}:{}", name, value)));
+        }
+        if let Some(body) = &request.body {
+            parts.push(format!("--raw {}", shell_quote(body)));
+        }
+    } else {
+        parts.push(format!("curl -X {} {}", request.method, shell_quote(&url)));
+        for (name, value) in headers {
+            parts.push(format!(
+                "-H {}",
+                shell_quote(&format!("{}: {}", name, value))
+            ));
+        }
+        if let Some(body) = &request.body {
+            parts.push(format!("--data-raw {}", shell_quote(body)));
+        }
+    }
+
+    parts.join(" \\\n  ")
+}
+
+/// Quote a string for POSIX shells
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Show info about a specific project
+async fn cmd_project_info(name: &str) -> Result<()> {
+    let (project, processes, budgets) = fetch_project_info(name).await?;
+    print_project_info(&project, &processes, &budgets);
+
+    println!();
+    println!("Commands:");
+    println!("  proj {} run <cmd>   Run a command", project.name);
+    println!("  proj {} open        Open in browser", project.name);
+    println!("  proj {} stop        Stop processes", project.name);
+
+    Ok(())
+}
+
+/// A project and its processes as JSON, for scripts and editor launch
+/// configurations (e.g. `.processes[0].named_ports.debug`)
+async fn cmd_project_info_json(name: &str) -> Result<()> {
+    let (project, processes, _) = fetch_project_info(name).await?;
+    let info = serde_json::json!({ "project": project, "processes": processes });
+    println!("{}", serde_json::to_string_pretty(&info)?);
+    Ok(())
+}
+
+/// A project, its processes and where its traffic stands against its budgets
+async fn fetch_project_info(name: &str) -> Result<(Project, Vec<ProcessInfo>, Vec<BudgetStatus>)> {
+    let project = get_project(name).await?;
+    let processes = match send_request(IpcRequest::ListProcesses {
+        project_name: Some(name.to_string()),
+    })
+    .await?
+    {
+        IpcResponse::Processes(p) => p,
+        _ => vec![],
+    };
+    let budgets = if project.proxy.budgets.is_empty() {
+        vec![]
+    } else {
+        fetch_budgets(name).await.unwrap_or_default()
+    };
+    Ok((project, processes, budgets))
+}
+
+/// Summary of a project and its running process
+fn print_project_info(project: &Project, processes: &[ProcessInfo], budgets: &[BudgetStatus]) {
+    let running: Vec<_> = processes.iter().filter(|p| p.status.is_alive()).collect();
+
+    println!("Project: {}", styled_name(project));
+    println!("  Root:    {}", project.root_dir.display());
+    println!("  Created: {}", project.created_at.format("%Y-%m-%d %H:%M"));
+    if let Some(template) = &project.template {
+        println!("  Template: {}", template);
+    }
+    if !project.depends_on.is_empty() {
+        println!("  Needs:   {}", project.depends_on.join(", "));
+    }
+    if !project.priority.is_default() {
+        println!("  Priority: {}", project.priority);
+    }
+    if project.sandbox.mode != SandboxMode::Off {
+        println!("  Sandbox: {}", project.sandbox);
+    }
+    if !project.env_profiles.is_empty() {
+        println!("  Env:     profiles {}", project.env_profiles.join(", "));
+    }
+    if !project.aliases.is_empty() {
+        println!("  Aliases: {}", project.aliases.join(", "));
+    }
+    if !project.proxy.routes.is_empty() {
+        let routes: Vec<String> = project
+            .proxy
+            .routes
+            .iter()
+            .map(|(prefix, port)| format!("{} → {}", prefix, port))
+            .collect();
+        println!("  Routes:  {}", routes.join(", "));
+    }
+    if !project.proxy.overrides.is_empty() {
+        let overrides: Vec<String> = project
+            .proxy
+            .overrides
+            .iter()
+            .map(|(host, target)| format!("{} → {}", host, target))
+            .collect();
+        println!("  Hosts:   {}", overrides.join(", "));
+    }
+    if project.proxy.relax_csp {
+        println!("  CSP:     \x1b[33mrelaxed (dev only)\x1b[0m");
+    }
+    if project.proxy.cors {
+        println!("  CORS:    \x1b[33many origin (dev only)\x1b[0m");
+    }
+    if project.proxy.no_cache {
+        println!("  Cache:   \x1b[33moff (dev only)\x1b[0m");
+    }
+    if project.proxy.kill_service_workers {
+        println!("  SW:      \x1b[33mkilled (dev only)\x1b[0m");
+    }
+    if project.proxy.rewrite_host {
+        println!("  Host:    rewritten to {}", host_header(project));
+    }
+    if let Some(host) = &project.proxy.upstream_host {
+        println!("  Runs on: {}", host);
+    }
+    if let Some(mins) = project.proxy.idle_stop_mins {
+        println!("  Idle:    stops after {} min without requests", mins);
+    }
+    if project.proxy.lazy_start {
+        println!("  Lazy:    starts on the first request");
+    }
+    if let Some(limit) = project.proxy.max_in_flight {
+        println!("  Limit:   {} requests at a time", limit);
+    }
+    if let Some(persona) = &project.proxy.persona {
+        println!("  Acting:  as {}", persona);
+    }
+
+    // The process the proxy most likely routes to, as the daemon picks it
+    let serving = running
+        .iter()
+        .max_by_key(|p| (p.primary, p.port.is_some(), p.started_at));
+    if let Some(proc) = serving {
+        if proc.status == ProcessStatus::Unhealthy {
+            println!("  Status:  \x1b[33mrunning, unhealthy\x1b[0m");
+        } else {
+            println!("  Status:  \x1b[32mrunning\x1b[0m");
+        }
+        if let Some(port) = proc.port {
+            println!("  Port:    {}", port);
+            if proc.ports.len() > 1 {
+                let all: Vec<String> = proc.ports.iter().map(|p| p.to_string()).collect();
+                println!("  Ports:   {}", all.join(", "));
+            }
+            if project.is_direct() {
+                println!("  URL:     {} (direct mode)", proj_common::direct_url(port));
+            } else {
+                println!("  URL:     {}", project_url(&project.name));
+            }
+        }
+        let named: Vec<String> = proc
+            .named_ports
+            .iter()
+            .filter(|(name, _)| name.as_str() != "web")
+            .map(|(name, port)| format!("{} {}", name, port))
+            .collect();
+        if !named.is_empty() {
+            println!("  Named:   {}", named.join(", "));
+        }
+        println!("  PID:     {} ({})", proc.pid, proc.short_id());
+        println!("  Command: {}", proc.command);
+        println!("  Uptime:  {}", format_uptime(Utc::now() - proc.started_at));
+        if running.len() > 1 {
+            println!(
+                "  Others:  {} more running (proj {} ps)",
+                running.len() - 1,
+                project.name
+            );
+        }
+        if let Some(usage) = proc.usage {
+            println!(
+                "  Usage:   {:.1}% CPU, {}",
+                usage.cpu_percent,
+                format_bytes(usage.memory_bytes)
+            );
+        }
+        if proc.restart_count > 0 {
+            println!(
+                "  Restarts: {} (last exit code: {})",
+                proc.restart_count,
+                proc.last_exit_code
+                    .map(|c| c.to_string())
+                    .unwrap_or_else(|| "signal".to_string())
+            );
+        }
+    } else {
+        println!("  Status:  \x1b[90mstopped\x1b[0m");
+    }
+    // A resource limit that ended the latest run is worth knowing about
+    let latest = processes.iter().max_by_key(|p| p.started_at);
+    if let Some(reason) = latest.and_then(|p| p.failure_reason.as_ref()) {
+        println!("  Failed:  \x1b[31m{}\x1b[0m", reason);
+    }
+    for status in budgets.iter().filter(|s| s.exceeded) {
+        if let (Some(value), Some(limit)) = (status.value, status.limit) {
+            println!(
+                "  Budget:  \x1b[33m{} is {}, over its {} budget\x1b[0m",
+                status.metric,
+                status.metric.format(value),
+                status.metric.format(limit)
+            );
+        }
+    }
+
+    if !project.services.is_empty() {
+        let names: Vec<String> = project
+            .services
+            .keys()
+            .map(|name| {
+                let up = running
+                    .iter()
+                    .any(|p| p.service.as_deref() == Some(name.as_str()));
+                if up {
+                    format!("\x1b[32m{}\x1b[0m", name)
+                } else {
+                    name.clone()
+                }
+            })
+            .collect();
+        println!("  Services: {}", names.join(", "));
+    }
+}
+
+/// Live panel for one project: status, traffic and the latest output,
+/// redrawn every second and whenever one of its processes changes
+async fn cmd_info_watch(name: &str) -> Result<()> {
+    let mut logs = Subscription::open(IpcRequest::SubscribeLogs {
+        project_name: name.to_string(),
+    })
+    .await?;
+    let mut events = Subscription::open(IpcRequest::Subscribe {
+        filters: EventFilters {
+            project_name: Some(name.to_string()),
+            events: Vec::new(),
+        },
+    })
+    .await?;
+
+    let mut recent: VecDeque<LogLine> = VecDeque::new();
+    let mut previous: Option<(Instant, ProxyTraffic)> = None;
+    let mut tick = tokio::time::interval(INFO_REFRESH_INTERVAL);
+    loop {
+        tokio::select! {
+            line = logs.next() => match line? {
+                Some(IpcResponse::Log(line)) => {
+                    recent.push_back(line);
+                    if recent.len() > INFO_LOG_LINES {
+                        recent.pop_front();
+                    }
+                    // Shown with the next redraw
+                    continue;
+                }
+                Some(_) => continue,
+                None => anyhow::bail!("Daemon closed the connection"),
+            },
+            event = events.next() => match event? {
+                Some(IpcResponse::Event(event)) if event.kind == EventKind::ProjectDeleted => {
+                    anyhow::bail!("Project '{}' was deleted", name)
+                }
+                Some(_) => {}
+                None => anyhow::bail!("Daemon closed the connection"),
+            },
+            _ = tick.tick() => {}
+        }
+
+        let (project, processes, budgets) = fetch_project_info(name).await?;
+        let traffic = match send_request(IpcRequest::GetTraffic {
+            project_name: name.to_string(),
+        })
+        .await?
+        {
+            IpcResponse::Traffic(traffic) => traffic,
+            _ => ProxyTraffic::default(),
+        };
+        let now = Instant::now();
+        // Rates over the time since the last redraw
+        let rates = previous.map(|(at, before)| {
+            let secs = now.duration_since(at).as_secs_f64().max(0.001);
+            (
+                traffic.requests.saturating_sub(before.requests) as f64 / secs,
+                traffic.errors.saturating_sub(before.errors) as f64 / secs,
+            )
+        });
+        previous = Some((now, traffic));
+
+        // Clear the screen and redraw from the top
+        print!("\x1b[2J\x1b[H");
+        print_project_info(&project, &processes, &budgets);
+        match rates {
+            Some((requests, errors)) if errors > 0.0 => println!(
+                "  Traffic: {:.1} req/s, \x1b[31m{:.1} errors/s\x1b[0m ({} total)",
+                requests, errors, traffic.requests
+            ),
+            Some((requests, _)) => println!(
+                "  Traffic: {:.1} req/s ({} total)",
+                requests, traffic.requests
+            ),
+            None => println!("  Traffic: - ({} total)", traffic.requests),
+        }
+
+        println!();
+        println!("\x1b[1mRecent output\x1b[0m");
+        let width = ratatui::crossterm::terminal::size().map_or(100, |(w, _)| w as usize);
+        if recent.is_empty() {
+            println!("\x1b[90m  (none yet)\x1b[0m");
+        }
+        for line in &recent {
+            let text: String = line.line.chars().take(width.saturating_sub(2)).collect();
+            if line.is_stderr {
+                println!("  \x1b[31m{}\x1b[0m", text);
+            } else {
+                println!("  {}", text);
+            }
+        }
+        println!();
+        println!("\x1b[90mWatching {} (Ctrl+C to exit)\x1b[0m", name);
+    }
+}
+
+/// How often `info --watch` redraws without a process change
+const INFO_REFRESH_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(1);
+
+/// Output lines shown by `info --watch`
+const INFO_LOG_LINES: usize = 10;
+
+/// Send a request to the daemon and get a response
+async fn send_request(request: IpcRequest) -> Result<IpcResponse> {
+    let stream = connect_daemon().await?;
+    let (reader, mut writer) = tokio::io::split(stream);
+
+    // Send request
+    let json = serde_json::to_string(&request)?;
+    writer.write_all(json.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+
+    // Read response
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+
+    let response: IpcResponse =
+        serde_json::from_str(&line).context("Invalid response from daemon")?;
+
+    supported(response)
+}
+
+/// A response, unless it says the daemon doesn't know the request
+fn supported(response: IpcResponse) -> Result<IpcResponse> {
+    match response {
+        IpcResponse::UnsupportedRequest {
+            request_type,
+            daemon_version,
+            ..
+        } => anyhow::bail!(
+            "The running daemon ({}) is older than proj {} and doesn't support '{}'. \
+             Restart it with: proj daemon restart",
+            daemon_version,
+            VERSION,
+            request_type
+        ),
+        response => Ok(response),
+    }
+}
+
+/// Send a run request, reporting the queue position while a heavy run
+/// waits for a slot
+async fn send_run_request(request: IpcRequest) -> Result<IpcResponse> {
+    let stream = connect_daemon().await?;
+    let (reader, mut writer) = tokio::io::split(stream);
+
+    let json = serde_json::to_string(&request)?;
+    writer.write_all(json.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        match supported(serde_json::from_str(&line).context("Invalid response from daemon")?)? {
+            IpcResponse::HeavyQueued { position, limit } => println!(
+                "\x1b[33m…\x1b[0m Waiting for a heavy job slot ({} at once): position {} \
+                 (Ctrl+C to give up)",
+                limit, position
+            ),
+            response => return Ok(response),
+        }
+    }
+    anyhow::bail!("Daemon closed the connection")
+}
+
+/// Connect to the daemon, starting it first if nothing is listening. The
+/// first connection of a command checks the daemon's protocol version, and
+/// goes on to carry the request when the daemon allows it.
+async fn connect_daemon() -> Result<Connection> {
+    let stream = open_connection(true).await?;
+    if HANDSHAKE_DONE.swap(true, Ordering::SeqCst) {
+        return Ok(stream);
+    }
+
+    match handshake(stream).await? {
+        Some(stream) => Ok(stream),
+        None => open_connection(false).await,
+    }
+}
+
+/// A connection to the remote daemon if there is one, else to the local
+/// one, which is started first if nothing is listening and `start` is set
+async fn open_connection(start: bool) -> Result<Connection> {
+    if let Some(host) = remote_host() {
+        return connect_remote(host).await;
+    }
+    let socket = socket_path()?;
+    let stream = match Ipc::connect(&socket).await {
+        Ok(stream) => stream,
+        Err(_) if start && !NO_AUTOSTART.load(Ordering::SeqCst) => {
+            auto_start_daemon().await?;
+            Ipc::connect(&socket)
+                .await
+                .context(tr!("daemon-connect-failed-try"))?
+        }
+        Err(e) => return Err(e).context(tr!("daemon-connect-failed")),
+    };
+    Ok(Box::new(stream))
+}
+
+/// Address of the remote daemon from `--host` or `PROJ_HOST`, if any
+fn remote_host() -> Option<&'static str> {
+    REMOTE_HOST.get().and_then(Option::as_deref)
+}
+
+/// Connect to a daemon's TCP listener and authenticate with `tcp_token`
+async fn connect_remote(host: &str) -> Result<Connection> {
+    let token = load_config().tcp_token.with_context(|| {
+        format!(
+            "Set tcp_token (or PROJ_TCP_TOKEN) to the token of the daemon at {}",
+            host
+        )
+    })?;
+    let stream = tokio::net::TcpStream::connect(host)
+        .await
+        .with_context(|| format!("Failed to connect to the daemon at {}", host))?;
+    stream.set_nodelay(true)?;
+
+    // Buffered from here on, so nothing after the answer is lost
+    let mut stream = BufReader::new(stream);
+    let json = serde_json::to_string(&IpcRequest::Authenticate { token })?;
+    stream.write_all(json.as_bytes()).await?;
+    stream.write_all(b"\n").await?;
+    let mut line = String::new();
+    stream.read_line(&mut line).await?;
+    match serde_json::from_str(&line).context("Invalid response from daemon")? {
+        IpcResponse::Success { .. } => Ok(Box::new(stream)),
+        IpcResponse::Error(error) => Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    }
+}
+
+/// Set once this command has checked the daemon's version, or doesn't need to
+static HANDSHAKE_DONE: AtomicBool = AtomicBool::new(false);
+
+/// Set by `--no-autostart`: a daemon that isn't running stays that way
+static NO_AUTOSTART: AtomicBool = AtomicBool::new(false);
+
+/// `action`, with the version check skipped for it
+fn skip_handshake(action: Option<DaemonAction>) -> Option<DaemonAction> {
+    HANDSHAKE_DONE.store(true, Ordering::SeqCst);
+    action
+}
+
+/// Check the daemon speaks this CLI's protocol, offering to restart an
+/// outdated one. Returns the connection if the daemon keeps it open for a
+/// request.
+async fn handshake(stream: Connection) -> Result<Option<Connection>> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let json = serde_json::to_string(&IpcRequest::Hello {
+        protocol_version: PROTOCOL_VERSION,
+        client_version: VERSION.to_string(),
+    })?;
+    writer.write_all(json.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    let mut line = String::new();
+    let mut reader = BufReader::new(reader);
+    reader.read_line(&mut line).await?;
+
+    // Daemons from before the handshake reject it as an unknown request
+    let (protocol_version, daemon_version, keeps_connection) = match serde_json::from_str(&line) {
+        Ok(IpcResponse::Hello {
+            protocol_version,
+            daemon_version,
+            keeps_connection,
+        }) => (
+            protocol_version,
+            format!(" ({})", daemon_version),
+            keeps_connection,
+        ),
+        _ => (0, String::new(), false),
+    };
+    // Nothing more was sent, so the reader holds nothing back
+    let stream = keeps_connection.then(|| reader.into_inner().unsplit(writer));
+    if protocol_version == PROTOCOL_VERSION {
+        return Ok(stream);
+    }
+    if protocol_version > PROTOCOL_VERSION {
+        eprintln!(
+            "\x1b[33m⚠\x1b[0m The running daemon{} is newer than proj {}; upgrade the CLI",
+            daemon_version, VERSION
+        );
+        return Ok(stream);
+    }
+
+    let outdated = format!(
+        "The running daemon{} is older than proj {}",
+        daemon_version, VERSION
+    );
+    if let Some(host) = remote_host() {
+        anyhow::bail!(
+            "{}. Restart it on {} with: proj daemon restart",
+            outdated,
+            host
+        );
+    }
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!("{}. Restart it with: proj daemon restart", outdated);
+    }
+    print!("{}. Restart it now? [Y/n] ", outdated);
+    std::io::Write::flush(&mut std::io::stdout())?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if matches!(answer.trim(), "n" | "N" | "no") {
+        anyhow::bail!("Restart it with: proj daemon restart");
+    }
+    // Boxed, as stopping connects to the daemon again
+    Box::pin(cmd_daemon_stop()).await?;
+    cmd_daemon(false).await?;
+    Ok(None)
+}
+
+/// A long-lived connection receiving pushed responses (e.g. registry changes)
+struct Subscription {
+    lines: tokio::io::Lines<BufReader<ReadHalf<Connection>>>,
+    /// Dropping the write half closes the subscription on the daemon's side
+    writer: WriteHalf<Connection>,
+}
+
+impl Subscription {
+    /// Send a subscribe request and wait for the daemon to acknowledge it
+    async fn open(request: IpcRequest) -> Result<Self> {
+        let stream = connect_daemon().await?;
+        let (reader, mut writer) = tokio::io::split(stream);
+        let json = serde_json::to_string(&request)?;
+        writer.write_all(json.as_bytes()).await?;
+        writer.write_all(b"\n").await?;
+
+        let mut subscription = Self {
+            lines: BufReader::new(reader).lines(),
+            writer,
+        };
+        match subscription.next().await?.map(supported).transpose()? {
+            Some(IpcResponse::Success { .. }) => Ok(subscription),
+            Some(IpcResponse::Error(error)) => Err(error.into()),
+            _ => anyhow::bail!(tr!("unexpected-response")),
+        }
+    }
+
+    /// The next pushed response, or `None` once the daemon hangs up
+    async fn next(&mut self) -> Result<Option<IpcResponse>> {
+        match self.lines.next_line().await? {
+            Some(line) => Ok(Some(
+                serde_json::from_str(&line).context("Invalid response from daemon")?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Send a request on the open connection, such as keystrokes for an
+    /// attached terminal
+    async fn send(&mut self, request: &IpcRequest) -> Result<()> {
+        let json = serde_json::to_string(request)?;
+        self.writer.write_all(json.as_bytes()).await?;
+        self.writer.write_all(b"\n").await?;
+        Ok(())
+    }
+}
+
+/// Auto-start the daemon in the background
+async fn auto_start_daemon() -> Result<()> {
+    let daemon_path = daemon_binary()?;
+
+    if !daemon_path.exists() {
+        anyhow::bail!(tr!("daemon-binary-missing"));
+    }
+
+    start_in_background(&daemon_path)?;
+
+    // Wait for daemon to be ready
+    let socket = socket_path()?;
+    for _ in 0..20 {
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        if Ipc::connect(&socket).await.is_ok() {
+            warn_proxy_bind_error().await;
+            return Ok(());
+        }
+    }
+
+    anyhow::bail!("Daemon failed to start. Try: proj daemon -f")
+}
+
+/// The `proj-daemon` binary installed next to this one
+fn daemon_binary() -> Result<PathBuf> {
+    Ok(std::env::current_exe()?
+        .parent()
+        .context("No parent directory")?
+        .join(format!("proj-daemon{}", std::env::consts::EXE_SUFFIX)))
+}
+
+/// Start the daemon in the background: through the service manager once
+/// `proj daemon install` has run, otherwise as a detached process
+fn start_in_background(daemon_path: &std::path::Path) -> Result<()> {
+    if service::installed() {
+        service::start()
+    } else {
+        spawn_detached(daemon_path)
+    }
+}
+
+/// Start the daemon in the background, detached from this terminal
+fn spawn_detached(daemon_path: &std::path::Path) -> Result<()> {
+    let mut command = std::process::Command::new(daemon_path);
+    command
+        .envs(config_flag_env())
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+
+    // Without its own console the daemon survives the terminal closing
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const DETACHED_PROCESS: u32 = 0x0000_0008;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+        command.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+    }
+
+    command.spawn().context("Failed to start daemon")?;
+    Ok(())
+}
+
+/// Create a new project
+async fn cmd_new(name: String, dir: Option<PathBuf>, template: Option<String>) -> Result<()> {
+    naming::project_host(&name, load_config().tld())?;
+
+    let template = template
+        .map(|t| templates::Template::load(&t))
+        .transpose()?;
+    // Templates scaffold into a fresh directory, which is removed again if
+    // the project can't be created
+    let mut created_dir = None;
+    let root_dir = match (dir, &template) {
+        (dir, Some(_)) => {
+            let dir = dir.unwrap_or_else(|| PathBuf::from(&name));
+            templates::ensure_empty(&dir)?;
+            if !dir.exists() {
+                std::fs::create_dir_all(&dir)
+                    .with_context(|| format!("Failed to create {}", dir.display()))?;
+                created_dir = Some(dir.clone());
+            }
+            dir.canonicalize().context("Invalid directory path")?
+        }
+        (Some(d), None) => d.canonicalize().context("Invalid directory path")?,
+        (None, None) => std::env::current_dir()?,
+    };
+
+    let response = send_request(IpcRequest::CreateProject {
+        name: name.clone(),
+        root_dir: root_dir.clone(),
+        template: template.as_ref().map(|t| t.name.clone()),
+        run_command: template.as_ref().and_then(|t| t.dev_command.clone()),
+    })
+    .await;
+
+    let project = match response {
+        Ok(IpcResponse::Project(project)) => project,
+        other => {
+            if let Some(dir) = created_dir {
+                let _ = std::fs::remove_dir(dir);
+            }
+            match other? {
+                IpcResponse::Error(error) => return Err(error.into()),
+                _ => anyhow::bail!(tr!("unexpected-response")),
+            }
+        }
+    };
+
+    let name = format!("\x1b[1m{}\x1b[0m", project.name);
+    println!("\x1b[32m✓\x1b[0m {}", tr!("project-created", name = name));
+    let root = project.root_dir.display().to_string();
+    println!("  {}", tr!("project-root", path = root));
+    if let Some(template) = &template {
+        let files = template.scaffold(&project.root_dir, &project.name)?;
+        let name = template.name.as_str();
+        println!(
+            "  {}",
+            tr!("project-template", template = name, files = files)
+        );
+    }
+    println!();
+    println!("{}", tr!("next-steps"));
+    match &template {
+        Some(template) => {
+            if let Some(setup) = &template.setup {
+                println!(
+                    "  cd {} && {}",
+                    shell_quote(&project.root_dir.display().to_string()),
+                    setup
+                );
+            }
+            if template.dev_command.is_some() {
+                println!("  proj up {}   {}", project.name, tr!("next-up"));
+            } else {
+                println!("  proj {} run <cmd>   {}", project.name, tr!("next-run"));
+            }
+        }
+        None => println!("  proj {} run <cmd>   {}", project.name, tr!("next-run")),
+    }
+    println!("  proj {} open        {}", project.name, tr!("next-open"));
+
+    Ok(())
+}
+
+/// Register the git working tree (or, outside one, the directory) the
+/// current directory is in as a project, and name it in the root's proj.toml
+/// so later commands find it without scanning every project
+async fn cmd_init(name: Option<String>) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let root_dir = working_tree_root(&cwd).unwrap_or(cwd);
+    let named = ProjectFile::load(&root_dir)?
+        .map(|file| file.name)
+        .filter(|name| !name.is_empty());
+    if let (Some(name), Some(named)) = (&name, &named) {
+        if name != named {
+            anyhow::bail!(
+                "{} names project '{}'; run `proj init` without a name, or edit it",
+                root_dir.join(schema::PROJECT_FILE).display(),
+                named
+            );
+        }
+    }
+
+    let projects = list_projects(ProjectFilter::default()).await?;
+    let registered = projects.into_iter().find(|project| {
+        project.root_dir == root_dir && name.as_ref().is_none_or(|name| *name == project.name)
+    });
+    let (project, created) = match registered {
+        Some(project) => (project, false),
+        None => {
+            let name = match name.or(named) {
+                Some(name) => name,
+                None => project_name_for(&root_dir)?,
+            };
+            naming::project_host(&name, load_config().tld())?;
+            let response = send_request(IpcRequest::CreateProject {
+                name,
+                root_dir: root_dir.clone(),
+                template: None,
+                run_command: None,
+            })
+            .await?;
+            match response {
+                IpcResponse::Project(project) => (project, true),
+                IpcResponse::Error(IpcError::ProjectExists { name }) => anyhow::bail!(
+                    "Project '{}' already exists elsewhere; pick another name: proj init <name>",
+                    name
+                ),
+                IpcResponse::Error(error) => return Err(error.into()),
+                _ => anyhow::bail!(tr!("unexpected-response")),
+            }
+        }
+    };
+    ProjectFile::write_name(&root_dir, &project.name)?;
+
+    if created {
+        println!(
+            "\x1b[32m✓\x1b[0m Created project \x1b[1m{}\x1b[0m",
+            project.name
+        );
+    } else {
+        println!(
+            "\x1b[32m✓\x1b[0m \x1b[1m{}\x1b[0m is already a project",
+            project.name
+        );
+    }
+    println!("  Root: {}", project.root_dir.display());
+    println!(
+        "  Marker: {} (commit it to keep the name)",
+        root_dir.join(schema::PROJECT_FILE).display()
+    );
+    Ok(())
+}
+
+/// Root of the git working tree `dir` is in. Linked worktrees (where `.git`
+/// is a file) count as their own root, so each can be its own project.
+fn working_tree_root(dir: &std::path::Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(std::path::Path::to_path_buf)
+}
+
+/// A project name from a directory's name, e.g. `My App.v2` -> `my-app-v2`
+fn project_name_for(dir: &std::path::Path) -> Result<String> {
+    let base = dir.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    sanitize_label(base).with_context(|| {
+        format!(
+            "Can't name a project after {}: proj init <name>",
+            dir.display()
+        )
+    })
+}
+
+/// Options accepted by `proj <project> run` before the command itself
+#[derive(Debug, Default)]
+struct RunOptions {
+    restart: RestartPolicy,
+    max_restarts: Option<u32>,
+    watch: Option<WatchOptions>,
+    /// Run-only: hold the project's lock, queueing behind other exclusive runs
+    exclusive: bool,
+    /// Run-only: with `exclusive`, fail instead of queueing
+    no_wait: bool,
+    /// Run-only: take a global heavy-job slot, queueing while all are in use
+    heavy: bool,
+    /// Run-only: file to also write the process's output to
+    output: Option<PathBuf>,
+    /// Run-only: confinement overriding the project's
+    sandbox: Option<SandboxMode>,
+    /// Run-only: shorthand for the read-only sandbox
+    read_only: bool,
+    /// Run-only: make the process the one the project's hostname goes to
+    primary: bool,
+    /// Run-only: command to run next if the command succeeds
+    then: Option<Vec<String>>,
+    /// Run-only: command to run next if the command fails
+    otherwise: Option<Vec<String>>,
+    /// Run-only: memory, CPU and open-file caps
+    limits: ResourceLimits,
+    /// Run-only: profiler to run under
+    profile: Option<ProfileKind>,
+    /// Run-only: run in a pseudo-terminal and attach to it
+    tty: bool,
+    /// Run-only: what to do when the command is already running
+    on_conflict: Option<OnConflict>,
+    /// Service-only: port policy
+    port: Option<PortPolicy>,
+    /// Service-only: extra environment variables
+    env: BTreeMap<String, String>,
+    /// Service-only: readiness probe gating the route
+    health: Option<HealthCheck>,
+    /// Service-only: other ports it listens on, by name
+    named_ports: BTreeMap<String, u16>,
+}
+
+/// Split leading run options from the command to execute.
+///
+/// Options must come before the command; `--` ends option parsing.
+fn parse_run_args(args: Vec<String>) -> Result<(RunOptions, Vec<String>)> {
+    let mut options = RunOptions::default();
+    let mut iter = args.into_iter().peekable();
+
+    while let Some(arg) = iter.peek() {
+        if arg == "--" {
+            iter.next();
+            break;
+        }
+        if !arg.starts_with("--") {
+            break;
+        }
+
+        let arg = iter.next().unwrap_or_default();
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| iter.next())
+                .with_context(|| format!("Missing value for {}", flag))
+        };
+
+        match flag.as_str() {
+            "--restart" => options.restart = value()?.parse()?,
+            "--max-restarts" => {
+                options.max_restarts = Some(
+                    value()?
+                        .parse()
+                        .context("--max-restarts expects a number")?,
+                )
+            }
+            "--watch" => {
+                options.watch.get_or_insert_with(WatchOptions::default);
+            }
+            "--watch-ignore" => {
+                let pattern = value()?;
+                options
+                    .watch
+                    .get_or_insert_with(WatchOptions::default)
+                    .ignore
+                    .push(pattern);
+            }
+            "--watch-debounce" => {
+                let debounce_ms = value()?
+                    .parse()
+                    .context("--watch-debounce expects milliseconds")?;
+                options
+                    .watch
+                    .get_or_insert_with(WatchOptions::default)
+                    .debounce_ms = debounce_ms;
+            }
+            "--exclusive" => options.exclusive = true,
+            "--no-wait" => options.no_wait = true,
+            "--heavy" => options.heavy = true,
+            "--output" => options.output = Some(PathBuf::from(value()?)),
+            "--read-only" => options.read_only = true,
+            "--primary" => options.primary = true,
+            // A bare --sandbox means `files`; its value can't be a separate word
+            "--sandbox" => {
+                options.sandbox = Some(match &inline_value {
+                    Some(mode) => mode.parse()?,
+                    None => SandboxMode::Files,
+                })
+            }
+            "--mem" => options.limits.memory_bytes = Some(parse_size(&value()?)?),
+            "--cpus" => {
+                options.limits.cpus = Some(
+                    value()?
+                        .parse()
+                        .context("--cpus expects a number of cores")?,
+                )
+            }
+            "--files" => {
+                options.limits.open_files = Some(
+                    value()?
+                        .parse()
+                        .context("--files expects a number of open files")?,
+                )
+            }
+            "--profile" => options.profile = Some(value()?.parse()?),
+            "--tty" => options.tty = true,
+            "--on-conflict" => options.on_conflict = Some(value()?.parse()?),
+            "--then" => options.then = Some(split_command(&flag, &value()?)?),
+            "--else" => options.otherwise = Some(split_command(&flag, &value()?)?),
+            "--port" => options.port = Some(value()?.parse()?),
+            "--health" => {
+                let target = value()?;
+                let check = options.health.get_or_insert_with(HealthCheck::default);
+                check.smoke = target == "smoke";
+                check.path = match target.as_str() {
+                    "tcp" | "smoke" => None,
+                    path if path.starts_with('/') => Some(path.to_string()),
+                    _ => anyhow::bail!("--health expects an HTTP path (/health), 'tcp' or 'smoke'"),
+                };
+            }
+            "--health-interval" => {
+                options
+                    .health
+                    .get_or_insert_with(HealthCheck::default)
+                    .interval_ms = value()?
+                    .parse()
+                    .context("--health-interval expects milliseconds")?;
+            }
+            "--health-timeout" => {
+                options
+                    .health
+                    .get_or_insert_with(HealthCheck::default)
+                    .timeout_ms = value()?
+                    .parse()
+                    .context("--health-timeout expects milliseconds")?;
+            }
+            "--env" => {
+                let pair = value()?;
+                let (key, value) = pair
+                    .split_once('=')
+                    .with_context(|| format!("Expected KEY=VALUE, got '{}'", pair))?;
+                options.env.insert(key.to_string(), value.to_string());
+            }
+            "--named-port" => {
+                let pair = value()?;
+                let (name, port) = pair
+                    .split_once('=')
+                    .with_context(|| format!("Expected NAME=PORT, got '{}'", pair))?;
+                let port = port
+                    .parse()
+                    .with_context(|| format!("Invalid port in '{}'", pair))?;
+                options.named_ports.insert(name.to_string(), port);
+            }
+            _ => anyhow::bail!("Unknown run option: {}", flag),
+        }
+    }
+
+    Ok((options, iter.collect()))
+}
+
+/// Split a command given as one argument, e.g. `--then "npm test"`.
+///
+/// Words are split with a shell's quoting, so `"a b"` is one word, but no
+/// shell runs it: pipes, redirects and `&&` would reach the program as
+/// arguments, so they're refused instead.
+fn split_command(flag: &str, command: &str) -> Result<Vec<String>> {
+    let words = shell_words::split(command)
+        .with_context(|| format!("{} has unbalanced quotes: {}", flag, command))?;
+    if words.is_empty() {
+        anyhow::bail!("{} expects a command", flag);
+    }
+    if let Some(operator) = words
+        .iter()
+        .find(|word| SHELL_OPERATORS.contains(&word.as_str()))
+    {
+        anyhow::bail!(
+            "`{}` in {} needs a shell, which proj doesn't run commands in; use sh -c {}",
+            operator,
+            flag,
+            shell_words::quote(command)
+        );
+    }
+    Ok(words)
+}
+
+/// Words a shell would treat as syntax rather than arguments
+const SHELL_OPERATORS: &[&str] = &["|", "||", "&&", "&", ";", ">", ">>", "<", "2>", "2>&1"];
+
+/// Run a command in project context
+async fn cmd_run(project_name: String, command: Vec<String>) -> Result<()> {
+    let (options, command) = parse_run_args(command)?;
+    // `run "npm run dev"`: one argument holding a whole command line
+    let command = match command.as_slice() {
+        [line] if line.contains(char::is_whitespace) && !std::path::Path::new(line).exists() => {
+            split_command("the command", line)?
+        }
+        _ => command,
+    };
+    if command.is_empty() {
+        anyhow::bail!("No command specified");
+    }
+    if options.port.is_some()
+        || !options.env.is_empty()
+        || options.health.is_some()
+        || !options.named_ports.is_empty()
+    {
+        anyhow::bail!(
+            "--port, --env, --health and --named-port are for services: proj {} service add <name> [options] -- <cmd>",
+            project_name
+        );
+    }
+    if options.no_wait && !options.exclusive {
+        anyhow::bail!("--no-wait only applies to --exclusive runs");
+    }
+    options.limits.validate()?;
+    if options.tty {
+        if options.restart != RestartPolicy::Never
+            || options.watch.is_some()
+            || options.then.is_some()
+            || options.otherwise.is_some()
+        {
+            anyhow::bail!("--tty runs can't restart, watch files or chain --then/--else");
+        }
+        attach::check_terminal()?;
+    }
+    let sandbox = match (options.read_only, options.sandbox) {
+        (true, Some(_)) => anyhow::bail!("--read-only is a sandbox mode; use it or --sandbox"),
+        (true, None) => Some(SandboxMode::ReadOnly),
+        (false, sandbox) => sandbox,
+    };
+    // The daemon runs elsewhere, so resolve against this shell's directory
+    let output = match &options.output {
+        Some(path) => Some(std::env::current_dir()?.join(path)),
+        None => None,
+    };
+
+    let cmd = command[0].clone();
+    let args = command[1..].to_vec();
+
+    // Quoted as a shell would need it, so arguments with spaces stand out
+    println!(
+        "\x1b[36m▶\x1b[0m Running in \x1b[1m{}\x1b[0m: {}",
+        project_name,
+        shell_words::join(&command)
+    );
+
+    let request = |no_wait| IpcRequest::RunCommand {
+        project_name: project_name.clone(),
+        command: cmd.clone(),
+        args: args.clone(),
+        restart: options.restart,
+        max_restarts: options.max_restarts.unwrap_or(DEFAULT_MAX_RESTARTS),
+        watch: options.watch.clone(),
+        exclusive: options.exclusive,
+        no_wait,
+        heavy: options.heavy,
+        output: output.clone(),
+        sandbox,
+        primary: options.primary,
+        then: options.then.clone(),
+        otherwise: options.otherwise.clone(),
+        limits: options.limits,
+        profile: options.profile,
+        tty: options.tty.then(attach::size),
+        cwd: std::env::current_dir().ok(),
+        on_conflict: options.on_conflict.unwrap_or_default(),
+    };
+
+    // Ask without queueing first so a wait can be announced
+    let mut response = send_run_request(request(options.exclusive)).await?;
+    if let IpcResponse::Locked { message } = &response {
+        if options.no_wait {
+            anyhow::bail!("{}", message);
+        }
+        println!("\x1b[33m…\x1b[0m {}, waiting (Ctrl+C to give up)", message);
+        response = send_run_request(request(false)).await?;
+    }
+
+    match response {
+        IpcResponse::ProcessStarted {
+            process, warnings, ..
+        } if options.tty => {
+            println!(
+                "  PID: {} (Ctrl+] detaches, leaving it running)",
+                process.pid
+            );
+            print_warnings(&warnings);
+            return attached(&project_name, &process).await;
+        }
+        IpcResponse::ProcessStarted {
+            process,
+            url,
+            https_url,
+            command_path,
+            warnings,
+        } => {
+            println!("  PID: {}", process.pid);
+            if let Some(path) = &command_path {
+                println!("  Command: {}", path.display());
+            }
+            if process.restart_policy != RestartPolicy::Never {
+                println!("  Restart: {:?}", process.restart_policy);
+            }
+            if process.watching {
+                println!("  Watching for file changes");
+            }
+            if let Some(path) = &process.output {
+                println!("  Output: {}", path.display());
+            }
+            if !process.limits.is_empty() {
+                println!("  Limits: {}", process.limits);
+            }
+            if let Some(profile) = &process.profile {
+                println!(
+                    "  Profile: {} ({} profile, written on exit)",
+                    profile.path.display(),
+                    profile.kind
+                );
+                println!("  Open with: {}", profile.open_with);
+            }
+            if let Some(then) = &options.then {
+                println!("  Then: {}", then.join(" "));
+            }
+            if let Some(otherwise) = &options.otherwise {
+                println!("  Else: {}", otherwise.join(" "));
+            }
+            print_warnings(&warnings);
+            println!();
+            // Older daemons leave the URL to the client's reading of the
+            // config, and a project in direct mode has none until its port
+            let url = match url {
+                Some(url) => Some(url),
+                None => load_config().project_url(&get_project(&project_name).await?),
+            };
+            if let Some(url) = url {
+                println!("\x1b[32m✓\x1b[0m Access at: \x1b[4m{}\x1b[0m", url);
+            }
+            if let Some(https_url) = https_url {
+                println!("  HTTPS: \x1b[4m{}\x1b[0m", https_url);
+            }
+            println!("  Stop with: proj {} stop", project_name);
+        }
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => {
+            anyhow::bail!(tr!("unexpected-response"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Warnings the daemon gave with a started run
+fn print_warnings(warnings: &[String]) {
+    for warning in warnings {
+        println!("  \x1b[33m⚠\x1b[0m {}", warning);
+    }
+}
+
+/// `attach [<id>]`: reattach to a run started with `--tty`
+async fn cmd_attach(project_name: &str, id: Option<&str>) -> Result<()> {
+    attach::check_terminal()?;
+    let running = running_processes(project_name).await?;
+    let process = match (id, running.as_slice()) {
+        (Some(id), _) => find_process(&running, project_name, id)?,
+        (None, [process]) => process,
+        (None, []) => anyhow::bail!("No running processes for project '{}'", project_name),
+        (None, _) => anyhow::bail!(
+            "{} has several running processes; pick one: proj {} attach <id> (see proj {} ps)",
+            project_name,
+            project_name,
+            project_name
+        ),
+    };
+    attached(project_name, process).await
+}
+
+/// Attach to a process's terminal, then report how the session ended,
+/// exiting with the process's code
+async fn attached(project_name: &str, process: &ProcessInfo) -> Result<()> {
+    match attach::attach(process).await? {
+        attach::Ended::Exited(Some(0)) => Ok(()),
+        attach::Ended::Exited(Some(code)) => std::process::exit(code),
+        attach::Ended::Exited(None) => anyhow::bail!("The process was killed"),
+        attach::Ended::Detached => {
+            println!(
+                "\n\x1b[33m…\x1b[0m Detached; {} keeps running. Reattach with: proj {} attach {}",
+                process.short_id(),
+                project_name,
+                process.short_id()
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Open browser for a project
+async fn cmd_open(project_name: String, browser: Option<String>) -> Result<()> {
+    let project = get_project(&project_name).await?;
+    let browser = project_browser(&project, browser.as_deref())?;
+    let url = open_url(&project)?;
+
+    println!(
+        "\x1b[36m▶\x1b[0m Opening \x1b[4m{}\x1b[0m with isolated {} profile",
+        url, browser
+    );
+
+    if browser.open(&project.name, &url)? == Opened::Default {
+        println!("\x1b[33m⚠\x1b[0m {}", browser.fallback_notice());
+    }
+    Ok(())
+}
+
+/// The browser to open a project in: `choice` if given, else the `browser`
+/// setting as resolved for the project's root
+fn project_browser(project: &Project, choice: Option<&str>) -> Result<Browser> {
+    match choice {
+        Some(choice) => choice.parse(),
+        None => {
+            let flags = CONFIG_FLAGS.get().map_or(&[][..], Vec::as_slice);
+            layers::load(Some(&project.root_dir), flags)?
+                .config
+                .browser
+                .parse()
+        }
+    }
+}
+
+/// Launch a project's configured browser on its URL with its isolated profile
+fn open_browser(project: &Project) -> Result<Opened> {
+    project_browser(project, None)?.open(&project.name, &open_url(project)?)
+}
+
+/// Where a browser is pointed at a project: its URL, which in direct mode
+/// needs the project to have a port
+fn open_url(project: &Project) -> Result<String> {
+    load_config().project_url(project).with_context(|| {
+        format!(
+            "{} is in direct mode and has no port yet; start it first",
+            project.name
+        )
+    })
+}
+
+/// Delete a project's isolated browser profile so the next `open` starts fresh
+async fn cmd_profile_reset(project_name: &str, flags: &[String]) -> Result<()> {
+    let (choice, yes) = match flags {
+        [] => (None, false),
+        [yes] if yes == "-y" || yes == "--yes" => (None, true),
+        [flag, browser] if flag == "--browser" => (Some(browser.as_str()), false),
+        [flag, browser, yes] | [yes, flag, browser]
+            if flag == "--browser" && (yes == "-y" || yes == "--yes") =>
+        {
+            (Some(browser.as_str()), true)
+        }
+        _ => anyhow::bail!(
+            "Usage: proj {} profile reset [--browser <browser>] [-y]",
+            project_name
+        ),
+    };
+    let project = get_project(project_name).await?;
+    let browser = project_browser(&project, choice)?;
+    let profile = browser.profile_dir(&project.name)?;
+    if !profile.exists() {
+        let browser = browser.to_string();
+        let project = project.name.as_str();
+        println!(
+            "{}",
+            tr!("no-profile", project = project, browser = browser)
+        );
+        return Ok(());
+    }
+
+    if !yes {
+        let question = tr!(
+            "profile-reset-confirm",
+            project = project.name.as_str(),
+            browser = browser.to_string(),
+            path = profile.display().to_string()
+        );
+        print!("{} ", question);
+        std::io::Write::flush(&mut std::io::stdout())?;
+
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            println!("{}", tr!("aborted"));
+            return Ok(());
+        }
+    }
+    if !close_profile_browser(&project.name, &browser.to_string(), &profile, yes).await? {
+        println!("{}", tr!("aborted"));
+        return Ok(());
+    }
+
+    trash::put(
+        trash::Operation::ProfileReset,
+        &project.name,
+        &[profile],
+        None,
+    )?;
+    let done = tr!(
+        "profile-reset",
+        project = project.name.as_str(),
+        browser = browser.to_string()
+    );
+    println!("\x1b[32m✓\x1b[0m {}", done);
+    Ok(())
+}
+
+/// Make sure no browser still has `profile` open before it goes, closing
+/// one that does once the user agrees (or with `yes`), and force-quitting it
+/// if it hangs. A profile removed from under a running browser is left
+/// half-written, in the trash and in whatever the browser recreates.
+/// Returns false if the user said no.
+async fn close_profile_browser(
+    project_name: &str,
+    browser: &str,
+    profile: &std::path::Path,
+    yes: bool,
+) -> Result<bool> {
+    let pids = browser::profile_users(profile);
+    if pids.is_empty() {
+        return Ok(true);
+    }
+    let list = |pids: &[u32]| {
+        pids.iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let open = tr!(
+        "browser-profile-open",
+        project = project_name,
+        browser = browser,
+        pids = list(&pids)
+    );
+    println!("\x1b[33m⚠\x1b[0m {}", open);
+    if !yes {
+        if !std::io::stdin().is_terminal() {
+            anyhow::bail!(tr!(
+                "browser-close-first",
+                project = project_name,
+                browser = browser
+            ));
+        }
+        if !confirm(&format!("{} ", tr!("browser-close-confirm")))? {
+            return Ok(false);
+        }
+    }
+    if browser::close(profile, &pids, false).await {
+        println!(
+            "\x1b[32m✓\x1b[0m {}",
+            tr!("browser-closed", browser = browser)
+        );
+        return Ok(true);
+    }
+
+    let pids = browser::profile_users(profile);
+    let seconds = browser::CLOSE_TIMEOUT.as_secs();
+    let timeout = tr!(
+        "browser-close-timeout",
+        browser = browser,
+        seconds = seconds
+    );
+    println!("\x1b[33m⚠\x1b[0m {}", timeout);
+    if !yes && !confirm(&format!("{} ", tr!("browser-force-confirm")))? {
+        return Ok(false);
+    }
+    if !browser::close(profile, &pids, true).await {
+        anyhow::bail!(tr!(
+            "browser-quit-failed",
+            project = project_name,
+            browser = browser,
+            pids = list(&browser::profile_users(profile))
+        ));
+    }
+    println!(
+        "\x1b[32m✓\x1b[0m {}",
+        tr!("browser-force-quit", browser = browser)
+    );
+    Ok(true)
+}
+
+/// Ask a yes/no question on the terminal, defaulting to no
+fn confirm(question: &str) -> Result<bool> {
+    print!("{}", question);
+    std::io::Write::flush(&mut std::io::stdout())?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// URL a project is served at, per the proxy settings in config.json
+fn project_url(project_name: &str) -> String {
+    load_config().url(project_name)
+}
+
+/// Hostname a project is served at, e.g. `my-app.localhost`
+fn project_host(project_name: &str) -> String {
+    load_config().host(project_name)
+}
+
+/// A project's name as listings show it: bold, in its color, after its icon
+fn styled_name(project: &Project) -> String {
+    style_name(&project.name, &project.appearance)
+}
+
+fn style_name(name: &str, appearance: &Appearance) -> String {
+    let name = match appearance.rgb() {
+        Some((r, g, b)) => format!("\x1b[1;38;2;{};{};{}m{}\x1b[0m", r, g, b, name),
+        None => format!("\x1b[1m{}\x1b[0m", name),
+    };
+    match &appearance.icon {
+        Some(icon) => format!("{} {}", icon, name),
+        None => name,
+    }
+}
+
+/// How long `proj up` waits for a project to accept connections, or pass its
+/// health check, before moving on
+const UP_READY_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(30);
+
+/// Start projects' services (or last run command), a bounded number at a time,
+/// each after the projects it depends on are ready
+async fn cmd_up(names: Vec<String>, all: bool, jobs: Option<usize>) -> Result<()> {
+    if names.is_empty() && !all {
+        anyhow::bail!("Usage: proj up <project|group>... | --all [-j N]");
+    }
+
+    let projects = list_projects(ProjectFilter::default()).await?;
+    let running: Vec<(String, Option<String>)> =
+        match send_request(IpcRequest::ListProcesses { project_name: None }).await? {
+            IpcResponse::Processes(processes) => processes
+                .into_iter()
+                .filter(|p| p.status.is_alive())
+                .map(|p| (p.project_name, p.service))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+    let config = load_config();
+    let startable = |p: &Project| !p.services.is_empty() || p.run_command.is_some();
+    let names = if all {
+        projects
+            .iter()
+            .filter(|p| startable(p))
+            .map(|p| p.name.clone())
+            .collect()
+    } else {
+        config.expand_groups(&names)
+    };
+    let order = deps::start_order(&projects, &names)?;
+
+    // One unit per service, or the last run command for projects without
+    // services, in waves that only depend on earlier waves
+    let mut waves = Vec::new();
+    for wave in order {
+        let mut targets = Vec::new();
+        for name in wave {
+            let project = projects
+                .iter()
+                .find(|p| p.name == name)
+                .expect("ordered projects exist");
+            if !startable(project) {
+                anyhow::bail!(
+                    "Project '{}' has no services and has never been run. \
+                     Add one with: proj {} service add <name> -- <cmd>",
+                    name,
+                    name
+                );
+            }
+            let services: Vec<Option<String>> = if project.services.is_empty() {
+                vec![None]
+            } else {
+                project.services.keys().cloned().map(Some).collect()
+            };
+            for service in services {
+                let up = running.iter().any(|(name, svc)| {
+                    name == &project.name && (service.is_none() || svc == &service)
+                });
+                let label = match &service {
+                    Some(service) => format!("{}/{}", project.name, service),
+                    None => project.name.clone(),
+                };
+                if up {
+                    println!("\x1b[90m○\x1b[0m {} already running", label);
+                } else {
+                    targets.push((label, project.clone(), service));
+                }
+            }
+        }
+        if !targets.is_empty() {
+            waves.push(targets);
+        }
+    }
+    if waves.is_empty() {
+        return Ok(());
+    }
+
+    let jobs = jobs.unwrap_or(config.up_concurrency).max(1);
+    let total: usize = waves.iter().map(Vec::len).sum();
+    println!(
+        "\x1b[36m▶\x1b[0m Starting {} service(s), {} at a time",
+        total, jobs
+    );
+
+    // Each task holds a permit until its project is ready, so at most `jobs`
+    // projects are booting at once
+    let permits = std::sync::Arc::new(tokio::sync::Semaphore::new(jobs));
+    let mut started = 0;
+    let mut failures = Vec::new();
+    let mut interrupted = false;
+    let mut waves = waves.into_iter();
+    for wave in waves.by_ref() {
+        let mut tasks = tokio::task::JoinSet::new();
+        for (label, project, service) in wave {
+            let permits = permits.clone();
+            tasks.spawn(async move {
+                let _permit = permits.acquire_owned().await;
+                let result = start_unit(&project, service).await;
+                (label, result)
+            });
+        }
+
+        interrupted = loop {
+            tokio::select! {
+                next = tasks.join_next() => {
+                    let Some(next) = next else { break false };
+                    match next {
+                        Ok((_, Ok(()))) => started += 1,
+                        Ok((name, Err(e))) => failures.push((name, e)),
+                        Err(e) => failures.push(("?".to_string(), e.into())),
+                    }
+                    print!("\r\x1b[K  {}/{} started", started, total);
+                    std::io::Write::flush(&mut std::io::stdout())?;
+                }
+                _ = tokio::signal::ctrl_c() => {
+                    tasks.abort_all();
+                    break true;
+                }
+            }
+        };
+        // Later waves depend on this one
+        if interrupted || !failures.is_empty() {
+            break;
+        }
+    }
+    println!();
+
+    for (name, e) in &failures {
+        println!("\x1b[31m✗\x1b[0m {}: {}", name, e);
+    }
+    let skipped: Vec<String> = waves.flatten().map(|(label, _, _)| label).collect();
+    if !interrupted && !skipped.is_empty() {
+        println!(
+            "\x1b[33m■\x1b[0m Not started, their dependencies failed: {}",
+            skipped.join(", ")
+        );
+    }
+    if interrupted {
+        println!(
+            "\x1b[33m■\x1b[0m Cancelled: {}/{} started; started projects keep running",
+            started, total
+        );
+    } else if failures.is_empty() {
+        println!("\x1b[32m✓\x1b[0m All {} service(s) started", total);
+    }
+
+    if !failures.is_empty() {
+        anyhow::bail!("{} of {} service(s) failed to start", failures.len(), total);
+    }
+    Ok(())
+}
+
+/// Stop projects, dependents before the projects they depend on
+async fn cmd_down(names: Vec<String>, all: bool) -> Result<()> {
+    if names.is_empty() && !all {
+        anyhow::bail!("Usage: proj down <project|group>... | --all");
+    }
+
+    let projects = list_projects(ProjectFilter::default()).await?;
+    let running: Vec<ProcessInfo> =
+        match send_request(IpcRequest::ListProcesses { project_name: None }).await? {
+            IpcResponse::Processes(processes) => processes
+                .into_iter()
+                .filter(|p| p.status.is_alive())
+                .collect(),
+            IpcResponse::Error(error) => return Err(error.into()),
+            _ => anyhow::bail!(tr!("unexpected-response")),
+        };
+
+    let names = if all {
+        projects.iter().map(|p| p.name.clone()).collect()
+    } else {
+        load_config().expand_groups(&names)
+    };
+    // Dependencies outside the selection may be shared, so they keep running
+    let order = deps::start_order(&projects, &names)?;
+
+    let mut failed = 0;
+    for name in order.into_iter().rev().flatten() {
+        if !names.contains(&name) {
+            continue;
+        }
+        let processes: Vec<&ProcessInfo> =
+            running.iter().filter(|p| p.project_name == name).collect();
+        if processes.is_empty() {
+            if !all {
+                println!("\x1b[90m○\x1b[0m {} not running", name);
+            }
+            continue;
+        }
+        for process in processes {
+            match send_request(IpcRequest::StopProcess {
+                project_name: name.clone(),
+                process_id: process.id,
+            })
+            .await?
+            {
+                IpcResponse::Success { .. } => {
+                    let label = match &process.service {
+                        Some(service) => format!("{}/{}", name, service),
+                        None => name.clone(),
+                    };
+                    println!(
+                        "\x1b[33m■\x1b[0m Stopped \x1b[1m{}\x1b[0m (PID: {})",
+                        label, process.pid
+                    );
+                }
+                IpcResponse::Error(error) => {
+                    failed += 1;
+                    eprintln!(
+                        "\x1b[31m✗\x1b[0m Failed to stop {}: {}",
+                        name,
+                        error_message(error)
+                    );
+                }
+                _ => anyhow::bail!(tr!("unexpected-response")),
+            }
+        }
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{} process(es) failed to stop", failed);
+    }
+    Ok(())
+}
+
+/// Start a service (or the project's last run command) and wait until it
+/// passes its health check, or without one until it listens, exits, or times out
+async fn start_unit(project: &Project, service: Option<String>) -> Result<()> {
+    let health_checked = service
+        .as_ref()
+        .and_then(|name| project.services.get(name))
+        .is_some_and(|s| s.health.is_some());
+    let request = match service {
+        Some(name) => IpcRequest::StartService {
+            project_name: project.name.clone(),
+            name,
+            debug: false,
+        },
+        None => {
+            let command = project.run_command.clone().unwrap_or_default();
+            let (cmd, args) = command.split_first().context("No run command recorded")?;
+            IpcRequest::RunCommand {
+                project_name: project.name.clone(),
+                command: cmd.clone(),
+                args: args.to_vec(),
+                restart: RestartPolicy::default(),
+                max_restarts: DEFAULT_MAX_RESTARTS,
+                watch: None,
+                exclusive: false,
+                no_wait: false,
+                heavy: false,
+                output: None,
+                sandbox: None,
+                primary: false,
+                then: None,
+                otherwise: None,
+                limits: ResourceLimits::default(),
+                profile: None,
+                tty: None,
+                cwd: None,
+                // `up` on a project already running its command leaves it be
+                on_conflict: OnConflict::Attach,
+            }
+        }
+    };
+
+    let process = match send_request(request).await? {
+        IpcResponse::ProcessStarted { process, .. } => process,
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    };
+
+    let deadline = tokio::time::Instant::now() + UP_READY_TIMEOUT;
+    while tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
+
+        let current = match send_request(IpcRequest::ListProcesses {
+            project_name: Some(project.name.clone()),
+        })
+        .await?
+        {
+            IpcResponse::Processes(processes) => processes.into_iter().find(|p| p.id == process.id),
+            _ => None,
+        };
+        let Some(current) = current else {
+            anyhow::bail!("Process disappeared");
+        };
+        if !current.status.is_alive() {
+            if let Some(reason) = &current.failure_reason {
+                anyhow::bail!("{}", reason);
+            }
+            match current.last_exit_code {
+                Some(code) => anyhow::bail!("Exited with code {}", code),
+                None => anyhow::bail!("Exited"),
+            }
+        }
+        if health_checked {
+            if current.healthy == Some(true) {
+                return Ok(());
+            }
+        } else if let Some(port) = current.port {
+            if tokio::net::TcpStream::connect(("127.0.0.1", port))
+                .await
+                .is_ok()
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    if health_checked {
+        anyhow::bail!(
+            "Health check did not pass within {}s",
+            UP_READY_TIMEOUT.as_secs()
+        );
+    }
+    // Not every project listens on a port; a process still running counts as started
+    Ok(())
+}
+
+/// Audit listening ports: which belong to projects, and what squats on project ports
+async fn cmd_ports() -> Result<()> {
+    let usage = match send_request(IpcRequest::ListPorts).await? {
+        IpcResponse::Ports(usage) => usage,
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    };
+    let projects = list_projects(ProjectFilter::default()).await?;
+
+    if usage.is_empty() {
+        println!("No listening ports found");
+        return Ok(());
+    }
+
+    println!("{:>6}  {:>7}  {:<20}  PROJECT", "PORT", "PID", "PROCESS");
+    let mut conflicts = 0;
+    for entry in &usage {
+        // A project's port held by something other than that project's process
+        let expected_by: Vec<&str> = projects
+            .iter()
+            .filter(|p| p.port == Some(entry.port))
+            .map(|p| p.name.as_str())
+            .filter(|name| entry.project_name.as_deref() != Some(*name))
+            .collect();
+
+        let pid = entry.pid.map(|p| p.to_string()).unwrap_or("-".into());
+        let process = entry.process_name.as_deref().unwrap_or("-");
+        let project = if !entry.listening {
+            format!(
+                "\x1b[90m{} (not listening)\x1b[0m",
+                entry.project_name.as_deref().unwrap_or_default()
+            )
+        } else if !expected_by.is_empty() {
+            conflicts += 1;
+            format!("\x1b[31m⚠ conflicts with {}\x1b[0m", expected_by.join(", "))
+        } else {
+            entry.project_name.clone().unwrap_or_default()
+        };
+
+        println!(
+            "{:>6}  {:>7}  {:<20}  {}",
+            entry.port, pid, process, project
+        );
+    }
+
+    if conflicts > 0 {
+        println!();
+        println!(
+            "\x1b[33m{} port conflict{}\x1b[0m: stop the squatting process or run the project on another port",
+            conflicts,
+            if conflicts == 1 { "" } else { "s" }
+        );
+    }
+
+    Ok(())
+}
+
+/// Print daemon events until interrupted
+async fn cmd_watch(project_name: Option<String>, events: Vec<String>, json: bool) -> Result<()> {
+    let mut subscription = Subscription::open(IpcRequest::Subscribe {
+        filters: EventFilters {
+            project_name,
+            events,
+        },
+    })
+    .await?;
+
+    while let Some(response) = subscription.next().await? {
+        let IpcResponse::Event(event) = response else {
+            continue;
+        };
+        if json {
+            println!("{}", serde_json::to_string(&event)?);
+            continue;
+        }
+
+        let detail = match &event.kind {
+            EventKind::ProcessStarted {
+                pid,
+                service,
+                command,
+                ..
+            } => match service {
+                Some(service) => format!("{} pid {}: {}", service, pid, command),
+                None => format!("pid {}: {}", pid, command),
+            },
+            EventKind::ProcessExited {
+                exit_code, status, ..
+            } => match exit_code {
+                Some(code) => format!("{:?}, exit code {}", status, code).to_lowercase(),
+                None => format!("{:?}", status).to_lowercase(),
+            },
+            EventKind::PortDetected { port, .. } => format!("port {}", port),
+            EventKind::RouteAdded { port } => {
+                format!("{} -> {}", project_url(&event.project_name), port)
+            }
+            EventKind::RouteRemoved => project_url(&event.project_name),
+            EventKind::ProjectCreated | EventKind::ProjectDeleted => String::new(),
+            EventKind::CrashBundle { path, location, .. } => match location {
+                Some(location) => format!("{} ({})", path.display(), location),
+                None => path.display().to_string(),
+            },
+            EventKind::Milestone {
+                milestone,
+                after_ms,
+                ..
+            } => format!("{} after {}", milestone, format_ms(*after_ms)),
+            EventKind::Budget {
+                metric,
+                value,
+                limit,
+                exceeded,
+            } => {
+                let state = if *exceeded { "over" } else { "within" };
+                format!(
+                    "{} {}, {} its {} budget",
+                    metric,
+                    metric.format(*value),
+                    state,
+                    metric.format(*limit)
+                )
+            }
+            EventKind::Panic {
+                task,
+                message,
+                location,
+            } => match location {
+                Some(location) => format!("in {}: {} at {}", task, message, location),
+                None => format!("in {}: {}", task, message),
+            },
+            EventKind::Maintenance(report) => report.to_string(),
+            EventKind::NetworkChanged {
+                added,
+                removed,
+                lan_urls,
+            } => {
+                let mut changes: Vec<String> = added.iter().map(|ip| format!("+{}", ip)).collect();
+                changes.extend(removed.iter().map(|ip| format!("-{}", ip)));
+                match lan_urls.as_slice() {
+                    [] => changes.join(" "),
+                    urls => format!("{}; LAN: {}", changes.join(" "), urls.join(", ")),
+                }
+            }
+            // The `bell` notification backend
+            EventKind::Notification { title, message, .. } => {
+                print!("\x07");
+                format!("{}: {}", title, message)
+            }
+        };
+        println!(
+            "\x1b[90m{}\x1b[0m \x1b[1m{:<16}\x1b[0m {:<16} {}",
+            event.timestamp.with_timezone(&Local).format("%H:%M:%S"),
+            event.project_name,
+            event.kind.name(),
+            detail
+        );
+    }
+
+    anyhow::bail!("Daemon closed the connection")
+}
+
+/// Set do-not-disturb's mode, or show where it stands without one
+async fn cmd_dnd(mode: Option<DndMode>) -> Result<()> {
+    let status = match send_request(IpcRequest::SetDnd { mode }).await? {
+        IpcResponse::Dnd(status) => status,
+        IpcResponse::UnsupportedRequest { .. } => anyhow::bail!(
+            "The daemon predates do-not-disturb; restart it with `proj daemon restart`"
+        ),
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    };
+    let state = if status.active { "on" } else { "off" };
+    let why = match status.mode {
+        DndMode::Auto if status.sessions > 0 => format!(
+            " ({} attached terminal{})",
+            status.sessions,
+            if status.sessions == 1 { "" } else { "s" }
+        ),
+        DndMode::Auto => " (on while a terminal is attached)".to_string(),
+        DndMode::On | DndMode::Off => " (set by hand; `proj dnd auto` undoes it)".to_string(),
+    };
+    if mode.is_some() {
+        println!("\x1b[32m✓\x1b[0m Do not disturb: {}{}", state, why);
+    } else {
+        println!("Do not disturb: {}{}", state, why);
+    }
+    if status.held > 0 {
+        println!(
+            "  {} notification{} held for a summary when it ends",
+            status.held,
+            if status.held == 1 { "" } else { "s" }
+        );
+    }
+    Ok(())
+}
+
+/// Resource usage of running processes, for one project or all of them
+async fn cmd_ps(project_name: Option<&str>, sort: &str) -> Result<()> {
+    let mut processes = match send_request(IpcRequest::ListProcesses {
+        project_name: project_name.map(str::to_string),
+    })
+    .await?
+    {
+        IpcResponse::Processes(processes) => processes,
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    };
+    processes.retain(|p| p.status.is_alive());
+
+    let usage = |p: &ProcessInfo| p.usage.unwrap_or_default();
+    match sort {
+        "cpu" => processes.sort_by(|a, b| usage(b).cpu_percent.total_cmp(&usage(a).cpu_percent)),
+        "mem" => processes.sort_by_key(|p| std::cmp::Reverse(usage(p).memory_bytes)),
+        "uptime" => processes.sort_by_key(|p| p.started_at),
+        other => anyhow::bail!("Unknown sort key '{}'. Use cpu, mem or uptime", other),
+    }
+
+    let locks = match send_request(IpcRequest::ListLocks {
+        project_name: project_name.map(str::to_string),
+    })
+    .await?
+    {
+        IpcResponse::Locks(locks) => locks,
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    };
+
+    if processes.is_empty() && locks.iter().all(|l| l.waiters.is_empty()) {
+        println!("No running processes");
+        return Ok(());
+    }
+
+    println!(
+        "\x1b[1m{:<8} {:<24} {:>7} {:>6} {:>9} {:>8} {:>6}  COMMAND\x1b[0m",
+        "ID", "PROJECT", "PID", "CPU%", "MEM", "UPTIME", "PORT"
+    );
+    let now = Utc::now();
+    for p in &processes {
+        let name = match &p.service {
+            Some(service) => format!("{}/{}", p.project_name, service),
+            None => p.project_name.clone(),
+        };
+        // Usage appears after the daemon's first sample
+        let (cpu, mem) = match p.usage {
+            Some(usage) => (
+                format!("{:.1}", usage.cpu_percent),
+                format_bytes(usage.memory_bytes),
+            ),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        let exclusive = locks.iter().any(|l| l.holder == Some(p.id));
+        // Follow-up stages of a chained run say which branch they are
+        let stage = match p.job {
+            Some(job) if job.stage != JobStage::Run => format!("  \x1b[2m({})\x1b[0m", job.stage),
+            _ => String::new(),
+        };
+        println!(
+            "{:<8} {:<24} {:>7} {:>6} {:>9} {:>8} {:>6}  {}{}{}{}",
+            p.short_id(),
+            name,
+            p.pid,
+            cpu,
+            mem,
+            format_uptime(now - p.started_at),
+            p.port.map(|port| port.to_string()).unwrap_or("-".into()),
+            p.command,
+            stage,
+            if exclusive {
+                "  \x1b[33m(exclusive)\x1b[0m"
+            } else {
+                ""
+            },
+            if p.primary {
+                "  \x1b[36m(primary)\x1b[0m"
+            } else {
+                ""
+            }
+        );
+    }
+    // Queued exclusive runs, with how long they have waited
+    for lock in &locks {
+        for waiter in &lock.waiters {
+            println!(
+                "{:<8} {:<24} {:>7} {:>6} {:>9} {:>8} {:>6}  \x1b[2mwaiting:\x1b[0m {}",
+                "-",
+                lock.project_name,
+                "-",
+                "-",
+                "-",
+                format_uptime(now - waiter.queued_at),
+                "-",
+                waiter.command
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Human-readable byte count: 512K, 48.2M, 1.3G
+fn format_bytes(bytes: u64) -> String {
+    const KB: f64 = 1024.0;
+    let bytes = bytes as f64;
+    if bytes < KB * KB {
+        format!("{:.0}K", bytes / KB)
+    } else if bytes < KB * KB * KB {
+        format!("{:.1}M", bytes / (KB * KB))
+    } else {
+        format!("{:.1}G", bytes / (KB * KB * KB))
+    }
+}
+
+/// Runs `proj <name> history` lists without `-n`
+const HISTORY_LIMIT: usize = 20;
+
+/// Runs `proj <name> rerun` looks back through for an ad-hoc command
+const HISTORY_SEARCH: usize = 100;
+
+/// Trailing lines of a stack trace `proj <name> crash` shows
+const CRASH_TRACE_PREVIEW: usize = 15;
+
+/// List a project's recent runs, newest first, with how each ended
+async fn get_history(project_name: &str, limit: usize) -> Result<Vec<RunRecord>> {
+    match send_request(IpcRequest::GetHistory {
+        project_name: project_name.to_string(),
+        limit,
+    })
+    .await?
+    {
+        IpcResponse::Runs(runs) => Ok(runs),
+        IpcResponse::Error(error) => Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    }
+}
+
+async fn cmd_history(project_name: &str, limit: usize) -> Result<()> {
+    let runs = get_history(project_name, limit).await?;
+    if runs.is_empty() {
+        println!("No runs of {} yet", project_name);
+        return Ok(());
+    }
+
+    println!(
+        "\x1b[1m{:<19} {:>8} {:>7} {:>5} {:<12} {:<12}  COMMAND\x1b[0m",
+        "STARTED", "DURATION", "PID", "PORT", "RESULT", "SERVICE"
+    );
+    let now = Utc::now();
+    for run in &runs {
+        let result = match (&run.status, run.exit_code) {
+            (None, _) => "\x1b[32mrunning\x1b[0m     ".to_string(),
+            (Some(ProcessStatus::Failed), Some(code)) => {
+                format!("\x1b[31m{:<12}\x1b[0m", format!("failed ({})", code))
+            }
+            (Some(ProcessStatus::Failed), None) => format!("\x1b[31m{:<12}\x1b[0m", "failed"),
+            (Some(_), Some(code)) => format!("{:<12}", format!("exited ({})", code)),
+            (Some(_), None) => format!("{:<12}", "stopped"),
+        };
+        println!(
+            "{:<19} {:>8} {:>7} {:>5} {} {:<12}  {}",
+            run.started_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S"),
+            format_uptime(run.ended_at.unwrap_or(now) - run.started_at),
+            run.pid,
+            run.port.map_or("-".to_string(), |port| port.to_string()),
+            result,
+            run.service.as_deref().unwrap_or("-"),
+            run.command
+        );
+    }
+    Ok(())
+}
+
+/// Timed startups `stats --startup` charts without `-n`
+const STARTUP_RUNS: usize = 20;
+
+/// Width of the longest bar in `stats --startup`
+const STARTUP_BAR_WIDTH: u64 = 40;
+
+/// How many times its usual startup a run has to take to be flagged
+const STARTUP_REGRESSION_FACTOR: u64 = 2;
+
+/// `stats --startup [-n <count>]`: how long recent runs took to be ready,
+/// oldest first, flagging ones much slower than usual
+async fn cmd_stats(project_name: &str, args: Vec<String>) -> Result<()> {
+    let usage = || anyhow::anyhow!("Usage: proj {} stats --startup [-n <count>]", project_name);
+    let mut limit = STARTUP_RUNS;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--startup" => {}
+            "-n" => {
+                let n = args.next().ok_or_else(usage)?;
+                limit = n
+                    .parse()
+                    .with_context(|| format!("Invalid count '{}'", n))?;
+            }
+            _ => return Err(usage()),
+        }
+    }
+
+    // Runs that never got anywhere near ready, like tests, aren't timed
+    let runs = get_history(project_name, u32::MAX as usize).await?;
+    let mut timed: Vec<(&RunRecord, u64)> = runs
+        .iter()
+        .filter_map(|run| Some((run, run.startup_ms()?)))
+        .take(limit)
+        .collect();
+    timed.reverse();
+    if timed.is_empty() {
+        println!(
+            "No timed startups of {} yet; runs are timed once their output shows they are \
+             ready or they listen on a port",
+            project_name
+        );
+        return Ok(());
+    }
+
+    // Services and commands start at their own pace, so each is compared
+    // with itself
+    let label = |run: &RunRecord| run.service.clone().unwrap_or_else(|| run.command.clone());
+    let mut by_label: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+    for (run, ms) in &timed {
+        by_label.entry(label(run)).or_default().push(*ms);
+    }
+    let medians: BTreeMap<String, u64> = by_label
+        .into_iter()
+        .map(|(label, mut times)| {
+            times.sort_unstable();
+            (label, times[times.len() / 2])
+        })
+        .collect();
+
+    println!(
+        "\x1b[1mStartup of {}\x1b[0m, last {} timed runs, oldest first",
+        project_name,
+        timed.len()
+    );
+    let longest = timed.iter().map(|(_, ms)| *ms).max().unwrap_or(1).max(1);
+    for (run, ms) in &timed {
+        let label = label(run);
+        let bar = "█".repeat((ms * STARTUP_BAR_WIDTH / longest).max(1) as usize);
+        let slow = *ms > medians[&label] * STARTUP_REGRESSION_FACTOR;
+        let compiled = run
+            .compiled_ms
+            .map(|compiled| format!("  \x1b[90mcompiled {}\x1b[0m", format_ms(compiled)))
+            .unwrap_or_default();
+        println!(
+            "{}  {:<20}  {}{:<width$}\x1b[0m  {:>7}{}",
+            run.started_at.with_timezone(&Local).format("%m-%d %H:%M"),
+            label.chars().take(20).collect::<String>(),
+            if slow { "\x1b[31m" } else { "" },
+            bar,
+            format_ms(*ms),
+            compiled,
+            width = STARTUP_BAR_WIDTH as usize
+        );
+    }
+
+    let (latest, latest_ms) = timed[timed.len() - 1];
+    let median = medians[&label(latest)];
+    if latest_ms > median * STARTUP_REGRESSION_FACTOR {
+        println!(
+            "\n\x1b[33m!\x1b[0m The latest startup took {}, {:.1}x the usual {}",
+            format_ms(latest_ms),
+            latest_ms as f64 / median.max(1) as f64,
+            format_ms(median)
+        );
+    }
+    Ok(())
+}
+
+/// A duration in milliseconds as `850ms` or `3.2s`
+fn format_ms(ms: u64) -> String {
+    if ms < 1000 {
+        format!("{}ms", ms)
+    } else {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    }
+}
+
+/// Lines `logs` shows without `-n`
+const LOGS_LIMIT: usize = 200;
+
+/// `logs [--grep <text>] [--since <duration>] [-n <count>] [-f]`: stored
+/// output filtered by the daemon, then with `-f` each new matching line
+async fn cmd_logs(project_name: &str, args: Vec<String>) -> Result<()> {
+    let usage = || {
+        anyhow::anyhow!(
+            "Usage: proj {} logs [--grep <text>] [--since <duration>] [-n <count>] [-f]",
+            project_name
+        )
+    };
+    let mut query = LogQuery {
+        limit: Some(LOGS_LIMIT),
+        ..LogQuery::default()
+    };
+    let mut follow = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--grep" => query.grep = Some(args.next().ok_or_else(usage)?.clone()),
+            "--since" => {
+                let since = parse_duration(args.next().ok_or_else(usage)?)?;
+                query.since = Some(Utc::now() - since);
+            }
+            "-n" => {
+                let n = args.next().ok_or_else(usage)?;
+                query.limit = Some(
+                    n.parse()
+                        .with_context(|| format!("Invalid count '{}'", n))?,
+                );
+            }
+            "-f" | "--follow" => follow = true,
+            _ => return Err(usage()),
+        }
+    }
+
+    // Subscribed first, so no line is missed between the stored ones and
+    // the live ones
+    let mut live = if follow {
+        let request = IpcRequest::SubscribeLogs {
+            project_name: project_name.to_string(),
+        };
+        Some(Subscription::open(request).await?)
+    } else {
+        None
+    };
+    let lines = match send_request(IpcRequest::GetLogs {
+        project_name: project_name.to_string(),
+        query: query.clone(),
+    })
+    .await?
+    {
+        IpcResponse::Logs(lines) => lines,
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    };
+    if lines.is_empty() && !follow {
+        println!("No matching output from {}", project_name);
+    }
+    for line in &lines {
+        print_log_line(line);
+    }
+
+    let Some(live) = &mut live else {
+        return Ok(());
+    };
+    let last = lines.last().map(|line| line.timestamp);
+    while let Some(response) = live.next().await? {
+        if let IpcResponse::Log(line) = response {
+            // The subscription starts with buffered lines printed above
+            if last.is_some_and(|last| line.timestamp <= last) || !query.matches(&line) {
+                continue;
+            }
+            print_log_line(&line);
+        }
+    }
+    Ok(())
+}
+
+/// One output line: when, which service or process, and the text, stderr in
+/// red and notes about collapsed repeats dimmed
+fn print_log_line(line: &LogLine) {
+    let source = line
+        .service
+        .clone()
+        .unwrap_or_else(|| line.process_id.simple().to_string()[..8].to_string());
+    let text = if line.collapsed.is_some() {
+        format!("\x1b[2m… {}\x1b[0m", line.line)
+    } else if line.is_stderr {
+        format!("\x1b[31m{}\x1b[0m", line.line)
+    } else {
+        line.line.clone()
+    };
+    println!(
+        "\x1b[90m{} {:<8}\x1b[0m {}",
+        line.timestamp
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M:%S"),
+        source,
+        text
+    );
+}
+
+async fn cmd_prune(project: Option<String>) -> Result<()> {
+    let response = send_request(IpcRequest::Prune {
+        project_name: project,
+    })
+    .await?;
+    match response {
+        IpcResponse::Pruned {
+            processes,
+            log_files,
+            bytes,
+        } => {
+            println!(
+                "\x1b[32m✓\x1b[0m Removed {} exited process record(s) and {} log file(s) ({})",
+                processes,
+                log_files,
+                format_bytes(bytes)
+            );
+            Ok(())
+        }
+        IpcResponse::Error(error) => Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    }
+}
+
+/// Run the project's most recent ad-hoc command again
+async fn cmd_rerun(project_name: &str) -> Result<()> {
+    let runs = get_history(project_name, HISTORY_SEARCH).await?;
+    let run = runs
+        .into_iter()
+        .find(|run| run.service.is_none() && !run.argv.is_empty())
+        .with_context(|| format!("No command has been run in {} yet", project_name))?;
+    println!("Rerunning: {}", run.command);
+    let mut command = vec!["--".to_string()];
+    command.extend(run.argv);
+    cmd_run(project_name.to_string(), command).await
+}
+
+/// A project's crash bundles and their quick actions:
+/// `crash [ls | copy | edit | open] [<bundle>]`, on the latest bundle by default
+fn cmd_crash(project_name: &str, args: Vec<String>) -> Result<()> {
+    let usage = || {
+        anyhow::anyhow!(
+            "Usage: proj {} crash [ls | copy | edit | open] [<bundle>]",
+            project_name
+        )
+    };
+    let (action, name) = match args.as_slice() {
+        [] => (None, None),
+        [action] => (Some(action.as_str()), None),
+        [action, name] if action != "ls" => (Some(action.as_str()), Some(name.as_str())),
+        _ => return Err(usage()),
+    };
+    let bundles = crash::list(project_name)?;
+
+    if action == Some("ls") {
+        if bundles.is_empty() {
+            println!("No crashes of {} recorded", project_name);
+            return Ok(());
+        }
+        println!(
+            "\x1b[1m{:<19} {:>4}  {:<24}  COMMAND\x1b[0m",
+            "CRASHED", "EXIT", "BUNDLE"
+        );
+        for bundle in &bundles {
+            println!(
+                "{:<19} {:>4}  {:<24}  {}",
+                bundle
+                    .info
+                    .crashed_at
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S"),
+                bundle
+                    .info
+                    .exit_code
+                    .map_or("-".to_string(), |code| code.to_string()),
+                bundle.name(),
+                bundle.info.command
+            );
+        }
+        return Ok(());
+    }
+
+    // A bundle is named by a prefix of its name or of its process's short ID
+    let bundle = match name {
+        Some(name) => bundles
+            .iter()
+            .find(|bundle| {
+                let bundle_name = bundle.name();
+                bundle_name.starts_with(name)
+                    || bundle_name
+                        .rsplit('-')
+                        .next()
+                        .is_some_and(|id| id.starts_with(name))
+            })
+            .with_context(|| format!("No crash bundle of {} matches '{}'", project_name, name))?,
+        None => bundles
+            .first()
+            .with_context(|| format!("No crashes of {} recorded", project_name))?,
+    };
+
+    let Some(action) = action else {
+        let exit = bundle
+            .info
+            .exit_code
+            .map_or(String::new(), |code| format!(" (exit {})", code));
+        println!(
+            "\x1b[31m✗\x1b[0m {} crashed {}{}",
+            bundle.info.command,
+            bundle
+                .info
+                .crashed_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S"),
+            exit
+        );
+        println!("  Bundle:  {}", bundle.dir.display());
+        if let Some(location) = &bundle.info.location {
+            println!("  Source:  {}", location);
+        }
+        let trace = bundle.trace();
+        let lines: Vec<&str> = trace.lines().collect();
+        if !lines.is_empty() {
+            println!();
+            for line in &lines[lines.len().saturating_sub(CRASH_TRACE_PREVIEW)..] {
+                println!("  \x1b[90m{}\x1b[0m", line);
+            }
+        }
+        println!();
+        println!(
+            "Quick actions: proj {} crash copy | edit | open",
+            project_name
+        );
+        return Ok(());
+    };
+
+    let action: CrashAction = action.parse()?;
+    bundle.run(action)?;
+    match action {
+        CrashAction::Copy => println!("\x1b[32m✓\x1b[0m Copied the stack trace to the clipboard"),
+        CrashAction::Edit => {}
+        CrashAction::Open => println!("\x1b[32m✓\x1b[0m Opened {}", bundle.dir.display()),
+    }
+    Ok(())
+}
+
+/// Compact uptime: 42s, 5m12s, 3h04m, 2d05h
+fn format_uptime(uptime: chrono::Duration) -> String {
+    let secs = uptime.num_seconds().max(0);
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m{:02}s", s / 60, s % 60),
+        s if s < 86400 => format!("{}h{:02}m", s / 3600, s % 3600 / 60),
+        s => format!("{}d{:02}h", s / 86400, s % 86400 / 3600),
+    }
+}
+
+/// Manage injected environment variables: `env ls`, `env set KEY=VALUE...`,
+/// `env unset KEY...`, `env edit`, `env use PROFILE...`, `env unuse PROFILE...`
+async fn cmd_env(project_name: &str, args: Vec<String>) -> Result<()> {
+    let project_name = project_name.to_string();
+    let project = get_project(&project_name).await?;
+    let mut profiles = project.env_profiles.clone();
+    let request = match args.split_first() {
+        None => IpcRequest::GetEnv { project_name },
+        Some((action, rest)) => match action.as_str() {
+            "ls" => IpcRequest::GetEnv { project_name },
+            "set" if !rest.is_empty() => {
+                let vars = rest
+                    .iter()
+                    .map(|pair| {
+                        let (key, value) = pair
+                            .split_once('=')
+                            .with_context(|| format!("Expected KEY=VALUE, got '{}'", pair))?;
+                        naming::validate_env_name(key)?;
+                        Ok((key.to_string(), value.to_string()))
+                    })
+                    .collect::<Result<_>>()?;
+                IpcRequest::SetEnv { project_name, vars }
+            }
+            "edit" => {
+                let show = match rest {
+                    [] => false,
+                    [flag] if flag == "--show" => true,
+                    _ => anyhow::bail!("Usage: proj {} env edit [--show]", project_name),
+                };
+                return cmd_env_edit(&project_name, show).await;
+            }
+            "unset" if !rest.is_empty() => IpcRequest::UnsetEnv {
+                project_name,
+                keys: rest.to_vec(),
+            },
+            "use" | "unuse" if !rest.is_empty() => {
+                profiles.retain(|profile| !rest.contains(profile));
+                if action == "use" {
+                    profiles.extend(rest.iter().cloned());
+                }
+                IpcRequest::SetEnvProfiles {
+                    project_name,
+                    profiles: profiles.clone(),
+                    expected_revision: Some(project.revision),
+                }
+            }
+            _ => anyhow::bail!(
+                "Usage: proj {} env [ls | set KEY=VALUE... | unset KEY... | edit [--show] | use PROFILE... | unuse PROFILE...]",
+                project_name
+            ),
+        },
+    };
+
+    let vars = match send_request(request).await? {
+        IpcResponse::Env(vars) => vars,
+        IpcResponse::Error(error) => return Err(error.into()),
+        IpcResponse::Conflict { message, .. } => anyhow::bail!("{}", message),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    };
+
+    if !profiles.is_empty() {
+        println!("\x1b[90mProfiles: {}\x1b[0m", profiles.join(", "));
+    }
+    if vars.is_empty() {
+        println!("No environment variables (set with: proj <name> env set KEY=VALUE)");
+        return Ok(());
+    }
+    for var in vars {
+        let source = match var.source {
+            EnvSource::Project => "",
+            EnvSource::DotEnv => "  \x1b[90m(.env)\x1b[0m",
+            EnvSource::Profile => "  \x1b[90m(profile)\x1b[0m",
+        };
+        println!("{}={}{}", var.key, var.value, source);
+    }
+    println!();
+    println!("\x1b[90mApplied to processes started from now on\x1b[0m");
+
+    Ok(())
+}
+
+/// Edit a project's stored variables as a `.env` file in the user's editor,
+/// with secrets masked unless `show`, then apply what changed
+async fn cmd_env_edit(project_name: &str, show: bool) -> Result<()> {
+    let stored: BTreeMap<String, String> = match send_request(IpcRequest::GetEnv {
+        project_name: project_name.to_string(),
+    })
+    .await?
+    {
+        IpcResponse::Env(vars) => vars
+            .into_iter()
+            .filter(|var| var.source == EnvSource::Project)
+            .map(|var| (var.key, var.value))
+            .collect(),
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    };
+    let masked: Vec<&String> = stored
+        .iter()
+        .filter(|(key, value)| !show && dotenv::is_secret(key, value))
+        .map(|(key, _)| key)
+        .collect();
+
+    let mut content = format!(
+        "# Variables stored for {}, one KEY=VALUE per line; delete a line to unset it.\n\
+         # The project's .env and env profiles aren't included.\n",
+        project_name
+    );
+    if !masked.is_empty() {
+        content.push_str(&format!(
+            "# Secrets show as {}; leave them as they are to keep them.\n",
+            dotenv::MASK
+        ));
+    }
+    content.push('\n');
+    for (key, value) in &stored {
+        let value = if masked.contains(&key) {
+            dotenv::MASK
+        } else {
+            value
+        };
+        content.push_str(&dotenv::line(key, value));
+        content.push('\n');
+    }
+
+    // Editors that save by replacing the file keep it private too
+    let dir = PrivateDir::create("proj-env")?;
+    let path = dir.path().join(format!("{}.env", project_name));
+    write_private(&path, &content)?;
+    let edited = loop {
+        let opened = open_in_editor(&path).and_then(|()| {
+            std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))
+        });
+        let parsed = opened.and_then(|text| {
+            let mut vars = dotenv::parse_strict(&text)?;
+            for (key, value) in vars.iter_mut() {
+                if value == dotenv::MASK {
+                    match stored.get(key).filter(|_| masked.contains(&key)) {
+                        Some(secret) => value.clone_from(secret),
+                        None => anyhow::bail!(
+                            "{} is {}, which only stands for a hidden value",
+                            key,
+                            dotenv::MASK
+                        ),
+                    }
+                }
+            }
+            Ok(vars)
+        });
+        match parsed {
+            Ok(vars) => break vars,
+            Err(e) if std::io::stdin().is_terminal() => {
+                eprintln!("\x1b[31m✗\x1b[0m {:#}", e);
+                print!("Edit again? [Y/n] ");
+                std::io::Write::flush(&mut std::io::stdout())?;
+                let mut answer = String::new();
+                std::io::stdin().read_line(&mut answer)?;
+                if matches!(answer.trim(), "n" | "N" | "no") {
+                    anyhow::bail!("Discarded the changes");
+                }
+            }
+            Err(e) => return Err(e.context("Discarded the changes")),
+        }
+    };
+    drop(dir);
+
+    let set: BTreeMap<String, String> = edited
+        .iter()
+        .filter(|(key, value)| stored.get(*key) != Some(*value))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    let unset: Vec<String> = stored
+        .keys()
+        .filter(|key| !edited.contains_key(*key))
+        .cloned()
+        .collect();
+    if set.is_empty() && unset.is_empty() {
+        println!("No changes");
+        return Ok(());
+    }
+
+    for key in set.keys() {
+        let mark = if stored.contains_key(key) {
+            "\x1b[33m~\x1b[0m"
+        } else {
+            "\x1b[32m+\x1b[0m"
+        };
+        println!("  {} {}", mark, key);
+    }
+    for key in &unset {
+        println!("  \x1b[31m-\x1b[0m {}", key);
+    }
+    let mut requests = Vec::new();
+    if !set.is_empty() {
+        requests.push(IpcRequest::SetEnv {
+            project_name: project_name.to_string(),
+            vars: set.clone(),
+        });
+    }
+    if !unset.is_empty() {
+        requests.push(IpcRequest::UnsetEnv {
+            project_name: project_name.to_string(),
+            keys: unset.clone(),
+        });
+    }
+    for request in requests {
+        match send_request(request).await? {
+            IpcResponse::Env(_) => {}
+            IpcResponse::Error(error) => return Err(error.into()),
+            _ => anyhow::bail!(tr!("unexpected-response")),
+        }
+    }
+    println!(
+        "\x1b[32m✓\x1b[0m Updated {} variable(s); applied to processes started from now on",
+        set.len() + unset.len()
+    );
+    Ok(())
+}
+
+/// A fresh directory under the temp dir that only the current user can
+/// enter, removed with its contents when dropped
+struct PrivateDir(PathBuf);
+
+impl PrivateDir {
+    fn create(prefix: &str) -> Result<Self> {
+        let name = format!("{}-{}", prefix, uuid::Uuid::new_v4().simple());
+        let path = std::env::temp_dir().join(name);
+        let mut builder = std::fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        builder
+            .create(&path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        Ok(Self(path))
+    }
+
+    fn path(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+impl Drop for PrivateDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Write a new file only the current user can read, as one that may hold
+/// secrets; an existing file or symlink at `path` is an error
+fn write_private(path: &std::path::Path, content: &str) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    std::io::Write::write_all(&mut file, content.as_bytes())?;
+    Ok(())
+}
+
+/// Written by `proj profile-env edit` when there is no env profiles file yet
+const ENV_PROFILES_EXAMPLE: &str = r#"{
+  "cuda": {
+    "CUDA_VISIBLE_DEVICES": "0",
+    "LD_LIBRARY_PATH": "/usr/local/cuda/lib64:${LD_LIBRARY_PATH}"
+  },
+  "metal": {
+    "PYTORCH_ENABLE_MPS_FALLBACK": "1"
+  }
+}
+"#;
+
+/// List env profiles with their variables and the projects using each
+async fn cmd_profile_env_ls() -> Result<()> {
+    let profiles = load_env_profiles()?;
+    if profiles.is_empty() {
+        println!("No env profiles (create some with: proj profile-env edit)");
+        return Ok(());
+    }
+    let projects = list_projects(ProjectFilter::default()).await?;
+
+    for (name, vars) in &profiles {
+        let users: Vec<&str> = projects
+            .iter()
+            .filter(|p| p.env_profiles.contains(name))
+            .map(|p| p.name.as_str())
+            .collect();
+        let users = if users.is_empty() {
+            "unused".to_string()
+        } else {
+            format!("used by {}", users.join(", "))
+        };
+        println!("\x1b[1m{}\x1b[0m  \x1b[90m{}\x1b[0m", name, users);
+        for (key, value) in vars {
+            println!("  {}={}", key, value);
+        }
+    }
+    println!();
+    println!("\x1b[90mUse one in a project with: proj <name> env use <profile>\x1b[0m");
+    Ok(())
+}
+
+/// Open the env profiles file in the user's editor, then check it still parses
+fn cmd_profile_env_edit() -> Result<()> {
+    let path = env_profiles_path()?;
+    if !path.exists() {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, ENV_PROFILES_EXAMPLE)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+
+    open_in_editor(&path)?;
+
+    let profiles = load_env_profiles()
+        .map_err(|e| anyhow::anyhow!("{:#}; fix it with: proj profile-env edit", e))?;
+    println!(
+        "\x1b[32m✓\x1b[0m {} env profile(s); changes apply to processes started from now on",
+        profiles.len()
+    );
+    Ok(())
+}
+
+/// Open a file in `$VISUAL` or `$EDITOR` and wait for it to close
+fn open_in_editor(path: &std::path::Path) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| if cfg!(windows) { "notepad" } else { "vi" }.to_string());
+    // Editors are often given with flags, e.g. `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words.next().context("$EDITOR is empty")?;
+    let status = std::process::Command::new(program)
+        .args(words)
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !status.success() {
+        anyhow::bail!("{} exited with {}", program, status);
+    }
+    Ok(())
+}
+
+/// Background jobs run one after another: `queue [ls | add <cmd> | clear]`
+async fn cmd_queue(project_name: &str, args: Vec<String>) -> Result<()> {
+    let project_name = project_name.to_string();
+    let usage = || {
+        anyhow::anyhow!(
+            "Usage: proj {} queue [ls | add <cmd> | clear]",
+            project_name
+        )
+    };
+    let request = match args.split_first() {
+        None => IpcRequest::ListQueue {
+            project_name: project_name.clone(),
+        },
+        Some((action, rest)) => match action.as_str() {
+            "ls" if rest.is_empty() => IpcRequest::ListQueue {
+                project_name: project_name.clone(),
+            },
+            "add" => {
+                let command = match rest.split_first() {
+                    Some((dashes, command)) if dashes == "--" => command.to_vec(),
+                    _ => rest.to_vec(),
+                };
+                if command.is_empty() {
+                    return Err(usage());
+                }
+                IpcRequest::QueueAdd {
+                    project_name: project_name.clone(),
+                    command,
+                }
+            }
+            "clear" if rest.is_empty() => IpcRequest::ClearQueue {
+                project_name: project_name.clone(),
+            },
+            _ => return Err(usage()),
+        },
+    };
+    let adding = matches!(request, IpcRequest::QueueAdd { .. });
+
+    let jobs = match send_request(request).await? {
+        IpcResponse::Queue(jobs) => jobs,
+        IpcResponse::Success { message } => {
+            if let Some(msg) = message {
+                println!("{}", msg);
+            }
+            return Ok(());
+        }
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    };
+
+    let pending: Vec<_> = jobs
+        .iter()
+        .filter(|j| j.status == QueueJobStatus::Queued)
+        .collect();
+    if adding {
+        // The new job is always listed last
+        let job = jobs.last().context("Daemon returned an empty queue")?;
+        if job.status == QueueJobStatus::Running {
+            println!("\x1b[32m✓\x1b[0m Started {}", job.command.join(" "));
+        } else {
+            println!(
+                "\x1b[32m✓\x1b[0m Queued {} (position {})",
+                job.command.join(" "),
+                pending.len()
+            );
+        }
+        return Ok(());
+    }
+
+    if jobs.is_empty() {
+        println!(
+            "No jobs (add one with: proj {} queue add <cmd>)",
+            project_name
+        );
+        return Ok(());
+    }
+    let now = Utc::now();
+    println!(
+        "\x1b[1m{:<4} {:<9} {:<10} {:>4} {:>7}  COMMAND\x1b[0m",
+        "POS", "ID", "STATUS", "EXIT", "TIME"
+    );
+    let mut position = 0;
+    for job in &jobs {
+        let pos = if job.status == QueueJobStatus::Queued {
+            position += 1;
+            position.to_string()
+        } else {
+            "-".to_string()
+        };
+        // Run time once started, otherwise time spent waiting
+        let time = match (job.started_at, job.finished_at) {
+            (Some(start), Some(end)) => format_uptime(end - start),
+            (Some(start), None) => format_uptime(now - start),
+            (None, _) => format_uptime(now - job.queued_at),
+        };
+        let (label, color) = match job.status {
+            QueueJobStatus::Queued => ("queued", "0"),
+            QueueJobStatus::Running => ("running", "33"),
+            QueueJobStatus::Succeeded => ("succeeded", "32"),
+            QueueJobStatus::Failed => ("failed", "31"),
+            QueueJobStatus::Cancelled => ("cancelled", "90"),
+        };
+        println!(
+            "{:<4} {:<9} \x1b[{}m{:<10}\x1b[0m {:>4} {:>7}  {}",
+            pos,
+            &job.id.to_string()[..8],
+            color,
+            label,
+            job.exit_code.map_or("-".to_string(), |c| c.to_string()),
+            time,
+            job.command.join(" ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Manage a project's services: `service [ls | add | rm | start | stop]`
+async fn cmd_service(project_name: &str, args: Vec<String>) -> Result<()> {
+    let usage = || {
+        anyhow::anyhow!(
+            "Usage: proj {} service [ls | add <name> [--port auto|detect|N] [--env K=V] \
+             [--health /path|tcp|smoke] [--named-port NAME=PORT] [--restart ...] [--watch] -- <cmd> | rm <name> | \
+             start <name> | stop <name>]",
+            project_name
+        )
+    };
+    let project_name = project_name.to_string();
+
+    let (action, rest) = match args.split_first() {
+        None => ("ls", &[][..]),
+        Some((action, rest)) => (action.as_str(), rest),
+    };
+    let request = match (action, rest) {
+        ("ls", []) => return print_services(&project_name).await,
+        ("add", [name, options @ ..]) => {
+            let (options, command) = parse_run_args(options.to_vec())?;
+            if command.is_empty() {
+                return Err(usage());
+            }
+            if options.exclusive
+                || options.no_wait
+                || options.heavy
+                || options.output.is_some()
+                || options.sandbox.is_some()
+                || options.read_only
+                || options.primary
+                || options.then.is_some()
+                || options.otherwise.is_some()
+                || !options.limits.is_empty()
+                || options.profile.is_some()
+                || options.on_conflict.is_some()
+            {
+                anyhow::bail!(
+                    "--exclusive, --no-wait, --heavy, --output, --sandbox, --read-only, --primary, --then, --else, --mem, --cpus, --files, --profile and --on-conflict are for one-off runs: proj {} run <options> <cmd>",
+                    project_name
+                );
+            }
+            IpcRequest::SetService {
+                project_name,
+                name: name.clone(),
+                service: Service {
+                    command,
+                    env: options.env,
+                    port: options.port.unwrap_or_default(),
+                    restart: options.restart,
+                    max_restarts: options.max_restarts.unwrap_or(DEFAULT_MAX_RESTARTS),
+                    watch: options.watch,
+                    health: options.health,
+                    ports: options.named_ports,
+                },
+                // The whole service is given, so there's no stale copy to guard
+                expected_revision: None,
+            }
+        }
+        ("rm", [name]) => IpcRequest::RemoveService {
+            project_name,
+            name: name.clone(),
+            expected_revision: None,
+        },
+        ("start", [name]) => IpcRequest::StartService {
+            project_name,
+            name: name.clone(),
+            debug: false,
+        },
+        ("stop", [name]) => IpcRequest::StopService {
+            project_name,
+            name: name.clone(),
+        },
+        _ => return Err(usage()),
+    };
+
+    match send_request(request).await? {
+        IpcResponse::Project(project) => {
+            println!(
+                "\x1b[32m✓\x1b[0m {} has {} service(s)",
+                project.name,
+                project.services.len()
+            );
+        }
+        IpcResponse::ProcessStarted { process, .. } => {
+            println!(
+                "\x1b[32m✓\x1b[0m Started {} (pid {})",
+                process.service.as_deref().unwrap_or_default(),
+                process.pid
+            );
+            if let Some(port) = process.port {
+                println!("  Port: {}", port);
+            }
+        }
+        IpcResponse::Success { message } => {
+            println!("\x1b[33m■\x1b[0m {}", message.unwrap_or_default());
+        }
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    }
+    Ok(())
+}
+
+/// Restart a service under its stack's debugger and give VS Code a
+/// configuration to attach with: `debug [service] [--write]`
+async fn cmd_debug(project_name: &str, args: &[String]) -> Result<()> {
+    let mut write = false;
+    let mut service = None;
+    for arg in args {
+        match arg.as_str() {
+            "--write" => write = true,
+            flag if flag.starts_with('-') => anyhow::bail!(
+                "Unknown option '{}'. Usage: proj {} debug [service] [--write]",
+                flag,
+                project_name
+            ),
+            name => service = Some(name.to_string()),
+        }
+    }
+
+    let project = get_project(project_name).await?;
+    let names: Vec<&str> = project.services.keys().map(String::as_str).collect();
+    let service = match service {
+        Some(name) => name,
+        None => match names.as_slice() {
+            [name] => name.to_string(),
+            [] => anyhow::bail!(
+                "{} has no services to debug (add one with: proj {} service add <name> -- <cmd>)",
+                project.name,
+                project.name
+            ),
+            names => anyhow::bail!(
+                "{} has several services; pick one: proj {} debug <{}>",
+                project.name,
+                project.name,
+                names.join("|")
+            ),
+        },
+    };
+    // Checked here too, to fail before a running instance is stopped
+    let stack = match project.services.get(&service) {
+        Some(definition) => debug::Stack::of(&definition.command)?,
+        None => anyhow::bail!("{} has no service '{}'", project.name, service),
+    };
+
+    let process = match send_request(IpcRequest::StartService {
+        project_name: project.name.clone(),
+        name: service.clone(),
+        debug: true,
+    })
+    .await?
+    {
+        IpcResponse::ProcessStarted { process, .. } => process,
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    };
+    let port = process
+        .named_ports
+        .get(debug::DEBUG_PORT_NAME)
+        .copied()
+        .context("The daemon didn't record a debug port")?;
+    println!(
+        "\x1b[32m✓\x1b[0m Restarted {} under the debugger (pid {}), listening on 127.0.0.1:{}",
+        service, process.pid, port
+    );
+
+    let config_name = format!("proj: {} {}", project.name, service);
+    let config = stack.vscode_config(&config_name, port);
+    if write {
+        let path = add_launch_config(&project.root_dir, config)?;
+        println!("  Attach with \"{}\" in {}", config_name, path.display());
+    } else {
+        println!("  VS Code attach configuration, for .vscode/launch.json (or use --write):");
+        println!("{}", serde_json::to_string_pretty(&config)?);
+    }
+    Ok(())
+}
+
+/// Add a configuration to a project's `.vscode/launch.json`, replacing one of
+/// the same name
+fn add_launch_config(root_dir: &std::path::Path, config: serde_json::Value) -> Result<PathBuf> {
+    let path = root_dir.join(".vscode").join("launch.json");
+    let mut launch = match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str::<serde_json::Value>(&content).with_context(|| {
+            format!(
+                "Can't edit {} (VS Code allows comments, proj doesn't); add the configuration by hand",
+                path.display()
+            )
+        })?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            serde_json::json!({ "version": "0.2.0", "configurations": [] })
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+
+    let configurations = launch
+        .as_object_mut()
+        .context("launch.json is not an object")?
+        .entry("configurations")
+        .or_insert_with(|| serde_json::json!([]))
+        .as_array_mut()
+        .context("launch.json's configurations is not a list")?;
+    configurations.retain(|existing| existing["name"] != config["name"]);
+    configurations.push(config);
+
+    std::fs::create_dir_all(path.parent().expect("has a parent"))?;
+    std::fs::write(&path, serde_json::to_string_pretty(&launch)? + "\n")
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Table of a project's services and their current processes
+async fn print_services(project_name: &str) -> Result<()> {
+    let services = match send_request(IpcRequest::ListServices {
+        project_name: project_name.to_string(),
+    })
+    .await?
+    {
+        IpcResponse::Services(services) => services,
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    };
+
+    if services.is_empty() {
+        println!(
+            "No services (add one with: proj {} service add <name> -- <cmd>)",
+            project_name
+        );
+        return Ok(());
+    }
+
+    println!(
+        "\x1b[1m{:<16} {:<9} {:>7} {:>6}  COMMAND\x1b[0m",
+        "SERVICE", "STATUS", "PID", "PORT"
+    );
+    for info in services {
+        let (status, pid, port) = match &info.process {
+            Some(p) if p.status.is_alive() => (
+                if p.status == proj_common::ProcessStatus::Unhealthy {
+                    "\x1b[33munhealthy\x1b[0m"
+                } else {
+                    "\x1b[32mrunning\x1b[0m  "
+                },
+                p.pid.to_string(),
+                p.port.map(|port| port.to_string()).unwrap_or_default(),
+            ),
+            Some(p) if p.status == proj_common::ProcessStatus::Failed => {
+                ("\x1b[31mfailed\x1b[0m   ", String::new(), String::new())
+            }
+            _ => ("\x1b[90mstopped\x1b[0m  ", String::new(), String::new()),
+        };
+        let port = match (port.is_empty(), info.service.port) {
+            (false, _) => port,
+            (true, PortPolicy::Fixed(port)) => port.to_string(),
+            (true, _) => "-".to_string(),
+        };
+        println!(
+            "{:<16} {} {:>7} {:>6}  {}",
+            info.name,
+            status,
+            pid,
+            port,
+            info.service.command.join(" ")
+        );
+    }
+    Ok(())
+}
+
+/// Manage a process started outside proj: `adopt <pid>` or `adopt --port <port>`
+async fn cmd_adopt(project_name: &str, args: Vec<String>) -> Result<()> {
+    let (pid, port) = match args.as_slice() {
+        [flag, port] if flag == "--port" => {
+            (None, Some(port.parse().context("--port expects a number")?))
+        }
+        [pid] => (Some(pid.parse().context("PID must be a number")?), None),
+        _ => anyhow::bail!("Usage: proj {} adopt <pid> | --port <port>", project_name),
+    };
+
+    let response = send_request(IpcRequest::AdoptProcess {
+        project_name: project_name.to_string(),
+        pid,
+        port,
+    })
+    .await?;
+
+    match response {
+        IpcResponse::ProcessStarted { process, .. } => {
+            println!(
+                "\x1b[32m✓\x1b[0m Adopted \x1b[1m{}\x1b[0m (PID: {}) into {}",
+                process.command, process.pid, project_name
+            );
+            if !get_project(project_name).await?.is_direct() {
+                println!(
+                    "  Routing to \x1b[4m{}\x1b[0m once its port is detected",
+                    project_url(project_name)
+                );
+            }
+        }
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    }
+
+    Ok(())
+}
+
+/// Delete a project after confirmation
+async fn cmd_rm(name: String, keep_data: bool, yes: bool) -> Result<()> {
+    let project = get_project(&name).await?;
+
+    if !yes {
+        let name = project.name.as_str();
+        let question = if keep_data {
+            tr!("rm-confirm", name = name)
+        } else {
+            let path = project_dir(name)?.display().to_string();
+            tr!("rm-confirm-data", name = name, path = path)
+        };
+        print!("{} ", question);
+        std::io::Write::flush(&mut std::io::stdout())?;
+
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            println!("{}", tr!("aborted"));
+            return Ok(());
+        }
+    }
+    if !keep_data {
+        for (browser, profile) in browser::profiles(&project.name)? {
+            if !close_profile_browser(&project.name, &browser, &profile, yes).await? {
+                println!("{}", tr!("aborted"));
+                return Ok(());
+            }
+        }
+    }
+
+    let response = send_request(IpcRequest::DeleteProject {
+        name: project.name.clone(),
+        keep_data,
+    })
+    .await?;
+
+    match response {
+        IpcResponse::Success { .. } => {
+            let name = format!("\x1b[1m{}\x1b[0m", project.name);
+            println!("\x1b[32m✓\x1b[0m {}", tr!("project-deleted", name = name));
+            if keep_data {
+                let path = project_dir(&project.name)?.display().to_string();
+                println!("  {}", tr!("data-kept", path = path));
+            }
+            println!("  {}", tr!("restore-hint"));
+        }
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    }
+
+    Ok(())
+}
+
+/// Put back what a trash entry holds, the newest one by default, registering
+/// the project again if it was removed
+async fn cmd_undo(id: Option<String>) -> Result<()> {
+    let entries = trash::list()?;
+    let entry = match &id {
+        Some(id) => entries
+            .into_iter()
+            .find(|entry| entry.id == *id)
+            .with_context(|| format!("No trash entry '{}' (see: proj trash ls)", id))?,
+        None => entries
+            .into_iter()
+            .next()
+            .context("Nothing to undo: the trash is empty")?,
+    };
+    if let Some(project) = &entry.project {
+        if get_project(&project.name).await.is_ok() {
+            anyhow::bail!(
+                "Can't restore {}: there's a project named {} again",
+                entry.summary(),
+                project.name
+            );
+        }
+    }
+
+    trash::restore_files(&entry)?;
+    if let Some(project) = &entry.project {
+        match send_request(IpcRequest::ImportProject {
+            project: Box::new(project.clone()),
+        })
+        .await?
+        {
+            IpcResponse::Project(_) => {}
+            IpcResponse::Error(error) => anyhow::bail!(
+                "Restored the files of {}, but couldn't register it again: {}",
+                entry.summary(),
+                error_message(error)
+            ),
+            _ => anyhow::bail!(tr!("unexpected-response")),
+        }
+    }
+    trash::remove(&entry)?;
+
+    println!("\x1b[32m✓\x1b[0m Restored {}", entry.summary());
+    for item in &entry.items {
+        println!("  {}", item.original.display());
+    }
+    Ok(())
+}
+
+/// List trash entries, newest first
+fn cmd_trash_ls() -> Result<()> {
+    let entries = trash::list()?;
+    if entries.is_empty() {
+        println!("The trash is empty");
+        return Ok(());
+    }
+    println!(
+        "\x1b[1m{:<40} {:<16} {:<28} {:>9}\x1b[0m",
+        "ID", "DELETED", "WHAT", "SIZE"
+    );
+    for entry in &entries {
+        println!(
+            "{:<40} {:<16} {:<28} {:>9}",
+            entry.id,
+            entry
+                .deleted_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M"),
+            entry.summary(),
+            format_size(entry.size())
+        );
+    }
+    println!();
+    println!(
+        "\x1b[90mproj undo [<id>] restores one; entries are deleted after {} days\x1b[0m",
+        trash::RETENTION_DAYS
+    );
+    Ok(())
+}
+
+/// Delete every trash entry for good
+fn cmd_trash_empty(yes: bool) -> Result<()> {
+    let count = trash::list()?.len();
+    if count == 0 {
+        println!("The trash is empty");
+        return Ok(());
+    }
+    if !yes {
+        print!("Delete {} trash entries for good? [y/N] ", count);
+        std::io::Write::flush(&mut std::io::stdout())?;
+
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            println!("{}", tr!("aborted"));
+            return Ok(());
+        }
+    }
+    let (entries, bytes) = trash::empty(None)?;
+    println!(
+        "\x1b[32m✓\x1b[0m Deleted {} trash entries ({})",
+        entries,
+        format_size(bytes)
+    );
+    Ok(())
+}
+
+/// Prints the remote home directory, then one `{"project": ..., "env": ...}`
+/// object per project, from the remote store (read with `sqlite3`) and from
+/// project.json files an older proj left there
+const MIGRATE_SCRIPT: &str = r#"echo "$HOME"
+env_json() { if [ -f "$HOME/.proj/projects/$1/env.json" ]; then cat "$HOME/.proj/projects/$1/env.json"; else printf '{}'; fi; }
+db="$HOME/.proj/proj.db"
+if [ -f "$db" ]; then
+  command -v sqlite3 >/dev/null || { echo "sqlite3 is needed to read $db" >&2; exit 1; }
+  for name in $(sqlite3 "$db" "SELECT name FROM projects"); do
+    printf '{"project":'; sqlite3 "$db" "SELECT data FROM projects WHERE name = '$name'"
+    printf ',"env":'; env_json "$name"
+    printf '}\n'
+  done
+fi
+for d in "$HOME"/.proj/projects/*/; do
+  [ -f "$d/project.json" ] || continue
+  printf '{"project":'; cat "$d/project.json"
+  printf ',"env":'; env_json "$(basename "$d")"
+  printf '}\n'
+done"#;
+
+/// A project as stored in another machine's data directory
+#[derive(serde::Deserialize)]
+struct RemoteProject {
+    project: Project,
+    env: BTreeMap<String, String>,
+    /// The project's proj.toml, from a bundle
+    #[serde(default)]
+    proj_toml: Option<String>,
+}
+
+/// Import projects from another machine's `~/.proj` over SSH, remapping paths
+async fn cmd_migrate_from(
+    source: &str,
+    names: Vec<String>,
+    maps: Vec<String>,
+    yes: bool,
+) -> Result<()> {
+    let (destination, port) = parse_ssh_source(source)?;
+    let mut ssh = tokio::process::Command::new("ssh");
+    if let Some(port) = port {
+        ssh.args(["-p", port]);
+    }
+    let output = ssh
+        .arg(destination)
+        .arg(format!("sh -c {}", shell_quote(MIGRATE_SCRIPT)))
+        .stderr(std::process::Stdio::inherit())
+        .output()
+        .await
+        .context("Failed to run ssh")?;
+    if !output.status.success() {
+        anyhow::bail!("ssh {} failed ({})", destination, output.status);
+    }
+
+    let stdout = String::from_utf8(output.stdout).context("Remote output is not UTF-8")?;
+    let (remote_home, rest) = stdout
+        .split_once('\n')
+        .context("Unexpected output from remote machine")?;
+    let mut remote: Vec<RemoteProject> = serde_json::Deserializer::from_str(rest)
+        .into_iter()
+        .collect::<Result<_, _>>()
+        .context("Failed to parse remote projects")?;
+    remote.sort_by(|a, b| a.project.name.cmp(&b.project.name));
+
+    if !names.is_empty() {
+        if let Some(missing) = names
+            .iter()
+            .find(|n| !remote.iter().any(|r| &r.project.name == *n))
+        {
+            anyhow::bail!("No project '{}' on {}", missing, destination);
+        }
+        remote.retain(|r| names.contains(&r.project.name));
+    }
+    if remote.is_empty() {
+        println!("No projects on {}", destination);
+        return Ok(());
+    }
+
+    let remap = path_remapper(&maps, std::path::Path::new(remote_home))?;
+    let source = format!("on {}", destination);
+    import_projects(&source, remote, remap, yes, false).await
+}
+
+/// Map paths from another machine to this one: by the first `--map FROM=TO`
+/// that applies, else from the other machine's home to this one's
+fn path_remapper(
+    maps: &[String],
+    remote_home: &std::path::Path,
+) -> Result<impl Fn(&std::path::Path) -> PathBuf> {
+    let mut path_maps = maps
+        .iter()
+        .map(|m| {
+            let (from, to) = m
+                .split_once('=')
+                .with_context(|| format!("--map expects FROM=TO, got '{}'", m))?;
+            Ok((PathBuf::from(from), PathBuf::from(to)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if let Some(home) = dirs::home_dir() {
+        path_maps.push((remote_home.to_path_buf(), home));
+    }
+    Ok(move |path: &std::path::Path| {
+        path_maps
+            .iter()
+            .find_map(|(from, to)| path.strip_prefix(from).ok().map(|rest| to.join(rest)))
+            .unwrap_or_else(|| path.to_path_buf())
+    })
+}
+
+/// Recreate projects from another machine (`source` says where, e.g. "on
+/// old-laptop"), with their paths remapped. With
+/// `ask_roots`, asks where each project whose remapped root doesn't exist is.
+async fn import_projects(
+    source: &str,
+    remote: Vec<RemoteProject>,
+    remap: impl Fn(&std::path::Path) -> PathBuf,
+    yes: bool,
+    ask_roots: bool,
+) -> Result<()> {
+    let local = list_projects(ProjectFilter::default()).await?;
+
+    println!("Projects {}:", source);
+    let mut imports = Vec::new();
+    for mut remote_project in remote {
+        let project = &mut remote_project.project;
+        if local.iter().any(|p| p.name == project.name) {
+            println!(
+                "  \x1b[90m{:<20} already exists here, skipped\x1b[0m",
+                project.name
+            );
+            continue;
+        }
+        let mut root_dir = remap(&project.root_dir);
+        if ask_roots && !root_dir.exists() && std::io::stdin().is_terminal() {
+            print!(
+                "  {} was in {}; where is it here? [{}] ",
+                project.name,
+                project.root_dir.display(),
+                root_dir.display()
+            );
+            std::io::Write::flush(&mut std::io::stdout())?;
+            let mut answer = String::new();
+            std::io::stdin().read_line(&mut answer)?;
+            if !answer.trim().is_empty() {
+                root_dir = PathBuf::from(answer.trim());
+            }
+        }
+        let note = if root_dir.exists() {
+            ""
+        } else {
+            " \x1b[33m(not found here yet)\x1b[0m"
+        };
+        println!(
+            "  {:<20} {} → {}{}",
+            project.name,
+            project.root_dir.display(),
+            root_dir.display(),
+            note
+        );
+        project.proxy.openapi_spec = project.proxy.openapi_spec.as_deref().map(&remap);
+        project.root_dir = root_dir;
+        imports.push(remote_project);
+    }
+    if imports.is_empty() {
+        return Ok(());
+    }
+
+    if !yes {
+        print!("Import {} project(s)? [y/N] ", imports.len());
+        std::io::Write::flush(&mut std::io::stdout())?;
+
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            println!("{}", tr!("aborted"));
+            return Ok(());
+        }
+    }
+
+    for RemoteProject {
+        project,
+        env: vars,
+        proj_toml,
+    } in imports
+    {
+        let name = project.name.clone();
+        let root_dir = project.root_dir.clone();
+        match send_request(IpcRequest::ImportProject {
+            project: Box::new(project),
+        })
+        .await?
+        {
+            IpcResponse::Project(_) => {}
+            IpcResponse::Error(error) => {
+                println!("\x1b[31m✗\x1b[0m {}: {}", name, error_message(error));
+                continue;
+            }
+            _ => anyhow::bail!(tr!("unexpected-response")),
+        }
+        // Only restored where the checkout lacks one; the file is the checkout's
+        let proj_toml_path = root_dir.join(schema::PROJECT_FILE);
+        if let Some(content) = proj_toml.filter(|_| root_dir.is_dir() && !proj_toml_path.exists()) {
+            if let Err(e) = std::fs::write(&proj_toml_path, content) {
+                println!(
+                    "\x1b[33m⚠\x1b[0m {}: couldn't write {}: {}",
+                    name,
+                    proj_toml_path.display(),
+                    e
+                );
+            }
+        }
+        if !vars.is_empty() {
+            let request = IpcRequest::SetEnv {
+                project_name: name.clone(),
+                vars,
+            };
+            if let IpcResponse::Error(error) = send_request(request).await? {
+                println!(
+                    "\x1b[33m⚠\x1b[0m {}: imported, but not its environment: {}",
+                    name,
+                    error_message(error)
+                );
+                continue;
+            }
+        }
+        println!("\x1b[32m✓\x1b[0m Imported \x1b[1m{}\x1b[0m", name);
+    }
+
+    Ok(())
+}
+
+/// Register a copy of a project under a new name: its settings, stored env
+/// and aliases (renamed where they contain the source's name), with a new id
+/// and so a fresh browser profile. With `copy` or `clone` its working
+/// directory is copied or cloned to the new root first.
+async fn cmd_duplicate(
+    source_name: String,
+    name: String,
+    dir: Option<PathBuf>,
+    copy: bool,
+    clone: bool,
+) -> Result<()> {
+    naming::project_host(&name, load_config().tld())?;
+    let source = get_project(&source_name).await?;
+    if get_project(&name).await.is_ok() {
+        return Err(IpcError::ProjectExists { name }.into());
+    }
+
+    // A copied or cloned directory is removed again if the project can't be
+    // created
+    let mut created_dir = None;
+    let mut copied_files = None;
+    let root_dir = if copy || clone {
+        let dir = dir.unwrap_or_else(|| {
+            let parent = source.root_dir.parent().unwrap_or(&source.root_dir);
+            parent.join(&name)
+        });
+        if dir.exists() {
+            anyhow::bail!("{} already exists", dir.display());
+        }
+        if copy {
+            let files = copy_tree(&source.root_dir, &dir).inspect_err(|_| {
+                let _ = std::fs::remove_dir_all(&dir);
+            })?;
+            copied_files = Some(files);
+        } else {
+            let status = std::process::Command::new("git")
+                .args(["clone", "--quiet"])
+                .arg(&source.root_dir)
+                .arg(&dir)
+                .status()
+                .context("Failed to run git")?;
+            if !status.success() {
+                anyhow::bail!("git clone of {} failed", source.root_dir.display());
+            }
+        }
+        created_dir = Some(dir.clone());
+        dir.canonicalize().context("Invalid directory path")?
+    } else {
+        match dir {
+            Some(dir) => dir.canonicalize().with_context(|| {
+                format!(
+                    "{} doesn't exist; add --copy or --clone to create it from {}",
+                    dir.display(),
+                    source.name
+                )
+            })?,
+            None => source.root_dir.clone(),
+        }
+    };
+
+    let mut aliases = Vec::new();
+    let mut dropped = Vec::new();
+    for alias in &source.aliases {
+        let renamed: Vec<&str> = alias
+            .split('.')
+            .map(|label| if label == source.name { &name } else { label })
+            .collect();
+        let renamed = renamed.join(".");
+        if renamed == *alias {
+            dropped.push(alias.as_str());
+        } else {
+            aliases.push(renamed);
+        }
+    }
+
+    let fresh = Project::new(name.clone(), root_dir.clone());
+    let mut project = Project {
+        name: fresh.name,
+        id: fresh.id,
+        created_at: fresh.created_at,
+        root_dir: fresh.root_dir,
+        aliases,
+        port: None,
+        last_used: None,
+        revision: 0,
+        ..source.clone()
+    };
+    // Files in the source's tree are looked for in the copy's
+    project.proxy.openapi_spec = source.proxy.openapi_spec.as_deref().map(|path| {
+        match path.strip_prefix(&source.root_dir) {
+            Ok(relative) => root_dir.join(relative),
+            Err(_) => path.to_path_buf(),
+        }
+    });
+
+    let response = send_request(IpcRequest::ImportProject {
+        project: Box::new(project),
+    })
+    .await;
+    let project = match response {
+        Ok(IpcResponse::Project(project)) => project,
+        other => {
+            if let Some(dir) = created_dir {
+                let _ = std::fs::remove_dir_all(dir);
+            }
+            match other? {
+                IpcResponse::Error(error) => return Err(error.into()),
+                _ => anyhow::bail!(tr!("unexpected-response")),
+            }
+        }
+    };
+
+    let vars: BTreeMap<String, String> = match send_request(IpcRequest::GetEnv {
+        project_name: source.name.clone(),
+    })
+    .await?
+    {
+        IpcResponse::Env(vars) => vars
+            .into_iter()
+            .filter(|var| var.source == EnvSource::Project)
+            .map(|var| (var.key, var.value))
+            .collect(),
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    };
+    let var_count = vars.len();
+    if !vars.is_empty() {
+        let request = IpcRequest::SetEnv {
+            project_name: project.name.clone(),
+            vars,
+        };
+        if let IpcResponse::Error(error) = send_request(request).await? {
+            anyhow::bail!(
+                "Created {}, but not its environment: {}",
+                project.name,
+                error_message(error)
+            );
+        }
+    }
+
+    // A copy's proj.toml still names the source
+    if root_dir != source.root_dir {
+        let marked = if created_dir.is_some() {
+            ProjectFile::rename(&root_dir, &project.name)
+        } else {
+            ProjectFile::write_name(&root_dir, &project.name)
+        };
+        if let Err(e) = marked {
+            println!("\x1b[33m⚠\x1b[0m {:#}", e);
+        }
+    }
+
+    println!(
+        "\x1b[32m✓\x1b[0m Duplicated {} as \x1b[1m{}\x1b[0m",
+        source.name, project.name
+    );
+    println!("  Root: {}", project.root_dir.display());
+    match copied_files {
+        Some(files) => println!(
+            "  Copied {} files from {}",
+            files,
+            source.root_dir.display()
+        ),
+        None if clone => println!("  Cloned from {}", source.root_dir.display()),
+        None if root_dir == source.root_dir => println!(
+            "  \x1b[90mShared with {}; --copy or --clone gives it its own\x1b[0m",
+            source.name
+        ),
+        None => {}
+    }
+    if var_count > 0 {
+        println!("  Env: {} variable(s)", var_count);
+    }
+    if !project.aliases.is_empty() {
+        println!("  Aliases: {}", project.aliases.join(", "));
+    }
+    if !dropped.is_empty() {
+        println!(
+            "  \x1b[33mNot copied\x1b[0m: {} (an alias belongs to one project)",
+            dropped.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Copy a directory tree, keeping symlinks as links; returns how many files
+/// were copied
+fn copy_tree(from: &std::path::Path, to: &std::path::Path) -> Result<usize> {
+    std::fs::create_dir_all(to).with_context(|| format!("Failed to create {}", to.display()))?;
+    let mut files = 0;
+    for entry in
+        std::fs::read_dir(from).with_context(|| format!("Failed to read {}", from.display()))?
+    {
+        let entry = entry?;
+        let (source, target) = (entry.path(), to.join(entry.file_name()));
+        let kind = entry.file_type()?;
+        if kind.is_dir() {
+            files += copy_tree(&source, &target)?;
+        } else if kind.is_symlink() {
+            let link = std::fs::read_link(&source)?;
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&link, &target)?;
+            #[cfg(windows)]
+            if source.is_dir() {
+                std::os::windows::fs::symlink_dir(&link, &target)?;
+            } else {
+                std::os::windows::fs::symlink_file(&link, &target)?;
+            }
+            files += 1;
+        } else {
+            std::fs::copy(&source, &target)
+                .with_context(|| format!("Failed to copy {}", source.display()))?;
+            files += 1;
+        }
+    }
+    Ok(files)
+}
+
+/// Identifies a `proj export` bundle
+const BUNDLE_FORMAT: &str = "proj-export";
+
+/// Version of the bundle layout; projects in it carry their own format version
+const BUNDLE_VERSION: u32 = 1;
+
+/// Projects written by `proj export`, for `proj import` on another machine
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Bundle {
+    format: String,
+    version: u32,
+    exported_at: DateTime<Utc>,
+    /// Home directory of the exporting machine, remapped to the importing one's
+    home: PathBuf,
+    projects: Vec<BundledProject>,
+}
+
+/// A project in a bundle. The project is kept as JSON so one from an older
+/// proj can be migrated before it's parsed.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct BundledProject {
+    project: serde_json::Value,
+    /// Variables stored with `proj <name> env set`
+    env: BTreeMap<String, String>,
+    /// The project's proj.toml
+    #[serde(default)]
+    proj_toml: Option<String>,
+}
+
+/// Write projects, their stored env and proj.toml to a bundle (not logs or
+/// browser profiles)
+async fn cmd_export(names: Vec<String>, all: bool, output: Option<PathBuf>) -> Result<()> {
+    if names.is_empty() && !all {
+        anyhow::bail!("Name the projects to export, or use --all");
+    }
+    let mut projects = list_projects(ProjectFilter::default()).await?;
+    if let Some(missing) = names
+        .iter()
+        .find(|n| !projects.iter().any(|p| &p.name == *n))
+    {
+        return Err(IpcError::ProjectNotFound {
+            name: missing.clone(),
+        }
+        .into());
+    }
+    if !all {
+        projects.retain(|p| names.contains(&p.name));
+    }
+    projects.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut bundled = Vec::new();
+    for project in projects {
+        let env = match send_request(IpcRequest::GetEnv {
+            project_name: project.name.clone(),
+        })
+        .await?
+        {
+            IpcResponse::Env(vars) => vars
+                .into_iter()
+                .filter(|var| var.source == EnvSource::Project)
+                .map(|var| (var.key, var.value))
+                .collect(),
+            IpcResponse::Error(error) => return Err(error.into()),
+            _ => anyhow::bail!(tr!("unexpected-response")),
+        };
+        let proj_toml = std::fs::read_to_string(project.root_dir.join(schema::PROJECT_FILE)).ok();
+        bundled.push(BundledProject {
+            project: serde_json::to_value(&project)?,
+            env,
+            proj_toml,
+        });
+    }
+
+    let count = bundled.len();
+    let bundle = Bundle {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        exported_at: Utc::now(),
+        home: dirs::home_dir().context("Could not find home directory")?,
+        projects: bundled,
+    };
+    let json = serde_json::to_string_pretty(&bundle)? + "\n";
+    match output {
+        Some(path) => {
+            std::fs::write(&path, json)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            println!(
+                "\x1b[32m✓\x1b[0m Exported {} project(s) to {}",
+                count,
+                path.display()
+            );
+            println!("  It includes stored env vars, which may be secrets");
+        }
+        None => print!("{}", json),
+    }
+    Ok(())
+}
+
+/// Recreate the projects in a `proj export` bundle (`-` reads stdin)
+async fn cmd_import(file: PathBuf, maps: Vec<String>, yes: bool) -> Result<()> {
+    let content = if file.as_os_str() == "-" {
+        std::io::read_to_string(std::io::stdin())?
+    } else {
+        std::fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", file.display()))?
+    };
+    let bundle: Bundle = serde_json::from_str(&content)
+        .with_context(|| format!("{} is not a proj export bundle", file.display()))?;
+    if bundle.format != BUNDLE_FORMAT {
+        anyhow::bail!("{} is not a proj export bundle", file.display());
+    }
+    if bundle.version > BUNDLE_VERSION {
+        anyhow::bail!(
+            "{} was written by a newer proj (bundle version {}); upgrade proj to import it",
+            file.display(),
+            bundle.version
+        );
+    }
+
+    let mut remote = Vec::new();
+    for BundledProject {
+        mut project,
+        env,
+        proj_toml,
+    } in bundle.projects
+    {
+        migrate::PROJECT.upgrade(&mut project)?;
+        let project: Project =
+            serde_json::from_value(project).context("Invalid project in bundle")?;
+        remote.push(RemoteProject {
+            project,
+            env,
+            proj_toml,
+        });
+    }
+
+    let remap = path_remapper(&maps, &bundle.home)?;
+    let exported_at = bundle.exported_at.with_timezone(&Local);
+    let source = format!(
+        "in {} (exported {})",
+        file.display(),
+        exported_at.format("%Y-%m-%d %H:%M")
+    );
+    import_projects(&source, remote, remap, yes, !yes).await
+}
+
+/// Split `ssh://[user@]host[:port]` (or a bare host) into an ssh destination and port
+fn parse_ssh_source(source: &str) -> Result<(&str, Option<&str>)> {
+    let Some(rest) = source.strip_prefix("ssh://") else {
+        return Ok((source, None));
+    };
+    let rest = rest.trim_end_matches('/');
+    if rest.is_empty() {
+        anyhow::bail!("Expected ssh://[user@]host[:port], got '{}'", source);
+    }
+    Ok(match rest.rsplit_once(':') {
+        Some((host, port)) if port.parse::<u16>().is_ok() => (host, Some(port)),
+        _ => (rest, None),
+    })
+}
+
+/// List projects passing `filter`, in its order or grouped by tag, or with
+/// `plain` in fixed columns
+async fn cmd_list(filter: ProjectFilter, by_tag: bool, plain: bool, watch: bool) -> Result<()> {
+    if !watch {
+        return print_projects(&filter, by_tag, plain, true).await;
+    }
+
+    let mut changes = Subscription::open(IpcRequest::SubscribeRegistry).await?;
+    loop {
+        // Clear the screen and redraw from the top
+        print!("\x1b[2J\x1b[H");
+        print_projects(&filter, by_tag, plain, false).await?;
+        println!();
+        println!("\x1b[90mWatching for changes (Ctrl+C to exit)\x1b[0m");
+
+        match changes.next().await? {
+            Some(IpcResponse::RegistryChanged(_)) => {}
+            Some(IpcResponse::Error(error)) => return Err(error.into()),
+            Some(_) => {}
+            None => anyhow::bail!("Daemon closed the connection"),
+        }
+    }
+}
+
+/// Print the projects passing `filter` with their status. With `cached`, a
+/// full listing to a terminal first shows the last one fetched, then redraws
+/// it if the daemon's differs. When the daemon can't be reached, the last
+/// listing fetched for `filter` is shown, marked as stale.
+async fn print_projects(
+    filter: &ProjectFilter,
+    by_tag: bool,
+    plain: bool,
+    cached: bool,
+) -> Result<()> {
+    let render = |summaries: &[ProjectSummary]| {
+        if plain {
+            render_plain(filter, summaries)
+        } else {
+            render_projects(filter, by_tag, summaries)
+        }
+    };
+    let cached =
+        cached && !plain && *filter == ProjectFilter::default() && std::io::stdout().is_terminal();
+    let shown = match cached.then(|| read_ls_cache(filter)).flatten() {
+        Some(last) => {
+            let listing = render(&last.summaries);
+            print!("{}", listing);
+            Some(listing)
+        }
+        None => None,
+    };
+
+    let summaries = match project_summaries(filter).await {
+        Ok(summaries) => summaries,
+        Err(e) => {
+            if let Some(shown) = &shown {
+                print!("\x1b[{}F\x1b[J", screen_rows(shown));
+            }
+            // Not when the daemon answered, e.g. with an error
+            let last = match e.downcast_ref::<IpcError>() {
+                Some(_) => None,
+                None => read_ls_cache(filter),
+            };
+            let Some(last) = last else {
+                return Err(e);
+            };
+            print!("{}", render(&last.summaries));
+            let at = last
+                .fetched_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S");
+            if plain {
+                println!("stale: daemon unreachable, listing from {}", at);
+            } else {
+                println!(
+                    "\x1b[33m⚠\x1b[0m Daemon unreachable; this is the listing from {}",
+                    at
+                );
+            }
+            return Ok(());
+        }
+    };
+    let listing = render(&summaries);
+    write_ls_cache(filter, summaries);
+    match shown {
+        Some(shown) if shown == listing => {}
+        Some(shown) => print!("\x1b[{}F\x1b[J{}", screen_rows(&shown), listing),
+        None => print!("{}", listing),
+    }
+    Ok(())
+}
+
+/// Fetch what `proj ls` shows of the projects passing `filter`
+async fn project_summaries(filter: &ProjectFilter) -> Result<Vec<ProjectSummary>> {
+    let request = IpcRequest::ProjectsWithProcesses {
+        filter: filter.clone(),
+    };
+    match send_request(request).await? {
+        IpcResponse::ProjectSummaries(summaries) => Ok(summaries),
+        // Older daemons list projects and processes separately
+        IpcResponse::UnsupportedRequest { .. } => {
+            let projects = list_projects(filter.clone()).await?;
+            let processes =
+                match send_request(IpcRequest::ListProcesses { project_name: None }).await? {
+                    IpcResponse::Processes(processes) => processes,
+                    _ => vec![],
+                };
+            Ok(filter.summaries(projects, &processes))
+        }
+        IpcResponse::Error(error) => Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    }
+}
+
+/// The listing `proj ls` prints for `summaries`
+fn render_projects(filter: &ProjectFilter, by_tag: bool, summaries: &[ProjectSummary]) -> String {
+    use std::fmt::Write;
+
+    let mut out = String::new();
+    if summaries.is_empty() {
+        if *filter == ProjectFilter::default() {
+            out.push_str("No projects yet.\n\nCreate one with: proj new <name>\n");
+        } else {
+            out.push_str("No matching projects\n");
+        }
+        return out;
+    }
+
+    if !by_tag {
+        for project in summaries {
+            render_project(&mut out, project, "");
+        }
+        return out;
+    }
+    let mut groups: BTreeMap<&str, Vec<&ProjectSummary>> = BTreeMap::new();
+    let mut untagged = Vec::new();
+    for project in summaries {
+        if project.tags.is_empty() {
+            untagged.push(project);
+        }
+        for tag in &project.tags {
+            groups.entry(tag).or_default().push(project);
+        }
+    }
+    let untagged = (!untagged.is_empty()).then_some(("(untagged)", untagged));
+    for (tag, members) in groups.into_iter().chain(untagged) {
+        let _ = writeln!(out, "\x1b[1m{}\x1b[0m", tag);
+        for project in members {
+            render_project(&mut out, project, "  ");
+        }
+    }
+    out
+}
+
+/// A project's two lines in `proj ls`: status, name, port and tags, then
+/// its directory
+fn render_project(out: &mut String, project: &ProjectSummary, indent: &str) {
+    use std::fmt::Write;
+
+    let (status_icon, status_color) = if project.running > 0 {
+        ("●", "\x1b[32m") // green
+    } else {
+        ("○", "\x1b[90m") // gray
+    };
+
+    let port_str = project.port.map(|p| format!(":{}", p)).unwrap_or_default();
+    let tags: String = project
+        .tags
+        .iter()
+        .map(|tag| format!(" #{}", tag))
+        .collect();
+
+    let _ = writeln!(
+        out,
+        "{}{}{}\x1b[0m {}{}\x1b[90m{}\x1b[0m",
+        indent,
+        status_color,
+        status_icon,
+        style_name(&project.name, &project.appearance),
+        port_str,
+        tags
+    );
+    let _ = writeln!(out, "{}    {}", indent, project.root_dir.display());
+}
+
+/// The listing `proj ls --plain` prints for `summaries`: a header and a row
+/// per project in columns as wide as their widest value, without colors
+fn render_plain(filter: &ProjectFilter, summaries: &[ProjectSummary]) -> String {
+    if summaries.is_empty() {
+        return if *filter == ProjectFilter::default() {
+            "No projects\n".to_string()
+        } else {
+            "No matching projects\n".to_string()
+        };
+    }
+    let header = ["NAME", "STATUS", "PORT", "TAGS", "DIR"].map(str::to_string);
+    let rows: Vec<[String; 5]> = std::iter::once(header)
+        .chain(summaries.iter().map(plain_row))
+        .collect();
+    let widths: Vec<usize> = (0..4)
+        .map(|column| {
+            rows.iter()
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let mut out = String::new();
+    for row in &rows {
+        for (cell, width) in row.iter().zip(&widths) {
+            out.push_str(&format!("{:<width$}  ", cell, width = width));
+        }
+        out.push_str(&row[4]);
+        out.push('\n');
+    }
+    out
+}
+
+/// A project's cells in `proj ls --plain`
+fn plain_row(project: &ProjectSummary) -> [String; 5] {
+    let status = match project.running {
+        0 => "stopped".to_string(),
+        1 => "running".to_string(),
+        n => format!("running({})", n),
+    };
+    let port = project
+        .port
+        .map_or("-".to_string(), |port| port.to_string());
+    let tags = if project.tags.is_empty() {
+        "-".to_string()
+    } else {
+        project.tags.join(",")
+    };
+    [
+        project.name.clone(),
+        status,
+        port,
+        tags,
+        project.root_dir.display().to_string(),
+    ]
+}
+
+/// Where `proj ls` keeps the last listing it fetched for each filter
+fn ls_cache_path() -> Result<PathBuf> {
+    Ok(proj_common::proj_dir()?.join("cache").join("ls.json"))
+}
+
+/// A listing `proj ls` fetched, kept for the next one
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CachedListing {
+    fetched_at: DateTime<Utc>,
+    summaries: Vec<ProjectSummary>,
+}
+
+/// Cached listings by their filter, as JSON
+type LsCache = BTreeMap<String, CachedListing>;
+
+fn read_ls_caches() -> Option<LsCache> {
+    let content = std::fs::read(ls_cache_path().ok()?).ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+fn read_ls_cache(filter: &ProjectFilter) -> Option<CachedListing> {
+    read_ls_caches()?.remove(&serde_json::to_string(filter).ok()?)
+}
+
+/// Save a listing for the next `proj ls`; it's only a head start or a
+/// fallback, so failing to is fine
+fn write_ls_cache(filter: &ProjectFilter, summaries: Vec<ProjectSummary>) {
+    let (Ok(path), Ok(key)) = (ls_cache_path(), serde_json::to_string(filter)) else {
+        return;
+    };
+    let mut caches = read_ls_caches().unwrap_or_default();
+    let listing = CachedListing {
+        fetched_at: Utc::now(),
+        summaries,
+    };
+    caches.insert(key, listing);
+    let Ok(json) = serde_json::to_vec(&caches) else {
+        return;
+    };
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    // Renamed into place, so a concurrent `ls` never reads a half-written file
+    let temp = path.with_extension(format!("{}.tmp", std::process::id()));
+    if std::fs::write(&temp, json).is_err() || std::fs::rename(&temp, &path).is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+}
+
+/// Terminal rows `text` took up when printed, counting wrapped lines when
+/// the terminal's width is known
+fn screen_rows(text: &str) -> usize {
+    let cols = match attach::size().cols {
+        0 => usize::MAX,
+        cols => usize::from(cols),
+    };
+    text.lines()
+        .map(|line| {
+            let mut width = 0usize;
+            let mut escape = false;
+            for c in line.chars() {
+                match c {
+                    '\x1b' => escape = true,
+                    'm' if escape => escape = false,
+                    _ if escape => {}
+                    _ => width += 1,
+                }
+            }
+            width.div_ceil(cols).max(1)
+        })
+        .sum()
+}
+
+/// Start or manage the daemon
+async fn cmd_daemon(foreground: bool) -> Result<()> {
+    let socket = socket_path()?;
+    let pid_file = pid_file_path()?;
+
+    // Check if daemon is already running
+    if Ipc::connect(&socket).await.is_ok() {
+        println!("\x1b[32m●\x1b[0m {}", tr!("daemon-already-running"));
+        return Ok(());
+    }
+    // A dead daemon's PID file; its socket is replaced when the new one binds
+    if pid_file.exists() {
+        let _ = tokio::fs::remove_file(&pid_file).await;
+    }
+
+    if foreground {
+        println!("\x1b[36m▶\x1b[0m {}", tr!("daemon-foreground"));
+        println!();
+
+        // Run daemon directly - exec into it
+        let daemon_path = daemon_binary()?;
+
+        if !daemon_path.exists() {
+            anyhow::bail!(tr!(
+                "daemon-binary-not-found",
+                path = daemon_path.display().to_string()
+            ));
+        }
+
+        let status = std::process::Command::new(&daemon_path)
+            .envs(config_flag_env())
+            .status()
+            .context("Failed to start daemon")?;
+
+        if !status.success() {
+            anyhow::bail!(tr!("daemon-exited-with-error"));
+        }
+    } else {
+        // Spawn daemon in background
+        let daemon_path = daemon_binary()?;
+
+        if !daemon_path.exists() {
+            anyhow::bail!(tr!(
+                "daemon-binary-not-found",
+                path = daemon_path.display().to_string()
+            ));
+        }
+
+        start_in_background(&daemon_path)?;
+
+        // Wait a bit and verify it started
+        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+        if Ipc::connect(&socket).await.is_ok() {
+            let url = format!("\x1b[4mhttp://{}\x1b[0m", load_config().proxy_addr());
+            println!("\x1b[32m✓\x1b[0m {}", tr!("daemon-started", url = url));
+            warn_proxy_bind_error().await;
+        } else {
+            anyhow::bail!(tr!("daemon-failed-to-start"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Tell the user when a daemon that just started can't listen for the proxy
+async fn warn_proxy_bind_error() {
+    // Boxed, as sending may start the daemon again
+    let status = Box::pin(send_request(IpcRequest::Status)).await;
+    if let Ok(IpcResponse::Status {
+        proxy_error: Some(error),
+        ..
+    }) = status
+    {
+        eprintln!("\x1b[33m⚠\x1b[0m {}", error);
+    }
+}
+
+/// Ask a running daemon to shut down and wait until it has cleaned up
+async fn cmd_daemon_stop() -> Result<()> {
+    let socket = socket_path()?;
+    if Ipc::connect(&socket).await.is_err() {
+        println!("\x1b[90m○\x1b[0m {}", tr!("daemon-not-running"));
+        return Ok(());
     }
 
-    #[cfg(target_os = "linux")]
-    {
-        // Try different Chrome/Chromium variants
-        let browsers = ["google-chrome", "chromium", "chromium-browser"];
-        let mut opened = false;
-
-        for browser in browsers {
-            if std::process::Command::new(browser)
-                .args([&format!("--user-data-dir={}", chrome_dir.display()), &url])
-                .spawn()
-                .is_ok()
-            {
-                opened = true;
-                break;
+    // With the `ask` policy the daemon has no terminal to ask on, so ask here
+    let stop_processes = match resolve_config().map(|r| r.config.on_daemon_exit) {
+        Ok(DaemonExitPolicy::Ask) if std::io::stdin().is_terminal() => {
+            match send_request(IpcRequest::Status).await? {
+                IpcResponse::Status { process_count, .. } if process_count > 0 => {
+                    print!("{} ", tr!("daemon-stop-confirm", count = process_count));
+                    std::io::Write::flush(&mut std::io::stdout())?;
+                    let mut answer = String::new();
+                    std::io::stdin().read_line(&mut answer)?;
+                    Some(!matches!(answer.trim(), "n" | "N" | "no"))
+                }
+                _ => None,
             }
         }
+        _ => None,
+    };
+
+    let stream = Ipc::connect(&socket)
+        .await
+        .context(tr!("daemon-connect-failed"))?;
+    let (reader, mut writer) = tokio::io::split(stream);
+    let json = serde_json::to_string(&IpcRequest::Shutdown { stop_processes })?;
+    writer.write_all(json.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+
+    if stop_processes == Some(false) {
+        println!("\x1b[36m▶\x1b[0m {}", tr!("daemon-stopping-keep"));
+    } else {
+        println!("\x1b[36m▶\x1b[0m {}", tr!("daemon-stopping"));
+    }
 
-        if !opened {
-            anyhow::bail!("Failed to open Chrome/Chromium. Is it installed?");
+    // The daemon stops listening as the last step of shutting down
+    for _ in 0..600 {
+        if Ipc::connect(&socket).await.is_err() {
+            println!("\x1b[33m■\x1b[0m {}", tr!("daemon-stopped"));
+            return Ok(());
         }
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
     }
 
-    Ok(())
+    anyhow::bail!(tr!("daemon-stop-timeout", seconds = 60))
 }
 
-/// List all projects
-async fn cmd_list() -> Result<()> {
-    let response = send_request(IpcRequest::ListProjects).await?;
+/// Register the daemon with the service manager and start it there
+async fn cmd_daemon_install() -> Result<()> {
+    let daemon_path = daemon_binary()?;
+    if !daemon_path.exists() {
+        anyhow::bail!(tr!(
+            "daemon-binary-not-found",
+            path = daemon_path.display().to_string()
+        ));
+    }
 
-    match response {
-        IpcResponse::Projects(projects) => {
-            if projects.is_empty() {
-                println!("No projects yet.");
-                println!();
-                println!("Create one with: proj new <name>");
-                return Ok(());
-            }
+    let log = proj_common::proj_dir()?.join("daemon.log");
+    service::install(&daemon_path, &log)?;
 
-            // Also get processes to show status
-            let proc_response =
-                send_request(IpcRequest::ListProcesses { project_name: None }).await?;
-            let processes = match proc_response {
-                IpcResponse::Processes(p) => p,
-                _ => vec![],
-            };
+    // A daemon started by hand would hold the socket the service needs
+    let socket = socket_path()?;
+    if Ipc::connect(&socket).await.is_ok() && service::main_pid().is_none() {
+        cmd_daemon_stop().await?;
+    }
+    service::start()?;
 
-            for project in projects {
-                let proc = processes.iter().find(|p| {
-                    p.project_name == project.name
-                        && p.status == proj_common::ProcessStatus::Running
-                });
+    let socket = socket_path()?;
+    for _ in 0..20 {
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        if Ipc::connect(&socket).await.is_ok() {
+            println!(
+                "\x1b[32m✓\x1b[0m Daemon installed as {} {} and running on \x1b[4mhttp://{}\x1b[0m",
+                service::manager_name(),
+                service::service_name(),
+                load_config().proxy_addr()
+            );
+            println!("  \x1b[90m{}\x1b[0m", service::unit_path()?.display());
+            return Ok(());
+        }
+    }
 
-                let (status_icon, status_color) = if proc.is_some() {
-                    ("●", "\x1b[32m") // green
-                } else {
-                    ("○", "\x1b[90m") // gray
-                };
+    anyhow::bail!(
+        "Installed {}, but the daemon didn't start. Try: proj daemon -f",
+        service::unit_path()?.display()
+    )
+}
+
+/// Stop the service-managed daemon and remove it from the service manager
+fn cmd_daemon_uninstall() -> Result<()> {
+    if service::uninstall()? {
+        println!(
+            "\x1b[33m■\x1b[0m Removed {} from {}",
+            service::service_name(),
+            service::manager_name()
+        );
+    } else {
+        println!(
+            "\x1b[90m○\x1b[0m Daemon isn't installed with {}",
+            service::manager_name()
+        );
+    }
+    Ok(())
+}
 
-                let port_str = proc
-                    .and_then(|p| p.port)
-                    .map(|p| format!(":{}", p))
-                    .unwrap_or_default();
+/// Report how the daemon was started
+async fn cmd_daemon_status() -> Result<()> {
+    let running = Ipc::connect(&socket_path()?).await.is_ok();
+    let pid = std::fs::read_to_string(pid_file_path()?)
+        .ok()
+        .and_then(|pid| pid.trim().parse::<u32>().ok());
+    let managed_pid = service::main_pid();
+
+    match (running, managed_pid) {
+        (true, Some(managed)) if pid.is_none_or(|pid| pid == managed) => println!(
+            "\x1b[32m●\x1b[0m Daemon running under {} (pid {})",
+            service::manager_name(),
+            managed
+        ),
+        (true, _) => match pid {
+            Some(pid) => println!(
+                "\x1b[32m●\x1b[0m Daemon running, started by hand (pid {})",
+                pid
+            ),
+            None => println!("\x1b[32m●\x1b[0m Daemon running, started by hand"),
+        },
+        (false, _) => println!("\x1b[90m○\x1b[0m {}", tr!("daemon-not-running")),
+    }
 
+    if service::installed() {
+        println!(
+            "  Installed as {} {}: \x1b[90m{}\x1b[0m",
+            service::manager_name(),
+            service::service_name(),
+            service::unit_path()?.display()
+        );
+        let daemon_path = daemon_binary()?;
+        if let Some(installed) = service::installed_binary() {
+            if installed != daemon_path {
                 println!(
-                    "{}{}\x1b[0m \x1b[1m{}\x1b[0m{}",
-                    status_color, status_icon, project.name, port_str
+                    "  \x1b[33m!\x1b[0m The service runs {}, not {}; run \x1b[1mproj daemon install\x1b[0m to update it",
+                    installed.display(),
+                    daemon_path.display()
                 );
-                println!("    {}", project.root_dir.display());
             }
         }
-        IpcResponse::Error { message } => {
-            anyhow::bail!("{}", message);
-        }
-        _ => {
-            anyhow::bail!("Unexpected response from daemon");
-        }
+    } else {
+        println!(
+            "  Not installed with {}; \x1b[1mproj daemon install\x1b[0m starts it at login",
+            service::manager_name()
+        );
     }
 
     Ok(())
 }
 
-/// Start or manage the daemon
-async fn cmd_daemon(foreground: bool) -> Result<()> {
-    let socket = socket_path()?;
-    let pid_file = pid_file_path()?;
-
-    // Check if daemon is already running
-    if socket.exists() {
-        // Try to connect to verify it's alive
-        if UnixStream::connect(&socket).await.is_ok() {
-            println!("\x1b[32m●\x1b[0m Daemon already running");
-            return Ok(());
-        } else {
-            // Socket exists but daemon is dead, clean up
-            let _ = tokio::fs::remove_file(&socket).await;
-            if pid_file.exists() {
-                let _ = tokio::fs::remove_file(&pid_file).await;
-            }
-        }
+/// Re-read config.json in the running daemon
+async fn cmd_daemon_reload() -> Result<()> {
+    if Ipc::connect(&socket_path()?).await.is_err() {
+        println!("\x1b[90m○\x1b[0m {}", tr!("daemon-not-running"));
+        return Ok(());
     }
 
-    if foreground {
-        println!("\x1b[36m▶\x1b[0m Starting daemon in foreground (Ctrl+C to stop)");
-        println!();
-
-        // Run daemon directly - exec into it
-        let daemon_path = std::env::current_exe()?
-            .parent()
-            .context("No parent directory")?
-            .join("proj-daemon");
-
-        if !daemon_path.exists() {
-            anyhow::bail!(
-                "Daemon binary not found at {:?}. Build with: cargo build",
-                daemon_path
+    match send_request(IpcRequest::ReloadConfig).await? {
+        IpcResponse::Success { message } => {
+            println!(
+                "\x1b[32m✓\x1b[0m {}",
+                message.unwrap_or_else(|| "Config reloaded".to_string())
             );
         }
-
-        let status = std::process::Command::new(&daemon_path)
-            .status()
-            .context("Failed to start daemon")?;
-
-        if !status.success() {
-            anyhow::bail!("Daemon exited with error");
-        }
-    } else {
-        // Spawn daemon in background
-        let daemon_path = std::env::current_exe()?
-            .parent()
-            .context("No parent directory")?
-            .join("proj-daemon");
-
-        if !daemon_path.exists() {
-            anyhow::bail!(
-                "Daemon binary not found at {:?}. Build with: cargo build",
-                daemon_path
-            );
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => {
+            anyhow::bail!(tr!("unexpected-response"));
         }
+    }
 
-        // Spawn detached
-        std::process::Command::new(&daemon_path)
-            .stdin(std::process::Stdio::null())
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .spawn()
-            .context("Failed to start daemon")?;
+    Ok(())
+}
 
-        // Wait a bit and verify it started
-        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+/// Have the daemon run a maintenance pass now and show what it cleaned up
+async fn cmd_daemon_maintenance() -> Result<()> {
+    let report = match send_request(IpcRequest::RunMaintenance).await? {
+        IpcResponse::Maintenance(report) => report,
+        IpcResponse::UnsupportedRequest { .. } => {
+            anyhow::bail!("The daemon predates maintenance; restart it with `proj daemon restart`")
+        }
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    };
+    println!(
+        "\x1b[32m✓\x1b[0m Maintenance took {}",
+        format_ms(report.duration_ms)
+    );
+    println!(
+        "  Exited processes: {} records, {} log files ({})",
+        report.processes,
+        report.log_files,
+        format_bytes(report.log_bytes)
+    );
+    println!(
+        "  Browser caches:   {} profile(s) ({})",
+        report.profiles,
+        format_bytes(report.profile_bytes)
+    );
+    println!(
+        "  Database:         {} smaller",
+        format_bytes(report.store_bytes)
+    );
+    if report.stale_metrics > 0 {
+        println!(
+            "  Proxy stats:      dropped for {} removed projects",
+            report.stale_metrics
+        );
+    }
+    for error in &report.errors {
+        println!("  \x1b[33m⚠ {}\x1b[0m", error);
+    }
+    Ok(())
+}
 
-        if socket.exists() {
-            println!("\x1b[32m✓\x1b[0m Daemon started on \x1b[4mhttp://localhost:8080\x1b[0m");
-        } else {
-            anyhow::bail!("Daemon failed to start. Try: proj daemon -f");
+/// Change the running daemon's log levels, then show the filter in effect
+async fn cmd_daemon_log_level(levels: Vec<String>, reset: bool) -> Result<()> {
+    let changed = reset || !levels.is_empty();
+    match send_request(IpcRequest::SetLogLevels { levels, reset }).await? {
+        IpcResponse::Success { message } => {
+            let filter = message.unwrap_or_default();
+            if changed {
+                println!("\x1b[32m✓\x1b[0m Log filter: {}", filter);
+            } else {
+                println!("Log filter: {}", filter);
+            }
         }
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
     }
 
     Ok(())
 }
 
 /// Show daemon status
-async fn cmd_status() -> Result<()> {
+async fn cmd_status(verbose: bool) -> Result<()> {
     let response = send_request(IpcRequest::Status).await?;
 
     match response {
@@ -562,14 +8556,108 @@ async fn cmd_status() -> Result<()> {
             running: _,
             project_count,
             process_count,
+            protocol_version,
+            daemon_version,
+            panics,
+            proxy_error,
+            git_hash,
+            started_at,
+            proxy_addr,
+            socket_path,
+            route_count,
+            subscriber_count,
+            lan_urls,
         } => {
-            println!("\x1b[32m●\x1b[0m proj daemon running on \x1b[4mhttp://localhost:8080\x1b[0m");
+            // Daemons from before it was reported are assumed to follow the config
+            let proxy_addr = proxy_addr.unwrap_or_else(|| load_config().proxy_addr());
+            match &proxy_error {
+                None if project_count == 0 && load_config().proxy_wait_for_projects => println!(
+                    "\x1b[32m●\x1b[0m proj daemon {} running, proxy waiting for a project to listen on {}",
+                    daemon_version, proxy_addr
+                ),
+                None => println!(
+                    "\x1b[32m●\x1b[0m proj daemon {} running on \x1b[4mhttp://{}\x1b[0m",
+                    daemon_version, proxy_addr
+                ),
+                Some(error) => {
+                    println!(
+                        "\x1b[33m●\x1b[0m proj daemon {} running, proxy not listening on {}",
+                        daemon_version, error.addr
+                    );
+                    println!("  \x1b[33m⚠\x1b[0m {}", error);
+                }
+            }
+            if protocol_version != PROTOCOL_VERSION {
+                println!(
+                    "  \x1b[33m⚠\x1b[0m Speaks protocol {}, proj {} speaks {}; restart it with: proj daemon restart",
+                    protocol_version, VERSION, PROTOCOL_VERSION
+                );
+            }
             println!(
                 "  {} project{}, {} running",
                 project_count,
                 if project_count == 1 { "" } else { "s" },
                 process_count
             );
+            if !lan_urls.is_empty() {
+                println!("  LAN: {}", lan_urls.join(", "));
+            }
+            let degraded = format!(
+                "degraded ({} panic{} in the last hour)",
+                panics.len(),
+                if panics.len() == 1 { "" } else { "s" }
+            );
+            if verbose {
+                println!(
+                    "  Build: {}{}, protocol {}",
+                    daemon_version,
+                    git_hash
+                        .map(|hash| format!(" ({})", hash))
+                        .unwrap_or_default(),
+                    protocol_version
+                );
+                if let Some(started_at) = started_at {
+                    println!(
+                        "  Up {}, since {}",
+                        format_uptime(Utc::now() - started_at),
+                        started_at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S")
+                    );
+                }
+                println!("  Proxy: http://{}", proxy_addr);
+                if let Some(socket_path) = socket_path {
+                    println!("  Socket: {}", socket_path.display());
+                }
+                println!(
+                    "  {} routed project{}, {} subscriber{}",
+                    route_count,
+                    if route_count == 1 { "" } else { "s" },
+                    subscriber_count,
+                    if subscriber_count == 1 { "" } else { "s" }
+                );
+                if panics.is_empty() {
+                    println!("  Daemon health: \x1b[32mok\x1b[0m");
+                } else {
+                    println!("  Daemon health: \x1b[33m{}\x1b[0m", degraded);
+                }
+                for panic in &panics {
+                    println!(
+                        "    \x1b[90m{}\x1b[0m {}: {}{}",
+                        panic.timestamp.with_timezone(&Local).format("%H:%M:%S"),
+                        panic.task,
+                        panic.message,
+                        panic
+                            .location
+                            .as_ref()
+                            .map(|l| format!(" \x1b[90m({})\x1b[0m", l))
+                            .unwrap_or_default()
+                    );
+                }
+            } else if !panics.is_empty() {
+                println!(
+                    "  \x1b[33m⚠\x1b[0m Daemon health: {}; see proj status --verbose",
+                    degraded
+                );
+            }
             println!();
             println!("Commands:");
             println!("  proj new <name>         Create a project");
@@ -577,46 +8665,145 @@ async fn cmd_status() -> Result<()> {
             println!("  proj <name> open        Open browser");
             println!("  proj ls                 List all projects");
         }
-        IpcResponse::Error { message } => {
-            anyhow::bail!("{}", message);
-        }
+        IpcResponse::Error(error) => return Err(error.into()),
         _ => {
-            anyhow::bail!("Unexpected response from daemon");
+            anyhow::bail!(tr!("unexpected-response"));
         }
     }
 
     Ok(())
 }
 
-/// Stop a running process
-async fn cmd_stop(project_name: String) -> Result<()> {
-    // Get running process for project
-    let response = send_request(IpcRequest::ListProcesses {
-        project_name: Some(project_name.clone()),
-    })
-    .await?;
+/// What works on this machine, from the CLI's side and then the daemon's
+async fn cmd_doctor() -> Result<()> {
+    let browser: Browser = load_config().browser.parse()?;
+    println!("\x1b[1mproj\x1b[0m");
+    print_capabilities(&[Capability::binary("binary"), browser.capability()]);
 
-    let processes = match response {
-        IpcResponse::Processes(p) => p,
-        IpcResponse::Error { message } => {
-            anyhow::bail!("{}", message);
+    println!("\n\x1b[1mDaemon\x1b[0m");
+    match send_request(IpcRequest::Capabilities).await? {
+        IpcResponse::Capabilities(capabilities) => print_capabilities(&capabilities),
+        IpcResponse::UnsupportedRequest { .. } => {
+            anyhow::bail!("The daemon predates proj doctor; restart it with `proj daemon restart`")
         }
-        _ => {
-            anyhow::bail!("Unexpected response from daemon");
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    }
+    Ok(())
+}
+
+fn print_capabilities(capabilities: &[Capability]) {
+    for capability in capabilities {
+        let mark = match capability.status {
+            CapabilityStatus::Ok => "\x1b[32m✓\x1b[0m",
+            CapabilityStatus::Degraded => "\x1b[33m⚠\x1b[0m",
+            CapabilityStatus::Unavailable => "\x1b[31m✗\x1b[0m",
+        };
+        println!("  {} {}: {}", mark, capability.name, capability.detail);
+    }
+}
+
+/// Install the daemon's local CA so browsers trust https://<name>.localhost
+async fn cmd_trust() -> Result<()> {
+    // The daemon generates the CA on startup
+    send_request(IpcRequest::Status).await?;
+
+    let ca = ca_cert_path()?;
+    if !ca.exists() {
+        anyhow::bail!(
+            "Local CA not found at {}. Is HTTPS enabled (https_port in ~/.proj/config.json)?",
+            ca.display()
+        );
+    }
+
+    #[cfg(unix)]
+    println!(
+        "\x1b[36m▶\x1b[0m Installing {} into the system trust store (requires sudo)",
+        ca.display()
+    );
+
+    #[cfg(target_os = "macos")]
+    {
+        run_sudo(&[
+            "security",
+            "add-trusted-cert",
+            "-d",
+            "-r",
+            "trustRoot",
+            "-k",
+            "/Library/Keychains/System.keychain",
+            &ca.to_string_lossy(),
+        ])?;
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let ca = ca.to_string_lossy();
+        if std::path::Path::new("/usr/local/share/ca-certificates").is_dir() {
+            run_sudo(&["cp", &ca, "/usr/local/share/ca-certificates/proj-ca.crt"])?;
+            run_sudo(&["update-ca-certificates"])?;
+        } else if std::path::Path::new("/etc/pki/ca-trust/source/anchors").is_dir() {
+            run_sudo(&["cp", &ca, "/etc/pki/ca-trust/source/anchors/proj-ca.pem"])?;
+            run_sudo(&["update-ca-trust"])?;
+        } else {
+            anyhow::bail!(
+                "Unrecognized trust store layout. Add {} to your system CA bundle manually",
+                ca
+            );
         }
-    };
+        println!("  Chrome and Firefox keep their own NSS database on Linux; import the CA there too if needed");
+    }
 
-    let running: Vec<_> = processes
-        .into_iter()
-        .filter(|p| p.status == proj_common::ProcessStatus::Running)
-        .collect();
+    #[cfg(windows)]
+    {
+        // The current user's root store needs no elevation; Windows asks to confirm
+        println!(
+            "\x1b[36m▶\x1b[0m Installing {} into your Windows trust store",
+            ca.display()
+        );
+        let status = std::process::Command::new("certutil")
+            .args(["-user", "-addstore", "Root"])
+            .arg(&ca)
+            .status()
+            .context("Failed to run certutil")?;
+        if !status.success() {
+            anyhow::bail!("certutil failed to add the CA");
+        }
+    }
+
+    println!("\x1b[32m✓\x1b[0m Trusted proj development CA");
+    Ok(())
+}
+
+/// Run a command with sudo, failing if it exits unsuccessfully
+#[cfg(unix)]
+fn run_sudo(args: &[&str]) -> Result<()> {
+    let status = std::process::Command::new("sudo")
+        .args(args)
+        .status()
+        .context("Failed to run sudo")?;
+    if !status.success() {
+        anyhow::bail!("`sudo {}` failed", args.join(" "));
+    }
+    Ok(())
+}
 
+/// Stop one of a project's running processes, by ID prefix, or all of them
+async fn cmd_stop(project_name: String, id: Option<&str>) -> Result<()> {
+    let running = running_processes(&project_name).await?;
     if running.is_empty() {
-        println!("No running processes for project '{}'", project_name);
+        println!(
+            "{}",
+            tr!("no-running-processes", name = project_name.as_str())
+        );
         return Ok(());
     }
+    let targets = match id {
+        Some(id) => vec![find_process(&running, &project_name, id)?.clone()],
+        None => running,
+    };
 
-    for proc in running {
+    for proc in targets {
         let response = send_request(IpcRequest::StopProcess {
             project_name: project_name.clone(),
             process_id: proc.id,
@@ -625,16 +8812,21 @@ async fn cmd_stop(project_name: String) -> Result<()> {
 
         match response {
             IpcResponse::Success { .. } => {
-                println!(
-                    "\x1b[33m■\x1b[0m Stopped \x1b[1m{}\x1b[0m (PID: {})",
-                    project_name, proc.pid
+                let stopped = tr!(
+                    "process-stopped",
+                    name = format!("\x1b[1m{}\x1b[0m", project_name),
+                    id = proc.short_id(),
+                    pid = proc.pid.to_string()
                 );
+                println!("\x1b[33m■\x1b[0m {}", stopped);
             }
-            IpcResponse::Error { message } => {
-                eprintln!(
-                    "\x1b[31m✗\x1b[0m Failed to stop process {}: {}",
-                    proc.id, message
+            IpcResponse::Error(error) => {
+                let failed = tr!(
+                    "process-stop-failed",
+                    id = proc.short_id(),
+                    error = error_message(error)
                 );
+                eprintln!("\x1b[31m✗\x1b[0m {}", failed);
             }
             _ => {}
         }
@@ -643,36 +8835,273 @@ async fn cmd_stop(project_name: String) -> Result<()> {
     Ok(())
 }
 
+/// A project's running processes
+async fn running_processes(project_name: &str) -> Result<Vec<ProcessInfo>> {
+    match send_request(IpcRequest::ListProcesses {
+        project_name: Some(project_name.to_string()),
+    })
+    .await?
+    {
+        IpcResponse::Processes(processes) => Ok(processes
+            .into_iter()
+            .filter(|p| p.status.is_alive())
+            .collect()),
+        IpcResponse::Error(error) => Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    }
+}
+
+/// The process whose ID starts with `id`, as shown by `ps`
+fn find_process<'a>(
+    processes: &'a [ProcessInfo],
+    project_name: &str,
+    id: &str,
+) -> Result<&'a ProcessInfo> {
+    let id = id.to_ascii_lowercase();
+    let matching: Vec<&ProcessInfo> = processes
+        .iter()
+        .filter(|p| p.id.simple().to_string().starts_with(&id))
+        .collect();
+    match matching.as_slice() {
+        [proc] => Ok(proc),
+        [] => anyhow::bail!(tr!("no-such-process", id = id, project = project_name)),
+        _ => anyhow::bail!(tr!("ambiguous-process", id = id)),
+    }
+}
+
+/// Show or designate the process a project's hostname goes to:
+/// `primary`, `primary <id>`, `primary --clear`
+async fn cmd_primary(project_name: &str, args: Vec<String>) -> Result<()> {
+    let running = running_processes(project_name).await?;
+    let process_id = match args.as_slice() {
+        [] => {
+            match running.iter().find(|p| p.primary) {
+                Some(proc) => println!("{} {}: {}", project_name, proc.short_id(), proc.command),
+                None => println!(
+                    "{} has no primary process; its newest one with a port is routed",
+                    project_name
+                ),
+            }
+            return Ok(());
+        }
+        [flag] if flag == "--clear" => None,
+        [id] if !id.starts_with('-') => Some(find_process(&running, project_name, id)?.id),
+        _ => anyhow::bail!("Usage: proj {} primary [<id>|--clear]", project_name),
+    };
+
+    match send_request(IpcRequest::SetPrimaryProcess {
+        project_name: project_name.to_string(),
+        process_id,
+    })
+    .await?
+    {
+        IpcResponse::Processes(_) => {}
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    }
+    match running.iter().find(|p| Some(p.id) == process_id) {
+        Some(proc) => println!(
+            "\x1b[32m✓\x1b[0m {} now serves {}",
+            proc.short_id(),
+            project_host(project_name)
+        ),
+        None => println!(
+            "\x1b[32m✓\x1b[0m {} goes to its newest process with a port",
+            project_host(project_name)
+        ),
+    }
+    Ok(())
+}
+
+/// How long `restart` waits for a process to come back with a new PID
+const RESTART_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(30);
+
+/// Restart a project's running processes (or one service's) with the command,
+/// arguments and environment they were started with. A project with nothing
+/// running is started like `proj <name> up`.
+async fn cmd_restart(project_name: &str, service: Option<&str>) -> Result<()> {
+    let processes = match send_request(IpcRequest::ListProcesses {
+        project_name: Some(project_name.to_string()),
+    })
+    .await?
+    {
+        IpcResponse::Processes(processes) => processes,
+        IpcResponse::Error(error) => return Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    };
+    let running: Vec<ProcessInfo> = processes
+        .into_iter()
+        .filter(|p| p.status.is_alive())
+        .filter(|p| service.is_none() || p.service.as_deref() == service)
+        .collect();
+
+    if running.is_empty() {
+        if let Some(service) = service {
+            anyhow::bail!(tr!(
+                "service-not-running",
+                service = service,
+                project = project_name
+            ));
+        }
+        let starting = tr!("not-running-starting", name = project_name);
+        println!("\x1b[90m○\x1b[0m {}", starting);
+        return cmd_up(vec![project_name.to_string()], false, None).await;
+    }
+
+    let mut failed = 0;
+    for process in running {
+        let label = match &process.service {
+            Some(service) => format!("{}/{}", project_name, service),
+            None => project_name.to_string(),
+        };
+        match send_request(IpcRequest::RestartProcess {
+            project_name: project_name.to_string(),
+            process_id: process.id,
+        })
+        .await?
+        {
+            IpcResponse::Success { .. } => {}
+            IpcResponse::Error(error) => {
+                failed += 1;
+                eprintln!("\x1b[31m✗\x1b[0m {}: {}", label, error_message(error));
+                continue;
+            }
+            _ => anyhow::bail!(tr!("unexpected-response")),
+        }
+        let restarting = tr!(
+            "restarting",
+            name = format!("\x1b[1m{}\x1b[0m", label),
+            pid = process.pid.to_string()
+        );
+        println!("\x1b[36m↻\x1b[0m {}", restarting);
+
+        // The daemon stops the old process gracefully before respawning it
+        let deadline = tokio::time::Instant::now() + RESTART_TIMEOUT;
+        let restarted = loop {
+            tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
+            let current = match send_request(IpcRequest::ListProcesses {
+                project_name: Some(project_name.to_string()),
+            })
+            .await?
+            {
+                IpcResponse::Processes(processes) => {
+                    processes.into_iter().find(|p| p.id == process.id)
+                }
+                _ => None,
+            };
+            match current {
+                Some(p) if p.pid != process.pid && p.status.is_alive() => break Some(p),
+                Some(p) if !p.status.is_alive() && p.restart_count > process.restart_count => {
+                    break Some(p)
+                }
+                None => break None,
+                _ if tokio::time::Instant::now() >= deadline => break None,
+                _ => {}
+            }
+        };
+        match restarted {
+            Some(p) if p.status.is_alive() => {
+                let restarted = tr!(
+                    "restarted",
+                    name = format!("\x1b[1m{}\x1b[0m", label),
+                    pid = p.pid.to_string()
+                );
+                println!("\x1b[32m✓\x1b[0m {}", restarted);
+            }
+            Some(p) => {
+                failed += 1;
+                let code = p.last_exit_code.map_or("?".to_string(), |c| c.to_string());
+                let exited = tr!("restart-exited", name = label.as_str(), code = code);
+                eprintln!("\x1b[31m✗\x1b[0m {}", exited);
+            }
+            None => {
+                failed += 1;
+                let seconds = RESTART_TIMEOUT.as_secs();
+                let timeout = tr!("restart-timeout", name = label.as_str(), seconds = seconds);
+                eprintln!("\x1b[31m✗\x1b[0m {}", timeout);
+            }
+        }
+    }
+
+    if failed > 0 {
+        anyhow::bail!(tr!("restart-failed", count = failed));
+    }
+    Ok(())
+}
+
 /// Try to detect project from current working directory
 fn detect_project_from_cwd() -> Result<String> {
-    let cwd = std::env::current_dir()?;
+    if let Some(project) = project_from_cwd() {
+        return Ok(project.name);
+    }
 
-    // Check if any project.json files match our cwd
-    let projects_path = projects_dir()?;
-    if projects_path.exists() {
-        if let Ok(entries) = std::fs::read_dir(&projects_path) {
-            for entry in entries.flatten() {
-                let project_file = entry.path().join("project.json");
-                if project_file.exists() {
-                    if let Ok(content) = std::fs::read_to_string(&project_file) {
-                        if let Ok(project) = serde_json::from_str::<proj_common::Project>(&content)
-                        {
-                            // Check if cwd is the project root or a subdirectory
-                            if cwd.starts_with(&project.root_dir) {
-                                return Ok(project.name);
-                            }
-                        }
-                    }
-                }
+    anyhow::bail!(tr!("not-in-project"))
+}
+
+/// The project whose root is the current directory or one of its parents.
+/// A proj.toml naming the project (see `proj init`) is looked up directly;
+/// otherwise every project's root is checked.
+fn project_from_cwd() -> Option<Project> {
+    let cwd = std::env::current_dir().ok()?;
+    let store = open_store().ok()?;
+    if let Some((root_dir, file)) = ProjectFile::find(&cwd) {
+        if let Ok(Some(project)) = store.project(&file.name) {
+            if project.root_dir == root_dir {
+                return Some(project);
             }
         }
     }
 
-    anyhow::bail!(
-        "Not in a project directory. Specify project name:\n\
-         \n\
-         Usage: proj <project> <command>\n\
-         \n\
-         List projects: proj ls"
-    )
+    // Check if cwd is a project root or a subdirectory of one
+    let projects = store.projects().ok()?;
+    projects
+        .into_iter()
+        .filter_map(|(_, project)| project.ok())
+        .find(|project| cwd.starts_with(&project.root_dir))
+}
+
+/// The project store, after importing files left by an older proj as the
+/// daemon does when it starts
+fn open_store() -> Result<Store> {
+    let store = Store::open_default()?;
+    store.import_files(&projects_dir()?, &processes_path()?)?;
+    Ok(store)
+}
+
+/// Resolve the config for this command: the user's config file, then the
+/// current project's, then `PROJ_*` variables, then `--config` flags
+fn resolve_config() -> Result<layers::ResolvedConfig> {
+    let root = project_from_cwd().map(|p| p.root_dir);
+    let flags = CONFIG_FLAGS.get().map_or(&[][..], Vec::as_slice);
+    layers::load(root.as_deref(), flags)
+}
+
+/// The effective config, or the defaults (with a warning) if it can't be read
+fn load_config() -> Config {
+    static CONFIG: OnceLock<Config> = OnceLock::new();
+    CONFIG
+        .get_or_init(|| {
+            resolve_config().map(|r| r.config).unwrap_or_else(|e| {
+                eprintln!("\x1b[33m!\x1b[0m {:#}; using the default config", e);
+                Config::default()
+            })
+        })
+        .clone()
+}
+
+/// `--config` flags as the environment variables the daemon reads, so a
+/// daemon started by this command sees them too
+fn config_flag_env() -> Vec<(String, String)> {
+    CONFIG_FLAGS
+        .get()
+        .into_iter()
+        .flatten()
+        .filter_map(|flag| {
+            let (name, value) = flag.split_once('=')?;
+            Some((
+                format!("{}{}", layers::ENV_PREFIX, name.trim().to_uppercase()),
+                value.to_string(),
+            ))
+        })
+        .collect()
 }
@@ -0,0 +1,132 @@
+//! External subcommands: `proj-<name>` executables on `PATH`
+//!
+//! Like git and cargo, `proj <name> [args...]` runs `proj-<name> [args...]`
+//! when `<name>` is neither a built-in command nor a project, so extensions
+//! such as `proj-deploy` ship on their own. A plugin gets:
+//!
+//!   PROJ_SOCKET   the daemon's socket (or named pipe) to send IPC requests on
+//!   PROJ_PROJECT  the project the current directory is in, if any
+//!   PROJ_HOST     the remote daemon chosen with `--host`, if any
+//!   PROJ_BIN      this `proj` executable, to call back into
+//!
+//! `proj plugins` lists the plugins found, the first on `PATH` winning.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Prefix of a plugin's executable name
+const PREFIX: &str = "proj-";
+
+/// The executable for plugin `name`, if one is on `PATH`
+pub fn find(name: &str) -> Option<PathBuf> {
+    if name.is_empty() || name.starts_with('-') || name.contains(['/', '\\', '.']) {
+        return None;
+    }
+    path_dirs().find_map(|dir| {
+        executable_names(&format!("{}{}", PREFIX, name))
+            .map(|file| dir.join(file))
+            .find(|path| is_executable(path))
+    })
+}
+
+/// Every plugin on `PATH` by name
+pub fn discover() -> BTreeMap<String, PathBuf> {
+    let mut plugins = BTreeMap::new();
+    for dir in path_dirs() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(name) = plugin_name(&path) else {
+                continue;
+            };
+            if is_executable(&path) {
+                plugins.entry(name).or_insert(path);
+            }
+        }
+    }
+    plugins
+}
+
+/// Run a plugin with `args` and exit with its status
+pub fn run(path: &Path, args: &[String], env: Vec<(&str, String)>) -> Result<()> {
+    let mut command = std::process::Command::new(path);
+    command.args(args).envs(env);
+
+    // Signals and the terminal go straight to the plugin
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        let error = command.exec();
+        Err(error).with_context(|| format!("Failed to run {}", path.display()))
+    }
+
+    #[cfg(not(unix))]
+    {
+        let status = command
+            .status()
+            .with_context(|| format!("Failed to run {}", path.display()))?;
+        std::process::exit(status.code().unwrap_or(1));
+    }
+}
+
+fn path_dirs() -> impl Iterator<Item = PathBuf> {
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|dir| !dir.as_os_str().is_empty())
+}
+
+/// The plugin a file on `PATH` would be, going by its name
+fn plugin_name(path: &Path) -> Option<String> {
+    let file = path.file_name()?.to_str()?;
+    #[cfg(not(unix))]
+    let file = {
+        let (stem, ext) = file.rsplit_once('.')?;
+        executable_extensions()
+            .contains(&format!(".{}", ext.to_ascii_lowercase()))
+            .then_some(stem)?
+    };
+    let name = file.strip_prefix(PREFIX)?;
+    (!name.is_empty() && !name.contains('.')).then(|| name.to_string())
+}
+
+/// File names an executable called `name` can have
+#[cfg(unix)]
+fn executable_names(name: &str) -> impl Iterator<Item = String> {
+    std::iter::once(name.to_string())
+}
+
+#[cfg(not(unix))]
+fn executable_names(name: &str) -> impl Iterator<Item = String> {
+    let name = name.to_string();
+    executable_extensions()
+        .into_iter()
+        .map(move |ext| format!("{}{}", name, ext))
+}
+
+/// Extensions Windows runs files with, lowercased with their dot
+#[cfg(not(unix))]
+fn executable_extensions() -> Vec<String> {
+    std::env::var("PATHEXT")
+        .unwrap_or_else(|_| ".EXE;.CMD;.BAT".to_string())
+        .split(';')
+        .filter(|ext| !ext.is_empty())
+        .map(|ext| ext.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
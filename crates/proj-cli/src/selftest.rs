@@ -0,0 +1,442 @@
+//! `proj selftest` - put the daemon through spawning, stopping and proxying
+//! in bulk, then check it let go of everything it took
+//!
+//! A round creates a throwaway project, starts trivial processes (half exit
+//! on their own, half are stopped), sends requests through the proxy to a
+//! backend served from here, and deletes the project. A small round warms
+//! the daemon up first so buffers and pools it keeps for good aren't counted;
+//! its open files, tasks, memory, routes and process records are compared
+//! before and after the real one.
+
+use anyhow::{Context, Result};
+use proj_common::{
+    tr, DaemonDiagnostics, IpcRequest, IpcResponse, OnConflict, ProcessInfo, ResourceLimits,
+    RestartPolicy,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio::time::{Duration, Instant};
+
+use crate::{
+    error_message, format_bytes, load_config, remote_host, send_request, update_project,
+    DEFAULT_MAX_RESTARTS,
+};
+
+/// Processes started and requests proxied in a round
+#[derive(Clone, Copy)]
+struct Round {
+    processes: usize,
+    requests: usize,
+}
+
+const WARM_UP: Round = Round {
+    processes: 10,
+    requests: 50,
+};
+const QUICK: Round = Round {
+    processes: 20,
+    requests: 200,
+};
+const STRESS: Round = Round {
+    processes: 400,
+    requests: 5000,
+};
+
+/// Processes started, or requests sent, at a time
+const CONCURRENCY: usize = 16;
+
+/// How long processes get to exit, and the daemon to settle, after a round
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a proxied request may take
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Growth allowed once the daemon has settled. Allocators hold on to some
+/// freed memory; a leak per process or request outgrows the margin.
+const MAX_FD_GROWTH: u64 = 8;
+const MAX_TASK_GROWTH: usize = 8;
+const MAX_MEMORY_GROWTH: u64 = 32 << 20;
+
+/// A command that exits right away, and one that runs until it's stopped
+#[cfg(unix)]
+const EXITING: (&str, &[&str]) = ("true", &[]);
+#[cfg(unix)]
+const LONG_RUNNING: (&str, &[&str]) = ("sleep", &["600"]);
+#[cfg(not(unix))]
+const EXITING: (&str, &[&str]) = ("cmd", &["/c", "exit", "0"]);
+#[cfg(not(unix))]
+const LONG_RUNNING: (&str, &[&str]) = ("ping", &["-n", "600", "127.0.0.1"]);
+
+/// One line of the report
+struct Check {
+    passed: bool,
+    line: String,
+}
+
+impl Check {
+    fn new(passed: bool, line: String) -> Self {
+        Self { passed, line }
+    }
+}
+
+/// Run the self-test and print a report, failing if any check does
+pub async fn run(stress: bool) -> Result<()> {
+    if remote_host().is_some() {
+        anyhow::bail!("proj selftest only tests the local daemon");
+    }
+    let round = if stress { STRESS } else { QUICK };
+
+    println!("Warming up the daemon...");
+    exercise_round("warmup", WARM_UP).await?;
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let before = diagnostics().await?;
+
+    println!(
+        "Starting {} processes and proxying {} requests...",
+        round.processes, round.requests
+    );
+    let mut checks = exercise_round("run", round).await?;
+
+    // Tasks and connections wind down shortly after the work does
+    let deadline = Instant::now() + SETTLE_TIMEOUT;
+    let leaks = loop {
+        let leaks = leak_checks(&before, &diagnostics().await?);
+        if leaks.iter().all(|check| check.passed) || Instant::now() >= deadline {
+            break leaks;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    };
+    checks.extend(leaks);
+
+    println!();
+    for check in &checks {
+        let mark = if check.passed {
+            "\x1b[32m✓\x1b[0m"
+        } else {
+            "\x1b[31m✗\x1b[0m"
+        };
+        println!("  {} {}", mark, check.line);
+    }
+    println!();
+    let failed = checks.iter().filter(|check| !check.passed).count();
+    if failed > 0 {
+        anyhow::bail!("Self-test failed {} of {} checks", failed, checks.len());
+    }
+    println!("\x1b[32mPASS\x1b[0m: the daemon cleaned up after itself");
+    Ok(())
+}
+
+async fn diagnostics() -> Result<DaemonDiagnostics> {
+    match send_request(IpcRequest::Diagnostics).await? {
+        IpcResponse::Diagnostics(diagnostics) => Ok(diagnostics),
+        IpcResponse::UnsupportedRequest { .. } => {
+            anyhow::bail!(
+                "The daemon predates proj selftest; restart it with `proj daemon restart`"
+            )
+        }
+        IpcResponse::Error(error) => Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    }
+}
+
+/// Run a round in a throwaway project, which is deleted even if it fails
+async fn exercise_round(label: &str, round: Round) -> Result<Vec<Check>> {
+    let name = format!("selftest-{}-{}", std::process::id(), label);
+    let root_dir = std::env::temp_dir().join(&name);
+    std::fs::create_dir_all(&root_dir)
+        .with_context(|| format!("Failed to create {}", root_dir.display()))?;
+
+    let created = send_request(IpcRequest::CreateProject {
+        name: name.clone(),
+        root_dir: root_dir.clone(),
+        template: None,
+        run_command: None,
+    })
+    .await;
+    let checks = match created {
+        Ok(IpcResponse::Project(_)) => exercise(&name, round).await,
+        Ok(IpcResponse::Error(error)) => Err(error.into()),
+        Ok(_) => Err(anyhow::anyhow!(tr!("unexpected-response"))),
+        Err(e) => Err(e),
+    };
+
+    let deleted = send_request(IpcRequest::DeleteProject {
+        name: name.clone(),
+        keep_data: false,
+    })
+    .await;
+    let _ = std::fs::remove_dir_all(&root_dir);
+    let checks = checks?;
+    match deleted? {
+        IpcResponse::Success { .. } => Ok(checks),
+        IpcResponse::Error(error) => Err(error.into()),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    }
+}
+
+async fn exercise(name: &str, round: Round) -> Result<Vec<Check>> {
+    let mut checks = Vec::new();
+
+    // Processes: every other one is stopped, the rest exit by themselves
+    let started = Instant::now();
+    let results = in_batches(round.processes, |i| {
+        let name = name.to_string();
+        async move {
+            let command = if i % 2 == 0 { EXITING } else { LONG_RUNNING };
+            let process = start_process(&name, command).await?;
+            if command == LONG_RUNNING {
+                stop_process(process).await?;
+            }
+            Ok(())
+        }
+    })
+    .await;
+    let exited = wait_for_exit(name).await?;
+    checks.push(outcome(
+        &results,
+        format!(
+            "Started and stopped {} processes in {:.1}s",
+            round.processes,
+            started.elapsed().as_secs_f64()
+        ),
+    ));
+    checks.push(Check::new(
+        exited,
+        if exited {
+            "Every process exited".to_string()
+        } else {
+            format!(
+                "Processes still running {}s after being stopped",
+                SETTLE_TIMEOUT.as_secs()
+            )
+        },
+    ));
+
+    // Proxy traffic to a backend served from here
+    let backend = TcpListener::bind("127.0.0.1:0")
+        .await
+        .context("Failed to start a test backend")?;
+    let port = backend.local_addr()?.port();
+    let serving = tokio::spawn(serve(backend));
+    update_project(name, |mut project| {
+        project.proxy.routes.insert("/".to_string(), port);
+        Ok(IpcRequest::SetProxyOptions {
+            name: project.name.clone(),
+            options: project.proxy,
+            expected_revision: Some(project.revision),
+        })
+    })
+    .await?;
+
+    let config = load_config();
+    let proxy = format!("127.0.0.1:{}", config.proxy_port);
+    let host = config.host(name);
+    let started = Instant::now();
+    let results = in_batches(round.requests, |_| {
+        let (proxy, host) = (proxy.clone(), host.clone());
+        async move {
+            tokio::time::timeout(REQUEST_TIMEOUT, get(&proxy, &host))
+                .await
+                .unwrap_or_else(|_| {
+                    anyhow::bail!("No response within {}s", REQUEST_TIMEOUT.as_secs())
+                })
+        }
+    })
+    .await;
+    serving.abort();
+    checks.push(outcome(
+        &results,
+        format!(
+            "Proxied {} requests in {:.1}s",
+            round.requests,
+            started.elapsed().as_secs_f64()
+        ),
+    ));
+    Ok(checks)
+}
+
+/// Run `count` jobs, `CONCURRENCY` at a time
+async fn in_batches<F, Fut>(count: usize, job: F) -> Vec<Result<()>>
+where
+    F: Fn(usize) -> Fut,
+    Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+{
+    let mut results = Vec::with_capacity(count);
+    let mut running = JoinSet::new();
+    for i in 0..count {
+        if running.len() == CONCURRENCY {
+            if let Some(result) = running.join_next().await {
+                results.push(result.unwrap_or_else(|e| Err(e.into())));
+            }
+        }
+        running.spawn(job(i));
+    }
+    while let Some(result) = running.join_next().await {
+        results.push(result.unwrap_or_else(|e| Err(e.into())));
+    }
+    results
+}
+
+/// A check that every job succeeded, naming the first failure
+fn outcome(results: &[Result<()>], line: String) -> Check {
+    let failures: Vec<&anyhow::Error> = results.iter().filter_map(|r| r.as_ref().err()).collect();
+    match failures.first() {
+        None => Check::new(true, line),
+        Some(first) => Check::new(
+            false,
+            format!(
+                "{}: {} failed, first with: {:#}",
+                line,
+                failures.len(),
+                first
+            ),
+        ),
+    }
+}
+
+async fn start_process(name: &str, (command, args): (&str, &[&str])) -> Result<ProcessInfo> {
+    let request = IpcRequest::RunCommand {
+        project_name: name.to_string(),
+        command: command.to_string(),
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+        restart: RestartPolicy::Never,
+        max_restarts: DEFAULT_MAX_RESTARTS,
+        watch: None,
+        exclusive: false,
+        no_wait: false,
+        heavy: false,
+        output: None,
+        sandbox: None,
+        primary: false,
+        then: None,
+        otherwise: None,
+        limits: ResourceLimits::default(),
+        profile: None,
+        tty: None,
+        cwd: None,
+        on_conflict: OnConflict::Allow,
+    };
+    match send_request(request).await? {
+        IpcResponse::ProcessStarted { process, .. } => Ok(process),
+        IpcResponse::Error(error) => anyhow::bail!("{}", error_message(error)),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    }
+}
+
+async fn stop_process(process: ProcessInfo) -> Result<()> {
+    let request = IpcRequest::StopProcess {
+        project_name: process.project_name,
+        process_id: process.id,
+    };
+    match send_request(request).await? {
+        IpcResponse::Success { .. } => Ok(()),
+        IpcResponse::Error(error) => anyhow::bail!("{}", error_message(error)),
+        _ => anyhow::bail!(tr!("unexpected-response")),
+    }
+}
+
+/// Whether every process of the project exited within the timeout
+async fn wait_for_exit(name: &str) -> Result<bool> {
+    let deadline = Instant::now() + SETTLE_TIMEOUT;
+    loop {
+        let request = IpcRequest::ListProcesses {
+            project_name: Some(name.to_string()),
+        };
+        let alive = match send_request(request).await? {
+            IpcResponse::Processes(processes) => processes.iter().any(|p| p.status.is_alive()),
+            IpcResponse::Error(error) => return Err(error.into()),
+            _ => anyhow::bail!(tr!("unexpected-response")),
+        };
+        if !alive {
+            return Ok(true);
+        }
+        if Instant::now() >= deadline {
+            return Ok(false);
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+}
+
+/// Answer every request with `200 ok`, closing each connection after it
+async fn serve(listener: TcpListener) {
+    while let Ok((mut stream, _)) = listener.accept().await {
+        tokio::spawn(async move {
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+            let _ = stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok")
+                .await;
+        });
+    }
+}
+
+/// A request through the proxy, which must reach the backend
+async fn get(proxy: &str, host: &str) -> Result<()> {
+    let mut stream = TcpStream::connect(proxy)
+        .await
+        .with_context(|| format!("Failed to connect to the proxy at {}", proxy))?;
+    let request = format!(
+        "GET /selftest HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        host
+    );
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    let response = String::from_utf8_lossy(&response);
+    let status = response.lines().next().unwrap_or_default();
+    anyhow::ensure!(status.contains(" 200 "), "Got '{}'", status);
+    Ok(())
+}
+
+/// Checks that the daemon holds no more than it did before the round
+fn leak_checks(before: &DaemonDiagnostics, after: &DaemonDiagnostics) -> Vec<Check> {
+    let mut checks = Vec::new();
+    if let (Some(before), Some(after)) = (before.open_fds, after.open_fds) {
+        checks.push(Check::new(
+            after <= before + MAX_FD_GROWTH,
+            format!("Open files: {} → {}", before, after),
+        ));
+    }
+    checks.push(Check::new(
+        after.tasks <= before.tasks + MAX_TASK_GROWTH,
+        format!("Tasks: {} → {}", before.tasks, after.tasks),
+    ));
+    if let (Some(before), Some(after)) = (before.memory_bytes, after.memory_bytes) {
+        checks.push(Check::new(
+            after <= before + MAX_MEMORY_GROWTH,
+            format!("Memory: {} → {}", format_bytes(before), format_bytes(after)),
+        ));
+    }
+    let leftover: Vec<&String> = after
+        .routes
+        .iter()
+        .filter(|route| !before.routes.contains(route))
+        .collect();
+    checks.push(Check::new(
+        leftover.is_empty(),
+        if leftover.is_empty() {
+            format!(
+                "Routing table: {} → {} routes",
+                before.routes.len(),
+                after.routes.len()
+            )
+        } else {
+            format!("Routing table kept routes for {:?}", leftover)
+        },
+    ));
+    checks.push(Check::new(
+        after.process_records <= before.process_records,
+        format!(
+            "Process records: {} → {}",
+            before.process_records, after.process_records
+        ),
+    ));
+    checks
+}
@@ -0,0 +1,346 @@
+//! Running the daemon under the system's service manager (`proj daemon install`)
+//!
+//! On Linux the daemon becomes a systemd user unit, `proj-daemon.service`, and
+//! on macOS a launchd agent, `dev.proj.daemon`; both start it at login and
+//! restart it if it crashes, but not after `proj daemon stop`. Neither stops
+//! the daemon's processes along with it, so `on_daemon_exit` still decides
+//! what happens to them. Service managers start programs with a minimal
+//! `PATH`, so the one `install` ran with is written into the unit.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// systemd unit name
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+const SYSTEMD_UNIT: &str = "proj-daemon.service";
+
+/// launchd job label
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+const LAUNCHD_LABEL: &str = "dev.proj.daemon";
+
+/// The service manager of this platform
+pub fn manager_name() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "launchd"
+    } else if cfg!(target_os = "linux") {
+        "systemd"
+    } else {
+        "a service manager"
+    }
+}
+
+/// The service's name in its manager
+pub fn service_name() -> &'static str {
+    if cfg!(target_os = "macos") {
+        LAUNCHD_LABEL
+    } else {
+        SYSTEMD_UNIT
+    }
+}
+
+/// Where the service definition is written
+pub fn unit_path() -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Could not find home directory")?;
+    Ok(if cfg!(target_os = "macos") {
+        home.join("Library/LaunchAgents")
+            .join(format!("{}.plist", LAUNCHD_LABEL))
+    } else {
+        dirs::config_dir()
+            .unwrap_or_else(|| home.join(".config"))
+            .join("systemd/user")
+            .join(SYSTEMD_UNIT)
+    })
+}
+
+/// Whether the service is installed
+pub fn installed() -> bool {
+    unit_path().is_ok_and(|path| path.exists())
+}
+
+/// The daemon binary an installed service runs
+pub fn installed_binary() -> Option<PathBuf> {
+    let unit = std::fs::read_to_string(unit_path().ok()?).ok()?;
+    if cfg!(target_os = "macos") {
+        let args = unit.split("<key>ProgramArguments</key>").nth(1)?;
+        let start = args.find("<string>")? + "<string>".len();
+        let end = start + args[start..].find("</string>")?;
+        Some(PathBuf::from(xml_unescape(&args[start..end])))
+    } else {
+        unit.lines()
+            .find_map(|line| line.strip_prefix("ExecStart="))
+            .map(|exec| PathBuf::from(exec.trim().trim_matches('"')))
+    }
+}
+
+/// Write the service definition for `daemon` and register it to start at
+/// login. The definition is removed again if registering fails.
+pub fn install(daemon: &Path, log: &Path) -> Result<()> {
+    let path = unit_path()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let search_path = std::env::var("PATH").unwrap_or_default();
+    let contents = if cfg!(target_os = "macos") {
+        launchd_plist(daemon, log, &search_path)
+    } else {
+        systemd_unit(daemon, &search_path)
+    };
+    std::fs::write(&path, contents)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    platform_register().inspect_err(|_| {
+        let _ = std::fs::remove_file(&path);
+    })
+}
+
+/// Stop the service and remove its definition. Returns false if it wasn't
+/// installed. The definition is removed even if the service manager can't be
+/// reached, so a broken service never keeps the daemon from starting.
+pub fn uninstall() -> Result<bool> {
+    let path = unit_path()?;
+    if !path.exists() {
+        return Ok(false);
+    }
+    let stopped = platform_unregister();
+    std::fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    stopped?;
+    Ok(true)
+}
+
+/// Have the service manager start the daemon, loading it first if needed
+pub fn start() -> Result<()> {
+    platform_start()
+}
+
+/// PID of the daemon the service manager is running, if it is
+pub fn main_pid() -> Option<u32> {
+    platform_main_pid().filter(|pid| *pid != 0)
+}
+
+#[cfg(target_os = "linux")]
+fn platform_register() -> Result<()> {
+    systemctl(&["daemon-reload"])?;
+    systemctl(&["enable", SYSTEMD_UNIT])
+}
+
+#[cfg(target_os = "linux")]
+fn platform_unregister() -> Result<()> {
+    systemctl(&["disable", "--now", SYSTEMD_UNIT])
+}
+
+#[cfg(target_os = "linux")]
+fn platform_start() -> Result<()> {
+    systemctl(&["start", SYSTEMD_UNIT])
+}
+
+#[cfg(target_os = "linux")]
+fn platform_main_pid() -> Option<u32> {
+    let output = Command::new("systemctl")
+        .args([
+            "--user",
+            "show",
+            SYSTEMD_UNIT,
+            "--property",
+            "MainPID",
+            "--value",
+        ])
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+#[cfg(target_os = "linux")]
+fn systemctl(args: &[&str]) -> Result<()> {
+    run(Command::new("systemctl").arg("--user").args(args))
+}
+
+#[cfg(target_os = "macos")]
+fn platform_register() -> Result<()> {
+    // Loading the agent starts it, as it's marked to run at load, so that's
+    // left to `start`; launchd picks it up by itself at the next login
+    run(Command::new("launchctl")
+        .arg("print")
+        .arg(launchd_domain()?))
+}
+
+#[cfg(target_os = "macos")]
+fn platform_unregister() -> Result<()> {
+    if platform_main_pid().is_none() && !loaded()? {
+        return Ok(());
+    }
+    run(Command::new("launchctl").arg("bootout").arg(format!(
+        "{}/{}",
+        launchd_domain()?,
+        LAUNCHD_LABEL
+    )))
+}
+
+#[cfg(target_os = "macos")]
+fn platform_start() -> Result<()> {
+    if loaded()? {
+        run(Command::new("launchctl").arg("kickstart").arg(format!(
+            "{}/{}",
+            launchd_domain()?,
+            LAUNCHD_LABEL
+        )))
+    } else {
+        run(Command::new("launchctl")
+            .arg("bootstrap")
+            .arg(launchd_domain()?)
+            .arg(unit_path()?))
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn platform_main_pid() -> Option<u32> {
+    let output = Command::new("launchctl")
+        .arg("print")
+        .arg(format!("{}/{}", launchd_domain().ok()?, LAUNCHD_LABEL))
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("pid = ")?.parse().ok())
+}
+
+/// Whether the agent is loaded into the user's launchd domain
+#[cfg(target_os = "macos")]
+fn loaded() -> Result<bool> {
+    Ok(Command::new("launchctl")
+        .arg("print")
+        .arg(format!("{}/{}", launchd_domain()?, LAUNCHD_LABEL))
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .context("Failed to run launchctl")?
+        .success())
+}
+
+/// The logged-in user's launchd domain, `gui/<uid>`
+#[cfg(target_os = "macos")]
+fn launchd_domain() -> Result<String> {
+    let output = Command::new("id")
+        .arg("-u")
+        .output()
+        .context("Failed to run id")?;
+    Ok(format!(
+        "gui/{}",
+        String::from_utf8_lossy(&output.stdout).trim()
+    ))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn platform_register() -> Result<()> {
+    anyhow::bail!("Installing the daemon as a service isn't supported on this platform")
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn platform_unregister() -> Result<()> {
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn platform_start() -> Result<()> {
+    anyhow::bail!("Installing the daemon as a service isn't supported on this platform")
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn platform_main_pid() -> Option<u32> {
+    None
+}
+
+#[cfg_attr(not(any(target_os = "linux", target_os = "macos")), allow(dead_code))]
+fn run(command: &mut Command) -> Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .output()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// systemd user unit running `daemon`. Only the daemon is stopped with the
+/// unit; its processes are left to `on_daemon_exit`.
+fn systemd_unit(daemon: &Path, search_path: &str) -> String {
+    format!(
+        "[Unit]\n\
+         Description=proj daemon\n\
+         \n\
+         [Service]\n\
+         ExecStart=\"{}\"\n\
+         Environment=\"PATH={}\"\n\
+         Restart=on-failure\n\
+         KillMode=process\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        daemon.display(),
+        search_path.replace('%', "%%")
+    )
+}
+
+/// launchd agent running `daemon` at login, restarted unless it exits
+/// cleanly, with its output in `log`
+fn launchd_plist(daemon: &Path, log: &Path, search_path: &str) -> String {
+    let daemon = xml_escape(&daemon.display().to_string());
+    let log = xml_escape(&log.display().to_string());
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{daemon}</string>
+    </array>
+    <key>EnvironmentVariables</key>
+    <dict>
+        <key>PATH</key>
+        <string>{path}</string>
+    </dict>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>AbandonProcessGroup</key>
+    <true/>
+    <key>StandardOutPath</key>
+    <string>{log}</string>
+    <key>StandardErrorPath</key>
+    <string>{log}</string>
+</dict>
+</plist>
+"#,
+        label = LAUNCHD_LABEL,
+        daemon = daemon,
+        path = xml_escape(search_path),
+        log = log
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn xml_unescape(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&gt;", ">")
+        .replace("&lt;", "<")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,310 @@
+//! Project scaffolding for `proj new --template`
+//!
+//! Built-in templates are compiled in; user templates are plain directories
+//! under `~/.proj/templates/<name>/`, copied as-is. `{{name}}` in text files
+//! is replaced with the project name.
+
+use anyhow::{Context, Result};
+use proj_common::schema::PROJECT_FILE;
+use proj_common::templates_dir;
+use std::path::{Path, PathBuf};
+
+/// A template compiled into the CLI
+struct Builtin {
+    name: &'static str,
+    /// Default dev command; processes get their port in `PORT`
+    dev: &'static str,
+    /// Command to run once after scaffolding, e.g. installing dependencies
+    setup: &'static str,
+    files: &'static [(&'static str, &'static str)],
+}
+
+const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "vite-react",
+        dev: "npm run dev",
+        setup: "npm install",
+        files: &[
+            (
+                "package.json",
+                r#"{
+  "name": "{{name}}",
+  "private": true,
+  "version": "0.0.0",
+  "type": "module",
+  "scripts": {
+    "dev": "vite",
+    "build": "vite build",
+    "preview": "vite preview"
+  },
+  "dependencies": {
+    "react": "^18.3.1",
+    "react-dom": "^18.3.1"
+  },
+  "devDependencies": {
+    "@vitejs/plugin-react": "^4.3.4",
+    "vite": "^6.0.0"
+  }
+}
+"#,
+            ),
+            (
+                "vite.config.js",
+                r#"import { defineConfig } from "vite";
+import react from "@vitejs/plugin-react";
+
+// proj hands out the port in PORT and proxies {{name}}.localhost to it
+export default defineConfig({
+  plugins: [react()],
+  server: {
+    port: Number(process.env.PORT) || 5173,
+    strictPort: true,
+  },
+});
+"#,
+            ),
+            (
+                "index.html",
+                r#"<!doctype html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8" />
+    <meta name="viewport" content="width=device-width, initial-scale=1.0" />
+    <title>{{name}}</title>
+  </head>
+  <body>
+    <div id="root"></div>
+    <script type="module" src="/src/main.jsx"></script>
+  </body>
+</html>
+"#,
+            ),
+            (
+                "src/main.jsx",
+                r#"import { StrictMode } from "react";
+import { createRoot } from "react-dom/client";
+import App from "./App.jsx";
+
+createRoot(document.getElementById("root")).render(
+  <StrictMode>
+    <App />
+  </StrictMode>,
+);
+"#,
+            ),
+            (
+                "src/App.jsx",
+                r#"export default function App() {
+  return <h1>{{name}}</h1>;
+}
+"#,
+            ),
+            (".gitignore", "node_modules\ndist\n"),
+        ],
+    },
+    Builtin {
+        name: "fastapi",
+        dev: "python3 main.py",
+        setup: "pip install -r requirements.txt",
+        files: &[
+            (
+                "main.py",
+                r#"import os
+
+import uvicorn
+from fastapi import FastAPI
+
+app = FastAPI(title="{{name}}")
+
+
+@app.get("/")
+def index():
+    return {"project": "{{name}}"}
+
+
+if __name__ == "__main__":
+    # proj hands out the port in PORT and proxies {{name}}.localhost to it
+    uvicorn.run("main:app", port=int(os.environ.get("PORT", "8000")), reload=True)
+"#,
+            ),
+            ("requirements.txt", "fastapi\nuvicorn[standard]\n"),
+            (".gitignore", "__pycache__/\n.venv/\n"),
+        ],
+    },
+];
+
+/// A template loaded and ready to be written out
+pub struct Template {
+    pub name: String,
+    /// Files relative to the project root
+    files: Vec<(PathBuf, Vec<u8>)>,
+    /// Default dev command, recorded as the project's run command
+    pub dev_command: Option<Vec<String>>,
+    /// Suggested first command after scaffolding
+    pub setup: Option<String>,
+}
+
+impl Template {
+    /// Load a user template, falling back to the built-in one of that name
+    pub fn load(name: &str) -> Result<Self> {
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            anyhow::bail!("Invalid template name '{}'", name);
+        }
+        let dir = templates_dir()?.join(name);
+        if dir.is_dir() {
+            return Self::load_dir(name, &dir);
+        }
+        match BUILTINS.iter().find(|b| b.name == name) {
+            Some(builtin) => Ok(Self::builtin(builtin)),
+            None => anyhow::bail!(
+                "Unknown template '{}'. Templates: {}",
+                name,
+                available()?.join(", ")
+            ),
+        }
+    }
+
+    fn builtin(builtin: &Builtin) -> Self {
+        Self {
+            name: builtin.name.to_string(),
+            files: builtin
+                .files
+                .iter()
+                .map(|(path, content)| (PathBuf::from(path), content.as_bytes().to_vec()))
+                .collect(),
+            dev_command: Some(builtin.dev.split_whitespace().map(String::from).collect()),
+            setup: Some(builtin.setup.to_string()),
+        }
+    }
+
+    fn load_dir(name: &str, dir: &Path) -> Result<Self> {
+        let mut files = Vec::new();
+        collect_files(dir, Path::new(""), &mut files)
+            .with_context(|| format!("Failed to read template {}", dir.display()))?;
+        // A template's own proj.toml names its dev command
+        let dev_command = files
+            .iter()
+            .find(|(path, _)| path == Path::new(PROJECT_FILE))
+            .and_then(|(_, content)| toml_string(&String::from_utf8_lossy(content), "dev"))
+            .map(|dev| dev.split_whitespace().map(String::from).collect());
+        Ok(Self {
+            name: name.to_string(),
+            files,
+            dev_command,
+            setup: None,
+        })
+    }
+
+    /// Write the template into `root`, which must be empty, returning how
+    /// many files were created
+    pub fn scaffold(&self, root: &Path, project_name: &str) -> Result<usize> {
+        let mut written = 0;
+        for (path, content) in &self.files {
+            write_file(&root.join(path), &substitute(content, project_name))?;
+            written += 1;
+        }
+        if !self
+            .files
+            .iter()
+            .any(|(path, _)| path == Path::new(PROJECT_FILE))
+        {
+            write_file(
+                &root.join(PROJECT_FILE),
+                self.project_file(project_name).as_bytes(),
+            )?;
+            written += 1;
+        }
+        Ok(written)
+    }
+
+    /// Generated `proj.toml` for templates that don't bring their own
+    fn project_file(&self, project_name: &str) -> String {
+        let dev = match &self.dev_command {
+            Some(command) => format!("dev = \"{}\"\n", command.join(" ")),
+            None => "# dev = \"npm run dev\"\n".to_string(),
+        };
+        format!(
+            "# Created by `proj new --template {}`\n\
+             name = \"{}\"\n\
+             template = \"{}\"\n\
+             \n\
+             [run]\n\
+             # Default dev command; `proj up` starts it with the port in PORT\n\
+             {}",
+            self.name, project_name, self.name, dev
+        )
+    }
+}
+
+/// Names of built-in and user templates
+pub fn available() -> Result<Vec<String>> {
+    let mut names: Vec<String> = BUILTINS.iter().map(|b| b.name.to_string()).collect();
+    if let Ok(entries) = std::fs::read_dir(templates_dir()?) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.path().is_dir() && !name.starts_with('.') && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Fail unless `dir` is missing or empty
+pub fn ensure_empty(dir: &Path) -> Result<()> {
+    if let Ok(mut entries) = std::fs::read_dir(dir) {
+        if entries.next().is_some() {
+            anyhow::bail!(
+                "{} is not empty; templates scaffold into a new directory",
+                dir.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn collect_files(root: &Path, relative: &Path, files: &mut Vec<(PathBuf, Vec<u8>)>) -> Result<()> {
+    let mut entries: Vec<_> =
+        std::fs::read_dir(root.join(relative))?.collect::<std::io::Result<_>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let path = relative.join(entry.file_name());
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            collect_files(root, &path, files)?;
+        } else {
+            files.push((path, std::fs::read(entry.path())?));
+        }
+    }
+    Ok(())
+}
+
+fn write_file(path: &Path, content: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Replace `{{name}}` in text files; binary files are copied untouched
+fn substitute(content: &[u8], project_name: &str) -> Vec<u8> {
+    match std::str::from_utf8(content) {
+        Ok(text) => text.replace("{{name}}", project_name).into_bytes(),
+        Err(_) => content.to_vec(),
+    }
+}
+
+/// Value of a top-level-looking `key = "value"` line
+fn toml_string(content: &str, key: &str) -> Option<String> {
+    content.lines().find_map(|line| {
+        let (k, v) = line.split_once('=')?;
+        if k.trim() != key {
+            return None;
+        }
+        let v = v.trim();
+        v.strip_prefix('"')?.strip_suffix('"').map(String::from)
+    })
+}
@@ -0,0 +1,486 @@
+//! `proj top` - full-screen dashboard of projects, processes and live output
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use proj_common::{
+    IpcRequest, IpcResponse, LogLine, ProcessInfo, ProcessStatus, Project, ProjectFilter,
+    RegistryChangeKind,
+};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use tokio::sync::mpsc;
+use tokio::time::{interval, Duration};
+
+use crate::browser::Opened;
+use crate::{
+    error_message, format_bytes, format_uptime, list_projects, open_browser, send_request,
+    start_unit, Subscription,
+};
+
+/// How often processes and their resource usage are refreshed
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Output lines kept for the log pane
+const MAX_LOG_LINES: usize = 1000;
+
+/// Run the dashboard until the user quits
+pub async fn run() -> Result<()> {
+    let mut registry = Subscription::open(IpcRequest::SubscribeRegistry).await?;
+    let projects = list_projects(ProjectFilter::default()).await?;
+
+    let mut terminal = ratatui::try_init().context("Failed to set up the terminal")?;
+    let result = App::new(projects).run(&mut terminal, &mut registry).await;
+    ratatui::restore();
+    result
+}
+
+struct App {
+    projects: Vec<Project>,
+    processes: Vec<ProcessInfo>,
+    selected: TableState,
+    /// Project whose output is in `logs`
+    logs_for: Option<String>,
+    logs: VecDeque<LogLine>,
+    /// Lines scrolled up from the newest output
+    log_scroll: usize,
+    /// Result of the last action, shown in the footer
+    message: Option<String>,
+}
+
+impl App {
+    fn new(mut projects: Vec<Project>) -> Self {
+        projects.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut selected = TableState::default();
+        if !projects.is_empty() {
+            selected.select(Some(0));
+        }
+        Self {
+            projects,
+            processes: Vec::new(),
+            selected,
+            logs_for: None,
+            logs: VecDeque::new(),
+            log_scroll: 0,
+            message: None,
+        }
+    }
+
+    async fn run(
+        mut self,
+        terminal: &mut DefaultTerminal,
+        registry: &mut Subscription,
+    ) -> Result<()> {
+        // crossterm reads block, so keys come from a thread of their own
+        let (key_tx, mut keys) = mpsc::channel(16);
+        std::thread::spawn(move || {
+            while let Ok(event) = event::read() {
+                if let Event::Key(key) = event {
+                    if key.kind == KeyEventKind::Press && key_tx.blocking_send(key).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        // Background actions report back here so the UI never waits on them
+        let (message_tx, mut messages) = mpsc::channel(16);
+        let mut logs: Option<Subscription> = None;
+        let mut refresh = interval(REFRESH_INTERVAL);
+
+        loop {
+            if self.logs_for.as_deref() != self.selected_project().map(|p| p.name.as_str()) {
+                self.logs_for = self.selected_project().map(|p| p.name.clone());
+                self.logs.clear();
+                self.log_scroll = 0;
+                logs = match &self.logs_for {
+                    Some(name) => Subscription::open(IpcRequest::SubscribeLogs {
+                        project_name: name.clone(),
+                    })
+                    .await
+                    .ok(),
+                    None => None,
+                };
+            }
+
+            terminal.draw(|frame| self.draw(frame))?;
+
+            tokio::select! {
+                Some(key) = keys.recv() => {
+                    if !self.handle_key(key, &message_tx) {
+                        return Ok(());
+                    }
+                }
+                change = registry.next() => match change? {
+                    Some(IpcResponse::RegistryChanged(change)) => {
+                        let selected = self.selected_project().map(|p| p.name.clone());
+                        self.projects.retain(|p| p.name != change.name);
+                        if change.kind != RegistryChangeKind::Deleted {
+                            self.projects.extend(change.project);
+                            self.projects.sort_by(|a, b| a.name.cmp(&b.name));
+                        }
+                        self.reselect(selected.as_deref());
+                    }
+                    Some(_) => {}
+                    None => anyhow::bail!("Daemon closed the connection"),
+                },
+                line = async { logs.as_mut().unwrap().next().await }, if logs.is_some() => {
+                    match line {
+                        Ok(Some(IpcResponse::Log(line))) => {
+                            if self.logs.len() >= MAX_LOG_LINES {
+                                self.logs.pop_front();
+                            }
+                            self.logs.push_back(line);
+                            if self.log_scroll > 0 {
+                                // Keep the lines being read in place
+                                self.log_scroll += 1;
+                            }
+                        }
+                        Ok(Some(_)) => {}
+                        Ok(None) | Err(_) => logs = None,
+                    }
+                }
+                Some(message) = messages.recv() => self.message = Some(message),
+                _ = refresh.tick() => {
+                    if let IpcResponse::Processes(processes) =
+                        send_request(IpcRequest::ListProcesses { project_name: None }).await?
+                    {
+                        self.processes = processes;
+                    }
+                }
+            }
+        }
+    }
+
+    fn selected_project(&self) -> Option<&Project> {
+        self.selected.selected().and_then(|i| self.projects.get(i))
+    }
+
+    /// Keep the same project selected after the list changes
+    fn reselect(&mut self, name: Option<&str>) {
+        let index = name
+            .and_then(|name| self.projects.iter().position(|p| p.name == name))
+            .or_else(|| {
+                let last = self.projects.len().checked_sub(1)?;
+                Some(self.selected.selected().unwrap_or(0).min(last))
+            });
+        self.selected.select(index);
+    }
+
+    /// Running processes of a project
+    fn running(&self, project_name: &str) -> Vec<&ProcessInfo> {
+        self.processes
+            .iter()
+            .filter(|p| p.project_name == project_name && p.status.is_alive())
+            .collect()
+    }
+
+    /// Handle a key press; returns false to quit
+    fn handle_key(&mut self, key: KeyEvent, messages: &mpsc::Sender<String>) -> bool {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Down | KeyCode::Char('j') if !self.projects.is_empty() => {
+                let last = self.projects.len() - 1;
+                let next = self.selected.selected().map_or(0, |i| (i + 1).min(last));
+                self.selected.select(Some(next));
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.selected.select_previous(),
+            KeyCode::PageUp => {
+                self.log_scroll = (self.log_scroll + 10).min(self.logs.len().saturating_sub(1))
+            }
+            KeyCode::PageDown => self.log_scroll = self.log_scroll.saturating_sub(10),
+            KeyCode::End => self.log_scroll = 0,
+            KeyCode::Char('s') => self.stop_selected(messages),
+            KeyCode::Char('r') => self.restart_selected(messages),
+            KeyCode::Char('o') => {
+                if let Some(project) = self.selected_project() {
+                    self.message = Some(match open_browser(project) {
+                        Ok(Opened::Isolated) => format!("Opened {}", project.name),
+                        Ok(Opened::Default) => format!(
+                            "Opened {} in the default browser, without its own profile",
+                            project.name
+                        ),
+                        Err(e) => e.to_string(),
+                    });
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn stop_selected(&mut self, messages: &mpsc::Sender<String>) {
+        let Some(project) = self.selected_project() else {
+            return;
+        };
+        let name = project.name.clone();
+        let ids: Vec<_> = self.running(&name).iter().map(|p| p.id).collect();
+        if ids.is_empty() {
+            self.message = Some(format!("{} is not running", name));
+            return;
+        }
+
+        self.message = Some(format!("Stopping {}...", name));
+        let messages = messages.clone();
+        tokio::spawn(async move {
+            for process_id in ids {
+                let request = IpcRequest::StopProcess {
+                    project_name: name.clone(),
+                    process_id,
+                };
+                if let Ok(IpcResponse::Error(error)) = send_request(request).await {
+                    let _ = messages.send(error_message(error)).await;
+                    return;
+                }
+            }
+            let _ = messages.send(format!("Stopped {}", name)).await;
+        });
+    }
+
+    /// Restart a running project's processes, or start a stopped project
+    fn restart_selected(&mut self, messages: &mpsc::Sender<String>) {
+        let Some(project) = self.selected_project().cloned() else {
+            return;
+        };
+        let ids: Vec<_> = self.running(&project.name).iter().map(|p| p.id).collect();
+        let messages = messages.clone();
+
+        if ids.is_empty() {
+            self.message = Some(format!("Starting {}...", project.name));
+            tokio::spawn(async move {
+                let services: Vec<_> = project.services.keys().cloned().map(Some).collect();
+                let units = if services.is_empty() {
+                    vec![None]
+                } else {
+                    services
+                };
+                for service in units {
+                    if let Err(e) = start_unit(&project, service).await {
+                        let _ = messages.send(format!("{}: {}", project.name, e)).await;
+                        return;
+                    }
+                }
+                let _ = messages.send(format!("Started {}", project.name)).await;
+            });
+            return;
+        }
+
+        self.message = Some(format!("Restarting {}...", project.name));
+        tokio::spawn(async move {
+            for process_id in ids {
+                let request = IpcRequest::RestartProcess {
+                    project_name: project.name.clone(),
+                    process_id,
+                };
+                if let Ok(IpcResponse::Error(error)) = send_request(request).await {
+                    let _ = messages.send(error_message(error)).await;
+                    return;
+                }
+            }
+            let _ = messages.send(format!("Restarted {}", project.name)).await;
+        });
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [top, output, footer] = Layout::vertical([
+            Constraint::Percentage(40),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [projects, processes] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Fill(1)]).areas(top);
+
+        self.draw_projects(frame, projects);
+        self.draw_processes(frame, processes);
+        self.draw_output(frame, output);
+
+        let mut spans =
+            vec![
+                Span::raw(" ↑↓ select  s stop  r restart  o open  PgUp/PgDn scroll  q quit")
+                    .dark_gray(),
+            ];
+        if let Some(message) = &self.message {
+            spans.push(Span::raw(format!("   {}", message)).yellow());
+        }
+        frame.render_widget(Line::from(spans), footer);
+    }
+
+    fn draw_projects(&mut self, frame: &mut Frame, area: Rect) {
+        let rows: Vec<Row> = self
+            .projects
+            .iter()
+            .map(|project| {
+                let running = self.running(&project.name);
+                let (cpu, mem) = running
+                    .iter()
+                    .filter_map(|p| p.usage)
+                    .fold((0.0, 0), |(cpu, mem), usage| {
+                        (cpu + usage.cpu_percent, mem + usage.memory_bytes)
+                    });
+                let port = running
+                    .iter()
+                    .find_map(|p| p.port)
+                    .map(|port| format!(":{}", port))
+                    .unwrap_or_default();
+                let (icon, color) = if running.is_empty() {
+                    ("○", Color::DarkGray)
+                } else if running.iter().any(|p| p.status == ProcessStatus::Unhealthy) {
+                    ("●", Color::Yellow)
+                } else {
+                    ("●", Color::Green)
+                };
+                let usage = |s: String| if running.is_empty() { String::new() } else { s };
+                Row::new(vec![
+                    Line::from(vec![
+                        Span::raw(icon).fg(color),
+                        Span::raw(" "),
+                        Span::raw(project.appearance.label(&project.name))
+                            .bold()
+                            .fg(match project.appearance.rgb() {
+                                Some((r, g, b)) => Color::Rgb(r, g, b),
+                                None => Color::Reset,
+                            }),
+                    ]),
+                    Line::from(port),
+                    Line::from(usage(format!("{:.1}%", cpu))).right_aligned(),
+                    Line::from(usage(format_bytes(mem))).right_aligned(),
+                ])
+            })
+            .collect();
+
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Fill(1),
+                Constraint::Length(6),
+                Constraint::Length(7),
+                Constraint::Length(7),
+            ],
+        )
+        .header(Row::new(["PROJECT", "PORT", "CPU", "MEM"]).bold())
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+        .block(Block::bordered().title(" Projects "));
+        frame.render_stateful_widget(table, area, &mut self.selected);
+    }
+
+    fn draw_processes(&self, frame: &mut Frame, area: Rect) {
+        let name = self.selected_project().map(|p| p.name.as_str());
+        let mut processes: Vec<&ProcessInfo> = self
+            .processes
+            .iter()
+            .filter(|p| Some(p.project_name.as_str()) == name)
+            .collect();
+        // Running first, then the most recently started
+        processes.sort_by_key(|p| (!p.status.is_alive(), std::cmp::Reverse(p.started_at)));
+
+        let now = Utc::now();
+        let rows: Vec<Row> = processes
+            .iter()
+            .map(|p| {
+                let (status, color) = match p.status {
+                    ProcessStatus::Running => ("running", Color::Green),
+                    ProcessStatus::Unhealthy => ("unhealthy", Color::Yellow),
+                    ProcessStatus::Stopped => ("stopped", Color::DarkGray),
+                    ProcessStatus::Failed => ("failed", Color::Red),
+                };
+                let alive = p.status.is_alive();
+                let usage = p.usage.filter(|_| alive);
+                Row::new(vec![
+                    Line::from(p.service.clone().unwrap_or_else(|| "-".into())),
+                    Line::from(status).fg(color),
+                    Line::from(p.pid.to_string()).right_aligned(),
+                    Line::from(
+                        usage
+                            .map(|u| format!("{:.1}", u.cpu_percent))
+                            .unwrap_or_default(),
+                    )
+                    .right_aligned(),
+                    Line::from(
+                        usage
+                            .map(|u| format_bytes(u.memory_bytes))
+                            .unwrap_or_default(),
+                    )
+                    .right_aligned(),
+                    Line::from(if alive {
+                        format_uptime(now - p.started_at)
+                    } else {
+                        String::new()
+                    })
+                    .right_aligned(),
+                    Line::from(p.port.map(|port| port.to_string()).unwrap_or_default())
+                        .right_aligned(),
+                    Line::from(p.command.clone()),
+                ])
+            })
+            .collect();
+
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(10),
+                Constraint::Length(9),
+                Constraint::Length(7),
+                Constraint::Length(6),
+                Constraint::Length(7),
+                Constraint::Length(7),
+                Constraint::Length(6),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(
+            Row::new(vec![
+                Line::from("SERVICE"),
+                Line::from("STATUS"),
+                Line::from("PID").right_aligned(),
+                Line::from("CPU%").right_aligned(),
+                Line::from("MEM").right_aligned(),
+                Line::from("UPTIME").right_aligned(),
+                Line::from("PORT").right_aligned(),
+                Line::from("COMMAND"),
+            ])
+            .bold(),
+        )
+        .block(Block::bordered().title(format!(" Processes: {} ", name.unwrap_or("-"))));
+        frame.render_widget(table, area);
+    }
+
+    fn draw_output(&self, frame: &mut Frame, area: Rect) {
+        let height = area.height.saturating_sub(2) as usize;
+        let end = self.logs.len().saturating_sub(self.log_scroll);
+        let start = end.saturating_sub(height);
+        let lines: Vec<Line> = self
+            .logs
+            .range(start..end)
+            .map(|log| {
+                let mut spans = vec![Span::raw(
+                    log.timestamp
+                        .with_timezone(&chrono::Local)
+                        .format("%H:%M:%S ")
+                        .to_string(),
+                )
+                .dark_gray()];
+                if let Some(service) = &log.service {
+                    spans.push(Span::raw(format!("{} ", service)).cyan());
+                }
+                let line = Span::raw(log.line.clone());
+                spans.push(if log.is_stderr { line.red() } else { line });
+                Line::from(spans)
+            })
+            .collect();
+
+        let mut title = format!(" Output: {} ", self.logs_for.as_deref().unwrap_or("-"));
+        if self.log_scroll > 0 {
+            title.push_str("(scrolled, End to follow) ");
+        }
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(title)),
+            area,
+        );
+    }
+}
@@ -0,0 +1,34 @@
+//! How long reading the project index takes with a thousand projects stored,
+//! which is what the daemon reads of them at startup:
+//! `cargo bench -p proj-common --bench project_index`
+
+use proj_common::store::Store;
+use proj_common::Project;
+use std::time::Instant;
+
+const PROJECTS: usize = 1000;
+const READS: u32 = 20;
+
+fn main() {
+    let dir = std::env::temp_dir().join(format!("proj-bench-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let store = Store::open(&dir.join("proj.db")).unwrap();
+    for i in 0..PROJECTS {
+        let mut project = Project::new(format!("app-{}", i), dir.clone());
+        project.tags = vec!["client-x".to_string()];
+        project.aliases = vec![format!("app{}.test", i)];
+        project.proxy.routes.insert("/api".to_string(), 4000);
+        store.save_project(&project).unwrap();
+    }
+
+    let started = Instant::now();
+    for _ in 0..READS {
+        assert_eq!(store.project_index().unwrap().len(), PROJECTS);
+    }
+    println!(
+        "project_index, {} projects: {:?} per read",
+        PROJECTS,
+        started.elapsed() / READS
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,166 @@
+# Meldungen der CLI auf Deutsch. Fehlende Meldungen erscheinen auf Englisch.
+
+## Bezeichnungen
+
+error-label = Fehler:
+hint-label = Tipp:
+caused-by-label = Ursache:
+daemon-not-running = Daemon läuft nicht
+
+## Fehler des Daemons
+
+error-project-not-found = Projekt „{ $name }“ nicht gefunden
+error-project-exists = Projekt „{ $name }“ existiert bereits
+error-service-not-found = Dienst „{ $service }“ in „{ $project }“ nicht gefunden
+error-service-running = Dienst „{ $service }“ läuft bereits (PID { $pid })
+error-already-running = `{ $command }` läuft bereits (PID { $pid })
+error-process-not-found = Prozess { $id } nicht gefunden
+error-request-not-found = Anfrage { $id } für Projekt „{ $project }“ nicht gefunden
+error-spawn-failed = `{ $command }` konnte nicht gestartet werden
+error-port-conflict = Port { $port } ist bereits belegt
+error-port-conflict-pid = Port { $port } ist bereits von PID { $pid } belegt
+error-unauthorized = Der Daemon hat die Verbindung abgelehnt: Token falsch oder fehlt
+
+## Tipps nach Fehlern des Daemons
+
+hint-project-not-found = Projekte auflisten mit `proj ls`, oder dieses anlegen mit `proj new { $name }`
+hint-project-exists = Anderen Namen wählen, oder das vorhandene Projekt entfernen mit `proj rm { $name }`
+hint-service-not-found = Dienste auflisten mit `proj { $project } service ls`
+hint-service-running = Neu starten mit `proj <name> restart <service>`
+hint-already-running = Mit `--on-conflict attach` übernehmen, mit `--on-conflict restart` ersetzen, oder mit `--on-conflict allow` einen weiteren starten
+hint-process-not-found = Laufende Prozesse auflisten mit `proj ps`
+hint-request-not-found = Aufgezeichnete Anfragen auflisten mit `proj { $project } requests ls`
+hint-spawn-failed = Prüfen, ob `{ $command }` installiert ist und im PATH liegt, mit dem der Daemon gestartet wurde
+hint-unauthorized = tcp_token (oder PROJ_TCP_TOKEN) auf das Token des entfernten Daemons setzen
+hint-port-conflict = Mit `proj ports` nachsehen, wer Port { $port } belegt, oder mit `--port auto` einen wählen lassen
+
+## Von vielen Befehlen genutzt
+
+unexpected-response = Unerwartete Antwort vom Daemon
+aborted = Abgebrochen
+daemon-connect-failed = Keine Verbindung zum Daemon
+daemon-connect-failed-try = Keine Verbindung zum Daemon. Versuch: proj daemon -f
+not-in-project =
+    Kein Projektverzeichnis. Projektnamen angeben:
+
+    Aufruf: proj <project> <command>
+
+    Projekte auflisten: proj ls
+
+## proj new
+
+project-created = Projekt { $name } angelegt
+project-root = Verzeichnis: { $path }
+project-template = Vorlage: { $template } ({ $files ->
+        [one] 1 Datei
+       *[other] { $files } Dateien
+    })
+next-steps = Nächste Schritte:
+next-up = Dev-Server starten
+next-run = Einen Dev-Server starten
+next-open = Im eigenen Browserprofil öffnen
+
+## proj rm, proj <name> profile reset
+
+rm-confirm = Projekt „{ $name }“ löschen? [y/N]
+rm-confirm-data = Projekt „{ $name }“ löschen und { $path } in den Papierkorb verschieben? [y/N]
+project-deleted = Projekt { $name } gelöscht
+data-kept = Daten bleiben in { $path }
+restore-hint = Wiederherstellen mit: proj undo
+no-profile = { $project } hat kein { $browser }-Profil
+profile-reset-confirm = { $browser }-Profil von { $project } ({ $path }) in den Papierkorb verschieben? [y/N]
+profile-reset = { $browser }-Profil von { $project } zurückgesetzt (proj undo stellt es wieder her)
+browser-profile-open = Das { $browser }-Profil von { $project } ist in einem laufenden Browser geöffnet (PID { $pids })
+browser-close-first = Zuerst das { $browser }-Fenster mit dem Profil von { $project } schließen, oder mit -y proj schließen lassen
+browser-close-confirm = Schließen? [y/N]
+browser-closed = { $browser } geschlossen
+browser-close-timeout = { $browser } hat sich nicht innerhalb von { $seconds } Sekunden beendet
+browser-force-confirm = Sofort beenden? Ungesichertes in seinen Fenstern geht verloren. [y/N]
+browser-quit-failed = { $browser } mit dem Profil von { $project } (PID { $pids }) ließ sich nicht beenden; schließen und erneut versuchen
+browser-force-quit = { $browser } sofort beendet
+
+## proj <name> stop, proj <name> restart
+
+no-running-processes = Keine laufenden Prozesse für Projekt „{ $name }“
+process-stopped = { $name } { $id } beendet (PID: { $pid })
+process-stop-failed = Prozess { $id } konnte nicht beendet werden: { $error }
+no-such-process = Kein laufender Prozess { $id } in { $project } (siehe proj { $project } ps)
+ambiguous-process = Mehrere Prozesse beginnen mit { $id }; mehr von der ID angeben
+service-not-running = Dienst „{ $service }“ von { $project } läuft nicht
+not-running-starting = { $name } läuft nicht; wird gestartet
+restarting = { $name } wird neu gestartet (PID: { $pid })
+restarted = { $name } neu gestartet (PID: { $pid })
+restart-exited = { $name } hat sich direkt nach dem Neustart beendet (Code { $code })
+restart-timeout = { $name } ist nicht innerhalb von { $seconds } s zurückgekommen
+restart-failed = { $count ->
+        [one] 1 Prozess konnte nicht neu gestartet werden
+       *[other] { $count } Prozesse konnten nicht neu gestartet werden
+    }
+
+## proj daemon
+
+daemon-already-running = Daemon läuft bereits
+daemon-binary-not-found = Daemon-Programm nicht gefunden unter { $path }. Bauen mit: cargo build
+daemon-binary-missing = Daemon-Programm nicht gefunden. proj neu installieren oder ausführen: cargo build --release
+daemon-foreground = Daemon startet im Vordergrund (Strg+C beendet ihn)
+daemon-exited-with-error = Daemon mit Fehler beendet
+daemon-started = Daemon gestartet auf { $url }
+daemon-failed-to-start = Daemon konnte nicht gestartet werden. Versuch: proj daemon -f
+daemon-stop-confirm = { $count ->
+        [one] 1 verwalteten Prozess beenden? [Y/n]
+       *[other] { $count } verwaltete Prozesse beenden? [Y/n]
+    }
+daemon-stopping = Daemon wird beendet...
+daemon-stopping-keep = Daemon wird beendet, Prozesse laufen weiter...
+daemon-stopped = Daemon beendet
+daemon-stop-timeout = Daemon hat sich nicht innerhalb von { $seconds } s beendet
+
+## Hilfe (die englischen Texte stehen in den Doc-Kommentaren der CLI)
+
+help-about = Projektbezogene Verwaltung von Entwicklungsumgebungen
+help-usage = Aufruf
+help-commands = Befehle
+help-options = Optionen
+help-examples =
+    BEISPIELE:
+        proj new my-app              Neues Projekt anlegen
+        proj my-app run npm run dev  Dev-Server im Projektkontext starten
+        proj my-app open             Browser mit eigenem Profil öffnen
+        proj my-app stop             Prozesse des Projekts beenden
+        proj my-app                  Projektinfos anzeigen
+        proj ls                      Alle Projekte mit Status auflisten
+        proj rm my-app               Projekt löschen
+        proj up --all                Jedes Projekt mit seinem letzten Befehl starten
+        proj ports                   Wer lauscht, und auf wessen Projektport
+        proj trust                   Der lokalen HTTPS-Zertifizierungsstelle vertrauen
+        proj                         Statusübersicht des Daemons
+help-arg-config = Eine Einstellung für diesen Befehl überschreiben, z. B. -c up_concurrency=8 (wiederholbar)
+help-arg-host = Den Daemon auf einem anderen Rechner über seine `listen_tcp`-Adresse steuern, angemeldet mit `tcp_token` (oder PROJ_HOST setzen)
+help-arg-help = Hilfe anzeigen
+help-arg-version = Version anzeigen
+help-cmd-init = Das Repository des aktuellen Verzeichnisses als Projekt registrieren
+help-cmd-new = Neues Projekt anlegen (proj new <name>)
+help-cmd-rm = Projekt löschen und seine Prozesse beenden (proj rm <name>)
+help-cmd-list = Alle Projekte auflisten (Alias: ls)
+help-cmd-up = Dienste von Projekten starten, oder den zuletzt ausgeführten Befehl, nach den Projekten, von denen sie abhängen (proj up --all)
+help-cmd-down = Projekte beenden, jedes vor den Projekten, von denen es abhängt (proj down --all)
+help-cmd-migrate = Projekte von einem anderen Rechner übernehmen (proj migrate from ssh://old-laptop)
+help-cmd-export = Projekte, ihre gespeicherte Umgebung und proj.toml für einen anderen Rechner in ein Paket schreiben
+help-cmd-import = Die Projekte eines mit `proj export` geschriebenen Pakets neu anlegen
+help-cmd-migrate-data = Von älteren Versionen geschriebene Projektdateien ins aktuelle Format bringen
+help-cmd-daemon = Hintergrund-Daemon starten (proj daemon [stop|restart|reload|install|uninstall|status])
+help-cmd-status = Status des Daemons anzeigen
+help-cmd-selftest = Prozesse und Proxy-Verkehr in großer Zahl erzeugen und prüfen, dass der Daemon nichts zurückbehält (proj selftest --stress)
+help-cmd-trust = Die lokale HTTPS-Zertifizierungsstelle im System als vertrauenswürdig eintragen
+help-cmd-ports = Lauschende Ports auflisten und Konflikte mit Projektports markieren
+help-cmd-top = Vollbild-Übersicht über Projekte, Prozesse und laufende Ausgabe
+help-cmd-watch = Ereignisse des Daemons live verfolgen (proj watch [project] --json)
+help-cmd-prune = Logdateien und Einträge beendeter Prozesse löschen (proj prune [name])
+help-cmd-ps = CPU, Speicher und Laufzeit jedes laufenden Prozesses (proj ps --sort cpu|mem|uptime)
+help-cmd-schedule = Wartungsfenster geplanter Projekte anzeigen (proj schedule show)
+help-cmd-graph = Graph der Dienste, Abhängigkeiten und Routen aller Projekte ausgeben (proj graph --format dot|mermaid)
+help-cmd-completions = Skript für die Vervollständigung in der Shell ausgeben (proj completions bash > /etc/bash_completion.d/proj)
+help-cmd-config = Die wirksame Konfiguration anzeigen (proj config show --origin)
+help-cmd-profile-env = Gemeinsame Umgebungsprofile, die Projekte nutzen können (proj profile-env ls|edit)
+help-cmd-plugins = Plugins auflisten: `proj-<name>`-Programme im PATH laufen als `proj <name>`
+help-cmd-schema = JSON-Schemas für IPC-Nachrichten, Konfigurationsdateien und die HTTP-API ausgeben
@@ -0,0 +1,117 @@
+# Messages the CLI prints, in English. Every message the CLI looks up must be
+# here; other locales fall back to these for the ones they lack.
+
+## Labels
+
+error-label = Error:
+hint-label = Hint:
+caused-by-label = Caused by:
+daemon-not-running = Daemon not running
+
+## Daemon errors
+
+error-project-not-found = Project '{ $name }' not found
+error-project-exists = Project '{ $name }' already exists
+error-service-not-found = Service '{ $service }' not found in '{ $project }'
+error-service-running = Service '{ $service }' is already running (pid { $pid })
+error-already-running = `{ $command }` is already running (pid { $pid })
+error-process-not-found = Process { $id } not found
+error-request-not-found = Request { $id } not found for project '{ $project }'
+error-spawn-failed = Failed to start `{ $command }`
+error-port-conflict = Port { $port } is already in use
+error-port-conflict-pid = Port { $port } is already in use by pid { $pid }
+error-unauthorized = The daemon refused the connection: wrong or missing token
+
+## Hints after daemon errors
+
+hint-project-not-found = List projects with `proj ls`, or create this one with `proj new { $name }`
+hint-project-exists = Pick another name, or remove the existing project with `proj rm { $name }`
+hint-service-not-found = List its services with `proj { $project } service ls`
+hint-service-running = Restart it with `proj <name> restart <service>`
+hint-already-running = Use it with `--on-conflict attach`, replace it with `--on-conflict restart`, or start another with `--on-conflict allow`
+hint-process-not-found = List running processes with `proj ps`
+hint-request-not-found = List captured requests with `proj { $project } requests ls`
+hint-spawn-failed = Check that `{ $command }` is installed and on the PATH the daemon started with
+hint-unauthorized = Set tcp_token (or PROJ_TCP_TOKEN) to the remote daemon's token
+hint-port-conflict = See what holds port { $port } with `proj ports`, or let proj pick one with `--port auto`
+
+## Shared by many commands
+
+unexpected-response = Unexpected response from daemon
+aborted = Aborted
+daemon-connect-failed = Failed to connect to daemon
+daemon-connect-failed-try = Failed to connect to daemon. Try: proj daemon -f
+not-in-project =
+    Not in a project directory. Specify project name:
+
+    Usage: proj <project> <command>
+
+    List projects: proj ls
+
+## proj new
+
+project-created = Created project { $name }
+project-root = Root: { $path }
+project-template = Template: { $template } ({ $files ->
+        [one] 1 file
+       *[other] { $files } files
+    })
+next-steps = Next steps:
+next-up = Start the dev server
+next-run = Start a dev server
+next-open = Open in isolated browser
+
+## proj rm, proj <name> profile reset
+
+rm-confirm = Delete project '{ $name }'? [y/N]
+rm-confirm-data = Delete project '{ $name }' and move { $path } to the trash? [y/N]
+project-deleted = Deleted project { $name }
+data-kept = Data kept in { $path }
+restore-hint = Restore it with: proj undo
+no-profile = { $project } has no { $browser } profile
+profile-reset-confirm = Move { $project }'s { $browser } profile ({ $path }) to the trash? [y/N]
+profile-reset = Reset { $project }'s { $browser } profile (proj undo restores it)
+browser-profile-open = { $project }'s { $browser } profile is open in a running browser (PID { $pids })
+browser-close-first = Close the { $browser } window using { $project }'s profile first, or pass -y to have proj close it
+browser-close-confirm = Close it? [y/N]
+browser-closed = Closed { $browser }
+browser-close-timeout = { $browser } didn't close within { $seconds } seconds
+browser-force-confirm = Force-quit it? Anything unsaved in its windows is lost. [y/N]
+browser-quit-failed = Couldn't quit the { $browser } using { $project }'s profile (PID { $pids }); close it and try again
+browser-force-quit = Force-quit { $browser }
+
+## proj <name> stop, proj <name> restart
+
+no-running-processes = No running processes for project '{ $name }'
+process-stopped = Stopped { $name } { $id } (PID: { $pid })
+process-stop-failed = Failed to stop process { $id }: { $error }
+no-such-process = No running process { $id } in { $project } (see proj { $project } ps)
+ambiguous-process = Several processes start with { $id }; give more of the ID
+service-not-running = Service '{ $service }' of { $project } is not running
+not-running-starting = { $name } is not running; starting it
+restarting = Restarting { $name } (PID: { $pid })
+restarted = Restarted { $name } (PID: { $pid })
+restart-exited = { $name } exited right after restarting (code { $code })
+restart-timeout = { $name } did not come back within { $seconds }s
+restart-failed = { $count ->
+        [one] 1 process failed to restart
+       *[other] { $count } processes failed to restart
+    }
+
+## proj daemon
+
+daemon-already-running = Daemon already running
+daemon-binary-not-found = Daemon binary not found at { $path }. Build with: cargo build
+daemon-binary-missing = Daemon binary not found. Please reinstall proj or run: cargo build --release
+daemon-foreground = Starting daemon in foreground (Ctrl+C to stop)
+daemon-exited-with-error = Daemon exited with error
+daemon-started = Daemon started on { $url }
+daemon-failed-to-start = Daemon failed to start. Try: proj daemon -f
+daemon-stop-confirm = { $count ->
+        [one] Stop 1 managed process? [Y/n]
+       *[other] Stop { $count } managed processes? [Y/n]
+    }
+daemon-stopping = Stopping daemon...
+daemon-stopping-keep = Stopping daemon, leaving processes running...
+daemon-stopped = Daemon stopped
+daemon-stop-timeout = Daemon did not stop within { $seconds }s
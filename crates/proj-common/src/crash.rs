@@ -0,0 +1,485 @@
+//! Crash bundles: what a failed process left behind, kept under
+//! `~/.proj/projects/<name>/crashes/`
+//!
+//! A bundle is a directory named after the crash time and the process's short
+//! ID, holding its recent output (`output.log`), the stack trace found at the
+//! end of it (`trace.txt`) and a summary (`crash.json`) with the source line
+//! the trace points at. Quick actions work on a bundle: copy the trace to the
+//! clipboard, open that source line in the editor, or open the bundle's
+//! directory. The daemon runs those listed in the `crash_actions` setting as
+//! soon as it writes a bundle; `proj <name> crash` runs them on demand.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use uuid::Uuid;
+
+use crate::{project_dir, ProcessInfo};
+
+/// Bundles kept per project; writing one removes the oldest beyond this
+const MAX_BUNDLES: usize = 20;
+
+/// Lines of a stack trace kept
+const MAX_TRACE_LINES: usize = 200;
+
+/// Trailing lines kept as the trace when no stack trace is recognized
+const FALLBACK_TRACE_LINES: usize = 20;
+
+const OUTPUT_FILE: &str = "output.log";
+const TRACE_FILE: &str = "trace.txt";
+const INFO_FILE: &str = "crash.json";
+
+/// Quick action on a crash bundle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CrashAction {
+    /// Copy the stack trace to the clipboard
+    Copy,
+    /// Open the source line the trace points at in `$VISUAL` or `$EDITOR`
+    Edit,
+    /// Open the bundle's directory
+    Open,
+}
+
+impl CrashAction {
+    pub const NAMES: [&'static str; 3] = ["copy", "edit", "open"];
+}
+
+impl std::str::FromStr for CrashAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "copy" => Ok(Self::Copy),
+            "edit" => Ok(Self::Edit),
+            "open" => Ok(Self::Open),
+            other => anyhow::bail!(
+                "Unknown crash action '{}' (expected {})",
+                other,
+                Self::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+impl fmt::Display for CrashAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Copy => "copy",
+            Self::Edit => "edit",
+            Self::Open => "open",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Source line a stack trace points at
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SourceLocation {
+    pub file: PathBuf,
+    pub line: u32,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file.display(), self.line)
+    }
+}
+
+/// Summary of a crash, stored in its bundle
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CrashInfo {
+    pub process_id: Uuid,
+    pub command: String,
+    #[serde(default)]
+    pub service: Option<String>,
+    pub exit_code: Option<i32>,
+    pub started_at: DateTime<Utc>,
+    pub crashed_at: DateTime<Utc>,
+    #[serde(default)]
+    pub location: Option<SourceLocation>,
+}
+
+/// A crash bundle on disk
+#[derive(Debug, Clone)]
+pub struct CrashBundle {
+    pub dir: PathBuf,
+    pub info: CrashInfo,
+}
+
+impl CrashBundle {
+    /// Name of the bundle's directory, `<YYYYmmdd-HHMMSS>-<short id>`
+    pub fn name(&self) -> String {
+        self.dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    /// The stack trace found in the process's output
+    pub fn trace(&self) -> String {
+        std::fs::read_to_string(self.dir.join(TRACE_FILE)).unwrap_or_default()
+    }
+
+    /// Run a quick action on the bundle
+    pub fn run(&self, action: CrashAction) -> Result<()> {
+        match action {
+            CrashAction::Copy => {
+                let trace = self.trace();
+                if trace.trim().is_empty() {
+                    anyhow::bail!("The crash left no output to copy");
+                }
+                copy_to_clipboard(&trace)
+            }
+            CrashAction::Edit => match &self.info.location {
+                Some(location) => open_in_editor(location),
+                None => anyhow::bail!("The stack trace points at no file that exists"),
+            },
+            CrashAction::Open => open_path(&self.dir),
+        }
+    }
+}
+
+/// Directory holding a project's crash bundles
+pub fn crashes_dir(project_name: &str) -> Result<PathBuf> {
+    Ok(project_dir(project_name)?.join("crashes"))
+}
+
+/// Write a bundle for a failed process from its output, oldest line first.
+/// Relative paths in the stack trace are looked up under `root`.
+pub fn write_bundle(process: &ProcessInfo, output: &[String], root: &Path) -> Result<CrashBundle> {
+    let crashed_at = Utc::now();
+    let dir = crashes_dir(&process.project_name)?.join(format!(
+        "{}-{}",
+        crashed_at.format("%Y%m%d-%H%M%S"),
+        process.short_id()
+    ));
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let trace = stack_trace(output);
+    let info = CrashInfo {
+        process_id: process.id,
+        command: process.command.clone(),
+        service: process.service.clone(),
+        exit_code: process.last_exit_code,
+        started_at: process.started_at,
+        crashed_at,
+        location: source_location(&trace, root),
+    };
+    let mut log = output.join("\n");
+    log.push('\n');
+    std::fs::write(dir.join(OUTPUT_FILE), log).context("Failed to write crash output")?;
+    std::fs::write(dir.join(TRACE_FILE), trace + "\n").context("Failed to write stack trace")?;
+    std::fs::write(
+        dir.join(INFO_FILE),
+        serde_json::to_string_pretty(&info)? + "\n",
+    )
+    .context("Failed to write crash summary")?;
+
+    // One that can't be removed now is tried again after the next crash
+    for old in list(&process.project_name)?.into_iter().skip(MAX_BUNDLES) {
+        let _ = std::fs::remove_dir_all(&old.dir);
+    }
+    Ok(CrashBundle { dir, info })
+}
+
+/// A project's crash bundles, newest first; unreadable ones are skipped
+pub fn list(project_name: &str) -> Result<Vec<CrashBundle>> {
+    let Ok(entries) = std::fs::read_dir(crashes_dir(project_name)?) else {
+        return Ok(Vec::new());
+    };
+    let mut bundles: Vec<CrashBundle> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let dir = entry.path();
+            let content = std::fs::read_to_string(dir.join(INFO_FILE)).ok()?;
+            let info = serde_json::from_str(&content).ok()?;
+            Some(CrashBundle { dir, info })
+        })
+        .collect();
+    bundles.sort_by_key(|bundle| std::cmp::Reverse(bundle.info.crashed_at));
+    Ok(bundles)
+}
+
+/// The last stack trace in a process's output: from the last line starting
+/// one (a Python traceback, a Rust or Go panic, a Java exception, or the
+/// error line above a block of JavaScript `at` frames) to the end. Without
+/// one, the last few lines.
+pub fn stack_trace(lines: &[String]) -> String {
+    let starts_trace = |i: usize| {
+        let line = lines[i].trim_start();
+        let above_frames = !line.starts_with("at ")
+            && lines
+                .get(i + 1)
+                .is_some_and(|next| next.trim_start().starts_with("at "));
+        line.starts_with("Traceback (most recent call last)")
+            || line.contains("panicked at")
+            || line.starts_with("panic: ")
+            || line.starts_with("Exception in thread")
+            || above_frames
+    };
+    let start = (0..lines.len())
+        .rev()
+        .find(|&i| starts_trace(i))
+        .unwrap_or(lines.len().saturating_sub(FALLBACK_TRACE_LINES));
+    lines[start..]
+        .iter()
+        .take(MAX_TRACE_LINES)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The source line a stack trace points at: the innermost frame in a file
+/// under `root` outside dependency directories, else the innermost in any
+/// file that exists. Python lists the innermost frame last.
+pub fn source_location(trace: &str, root: &Path) -> Option<SourceLocation> {
+    let mut frames: Vec<SourceLocation> = trace
+        .lines()
+        .filter_map(|line| frame_location(line, root))
+        .collect();
+    if trace.trim_start().starts_with("Traceback") {
+        frames.reverse();
+    }
+    let in_project = |location: &&SourceLocation| {
+        location.file.starts_with(root)
+            && !location.file.components().any(|c| {
+                matches!(
+                    c.as_os_str().to_str(),
+                    Some("node_modules" | "site-packages" | "vendor" | "target")
+                )
+            })
+    };
+    frames.iter().find(in_project).or(frames.first()).cloned()
+}
+
+/// A file reference in one line of a stack trace: Python's
+/// `File "<file>", line <n>`, or a word like `src/main.rs:10:5`
+fn frame_location(line: &str, root: &Path) -> Option<SourceLocation> {
+    let existing = |file: &str, line: u32| {
+        let path = root.join(file.strip_prefix("file://").unwrap_or(file));
+        (line > 0 && path.is_file()).then_some(SourceLocation { file: path, line })
+    };
+    let leading_number = |s: &str| -> Option<u32> {
+        let digits: String = s.chars().take_while(char::is_ascii_digit).collect();
+        digits.parse().ok()
+    };
+
+    if let Some(rest) = line.trim_start().strip_prefix("File \"") {
+        let (file, rest) = rest.split_once('"')?;
+        let number = rest.trim_start_matches(',').trim().strip_prefix("line ")?;
+        return existing(file, leading_number(number)?);
+    }
+
+    line.split(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | '\'' | '"'))
+        .find_map(|word| {
+            // The first colon followed by a number ends the file, which
+            // skips Windows drive letters
+            word.match_indices(':').find_map(|(i, _)| {
+                let number = leading_number(&word[i + 1..])?;
+                existing(&word[..i], number)
+            })
+        })
+}
+
+/// Put `text` on the system clipboard
+pub fn copy_to_clipboard(text: &str) -> Result<()> {
+    use std::io::Write;
+
+    let tools: &[&[&str]] = if cfg!(target_os = "macos") {
+        &[&["pbcopy"]]
+    } else if cfg!(windows) {
+        &[&["clip"]]
+    } else {
+        &[
+            &["wl-copy"],
+            &["xclip", "-selection", "clipboard"],
+            &["xsel", "--clipboard", "--input"],
+        ]
+    };
+    for tool in tools {
+        let Ok(mut child) = Command::new(tool[0])
+            .args(&tool[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        else {
+            continue;
+        };
+        // Closing stdin lets the tool take the text
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        if child.wait()?.success() {
+            return Ok(());
+        }
+    }
+    anyhow::bail!(
+        "No clipboard tool worked (tried {})",
+        tools
+            .iter()
+            .map(|tool| tool[0])
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+/// Open a source line in `$VISUAL` or `$EDITOR`, or the file in its default
+/// app when neither is set. Waits for terminal editors to exit.
+pub fn open_in_editor(location: &SourceLocation) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty());
+    let Some(editor) = editor else {
+        return open_path(&location.file);
+    };
+
+    // Editors are often given with flags, e.g. `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words.next().context("$EDITOR is empty")?;
+    let status = Command::new(program)
+        .args(words)
+        .args(editor_args(program, location))
+        .status()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !status.success() {
+        anyhow::bail!("{} exited with {}", program, status);
+    }
+    Ok(())
+}
+
+/// Arguments opening a file at a line, in the form the editor understands:
+/// `--goto file:line` for VS Code and its forks, `file:line` for Sublime
+/// Text, Zed and Helix, and `+line file` for the rest (vi, Emacs, nano, ...)
+fn editor_args(program: &str, location: &SourceLocation) -> Vec<String> {
+    let file = location.file.display().to_string();
+    let name = Path::new(program)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    match name.as_str() {
+        "code" | "code-insiders" | "codium" | "cursor" | "windsurf" => {
+            vec!["--goto".to_string(), location.to_string()]
+        }
+        "subl" | "zed" | "hx" | "helix" => vec![location.to_string()],
+        _ => vec![format!("+{}", location.line), file],
+    }
+}
+
+/// Open a file or directory with the system's default app
+pub fn open_path(path: &Path) -> Result<()> {
+    let program = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(windows) {
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+    let status = Command::new(program)
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to run {}", program))?;
+    // explorer exits with 1 even when it opened the path
+    if !status.success() && !cfg!(windows) {
+        anyhow::bail!("{} couldn't open {}", program, path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_stack_trace() {
+        let python = lines(
+            "Serving on 4000\n\
+             Traceback (most recent call last):\n  \
+             File \"app.py\", line 3, in <module>\n    \
+             main()\n\
+             ValueError: boom",
+        );
+        assert!(stack_trace(&python).starts_with("Traceback"));
+        assert!(stack_trace(&python).ends_with("ValueError: boom"));
+
+        let node = lines(
+            "listening\n\
+             TypeError: x is not a function\n    \
+             at run (/app/index.js:3:9)\n    \
+             at Object.<anonymous> (/app/index.js:5:1)",
+        );
+        assert!(stack_trace(&node).starts_with("TypeError"));
+
+        let rust = lines("ok\nthread 'main' panicked at src/main.rs:2:5:\nboom");
+        assert!(stack_trace(&rust).starts_with("thread 'main'"));
+
+        let plain: Vec<String> = (0..30).map(|n| n.to_string()).collect();
+        assert_eq!(stack_trace(&plain).lines().count(), FALLBACK_TRACE_LINES);
+        assert_eq!(stack_trace(&[]), "");
+    }
+
+    #[test]
+    fn test_source_location() {
+        let root = std::env::temp_dir().join(format!("proj-crash-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("node_modules/dep")).unwrap();
+        for file in [
+            "app.py",
+            "lib.py",
+            "src/main.rs",
+            "index.js",
+            "node_modules/dep/x.js",
+        ] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+
+        let python = "Traceback (most recent call last):\n  \
+                      File \"app.py\", line 3, in <module>\n  \
+                      File \"lib.py\", line 7, in run\n\
+                      ValueError: boom";
+        let location = source_location(python, &root).unwrap();
+        assert_eq!((location.file, location.line), (root.join("lib.py"), 7));
+
+        let rust = "thread 'main' panicked at src/main.rs:2:5:\nboom";
+        let location = source_location(rust, &root).unwrap();
+        assert_eq!(
+            location.to_string(),
+            format!("{}:2", root.join("src/main.rs").display())
+        );
+
+        // Frames in dependencies are passed over for the project's own
+        let node = format!(
+            "TypeError: x\n    at f ({}:1:1)\n    at g (index.js:12:3)\n    at missing.js:1:1",
+            root.join("node_modules/dep/x.js").display()
+        );
+        let location = source_location(&node, &root).unwrap();
+        assert_eq!((location.file, location.line), (root.join("index.js"), 12));
+
+        assert_eq!(source_location("Error: no frames", &root), None);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_editor_args() {
+        let location = SourceLocation {
+            file: PathBuf::from("/app/index.js"),
+            line: 12,
+        };
+        assert_eq!(
+            editor_args("code", &location),
+            ["--goto", "/app/index.js:12"]
+        );
+        assert_eq!(editor_args("/usr/bin/zed", &location), ["/app/index.js:12"]);
+        assert_eq!(editor_args("nvim", &location), ["+12", "/app/index.js"]);
+    }
+}
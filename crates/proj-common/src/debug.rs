@@ -0,0 +1,226 @@
+//! Debug runs - starting a service under its stack's debugger (`proj <name> debug`)
+//!
+//! The stack is told from the service's command. Node gets `--inspect` through
+//! `NODE_OPTIONS`, Python runs under debugpy, and native programs (Rust,
+//! through `cargo run` or a built binary) run under `lldb-server` with
+//! `RUST_BACKTRACE` on. The debugger listens on the port named `debug`.
+
+use crate::Service;
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// Name of the port a debugger listens on, in [`crate::ProcessInfo::named_ports`]
+pub const DEBUG_PORT_NAME: &str = "debug";
+
+/// Launchers that run a Node program
+const NODE_PROGRAMS: [&str; 9] = [
+    "node", "npm", "npx", "yarn", "pnpm", "nodemon", "ts-node", "tsx", "next",
+];
+
+/// Python tools that debugpy can run as modules
+const PYTHON_MODULES: [&str; 4] = ["uvicorn", "gunicorn", "flask", "pytest"];
+
+/// A runtime proj knows how to start under a debugger
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stack {
+    Node,
+    Python,
+    Native,
+}
+
+impl Stack {
+    /// The stack a command runs on, from its program
+    pub fn detect(command: &[String]) -> Option<Self> {
+        let program = command.first()?;
+        let name = Path::new(program).file_name()?.to_str()?;
+        if NODE_PROGRAMS.contains(&name) {
+            Some(Self::Node)
+        } else if is_python(name) || PYTHON_MODULES.contains(&name) {
+            Some(Self::Python)
+        } else if name == "cargo" || program.contains('/') {
+            Some(Self::Native)
+        } else {
+            None
+        }
+    }
+
+    /// The stack a command runs on, or why it can't be debugged
+    pub fn of(command: &[String]) -> Result<Self> {
+        match Self::detect(command) {
+            Some(stack) => Ok(stack),
+            None => anyhow::bail!(
+                "Can't tell what `{}` runs on: proj knows Node, Python and native (Rust) commands",
+                command.join(" ")
+            ),
+        }
+    }
+
+    /// Port the stack's debugger usually listens on
+    pub fn default_port(self) -> u16 {
+        match self {
+            Self::Node => 9229,
+            Self::Python => 5678,
+            Self::Native => 2345,
+        }
+    }
+
+    /// VS Code configuration attaching to a debugger listening on `port`
+    pub fn vscode_config(self, name: &str, port: u16) -> Value {
+        match self {
+            Self::Node => json!({
+                "name": name,
+                "type": "node",
+                "request": "attach",
+                "address": "127.0.0.1",
+                "port": port,
+                "restart": true,
+            }),
+            Self::Python => json!({
+                "name": name,
+                "type": "debugpy",
+                "request": "attach",
+                "connect": { "host": "127.0.0.1", "port": port },
+            }),
+            // CodeLLDB
+            Self::Native => json!({
+                "name": name,
+                "type": "lldb",
+                "request": "custom",
+                "processCreateCommands": [format!("gdb-remote 127.0.0.1:{}", port)],
+            }),
+        }
+    }
+}
+
+/// Rewrite a service to start under `stack`'s debugger, listening on `port`
+pub fn instrument(service: &mut Service, stack: Stack, port: u16) {
+    let listen = format!("127.0.0.1:{}", port);
+
+    match stack {
+        Stack::Node => {
+            let inspect = format!("--inspect={}", listen);
+            let options = match service.env.get("NODE_OPTIONS") {
+                Some(options) => format!("{} {}", options, inspect),
+                None => inspect,
+            };
+            service.env.insert("NODE_OPTIONS".to_string(), options);
+        }
+        Stack::Python => {
+            run_python_under(
+                &mut service.command,
+                &["-m", "debugpy", "--listen", &listen],
+            );
+        }
+        Stack::Native => {
+            run_under(
+                &mut service.command,
+                &["lldb-server", "gdbserver", &listen, "--"],
+            );
+            service
+                .env
+                .insert("RUST_BACKTRACE".to_string(), "1".to_string());
+        }
+    }
+    service.ports.insert(DEBUG_PORT_NAME.to_string(), port);
+}
+
+/// Run a native command's program under `runner` (a tool and its arguments).
+/// `cargo` itself isn't wrapped: it builds, then hands the binary to the runner.
+pub(crate) fn run_under(command: &mut Vec<String>, runner: &[&str]) {
+    if program_name(&command[0]) == "cargo" {
+        let runner: Vec<String> = runner.iter().map(|arg| format!("{:?}", arg)).collect();
+        let config = format!("target.'cfg(all())'.runner=[{}]", runner.join(","));
+        command.splice(1..1, ["--config".to_string(), config]);
+    } else {
+        command.splice(0..0, runner.iter().map(|arg| arg.to_string()));
+    }
+}
+
+/// Run a Python command under a tool given as interpreter arguments (e.g. `-m
+/// debugpy`). Tools like uvicorn are run as modules of the default Python.
+pub(crate) fn run_python_under(command: &mut Vec<String>, tool: &[&str]) {
+    let (program, args) = command.split_first().expect("not empty");
+    let name = program_name(program);
+    let mut wrapped = if is_python(name) {
+        vec![program.clone()]
+    } else {
+        vec!["python3".to_string()]
+    };
+    wrapped.extend(tool.iter().map(|arg| arg.to_string()));
+    if !is_python(name) {
+        wrapped.extend(["-m", name].map(str::to_string));
+    }
+    wrapped.extend(args.iter().cloned());
+    *command = wrapped;
+}
+
+fn program_name(program: &str) -> &str {
+    Path::new(program)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(program)
+}
+
+/// `python`, `python3`, `python3.12` and the like
+fn is_python(name: &str) -> bool {
+    name.strip_prefix("python")
+        .is_some_and(|version| version.chars().all(|c| c.is_ascii_digit() || c == '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(command: &str) -> Service {
+        serde_json::from_value(json!({
+            "command": command.split_whitespace().collect::<Vec<_>>(),
+        }))
+        .unwrap()
+    }
+
+    /// `command` as a service started under its debugger
+    fn instrumented(command: &str, port: u16) -> (Stack, Service) {
+        let mut service = service(command);
+        let stack = Stack::of(&service.command).unwrap();
+        instrument(&mut service, stack, port);
+        (stack, service)
+    }
+
+    #[test]
+    fn test_instrument() {
+        let (stack, node) = instrumented("npm run dev", 9229);
+        assert_eq!(stack, Stack::Node);
+        assert_eq!(node.env["NODE_OPTIONS"], "--inspect=127.0.0.1:9229");
+        assert_eq!(node.ports[DEBUG_PORT_NAME], 9229);
+
+        let (stack, script) = instrumented("python3.12 app.py", 5678);
+        assert_eq!(stack, Stack::Python);
+        assert_eq!(
+            script.command.join(" "),
+            "python3.12 -m debugpy --listen 127.0.0.1:5678 app.py"
+        );
+        let (_, server) = instrumented("uvicorn main:app --reload", 5678);
+        assert_eq!(
+            server.command.join(" "),
+            "python3 -m debugpy --listen 127.0.0.1:5678 -m uvicorn main:app --reload"
+        );
+
+        let (stack, cargo) = instrumented("cargo run --bin api", 2345);
+        assert_eq!(stack, Stack::Native);
+        assert_eq!(cargo.command[1], "--config");
+        assert_eq!(
+            cargo.command[2],
+            "target.'cfg(all())'.runner=[\"lldb-server\",\"gdbserver\",\"127.0.0.1:2345\",\"--\"]"
+        );
+        assert_eq!(cargo.command[3..], ["run", "--bin", "api"]);
+        assert_eq!(cargo.env["RUST_BACKTRACE"], "1");
+        let (_, binary) = instrumented("./target/debug/api", 2345);
+        assert_eq!(
+            binary.command.join(" "),
+            "lldb-server gdbserver 127.0.0.1:2345 -- ./target/debug/api"
+        );
+
+        assert!(Stack::of(&service("make serve").command).is_err());
+    }
+}
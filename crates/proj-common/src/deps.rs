@@ -0,0 +1,102 @@
+//! Start order of projects that depend on each other (`depends_on`)
+
+use crate::Project;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+
+/// `names` and every project they depend on, directly or not, grouped into
+/// waves: a project only depends on projects in earlier waves, so each wave
+/// can start once the one before it is up. Fails on unknown projects and on
+/// dependency cycles.
+pub fn start_order(projects: &[Project], names: &[String]) -> Result<Vec<Vec<String>>> {
+    let projects: BTreeMap<&str, &Project> =
+        projects.iter().map(|p| (p.name.as_str(), p)).collect();
+    let mut depths = BTreeMap::new();
+    for name in names {
+        depth(name, &projects, &mut depths, &mut Vec::new())?;
+    }
+
+    let mut waves = vec![Vec::new(); depths.values().max().map_or(0, |d| d + 1)];
+    for (name, depth) in depths {
+        waves[depth].push(name);
+    }
+    Ok(waves)
+}
+
+/// Wave of `name`: the one after its latest dependency's. `path` is the
+/// chain of dependents being resolved, to report cycles.
+fn depth(
+    name: &str,
+    projects: &BTreeMap<&str, &Project>,
+    depths: &mut BTreeMap<String, usize>,
+    path: &mut Vec<String>,
+) -> Result<usize> {
+    if let Some(depth) = depths.get(name) {
+        return Ok(*depth);
+    }
+    if let Some(start) = path.iter().position(|p| p == name) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(name.to_string());
+        anyhow::bail!("Dependency cycle: {}", cycle.join(" -> "));
+    }
+    let project = projects.get(name).with_context(|| match path.last() {
+        Some(dependent) => format!(
+            "'{}' depends on '{}', which is not a project",
+            dependent, name
+        ),
+        None => format!("Project '{}' not found", name),
+    })?;
+
+    path.push(name.to_string());
+    let mut depth = 0;
+    for dependency in &project.depends_on {
+        depth = depth.max(self::depth(dependency, projects, depths, path)? + 1);
+    }
+    path.pop();
+
+    depths.insert(name.to_string(), depth);
+    Ok(depth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(name: &str, depends_on: &[&str]) -> Project {
+        let mut project = Project::new(name.to_string(), std::env::temp_dir());
+        project.depends_on = depends_on.iter().map(|d| d.to_string()).collect();
+        project
+    }
+
+    #[test]
+    fn test_start_order() {
+        let mut projects = vec![
+            project("db", &[]),
+            project("cache", &[]),
+            project("api", &["db", "cache"]),
+            project("web", &["api"]),
+            project("admin", &["api", "db"]),
+            project("docs", &[]),
+        ];
+        let order = start_order(&projects, &["web".into(), "admin".into()]).unwrap();
+        assert_eq!(
+            order,
+            vec![vec!["cache", "db"], vec!["api"], vec!["admin", "web"]]
+        );
+
+        projects[1].depends_on = vec!["web".into()];
+        let message = start_order(&projects, &["web".into()])
+            .unwrap_err()
+            .to_string();
+        assert_eq!(message, "Dependency cycle: web -> api -> cache -> web");
+
+        projects[1].depends_on = vec!["redis".into()];
+        let message = start_order(&projects, &["api".into()])
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            message,
+            "'cache' depends on 'redis', which is not a project"
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! `.env` files: `KEY=VALUE` lines, `#` comments, an optional `export`
+//! prefix, and single- or double-quoted values (the latter with `\n`
+//! escapes). Projects' own `.env` files are read leniently; the files
+//! `proj <name> env edit` opens are checked strictly.
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+use crate::naming;
+
+/// Shown instead of a secret's value
+pub const MASK: &str = "********";
+
+/// Words in a variable's name that make its value a secret
+const SECRET_WORDS: [&str; 10] = [
+    "AUTH",
+    "CREDENTIAL",
+    "CREDENTIALS",
+    "KEY",
+    "PASS",
+    "PASSWD",
+    "PASSWORD",
+    "PRIVATE",
+    "SECRET",
+    "TOKEN",
+];
+
+/// Variables in `.env` contents, skipping lines that aren't assignments
+pub fn parse(content: &str) -> Vec<(String, String)> {
+    content
+        .lines()
+        .filter_map(parse_line)
+        .filter_map(Result::ok)
+        .collect()
+}
+
+/// Variables in `.env` contents where every line must be blank, a comment or
+/// an assignment to a valid name not assigned before; the errors name the
+/// offending lines
+pub fn parse_strict(content: &str) -> Result<BTreeMap<String, String>> {
+    let mut vars = BTreeMap::new();
+    let mut errors = Vec::new();
+    for (number, line) in content.lines().enumerate().map(|(i, line)| (i + 1, line)) {
+        match parse_line(line) {
+            None => {}
+            Some(Err(e)) => errors.push(format!("line {}: {}", number, e)),
+            Some(Ok((key, value))) => {
+                if let Err(e) = naming::validate_env_name(&key) {
+                    errors.push(format!("line {}: {}", number, e));
+                } else if vars.insert(key.clone(), value).is_some() {
+                    errors.push(format!("line {}: {} is set more than once", number, key));
+                }
+            }
+        }
+    }
+    if !errors.is_empty() {
+        anyhow::bail!("{}", errors.join("\n"));
+    }
+    Ok(vars)
+}
+
+/// A line's assignment; `None` for blank and comment lines
+fn parse_line(line: &str) -> Option<Result<(String, String), String>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let line = line.strip_prefix("export ").unwrap_or(line);
+    let Some((key, value)) = line.split_once('=') else {
+        return Some(Err(format!("expected KEY=VALUE, got '{}'", line)));
+    };
+    let key = key.trim();
+    if key.is_empty() {
+        return Some(Err(format!("no variable name in '{}'", line)));
+    }
+
+    let value = value.trim();
+    let value = if let Some(inner) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        inner.replace("\\n", "\n").replace("\\\"", "\"")
+    } else if let Some(inner) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+        inner.to_string()
+    } else {
+        // Unquoted values may carry a trailing comment
+        match value.find(" #") {
+            Some(i) => value[..i].trim_end().to_string(),
+            None => value.to_string(),
+        }
+    };
+    Some(Ok((key.to_string(), value)))
+}
+
+/// A `KEY=VALUE` line that parses back to the same value, quoted only when
+/// it has to be
+pub fn line(key: &str, value: &str) -> String {
+    let plain = value == value.trim()
+        && !value.contains(" #")
+        && !value.contains('\n')
+        && !value.starts_with(['"', '\'']);
+    if plain {
+        format!("{}={}", key, value)
+    } else if !value.contains(['\'', '\n']) {
+        format!("{}='{}'", key, value)
+    } else {
+        let escaped = value.replace('"', "\\\"").replace('\n', "\\n");
+        format!("{}=\"{}\"", key, escaped)
+    }
+}
+
+/// Whether a variable looks like it holds a secret: its name has a word like
+/// `TOKEN` or `PASSWORD` in it, or its value is a URL with a password
+pub fn is_secret(key: &str, value: &str) -> bool {
+    let key = key.to_ascii_uppercase();
+    key.split('_').any(|word| SECRET_WORDS.contains(&word))
+        || value
+            .split_once("://")
+            .and_then(|(_, rest)| rest.split(['/', '?', '#']).next())
+            .and_then(|authority| authority.rsplit_once('@'))
+            .is_some_and(|(userinfo, _)| userinfo.contains(':'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let content = r#"
+# database
+DATABASE_URL=postgres://localhost/dev
+export API_KEY = secret # not part of the value
+GREETING="hello\nworld"
+RAW='$HOME stays literal'
+BROKEN
+"#;
+
+        assert_eq!(
+            parse(content),
+            vec![
+                ("DATABASE_URL".into(), "postgres://localhost/dev".into()),
+                ("API_KEY".into(), "secret".into()),
+                ("GREETING".into(), "hello\nworld".into()),
+                ("RAW".into(), "$HOME stays literal".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_strict() {
+        let vars = parse_strict("# comment\nA=1\n\nexport B='two words'\n").unwrap();
+        assert_eq!(vars["A"], "1");
+        assert_eq!(vars["B"], "two words");
+
+        let error = parse_strict("A=1\nBROKEN\n2X=3\nA=4\n")
+            .unwrap_err()
+            .to_string();
+        let lines: Vec<&str> = error.lines().collect();
+        assert_eq!(lines.len(), 3, "{}", error);
+        assert!(lines[0].starts_with("line 2: expected KEY=VALUE"));
+        assert!(lines[1].starts_with("line 3: "));
+        assert_eq!(lines[2], "line 4: A is set more than once");
+    }
+
+    #[test]
+    fn test_line_round_trip() {
+        for value in [
+            "plain",
+            "",
+            " padded ",
+            "a #b",
+            "'quoted'",
+            "it's",
+            "two\nlines",
+            "say \"hi\"\nagain",
+        ] {
+            let line = line("KEY", value);
+            assert_eq!(parse(&line), vec![("KEY".into(), value.into())], "{}", line);
+        }
+        assert_eq!(line("KEY", "a b"), "KEY=a b");
+        assert_eq!(line("KEY", " a"), "KEY=' a'");
+    }
+
+    #[test]
+    fn test_is_secret() {
+        assert!(is_secret("GITHUB_TOKEN", "x"));
+        assert!(is_secret("stripe_secret_key", "x"));
+        assert!(is_secret("DB_PASSWORD", "x"));
+        assert!(is_secret("DATABASE_URL", "postgres://app:hunter2@db/dev"));
+        assert!(!is_secret("DATABASE_URL", "postgres://app@db/dev?x=a:b@c"));
+        assert!(!is_secret("PORT", "3000"));
+        assert!(!is_secret("KEYBOARD_LAYOUT", "us"));
+    }
+}
@@ -0,0 +1,239 @@
+//! The service, dependency and route graph `proj graph` prints
+//!
+//! Each project is drawn with its hostname and path routes, the services it
+//! configures, the running processes of each and the ports they listen on,
+//! and an edge to every project it `depends_on`.
+
+use crate::{ProcessInfo, Project};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Route,
+    Project,
+    Service,
+    Process,
+    Port,
+}
+
+/// Minimal directed graph that renders to Graphviz or Mermaid
+#[derive(Debug, Default)]
+pub struct Graph {
+    nodes: Vec<(String, String, NodeKind)>,
+    edges: Vec<(String, String, Option<&'static str>)>,
+}
+
+/// The graph of `projects` and their running `processes`, with `host`
+/// giving a project's hostname
+pub fn build(
+    projects: &[Project],
+    processes: &[ProcessInfo],
+    host: impl Fn(&str) -> String,
+) -> Graph {
+    let mut graph = Graph::default();
+    for project in projects {
+        let project_node = format!("project_{}", node_id(&project.name));
+        let route_node = format!("route_{}", node_id(&project.name));
+        let host = host(&project.name);
+        graph.node(&project_node, &project.name, NodeKind::Project);
+        graph.node(&route_node, &host, NodeKind::Route);
+        graph.edge(&route_node, &project_node, None);
+
+        // Path routes skip the project's processes for a port of their own
+        for (prefix, port) in &project.proxy.routes {
+            let path_node = format!("path_{}{}", node_id(&project.name), node_id(prefix));
+            let port_node = format!("port_{}", port);
+            graph.node(&path_node, &format!("{}{}", host, prefix), NodeKind::Route);
+            graph.node(&port_node, &format!(":{}", port), NodeKind::Port);
+            graph.edge(&route_node, &path_node, Some("path"));
+            graph.edge(&path_node, &port_node, Some("proxies"));
+        }
+
+        for dependency in &project.depends_on {
+            let dependency_node = format!("project_{}", node_id(dependency));
+            graph.node(&dependency_node, dependency, NodeKind::Project);
+            graph.edge(&project_node, &dependency_node, Some("depends on"));
+        }
+
+        for service in project.services.keys() {
+            let service_node = service_id(&project.name, service);
+            graph.node(&service_node, service, NodeKind::Service);
+            graph.edge(&project_node, &service_node, Some("defines"));
+        }
+
+        let running = processes
+            .iter()
+            .filter(|p| p.project_name == project.name && p.status.is_alive());
+        for process in running {
+            let process_node = format!("process_{}", process.id.simple());
+            let label = match &process.service {
+                Some(service) => format!(
+                    "{}: {} (pid {})",
+                    service,
+                    process.command.trim(),
+                    process.pid
+                ),
+                None => format!("{} (pid {})", process.command.trim(), process.pid),
+            };
+            graph.node(&process_node, &label, NodeKind::Process);
+            // A service that's no longer configured still shows what runs
+            match &process.service {
+                Some(service) if project.services.contains_key(service) => {
+                    let service_node = service_id(&project.name, service);
+                    graph.edge(&service_node, &process_node, Some("runs"));
+                }
+                _ => graph.edge(&project_node, &process_node, Some("runs")),
+            }
+
+            for port in &process.ports {
+                let port_node = format!("port_{}", port);
+                graph.node(&port_node, &format!(":{}", port), NodeKind::Port);
+                graph.edge(&process_node, &port_node, Some("listens"));
+                if process.port == Some(*port) {
+                    graph.edge(&route_node, &port_node, Some("proxies"));
+                }
+            }
+        }
+    }
+    graph
+}
+
+impl Graph {
+    fn node(&mut self, id: &str, label: &str, kind: NodeKind) {
+        if !self.nodes.iter().any(|(existing, _, _)| existing == id) {
+            self.nodes.push((id.to_string(), label.to_string(), kind));
+        }
+    }
+
+    fn edge(&mut self, from: &str, to: &str, label: Option<&'static str>) {
+        self.edges.push((from.to_string(), to.to_string(), label));
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph proj {\n    rankdir=LR;\n");
+        for (id, label, kind) in &self.nodes {
+            let shape = match kind {
+                NodeKind::Route => "cds",
+                NodeKind::Project => "box",
+                NodeKind::Service => "hexagon",
+                NodeKind::Process => "component",
+                NodeKind::Port => "circle",
+            };
+            out.push_str(&format!(
+                "    {} [label=\"{}\", shape={}];\n",
+                id,
+                label.replace('"', "\\\""),
+                shape
+            ));
+        }
+        for (from, to, label) in &self.edges {
+            match label {
+                Some(label) => {
+                    out.push_str(&format!("    {} -> {} [label=\"{}\"];\n", from, to, label))
+                }
+                None => out.push_str(&format!("    {} -> {};\n", from, to)),
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("graph LR\n");
+        for (id, label, kind) in &self.nodes {
+            let label = label.replace('"', "#quot;");
+            let node = match kind {
+                NodeKind::Route => format!("{}>\"{}\"]", id, label),
+                NodeKind::Project => format!("{}[\"{}\"]", id, label),
+                NodeKind::Service => format!("{}{{{{\"{}\"}}}}", id, label),
+                NodeKind::Process => format!("{}[[\"{}\"]]", id, label),
+                NodeKind::Port => format!("{}((\"{}\"))", id, label),
+            };
+            out.push_str(&format!("    {}\n", node));
+        }
+        for (from, to, label) in &self.edges {
+            match label {
+                Some(label) => out.push_str(&format!("    {} -->|{}| {}\n", from, label, to)),
+                None => out.push_str(&format!("    {} --> {}\n", from, to)),
+            }
+        }
+        out
+    }
+}
+
+fn service_id(project_name: &str, service: &str) -> String {
+    format!("service_{}_{}", node_id(project_name), node_id(service))
+}
+
+/// Make a name safe to use as a DOT/Mermaid node identifier
+fn node_id(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixture() -> Graph {
+        let mut web = Project::new("web".to_string(), PathBuf::from("/src/web"));
+        web.depends_on = vec!["api".to_string()];
+        web.proxy.routes.insert("/api".to_string(), 4000);
+        let mut api = Project::new("api".to_string(), PathBuf::from("/src/api"));
+        let worker = serde_json::from_str(r#"{"command": ["node", "worker.js"]}"#).unwrap();
+        api.services.insert("worker".to_string(), worker);
+        build(&[web, api], &[], |name| format!("{}.localhost", name))
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let dot = fixture().to_dot();
+        assert!(dot.starts_with("digraph proj {\n"), "{}", dot);
+        for line in [
+            "    route_web [label=\"web.localhost\", shape=cds];",
+            "    path_web_api [label=\"web.localhost/api\", shape=cds];",
+            "    port_4000 [label=\":4000\", shape=circle];",
+            "    service_api_worker [label=\"worker\", shape=hexagon];",
+            "    route_web -> project_web;",
+            "    route_web -> path_web_api [label=\"path\"];",
+            "    path_web_api -> port_4000 [label=\"proxies\"];",
+            "    project_web -> project_api [label=\"depends on\"];",
+            "    project_api -> service_api_worker [label=\"defines\"];",
+        ] {
+            assert!(
+                dot.lines().any(|l| l == line),
+                "missing {:?} in\n{}",
+                line,
+                dot
+            );
+        }
+        // Listed as a dependency first, the project node isn't repeated
+        assert_eq!(dot.matches("    project_api [label").count(), 1);
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn test_to_mermaid() {
+        let mermaid = fixture().to_mermaid();
+        assert!(mermaid.starts_with("graph LR\n"), "{}", mermaid);
+        for line in [
+            "    route_web>\"web.localhost\"]",
+            "    path_web_api>\"web.localhost/api\"]",
+            "    port_4000((\":4000\"))",
+            "    service_api_worker{{\"worker\"}}",
+            "    route_web --> project_web",
+            "    route_web -->|path| path_web_api",
+            "    path_web_api -->|proxies| port_4000",
+            "    project_web -->|depends on| project_api",
+            "    project_api -->|defines| service_api_worker",
+        ] {
+            assert!(
+                mermaid.lines().any(|l| l == line),
+                "missing {:?} in\n{}",
+                line,
+                mermaid
+            );
+        }
+    }
+}
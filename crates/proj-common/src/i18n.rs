@@ -0,0 +1,235 @@
+//! Translated messages for what the CLI prints
+//!
+//! Messages are Fluent files under `locales/`, built into the binary. The
+//! locale is the `locale` setting, else the first of `LC_ALL`, `LC_MESSAGES`
+//! and `LANG` that's set; a message its catalog lacks is printed in English.
+//! `en.ftl` holds every message looked up with [`tr!`](crate::tr). Help text
+//! is the exception: its English is the CLI's doc comments, and other
+//! catalogs translate it under `help-*` ids looked up with [`translated`].
+//!
+//! The catalogs cover help, daemon errors with their hints, and the output
+//! of the everyday commands (`new`, `rm`, `stop`, `restart`, `profile
+//! reset`, `daemon`). Every other message is still an English string at its
+//! call site until it's moved here.
+
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::FluentResource;
+use std::sync::OnceLock;
+use unic_langid::LanguageIdentifier;
+
+use crate::IpcError;
+
+pub use fluent_bundle::FluentArgs;
+
+/// Locales with a catalog, English first
+pub const LOCALES: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.ftl")),
+    ("de", include_str!("../locales/de.ftl")),
+];
+
+/// The selected locale's messages, then English's
+struct Catalog {
+    bundles: Vec<FluentBundle<FluentResource>>,
+}
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+/// Select the locale from the `locale` setting, falling back to the
+/// environment. Messages looked up before this use the environment's.
+pub fn init(setting: Option<&str>) {
+    CATALOG.get_or_init(|| Catalog::new(select(setting)));
+}
+
+/// A locale with a catalog for a setting such as `de_DE.UTF-8`, else `en`
+pub fn negotiate(requested: &str) -> &'static str {
+    let language = requested
+        .split(['.', '@'])
+        .next()
+        .unwrap_or_default()
+        .split(['_', '-'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    LOCALES
+        .iter()
+        .map(|(locale, _)| *locale)
+        .find(|locale| *locale == language)
+        .unwrap_or("en")
+}
+
+fn select(setting: Option<&str>) -> &'static str {
+    let requested = setting.map(str::to_string).or_else(|| {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+    });
+    negotiate(requested.as_deref().unwrap_or("en"))
+}
+
+fn catalog() -> &'static Catalog {
+    CATALOG.get_or_init(|| Catalog::new(select(None)))
+}
+
+impl Catalog {
+    fn new(locale: &str) -> Self {
+        let bundles = LOCALES
+            .iter()
+            .filter(|(name, _)| *name == locale)
+            .chain(
+                LOCALES
+                    .iter()
+                    .filter(|(name, _)| *name == "en" && locale != "en"),
+            )
+            .map(|(name, source)| bundle(name, source))
+            .collect();
+        Self { bundles }
+    }
+
+    fn format(&self, id: &str, args: Option<&FluentArgs>, fallback: bool) -> Option<String> {
+        let bundles = if fallback {
+            &self.bundles[..]
+        } else {
+            &self.bundles[..1]
+        };
+        bundles.iter().find_map(|bundle| {
+            let pattern = bundle.get_message(id)?.value()?;
+            let mut errors = Vec::new();
+            Some(
+                bundle
+                    .format_pattern(pattern, args, &mut errors)
+                    .into_owned(),
+            )
+        })
+    }
+}
+
+fn bundle(locale: &str, source: &'static str) -> FluentBundle<FluentResource> {
+    let language: LanguageIdentifier = locale.parse().expect("catalog locale");
+    let mut bundle = FluentBundle::new_concurrent(vec![language]);
+    // Terminals don't need bidi isolation marks around arguments
+    bundle.set_use_isolating(false);
+    let resource = FluentResource::try_new(source.to_string()).expect("catalog parses");
+    bundle
+        .add_resource(resource)
+        .expect("catalog ids are unique");
+    bundle
+}
+
+/// A message in the selected locale; use [`tr!`](crate::tr)
+pub fn message(id: &str, args: Option<&FluentArgs>) -> String {
+    catalog()
+        .format(id, args, true)
+        .unwrap_or_else(|| id.to_string())
+}
+
+/// A message if the selected locale translates it, for text whose English
+/// lives elsewhere
+pub fn translated(id: &str) -> Option<String> {
+    catalog().format(id, None, false)
+}
+
+/// A message from the catalog, with named arguments:
+/// `tr!("error-project-not-found", name = project_name)`
+#[macro_export]
+macro_rules! tr {
+    ($id:expr) => {
+        $crate::i18n::message($id, None)
+    };
+    ($id:expr, $($name:ident = $value:expr),+ $(,)?) => {{
+        let mut args = $crate::i18n::FluentArgs::new();
+        $(args.set(stringify!($name), $value);)+
+        $crate::i18n::message($id, Some(&args))
+    }};
+}
+
+/// A daemon error in the selected locale. Errors carrying a message of their
+/// own are shown as they are.
+pub fn error(error: &IpcError) -> String {
+    match error {
+        IpcError::ProjectNotFound { name } => tr!("error-project-not-found", name = name),
+        IpcError::ProjectExists { name } => tr!("error-project-exists", name = name),
+        IpcError::ServiceNotFound { project, service } => {
+            tr!(
+                "error-service-not-found",
+                project = project,
+                service = service
+            )
+        }
+        IpcError::ServiceRunning { service, pid } => {
+            tr!("error-service-running", service = service, pid = pid)
+        }
+        IpcError::AlreadyRunning { command, pid } => {
+            tr!("error-already-running", command = command, pid = pid)
+        }
+        IpcError::ProcessNotFound { id } => tr!("error-process-not-found", id = id),
+        IpcError::RequestNotFound { project, id } => {
+            tr!("error-request-not-found", project = project, id = id)
+        }
+        IpcError::SpawnFailed { command, .. } => tr!("error-spawn-failed", command = command),
+        IpcError::PortConflict { port, pid: None } => tr!("error-port-conflict", port = port),
+        IpcError::PortConflict {
+            port,
+            pid: Some(pid),
+        } => tr!("error-port-conflict-pid", port = port, pid = pid),
+        IpcError::Unauthorized => tr!("error-unauthorized"),
+        IpcError::InvalidRequest { message } | IpcError::Failed { message } => message.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate("de_DE.UTF-8"), "de");
+        assert_eq!(negotiate("de-AT"), "de");
+        assert_eq!(negotiate("en_US.UTF-8"), "en");
+        assert_eq!(negotiate("C"), "en");
+        assert_eq!(negotiate("fr_FR"), "en");
+    }
+
+    #[test]
+    fn test_catalogs() {
+        let english = Catalog::new("en");
+        let german = Catalog::new("de");
+        let mut args = FluentArgs::new();
+        args.set("port", 8080u16);
+        args.set("pid", 42u32);
+        // English matches the errors' own messages
+        let error = IpcError::PortConflict {
+            port: 8080,
+            pid: Some(42),
+        };
+        assert_eq!(
+            english.format("error-port-conflict-pid", Some(&args), true),
+            Some(error.to_string())
+        );
+        assert_eq!(
+            german.format("error-port-conflict-pid", Some(&args), true),
+            Some("Port 8080 ist bereits von PID 42 belegt".to_string())
+        );
+        assert!(english.format("help-about", None, false).is_none());
+
+        // Every locale parses, and has nothing English lacks besides help
+        let ids = |source: &'static str| -> Vec<&'static str> {
+            source
+                .lines()
+                .filter(|line| line.starts_with(|c: char| c.is_ascii_lowercase()))
+                .filter_map(|line| line.split_once(" =").map(|(id, _)| id))
+                .collect()
+        };
+        let english_ids = ids(LOCALES[0].1);
+        for (locale, source) in &LOCALES[1..] {
+            for id in ids(source) {
+                assert!(
+                    id.starts_with("help-") || english_ids.contains(&id),
+                    "{} has {} but en doesn't",
+                    locale,
+                    id
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! Layered configuration
+//!
+//! Settings are resolved from, lowest precedence first: built-in defaults,
+//! `~/.proj/config.json`, the current project's `.proj/config.json`,
+//! `PROJ_<SETTING>` environment variables and `--config <setting>=<value>`
+//! flags. Each setting is taken whole from the last layer that sets it.
+
+use crate::lint::{self, FindingKind};
+use crate::naming;
+use crate::{config_path, Config};
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Prefix of environment variables that override settings, e.g. `PROJ_TLD`
+pub const ENV_PREFIX: &str = "PROJ_";
+
+/// Config file inside a project's root directory
+pub const PROJECT_CONFIG: &str = ".proj/config.json";
+
+/// Where an effective setting came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigOrigin {
+    Default,
+    /// The user's config file
+    User(PathBuf),
+    /// A project's config file
+    Project(PathBuf),
+    /// An environment variable
+    Env(String),
+    /// A `--config` flag
+    Flag,
+}
+
+impl fmt::Display for ConfigOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::User(path) => write!(f, "user {}", path.display()),
+            Self::Project(path) => write!(f, "project {}", path.display()),
+            Self::Env(var) => write!(f, "env {}", var),
+            Self::Flag => write!(f, "flag --config"),
+        }
+    }
+}
+
+/// Effective config and where each of its settings came from
+#[derive(Debug, Clone)]
+pub struct ResolvedConfig {
+    pub config: Config,
+    /// Origin of every setting, by name
+    pub origins: BTreeMap<String, ConfigOrigin>,
+}
+
+impl ResolvedConfig {
+    /// Every setting's name, effective value and origin, sorted by name
+    pub fn settings(&self) -> Vec<(String, Value, &ConfigOrigin)> {
+        let Ok(Value::Object(values)) = serde_json::to_value(&self.config) else {
+            return Vec::new();
+        };
+        values
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let origin = self.origins.get(&name)?;
+                Some((name, value, origin))
+            })
+            .collect()
+    }
+}
+
+/// Applies layers of settings on top of the defaults, in precedence order
+pub struct ConfigResolver {
+    values: Map<String, Value>,
+    origins: BTreeMap<String, ConfigOrigin>,
+}
+
+impl Default for ConfigResolver {
+    fn default() -> Self {
+        let Ok(Value::Object(values)) = serde_json::to_value(Config::default()) else {
+            unreachable!("Config serializes to an object");
+        };
+        let origins = values
+            .keys()
+            .map(|name| (name.clone(), ConfigOrigin::Default))
+            .collect();
+        Self { values, origins }
+    }
+}
+
+impl ConfigResolver {
+    /// Apply the settings in a JSON config file; a missing file sets nothing.
+    /// Unknown keys, usually typos, are refused with the setting they resemble.
+    pub fn file(&mut self, path: &Path, origin: ConfigOrigin) -> Result<&mut Self> {
+        if !path.exists() {
+            return Ok(self);
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let values: Map<String, Value> = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+        let unknown: Vec<String> = lint::check::<Config>(&Value::Object(values.clone()))
+            .into_iter()
+            .filter(|f| matches!(f.kind, FindingKind::Unknown { .. }))
+            .map(|f| f.to_string())
+            .collect();
+        if !unknown.is_empty() {
+            anyhow::bail!("{}: {}", path.display(), unknown.join(", "));
+        }
+
+        for (name, value) in values {
+            self.set(name, value, origin.clone())?;
+        }
+        Ok(self)
+    }
+
+    /// Apply `PROJ_<SETTING>` variables among `vars`. Values are read as
+    /// JSON, falling back to a plain string (`PROJ_TLD=test`).
+    pub fn env(&mut self, vars: impl IntoIterator<Item = (String, String)>) -> Result<&mut Self> {
+        for (var, raw) in vars {
+            let Some(name) = var.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let name = name.to_lowercase();
+            if self.values.contains_key(&name) {
+                self.set(name, parse_value(&raw), ConfigOrigin::Env(var))?;
+            }
+        }
+        Ok(self)
+    }
+
+    /// Apply `<setting>=<value>` flags, values read like environment variables
+    pub fn flags(&mut self, flags: &[String]) -> Result<&mut Self> {
+        for flag in flags {
+            let (name, raw) = flag
+                .split_once('=')
+                .with_context(|| format!("--config expects <setting>=<value>, got '{}'", flag))?;
+            let name = name.trim();
+            if !self.values.contains_key(name) {
+                match lint::did_you_mean(name, self.values.keys().map(String::as_str)) {
+                    Some(suggestion) => anyhow::bail!(
+                        "Unknown setting '{}' (did you mean '{}'?)",
+                        name,
+                        suggestion
+                    ),
+                    None => anyhow::bail!(
+                        "Unknown setting '{}'. Settings: {}",
+                        name,
+                        self.values.keys().cloned().collect::<Vec<_>>().join(", ")
+                    ),
+                }
+            }
+            self.set(name.to_string(), parse_value(raw), ConfigOrigin::Flag)?;
+        }
+        Ok(self)
+    }
+
+    /// Check a value on its own, so a bad one is blamed on the layer that set it
+    fn set(&mut self, name: String, value: Value, origin: ConfigOrigin) -> Result<()> {
+        let single = Map::from_iter([(name.clone(), value.clone())]);
+        serde_json::from_value::<Config>(Value::Object(single))
+            .with_context(|| format!("Invalid {} from {}", name, origin))?;
+        self.values.insert(name.clone(), value);
+        self.origins.insert(name, origin);
+        Ok(())
+    }
+
+    pub fn resolve(self) -> Result<ResolvedConfig> {
+        let config: Config = serde_json::from_value(Value::Object(self.values))
+            .context("Failed to resolve config")?;
+        if let Some(origin) = self.origins.get("tld") {
+            naming::validate_tld(config.tld())
+                .with_context(|| format!("Invalid tld from {}", origin))?;
+        }
+        if let Some(origin) = self.origins.get("outbound") {
+            config
+                .outbound
+                .validate()
+                .with_context(|| format!("Invalid outbound from {}", origin))?;
+        }
+        if let Some(origin) = self.origins.get("notifications") {
+            config
+                .notifications
+                .validate()
+                .with_context(|| format!("Invalid notifications from {}", origin))?;
+        }
+        Ok(ResolvedConfig {
+            config,
+            origins: self.origins,
+        })
+    }
+}
+
+/// Resolve the config from every layer: the user's file, the config of the
+/// project rooted at `project_root` if any, the process environment and `flags`
+pub fn load(project_root: Option<&Path>, flags: &[String]) -> Result<ResolvedConfig> {
+    let mut resolver = ConfigResolver::default();
+    let user = config_path()?;
+    resolver.file(&user, ConfigOrigin::User(user.clone()))?;
+    if let Some(root) = project_root {
+        let path = root.join(PROJECT_CONFIG);
+        resolver.file(&path, ConfigOrigin::Project(path.clone()))?;
+    }
+    resolver.env(std::env::vars())?.flags(flags)?;
+    resolver.resolve()
+}
+
+fn parse_value(raw: &str) -> Value {
+    serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_precedence() {
+        let dir = std::env::temp_dir().join(format!("proj-layers-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let user = dir.join("user.json");
+        let project = dir.join("project.json");
+        std::fs::write(
+            &user,
+            r#"{"tld": "test", "proxy_port": 9000, "up_concurrency": 2}"#,
+        )
+        .unwrap();
+        std::fs::write(&project, r#"{"proxy_port": 9001, "up_concurrency": 3}"#).unwrap();
+
+        let mut resolver = ConfigResolver::default();
+        resolver
+            .file(&user, ConfigOrigin::User(user.clone()))
+            .unwrap()
+            .file(&project, ConfigOrigin::Project(project.clone()))
+            .unwrap()
+            .env([
+                ("PROJ_UP_CONCURRENCY".to_string(), "5".to_string()),
+                ("PROJ_BIND_ADDR".to_string(), "0.0.0.0".to_string()),
+                ("PROJ_NOT_A_SETTING".to_string(), "x".to_string()),
+                ("HOME".to_string(), "/home/me".to_string()),
+            ])
+            .unwrap()
+            .flags(&["bind_addr=127.0.0.2".to_string()])
+            .unwrap();
+        let resolved = resolver.resolve().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(resolved.config.tld, "test");
+        assert_eq!(resolved.config.proxy_port, 9001);
+        assert_eq!(resolved.config.up_concurrency, 5);
+        assert_eq!(resolved.config.bind_addr.to_string(), "127.0.0.2");
+        assert_eq!(resolved.config.https_port, Config::default().https_port);
+
+        let origin = |name: &str| resolved.origins[name].clone();
+        assert_eq!(origin("tld"), ConfigOrigin::User(user));
+        assert_eq!(origin("proxy_port"), ConfigOrigin::Project(project));
+        assert_eq!(
+            origin("up_concurrency"),
+            ConfigOrigin::Env("PROJ_UP_CONCURRENCY".to_string())
+        );
+        assert_eq!(origin("bind_addr"), ConfigOrigin::Flag);
+        assert_eq!(origin("https_port"), ConfigOrigin::Default);
+        assert_eq!(resolved.settings().len(), resolved.origins.len());
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let mut resolver = ConfigResolver::default();
+        let message = resolver
+            .env([("PROJ_PROXY_PORT".to_string(), "eighty".to_string())])
+            .err()
+            .unwrap()
+            .to_string();
+        assert_eq!(message, "Invalid proxy_port from env PROJ_PROXY_PORT");
+
+        assert!(resolver.flags(&["nope=1".to_string()]).is_err());
+        let message = resolver
+            .flags(&["up_concurency=2".to_string()])
+            .err()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            message,
+            "Unknown setting 'up_concurency' (did you mean 'up_concurrency'?)"
+        );
+
+        let path = std::env::temp_dir().join(format!("proj-strict-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"tld": "test", "port_range": {"strat": 4000, "end": 4999}}"#,
+        )
+        .unwrap();
+        let message = resolver
+            .file(&path, ConfigOrigin::User(path.clone()))
+            .err()
+            .unwrap()
+            .to_string();
+        std::fs::remove_file(&path).unwrap();
+        assert!(
+            message.ends_with("unknown key 'port_range.strat' (did you mean 'start'?)"),
+            "{}",
+            message
+        );
+        assert!(resolver.flags(&["tld".to_string()]).is_err());
+        resolver.flags(&["tld=dev_test".to_string()]).unwrap();
+        assert!(resolver.resolve().is_err());
+    }
+}
@@ -1,97 +1,2986 @@
 //! Shared types and utilities for the proj system.
 
+pub mod crash;
+pub mod debug;
+pub mod deps;
+pub mod dotenv;
+pub mod graph;
+pub mod i18n;
+pub mod layers;
+pub mod lint;
+pub mod migrate;
+pub mod naming;
+pub mod notifications;
+pub mod outbound;
+pub mod profile;
+pub mod schema;
+pub mod secrets;
+pub mod store;
+pub mod transport;
+pub mod trash;
+
 use anyhow::{Context, Result};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, NaiveTime, Utc, Weekday};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
-/// Project metadata stored in project.json
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Project {
-    pub name: String,
-    pub id: Uuid,
-    pub created_at: DateTime<Utc>,
-    pub root_dir: PathBuf,
-    #[serde(default)]
-    pub port: Option<u16>,
+/// Project metadata, stored in the `projects` table of [`db_path`]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Project {
+    pub name: String,
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub root_dir: PathBuf,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub proxy: ProxyOptions,
+    /// When the daemon is allowed to run this project
+    #[serde(default)]
+    pub schedule: Option<Schedule>,
+    /// Command (program and arguments) last started with `run`, reused by
+    /// `proj up` for projects without services
+    #[serde(default)]
+    pub run_command: Option<Vec<String>>,
+    /// Long-running services, by name; processes are instances of these
+    #[serde(default)]
+    pub services: BTreeMap<String, Service>,
+    /// Template the project was scaffolded from (`proj new --template`)
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Shell commands run at lifecycle points
+    #[serde(default)]
+    pub hooks: Hooks,
+    /// Projects that `proj up` starts, and waits to be ready, before this one
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// CPU and I/O priority its processes run at
+    #[serde(default)]
+    pub priority: Priority,
+    /// Shared env profiles its processes get, in order, under its own variables
+    #[serde(default)]
+    pub env_profiles: Vec<String>,
+    /// Confinement its processes run under
+    #[serde(default)]
+    pub sandbox: Sandbox,
+    /// Extra hostnames the proxy routes to it, besides `<name>.<tld>`
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Labels `proj ls` filters and groups by, e.g. a client or a layer
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Icon and color it's shown with, to tell similar projects apart
+    #[serde(default)]
+    pub appearance: Appearance,
+    /// When a run or service of it was last started
+    #[serde(default)]
+    pub last_used: Option<DateTime<Utc>>,
+    /// Whether the daemon echoes its processes' output in its own log;
+    /// `None` follows `output_passthrough` in the config
+    #[serde(default)]
+    pub output_passthrough: Option<bool>,
+    /// HTTP checks `proj <name> smoke` sends through the proxy
+    #[serde(default)]
+    pub smoke: Vec<SmokeCheck>,
+    /// Incremented on every saved change; clients pass it back as
+    /// `expected_revision` so concurrent edits conflict instead of clobbering
+    #[serde(default)]
+    pub revision: u64,
+    /// Format the project was stored in ([`migrate::PROJECT`]); 0 for
+    /// projects saved before versioning
+    #[serde(default)]
+    pub version: u32,
+}
+
+/// How a project stands out in `proj ls`, `proj top`, the dashboard, its
+/// notifications and the proxy's pages
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct Appearance {
+    /// Emoji shown before its name
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// Color its name is shown in, as `#rrggbb`
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+/// Colors that can be given by name, and what they stand for
+pub const NAMED_COLORS: [(&str, &str); 10] = [
+    ("red", "#e5484d"),
+    ("orange", "#f76b15"),
+    ("yellow", "#e2b203"),
+    ("green", "#30a46c"),
+    ("teal", "#12a594"),
+    ("cyan", "#00a2c7"),
+    ("blue", "#0090ff"),
+    ("purple", "#8e4ec6"),
+    ("pink", "#d6409f"),
+    ("gray", "#8b8d98"),
+];
+
+impl Appearance {
+    /// `name` with the icon in front, if there is one
+    pub fn label(&self, name: &str) -> String {
+        match &self.icon {
+            Some(icon) => format!("{} {}", icon, name),
+            None => name.to_string(),
+        }
+    }
+
+    /// The color's red, green and blue
+    pub fn rgb(&self) -> Option<(u8, u8, u8)> {
+        let hex = self.color.as_deref()?.strip_prefix('#')?;
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        Some((channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+/// Canonical form of a project icon: a short run of non-space characters,
+/// typically one emoji (which may take several code points)
+pub fn normalize_icon(icon: &str) -> Result<String> {
+    let icon = icon.trim();
+    let valid = icon.chars().all(|c| !c.is_whitespace() && !c.is_control());
+    if icon.is_empty() || icon.chars().count() > 8 || !valid {
+        anyhow::bail!("Invalid icon '{}' (use an emoji or a few characters)", icon);
+    }
+    Ok(icon.to_string())
+}
+
+/// Canonical form of a project color: `#rrggbb` from that, `#rgb` or one of
+/// [`NAMED_COLORS`]
+pub fn normalize_color(color: &str) -> Result<String> {
+    let color = color.trim().to_ascii_lowercase();
+    if let Some((_, hex)) = NAMED_COLORS.iter().find(|(name, _)| *name == color) {
+        return Ok(hex.to_string());
+    }
+    let digits = color.strip_prefix('#').unwrap_or(&color);
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!(
+            "Invalid color '{}' (use #rrggbb, #rgb or one of: {})",
+            color,
+            NAMED_COLORS.map(|(name, _)| name).join(", ")
+        );
+    }
+    match digits.len() {
+        6 => Ok(format!("#{}", digits)),
+        3 => Ok(digits.chars().fold("#".to_string(), |mut hex, c| {
+            hex.push(c);
+            hex.push(c);
+            hex
+        })),
+        _ => anyhow::bail!("Invalid color '{}' (use #rrggbb or #rgb)", color),
+    }
+}
+
+/// Shell commands a project runs at points in its lifecycle, in its root
+/// directory and environment
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Hooks {
+    /// Before a run or service starts; failing aborts the start
+    #[serde(default)]
+    pub pre_run: Option<String>,
+    /// Once a stop leaves the project with no running processes
+    #[serde(default)]
+    pub post_stop: Option<String>,
+    /// When a process exits with a failure nobody asked for
+    #[serde(default)]
+    pub on_crash: Option<String>,
+}
+
+impl Hooks {
+    pub const NAMES: [&'static str; 3] = ["pre_run", "post_stop", "on_crash"];
+
+    /// The command of the hook called `name`, if it's set
+    pub fn get(&self, name: &str) -> Option<&str> {
+        match name {
+            "pre_run" => self.pre_run.as_deref(),
+            "post_stop" => self.post_stop.as_deref(),
+            "on_crash" => self.on_crash.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The hook called `name`, for editing
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Option<String>> {
+        match name {
+            "pre_run" => Some(&mut self.pre_run),
+            "post_stop" => Some(&mut self.post_stop),
+            "on_crash" => Some(&mut self.on_crash),
+            _ => None,
+        }
+    }
+}
+
+/// Per-project proxy behavior
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct ProxyOptions {
+    /// Strip CSP and X-Frame-Options headers from responses (dev only)
+    #[serde(default)]
+    pub relax_csp: bool,
+    /// OpenAPI (JSON) spec that proxied responses are validated against
+    #[serde(default)]
+    pub openapi_spec: Option<PathBuf>,
+    /// Dev auth identities, by persona name
+    #[serde(default)]
+    pub personas: BTreeMap<String, Persona>,
+    /// Persona whose identity headers are injected into proxied requests
+    #[serde(default)]
+    pub persona: Option<String>,
+    /// Path prefixes sent to another port than the project's process, e.g.
+    /// `/api` → 4000; the longest matching prefix wins
+    #[serde(default)]
+    pub routes: BTreeMap<String, u16>,
+    /// Send `Host: 127.0.0.1:<port>` upstream instead of the project's
+    /// hostname, for servers that only answer to their own address
+    #[serde(default)]
+    pub rewrite_host: bool,
+    /// Answer CORS preflights and allow any origin on responses (dev only)
+    #[serde(default)]
+    pub cors: bool,
+    /// Hostnames the project's processes and browsers reach somewhere else,
+    /// e.g. `api.example.com` → `127.0.0.1:4000`, when they go through the proxy
+    #[serde(default)]
+    pub overrides: BTreeMap<String, String>,
+    /// Limits the proxied traffic is checked against
+    #[serde(default)]
+    pub budgets: PerfBudgets,
+    /// Keep browsers from caching responses: caching and validator headers
+    /// are replaced with `no-store`, and conditional requests go through as
+    /// plain ones (dev only)
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Answer the browser's service worker script fetches with a script that
+    /// empties the project's caches and unregisters itself (dev only)
+    #[serde(default)]
+    pub kill_service_workers: bool,
+    /// Host the project's ports are on when it runs on another machine, such
+    /// as a dev box; `None` is this one
+    #[serde(default)]
+    pub upstream_host: Option<String>,
+    /// Stop the project's processes after this many minutes without a
+    /// proxied request
+    #[serde(default)]
+    pub idle_stop_mins: Option<u64>,
+    /// Start the project when a request for it arrives and nothing runs,
+    /// holding the request until it's ready
+    #[serde(default)]
+    pub lazy_start: bool,
+    /// Requests the proxy forwards to the project at once; the rest queue,
+    /// and those still queued after a few seconds get a page that retries
+    #[serde(default)]
+    pub max_in_flight: Option<usize>,
+    /// Find/replace pairs applied, in order, to text responses, e.g. to point
+    /// hardcoded absolute URLs at the proxy
+    #[serde(default)]
+    pub rewrites: Vec<Rewrite>,
+    /// Pass on redirects to the backend's own address (e.g.
+    /// `http://127.0.0.1:3000/login`) as they are, instead of pointing them
+    /// back at the project's hostname
+    #[serde(default)]
+    pub keep_redirects: bool,
+    /// Redirect page requests so their paths end, or don't end, with a slash,
+    /// as the production host serves them
+    #[serde(default)]
+    pub trailing_slash: TrailingSlash,
+    /// Credentials attached to requests under a path prefix, e.g. `/api` for
+    /// a remote API routed there; the secrets themselves are kept in the
+    /// keychain
+    #[serde(default)]
+    pub auth: BTreeMap<String, UpstreamAuth>,
+    /// Leave the project out of the proxy: its processes still run and log,
+    /// but its hostname isn't routed and it's reached at its own port
+    #[serde(default)]
+    pub direct: bool,
+}
+
+/// How the proxy authenticates a project's requests to a backend
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(tag = "scheme", rename_all = "snake_case")]
+pub enum UpstreamAuth {
+    /// `Authorization: Basic`, with the password as the secret
+    Basic { username: String },
+    /// `Authorization: Bearer`, with the token as the secret
+    Bearer,
+}
+
+impl UpstreamAuth {
+    /// The Authorization header value for a secret
+    pub fn header(&self, secret: &str) -> String {
+        use base64::Engine;
+
+        match self {
+            Self::Basic { username } => format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD
+                    .encode(format!("{}:{}", username, secret))
+            ),
+            Self::Bearer => format!("Bearer {}", secret),
+        }
+    }
+}
+
+impl std::fmt::Display for UpstreamAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Basic { username } => write!(f, "basic auth as {}", username),
+            Self::Bearer => f.write_str("bearer token"),
+        }
+    }
+}
+
+/// How the proxy normalizes a trailing slash on request paths
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum TrailingSlash {
+    /// Pass paths on as they are
+    #[default]
+    Keep,
+    /// Redirect `/about` to `/about/`; paths to files, like `/app.js`, are left alone
+    Add,
+    /// Redirect `/about/` to `/about`
+    Remove,
+}
+
+impl std::str::FromStr for TrailingSlash {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "keep" => Ok(Self::Keep),
+            "add" => Ok(Self::Add),
+            "remove" => Ok(Self::Remove),
+            _ => anyhow::bail!(
+                "Invalid trailing slash mode '{}' (expected keep, add, or remove)",
+                s
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for TrailingSlash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Keep => "keep",
+            Self::Add => "add",
+            Self::Remove => "remove",
+        })
+    }
+}
+
+/// A find/replace applied to the bodies of a project's text responses
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct Rewrite {
+    /// Text to find, or a regular expression with `regex`
+    pub find: String,
+    /// What replaces each match; with `regex`, `$1` or `${name}` insert groups
+    /// and `$0` the whole match
+    pub replace: String,
+    #[serde(default)]
+    pub regex: bool,
+}
+
+impl Rewrite {
+    /// The pattern matching `find`, escaped unless it's a regular expression
+    pub fn pattern(&self) -> Result<regex::Regex> {
+        let pattern = if self.regex {
+            self.find.clone()
+        } else {
+            regex::escape(&self.find)
+        };
+        if pattern.is_empty() {
+            anyhow::bail!("A rewrite needs something to find");
+        }
+        regex::Regex::new(&pattern).with_context(|| format!("Invalid pattern '{}'", self.find))
+    }
+}
+
+/// Path on every project's host that clears the browser's cache and storage
+/// for it, service workers included
+pub const CLEAR_SITE_DATA_PATH: &str = "/__proj/clear-site-data";
+
+impl ProxyOptions {
+    /// The active dev auth persona, if any
+    pub fn active_persona(&self) -> Option<&Persona> {
+        self.persona
+            .as_ref()
+            .and_then(|name| self.personas.get(name))
+    }
+
+    /// The machine the project runs on as it's written before a port, with
+    /// an IPv6 address in brackets
+    pub fn upstream_authority(&self) -> String {
+        match self.upstream_host.as_deref() {
+            Some(host) if host.contains(':') => format!("[{}]", host),
+            Some(host) => host.to_string(),
+            None => "127.0.0.1".to_string(),
+        }
+    }
+
+    /// Address of `port` on the machine the project runs on
+    pub fn upstream(&self, port: u16) -> String {
+        format!("{}:{}", self.upstream_authority(), port)
+    }
+}
+
+/// Performance budgets for a project's local traffic, so a regression shows
+/// up while developing rather than in CI
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct PerfBudgets {
+    /// 95th percentile time to response headers, in milliseconds
+    #[serde(default)]
+    pub p95_ms: Option<u64>,
+    /// Size of the main document (HTML answering a GET), in bytes
+    #[serde(default)]
+    pub document_bytes: Option<u64>,
+}
+
+impl PerfBudgets {
+    /// The budget for `metric`, if one is set
+    pub fn limit(&self, metric: BudgetMetric) -> Option<u64> {
+        match metric {
+            BudgetMetric::P95Latency => self.p95_ms,
+            BudgetMetric::DocumentSize => self.document_bytes,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.p95_ms.is_none() && self.document_bytes.is_none()
+    }
+
+    pub fn set(&mut self, metric: BudgetMetric, limit: Option<u64>) {
+        match metric {
+            BudgetMetric::P95Latency => self.p95_ms = limit,
+            BudgetMetric::DocumentSize => self.document_bytes = limit,
+        }
+    }
+}
+
+/// What a performance budget limits
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetMetric {
+    /// 95th percentile latency, in milliseconds
+    P95Latency,
+    /// Main document size, in bytes
+    DocumentSize,
+}
+
+impl BudgetMetric {
+    pub const ALL: [BudgetMetric; 2] = [Self::P95Latency, Self::DocumentSize];
+
+    /// A value of this metric for people, e.g. `240ms` or `1.5 MiB`
+    pub fn format(&self, value: u64) -> String {
+        match self {
+            Self::P95Latency => format!("{}ms", value),
+            Self::DocumentSize => format_size(value),
+        }
+    }
+}
+
+impl std::str::FromStr for BudgetMetric {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "p95" | "latency" => Ok(Self::P95Latency),
+            "document" | "doc" => Ok(Self::DocumentSize),
+            _ => anyhow::bail!("Unknown budget '{}' (expected p95 or document)", s),
+        }
+    }
+}
+
+impl std::fmt::Display for BudgetMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::P95Latency => write!(f, "p95 latency"),
+            Self::DocumentSize => write!(f, "document size"),
+        }
+    }
+}
+
+/// A budget as the proxy last measured it
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct BudgetStatus {
+    pub metric: BudgetMetric,
+    /// The budget, if one is set
+    pub limit: Option<u64>,
+    /// Latest measurement; `None` until there is enough traffic
+    pub value: Option<u64>,
+    /// Requests the measurement is based on
+    pub samples: usize,
+    pub exceeded: bool,
+}
+
+/// Canonical form of a route's path prefix: leading slash, no trailing one
+pub fn normalize_route_prefix(prefix: &str) -> Result<String> {
+    if !prefix.starts_with('/') {
+        anyhow::bail!("Route path '{}' must start with /", prefix);
+    }
+    if prefix.contains(['?', '#', '*']) {
+        anyhow::bail!("Route path '{}' must be a plain path prefix", prefix);
+    }
+    let trimmed = prefix.trim_end_matches('/');
+    Ok(if trimmed.is_empty() { "/" } else { trimmed }.to_string())
+}
+
+/// Canonical form of where a hostname override points: `host:port`, where a
+/// bare port means `127.0.0.1:<port>`
+pub fn normalize_override_target(target: &str) -> Result<String> {
+    let target = target.trim();
+    let invalid = || {
+        anyhow::anyhow!(
+            "Invalid target '{}' (expected <port> or <host>:<port>)",
+            target
+        )
+    };
+    let (host, port) = match target.rsplit_once(':') {
+        Some((host, port)) => (host, port),
+        None => ("127.0.0.1", target),
+    };
+    let port: u16 = port
+        .parse()
+        .ok()
+        .filter(|port| *port != 0)
+        .ok_or_else(invalid)?;
+    let host = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_ascii_lowercase();
+    if host.is_empty() || host.contains(['/', ' ', '@']) {
+        return Err(invalid());
+    }
+    Ok(match host.parse::<std::net::IpAddr>() {
+        Ok(std::net::IpAddr::V6(ip)) => format!("[{}]:{}", ip, port),
+        _ => format!("{}:{}", host, port),
+    })
+}
+
+/// Identity injected as headers, for backends that trust an upstream auth proxy
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Persona {
+    pub user_id: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// Additional headers, e.g. a custom tenant header
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Persona {
+    /// Headers to set on proxied requests
+    pub fn identity_headers(&self) -> Vec<(String, String)> {
+        let mut headers = vec![("X-User-Id".to_string(), self.user_id.clone())];
+        if let Some(email) = &self.email {
+            headers.push(("X-Email".to_string(), email.clone()));
+        }
+        if !self.groups.is_empty() {
+            headers.push(("X-Groups".to_string(), self.groups.join(",")));
+        }
+        headers.extend(self.headers.iter().map(|(k, v)| (k.clone(), v.clone())));
+        headers
+    }
+}
+
+impl Project {
+    pub fn new(name: String, root_dir: PathBuf) -> Self {
+        Self {
+            name,
+            id: Uuid::new_v4(),
+            created_at: Utc::now(),
+            root_dir,
+            port: None,
+            proxy: ProxyOptions::default(),
+            schedule: None,
+            run_command: None,
+            services: BTreeMap::new(),
+            template: None,
+            hooks: Hooks::default(),
+            depends_on: Vec::new(),
+            priority: Priority::default(),
+            env_profiles: Vec::new(),
+            sandbox: Sandbox::default(),
+            aliases: Vec::new(),
+            tags: Vec::new(),
+            appearance: Appearance::default(),
+            last_used: None,
+            output_passthrough: None,
+            smoke: Vec::new(),
+            revision: 0,
+            version: migrate::PROJECT.version(),
+        }
+    }
+
+    /// Whether the proxy leaves the project alone, by `proj <name> route
+    /// disable` or `proxy = false` in its `proj.toml`
+    pub fn is_direct(&self) -> bool {
+        self.proxy.direct
+            || schema::ProjectFile::load(&self.root_dir)
+                .ok()
+                .flatten()
+                .is_some_and(|file| file.proxy == Some(false))
+    }
+
+    /// Whether `single_instance` in its `proj.toml` limits the project to
+    /// one running process
+    pub fn is_single_instance(&self) -> bool {
+        schema::ProjectFile::load(&self.root_dir)
+            .ok()
+            .flatten()
+            .is_some_and(|file| file.run.single_instance)
+    }
+}
+
+/// URL of a project in direct mode, which listens on `port`
+pub fn direct_url(port: u16) -> String {
+    format!("http://localhost:{}", port)
+}
+
+/// Maintenance windows during which a project's command is kept running
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Schedule {
+    pub windows: Vec<ScheduleWindow>,
+    /// Command (program and arguments) started when a window opens
+    pub command: Vec<String>,
+    /// Manual override of the windows
+    #[serde(default)]
+    pub forced: Option<ScheduleOverride>,
+}
+
+impl Schedule {
+    /// Whether the project should be running at `now` (local time)
+    pub fn should_run(&self, now: DateTime<Local>) -> bool {
+        if let Some(forced) = &self.forced {
+            if forced.until.is_none_or(|until| until > now) {
+                return forced.run;
+            }
+        }
+        let now = now.naive_local();
+        self.windows.iter().any(|w| w.contains(now))
+    }
+}
+
+/// A daily time range, optionally limited to certain weekdays.
+/// Ranges where `end` is before `start` wrap past midnight.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct ScheduleWindow {
+    /// Days the window opens on (empty = every day)
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl ScheduleWindow {
+    /// Whether the window is open at the given local time
+    pub fn contains(&self, at: NaiveDateTime) -> bool {
+        let on = |day: Weekday| self.days.is_empty() || self.days.contains(&day);
+        let (day, time) = (at.weekday(), at.time());
+        if self.start <= self.end {
+            on(day) && self.start <= time && time < self.end
+        } else {
+            (on(day) && time >= self.start) || (on(day.pred()) && time < self.end)
+        }
+    }
+}
+
+/// Force a scheduled project on or off, optionally until a point in time
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct ScheduleOverride {
+    pub run: bool,
+    #[serde(default)]
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Process information for a running command
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct ProcessInfo {
+    pub id: Uuid,
+    pub project_name: String,
+    pub pid: u32,
+    pub command: String,
+    /// Service this process is an instance of; `None` for ad-hoc runs
+    #[serde(default)]
+    pub service: Option<String>,
+    pub started_at: DateTime<Utc>,
+    #[serde(default)]
+    pub port: Option<u16>,
+    /// All ports the process tree listens on
+    #[serde(default)]
+    pub ports: Vec<u16>,
+    /// Ports by what they serve: `web` for the routed one, `debug` for an
+    /// inspector the process announces, and any its service names
+    #[serde(default)]
+    pub named_ports: BTreeMap<String, u16>,
+    pub status: ProcessStatus,
+    /// Outcome of the service's health check; `None` without one or until
+    /// the first probe passes
+    #[serde(default)]
+    pub healthy: Option<bool>,
+    #[serde(default)]
+    pub restart_policy: RestartPolicy,
+    #[serde(default)]
+    pub restart_count: u32,
+    #[serde(default)]
+    pub last_exit_code: Option<i32>,
+    #[serde(default)]
+    pub watching: bool,
+    /// Latest CPU and memory sample of the process tree, while running
+    #[serde(default)]
+    pub usage: Option<ResourceUsage>,
+    /// File the process's output is also written to
+    #[serde(default)]
+    pub output: Option<PathBuf>,
+    /// Chained job (`run --then/--else`) this process is a stage of
+    #[serde(default)]
+    pub job: Option<JobInfo>,
+    /// Designated to serve the project's hostname whenever it can, over its
+    /// other processes
+    #[serde(default)]
+    pub primary: bool,
+    /// Resource caps the process runs under
+    #[serde(default)]
+    pub limits: ResourceLimits,
+    /// Why the last run failed, when a resource limit ended it
+    #[serde(default)]
+    pub failure_reason: Option<String>,
+    /// Where the run's profile is written, for `run --profile`
+    #[serde(default)]
+    pub profile: Option<profile::Profile>,
+}
+
+impl ProcessInfo {
+    /// Leading characters of the ID, enough to tell a project's processes apart
+    pub fn short_id(&self) -> String {
+        self.id.simple().to_string()[..8].to_string()
+    }
+}
+
+/// A process's place in a job chained with `run --then/--else`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct JobInfo {
+    /// Shared by every stage of the job
+    pub id: Uuid,
+    pub stage: JobStage,
+}
+
+/// Stage of a chained job
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStage {
+    /// The command itself
+    Run,
+    /// Follow-up run after the command succeeded
+    Then,
+    /// Follow-up run after the command failed
+    Else,
+}
+
+impl std::fmt::Display for JobStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Run => write!(f, "run"),
+            Self::Then => write!(f, "then"),
+            Self::Else => write!(f, "else"),
+        }
+    }
+}
+
+/// A line of output from a managed process
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct LogLine {
+    pub project_name: String,
+    pub process_id: Uuid,
+    #[serde(default)]
+    pub service: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub is_stderr: bool,
+    pub line: String,
+    /// Stage of a chained job that printed the line
+    #[serde(default)]
+    pub stage: Option<JobStage>,
+    /// Set on a note standing in for this many repeated lines that were
+    /// left out (see `log_collapse_repeats`)
+    #[serde(default)]
+    pub collapsed: Option<u64>,
+}
+
+/// Which projects `proj ls` asks for, and in what order
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct ProjectFilter {
+    /// Only projects with all of these tags
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Only projects with a running process
+    #[serde(default)]
+    pub running: bool,
+    #[serde(default)]
+    pub sort: ProjectSort,
+}
+
+impl ProjectFilter {
+    /// The projects passing the filter, sorted. `is_running` tells whether a
+    /// project has a running process.
+    pub fn apply(&self, projects: Vec<Project>, is_running: impl Fn(&str) -> bool) -> Vec<Project> {
+        let mut projects: Vec<Project> = projects
+            .into_iter()
+            .filter(|project| self.tags.iter().all(|tag| project.tags.contains(tag)))
+            .filter(|project| !self.running || is_running(&project.name))
+            .collect();
+        projects.sort_by(|a, b| a.name.cmp(&b.name));
+        match self.sort {
+            ProjectSort::Name => {}
+            // Newest first; never used last
+            ProjectSort::LastUsed => projects.sort_by_key(|p| std::cmp::Reverse(p.last_used)),
+            ProjectSort::Created => projects.sort_by_key(|p| std::cmp::Reverse(p.created_at)),
+        }
+        projects
+    }
+
+    /// Summaries of the projects passing the filter, sorted, given every
+    /// process the daemon knows of
+    pub fn summaries(
+        &self,
+        projects: Vec<Project>,
+        processes: &[ProcessInfo],
+    ) -> Vec<ProjectSummary> {
+        let mut alive: BTreeMap<&str, Vec<&ProcessInfo>> = BTreeMap::new();
+        for process in processes.iter().filter(|p| p.status.is_alive()) {
+            alive
+                .entry(&process.project_name)
+                .or_default()
+                .push(process);
+        }
+        self.apply(projects, |name| alive.contains_key(name))
+            .into_iter()
+            .map(|project| {
+                let running = alive
+                    .get(project.name.as_str())
+                    .map_or(&[][..], Vec::as_slice);
+                ProjectSummary {
+                    running: running.len(),
+                    port: running.first().and_then(|p| p.port),
+                    name: project.name,
+                    root_dir: project.root_dir,
+                    tags: project.tags,
+                    appearance: project.appearance,
+                }
+            })
+            .collect()
+    }
+}
+
+/// What `proj ls` shows of a project: enough to list it, without the rest of
+/// its settings
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct ProjectSummary {
+    pub name: String,
+    pub root_dir: PathBuf,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub appearance: Appearance,
+    /// Processes running now
+    pub running: usize,
+    /// Port of the first of them
+    pub port: Option<u16>,
+}
+
+/// Order of a project listing
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProjectSort {
+    #[default]
+    Name,
+    /// Most recently started first
+    LastUsed,
+    /// Newest first
+    Created,
+}
+
+impl std::str::FromStr for ProjectSort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "name" => Ok(Self::Name),
+            "last-used" | "used" => Ok(Self::LastUsed),
+            "created" => Ok(Self::Created),
+            _ => anyhow::bail!("Unknown sort '{}' (expected name, last-used or created)", s),
+        }
+    }
+}
+
+/// Which stored output lines `proj <name> logs` asks for
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct LogQuery {
+    /// Only lines containing this text, ignoring case unless it has capitals
+    #[serde(default)]
+    pub grep: Option<String>,
+    /// Only lines printed at or after this time
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+    /// Only the newest this many matching lines
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+impl LogQuery {
+    /// Whether a line passes the text and time filters
+    pub fn matches(&self, line: &LogLine) -> bool {
+        if self.since.is_some_and(|since| line.timestamp < since) {
+            return false;
+        }
+        match self.grep.as_deref() {
+            None | Some("") => true,
+            Some(pattern) if pattern.chars().any(char::is_uppercase) => line.line.contains(pattern),
+            Some(pattern) => line.line.to_lowercase().contains(pattern),
+        }
+    }
+}
+
+/// A project's exclusive-run lock
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ProjectLock {
+    pub project_name: String,
+    /// Process holding the lock; `None` while a woken waiter is starting
+    pub holder: Option<Uuid>,
+    /// Exclusive runs queued behind the holder, oldest first
+    pub waiters: Vec<LockWaiter>,
+}
+
+/// An exclusive run waiting for its project's lock
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct LockWaiter {
+    pub command: String,
+    pub queued_at: DateTime<Utc>,
+}
+
+/// One run of a process, kept after it exits (`proj <name> history`)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RunRecord {
+    pub process_id: Uuid,
+    pub project_name: String,
+    #[serde(default)]
+    pub service: Option<String>,
+    pub command: String,
+    /// Program and arguments, for `proj <name> rerun`; empty for adopted processes
+    #[serde(default)]
+    pub argv: Vec<String>,
+    pub pid: u32,
+    pub started_at: DateTime<Utc>,
+    /// Port it was detected listening on
+    #[serde(default)]
+    pub port: Option<u16>,
+    /// `None` while the run is still going
+    #[serde(default)]
+    pub ended_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    /// How the run ended
+    #[serde(default)]
+    pub status: Option<ProcessStatus>,
+    /// Milliseconds from the start until its output said it compiled
+    #[serde(default)]
+    pub compiled_ms: Option<u64>,
+    /// Milliseconds until it listened, by its output or a detected port
+    #[serde(default)]
+    pub listening_ms: Option<u64>,
+    /// Milliseconds until its output said it was ready
+    #[serde(default)]
+    pub ready_ms: Option<u64>,
+}
+
+impl RunRecord {
+    /// How long the run took to start: until it was ready, or else until it
+    /// listened
+    pub fn startup_ms(&self) -> Option<u64> {
+        self.ready_ms.or(self.listening_ms)
+    }
+}
+
+/// A point in a process's startup, recognized from its output
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash, PartialOrd, Ord,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Milestone {
+    /// Its code finished compiling or bundling
+    Compiled,
+    /// It opened its port
+    Listening,
+    /// It said it was ready to serve
+    Ready,
+}
+
+impl Milestone {
+    pub const ALL: [Self; 3] = [Self::Compiled, Self::Listening, Self::Ready];
+}
+
+impl std::fmt::Display for Milestone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Compiled => write!(f, "compiled"),
+            Self::Listening => write!(f, "listening"),
+            Self::Ready => write!(f, "ready"),
+        }
+    }
+}
+
+/// A background job in a project's queue (`proj <name> queue add`)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct QueuedJob {
+    pub id: Uuid,
+    pub project_name: String,
+    /// Program and arguments
+    pub command: Vec<String>,
+    pub status: QueueJobStatus,
+    pub queued_at: DateTime<Utc>,
+    #[serde(default)]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Process the job ran as, whose output is in the project's logs
+    #[serde(default)]
+    pub process_id: Option<Uuid>,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueJobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    /// Removed with `queue clear` before it ran
+    Cancelled,
+}
+
+/// Something that happened in the daemon, pushed to `Subscribe` connections
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Event {
+    pub timestamp: DateTime<Utc>,
+    /// Empty for events about the daemon itself
+    pub project_name: String,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// Event payloads, tagged by an `event` field
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum EventKind {
+    ProcessStarted {
+        process_id: Uuid,
+        pid: u32,
+        #[serde(default)]
+        service: Option<String>,
+        command: String,
+    },
+    ProcessExited {
+        process_id: Uuid,
+        exit_code: Option<i32>,
+        status: ProcessStatus,
+    },
+    PortDetected {
+        process_id: Uuid,
+        port: u16,
+        ports: Vec<u16>,
+    },
+    RouteAdded {
+        port: u16,
+    },
+    RouteRemoved,
+    ProjectCreated,
+    ProjectDeleted,
+    /// A failed process's crash bundle was written
+    CrashBundle {
+        process_id: Uuid,
+        path: PathBuf,
+        /// Source line the stack trace points at, as `file:line`
+        location: Option<String>,
+    },
+    /// A process's run reached a startup milestone
+    Milestone {
+        process_id: Uuid,
+        milestone: Milestone,
+        /// Milliseconds since the run started
+        after_ms: u64,
+    },
+    /// Proxied traffic started or stopped exceeding a performance budget
+    Budget {
+        metric: BudgetMetric,
+        value: u64,
+        limit: u64,
+        exceeded: bool,
+    },
+    /// One of the daemon's tasks panicked
+    Panic {
+        task: String,
+        message: String,
+        /// Where in the daemon's source, as `file:line`
+        location: Option<String>,
+    },
+    /// The daemon finished a maintenance pass
+    Maintenance(MaintenanceReport),
+    /// The machine's network addresses changed, e.g. on a Wi-Fi reconnect or
+    /// a VPN going up or down
+    NetworkChanged {
+        added: Vec<IpAddr>,
+        removed: Vec<IpAddr>,
+        /// Where other devices reach the daemon now, as `proj status` lists
+        lan_urls: Vec<String>,
+    },
+    /// A notification for the `bell` backend of the `notifications` setting
+    Notification {
+        title: String,
+        message: String,
+        /// Type of the event it's about
+        about: String,
+    },
+}
+
+impl EventKind {
+    /// Every value of the `event` field
+    pub const NAMES: &'static [&'static str] = &[
+        "process_started",
+        "process_exited",
+        "port_detected",
+        "route_added",
+        "route_removed",
+        "project_created",
+        "project_deleted",
+        "crash_bundle",
+        "milestone",
+        "budget",
+        "panic",
+        "maintenance",
+        "network_changed",
+        "notification",
+    ];
+
+    /// The `event` field this kind is serialized with
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ProcessStarted { .. } => "process_started",
+            Self::ProcessExited { .. } => "process_exited",
+            Self::PortDetected { .. } => "port_detected",
+            Self::RouteAdded { .. } => "route_added",
+            Self::RouteRemoved => "route_removed",
+            Self::ProjectCreated => "project_created",
+            Self::ProjectDeleted => "project_deleted",
+            Self::CrashBundle { .. } => "crash_bundle",
+            Self::Milestone { .. } => "milestone",
+            Self::Budget { .. } => "budget",
+            Self::Panic { .. } => "panic",
+            Self::Maintenance(_) => "maintenance",
+            Self::NetworkChanged { .. } => "network_changed",
+            Self::Notification { .. } => "notification",
+        }
+    }
+}
+
+/// What a maintenance pass of the daemon cleaned up: the records and log
+/// files of exited processes (as `proj prune` does), caches of project
+/// browser profiles not in use, old project backups in the database, and
+/// proxy statistics of projects that are gone
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct MaintenanceReport {
+    /// Records of exited processes dropped
+    pub processes: usize,
+    pub log_files: usize,
+    pub log_bytes: u64,
+    /// Browser profiles whose caches were cleared
+    pub profiles: usize,
+    pub profile_bytes: u64,
+    /// How much smaller the database got
+    pub store_bytes: u64,
+    /// Projects whose proxy statistics were dropped
+    pub stale_metrics: usize,
+    /// Trash entries past their retention deleted
+    #[serde(default)]
+    pub trash: usize,
+    #[serde(default)]
+    pub trash_bytes: u64,
+    pub duration_ms: u64,
+    /// Steps that failed, with why
+    #[serde(default)]
+    pub errors: Vec<String>,
+}
+
+impl std::fmt::Display for MaintenanceReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "pruned {} exited processes and {} log files ({}), cleared {} browser caches ({}), shrank the database by {}",
+            self.processes,
+            self.log_files,
+            format_size(self.log_bytes),
+            self.profiles,
+            format_size(self.profile_bytes),
+            format_size(self.store_bytes)
+        )?;
+        if self.stale_metrics > 0 {
+            write!(
+                f,
+                ", dropped stats of {} removed projects",
+                self.stale_metrics
+            )?;
+        }
+        if self.trash > 0 {
+            write!(
+                f,
+                ", emptied {} old trash entries ({})",
+                self.trash,
+                format_size(self.trash_bytes)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A panic in one of the daemon's tasks, which ended that task
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct DaemonPanic {
+    pub timestamp: DateTime<Utc>,
+    /// What the task did, e.g. `proxy connection`
+    pub task: String,
+    pub message: String,
+    /// Where in the daemon's source, as `file:line`
+    pub location: Option<String>,
+}
+
+/// Why the proxy isn't listening where the config says, while the daemon
+/// keeps retrying
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct ProxyBindError {
+    pub addr: SocketAddr,
+    pub message: String,
+    /// The process holding the port, e.g. `nginx (pid 812)`, when the port is
+    /// in use and the process can be seen
+    #[serde(default)]
+    pub holder: Option<String>,
+    /// A nearby port that's free, to suggest for `proxy_port`
+    #[serde(default)]
+    pub free_port: Option<u16>,
+}
+
+impl std::fmt::Display for ProxyBindError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.holder {
+            Some(holder) => write!(f, "Proxy port {} in use by {}", self.addr.port(), holder),
+            None => write!(f, "Proxy can't listen on {}: {}", self.addr, self.message),
+        }?;
+        match self.free_port {
+            Some(port) => write!(f, "; set proxy_port (e.g. to {}, which is free)", port),
+            None => write!(f, "; set proxy_port"),
+        }?;
+        write!(f, " in ~/.proj/config.json and run `proj daemon reload`")
+    }
+}
+
+/// The daemon's own resource use and the size of its tables, compared
+/// before and after `proj selftest --stress` to catch leaks
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct DaemonDiagnostics {
+    /// Open file descriptors (handles on Windows), where the platform reports them
+    pub open_fds: Option<u64>,
+    /// Resident memory, where the platform reports it
+    pub memory_bytes: Option<u64>,
+    /// Tasks alive in the daemon's runtime
+    pub tasks: usize,
+    /// Projects with an entry in the proxy's routing table
+    pub routes: Vec<String>,
+    /// Process records kept, running or exited
+    pub process_records: usize,
+}
+
+/// How well a platform-specific part of proj works on this machine, for
+/// `proj doctor`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Capability {
+    /// What it's for, e.g. `port detection`
+    pub name: String,
+    pub status: CapabilityStatus,
+    /// How it works here, or what's missing
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CapabilityStatus {
+    Ok,
+    /// Works, but with a fallback that does less or is slower
+    Degraded,
+    Unavailable,
+}
+
+impl Capability {
+    pub fn new(name: &str, status: CapabilityStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            detail: detail.into(),
+        }
+    }
+
+    /// The running binary: what it was built for, and whether macOS runs it
+    /// translated by Rosetta when a native build would do
+    pub fn binary(name: &str) -> Self {
+        let target = build_target();
+        if translated() {
+            return Self::new(
+                name,
+                CapabilityStatus::Degraded,
+                format!(
+                    "{} build running under Rosetta; install the aarch64 build for native speed",
+                    target
+                ),
+            );
+        }
+        Self::new(name, CapabilityStatus::Ok, format!("{} build", target))
+    }
+}
+
+/// What this binary was built for, e.g. `aarch64-macos` or `x86_64-linux-musl`
+pub fn build_target() -> String {
+    let env = if cfg!(target_env = "musl") {
+        "-musl"
+    } else {
+        ""
+    };
+    format!("{}-{}{}", std::env::consts::ARCH, std::env::consts::OS, env)
+}
+
+/// Whether this process is an Intel build that Rosetta translates on Apple
+/// silicon
+fn translated() -> bool {
+    if !cfg!(all(target_os = "macos", target_arch = "x86_64")) {
+        return false;
+    }
+    std::process::Command::new("sysctl")
+        .args(["-n", "sysctl.proc_translated"])
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).trim() == "1")
+}
+
+/// Which events a subscriber receives; empty fields match everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct EventFilters {
+    #[serde(default)]
+    pub project_name: Option<String>,
+    /// Event names (see [`EventKind::NAMES`])
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+impl EventFilters {
+    pub fn matches(&self, event: &Event) -> bool {
+        self.project_name
+            .as_ref()
+            .is_none_or(|name| *name == event.project_name)
+            && (self.events.is_empty() || self.events.iter().any(|e| e == event.kind.name()))
+    }
+}
+
+/// Requests proxied to a project since the daemon started
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct ProxyTraffic {
+    pub requests: u64,
+    /// Requests that failed or got a 5xx response
+    pub errors: u64,
+}
+
+/// CPU and memory used by a process and its descendants
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Default)]
+pub struct ResourceUsage {
+    /// Percentage of one core, averaged since the previous sample
+    pub cpu_percent: f32,
+    /// Resident set size
+    pub memory_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessStatus {
+    Running,
+    /// Running, but failing its service's health check
+    Unhealthy,
+    Stopped,
+    Failed,
+}
+
+impl ProcessStatus {
+    /// Whether the process is still running, healthy or not
+    pub fn is_alive(&self) -> bool {
+        matches!(self, Self::Running | Self::Unhealthy)
+    }
+}
+
+/// An environment variable injected into a project's processes
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct EnvVar {
+    pub key: String,
+    pub value: String,
+    pub source: EnvSource,
+}
+
+/// Where an injected environment variable comes from
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EnvSource {
+    /// Stored with `proj <name> env set` (takes precedence)
+    Project,
+    /// The project's `.env` file
+    DotEnv,
+    /// An env profile the project uses
+    Profile,
+}
+
+/// A TCP port in use (or expected) on this machine
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct PortUsage {
+    pub port: u16,
+    /// Whether something is currently listening on the port
+    pub listening: bool,
+    #[serde(default)]
+    pub pid: Option<u32>,
+    #[serde(default)]
+    pub process_name: Option<String>,
+    /// Project whose managed process owns the port
+    #[serde(default)]
+    pub project_name: Option<String>,
+}
+
+/// A named, long-running part of a project (web server, worker, ...)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Service {
+    /// Program and arguments
+    pub command: Vec<String>,
+    /// Variables layered over the project environment
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub port: PortPolicy,
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    #[serde(default = "default_max_restarts")]
+    pub max_restarts: u32,
+    #[serde(default)]
+    pub watch: Option<WatchOptions>,
+    /// Probe that must pass before the service is routed
+    #[serde(default)]
+    pub health: Option<HealthCheck>,
+    /// Other ports the service listens on, by name (e.g. `metrics` = 9100)
+    #[serde(default)]
+    pub ports: BTreeMap<String, u16>,
+}
+
+/// Readiness probe for a service, run against its port
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct HealthCheck {
+    /// HTTP path to GET (a 2xx or 3xx response passes); `None` only checks
+    /// that the port accepts TCP connections
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default = "default_health_interval_ms")]
+    pub interval_ms: u64,
+    #[serde(default = "default_health_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Pass when all of the project's smoke checks do, sent straight to the
+    /// service's port, instead of probing `path`
+    #[serde(default)]
+    pub smoke: bool,
+}
+
+impl HealthCheck {
+    /// Consecutive failures before a healthy process is marked unhealthy
+    pub const FAILURE_THRESHOLD: u32 = 3;
+}
+
+impl Default for HealthCheck {
+    /// A TCP check
+    fn default() -> Self {
+        Self {
+            path: None,
+            interval_ms: default_health_interval_ms(),
+            timeout_ms: default_health_timeout_ms(),
+            smoke: false,
+        }
+    }
+}
+
+fn default_health_interval_ms() -> u64 {
+    1000
+}
+
+fn default_health_timeout_ms() -> u64 {
+    2000
+}
+
+/// An HTTP check of a project: a GET of `path` that must answer `status`,
+/// with a body containing `contains` when set
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct SmokeCheck {
+    pub path: String,
+    #[serde(default = "default_smoke_status")]
+    pub status: u16,
+    #[serde(default)]
+    pub contains: Option<String>,
+}
+
+impl std::fmt::Display for SmokeCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GET {} → {}", self.path, self.status)?;
+        if let Some(text) = &self.contains {
+            write!(f, " containing {:?}", text)?;
+        }
+        Ok(())
+    }
+}
+
+fn default_smoke_status() -> u16 {
+    200
+}
+
+/// Outcome of a [`SmokeCheck`]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct SmokeResult {
+    pub check: SmokeCheck,
+    /// Status the response had; `None` when none came
+    pub status: Option<u16>,
+    pub duration_ms: u64,
+    /// Why it failed; `None` when it passed
+    pub failure: Option<String>,
+}
+
+impl SmokeResult {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+/// How a service gets its port
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum PortPolicy {
+    /// Allocate a free port from the configured range and export it as `PORT`
+    #[default]
+    Auto,
+    /// Always export this port as `PORT`
+    Fixed(u16),
+    /// Don't export `PORT`; detect whatever the process listens on
+    Detect,
+}
+
+impl std::str::FromStr for PortPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "detect" => Ok(Self::Detect),
+            port => port.parse().map(Self::Fixed).map_err(|_| {
+                anyhow::anyhow!("Invalid port policy '{}' (auto, detect or a port)", s)
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for PortPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::Fixed(port) => write!(f, "{}", port),
+            Self::Detect => write!(f, "detect"),
+        }
+    }
+}
+
+/// Confinement of a project's processes, limiting what a misbehaving script
+/// (say, an npm postinstall) can touch
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct Sandbox {
+    #[serde(default)]
+    pub mode: SandboxMode,
+    /// Directories writable besides the project root, its data under
+    /// `~/.proj` and the temp directory, e.g. a package manager's cache
+    #[serde(default)]
+    pub writable: Vec<PathBuf>,
+}
+
+/// How far a sandboxed process is confined
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SandboxMode {
+    /// Not confined
+    #[default]
+    Off,
+    /// Writes only where [`Sandbox`] allows; reads and network are unrestricted
+    Files,
+    /// `files`, and no network at all, so the proxy can't reach it either
+    Offline,
+    /// Writes only to the temp directory, not even the project root: for
+    /// checking that a command like a build leaves the tree alone
+    ReadOnly,
+}
+
+impl std::str::FromStr for SandboxMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "off" => Ok(Self::Off),
+            "files" => Ok(Self::Files),
+            "offline" => Ok(Self::Offline),
+            "read-only" => Ok(Self::ReadOnly),
+            _ => anyhow::bail!(
+                "Invalid sandbox mode '{}' (expected off, files, offline or read-only)",
+                s
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for Sandbox {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.mode)?;
+        if self.mode != SandboxMode::ReadOnly && !self.writable.is_empty() {
+            let dirs: Vec<String> = self
+                .writable
+                .iter()
+                .map(|dir| dir.display().to_string())
+                .collect();
+            write!(f, ", also writes {}", dirs.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for SandboxMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Off => write!(f, "off"),
+            Self::Files => write!(f, "files"),
+            Self::Offline => write!(f, "offline"),
+            Self::ReadOnly => write!(f, "read-only"),
+        }
+    }
+}
+
+/// CPU and I/O priority of a project's processes, so background work like
+/// indexers and watchers yields to the editor and the dev server in use
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct Priority {
+    /// Niceness, from -20 (runs first) to 19 (runs last); below 0 needs root
+    #[serde(default)]
+    pub nice: Option<i32>,
+    /// I/O scheduling class (`ionice` on Linux; on macOS `idle` runs the
+    /// processes as background tasks with `taskpolicy -b`)
+    #[serde(default)]
+    pub io: Option<IoClass>,
+}
+
+impl Priority {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(nice) = self.nice {
+            if !(-20..=19).contains(&nice) {
+                anyhow::bail!("Niceness must be between -20 and 19, got {}", nice);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether processes run at the normal priority
+    pub fn is_default(&self) -> bool {
+        self.nice.unwrap_or(0) == 0 && self.io.is_none_or(|io| io == IoClass::BestEffort)
+    }
+}
+
+impl std::fmt::Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "nice {}", self.nice.unwrap_or(0))?;
+        match self.io {
+            Some(IoClass::BestEffort) | None => {}
+            Some(io) => write!(f, ", io {}", io)?,
+        }
+        Ok(())
+    }
+}
+
+/// I/O scheduling class of a project's processes
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoClass {
+    /// The normal class
+    BestEffort,
+    /// Only gets disk time no one else wants
+    Idle,
+}
+
+impl std::str::FromStr for IoClass {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "best-effort" => Ok(Self::BestEffort),
+            "idle" => Ok(Self::Idle),
+            _ => anyhow::bail!("Invalid I/O class '{}' (expected idle or best-effort)", s),
+        }
+    }
+}
+
+impl std::fmt::Display for IoClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BestEffort => write!(f, "best-effort"),
+            Self::Idle => write!(f, "idle"),
+        }
+    }
+}
+
+/// Caps on the resources a run may use (`run --mem 2G --cpus 2 --files 1024`)
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct ResourceLimits {
+    /// Memory of the whole process tree, in bytes
+    #[serde(default)]
+    pub memory_bytes: Option<u64>,
+    /// CPU time, in cores (`0.5` is half of one)
+    #[serde(default)]
+    pub cpus: Option<f64>,
+    /// Open file descriptors per process
+    #[serde(default)]
+    pub open_files: Option<u64>,
+}
+
+impl ResourceLimits {
+    pub fn validate(&self) -> Result<()> {
+        if self.memory_bytes == Some(0) {
+            anyhow::bail!("Memory limit must be above 0");
+        }
+        if let Some(cpus) = self.cpus {
+            if !(cpus > 0.0 && cpus.is_finite()) {
+                anyhow::bail!("CPU limit must be above 0, got {}", cpus);
+            }
+        }
+        if self.open_files == Some(0) {
+            anyhow::bail!("Open file limit must be above 0");
+        }
+        Ok(())
+    }
+
+    /// Whether no limit is set
+    pub fn is_empty(&self) -> bool {
+        self.memory_bytes.is_none() && self.cpus.is_none() && self.open_files.is_none()
+    }
+}
+
+impl std::fmt::Display for ResourceLimits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(bytes) = self.memory_bytes {
+            parts.push(format!("{} memory", format_size(bytes)));
+        }
+        if let Some(cpus) = self.cpus {
+            parts.push(format!(
+                "{} CPU{}",
+                cpus,
+                if cpus == 1.0 { "" } else { "s" }
+            ));
+        }
+        if let Some(files) = self.open_files {
+            parts.push(format!("{} open files", files));
+        }
+        if parts.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
+}
+
+/// Parse a size like `512M`, `2G`, `1.5GiB` or a plain number of bytes;
+/// suffixes are binary (`1K` is 1024 bytes)
+pub fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number
+        .parse()
+        .with_context(|| format!("Invalid size '{}' (expected e.g. 512M or 2G)", s))?;
+    let shift = match unit
+        .trim()
+        .to_ascii_uppercase()
+        .trim_end_matches("IB")
+        .trim_end_matches('B')
+    {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => anyhow::bail!("Invalid size '{}' (expected e.g. 512M or 2G)", s),
+    };
+    Ok((number * (1u64 << shift) as f64) as u64)
+}
+
+/// A size in the largest binary unit it reaches, e.g. `1.5 GiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+    if value.fract() == 0.0 {
+        format!("{} {}", value, unit)
+    } else {
+        format!("{:.1} {}", value, unit)
+    }
+}
+
+/// A project was created, updated, or deleted
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RegistryChange {
+    pub kind: RegistryChangeKind,
+    pub name: String,
+    /// The project after the change; `None` when deleted
+    pub project: Option<Project>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistryChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// A service together with its most recent process
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ServiceInfo {
+    pub name: String,
+    pub service: Service,
+    pub process: Option<ProcessInfo>,
+}
+
+/// When the daemon should restart a process after it exits
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    OnFailure,
+    Always,
+}
+
+impl std::str::FromStr for RestartPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "never" => Ok(Self::Never),
+            "on-failure" => Ok(Self::OnFailure),
+            "always" => Ok(Self::Always),
+            _ => anyhow::bail!(
+                "Invalid restart policy '{}' (expected never, on-failure, or always)",
+                s
+            ),
+        }
+    }
+}
+
+/// What a run does when the project is already running the same command,
+/// or anything with `single_instance` in its `proj.toml`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnConflict {
+    /// Fail, naming the running process
+    #[default]
+    Refuse,
+    /// Answer with the running process instead of starting another
+    Attach,
+    /// Stop the running process, then start this one
+    Restart,
+    /// Start another alongside it
+    Allow,
+}
+
+impl std::str::FromStr for OnConflict {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "refuse" => Ok(Self::Refuse),
+            "attach" => Ok(Self::Attach),
+            "restart" => Ok(Self::Restart),
+            "allow" => Ok(Self::Allow),
+            _ => anyhow::bail!(
+                "Invalid conflict policy '{}' (expected refuse, attach, restart, or allow)",
+                s
+            ),
+        }
+    }
+}
+
+/// File-watch settings for a run started with `--watch`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct WatchOptions {
+    /// Glob patterns (matched against path components) to ignore
+    #[serde(default = "default_watch_ignore")]
+    pub ignore: Vec<String>,
+    /// Quiet period after the last change before restarting
+    #[serde(default = "default_watch_debounce_ms")]
+    pub debounce_ms: u64,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            ignore: default_watch_ignore(),
+            debounce_ms: default_watch_debounce_ms(),
+        }
+    }
+}
+
+fn default_watch_ignore() -> Vec<String> {
+    ["node_modules", "target", ".git"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+fn default_watch_debounce_ms() -> u64 {
+    300
+}
+
+/// Default cap on automatic restarts for a single process
+pub const DEFAULT_MAX_RESTARTS: u32 = 5;
+
+fn default_max_restarts() -> u32 {
+    DEFAULT_MAX_RESTARTS
+}
+
+/// Older clients don't say, and keep starting duplicates
+fn default_on_conflict() -> OnConflict {
+    OnConflict::Allow
+}
+
+/// A request that passed through the proxy, kept for inspection and replay
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CapturedRequest {
+    pub id: u64,
+    pub timestamp: DateTime<Utc>,
+    pub method: String,
+    /// Path and query string
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    /// UTF-8 request body, if it was small enough to capture
+    #[serde(default)]
+    pub body: Option<String>,
+    pub upstream_port: u16,
+    #[serde(default)]
+    pub status: Option<u16>,
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+}
+
+/// A mismatch between a live response and the project's OpenAPI spec
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DriftReport {
+    pub timestamp: DateTime<Utc>,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub message: String,
+}
+
+/// Sizes of the responses the proxy served for one path
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AssetStats {
+    /// Path without its query string, so cache-busted URLs count as one
+    pub path: String,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    pub requests: u64,
+    /// Size of the latest response, in bytes as sent
+    pub last_bytes: u64,
+    pub max_bytes: u64,
+    /// Bytes served for this path over all its requests
+    pub total_bytes: u64,
+}
+
+impl AssetStats {
+    /// Broad kind of asset, e.g. `js` or `image`
+    pub fn kind(&self) -> &'static str {
+        let content_type = self.content_type.as_deref().unwrap_or("");
+        let mime = content_type.split(';').next().unwrap_or("").trim();
+        if self.path.ends_with(".map") {
+            "sourcemap"
+        } else if mime.contains("javascript") || mime == "text/jsx" {
+            "js"
+        } else if mime == "text/css" {
+            "css"
+        } else if mime == "text/html" {
+            "html"
+        } else if mime.starts_with("image/") {
+            "image"
+        } else if mime.starts_with("font/") || mime.contains("font-") {
+            "font"
+        } else if mime == "application/wasm" {
+            "wasm"
+        } else if mime.contains("json") {
+            "json"
+        } else if mime.starts_with("video/") || mime.starts_with("audio/") {
+            "media"
+        } else {
+            "other"
+        }
+    }
+}
+
+/// What the proxy served a project since `since`, largest total first
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AssetReport {
+    pub since: DateTime<Utc>,
+    pub assets: Vec<AssetStats>,
+}
+
+/// Timing statistics for a GraphQL operation seen by the proxy
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct GraphqlOpStats {
+    pub name: String,
+    /// `query`, `mutation`, or `subscription`
+    pub kind: String,
+    pub count: u64,
+    pub errors: u64,
+    pub avg_ms: u64,
+    pub p95_ms: u64,
+    pub max_ms: u64,
+}
+
+/// A project as listed by the dashboard API (`GET /__proj/api/projects`)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ApiProject {
+    pub name: String,
+    pub root_dir: PathBuf,
+    pub url: String,
+    pub appearance: Appearance,
+    /// Port the proxy currently routes the project's host to
+    pub routed_port: Option<u16>,
+    pub running: bool,
+    /// Newest first
+    pub processes: Vec<ProcessInfo>,
+}
+
+/// Body of a successful dashboard API action
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ApiMessage {
+    pub message: Option<String>,
+}
+
+/// Body of a failed dashboard API request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ApiError {
+    pub error: String,
+}
+
+/// Global configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default = "default_proxy_port")]
+    pub proxy_port: u16,
+    /// Address the proxy listens on; `0.0.0.0` exposes projects on the LAN
+    #[serde(default = "default_bind_addr")]
+    pub bind_addr: IpAddr,
+    /// Domain projects are served under: `<name>.<tld>`
+    #[serde(default = "default_tld")]
+    pub tld: String,
+    /// Close proxied WebSocket/SSE connections after this many idle seconds (0 = never)
+    #[serde(default = "default_proxy_idle_timeout_secs")]
+    pub proxy_idle_timeout_secs: u64,
+    /// Answer `/favicon.ico` and similar paths browsers ask every site for
+    /// with a placeholder when the backend 404s or is down
+    #[serde(default = "default_proxy_placeholders")]
+    pub proxy_placeholders: bool,
+    /// Keep the proxy's ports closed while no project is registered
+    #[serde(default)]
+    pub proxy_wait_for_projects: bool,
+    /// Seconds the proxy waits to connect to a backend (0 = no limit)
+    #[serde(default = "default_upstream_connect_timeout_secs")]
+    pub upstream_connect_timeout_secs: u64,
+    /// Seconds the proxy waits for a backend's response headers (0 = no limit)
+    #[serde(default = "default_upstream_read_timeout_secs")]
+    pub upstream_read_timeout_secs: u64,
+    /// Port for the HTTPS listener (0 = disabled)
+    #[serde(default = "default_https_port")]
+    pub https_port: u16,
+    /// Seconds to wait after SIGTERM before killing a stopped process tree
+    #[serde(default = "default_stop_grace_secs")]
+    pub stop_grace_secs: u64,
+    /// What happens to managed processes when the daemon exits
+    #[serde(default)]
+    pub on_daemon_exit: DaemonExitPolicy,
+    /// Ports handed to spawned processes via `PORT`
+    #[serde(default)]
+    pub port_range: PortRange,
+    /// How many projects `proj up` starts at once
+    #[serde(default = "default_up_concurrency")]
+    pub up_concurrency: usize,
+    /// How many `run --heavy` commands run at once across all projects
+    #[serde(default = "default_max_heavy_jobs")]
+    pub max_heavy_jobs: usize,
+    /// Named sets of projects for `proj up <group>` and `proj down <group>`
+    #[serde(default)]
+    pub groups: BTreeMap<String, Vec<String>>,
+    /// Language the CLI prints messages in, such as `de`; unset follows
+    /// `LC_ALL`, `LC_MESSAGES` and `LANG`
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// Browser `proj open` launches: chrome, chromium, firefox, brave, edge,
+    /// or a command with `{url}` and `{profile}` placeholders
+    #[serde(default = "default_browser")]
+    pub browser: String,
+    /// Quick actions the daemon runs when it writes a crash bundle: copy,
+    /// edit, open
+    #[serde(default)]
+    pub crash_actions: Vec<crash::CrashAction>,
+    /// Size in bytes at which a process's log file is rotated (0 = never)
+    #[serde(default = "default_log_max_bytes")]
+    pub log_max_bytes: u64,
+    /// Rotated log files kept per process besides the current one
+    #[serde(default = "default_log_max_files")]
+    pub log_max_files: usize,
+    /// Days log files are kept after their last line (0 = until pruned)
+    #[serde(default = "default_log_retention_days")]
+    pub log_retention_days: u64,
+    /// Minutes the record of an exited process is kept for `ps` and
+    /// `proj <name>` before it's dropped (0 = until pruned); run history
+    /// keeps its summary either way
+    #[serde(default = "default_exited_retention_mins")]
+    pub exited_retention_mins: u64,
+    /// Leave out lines a process already printed among its last few, such
+    /// as a banner re-printed on every reload, storing a count instead
+    #[serde(default)]
+    pub log_collapse_repeats: bool,
+    /// Echo managed processes' output in the daemon's own log, besides
+    /// storing it; projects can override it
+    #[serde(default)]
+    pub output_passthrough: bool,
+    /// Also accept requests over TCP at this address, e.g. `0.0.0.0:7070`, to
+    /// control the daemon from another machine. Needs `tcp_token`.
+    #[serde(default)]
+    pub listen_tcp: Option<SocketAddr>,
+    /// Secret TCP clients authenticate with; the CLI sends it to `PROJ_HOST`
+    #[serde(default)]
+    pub tcp_token: Option<String>,
+    /// Also listen here as an HTTP and SOCKS5 proxy, e.g. `0.0.0.0:8888`, for
+    /// phones and other devices to reach projects through. Serves clients on
+    /// private networks only.
+    #[serde(default)]
+    pub forward_proxy: Option<SocketAddr>,
+    /// Proxy for connections to hosts outside this machine, over the
+    /// `HTTPS_PROXY` and `NO_PROXY` the daemon was started with
+    #[serde(default)]
+    pub outbound: outbound::OutboundConfig,
+    /// Where crash, budget and panic notifications go, globally and per
+    /// event type
+    #[serde(default)]
+    pub notifications: notifications::NotificationConfig,
+    /// Local times of day the daemon cleans up after itself (see
+    /// [`MaintenanceReport`]), e.g. `["04:00"]`; empty turns it off
+    #[serde(default = "default_maintenance_at")]
+    pub maintenance_at: Vec<NaiveTime>,
+    /// Which commands `proj <name> <cmd>` runs without `run --`
+    #[serde(default)]
+    pub auto_run: AutoRun,
+}
+
+/// Commands `proj <name> <cmd>` may run, where `<cmd>` isn't one of proj's
+/// actions: those in `allow` run, those in `deny` are refused, and anything
+/// else is confirmed first. `"*"` in `allow` runs everything not denied.
+/// `proj <name> run -- <cmd>` runs any command.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct AutoRun {
+    #[serde(default = "default_auto_run_allow")]
+    pub allow: Vec<String>,
+    #[serde(default = "default_auto_run_deny")]
+    pub deny: Vec<String>,
+}
+
+impl Default for AutoRun {
+    fn default() -> Self {
+        Self {
+            allow: default_auto_run_allow(),
+            deny: default_auto_run_deny(),
+        }
+    }
+}
+
+/// What `proj <name> <cmd>` does with a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutoRunVerdict {
+    Run,
+    Refuse,
+    Confirm,
+}
+
+impl AutoRun {
+    /// Programs are matched by file name, exactly, so `./node_modules/.bin/vite`
+    /// is `vite`
+    pub fn verdict(&self, program: &str) -> AutoRunVerdict {
+        let name = Path::new(program)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(program);
+        let listed = |list: &[String]| list.iter().any(|entry| entry == name);
+        if listed(&self.deny) {
+            AutoRunVerdict::Refuse
+        } else if listed(&self.allow) || self.allow.iter().any(|entry| entry == "*") {
+            AutoRunVerdict::Run
+        } else {
+            AutoRunVerdict::Confirm
+        }
+    }
+}
+
+fn default_auto_run_allow() -> Vec<String> {
+    [
+        "npm", "npx", "pnpm", "yarn", "bun", "bunx", "node", "deno", "cargo", "go", "python",
+        "python3", "uv", "poetry", "pip", "make", "just", "bundle", "rails", "mix", "gradle",
+        "mvn", "dotnet", "php", "composer",
+    ]
+    .map(str::to_string)
+    .to_vec()
+}
+
+fn default_auto_run_deny() -> Vec<String> {
+    [
+        "rm", "rmdir", "sudo", "doas", "su", "dd", "mkfs", "shred", "chmod", "chown", "kill",
+        "killall", "pkill", "shutdown", "reboot",
+    ]
+    .map(str::to_string)
+    .to_vec()
 }
 
-impl Project {
-    pub fn new(name: String, root_dir: PathBuf) -> Self {
+/// Inclusive range of ports the daemon allocates from
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl Default for PortRange {
+    fn default() -> Self {
         Self {
-            name,
-            id: Uuid::new_v4(),
-            created_at: Utc::now(),
-            root_dir,
-            port: None,
+            start: 4000,
+            end: 4999,
         }
     }
 }
 
-/// Process information for a running command
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ProcessInfo {
-    pub id: Uuid,
-    pub project_name: String,
-    pub pid: u32,
-    pub command: String,
-    pub started_at: DateTime<Utc>,
-    #[serde(default)]
-    pub port: Option<u16>,
-    pub status: ProcessStatus,
+/// What happens to managed processes when the daemon shuts down
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum DaemonExitPolicy {
+    /// Stop every managed process
+    #[default]
+    KillAll,
+    /// Leave processes running; the next daemon re-adopts them
+    LeaveRunning,
+    /// Ask interactively, leaving processes running when nobody can answer
+    Ask,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-#[serde(rename_all = "lowercase")]
-pub enum ProcessStatus {
-    Running,
-    Stopped,
-    Failed,
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            proxy_port: default_proxy_port(),
+            bind_addr: default_bind_addr(),
+            tld: default_tld(),
+            proxy_idle_timeout_secs: default_proxy_idle_timeout_secs(),
+            proxy_placeholders: default_proxy_placeholders(),
+            proxy_wait_for_projects: false,
+            upstream_connect_timeout_secs: default_upstream_connect_timeout_secs(),
+            upstream_read_timeout_secs: default_upstream_read_timeout_secs(),
+            https_port: default_https_port(),
+            stop_grace_secs: default_stop_grace_secs(),
+            on_daemon_exit: DaemonExitPolicy::default(),
+            port_range: PortRange::default(),
+            up_concurrency: default_up_concurrency(),
+            max_heavy_jobs: default_max_heavy_jobs(),
+            groups: BTreeMap::new(),
+            locale: None,
+            browser: default_browser(),
+            crash_actions: Vec::new(),
+            log_max_bytes: default_log_max_bytes(),
+            log_max_files: default_log_max_files(),
+            log_retention_days: default_log_retention_days(),
+            exited_retention_mins: default_exited_retention_mins(),
+            log_collapse_repeats: false,
+            output_passthrough: false,
+            listen_tcp: None,
+            tcp_token: None,
+            forward_proxy: None,
+            outbound: outbound::OutboundConfig::default(),
+            notifications: notifications::NotificationConfig::default(),
+            maintenance_at: default_maintenance_at(),
+            auto_run: AutoRun::default(),
+        }
+    }
 }
 
-/// Global configuration
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct Config {
-    #[serde(default = "default_proxy_port")]
-    pub proxy_port: u16,
+impl Config {
+    /// Load the user's config file over the defaults, with `PROJ_*`
+    /// environment variables on top (see [`layers`])
+    pub fn load() -> Result<Self> {
+        layers::load(None, &[]).map(|resolved| resolved.config)
+    }
+
+    /// The domain projects are served under, without a leading dot
+    pub fn tld(&self) -> &str {
+        self.tld.trim_matches('.')
+    }
+
+    /// Hostname of a project, e.g. `my-app.localhost`
+    pub fn host(&self, project_name: &str) -> String {
+        format!("{}.{}", project_name, self.tld())
+    }
+
+    /// URL a project is reachable at through the proxy
+    pub fn url(&self, project_name: &str) -> String {
+        self.host_url(&self.host(project_name))
+    }
+
+    /// URL a project is reached at: through the proxy, or in direct mode at
+    /// its own port, which it may not have yet
+    pub fn project_url(&self, project: &Project) -> Option<String> {
+        if !project.is_direct() {
+            return Some(self.url(&project.name));
+        }
+        project.port.map(direct_url)
+    }
+
+    /// HTTPS URL a project is served at, unless the HTTPS listener is off
+    pub fn https_url(&self, project_name: &str) -> Option<String> {
+        let host = self.host(project_name);
+        match self.https_port {
+            0 => None,
+            443 => Some(format!("https://{}", host)),
+            port => Some(format!("https://{}:{}", host, port)),
+        }
+    }
+
+    /// URL of a hostname the proxy serves, such as an alias
+    pub fn host_url(&self, host: &str) -> String {
+        match self.proxy_port {
+            80 => format!("http://{}", host),
+            port => format!("http://{}:{}", host, port),
+        }
+    }
+
+    /// Address clients on this machine reach the proxy at
+    pub fn proxy_addr(&self) -> SocketAddr {
+        let ip = match self.bind_addr {
+            ip if ip.is_unspecified() => IpAddr::from([127, 0, 0, 1]),
+            ip => ip,
+        };
+        SocketAddr::new(ip, self.proxy_port)
+    }
+
+    /// Where other devices reach the daemon at `addrs`, the machine's
+    /// addresses: `http://` URLs of the proxy unless `bind_addr` is a loopback
+    /// one, and `socks5://` ones of the `forward_proxy`, which speaks HTTP too
+    pub fn lan_urls(&self, addrs: &[IpAddr]) -> Vec<String> {
+        let at = |bind: SocketAddr, scheme: &str| -> Vec<String> {
+            addrs
+                .iter()
+                .filter(|&&ip| match bind.ip() {
+                    IpAddr::V4(any) if any.is_unspecified() => ip.is_ipv4(),
+                    any if any.is_unspecified() => true,
+                    bound => ip == bound,
+                })
+                .filter(|ip| match ip {
+                    IpAddr::V4(ip) => !ip.is_loopback() && !ip.is_link_local(),
+                    // Reachable only with a scope ID in the URL
+                    IpAddr::V6(ip) => !ip.is_loopback() && !ip.is_unicast_link_local(),
+                })
+                .map(|&ip| format!("{}://{}", scheme, SocketAddr::new(ip, bind.port())))
+                .collect()
+        };
+        let mut urls = at(SocketAddr::new(self.bind_addr, self.proxy_port), "http");
+        if let Some(forward) = self.forward_proxy {
+            urls.extend(at(forward, "socks5"));
+        }
+        urls
+    }
+
+    /// Project names with group names replaced by their members, without
+    /// duplicates
+    pub fn expand_groups(&self, names: &[String]) -> Vec<String> {
+        let mut projects: Vec<String> = Vec::new();
+        for name in names {
+            let members = match self.groups.get(name) {
+                Some(members) => members.as_slice(),
+                None => std::slice::from_ref(name),
+            };
+            for member in members {
+                if !projects.contains(member) {
+                    projects.push(member.clone());
+                }
+            }
+        }
+        projects
+    }
+
+    /// Names of the settings that differ from `other`
+    pub fn changes(&self, other: &Config) -> Vec<&'static str> {
+        [
+            ("proxy_port", self.proxy_port != other.proxy_port),
+            ("bind_addr", self.bind_addr != other.bind_addr),
+            ("tld", self.tld() != other.tld()),
+            (
+                "proxy_idle_timeout_secs",
+                self.proxy_idle_timeout_secs != other.proxy_idle_timeout_secs,
+            ),
+            (
+                "proxy_placeholders",
+                self.proxy_placeholders != other.proxy_placeholders,
+            ),
+            (
+                "proxy_wait_for_projects",
+                self.proxy_wait_for_projects != other.proxy_wait_for_projects,
+            ),
+            (
+                "upstream_connect_timeout_secs",
+                self.upstream_connect_timeout_secs != other.upstream_connect_timeout_secs,
+            ),
+            (
+                "upstream_read_timeout_secs",
+                self.upstream_read_timeout_secs != other.upstream_read_timeout_secs,
+            ),
+            ("https_port", self.https_port != other.https_port),
+            (
+                "stop_grace_secs",
+                self.stop_grace_secs != other.stop_grace_secs,
+            ),
+            (
+                "on_daemon_exit",
+                self.on_daemon_exit != other.on_daemon_exit,
+            ),
+            ("port_range", self.port_range != other.port_range),
+            (
+                "up_concurrency",
+                self.up_concurrency != other.up_concurrency,
+            ),
+            (
+                "max_heavy_jobs",
+                self.max_heavy_jobs != other.max_heavy_jobs,
+            ),
+            ("groups", self.groups != other.groups),
+            ("crash_actions", self.crash_actions != other.crash_actions),
+            ("log_max_bytes", self.log_max_bytes != other.log_max_bytes),
+            ("log_max_files", self.log_max_files != other.log_max_files),
+            (
+                "log_retention_days",
+                self.log_retention_days != other.log_retention_days,
+            ),
+            (
+                "exited_retention_mins",
+                self.exited_retention_mins != other.exited_retention_mins,
+            ),
+            (
+                "log_collapse_repeats",
+                self.log_collapse_repeats != other.log_collapse_repeats,
+            ),
+            (
+                "output_passthrough",
+                self.output_passthrough != other.output_passthrough,
+            ),
+            ("listen_tcp", self.listen_tcp != other.listen_tcp),
+            ("tcp_token", self.tcp_token != other.tcp_token),
+            ("forward_proxy", self.forward_proxy != other.forward_proxy),
+            ("outbound", self.outbound != other.outbound),
+            ("notifications", self.notifications != other.notifications),
+            (
+                "maintenance_at",
+                self.maintenance_at != other.maintenance_at,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, changed)| changed.then_some(name))
+        .collect()
+    }
+}
+
+fn default_maintenance_at() -> Vec<NaiveTime> {
+    vec![NaiveTime::from_hms_opt(4, 0, 0).expect("valid time")]
+}
+
+fn default_bind_addr() -> IpAddr {
+    IpAddr::from([127, 0, 0, 1])
+}
+
+fn default_tld() -> String {
+    "localhost".to_string()
 }
 
 fn default_proxy_port() -> u16 {
     8080
 }
 
+fn default_proxy_idle_timeout_secs() -> u64 {
+    3600
+}
+
+fn default_proxy_placeholders() -> bool {
+    true
+}
+
+fn default_upstream_connect_timeout_secs() -> u64 {
+    5
+}
+
+fn default_upstream_read_timeout_secs() -> u64 {
+    300
+}
+
+fn default_https_port() -> u16 {
+    8443
+}
+
+fn default_stop_grace_secs() -> u64 {
+    10
+}
+
+fn default_up_concurrency() -> usize {
+    4
+}
+
+fn default_max_heavy_jobs() -> usize {
+    2
+}
+
+fn default_browser() -> String {
+    "chrome".to_string()
+}
+
+fn default_log_max_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_log_max_files() -> usize {
+    3
+}
+
+fn default_log_retention_days() -> u64 {
+    7
+}
+
+fn default_exited_retention_mins() -> u64 {
+    60
+}
+
+/// Version of the CLI–daemon protocol, bumped whenever requests or responses
+/// change. A client checks it with [`IpcRequest::Hello`] before its first
+/// request.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest protocol version whose daemons a client built now can still talk
+/// to. Raised only when a change leaves older messages unreadable (as typed
+/// errors did in 2), not with every bump of [`PROTOCOL_VERSION`].
+pub const MIN_PROTOCOL_VERSION: u32 = 2;
+
+/// Version of the proj binaries
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Size of a terminal, in character cells
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct TerminalSize {
+    pub cols: u16,
+    pub rows: u16,
+}
+
+impl Default for TerminalSize {
+    fn default() -> Self {
+        Self { cols: 80, rows: 24 }
+    }
+}
+
 /// IPC Request types from CLI to daemon
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum IpcRequest {
+    /// Handshake: which protocol the client speaks. Daemons from before the
+    /// handshake answer with an `Error`, as for any request they don't know;
+    /// others read the client's next request on the same connection.
+    Hello {
+        protocol_version: u32,
+        client_version: String,
+    },
+    /// Prove the client knows the daemon's `tcp_token`. The first request on
+    /// a TCP connection, answered with `Success` before the next is read.
+    Authenticate { token: String },
     /// Create a new project
-    CreateProject { name: String, root_dir: PathBuf },
-    /// List all projects
-    ListProjects,
+    CreateProject {
+        name: String,
+        root_dir: PathBuf,
+        /// Template the directory was scaffolded from
+        #[serde(default)]
+        template: Option<String>,
+        /// Default dev command, recorded as the project's run command
+        #[serde(default)]
+        run_command: Option<Vec<String>>,
+    },
+    /// Add a complete project definition, e.g. one migrated from another machine
+    ImportProject { project: Box<Project> },
+    /// List projects, all of them by default
+    ListProjects {
+        #[serde(default)]
+        filter: ProjectFilter,
+    },
+    /// List projects with where their processes stand, in one round trip
+    /// and without their full settings; answered with `ProjectSummaries`
+    ProjectsWithProcesses {
+        #[serde(default)]
+        filter: ProjectFilter,
+    },
     /// Get a specific project
     GetProject { name: String },
+    /// Delete a project, stopping its processes
+    DeleteProject {
+        name: String,
+        /// Keep ~/.proj/projects/<name> (Chrome profile, logs)
+        #[serde(default)]
+        keep_data: bool,
+    },
+    /// Replace a project's proxy options
+    SetProxyOptions {
+        name: String,
+        options: ProxyOptions,
+        /// Only apply if the project is still at this revision
+        #[serde(default)]
+        expected_revision: Option<u64>,
+    },
+    /// Replace (or clear) a project's maintenance schedule
+    SetSchedule {
+        name: String,
+        schedule: Option<Schedule>,
+        #[serde(default)]
+        expected_revision: Option<u64>,
+    },
+    /// Replace a project's lifecycle hooks
+    SetHooks {
+        name: String,
+        hooks: Hooks,
+        #[serde(default)]
+        expected_revision: Option<u64>,
+    },
+    /// Replace the projects a project depends on
+    SetDependencies {
+        name: String,
+        depends_on: Vec<String>,
+        #[serde(default)]
+        expected_revision: Option<u64>,
+    },
+    /// Set a project's priority and apply it to its running processes
+    SetPriority {
+        name: String,
+        priority: Priority,
+        #[serde(default)]
+        expected_revision: Option<u64>,
+    },
+    /// Set the extra hostnames the proxy routes to a project
+    SetAliases {
+        name: String,
+        aliases: Vec<String>,
+        #[serde(default)]
+        expected_revision: Option<u64>,
+    },
+    /// Set the labels `proj ls` filters and groups a project by
+    SetTags {
+        name: String,
+        tags: Vec<String>,
+        #[serde(default)]
+        expected_revision: Option<u64>,
+    },
+    /// Set the HTTP checks `proj <name> smoke` runs
+    SetSmokeChecks {
+        name: String,
+        checks: Vec<SmokeCheck>,
+        #[serde(default)]
+        expected_revision: Option<u64>,
+    },
+    /// Set the icon and color a project is shown with
+    SetAppearance {
+        name: String,
+        appearance: Appearance,
+        #[serde(default)]
+        expected_revision: Option<u64>,
+    },
+    /// Set whether the daemon echoes a project's output in its own log
+    /// (`None` to follow the config)
+    SetOutputPassthrough {
+        name: String,
+        passthrough: Option<bool>,
+        #[serde(default)]
+        expected_revision: Option<u64>,
+    },
+    /// Set the confinement a project's processes start under
+    SetSandbox {
+        name: String,
+        sandbox: Sandbox,
+        #[serde(default)]
+        expected_revision: Option<u64>,
+    },
     /// Run a command in project context
     RunCommand {
         project_name: String,
         command: String,
         args: Vec<String>,
+        #[serde(default)]
+        restart: RestartPolicy,
+        #[serde(default = "default_max_restarts")]
+        max_restarts: u32,
+        #[serde(default)]
+        watch: Option<WatchOptions>,
+        /// Hold the project's lock while running, queueing behind other exclusive runs
+        #[serde(default)]
+        exclusive: bool,
+        /// With `exclusive`, answer `Locked` instead of queueing when the lock is taken
+        #[serde(default)]
+        no_wait: bool,
+        /// Take one of the global heavy-job slots, queueing while all are in use
+        #[serde(default)]
+        heavy: bool,
+        /// Absolute path to also write the process's output to, truncated on start
+        #[serde(default)]
+        output: Option<PathBuf>,
+        /// Command (program and arguments) run next if this one exits with 0
+        #[serde(default)]
+        then: Option<Vec<String>>,
+        /// Command run next if this one fails
+        #[serde(default, rename = "else")]
+        otherwise: Option<Vec<String>>,
+        /// Confinement for this run instead of the project's
+        #[serde(default)]
+        sandbox: Option<SandboxMode>,
+        /// Make the process the project's primary one
+        #[serde(default)]
+        primary: bool,
+        /// Resource caps for the process tree
+        #[serde(default)]
+        limits: ResourceLimits,
+        /// Run under the stack's profiler
+        #[serde(default)]
+        profile: Option<profile::ProfileKind>,
+        /// Run in a pseudo-terminal of this size, for commands that prompt;
+        /// the client then attaches to it with `AttachTty`
+        #[serde(default)]
+        tty: Option<TerminalSize>,
+        /// Directory the client runs in, to warn when the run is elsewhere
+        #[serde(default)]
+        cwd: Option<PathBuf>,
+        /// What to do when the project is already running the command
+        #[serde(default = "default_on_conflict")]
+        on_conflict: OnConflict,
+    },
+    /// Connect to the terminal of a process run with `tty`. Its output is
+    /// pushed as `TtyOutput`, starting with what the client missed, and the
+    /// client sends `TtyInput` and `TtyResize` until it disconnects (the
+    /// process keeps running) or the process exits (`TtyExited`).
+    AttachTty {
+        process_id: Uuid,
+        size: TerminalSize,
+    },
+    /// Keystrokes for an attached terminal, base64-encoded
+    TtyInput { data: String },
+    /// The attached client's terminal changed size
+    TtyResize { size: TerminalSize },
+    /// Effective environment injected into a project's processes
+    GetEnv { project_name: String },
+    /// Set stored environment variables for a project
+    SetEnv {
+        project_name: String,
+        vars: BTreeMap<String, String>,
+    },
+    /// Remove stored environment variables from a project
+    UnsetEnv {
+        project_name: String,
+        keys: Vec<String>,
+    },
+    /// Choose the env profiles a project's processes get
+    SetEnvProfiles {
+        project_name: String,
+        profiles: Vec<String>,
+        #[serde(default)]
+        expected_revision: Option<u64>,
+    },
+    /// Manage an already-running process, found by PID or by the port it listens on
+    AdoptProcess {
+        project_name: String,
+        #[serde(default)]
+        pid: Option<u32>,
+        #[serde(default)]
+        port: Option<u16>,
     },
     /// Stop a process
     StopProcess {
         project_name: String,
         process_id: Uuid,
     },
+    /// Designate the process that serves a project's hostname whenever it
+    /// can, or with `None` go back to the newest one
+    SetPrimaryProcess {
+        project_name: String,
+        process_id: Option<Uuid>,
+    },
+    /// Stop a process and start it again with the same command
+    RestartProcess {
+        project_name: String,
+        process_id: Uuid,
+    },
+    /// Define or replace a service
+    SetService {
+        project_name: String,
+        name: String,
+        service: Service,
+        #[serde(default)]
+        expected_revision: Option<u64>,
+    },
+    /// Stop and remove a service
+    RemoveService {
+        project_name: String,
+        name: String,
+        #[serde(default)]
+        expected_revision: Option<u64>,
+    },
+    /// Start an instance of a service; with `debug`, restart it under its
+    /// stack's debugger
+    StartService {
+        project_name: String,
+        name: String,
+        #[serde(default)]
+        debug: bool,
+    },
+    /// Stop a service's running processes
+    StopService { project_name: String, name: String },
+    /// Services of a project with their current processes
+    ListServices { project_name: String },
+    /// List captured proxy requests for a project (newest first)
+    ListRequests { project_name: String, limit: usize },
+    /// Get a single captured request
+    GetRequest { project_name: String, id: u64 },
+    /// Get recorded API schema drift for a project
+    GetApiDrift { project_name: String },
+    /// Clear recorded API schema drift for a project
+    ClearApiDrift { project_name: String },
+    /// Measurements of a project's traffic against its performance budgets
+    GetBudgets { project_name: String },
+    /// Send a project's smoke checks through the proxy
+    RunSmokeChecks { project_name: String },
+    /// Response sizes the proxy served for a project, by path
+    GetAssets { project_name: String },
+    /// Start a project's response size report afresh
+    ClearAssets { project_name: String },
+    /// Get GraphQL operation statistics for a project
+    GetGraphqlOps { project_name: String },
+    /// Request counts of a project's proxied traffic
+    GetTraffic { project_name: String },
     /// List processes for a project
     ListProcesses { project_name: Option<String> },
+    /// A project's most recent runs, newest first
+    GetHistory { project_name: String, limit: usize },
+    /// A project's stored output lines matching a query, oldest first
+    GetLogs {
+        project_name: String,
+        #[serde(default)]
+        query: LogQuery,
+    },
+    /// Delete the log files and records of exited processes, of one project
+    /// or all of them
+    Prune { project_name: Option<String> },
+    /// List held exclusive-run locks and their queues
+    ListLocks { project_name: Option<String> },
+    /// Append a background job to a project's queue
+    QueueAdd {
+        project_name: String,
+        command: Vec<String>,
+    },
+    /// A project's queued, running and recently finished jobs
+    ListQueue { project_name: String },
+    /// Drop a project's pending jobs; the running one carries on
+    ClearQueue { project_name: String },
+    /// List listening ports on the machine, annotated with owning projects
+    ListPorts,
     /// Get daemon status
     Status,
+    /// The daemon's own resource use and table sizes
+    Diagnostics,
+    /// How well the daemon's platform-specific parts work here
+    Capabilities,
+    /// Keep the connection open and receive a `RegistryChanged` line for every
+    /// project created, updated, or deleted
+    SubscribeRegistry,
+    /// Keep the connection open and receive a project's recent output, then a
+    /// `Log` line for every new line its processes print
+    SubscribeLogs { project_name: String },
+    /// Keep the connection open and receive an `Event` line for every daemon
+    /// event that passes `filters`
+    Subscribe {
+        #[serde(default)]
+        filters: EventFilters,
+    },
+    /// Re-read ~/.proj/config.json and apply it without restarting
+    ReloadConfig,
+    /// Run a maintenance pass now instead of at `maintenance_at`
+    RunMaintenance,
+    /// Override the daemon's log level per subsystem (`proxy=debug`,
+    /// `proxy=default` to drop one) and answer with the filter in effect;
+    /// no `levels` only reports it
+    SetLogLevels {
+        #[serde(default)]
+        levels: Vec<String>,
+        /// Drop every override first
+        #[serde(default)]
+        reset: bool,
+    },
+    /// Set when non-critical notifications are held and answer with where
+    /// that stands; no `mode` only reports it
+    SetDnd {
+        #[serde(default)]
+        mode: Option<notifications::DndMode>,
+    },
     /// Shutdown daemon
-    Shutdown,
+    Shutdown {
+        /// Override the configured exit policy for managed processes
+        #[serde(default)]
+        stop_processes: Option<bool>,
+    },
 }
 
 /// IPC Response types from daemon to CLI
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type", content = "data", rename_all = "snake_case")]
 pub enum IpcResponse {
     /// Success with optional message
@@ -100,20 +2989,213 @@ pub enum IpcResponse {
     Project(Project),
     /// List of projects
     Projects(Vec<Project>),
+    /// Projects as `proj ls` lists them
+    ProjectSummaries(Vec<ProjectSummary>),
     /// Process started
-    ProcessStarted { process: ProcessInfo },
+    ProcessStarted {
+        process: ProcessInfo,
+        /// Where the proxy serves the project, by the daemon's settings;
+        /// answered to `RunCommand` by daemons that know it
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+        /// The same over HTTPS, while the HTTPS listener is on
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        https_url: Option<String>,
+        /// Program the command resolved to on the process's `PATH`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        command_path: Option<PathBuf>,
+        /// Why the run may not do what was expected, e.g. that it has no
+        /// port yet
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<String>,
+    },
+    /// Output of an attached terminal, base64-encoded
+    TtyOutput { data: String },
+    /// The process of an attached terminal exited
+    TtyExited { exit_code: Option<i32> },
     /// List of processes
     Processes(Vec<ProcessInfo>),
+    /// Past and current runs
+    Runs(Vec<RunRecord>),
+    /// Stored output lines, oldest first
+    Logs(Vec<LogLine>),
+    /// What a prune removed
+    Pruned {
+        processes: usize,
+        log_files: usize,
+        bytes: u64,
+    },
+    /// What a maintenance pass cleaned up
+    Maintenance(MaintenanceReport),
+    /// Do-not-disturb's mode and state
+    Dnd(notifications::DndStatus),
+    /// Captured proxy requests
+    Requests(Vec<CapturedRequest>),
+    /// A single captured request
+    Request(CapturedRequest),
+    /// API schema drift reports
+    ApiDrift(Vec<DriftReport>),
+    /// Outcomes of a project's smoke checks, in order
+    SmokeResults(Vec<SmokeResult>),
+    /// Performance budget measurements
+    Budgets(Vec<BudgetStatus>),
+    /// Response sizes by path
+    Assets(AssetReport),
+    /// GraphQL operation statistics
+    GraphqlOps(Vec<GraphqlOpStats>),
+    /// Request counts of a project's proxied traffic
+    Traffic(ProxyTraffic),
     /// Daemon status
     Status {
         running: bool,
         project_count: usize,
         process_count: usize,
+        /// 0 from daemons that predate versioning
+        #[serde(default)]
+        protocol_version: u32,
+        #[serde(default)]
+        daemon_version: String,
+        /// Panics in the daemon's tasks within the last hour, oldest first
+        #[serde(default)]
+        panics: Vec<DaemonPanic>,
+        /// Set while the proxy can't listen on its configured address
+        #[serde(default)]
+        proxy_error: Option<ProxyBindError>,
+        /// Commit the daemon was built from, when built in a git checkout
+        #[serde(default)]
+        git_hash: Option<String>,
+        #[serde(default)]
+        started_at: Option<DateTime<Utc>>,
+        /// Where the daemon's proxy serves HTTP, by the config it has loaded
+        #[serde(default)]
+        proxy_addr: Option<SocketAddr>,
+        #[serde(default)]
+        socket_path: Option<PathBuf>,
+        /// Projects the proxy has routes for
+        #[serde(default)]
+        route_count: usize,
+        /// Clients following events, logs or registry changes
+        #[serde(default)]
+        subscriber_count: usize,
+        /// Where other devices reach the daemon, by the machine's current
+        /// addresses
+        #[serde(default)]
+        lan_urls: Vec<String>,
+    },
+    /// The daemon's own resource use and table sizes
+    Diagnostics(DaemonDiagnostics),
+    /// Answer to `Capabilities`
+    Capabilities(Vec<Capability>),
+    /// Answer to `Hello`
+    Hello {
+        protocol_version: u32,
+        daemon_version: String,
+        /// Whether the next request can go on the same connection, saving a
+        /// connect; daemons from before this close it
+        #[serde(default)]
+        keeps_connection: bool,
     },
+    /// The request's type is one this daemon doesn't know, so the client is
+    /// newer than it
+    UnsupportedRequest {
+        request_type: String,
+        protocol_version: u32,
+        daemon_version: String,
+    },
+    /// Port audit
+    Ports(Vec<PortUsage>),
+    /// Project environment
+    Env(Vec<EnvVar>),
+    /// Services of a project
+    Services(Vec<ServiceInfo>),
+    /// Held exclusive-run locks
+    Locks(Vec<ProjectLock>),
+    /// Jobs of a project's queue, oldest first
+    Queue(Vec<QueuedJob>),
+    /// Pushed to `SubscribeRegistry` connections
+    RegistryChanged(RegistryChange),
+    /// Pushed to `SubscribeLogs` connections
+    Log(LogLine),
+    /// Pushed to `Subscribe` connections
+    Event(Event),
+
+    /// An update's `expected_revision` no longer matches; re-fetch and retry
+    Conflict { message: String, revision: u64 },
+    /// An exclusive run with `no_wait` found the project's lock taken
+    Locked { message: String },
+
+    /// Pushed while a heavy run waits for a slot; the final answer follows
+    HeavyQueued { position: usize, limit: usize },
+
     /// Error occurred
-    Error { message: String },
+    Error(IpcError),
+}
+
+/// Why the daemon couldn't do what it was asked, typed so clients can tell
+/// failures apart without matching on messages
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, thiserror::Error)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum IpcError {
+    #[error("Project '{name}' not found")]
+    ProjectNotFound { name: String },
+    #[error("Project '{name}' already exists")]
+    ProjectExists { name: String },
+    #[error("Service '{service}' not found in '{project}'")]
+    ServiceNotFound { project: String, service: String },
+    #[error("Service '{service}' is already running (pid {pid})")]
+    ServiceRunning { service: String, pid: u32 },
+    /// A run would duplicate a running process (see [`OnConflict`])
+    #[error("`{command}` is already running (pid {pid})")]
+    AlreadyRunning { command: String, pid: u32 },
+    /// No managed process has the ID, or it's no longer running
+    #[error("Process {id} not found")]
+    ProcessNotFound { id: String },
+    #[error("Request {id} not found for project '{project}'")]
+    RequestNotFound { project: String, id: u64 },
+    /// The command couldn't be started at all, e.g. it isn't installed
+    #[error("Failed to start `{command}`")]
+    SpawnFailed {
+        command: String,
+        source: RemoteError,
+        /// What to try instead when the command wasn't found, e.g. a copy
+        /// in the project's `node_modules/.bin`
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        suggestions: Vec<String>,
+    },
+    /// A port the request needs is held by another process
+    #[error("Port {port} is already in use{}", .pid.map(|pid| format!(" by pid {}", pid)).unwrap_or_default())]
+    PortConflict { port: u16, pid: Option<u32> },
+    /// A TCP client didn't authenticate with the daemon's token
+    #[error("The daemon refused the connection: wrong or missing token")]
+    Unauthorized,
+    /// The request is malformed or its arguments don't make sense
+    #[error("{message}")]
+    InvalidRequest { message: String },
+    /// Anything else that went wrong
+    #[error("{message}")]
+    Failed { message: String },
 }
 
+impl From<anyhow::Error> for IpcError {
+    /// Typed errors raised further down keep their type; the rest become
+    /// `Failed` with their whole chain of causes
+    fn from(e: anyhow::Error) -> Self {
+        match e.downcast::<IpcError>() {
+            Ok(error) => error,
+            Err(e) => IpcError::Failed {
+                message: format!("{:#}", e),
+            },
+        }
+    }
+}
+
+/// The message of an error that happened on the other side of the socket,
+/// kept as the source of an [`IpcError`]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, thiserror::Error)]
+#[serde(transparent)]
+#[error("{0}")]
+pub struct RemoteError(pub String);
+
 /// Get the base directory for proj data (~/.proj)
 pub fn proj_dir() -> Result<PathBuf> {
     let home = dirs::home_dir().context("Could not find home directory")?;
@@ -125,60 +3207,447 @@ pub fn projects_dir() -> Result<PathBuf> {
     Ok(proj_dir()?.join("projects"))
 }
 
+/// Get the user project templates directory (~/.proj/templates)
+pub fn templates_dir() -> Result<PathBuf> {
+    Ok(proj_dir()?.join("templates"))
+}
+
 /// Get the path for a specific project
 pub fn project_dir(name: &str) -> Result<PathBuf> {
     Ok(projects_dir()?.join(name))
 }
 
+/// Get the stored environment variables path for a project
+pub fn project_env_path(name: &str) -> Result<PathBuf> {
+    Ok(project_dir(name)?.join("env.json"))
+}
+
+/// Get the shared env profiles file path
+pub fn env_profiles_path() -> Result<PathBuf> {
+    Ok(proj_dir()?.join("env-profiles.json"))
+}
+
+/// Named sets of environment variables, kept out of every project's own
+/// config, that projects opt into with `proj <name> env use`
+pub type EnvProfiles = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Read the env profiles file; without one there are none
+pub fn load_env_profiles() -> Result<EnvProfiles> {
+    let path = env_profiles_path()?;
+    if !path.exists() {
+        return Ok(EnvProfiles::new());
+    }
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
 /// Get the daemon socket path
+#[cfg(unix)]
 pub fn socket_path() -> Result<PathBuf> {
     Ok(proj_dir()?.join("daemon.sock"))
 }
 
+/// Get the daemon's named pipe, one per user
+#[cfg(windows)]
+pub fn socket_path() -> Result<PathBuf> {
+    let user = std::env::var("USERNAME").unwrap_or_else(|_| "default".to_string());
+    Ok(PathBuf::from(format!(r"\\.\pipe\proj-{}", user)))
+}
+
 /// Get the config file path
 pub fn config_path() -> Result<PathBuf> {
     Ok(proj_dir()?.join("config.json"))
 }
 
+/// Get the process records file written before the store, imported into it
+pub fn processes_path() -> Result<PathBuf> {
+    Ok(proj_dir()?.join("processes.json"))
+}
+
+/// Get the database holding projects, process records and run history
+pub fn db_path() -> Result<PathBuf> {
+    Ok(proj_dir()?.join("proj.db"))
+}
+
+/// Get the TLS directory holding the local CA and leaf certificates (~/.proj/tls)
+pub fn tls_dir() -> Result<PathBuf> {
+    Ok(proj_dir()?.join("tls"))
+}
+
+/// Get the local CA certificate path
+pub fn ca_cert_path() -> Result<PathBuf> {
+    Ok(tls_dir()?.join("ca.pem"))
+}
+
 /// Get the daemon PID file path
 pub fn pid_file_path() -> Result<PathBuf> {
     Ok(proj_dir()?.join("daemon.pid"))
 }
 
-/// Validate project name (alphanumeric, hyphens, underscores only)
-pub fn validate_project_name(name: &str) -> Result<()> {
-    if name.is_empty() {
-        anyhow::bail!("Project name cannot be empty");
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_color() {
+        assert_eq!(normalize_color("#FF8800").unwrap(), "#ff8800");
+        assert_eq!(normalize_color("f80").unwrap(), "#ff8800");
+        assert_eq!(normalize_color("Teal").unwrap(), "#12a594");
+        assert!(normalize_color("#ff88").is_err());
+        assert!(normalize_color("chartreuse").is_err());
+
+        let appearance = Appearance {
+            icon: Some("🛒".to_string()),
+            color: Some("#ff8800".to_string()),
+        };
+        assert_eq!(appearance.rgb(), Some((255, 136, 0)));
+        assert_eq!(appearance.label("shop"), "🛒 shop");
+        assert!(normalize_icon("👩‍💻").is_ok());
+        assert!(normalize_icon("a b").is_err());
     }
-    if name.len() > 64 {
-        anyhow::bail!("Project name cannot exceed 64 characters");
+
+    #[test]
+    fn test_project_filter() {
+        let project = |name: &str, tags: &[&str], used: Option<i64>| Project {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            last_used: used.map(|secs| DateTime::from_timestamp(secs, 0).unwrap()),
+            ..Project::new(name.to_string(), PathBuf::from("/tmp"))
+        };
+        let projects = vec![
+            project("web", &["client-x", "frontend"], Some(10)),
+            project("api", &["client-x", "backend"], Some(20)),
+            project("blog", &[], None),
+        ];
+        let names = |filter: ProjectFilter| -> Vec<String> {
+            filter
+                .apply(projects.clone(), |name| name != "web")
+                .into_iter()
+                .map(|project| project.name)
+                .collect()
+        };
+
+        assert_eq!(names(ProjectFilter::default()), ["api", "blog", "web"]);
+        let client = ProjectFilter {
+            tags: vec!["client-x".to_string()],
+            ..ProjectFilter::default()
+        };
+        assert_eq!(names(client.clone()), ["api", "web"]);
+        let running = ProjectFilter {
+            running: true,
+            ..client
+        };
+        assert_eq!(names(running), ["api"]);
+        let by_use = ProjectFilter {
+            sort: "last-used".parse().unwrap(),
+            ..ProjectFilter::default()
+        };
+        assert_eq!(names(by_use), ["api", "web", "blog"]);
     }
-    if !name
-        .chars()
-        .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
-    {
-        anyhow::bail!(
-            "Project name can only contain alphanumeric characters, hyphens, and underscores"
+
+    #[test]
+    fn test_project_summaries() {
+        let process = |project: &str, port: u16, status: &str| -> ProcessInfo {
+            serde_json::from_value(serde_json::json!({
+                "id": Uuid::new_v4(),
+                "project_name": project,
+                "pid": 42,
+                "command": "npm run dev",
+                "started_at": Utc::now(),
+                "port": port,
+                "status": status,
+            }))
+            .unwrap()
+        };
+        let projects = vec![
+            Project::new("web".to_string(), PathBuf::from("/tmp/web")),
+            Project::new("api".to_string(), PathBuf::from("/tmp/api")),
+        ];
+        let processes = [
+            process("web", 3000, "stopped"),
+            process("web", 3001, "running"),
+            process("web", 3002, "unhealthy"),
+            process("gone", 4000, "running"),
+        ];
+
+        let summaries = ProjectFilter::default().summaries(projects.clone(), &processes);
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].name, "api");
+        assert_eq!(summaries[0].running, 0);
+        assert_eq!(summaries[1].running, 2);
+        assert_eq!(summaries[1].port, Some(3001));
+
+        let running = ProjectFilter {
+            running: true,
+            ..ProjectFilter::default()
+        };
+        let summaries = running.summaries(projects, &processes);
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].root_dir, PathBuf::from("/tmp/web"));
+    }
+
+    #[test]
+    fn test_normalize_override_target() {
+        assert_eq!(normalize_override_target("4000").unwrap(), "127.0.0.1:4000");
+        assert_eq!(
+            normalize_override_target("Staging.Local:8443").unwrap(),
+            "staging.local:8443"
         );
+        assert_eq!(
+            normalize_override_target("[::1]:4000").unwrap(),
+            "[::1]:4000"
+        );
+        assert!(normalize_override_target("0").is_err());
+        assert!(normalize_override_target("localhost").is_err());
+        assert!(normalize_override_target(":4000").is_err());
+        assert!(normalize_override_target("http://x:4000").is_err());
     }
-    if name.starts_with('-') || name.starts_with('_') {
-        anyhow::bail!("Project name cannot start with a hyphen or underscore");
+
+    #[test]
+    fn test_upstream() {
+        let mut options = ProxyOptions::default();
+        assert_eq!(options.upstream(3000), "127.0.0.1:3000");
+        options.upstream_host = Some("devbox.lan".to_string());
+        assert_eq!(options.upstream(3000), "devbox.lan:3000");
+        options.upstream_host = Some("fd00::1".to_string());
+        assert_eq!(options.upstream(3000), "[fd00::1]:3000");
     }
-    Ok(())
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_log_query() {
+        let now = Utc::now();
+        let line = LogLine {
+            project_name: "app".to_string(),
+            process_id: Uuid::nil(),
+            service: None,
+            timestamp: now,
+            is_stderr: false,
+            line: "GET /api ERROR timeout".to_string(),
+            stage: None,
+            collapsed: None,
+        };
+        let query = |grep: &str| LogQuery {
+            grep: Some(grep.to_string()),
+            ..LogQuery::default()
+        };
+        assert!(LogQuery::default().matches(&line));
+        assert!(query("error").matches(&line));
+        assert!(query("ERROR").matches(&line));
+        assert!(!query("Error").matches(&line));
+        assert!(!query("warn").matches(&line));
+
+        let since = |at| LogQuery {
+            since: Some(at),
+            ..LogQuery::default()
+        };
+        assert!(since(now).matches(&line));
+        assert!(!since(now + chrono::Duration::seconds(1)).matches(&line));
+    }
+
+    #[test]
+    fn test_priority() {
+        let priority = Priority {
+            nice: Some(10),
+            io: Some("idle".parse().unwrap()),
+        };
+        assert!(priority.validate().is_ok());
+        assert!(!priority.is_default());
+        assert_eq!(priority.to_string(), "nice 10, io idle");
+
+        let reset = Priority {
+            nice: Some(0),
+            io: Some(IoClass::BestEffort),
+        };
+        assert!(reset.is_default());
+        assert!(Priority::default().is_default());
+        assert!(Priority {
+            nice: Some(20),
+            io: None
+        }
+        .validate()
+        .is_err());
+        assert!("realtime".parse::<IoClass>().is_err());
+    }
+
+    #[test]
+    fn test_resource_limits() {
+        assert_eq!(parse_size("2G").unwrap(), 2 << 30);
+        assert_eq!(parse_size("512MiB").unwrap(), 512 << 20);
+        assert_eq!(parse_size("1.5g").unwrap(), 3 << 29);
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert!(parse_size("2X").is_err());
+        assert!(parse_size("lots").is_err());
+        assert_eq!(format_size(3 << 29), "1.5 GiB");
+        assert_eq!(format_size(512 << 20), "512 MiB");
+
+        let limits = ResourceLimits {
+            memory_bytes: Some(2 << 30),
+            cpus: Some(2.0),
+            open_files: Some(1024),
+        };
+        assert!(limits.validate().is_ok());
+        assert_eq!(limits.to_string(), "2 GiB memory, 2 CPUs, 1024 open files");
+        assert!(ResourceLimits::default().is_empty());
+        assert!(ResourceLimits {
+            cpus: Some(0.0),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
 
     #[test]
-    fn test_validate_project_name() {
-        assert!(validate_project_name("my-app").is_ok());
-        assert!(validate_project_name("my_app").is_ok());
-        assert!(validate_project_name("myapp123").is_ok());
-        assert!(validate_project_name("").is_err());
-        assert!(validate_project_name("-myapp").is_err());
-        assert!(validate_project_name("my app").is_err());
-        assert!(validate_project_name("my.app").is_err());
+    fn test_event_schema() {
+        let event = Event {
+            timestamp: Utc::now(),
+            project_name: "app".to_string(),
+            kind: EventKind::RouteAdded { port: 4000 },
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["event"], "route_added");
+        assert_eq!(json["port"], 4000);
+        assert_eq!(serde_json::from_value::<Event>(json).unwrap(), event);
+
+        let filters = EventFilters {
+            project_name: Some("app".to_string()),
+            events: vec!["route_added".to_string()],
+        };
+        assert!(filters.matches(&event));
+        assert!(!EventFilters {
+            events: vec!["route_removed".to_string()],
+            ..Default::default()
+        }
+        .matches(&event));
+    }
+
+    #[test]
+    fn test_config_urls() {
+        let config: Config =
+            serde_json::from_str(r#"{"tld": ".test", "bind_addr": "0.0.0.0"}"#).unwrap();
+        assert_eq!(config.host("app"), "app.test");
+        assert_eq!(config.url("app"), "http://app.test:8080");
+        assert_eq!(
+            config.https_url("app").as_deref(),
+            Some("https://app.test:8443")
+        );
+        assert_eq!(config.proxy_addr().to_string(), "127.0.0.1:8080");
+        assert_eq!(config.changes(&Config::default()), vec!["bind_addr", "tld"]);
+
+        let config = Config {
+            proxy_port: 80,
+            https_port: 0,
+            ..Config::default()
+        };
+        assert_eq!(config.url("app"), "http://app.localhost");
+        assert_eq!(config.https_url("app"), None);
+
+        let mut project = Project::new("app".to_string(), PathBuf::from("/nonexistent"));
+        assert_eq!(
+            config.project_url(&project).as_deref(),
+            Some("http://app.localhost")
+        );
+        project.proxy.direct = true;
+        assert_eq!(config.project_url(&project), None);
+        project.port = Some(3000);
+        assert_eq!(
+            config.project_url(&project).as_deref(),
+            Some("http://localhost:3000")
+        );
+    }
+
+    #[test]
+    fn test_lan_urls() {
+        let addrs: Vec<IpAddr> = [
+            "192.168.1.5",
+            "169.254.0.9",
+            "10.8.0.2",
+            "fe80::1",
+            "fd00::5",
+        ]
+        .iter()
+        .map(|ip| ip.parse().unwrap())
+        .collect();
+        assert!(Config::default().lan_urls(&addrs).is_empty());
+
+        let config: Config =
+            serde_json::from_str(r#"{"bind_addr": "0.0.0.0", "forward_proxy": "10.8.0.2:8888"}"#)
+                .unwrap();
+        assert_eq!(
+            config.lan_urls(&addrs),
+            [
+                "http://192.168.1.5:8080",
+                "http://10.8.0.2:8080",
+                "socks5://10.8.0.2:8888"
+            ]
+        );
+
+        let config: Config = serde_json::from_str(r#"{"bind_addr": "::"}"#).unwrap();
+        assert_eq!(
+            config.lan_urls(&addrs).last().unwrap(),
+            "http://[fd00::5]:8080"
+        );
+        // A specific address only while the machine has it
+        let config: Config = serde_json::from_str(r#"{"bind_addr": "192.168.1.9"}"#).unwrap();
+        assert!(config.lan_urls(&addrs).is_empty());
+    }
+
+    #[test]
+    fn test_unversioned_status() {
+        // What daemons from before the handshake send
+        let json =
+            r#"{"type":"status","data":{"running":true,"project_count":2,"process_count":1}}"#;
+        match serde_json::from_str(json).unwrap() {
+            IpcResponse::Status {
+                protocol_version,
+                daemon_version,
+                ..
+            } => assert_eq!((protocol_version, daemon_version.as_str()), (0, "")),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_auto_run() {
+        let auto_run = AutoRun::default();
+        assert_eq!(auto_run.verdict("npm"), AutoRunVerdict::Run);
+        assert_eq!(
+            auto_run.verdict("./node_modules/.bin/npx"),
+            AutoRunVerdict::Run
+        );
+        assert_eq!(auto_run.verdict("rm"), AutoRunVerdict::Refuse);
+        assert_eq!(auto_run.verdict("/bin/rm"), AutoRunVerdict::Refuse);
+        assert_eq!(auto_run.verdict("vite"), AutoRunVerdict::Confirm);
+
+        let auto_run = AutoRun {
+            allow: vec!["*".to_string()],
+            deny: vec!["rm".to_string()],
+        };
+        assert_eq!(auto_run.verdict("vite"), AutoRunVerdict::Run);
+        assert_eq!(auto_run.verdict("rm"), AutoRunVerdict::Refuse);
+    }
+
+    #[test]
+    fn test_ipc_error() {
+        let response = IpcResponse::Error(IpcError::ProjectNotFound {
+            name: "api".to_string(),
+        });
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            r#"{"type":"error","data":{"code":"project_not_found","name":"api"}}"#
+        );
+
+        // Typed errors survive a trip through anyhow; others keep their causes
+        let spawn = IpcError::SpawnFailed {
+            command: "vite".to_string(),
+            source: RemoteError("No such file or directory".to_string()),
+            suggestions: vec!["Did you mean ./node_modules/.bin/vite?".to_string()],
+        };
+        assert_eq!(IpcError::from(anyhow::Error::from(spawn.clone())), spawn);
+        assert_eq!(
+            format!("{:#}", anyhow::Error::from(spawn)),
+            "Failed to start `vite`: No such file or directory"
+        );
+        let failed = IpcError::from(anyhow::anyhow!("disk full").context("Failed to save"));
+        assert_eq!(failed.to_string(), "Failed to save: disk full");
     }
 }
@@ -0,0 +1,257 @@
+//! Unknown and deprecated keys in hand-edited files
+//!
+//! serde ignores keys it doesn't know, so a typo like `restart_polcy` silently
+//! falls back to the default. These checks walk a parsed file alongside the
+//! JSON Schema of the type it's read as, suggesting the closest known key.
+
+use schemars::JsonSchema;
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Something wrong with one key of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// Dotted path of the key, e.g. `services.web.restart_polcy`
+    pub path: String,
+    pub kind: FindingKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FindingKind {
+    /// Not a key of the type; `suggestion` is the known key it most resembles
+    Unknown { suggestion: Option<String> },
+    /// Still read, but going away
+    Deprecated,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            FindingKind::Unknown {
+                suggestion: Some(suggestion),
+            } => write!(
+                f,
+                "unknown key '{}' (did you mean '{}'?)",
+                self.path, suggestion
+            ),
+            FindingKind::Unknown { suggestion: None } => {
+                write!(f, "unknown key '{}'", self.path)
+            }
+            FindingKind::Deprecated => write!(f, "deprecated key '{}'", self.path),
+        }
+    }
+}
+
+/// Unknown and deprecated keys of `value`, read as a `T`
+pub fn check<T: JsonSchema>(value: &Value) -> Vec<Finding> {
+    let schema = schemars::schema_for!(T);
+    let mut findings = Vec::new();
+    walk(
+        value,
+        schema.as_value(),
+        schema.as_value(),
+        "",
+        &mut findings,
+    );
+    findings
+}
+
+/// The candidate closest to `key`, if any is a plausible typo of it. Failing
+/// that, the only candidate sharing its first word (`restart_polcy` → `restart`).
+pub fn did_you_mean<'a>(
+    key: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let candidates: Vec<&str> = candidates.into_iter().collect();
+    let max_distance = (key.chars().count() / 3).max(2);
+    let closest = candidates
+        .iter()
+        .map(|candidate| (edit_distance(key, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate);
+    if closest.is_some() {
+        return closest;
+    }
+
+    let first_word = |s: &'a str| s.split('_').next().unwrap_or(s);
+    let word = key.split('_').next().unwrap_or(key);
+    match candidates
+        .iter()
+        .filter(|c| first_word(c) == word)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [only] => Some(**only),
+        _ => None,
+    }
+}
+
+fn walk(value: &Value, schema: &Value, root: &Value, path: &str, findings: &mut Vec<Finding>) {
+    let schema = resolve(schema, root);
+    match value {
+        Value::Object(object) => walk_object(object, schema, root, path, findings),
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    walk(
+                        item,
+                        item_schema,
+                        root,
+                        &format!("{}[{}]", path, i),
+                        findings,
+                    );
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn walk_object(
+    object: &Map<String, Value>,
+    schema: &Value,
+    root: &Value,
+    path: &str,
+    findings: &mut Vec<Finding>,
+) {
+    // Options and enums are `anyOf`/`oneOf`; a key is known if any branch has it
+    let mut branches = vec![schema];
+    for keyword in ["anyOf", "oneOf", "allOf"] {
+        if let Some(Value::Array(options)) = schema.get(keyword) {
+            branches.extend(options.iter().map(|option| resolve(option, root)));
+        }
+    }
+
+    // Maps have arbitrary keys; check their values instead
+    if let Some(values) = branches
+        .iter()
+        .find_map(|branch| branch.get("additionalProperties").filter(|v| v.is_object()))
+    {
+        for (key, value) in object {
+            walk(value, values, root, &join(path, key), findings);
+        }
+        return;
+    }
+
+    let properties: Vec<&Map<String, Value>> = branches
+        .iter()
+        .filter_map(|branch| branch.get("properties")?.as_object())
+        .collect();
+    if properties.is_empty() {
+        return;
+    }
+    for (key, value) in object {
+        let key_path = join(path, key);
+        match properties.iter().find_map(|p| p.get(key)) {
+            Some(property) => {
+                let deprecated =
+                    |schema: &Value| schema.get("deprecated") == Some(&Value::Bool(true));
+                if deprecated(property) || deprecated(resolve(property, root)) {
+                    findings.push(Finding {
+                        path: key_path.clone(),
+                        kind: FindingKind::Deprecated,
+                    });
+                }
+                walk(value, property, root, &key_path, findings);
+            }
+            None => {
+                let known = properties.iter().flat_map(|p| p.keys().map(String::as_str));
+                findings.push(Finding {
+                    path: key_path,
+                    kind: FindingKind::Unknown {
+                        suggestion: did_you_mean(key, known).map(String::from),
+                    },
+                });
+            }
+        }
+    }
+}
+
+/// Follow a `$ref` into the root schema's `$defs`
+fn resolve<'a>(schema: &'a Value, root: &'a Value) -> &'a Value {
+    match schema
+        .get("$ref")
+        .and_then(Value::as_str)
+        .and_then(|r| r.strip_prefix("#/$defs/"))
+    {
+        Some(name) => root
+            .get("$defs")
+            .and_then(|defs| defs.get(name))
+            .unwrap_or(schema),
+        None => schema,
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Levenshtein distance
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Project};
+
+    #[test]
+    fn test_unknown_keys() {
+        let project = serde_json::json!({
+            "name": "web",
+            "services": {
+                "dev": {"command": "npm", "restart_polcy": "always", "health": {"pth": "/"}},
+            },
+            "schedule": {"windows": [{"start": "09:00", "end": "17:00", "dayz": []}], "command": []},
+            "hooks": {"pre_run": "make", "colour": "red"},
+        });
+        let findings: Vec<String> = check::<Project>(&project)
+            .iter()
+            .map(|f| f.to_string())
+            .collect();
+        assert_eq!(
+            findings,
+            vec![
+                "unknown key 'hooks.colour'",
+                "unknown key 'schedule.windows[0].dayz' (did you mean 'days'?)",
+                "unknown key 'services.dev.health.pth' (did you mean 'path'?)",
+                "unknown key 'services.dev.restart_polcy' (did you mean 'restart'?)",
+            ]
+        );
+
+        let config = serde_json::json!({"proxy_prot": 80, "groups": {"anything": ["a"]}});
+        assert_eq!(
+            check::<Config>(&config),
+            vec![Finding {
+                path: "proxy_prot".to_string(),
+                kind: FindingKind::Unknown {
+                    suggestion: Some("proxy_port".to_string())
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_did_you_mean() {
+        let known = ["restart", "max_restarts", "command"];
+        assert_eq!(did_you_mean("restrat", known), Some("restart"));
+        assert_eq!(did_you_mean("max_restart", known), Some("max_restarts"));
+        assert_eq!(did_you_mean("colour", known), None);
+    }
+}
@@ -0,0 +1,279 @@
+//! Versioned stored formats and the migrations between them
+//!
+//! Documents record their format in a top-level `version` key; those written
+//! before versioning are version 0. Loading runs each step from a document's
+//! version up to the current one on the raw JSON, before serde sees it, so
+//! renamed or reshaped fields carry over instead of falling back to defaults.
+//! Documents from a newer proj are never migrated or saved over, since this
+//! build would silently drop the fields it doesn't know.
+
+use serde_json::{Map, Value};
+
+/// Rewrites a document from the previous version to the next, returning a line
+/// for each change made
+type Step = fn(&mut Map<String, Value>) -> Vec<String>;
+
+/// A versioned document format
+pub struct Format {
+    /// What the documents are, for messages
+    pub name: &'static str,
+    /// Migrations from version `i` to `i + 1`, in order. The current version
+    /// is the number of steps.
+    steps: &'static [Step],
+}
+
+/// Stored projects. Bump it, with a step, whenever a field is added or
+/// changed, so older builds know not to save over them.
+pub const PROJECT: Format = Format {
+    name: "project",
+    steps: &[
+        project_v1,
+        project_v2,
+        project_v3,
+        project_v4,
+        project_v5,
+        project_v6,
+        project_v7,
+        project_v8,
+        project_v9,
+        project_v10,
+        project_v11,
+        project_v12,
+        project_v13,
+        project_v14,
+        project_v15,
+        project_v16,
+        project_v17,
+        project_v18,
+        project_v19,
+        project_v20,
+        project_v21,
+        project_v22,
+        project_v23,
+    ],
+};
+
+/// 0 → 1: versioning starts; nothing else changes
+fn project_v1(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 1 → 2: adds `priority`, which defaults to the normal priority
+fn project_v2(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 2 → 3: adds `env_profiles`, which defaults to none
+fn project_v3(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 3 → 4: adds `proxy.routes`, which defaults to none
+fn project_v4(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 4 → 5: adds `sandbox`, which defaults to off
+fn project_v5(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 5 → 6: adds `aliases`, which defaults to none
+fn project_v6(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 6 → 7: adds the `read-only` sandbox mode, which older builds can't read
+fn project_v7(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 7 → 8: adds `proxy.rewrite_host` and `proxy.cors`, which default to off
+fn project_v8(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 8 → 9: adds `proxy.overrides`, which defaults to none
+fn project_v9(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 9 → 10: adds `proxy.budgets`, which defaults to none
+fn project_v10(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 10 → 11: adds `proxy.no_cache`, which defaults to off
+fn project_v11(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 11 → 12: adds `proxy.kill_service_workers`, which defaults to off
+fn project_v12(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 12 → 13: adds `services.*.ports`, which defaults to none
+fn project_v13(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 13 → 14: adds `tags` and `last_used`, which default to none
+fn project_v14(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 14 → 15: adds `output_passthrough`, which defaults to the config's
+fn project_v15(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 15 → 16: adds `proxy.upstream_host`, which defaults to this machine
+fn project_v16(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 16 → 17: adds `proxy.idle_stop_mins` and `proxy.lazy_start`, which default
+/// to off
+fn project_v17(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 17 → 18: adds `proxy.max_in_flight`, which defaults to no limit
+fn project_v18(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 18 → 19: adds `appearance`, which defaults to no icon or color
+fn project_v19(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 19 → 20: adds `proxy.rewrites`, which defaults to none
+fn project_v20(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 20 → 21: adds `proxy.keep_redirects` and `proxy.trailing_slash`, which
+/// default to pointing redirects at the proxy and leaving paths alone
+fn project_v21(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 21 → 22: adds `proxy.auth`, which defaults to no credentials
+fn project_v22(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// 22 → 23: adds `smoke`, which defaults to no checks
+fn project_v23(_project: &mut Map<String, Value>) -> Vec<String> {
+    Vec::new()
+}
+
+/// What migrating a document did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upgrade {
+    pub from: u32,
+    pub to: u32,
+    pub changes: Vec<String>,
+}
+
+/// A document was written by a newer proj than this one
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{format} format version {found} is newer than the {supported} this proj understands; upgrade proj to change it")]
+pub struct NewerFormat {
+    pub format: &'static str,
+    pub found: u32,
+    pub supported: u32,
+}
+
+impl Format {
+    /// Version written by this build
+    pub fn version(&self) -> u32 {
+        self.steps.len() as u32
+    }
+
+    /// Version a parsed document is at
+    pub fn version_of(&self, value: &Value) -> u32 {
+        value
+            .get("version")
+            .and_then(Value::as_u64)
+            .map_or(0, |v| v as u32)
+    }
+
+    /// Fail with [`NewerFormat`] if this build can't safely write a document at `version`
+    pub fn check_writable(&self, version: u32) -> Result<(), NewerFormat> {
+        if version > self.version() {
+            return Err(NewerFormat {
+                format: self.name,
+                found: version,
+                supported: self.version(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Migrate `value` to the current version in place, or `None` if it's
+    /// already there
+    pub fn upgrade(&self, value: &mut Value) -> Result<Option<Upgrade>, NewerFormat> {
+        let from = self.version_of(value);
+        self.check_writable(from)?;
+        if from == self.version() {
+            return Ok(None);
+        }
+        let Value::Object(object) = value else {
+            // Not a document serde will accept either; let parsing report it
+            return Ok(None);
+        };
+
+        let mut changes = Vec::new();
+        for (version, step) in self.steps.iter().enumerate().skip(from as usize) {
+            changes.extend(step(object));
+            object.insert("version".to_string(), Value::from(version as u32 + 1));
+        }
+        Ok(Some(Upgrade {
+            from,
+            to: self.version(),
+            changes,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rename_cmd(object: &mut Map<String, Value>) -> Vec<String> {
+        match object.remove("cmd") {
+            Some(cmd) => {
+                object.insert("command".to_string(), cmd);
+                vec!["renamed cmd to command".to_string()]
+            }
+            None => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_upgrade() {
+        let format = Format {
+            name: "test",
+            steps: &[project_v1, rename_cmd],
+        };
+        assert_eq!(format.version(), 2);
+
+        let mut legacy = serde_json::json!({"cmd": "make"});
+        let upgrade = format.upgrade(&mut legacy).unwrap().unwrap();
+        assert_eq!((upgrade.from, upgrade.to), (0, 2));
+        assert_eq!(upgrade.changes, vec!["renamed cmd to command"]);
+        assert_eq!(legacy, serde_json::json!({"command": "make", "version": 2}));
+
+        let mut current = legacy.clone();
+        assert_eq!(format.upgrade(&mut current).unwrap(), None);
+        assert_eq!(current, legacy);
+
+        let mut newer = serde_json::json!({"version": 3, "cmd": "make"});
+        let err = format.upgrade(&mut newer).unwrap_err();
+        assert_eq!((err.found, err.supported), (3, 2));
+        assert_eq!(newer["cmd"], "make");
+    }
+}
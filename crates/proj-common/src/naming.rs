@@ -0,0 +1,318 @@
+//! Naming rules shared by the CLI, the registry and the proxy
+//!
+//! Projects are served at `<name>.<tld>` and their subdomains, so project
+//! and service names are DNS labels as RFC 1123 defines them: lowercase ASCII
+//! letters, digits and inner hyphens, at most 63 characters. The TLD (the
+//! namespace projects live in) and aliases are hostnames made of such labels,
+//! at most 253 characters in all.
+
+use anyhow::Result;
+
+/// Longest label in a hostname
+pub const MAX_LABEL: usize = 63;
+
+/// Longest hostname
+pub const MAX_HOST: usize = 253;
+
+/// Room left in `<name>.<tld>` for at least a one-letter subdomain, as in
+/// the `*.<name>.<tld>` certificates the proxy issues
+const SUBDOMAIN_ROOM: usize = 2;
+
+/// Whether `label` is a hostname label: letters, digits and inner hyphens
+fn is_label(label: &str) -> bool {
+    !label.is_empty()
+        && label.len() <= MAX_LABEL
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+}
+
+/// Check a name that becomes a hostname label, suggesting a valid one
+fn validate_label(kind: &str, name: &str) -> Result<()> {
+    if name.is_empty() {
+        anyhow::bail!("{} name cannot be empty", kind);
+    }
+    if name.len() > MAX_LABEL {
+        anyhow::bail!("{} name cannot exceed {} characters", kind, MAX_LABEL);
+    }
+    if !is_label(name) || name.chars().any(|c| c.is_ascii_uppercase()) {
+        let hint = match sanitize_label(name) {
+            Some(label) => format!("; try '{}'", label),
+            None => String::new(),
+        };
+        anyhow::bail!(
+            "{} name '{}' must be lowercase letters, digits and inner hyphens{}",
+            kind,
+            name,
+            hint
+        );
+    }
+    Ok(())
+}
+
+/// Check a project name, which is the first label of its hostname
+pub fn validate_project_name(name: &str) -> Result<()> {
+    validate_label("Project", name)
+}
+
+/// Check a service name
+pub fn validate_service_name(name: &str) -> Result<()> {
+    validate_label("Service", name)
+}
+
+/// Check the domain projects are served under, e.g. `localhost` or `dev.test`
+pub fn validate_tld(tld: &str) -> Result<()> {
+    if tld.is_empty() || !tld.split('.').all(is_label) {
+        anyhow::bail!(
+            "'{}' isn't a domain of letters, digits and inner hyphens",
+            tld
+        );
+    }
+    if tld.chars().any(|c| c.is_ascii_uppercase()) {
+        anyhow::bail!("'{}' must be lowercase", tld);
+    }
+    // Such as an IPv4 address
+    if tld.split('.').all(|label| label.parse::<u64>().is_ok()) {
+        anyhow::bail!("'{}' is numeric, not a domain", tld);
+    }
+    // Room for a project and a subdomain of it
+    if tld.len() + 2 + SUBDOMAIN_ROOM > MAX_HOST {
+        anyhow::bail!("'{}' leaves no room for project names", tld);
+    }
+    Ok(())
+}
+
+/// A project's hostname, `<name>.<tld>`, when it fits with room for the
+/// subdomains the proxy also routes to the project
+pub fn project_host(name: &str, tld: &str) -> Result<String> {
+    validate_project_name(name)?;
+    let host = format!("{}.{}", name, tld);
+    if host.len() + SUBDOMAIN_ROOM > MAX_HOST {
+        anyhow::bail!(
+            "{} is too long a hostname; use a shorter project name",
+            host
+        );
+    }
+    Ok(host)
+}
+
+/// The project a request for `host` is for: the label right before `.<tld>`,
+/// so `api.my-app.localhost` (with or without a port) is `my-app`. `None`
+/// for hosts outside the TLD and for the TLD itself.
+pub fn project_from_host(host: &str, tld: &str) -> Option<String> {
+    let host = host
+        .split(':')
+        .next()?
+        .trim_end_matches('.')
+        .to_ascii_lowercase();
+    let subdomains = host.strip_suffix(tld)?.strip_suffix('.')?;
+    subdomains
+        .rsplit('.')
+        .next()
+        .filter(|label| is_label(label))
+        .map(str::to_string)
+}
+
+/// Canonical form of a hostname alias: lowercase, no trailing dot, each label
+/// made of letters, digits and inner hyphens
+pub fn normalize_alias(host: &str) -> Result<String> {
+    let host = host.trim().trim_end_matches('.').to_ascii_lowercase();
+    if host.is_empty() || host.len() > MAX_HOST || !host.split('.').all(is_label) {
+        anyhow::bail!("Invalid hostname '{}'", host);
+    }
+    if host == "localhost" || host.parse::<std::net::IpAddr>().is_ok() {
+        anyhow::bail!("'{}' addresses the proxy itself", host);
+    }
+    Ok(host)
+}
+
+/// Canonical form of the host a project runs on: a hostname or an IP address
+/// (an IPv6 one with or without brackets), without a port
+pub fn normalize_upstream_host(host: &str) -> Result<String> {
+    let host = host.trim().trim_end_matches('.').to_ascii_lowercase();
+    let bare = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(&host);
+    if let Ok(ip) = bare.parse::<std::net::IpAddr>() {
+        return Ok(ip.to_string());
+    }
+    if host.contains(':') {
+        anyhow::bail!(
+            "'{}' includes a port; give the host alone and route its ports",
+            host
+        );
+    }
+    if host.is_empty() || host.len() > MAX_HOST || !host.split('.').all(is_label) {
+        anyhow::bail!("'{}' isn't a hostname or IP address", host);
+    }
+    Ok(host)
+}
+
+/// Check an environment variable name: letters, digits and underscores, not
+/// starting with a digit, as shells accept
+pub fn validate_env_name(name: &str) -> Result<()> {
+    let valid = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && name.chars().next().is_some_and(|c| !c.is_ascii_digit());
+    if !valid {
+        anyhow::bail!(
+            "'{}' isn't a variable name (use letters, digits and underscores, not starting with a digit)",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Canonical form of a project tag: lowercase letters, digits, `-` and `_`
+pub fn normalize_tag(tag: &str) -> Result<String> {
+    let tag = tag.trim().to_lowercase();
+    let valid = tag
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+    if tag.is_empty() || tag.len() > 64 || !valid {
+        anyhow::bail!(
+            "Invalid tag '{}' (use letters, digits, hyphens and underscores)",
+            tag
+        );
+    }
+    Ok(tag)
+}
+
+/// The closest hostname label to some text, e.g. `My App.v2` -> `my-app-v2`;
+/// `None` when nothing of it is usable
+pub fn sanitize_label(text: &str) -> Option<String> {
+    let mut label = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            label.push(c.to_ascii_lowercase());
+        } else if !label.is_empty() && !label.ends_with('-') {
+            label.push('-');
+        }
+    }
+    label.truncate(MAX_LABEL);
+    let label = label.trim_end_matches('-');
+    (!label.is_empty()).then(|| label.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_project_name() {
+        assert!(validate_project_name("my-app").is_ok());
+        assert!(validate_project_name("myapp123").is_ok());
+        assert!(validate_project_name(&"a".repeat(63)).is_ok());
+        assert!(validate_project_name(&"a".repeat(64)).is_err());
+        assert!(validate_project_name("").is_err());
+        assert!(validate_project_name("-myapp").is_err());
+        assert!(validate_project_name("myapp-").is_err());
+        assert!(validate_project_name("my app").is_err());
+        assert!(validate_project_name("my.app").is_err());
+        assert!(validate_project_name("café").is_err());
+        let error = validate_project_name("My_App").unwrap_err().to_string();
+        assert!(error.ends_with("try 'my-app'"), "{}", error);
+        assert!(validate_service_name("worker-2").is_ok());
+        assert!(validate_service_name("worker_2").is_err());
+    }
+
+    #[test]
+    fn test_validate_tld() {
+        assert!(validate_tld("localhost").is_ok());
+        assert!(validate_tld("dev.test").is_ok());
+        assert!(validate_tld("").is_err());
+        assert!(validate_tld("dev..test").is_err());
+        assert!(validate_tld("Test").is_err());
+        assert!(validate_tld("127.0.0.1").is_err());
+        assert!(validate_tld(&vec!["a".repeat(63); 4].join(".")).is_err());
+    }
+
+    #[test]
+    fn test_project_host() {
+        assert_eq!(
+            project_host("my-app", "localhost").unwrap(),
+            "my-app.localhost"
+        );
+        let tld = vec!["a".repeat(63); 3].join(".");
+        assert!(project_host(&"b".repeat(59), &tld).is_ok());
+        assert!(project_host(&"b".repeat(60), &tld).is_err());
+    }
+
+    #[test]
+    fn test_project_from_host() {
+        let project = |host| project_from_host(host, "localhost");
+        assert_eq!(project("my-app.localhost").as_deref(), Some("my-app"));
+        assert_eq!(project("My-App.localhost:8080").as_deref(), Some("my-app"));
+        assert_eq!(project("api.my-app.localhost").as_deref(), Some("my-app"));
+        assert_eq!(project("my-app.localhost.").as_deref(), Some("my-app"));
+        assert_eq!(project("localhost"), None);
+        assert_eq!(project(".localhost"), None);
+        assert_eq!(project("my-app.example.com"), None);
+        assert_eq!(project("notlocalhost"), None);
+        assert_eq!(
+            project_from_host("web.dev.test", "dev.test").as_deref(),
+            Some("web")
+        );
+    }
+
+    #[test]
+    fn test_normalize_alias() {
+        assert_eq!(normalize_alias("App.Dev.Local.").unwrap(), "app.dev.local");
+        assert_eq!(normalize_alias("my-app.test").unwrap(), "my-app.test");
+        assert!(normalize_alias("").is_err());
+        assert!(normalize_alias("-app.test").is_err());
+        assert!(normalize_alias("app..test").is_err());
+        assert!(normalize_alias("app_1.test").is_err());
+        assert!(normalize_alias("localhost").is_err());
+        assert!(normalize_alias("127.0.0.1").is_err());
+    }
+
+    #[test]
+    fn test_normalize_upstream_host() {
+        assert_eq!(
+            normalize_upstream_host("Devbox.LAN.").unwrap(),
+            "devbox.lan"
+        );
+        assert_eq!(normalize_upstream_host("localhost").unwrap(), "localhost");
+        assert_eq!(normalize_upstream_host("10.0.0.5").unwrap(), "10.0.0.5");
+        assert_eq!(normalize_upstream_host("[FD00::1]").unwrap(), "fd00::1");
+        assert_eq!(normalize_upstream_host("fd00::1").unwrap(), "fd00::1");
+        assert!(normalize_upstream_host("devbox:8080").is_err());
+        assert!(normalize_upstream_host("10.0.0.5:8080").is_err());
+        assert!(normalize_upstream_host("[fd00::1]:8080").is_err());
+        assert!(normalize_upstream_host("").is_err());
+        assert!(normalize_upstream_host("dev box").is_err());
+        assert!(normalize_upstream_host("devbox/api").is_err());
+    }
+
+    #[test]
+    fn test_validate_env_name() {
+        assert!(validate_env_name("DATABASE_URL").is_ok());
+        assert!(validate_env_name("_private2").is_ok());
+        assert!(validate_env_name("").is_err());
+        assert!(validate_env_name("2FA").is_err());
+        assert!(validate_env_name("MY-VAR").is_err());
+        assert!(validate_env_name("A.B").is_err());
+    }
+
+    #[test]
+    fn test_normalize_tag() {
+        assert_eq!(normalize_tag(" Client-X ").unwrap(), "client-x");
+        assert_eq!(normalize_tag("back_end").unwrap(), "back_end");
+        assert!(normalize_tag("").is_err());
+        assert!(normalize_tag("a b").is_err());
+    }
+
+    #[test]
+    fn test_sanitize_label() {
+        assert_eq!(sanitize_label("My App.v2").as_deref(), Some("my-app-v2"));
+        assert_eq!(sanitize_label("__init__").as_deref(), Some("init"));
+        assert_eq!(sanitize_label("café-ü").as_deref(), Some("caf"));
+        assert_eq!(sanitize_label("日本"), None);
+        assert_eq!(sanitize_label(&"x".repeat(80)).unwrap().len(), MAX_LABEL);
+        assert_eq!(
+            sanitize_label(&format!("{}-y", "x".repeat(62))).as_deref(),
+            Some("x".repeat(62).as_str())
+        );
+    }
+}
@@ -0,0 +1,272 @@
+//! Notifications - where the daemon tells you that a process crashed, that
+//! traffic went over a performance budget or that one of its tasks panicked
+//!
+//! The `notifications` setting lists [`Backend`]s: desktop popups, the
+//! terminal bell of `proj watch`, an HTTP POST to a webhook, or a shell
+//! command given the notification (e.g. `curl -d @- ntfy.sh/<topic>` to reach
+//! a phone). `notifications.backends` applies to the events that notify by
+//! default ([`DEFAULT_EVENTS`]), and `notifications.events` sets the backends
+//! of one event type, including ones that don't notify otherwise; an empty
+//! list there silences it.
+//!
+//! While do-not-disturb is on, which it is by default while a foreground
+//! session (a `--tty` run or `attach`) is attached in any terminal, only
+//! [`CRITICAL_EVENTS`] notify; the rest are held and sent as one summary when
+//! it ends. [`DndMode`] overrides that by hand.
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::EventKind;
+
+/// Events that notify without being listed in `notifications.events`
+pub const DEFAULT_EVENTS: [&str; 3] = ["process_exited", "budget", "panic"];
+
+/// Events that notify even during do-not-disturb
+pub const CRITICAL_EVENTS: [&str; 2] = ["process_exited", "panic"];
+
+/// Somewhere notifications go
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Backend {
+    /// A popup, with `osascript` on macOS and `notify-send` on Linux
+    Desktop,
+    /// A `notification` event, for which `proj watch` rings the terminal bell
+    Bell,
+    /// A JSON POST to an `http://` or `https://` URL
+    Webhook { url: String },
+    /// A shell command, given the message on stdin and in `PROJ_NOTIFY_*`
+    /// variables
+    Command { command: String },
+}
+
+impl Backend {
+    /// The `type` it's written with
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Desktop => "desktop",
+            Self::Bell => "bell",
+            Self::Webhook { .. } => "webhook",
+            Self::Command { .. } => "command",
+        }
+    }
+}
+
+/// The `notifications` setting
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NotificationConfig {
+    /// Backends of the events that notify by default
+    #[serde(default = "default_backends")]
+    pub backends: Vec<Backend>,
+    /// Backends of one event type, by its name, over `backends`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub events: BTreeMap<String, Vec<Backend>>,
+}
+
+fn default_backends() -> Vec<Backend> {
+    vec![Backend::Desktop]
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            backends: default_backends(),
+            events: BTreeMap::new(),
+        }
+    }
+}
+
+impl NotificationConfig {
+    /// Check the event names and webhook URLs
+    pub fn validate(&self) -> Result<()> {
+        for (name, backends) in &self.events {
+            if !EventKind::NAMES.contains(&name.as_str()) || name == "notification" {
+                anyhow::bail!("Unknown event '{}' in notifications.events", name);
+            }
+            check_backends(backends)?;
+        }
+        check_backends(&self.backends)
+    }
+
+    /// Backends an event of type `event` goes to
+    pub fn backends_for(&self, event: &str) -> &[Backend] {
+        match self.events.get(event) {
+            Some(backends) => backends,
+            None if DEFAULT_EVENTS.contains(&event) => &self.backends,
+            None => &[],
+        }
+    }
+}
+
+fn check_backends(backends: &[Backend]) -> Result<()> {
+    for backend in backends {
+        match backend {
+            Backend::Webhook { url } => {
+                WebhookUrl::parse(url)?;
+            }
+            Backend::Command { command } if command.trim().is_empty() => {
+                anyhow::bail!("Notification command is empty");
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// When non-critical notifications are held (`proj dnd`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DndMode {
+    /// While a foreground session is attached
+    #[default]
+    Auto,
+    /// Until the mode changes
+    On,
+    /// Never
+    Off,
+}
+
+impl fmt::Display for DndMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Auto => "auto",
+            Self::On => "on",
+            Self::Off => "off",
+        })
+    }
+}
+
+/// Where do-not-disturb stands
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DndStatus {
+    pub mode: DndMode,
+    /// Foreground sessions attached now
+    pub sessions: usize,
+    /// Whether notifications are being held
+    pub active: bool,
+    /// Notifications held for the summary
+    pub held: usize,
+}
+
+/// Where a webhook posts to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookUrl {
+    pub tls: bool,
+    pub host: String,
+    pub port: u16,
+    /// Path and query, starting with `/`
+    pub path: String,
+}
+
+impl WebhookUrl {
+    pub fn parse(url: &str) -> Result<Self> {
+        let (tls, rest) = match url.split_once("://") {
+            Some(("https", rest)) => (true, rest),
+            Some(("http", rest)) => (false, rest),
+            _ => anyhow::bail!("Webhook '{}' isn't an http:// or https:// URL", url),
+        };
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        if authority.contains('@') {
+            anyhow::bail!(
+                "Webhook '{}' has credentials in its URL; put a token in the path or use a command",
+                url
+            );
+        }
+        let default_port = if tls { 443 } else { 80 };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => match port.parse() {
+                Ok(port) => (host, port),
+                Err(_) => anyhow::bail!("Webhook '{}' has an invalid port", url),
+            },
+            _ => (authority, default_port),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            anyhow::bail!("Webhook '{}' has no host", url);
+        }
+        let path = if path.starts_with('?') {
+            format!("/{}", path)
+        } else {
+            path.to_string()
+        };
+        Ok(Self {
+            tls,
+            host: host.to_string(),
+            port,
+            path,
+        })
+    }
+
+    /// The `Host` header: the host, with the port unless it's the scheme's
+    pub fn authority(&self) -> String {
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+        match (self.tls, self.port) {
+            (true, 443) | (false, 80) => host,
+            (_, port) => format!("{}:{}", host, port),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backends_for() {
+        let config: NotificationConfig = serde_json::from_value(serde_json::json!({
+            "events": {
+                "panic": [],
+                "crash_bundle": [{"type": "command", "command": "cat > /tmp/crash"}],
+                "process_exited": [
+                    {"type": "bell"},
+                    {"type": "webhook", "url": "https://ntfy.sh/my-topic"}
+                ]
+            }
+        }))
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.backends_for("budget"), &[Backend::Desktop]);
+        assert!(config.backends_for("panic").is_empty());
+        assert!(config.backends_for("route_added").is_empty());
+        assert_eq!(config.backends_for("crash_bundle")[0].name(), "command");
+        assert_eq!(config.backends_for("process_exited").len(), 2);
+
+        let unknown: NotificationConfig =
+            serde_json::from_value(serde_json::json!({"events": {"crash": []}})).unwrap();
+        assert!(unknown.validate().is_err());
+        assert!(serde_json::from_value::<NotificationConfig>(
+            serde_json::json!({"backends": [{"type": "pager"}]})
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_webhook_url() {
+        let url = WebhookUrl::parse("https://ntfy.sh/my-topic?x=1").unwrap();
+        assert!(url.tls);
+        assert_eq!((url.host.as_str(), url.port), ("ntfy.sh", 443));
+        assert_eq!(url.path, "/my-topic?x=1");
+        assert_eq!(url.authority(), "ntfy.sh");
+
+        let url = WebhookUrl::parse("http://[::1]:8080").unwrap();
+        assert!(!url.tls);
+        assert_eq!((url.host.as_str(), url.port), ("::1", 8080));
+        assert_eq!(url.path, "/");
+        assert_eq!(url.authority(), "[::1]:8080");
+
+        assert!(WebhookUrl::parse("ntfy.sh/topic").is_err());
+        assert!(WebhookUrl::parse("https://user:pw@example.com/").is_err());
+        assert!(WebhookUrl::parse("http://:80/").is_err());
+    }
+}
@@ -0,0 +1,220 @@
+//! Profiled runs - a run under its stack's profiler (`run --profile cpu|heap`)
+//!
+//! Node is profiled through its inspector by a `--require`d helper, Python runs
+//! under cProfile or memray, and native programs under samply, else perf, for
+//! CPU and heaptrack for heap. Each run's profile goes to its own directory
+//! under `~/.proj/projects/<name>/profiles/`, next to the logs, and is
+//! written when the process exits. Node and Python die on SIGTERM without
+//! writing their profile, so their helpers turn it into a normal exit -
+//! `proj <name> stop` then leaves a profile behind.
+
+use crate::debug::{self, Stack};
+use crate::project_dir;
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// What a profiled run measures
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileKind {
+    Cpu,
+    Heap,
+}
+
+impl std::str::FromStr for ProfileKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "cpu" => Ok(Self::Cpu),
+            "heap" | "mem" => Ok(Self::Heap),
+            _ => anyhow::bail!("Unknown profile '{}' (expected cpu or heap)", s),
+        }
+    }
+}
+
+impl std::fmt::Display for ProfileKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cpu => write!(f, "cpu"),
+            Self::Heap => write!(f, "heap"),
+        }
+    }
+}
+
+/// Where a profiled run writes its profile, and how to look at it
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Profile {
+    pub kind: ProfileKind,
+    /// The profile, or the directory the profiler names it in
+    pub path: PathBuf,
+    /// Command or steps to open it
+    pub open_with: String,
+}
+
+/// Node helper, loaded with `--require`, profiling through the inspector
+const NODE_HELPER: (&str, &str) = ("profile.js", include_str!("profile_helpers/profile.js"));
+
+/// Python helper running a profiler module, exiting normally on SIGTERM
+const PYTHON_HELPER: (&str, &str) = (
+    "exit_on_signal.py",
+    include_str!("profile_helpers/exit_on_signal.py"),
+);
+
+/// Directory a project's profiles are kept in
+pub fn profiles_dir(project_name: &str) -> Result<PathBuf> {
+    Ok(project_dir(project_name)?.join("profiles"))
+}
+
+/// Create a run's profile directory, with the helpers [`instrument`] uses
+pub fn prepare(dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    for (name, source) in [NODE_HELPER, PYTHON_HELPER] {
+        std::fs::write(dir.join(name), source)?;
+    }
+    Ok(())
+}
+
+/// Wrap a run's command (program and arguments) and environment to profile
+/// it into `dir` (see [`prepare`]). `installed` tells whether a tool is on
+/// the daemon's PATH.
+pub fn instrument(
+    command: &mut Vec<String>,
+    env: &mut BTreeMap<String, String>,
+    kind: ProfileKind,
+    dir: &Path,
+    installed: impl Fn(&str) -> bool,
+) -> Result<Profile> {
+    let stack = Stack::of(command)?;
+    let (path, open_with) = match (stack, kind) {
+        (Stack::Node, _) => {
+            // `--cpu-prof` and `--heap-prof` aren't allowed in NODE_OPTIONS
+            let require = format!("--require \"{}\"", dir.join(NODE_HELPER.0).display());
+            let options = match env.get("NODE_OPTIONS") {
+                Some(existing) => format!("{} {}", existing, require),
+                None => require,
+            };
+            env.insert("NODE_OPTIONS".to_string(), options);
+            env.insert("PROJ_PROFILE".to_string(), kind.to_string());
+            let (file, panel) = match kind {
+                ProfileKind::Cpu => (".cpuprofile", "Performance"),
+                ProfileKind::Heap => (".heapprofile", "Memory"),
+            };
+            let open_with = format!(
+                "load the {} file in Chrome DevTools' {} panel, or at https://www.speedscope.app",
+                file, panel
+            );
+            (dir.to_path_buf(), open_with)
+        }
+        (Stack::Python, ProfileKind::Cpu) => {
+            let path = dir.join("profile.prof");
+            let out = path.display().to_string();
+            let helper = dir.join(PYTHON_HELPER.0).display().to_string();
+            debug::run_python_under(command, &[&helper, "cProfile", "-o", &out]);
+            (path.clone(), format!("snakeviz {}", path.display()))
+        }
+        (Stack::Python, ProfileKind::Heap) => {
+            let path = dir.join("heap.bin");
+            let out = path.display().to_string();
+            let helper = dir.join(PYTHON_HELPER.0).display().to_string();
+            debug::run_python_under(command, &[&helper, "memray", "run", "-o", &out]);
+            (
+                path.clone(),
+                format!("python3 -m memray flamegraph {}", path.display()),
+            )
+        }
+        (Stack::Native, ProfileKind::Cpu) if installed("samply") => {
+            let path = dir.join("profile.json.gz");
+            let out = path.display().to_string();
+            debug::run_under(
+                command,
+                &["samply", "record", "--save-only", "-o", &out, "--"],
+            );
+            (path.clone(), format!("samply load {}", path.display()))
+        }
+        (Stack::Native, ProfileKind::Cpu) if installed("perf") => {
+            let path = dir.join("perf.data");
+            let out = path.display().to_string();
+            debug::run_under(command, &["perf", "record", "-g", "-o", &out, "--"]);
+            (path.clone(), format!("perf report -i {}", path.display()))
+        }
+        (Stack::Native, ProfileKind::Cpu) => {
+            anyhow::bail!("Profiling native CPU use needs samply (cargo install samply) or perf")
+        }
+        (Stack::Native, ProfileKind::Heap) if installed("heaptrack") => {
+            let out = dir.join("heaptrack").display().to_string();
+            debug::run_under(command, &["heaptrack", "-o", &out]);
+            let open_with = format!("heaptrack_gui {}/heaptrack.*", dir.display());
+            (dir.to_path_buf(), open_with)
+        }
+        (Stack::Native, ProfileKind::Heap) => {
+            anyhow::bail!("Profiling native heap use needs heaptrack")
+        }
+    };
+    if stack == Stack::Native {
+        env.insert("RUST_BACKTRACE".to_string(), "1".to_string());
+    }
+    Ok(Profile {
+        kind,
+        path,
+        open_with,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instrumented(
+        command: &str,
+        kind: ProfileKind,
+        installed: &[&str],
+    ) -> Result<(Vec<String>, BTreeMap<String, String>, Profile)> {
+        let mut command: Vec<String> = command.split_whitespace().map(str::to_string).collect();
+        let mut env = BTreeMap::new();
+        let dir = Path::new("/p");
+        let profile = instrument(&mut command, &mut env, kind, dir, |tool| {
+            installed.contains(&tool)
+        })?;
+        Ok((command, env, profile))
+    }
+
+    #[test]
+    fn test_instrument() {
+        let (command, env, profile) = instrumented("npm start", ProfileKind::Cpu, &[]).unwrap();
+        assert_eq!(command, ["npm", "start"]);
+        assert_eq!(env["NODE_OPTIONS"], "--require \"/p/profile.js\"");
+        assert_eq!(env["PROJ_PROFILE"], "cpu");
+        assert_eq!(profile.path, Path::new("/p"));
+
+        let (command, _, profile) =
+            instrumented("uvicorn main:app", ProfileKind::Heap, &[]).unwrap();
+        assert_eq!(
+            command.join(" "),
+            "python3 /p/exit_on_signal.py memray run -o /p/heap.bin -m uvicorn main:app"
+        );
+        assert_eq!(
+            profile.open_with,
+            "python3 -m memray flamegraph /p/heap.bin"
+        );
+
+        // samply is preferred over perf
+        let installed = ["perf", "samply"];
+        let (command, env, _) =
+            instrumented("./target/release/api", ProfileKind::Cpu, &installed).unwrap();
+        assert_eq!(
+            command.join(" "),
+            "samply record --save-only -o /p/profile.json.gz -- ./target/release/api"
+        );
+        assert_eq!(env["RUST_BACKTRACE"], "1");
+        let (command, _, _) = instrumented("cargo run", ProfileKind::Cpu, &["perf"]).unwrap();
+        assert_eq!(
+            command[2],
+            "target.'cfg(all())'.runner=[\"perf\",\"record\",\"-g\",\"-o\",\"/p/perf.data\",\"--\"]"
+        );
+        assert!(instrumented("cargo run", ProfileKind::Heap, &["perf"]).is_err());
+    }
+}
@@ -0,0 +1,9 @@
+# Runs a profiler module (`exit_on_signal.py cProfile -o out app.py`) for proj's
+# profiled runs, exiting normally on SIGTERM so the profiler writes its output.
+import runpy
+import signal
+import sys
+
+signal.signal(signal.SIGTERM, lambda *_: sys.exit(143))
+sys.argv = sys.argv[1:]
+runpy.run_module(sys.argv[0], run_name="__main__", alter_sys=True)
@@ -0,0 +1,23 @@
+// Loaded with --require by proj's profiled runs: profiles this process with the
+// inspector and writes the profile next to this file on exit. SIGTERM and SIGINT
+// exit normally, so stopping the run still leaves a profile behind.
+const fs = require('fs');
+const path = require('path');
+const { Session } = require('inspector');
+const [domain, start, stop, extension] = process.env.PROJ_PROFILE === 'heap'
+  ? ['HeapProfiler', 'startSampling', 'stopSampling', 'heapprofile']
+  : ['Profiler', 'start', 'stop', 'cpuprofile'];
+const session = new Session();
+session.connect();
+session.post(`${domain}.enable`);
+session.post(`${domain}.${start}`);
+process.on('exit', () => {
+  session.post(`${domain}.${stop}`, (error, result) => {
+    if (error) return;
+    const name = `${new Date().toISOString().replace(/[:.]/g, '')}.${process.pid}.${extension}`;
+    fs.writeFileSync(path.join(__dirname, name), JSON.stringify(result.profile));
+  });
+});
+for (const [signal, code] of [['SIGTERM', 143], ['SIGINT', 130]]) {
+  process.on(signal, () => process.exit(code));
+}
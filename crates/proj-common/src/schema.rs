@@ -0,0 +1,229 @@
+//! JSON Schemas for proj's wire formats and files, for editor validation
+//! and client codegen (`proj schema`)
+
+use crate::{ApiError, ApiMessage, ApiProject, Config, IpcRequest, IpcResponse, LogLine, Project};
+use anyhow::{Context, Result};
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// File in a project's root describing how to run and route it
+pub const PROJECT_FILE: &str = "proj.toml";
+
+/// Documents `proj schema` can produce, with what each describes
+pub const DOCUMENTS: [(&str, &str); 6] = [
+    (
+        "ipc-request",
+        "Requests sent to the daemon's socket, one JSON line each",
+    ),
+    (
+        "ipc-response",
+        "Responses and pushed updates from the daemon",
+    ),
+    ("project", "Projects as stored in ~/.proj/proj.db"),
+    ("config", "~/.proj/config.json"),
+    (
+        "proj-toml",
+        "proj.toml in a project from `proj new --template` or `proj init`",
+    ),
+    (
+        "http-api",
+        "Bodies of the dashboard API on the proxy's bare host",
+    ),
+];
+
+/// `proj.toml`, as written by `proj new --template` and `proj init`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[schemars(title = "proj.toml")]
+#[serde(deny_unknown_fields)]
+pub struct ProjectFile {
+    /// Project name
+    #[serde(default)]
+    pub name: String,
+    /// Template the project was scaffolded from
+    #[serde(default)]
+    pub template: Option<String>,
+    #[serde(default)]
+    pub run: ProjectFileRun,
+    /// Path prefixes the proxy sends to another port, e.g. `"/api" = 4000`
+    #[serde(default)]
+    pub routes: BTreeMap<String, u16>,
+    /// `false` leaves the project out of the proxy, as `proj <name> route
+    /// disable` does
+    #[serde(default)]
+    pub proxy: Option<bool>,
+}
+
+impl ProjectFile {
+    /// Read the `proj.toml` in a project's root, if it has one
+    pub fn load(root_dir: &Path) -> Result<Option<Self>> {
+        let path = root_dir.join(PROJECT_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&content)
+            .map(Some)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// The nearest `proj.toml` naming a project, in `dir` or one of its
+    /// parents, with the directory it's in. Files that can't be read are
+    /// skipped.
+    pub fn find(dir: &Path) -> Option<(PathBuf, Self)> {
+        dir.ancestors().find_map(|dir| match Self::load(dir) {
+            Ok(Some(file)) if !file.name.is_empty() => Some((dir.to_path_buf(), file)),
+            _ => None,
+        })
+    }
+
+    /// Record which project a root belongs to in its `proj.toml`, creating
+    /// the file if needed. An existing file keeps its contents and comments.
+    pub fn write_name(root_dir: &Path, name: &str) -> Result<()> {
+        let path = root_dir.join(PROJECT_FILE);
+        let line = format!("name = {}\n", toml::Value::from(name));
+        let content = match Self::load(root_dir)? {
+            None => line,
+            Some(file) if file.name == name => return Ok(()),
+            Some(file) if !file.name.is_empty() => {
+                anyhow::bail!("{} already names project '{}'", path.display(), file.name)
+            }
+            // Top-level keys go before any table
+            Some(_) => line + &std::fs::read_to_string(&path)?,
+        };
+        std::fs::write(&path, content)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Point a root's `proj.toml` at another project, as in a copy of the
+    /// directory. The rest of the file stays as it is.
+    pub fn rename(root_dir: &Path, name: &str) -> Result<()> {
+        let path = root_dir.join(PROJECT_FILE);
+        if !path.exists() {
+            return Self::write_name(root_dir, name);
+        }
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut renamed = false;
+        let mut in_table = false;
+        let mut lines = Vec::new();
+        for line in content.lines() {
+            let trimmed = line.trim_start();
+            in_table |= trimmed.starts_with('[');
+            let key = trimmed.split('=').next().unwrap_or_default().trim();
+            if !in_table && !renamed && trimmed.contains('=') && key == "name" {
+                lines.push(format!("name = {}", toml::Value::from(name)));
+                renamed = true;
+            } else {
+                lines.push(line.to_string());
+            }
+        }
+        if !renamed {
+            return Self::write_name(root_dir, name);
+        }
+        std::fs::write(&path, lines.join("\n") + "\n")
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// `[run]` table of `proj.toml`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectFileRun {
+    /// Default dev command; `proj up` starts it with the port in `PORT`
+    #[serde(default)]
+    pub dev: Option<String>,
+    /// One process at a time: a run while any is running is a duplicate,
+    /// whatever its command
+    #[serde(default)]
+    pub single_instance: bool,
+}
+
+/// The schema called `name` (see [`DOCUMENTS`])
+pub fn generate(name: &str) -> Option<Schema> {
+    let schema = match name {
+        "ipc-request" => schemars::schema_for!(IpcRequest),
+        "ipc-response" => schemars::schema_for!(IpcResponse),
+        "project" => schemars::schema_for!(Project),
+        "config" => schemars::schema_for!(Config),
+        "proj-toml" => schemars::schema_for!(ProjectFile),
+        "http-api" => http_api(),
+        _ => return None,
+    };
+    Some(schema)
+}
+
+/// Every body the dashboard API sends, as definitions of one document
+fn http_api() -> Schema {
+    let mut generator = SchemaGenerator::default();
+    let bodies = [
+        generator.subschema_for::<ApiProject>(),
+        generator.subschema_for::<LogLine>(),
+        generator.subschema_for::<ApiMessage>(),
+        generator.subschema_for::<ApiError>(),
+    ];
+    let definitions = generator.take_definitions(true);
+    schemars::json_schema!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "proj dashboard API",
+        "description": "GET /__proj/api/projects returns an array of ApiProject; \
+            GET /__proj/api/projects/{name}/logs streams server-sent events whose data is a LogLine; \
+            POST /__proj/api/processes/{id}/{stop|restart} returns ApiMessage. \
+            Failed requests return ApiError.",
+        "anyOf": bodies,
+        "$defs": definitions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_documents_generate() {
+        for (name, _) in DOCUMENTS {
+            let schema = generate(name).unwrap();
+            assert!(schema.get("$schema").is_some(), "{}", name);
+        }
+        let api = generate("http-api").unwrap();
+        assert!(api.get("$defs").unwrap().get("ApiProject").is_some());
+        assert!(generate("nope").is_none());
+    }
+
+    #[test]
+    fn test_write_name_and_find() {
+        let root = std::env::temp_dir().join(format!("proj-schema-{}", std::process::id()));
+        let nested = root.join("src/app");
+        std::fs::create_dir_all(&nested).unwrap();
+        assert!(ProjectFile::find(&nested).is_none());
+
+        std::fs::write(
+            root.join(PROJECT_FILE),
+            "# dev server\n[run]\ndev = \"npm run dev\"\n",
+        )
+        .unwrap();
+        ProjectFile::write_name(&root, "shop").unwrap();
+        let (dir, file) = ProjectFile::find(&nested).unwrap();
+        assert_eq!(dir, root);
+        assert_eq!(file.name, "shop");
+        assert_eq!(file.run.dev.as_deref(), Some("npm run dev"));
+        assert!(std::fs::read_to_string(root.join(PROJECT_FILE))
+            .unwrap()
+            .contains("# dev server"));
+
+        ProjectFile::write_name(&root, "shop").unwrap();
+        assert!(ProjectFile::write_name(&root, "other").is_err());
+
+        ProjectFile::rename(&root, "shop-2").unwrap();
+        let content = std::fs::read_to_string(root.join(PROJECT_FILE)).unwrap();
+        assert!(
+            content.starts_with("name = \"shop-2\"\n# dev server\n"),
+            "{}",
+            content
+        );
+        assert_eq!(ProjectFile::find(&nested).unwrap().1.name, "shop-2");
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
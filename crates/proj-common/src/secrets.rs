@@ -0,0 +1,224 @@
+//! Secrets kept out of project files: the macOS keychain, the Secret Service
+//! (GNOME Keyring, KWallet) through `secret-tool` on Linux, or where neither
+//! is available, `~/.proj/secrets.json`, readable only by the current user
+//!
+//! Each secret is stored under the `proj` service with an account naming what
+//! it's for, e.g. `my-app /api` for the credentials the proxy attaches to a
+//! project's requests under `/api`.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+use std::process::{Command, Stdio};
+
+use crate::proj_dir;
+
+/// Service every secret is stored under
+const SERVICE: &str = "proj";
+
+/// Where a secret ended up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Keychain,
+    SecretService,
+    File,
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Keychain => "the keychain",
+            Self::SecretService => "the Secret Service keyring",
+            Self::File => "~/.proj/secrets.json",
+        })
+    }
+}
+
+/// Account of the credentials a project's requests under `prefix` get
+pub fn auth_account(project_name: &str, prefix: &str) -> String {
+    format!("{} {}", project_name, prefix)
+}
+
+/// Store a secret, replacing any under the same account
+pub fn set(account: &str, secret: &str) -> Result<Backend> {
+    if let Some(backend) = keyring_set(account, secret)? {
+        // Don't leave an older copy behind in the file
+        remove_from_file(account)?;
+        return Ok(backend);
+    }
+    let mut secrets = read_file()?;
+    secrets.insert(account.to_string(), secret.to_string());
+    write_file(&secrets)?;
+    Ok(Backend::File)
+}
+
+/// The secret stored under an account, if any
+pub fn get(account: &str) -> Result<Option<String>> {
+    if let Some(secret) = keyring_get(account) {
+        return Ok(Some(secret));
+    }
+    Ok(read_file()?.remove(account))
+}
+
+/// Forget a secret, wherever it's stored
+pub fn delete(account: &str) -> Result<()> {
+    keyring_delete(account);
+    remove_from_file(account)
+}
+
+/// The secret goes in on stdin, where `-w` without a value has `security`
+/// prompt for it (and, on a terminal, for it again), so `ps` never shows it
+#[cfg(target_os = "macos")]
+fn keyring_set(account: &str, secret: &str) -> Result<Option<Backend>> {
+    let mut child = Command::new("security")
+        .args([
+            "add-generic-password",
+            "-U",
+            "-s",
+            SERVICE,
+            "-a",
+            account,
+            "-w",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to run security")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A second line for the retype prompt; ignored without one
+        stdin.write_all(format!("{0}\n{0}\n", secret).as_bytes())?;
+    }
+    if !child.wait()?.success() {
+        anyhow::bail!("security couldn't store the secret in the keychain");
+    }
+    Ok(Some(Backend::Keychain))
+}
+
+#[cfg(target_os = "macos")]
+fn keyring_get(account: &str) -> Option<String> {
+    let output = Command::new("security")
+        .args(["find-generic-password", "-s", SERVICE, "-a", account, "-w"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output.status.success().then(|| {
+        let secret = String::from_utf8_lossy(&output.stdout);
+        secret.trim_end_matches('\n').to_string()
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn keyring_delete(account: &str) {
+    let _ = Command::new("security")
+        .args(["delete-generic-password", "-s", SERVICE, "-a", account])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+/// Stored with `secret-tool` when there is one and a keyring answers it;
+/// headless machines often have neither
+#[cfg(target_os = "linux")]
+fn keyring_set(account: &str, secret: &str) -> Result<Option<Backend>> {
+    let label = format!("{} {}", SERVICE, account);
+    let child = Command::new("secret-tool")
+        .args([
+            "store", "--label", &label, "service", SERVICE, "account", account,
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    let Ok(mut child) = child else {
+        return Ok(None);
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(secret.as_bytes())?;
+    }
+    Ok(child.wait()?.success().then_some(Backend::SecretService))
+}
+
+#[cfg(target_os = "linux")]
+fn keyring_get(account: &str) -> Option<String> {
+    let output = Command::new("secret-tool")
+        .args(["lookup", "service", SERVICE, "account", account])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    (output.status.success() && !output.stdout.is_empty())
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(target_os = "linux")]
+fn keyring_delete(account: &str) {
+    let _ = Command::new("secret-tool")
+        .args(["clear", "service", SERVICE, "account", account])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn keyring_set(_account: &str, _secret: &str) -> Result<Option<Backend>> {
+    Ok(None)
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn keyring_get(_account: &str) -> Option<String> {
+    None
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn keyring_delete(_account: &str) {}
+
+fn file_path() -> Result<PathBuf> {
+    Ok(proj_dir()?.join("secrets.json"))
+}
+
+fn read_file() -> Result<BTreeMap<String, String>> {
+    let path = file_path()?;
+    match std::fs::read_to_string(&path) {
+        Ok(json) => serde_json::from_str(&json)
+            .with_context(|| format!("Failed to parse {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+fn remove_from_file(account: &str) -> Result<()> {
+    let mut secrets = read_file()?;
+    if secrets.remove(account).is_some() {
+        write_file(&secrets)?;
+    }
+    Ok(())
+}
+
+/// Write the secrets file, readable only by the current user
+fn write_file(secrets: &BTreeMap<String, String>) -> Result<()> {
+    let path = file_path()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&path)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    // The mode only applies to a file being created; tighten an older one
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(serde_json::to_string_pretty(secrets)?.as_bytes())?;
+    Ok(())
+}
@@ -0,0 +1,768 @@
+//! SQLite store behind the registry and process records (`~/.proj/proj.db`)
+//!
+//! Every write is a transaction, so a crash leaves the previous state instead
+//! of a half-written file. Projects and process records are kept as JSON in a
+//! `data` column next to the columns they're looked up by, and project rows
+//! stay versioned by [`migrate::PROJECT`]. A project's aliases and proxy
+//! options are copied into columns of their own, so the daemon can route to
+//! every project without parsing any. The schema itself is versioned by
+//! `PRAGMA user_version`, counting the entries of [`SCHEMA`] applied.
+
+use crate::migrate::{self, NewerFormat, Upgrade};
+use crate::{db_path, Milestone, ProcessInfo, ProcessStatus, Project, ProxyOptions, RunRecord};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Schema changes, oldest first. Append to this; never edit an entry.
+const SCHEMA: &[&str] = &[
+    "
+    CREATE TABLE projects (
+        name TEXT PRIMARY KEY,
+        version INTEGER NOT NULL,
+        revision INTEGER NOT NULL,
+        data TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+    CREATE TABLE project_backups (
+        name TEXT NOT NULL,
+        version INTEGER NOT NULL,
+        data TEXT NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    CREATE TABLE processes (
+        id TEXT PRIMARY KEY,
+        project TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE runs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        process_id TEXT NOT NULL,
+        project TEXT NOT NULL,
+        service TEXT,
+        command TEXT NOT NULL,
+        pid INTEGER NOT NULL,
+        started_at TEXT NOT NULL,
+        ended_at TEXT,
+        exit_code INTEGER,
+        status TEXT
+    );
+    CREATE INDEX runs_by_project ON runs (project, id);
+",
+    "
+    ALTER TABLE runs ADD COLUMN argv TEXT;
+    ALTER TABLE runs ADD COLUMN port INTEGER;
+",
+    "
+    ALTER TABLE runs ADD COLUMN compiled_ms INTEGER;
+    ALTER TABLE runs ADD COLUMN listening_ms INTEGER;
+    ALTER TABLE runs ADD COLUMN ready_ms INTEGER;
+",
+    "
+    ALTER TABLE projects ADD COLUMN aliases TEXT NOT NULL DEFAULT '[]';
+    ALTER TABLE projects ADD COLUMN proxy TEXT NOT NULL DEFAULT '{}';
+    UPDATE projects SET aliases = json_extract(data, '$.aliases')
+        WHERE json_type(data, '$.aliases') = 'array';
+    UPDATE projects SET proxy = json_extract(data, '$.proxy')
+        WHERE json_type(data, '$.proxy') = 'object';
+",
+];
+
+/// Runs kept per project; the oldest are dropped as new ones start
+const RUNS_KEPT: u32 = 500;
+
+/// How long a write waits for another connection's to finish
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Handle to the database, shared by the registry and the process manager
+#[derive(Clone)]
+pub struct Store {
+    conn: Arc<Mutex<Connection>>,
+}
+
+/// A stored project that isn't at the current format version
+#[derive(Debug)]
+pub struct ProjectUpgrade {
+    pub name: String,
+    /// What migrating did, or would do; rows from a newer proj are left alone
+    pub result: Result<Upgrade, NewerFormat>,
+}
+
+/// A stored project's row without its settings: enough to know it's there,
+/// whether it changed and how the proxy reaches it
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexEntry {
+    pub name: String,
+    pub revision: u64,
+    /// When it was last written, as stored; it's only compared, so it isn't
+    /// parsed
+    pub updated_at: String,
+    pub aliases: Vec<String>,
+    pub proxy: ProxyOptions,
+}
+
+/// What [`Store::compact`] removed
+#[derive(Debug, Default)]
+pub struct Compacted {
+    pub backups: usize,
+    /// How much smaller the database got
+    pub bytes: u64,
+}
+
+/// Project files and process records found from before the store
+#[derive(Debug, Default)]
+pub struct Imported {
+    pub projects: Vec<String>,
+    pub processes: usize,
+}
+
+impl Store {
+    /// Open `~/.proj/proj.db`, creating it if needed
+    pub fn open_default() -> Result<Self> {
+        Self::open(&db_path()?)
+    }
+
+    /// Open a database, bringing its schema up to date
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let mut conn =
+            Connection::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        // WAL lets the CLI read while the daemon writes
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        apply_schema(&mut conn).with_context(|| format!("Failed to migrate {}", path.display()))?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Copy `project.json` files under `projects_dir` and the `processes`
+    /// records file into the store. Each file is renamed to `<file>.imported`
+    /// afterwards, so it's only imported once and stays around as a backup;
+    /// projects already in the store keep their stored version.
+    pub fn import_files(&self, projects_dir: &Path, processes: &Path) -> Result<Imported> {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(projects_dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|e| e.path().join("project.json"))
+                    .filter(|path| path.exists())
+                    .collect()
+            })
+            .unwrap_or_default();
+        files.sort();
+
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let mut imported = Imported::default();
+        let mut done = Vec::new();
+        for path in files {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let value: Value = serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse {}", path.display()))?;
+            let name = value
+                .get("name")
+                .and_then(Value::as_str)
+                .with_context(|| format!("{} has no name", path.display()))?
+                .to_string();
+            let column = |key: &str, empty: &str| {
+                value
+                    .get(key)
+                    .map_or_else(|| empty.to_string(), Value::to_string)
+            };
+            let added = tx.execute(
+                "INSERT OR IGNORE INTO projects
+                     (name, version, revision, data, updated_at, aliases, proxy)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    name,
+                    migrate::PROJECT.version_of(&value),
+                    value.get("revision").and_then(Value::as_u64).unwrap_or(0) as i64,
+                    content,
+                    Utc::now(),
+                    column("aliases", "[]"),
+                    column("proxy", "{}")
+                ],
+            )?;
+            if added > 0 {
+                imported.projects.push(name);
+            }
+            done.push(path);
+        }
+
+        let has_processes: bool =
+            tx.query_row("SELECT EXISTS (SELECT 1 FROM processes)", [], |row| {
+                row.get(0)
+            })?;
+        if processes.exists() {
+            if !has_processes {
+                let content = std::fs::read_to_string(processes)
+                    .with_context(|| format!("Failed to read {}", processes.display()))?;
+                let records: Vec<ProcessInfo> = serde_json::from_str(&content)
+                    .with_context(|| format!("Failed to parse {}", processes.display()))?;
+                for info in &records {
+                    put_process(&tx, info)?;
+                }
+                imported.processes = records.len();
+            }
+            done.push(processes.to_path_buf());
+        }
+        tx.commit()?;
+
+        for path in done {
+            let mut name = path.file_name().unwrap_or_default().to_os_string();
+            name.push(".imported");
+            match std::fs::rename(&path, path.with_file_name(name)) {
+                // Another process imported it at the same time
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                result => result.with_context(|| format!("Failed to rename {}", path.display()))?,
+            }
+        }
+        Ok(imported)
+    }
+
+    /// Stored projects not at the current format. With `write`, migrates them
+    /// in one transaction, keeping each original in `project_backups`.
+    pub fn upgrade_projects(&self, write: bool) -> Result<Vec<ProjectUpgrade>> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let rows: Vec<(String, String)> = tx
+            .prepare("SELECT name, data FROM projects WHERE version != ?1 ORDER BY name")?
+            .query_map([migrate::PROJECT.version()], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let mut upgrades = Vec::new();
+        for (name, data) in rows {
+            let mut value: Value = serde_json::from_str(&data)
+                .with_context(|| format!("Failed to parse stored project '{}'", name))?;
+            let result = match migrate::PROJECT.upgrade(&mut value) {
+                Ok(Some(upgrade)) => Ok(upgrade),
+                Ok(None) => continue,
+                Err(e) => Err(e),
+            };
+            if let (Ok(upgrade), true) = (&result, write) {
+                let project =
+                    serde_json::from_value::<Project>(value.clone()).with_context(|| {
+                        format!("Project '{}' does not parse after migrating", name)
+                    })?;
+                tx.execute(
+                    "INSERT INTO project_backups (name, version, data, created_at)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![name, upgrade.from, data, Utc::now()],
+                )?;
+                tx.execute(
+                    "UPDATE projects SET version = ?2, data = ?3, updated_at = ?4, aliases = ?5,
+                         proxy = ?6
+                     WHERE name = ?1",
+                    params![
+                        name,
+                        upgrade.to,
+                        serde_json::to_string_pretty(&value)?,
+                        Utc::now(),
+                        serde_json::to_string(&project.aliases)?,
+                        serde_json::to_string(&project.proxy)?
+                    ],
+                )?;
+            }
+            upgrades.push(ProjectUpgrade { name, result });
+        }
+        tx.commit()?;
+        Ok(upgrades)
+    }
+
+    /// Every stored project by name, or why it couldn't be read
+    pub fn projects(&self) -> Result<Vec<(String, Result<Project>)>> {
+        let conn = self.conn();
+        let rows: Vec<(String, String)> = conn
+            .prepare("SELECT name, data FROM projects ORDER BY name")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(rows
+            .into_iter()
+            .map(|(name, data)| {
+                let project = serde_json::from_str(&data)
+                    .with_context(|| format!("Failed to parse stored project '{}'", name));
+                (name, project)
+            })
+            .collect())
+    }
+
+    /// Every stored project's index entry, by name, without parsing any of
+    /// them
+    pub fn project_index(&self) -> Result<Vec<IndexEntry>> {
+        let conn = self.conn();
+        let rows: Vec<(String, i64, String, String, String)> = conn
+            .prepare(
+                "SELECT name, revision, updated_at, aliases, proxy FROM projects ORDER BY name",
+            )?
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(rows
+            .into_iter()
+            .map(|(name, revision, updated_at, aliases, proxy)| {
+                // A row that doesn't parse is reported once the project is read
+                let aliases = serde_json::from_str(&aliases).unwrap_or_default();
+                let proxy = serde_json::from_str(&proxy).unwrap_or_default();
+                IndexEntry {
+                    name,
+                    revision: revision as u64,
+                    updated_at,
+                    aliases,
+                    proxy,
+                }
+            })
+            .collect())
+    }
+
+    /// One stored project, if there is one by that name
+    pub fn project(&self, name: &str) -> Result<Option<Project>> {
+        let data: Option<String> = self
+            .conn()
+            .query_row("SELECT data FROM projects WHERE name = ?1", [name], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        data.map(|data| {
+            serde_json::from_str(&data)
+                .with_context(|| format!("Failed to parse stored project '{}'", name))
+        })
+        .transpose()
+    }
+
+    /// Insert or replace a project, returning its [`IndexEntry::updated_at`]
+    pub fn save_project(&self, project: &Project) -> Result<String> {
+        let data = serde_json::to_string_pretty(project).context("Failed to serialize project")?;
+        self.conn()
+            .query_row(
+                "INSERT INTO projects (name, version, revision, data, updated_at, aliases, proxy)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                 ON CONFLICT (name) DO UPDATE SET
+                     version = excluded.version,
+                     revision = excluded.revision,
+                     data = excluded.data,
+                     updated_at = excluded.updated_at,
+                     aliases = excluded.aliases,
+                     proxy = excluded.proxy
+                 RETURNING updated_at",
+                params![
+                    project.name,
+                    project.version,
+                    project.revision as i64,
+                    data,
+                    Utc::now(),
+                    serde_json::to_string(&project.aliases)?,
+                    serde_json::to_string(&project.proxy)?
+                ],
+                |row| row.get(0),
+            )
+            .context("Failed to save project")
+    }
+
+    /// Remove a project along with its backups and run history
+    pub fn delete_project(&self, name: &str) -> Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        for table in ["projects", "project_backups"] {
+            tx.execute(&format!("DELETE FROM {} WHERE name = ?1", table), [name])?;
+        }
+        tx.execute("DELETE FROM runs WHERE project = ?1", [name])?;
+        tx.commit().context("Failed to delete project")?;
+        Ok(())
+    }
+
+    /// Process records kept for the next daemon
+    pub fn processes(&self) -> Result<Vec<ProcessInfo>> {
+        let conn = self.conn();
+        let rows: Vec<String> = conn
+            .prepare("SELECT data FROM processes")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        rows.iter()
+            .map(|data| serde_json::from_str(data).context("Failed to parse process record"))
+            .collect()
+    }
+
+    /// Replace every process record
+    pub fn save_processes(&self, records: &[&ProcessInfo]) -> Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM processes", [])?;
+        for info in records {
+            put_process(&tx, info)?;
+        }
+        tx.commit().context("Failed to save process records")?;
+        Ok(())
+    }
+
+    /// Record that a process started from `argv` (empty if proj didn't start
+    /// it), dropping the project's oldest runs beyond [`RUNS_KEPT`]
+    pub fn start_run(&self, info: &ProcessInfo, argv: &[String]) -> Result<()> {
+        let argv = if argv.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(argv)?)
+        };
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO runs (process_id, project, service, command, argv, pid, port, started_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                info.id.to_string(),
+                info.project_name,
+                info.service,
+                info.command,
+                argv,
+                info.pid,
+                info.port,
+                info.started_at
+            ],
+        )?;
+        tx.execute(
+            "DELETE FROM runs WHERE project = ?1 AND id <= (
+                 SELECT id FROM runs WHERE project = ?1 ORDER BY id DESC LIMIT 1 OFFSET ?2
+             )",
+            params![info.project_name, RUNS_KEPT],
+        )?;
+        tx.commit().context("Failed to record run")?;
+        Ok(())
+    }
+
+    /// Record how a process's current run ended
+    pub fn end_run(&self, info: &ProcessInfo) -> Result<()> {
+        self.conn()
+            .execute(
+                "UPDATE runs SET ended_at = ?3, exit_code = ?4, status = ?5
+                 WHERE process_id = ?1 AND pid = ?2 AND ended_at IS NULL",
+                params![
+                    info.id.to_string(),
+                    info.pid,
+                    Utc::now(),
+                    info.last_exit_code,
+                    status_name(&info.status)
+                ],
+            )
+            .context("Failed to record run")?;
+        Ok(())
+    }
+
+    /// Record the port a process's current run was detected listening on
+    pub fn set_run_port(&self, info: &ProcessInfo) -> Result<()> {
+        self.conn()
+            .execute(
+                "UPDATE runs SET port = ?3 WHERE process_id = ?1 AND pid = ?2 AND ended_at IS NULL",
+                params![info.id.to_string(), info.pid, info.port],
+            )
+            .context("Failed to record run")?;
+        Ok(())
+    }
+
+    /// Record how long a process's current run took to reach `milestone`,
+    /// unless it already had
+    pub fn set_run_milestone(
+        &self,
+        info: &ProcessInfo,
+        milestone: Milestone,
+        after_ms: u64,
+    ) -> Result<()> {
+        let column = match milestone {
+            Milestone::Compiled => "compiled_ms",
+            Milestone::Listening => "listening_ms",
+            Milestone::Ready => "ready_ms",
+        };
+        self.conn()
+            .execute(
+                &format!(
+                    "UPDATE runs SET {column} = ?3
+                     WHERE process_id = ?1 AND pid = ?2 AND ended_at IS NULL AND {column} IS NULL"
+                ),
+                params![info.id.to_string(), info.pid, after_ms],
+            )
+            .context("Failed to record run")?;
+        Ok(())
+    }
+
+    /// A project's `limit` most recent runs, newest first
+    pub fn runs(&self, project: &str, limit: usize) -> Result<Vec<RunRecord>> {
+        let conn = self.conn();
+        let mut statement = conn.prepare(
+            "SELECT process_id, project, service, command, pid, started_at, ended_at, exit_code, status,
+                    argv, port, compiled_ms, listening_ms, ready_ms
+             FROM runs WHERE project = ?1 ORDER BY id DESC LIMIT ?2",
+        )?;
+        let rows = statement.query_map(params![project, limit as i64], |row| {
+            let process_id: String = row.get(0)?;
+            let status: Option<String> = row.get(8)?;
+            let argv: Option<String> = row.get(9)?;
+            Ok(RunRecord {
+                process_id: process_id.parse().unwrap_or_default(),
+                project_name: row.get(1)?,
+                service: row.get(2)?,
+                command: row.get(3)?,
+                pid: row.get(4)?,
+                started_at: row.get(5)?,
+                ended_at: row.get::<_, Option<DateTime<Utc>>>(6)?,
+                exit_code: row.get(7)?,
+                status: status.and_then(|s| serde_json::from_value(Value::String(s)).ok()),
+                argv: argv
+                    .and_then(|argv| serde_json::from_str(&argv).ok())
+                    .unwrap_or_default(),
+                port: row.get(10)?,
+                compiled_ms: row.get(11)?,
+                listening_ms: row.get(12)?,
+                ready_ms: row.get(13)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Drop project backups made before `backups_before` and rebuild the
+    /// database without the space freed rows left behind
+    pub fn compact(&self, backups_before: DateTime<Utc>) -> Result<Compacted> {
+        let conn = self.conn();
+        let size = |conn: &Connection| -> rusqlite::Result<u64> {
+            let pages: u64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+            let page_size: u64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+            Ok(pages * page_size)
+        };
+        let before = size(&conn)?;
+        let backups = conn.execute(
+            "DELETE FROM project_backups WHERE created_at < ?1",
+            [backups_before],
+        )?;
+        conn.execute_batch("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);")
+            .context("Failed to compact the database")?;
+        Ok(Compacted {
+            backups,
+            bytes: before.saturating_sub(size(&conn)?),
+        })
+    }
+
+    /// Schema version of the open database
+    pub fn schema_version(&self) -> Result<u32> {
+        Ok(self
+            .conn()
+            .query_row("PRAGMA user_version", [], |row| row.get(0))?)
+    }
+}
+
+/// Apply the entries of [`SCHEMA`] the database hasn't seen, all or none
+fn apply_schema(conn: &mut Connection) -> Result<()> {
+    let tx = conn.transaction()?;
+    let applied: usize = tx.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if applied > SCHEMA.len() {
+        anyhow::bail!(
+            "Database is schema version {}, newer than the {} this proj understands; upgrade proj",
+            applied,
+            SCHEMA.len()
+        );
+    }
+    for change in &SCHEMA[applied..] {
+        tx.execute_batch(change)?;
+    }
+    tx.pragma_update(None, "user_version", SCHEMA.len())?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn put_process(tx: &Transaction, info: &ProcessInfo) -> Result<()> {
+    tx.execute(
+        "INSERT OR REPLACE INTO processes (id, project, data) VALUES (?1, ?2, ?3)",
+        params![
+            info.id.to_string(),
+            info.project_name,
+            serde_json::to_string(info)?
+        ],
+    )?;
+    Ok(())
+}
+
+fn status_name(status: &ProcessStatus) -> Option<String> {
+    serde_json::to_value(status)
+        .ok()?
+        .as_str()
+        .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("proj-store-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_import_and_upgrade() {
+        let dir = temp_dir("import");
+        let legacy = dir.join("projects/web/project.json");
+        std::fs::create_dir_all(legacy.parent().unwrap()).unwrap();
+        let mut project = Project::new("web".to_string(), dir.clone());
+        project.version = 0;
+        project.revision = 4;
+        let mut value = serde_json::to_value(&project).unwrap();
+        value.as_object_mut().unwrap().remove("version");
+        std::fs::write(&legacy, value.to_string()).unwrap();
+
+        let store = Store::open(&dir.join("proj.db")).unwrap();
+        assert_eq!(store.schema_version().unwrap(), SCHEMA.len() as u32);
+        let imported = store
+            .import_files(&dir.join("projects"), &dir.join("processes.json"))
+            .unwrap();
+        assert_eq!(imported.projects, vec!["web"]);
+        assert!(!legacy.exists());
+        assert!(legacy.with_file_name("project.json.imported").exists());
+
+        // A dry run reports without writing
+        let upgrades = store.upgrade_projects(false).unwrap();
+        assert_eq!(upgrades.len(), 1);
+        assert_eq!(upgrades[0].result.as_ref().unwrap().from, 0);
+        assert_eq!(store.upgrade_projects(true).unwrap().len(), 1);
+        assert!(store.upgrade_projects(false).unwrap().is_empty());
+
+        let projects = store.projects().unwrap();
+        let web = projects[0].1.as_ref().unwrap();
+        assert_eq!((web.version, web.revision), (migrate::PROJECT.version(), 4));
+        assert_eq!(
+            store.project("web").unwrap().unwrap().root_dir,
+            web.root_dir
+        );
+        let index = store.project_index().unwrap();
+        assert_eq!((index[0].name.as_str(), index[0].revision), ("web", 4));
+        let mut web = web.clone();
+        web.aliases = vec!["web.test".to_string()];
+        web.proxy.routes.insert("/api".to_string(), 4000);
+        store.save_project(&web).unwrap();
+        let saved = store.project_index().unwrap();
+        assert_ne!(saved, index);
+        assert_eq!(saved[0].aliases, web.aliases);
+        assert_eq!(saved[0].proxy, web.proxy);
+
+        // The upgrade's backup goes once it's old enough
+        let month_ago = Utc::now() - chrono::Duration::days(30);
+        assert_eq!(store.compact(month_ago).unwrap().backups, 0);
+        let later = Utc::now() + chrono::Duration::seconds(1);
+        assert_eq!(store.compact(later).unwrap().backups, 1);
+
+        store.delete_project("web").unwrap();
+        assert!(store.projects().unwrap().is_empty());
+        assert!(store.project("web").unwrap().is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_index_columns_filled() {
+        let dir = temp_dir("columns");
+        let path = dir.join("proj.db");
+        // A database from before the index had aliases and proxy options
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(&SCHEMA[..3].concat()).unwrap();
+        conn.pragma_update(None, "user_version", 3).unwrap();
+        let mut project = Project::new("web".to_string(), dir.clone());
+        project.aliases = vec!["web.test".to_string()];
+        project.proxy.cors = true;
+        conn.execute(
+            "INSERT INTO projects (name, version, revision, data, updated_at)
+             VALUES ('web', ?1, 0, ?2, '')",
+            params![project.version, serde_json::to_string(&project).unwrap()],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO projects (name, version, revision, data, updated_at)
+             VALUES ('bare', ?1, 0, '{}', '')",
+            params![project.version],
+        )
+        .unwrap();
+        drop(conn);
+
+        let index = Store::open(&path).unwrap().project_index().unwrap();
+        assert_eq!(index[0].name, "bare");
+        assert!(index[0].aliases.is_empty());
+        assert_eq!(index[1].aliases, ["web.test"]);
+        assert!(index[1].proxy.cors);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_runs() {
+        let dir = temp_dir("runs");
+        let store = Store::open(&dir.join("proj.db")).unwrap();
+        let info: ProcessInfo = serde_json::from_value(serde_json::json!({
+            "id": uuid::Uuid::new_v4(),
+            "project_name": "api",
+            "pid": 42,
+            "command": "npm start",
+            "started_at": Utc::now(),
+            "status": "running",
+        }))
+        .unwrap();
+
+        store.start_run(&info, &[]).unwrap();
+        let respawned = ProcessInfo {
+            pid: 43,
+            last_exit_code: Some(1),
+            status: ProcessStatus::Failed,
+            ..info.clone()
+        };
+        // Only the run with the exiting PID ends
+        store.end_run(&respawned).unwrap();
+        assert!(store.runs("api", 10).unwrap()[0].ended_at.is_none());
+
+        let argv = vec!["npm".to_string(), "run".to_string(), "dev".to_string()];
+        store.start_run(&respawned, &argv).unwrap();
+        store
+            .set_run_port(&ProcessInfo {
+                port: Some(3000),
+                ..respawned.clone()
+            })
+            .unwrap();
+        store
+            .set_run_milestone(&respawned, Milestone::Ready, 3100)
+            .unwrap();
+        // Only the first time counts
+        store
+            .set_run_milestone(&respawned, Milestone::Ready, 4000)
+            .unwrap();
+        store.end_run(&respawned).unwrap();
+        let runs = store.runs("api", 10).unwrap();
+        assert_eq!(runs.len(), 2);
+        assert_eq!((runs[0].pid, runs[0].exit_code), (43, Some(1)));
+        assert_eq!((&runs[0].argv, runs[0].port), (&argv, Some(3000)));
+        assert!(runs[1].argv.is_empty());
+        assert_eq!(runs[0].status, Some(ProcessStatus::Failed));
+        assert_eq!(runs[0].startup_ms(), Some(3100));
+        assert_eq!(runs[1].startup_ms(), None);
+        assert!(runs[1].ended_at.is_none());
+
+        store.save_processes(&[&info]).unwrap();
+        assert_eq!(store.processes().unwrap()[0].pid, 42);
+        store.save_processes(&[]).unwrap();
+        assert!(store.processes().unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
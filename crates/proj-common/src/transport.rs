@@ -0,0 +1,196 @@
+//! Local IPC transport between the CLI and the daemon
+//!
+//! A Unix domain socket at `~/.proj/daemon.sock` on Unix, a per-user named
+//! pipe on Windows. Both carry the same newline-delimited JSON, as does the
+//! optional TCP listener (`listen_tcp`) remote clients authenticate on.
+
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// A listening endpoint and the connections made to it
+pub trait Transport {
+    type Stream: AsyncRead + AsyncWrite + Send + Unpin + 'static;
+    type Listener: Send + 'static;
+
+    /// Start listening at `endpoint`, replacing one a dead daemon left behind
+    fn bind(endpoint: &Path) -> io::Result<Self::Listener>;
+
+    /// Wait for the next client
+    fn accept(
+        listener: &mut Self::Listener,
+    ) -> impl Future<Output = io::Result<Self::Stream>> + Send;
+
+    /// Connect to a daemon listening at `endpoint`
+    fn connect(endpoint: &Path) -> impl Future<Output = io::Result<Self::Stream>> + Send;
+
+    /// Remove the endpoint once the daemon stops listening
+    fn unbind(endpoint: &Path);
+}
+
+/// The transport for this platform
+#[cfg(unix)]
+pub type Ipc = UnixSocket;
+#[cfg(windows)]
+pub type Ipc = NamedPipe;
+
+/// A connection over this platform's transport
+pub type IpcStream = <Ipc as Transport>::Stream;
+
+/// A connection over any transport, this platform's or TCP
+pub type Connection = Box<dyn Duplex>;
+
+/// A stream read and written both ways
+pub trait Duplex: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Duplex for T {}
+
+/// Unix domain sockets
+#[cfg(unix)]
+pub struct UnixSocket;
+
+#[cfg(unix)]
+impl Transport for UnixSocket {
+    type Stream = tokio::net::UnixStream;
+    type Listener = tokio::net::UnixListener;
+
+    fn bind(endpoint: &Path) -> io::Result<Self::Listener> {
+        match std::fs::remove_file(endpoint) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        if let Some(parent) = endpoint.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        tokio::net::UnixListener::bind(endpoint)
+    }
+
+    async fn accept(listener: &mut Self::Listener) -> io::Result<Self::Stream> {
+        listener.accept().await.map(|(stream, _)| stream)
+    }
+
+    async fn connect(endpoint: &Path) -> io::Result<Self::Stream> {
+        tokio::net::UnixStream::connect(endpoint).await
+    }
+
+    fn unbind(endpoint: &Path) {
+        let _ = std::fs::remove_file(endpoint);
+    }
+}
+
+/// Named pipes; each accepted client takes the waiting pipe instance and a
+/// fresh one is created for the next
+#[cfg(windows)]
+pub struct NamedPipe;
+
+#[cfg(windows)]
+pub struct PipeListener {
+    name: std::ffi::OsString,
+    next: tokio::net::windows::named_pipe::NamedPipeServer,
+}
+
+#[cfg(windows)]
+impl Transport for NamedPipe {
+    type Stream = PipeStream;
+    type Listener = PipeListener;
+
+    fn bind(endpoint: &Path) -> io::Result<Self::Listener> {
+        use tokio::net::windows::named_pipe::ServerOptions;
+
+        // Fails if another daemon already owns the pipe
+        let next = ServerOptions::new()
+            .first_pipe_instance(true)
+            .create(endpoint)?;
+        Ok(PipeListener {
+            name: endpoint.as_os_str().to_owned(),
+            next,
+        })
+    }
+
+    async fn accept(listener: &mut Self::Listener) -> io::Result<Self::Stream> {
+        use tokio::net::windows::named_pipe::ServerOptions;
+
+        listener.next.connect().await?;
+        let next = ServerOptions::new().create(&listener.name)?;
+        Ok(PipeStream::Server(std::mem::replace(
+            &mut listener.next,
+            next,
+        )))
+    }
+
+    async fn connect(endpoint: &Path) -> io::Result<Self::Stream> {
+        use tokio::net::windows::named_pipe::ClientOptions;
+        const ERROR_PIPE_BUSY: i32 = 231;
+
+        // Every instance can be momentarily taken between accepts
+        loop {
+            match ClientOptions::new().open(endpoint) {
+                Ok(client) => return Ok(PipeStream::Client(client)),
+                Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) => {
+                    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn unbind(_endpoint: &Path) {
+        // Pipes disappear with their last handle
+    }
+}
+
+/// Either end of a named pipe connection
+#[cfg(windows)]
+pub enum PipeStream {
+    Server(tokio::net::windows::named_pipe::NamedPipeServer),
+    Client(tokio::net::windows::named_pipe::NamedPipeClient),
+}
+
+#[cfg(windows)]
+impl AsyncRead for PipeStream {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        match self.get_mut() {
+            PipeStream::Server(pipe) => std::pin::Pin::new(pipe).poll_read(cx, buf),
+            PipeStream::Client(pipe) => std::pin::Pin::new(pipe).poll_read(cx, buf),
+        }
+    }
+}
+
+#[cfg(windows)]
+impl AsyncWrite for PipeStream {
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<io::Result<usize>> {
+        match self.get_mut() {
+            PipeStream::Server(pipe) => std::pin::Pin::new(pipe).poll_write(cx, buf),
+            PipeStream::Client(pipe) => std::pin::Pin::new(pipe).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        match self.get_mut() {
+            PipeStream::Server(pipe) => std::pin::Pin::new(pipe).poll_flush(cx),
+            PipeStream::Client(pipe) => std::pin::Pin::new(pipe).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        match self.get_mut() {
+            PipeStream::Server(pipe) => std::pin::Pin::new(pipe).poll_shutdown(cx),
+            PipeStream::Client(pipe) => std::pin::Pin::new(pipe).poll_shutdown(cx),
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! Trash - what `proj rm` and `proj <name> profile reset` delete is moved to
+//! `~/.proj/trash/<id>/` with a manifest instead, so `proj undo` can put it
+//! back. The daily maintenance empties entries older than [`RETENTION_DAYS`].
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::{proj_dir, Project};
+
+/// Age at which the maintenance empties entries
+pub const RETENTION_DAYS: i64 = 30;
+
+/// Each entry's description of what it holds
+const MANIFEST: &str = "manifest.json";
+
+/// Directory in an entry holding the moved files
+const DATA: &str = "data";
+
+/// What deleted the files
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    /// `proj rm`, which also unregistered the project
+    Remove,
+    /// `proj <name> profile reset`
+    ProfileReset,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Remove => "rm",
+            Self::ProfileReset => "profile reset",
+        })
+    }
+}
+
+/// A file or directory moved to the trash
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Item {
+    /// Where it was
+    pub original: PathBuf,
+    /// Its name under the entry's `data` directory
+    pub stored: String,
+}
+
+/// One destructive operation's worth of trash
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Directory name under `~/.proj/trash`; sorts by when it was made
+    pub id: String,
+    pub operation: Operation,
+    pub project_name: String,
+    pub deleted_at: DateTime<Utc>,
+    pub items: Vec<Item>,
+    /// The project as it was, for operations that unregistered it
+    #[serde(default)]
+    pub project: Option<Project>,
+}
+
+impl Entry {
+    pub fn dir(&self) -> Result<PathBuf> {
+        Ok(trash_dir()?.join(&self.id))
+    }
+
+    /// Size of the files it holds
+    pub fn size(&self) -> u64 {
+        self.dir().map_or(0, |dir| dir_size(&dir.join(DATA)))
+    }
+
+    /// What it holds, e.g. `my-app (rm)`
+    pub fn summary(&self) -> String {
+        format!("{} ({})", self.project_name, self.operation)
+    }
+}
+
+pub fn trash_dir() -> Result<PathBuf> {
+    Ok(proj_dir()?.join("trash"))
+}
+
+/// Move those of `paths` that exist into a new entry, recording `project` if
+/// the operation unregisters it
+pub fn put(
+    operation: Operation,
+    project_name: &str,
+    paths: &[PathBuf],
+    project: Option<Project>,
+) -> Result<Entry> {
+    let deleted_at = Utc::now();
+    let mut entry = Entry {
+        id: format!("{}-{}", deleted_at.format("%Y%m%d-%H%M%S%3f"), project_name),
+        operation,
+        project_name: project_name.to_string(),
+        deleted_at,
+        items: Vec::new(),
+        project,
+    };
+    let dir = entry.dir()?;
+    let data = dir.join(DATA);
+    std::fs::create_dir_all(&data)
+        .with_context(|| format!("Failed to create {}", data.display()))?;
+
+    for (i, path) in paths.iter().enumerate() {
+        if !path.exists() {
+            continue;
+        }
+        let name = path.file_name().map_or_else(
+            || "item".to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        let stored = format!("{}-{}", i, name);
+        std::fs::rename(path, data.join(&stored))
+            .with_context(|| format!("Failed to move {} to the trash", path.display()))?;
+        entry.items.push(Item {
+            original: path.clone(),
+            stored,
+        });
+    }
+    write_manifest(&dir, &entry)?;
+    Ok(entry)
+}
+
+/// Entries in the trash, newest first; unreadable ones are skipped
+pub fn list() -> Result<Vec<Entry>> {
+    let dir = trash_dir()?;
+    let Ok(dirs) = std::fs::read_dir(&dir) else {
+        return Ok(Vec::new());
+    };
+    let mut entries: Vec<Entry> = dirs
+        .flatten()
+        .filter_map(|dir| {
+            let manifest = std::fs::read_to_string(dir.path().join(MANIFEST)).ok()?;
+            serde_json::from_str(&manifest).ok()
+        })
+        .collect();
+    entries.sort_by(|a, b| b.id.cmp(&a.id));
+    Ok(entries)
+}
+
+/// Move an entry's files back where they were. Fails without moving anything
+/// if one of those places is taken again.
+pub fn restore_files(entry: &Entry) -> Result<()> {
+    if let Some(taken) = entry.items.iter().find(|item| item.original.exists()) {
+        anyhow::bail!(
+            "{} exists again; move it away to restore the trashed copy",
+            taken.original.display()
+        );
+    }
+    let data = entry.dir()?.join(DATA);
+    for item in &entry.items {
+        if let Some(parent) = item.original.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(data.join(&item.stored), &item.original)
+            .with_context(|| format!("Failed to restore {}", item.original.display()))?;
+    }
+    Ok(())
+}
+
+/// Delete an entry and what's left in it
+pub fn remove(entry: &Entry) -> Result<()> {
+    let dir = entry.dir()?;
+    std::fs::remove_dir_all(&dir).with_context(|| format!("Failed to delete {}", dir.display()))
+}
+
+/// Delete the entries made before `cutoff` (all of them without one),
+/// returning how many there were and the space freed
+pub fn empty(cutoff: Option<DateTime<Utc>>) -> Result<(usize, u64)> {
+    let (mut count, mut bytes) = (0, 0);
+    for entry in list()? {
+        if cutoff.is_some_and(|cutoff| entry.deleted_at >= cutoff) {
+            continue;
+        }
+        let size = entry.size();
+        remove(&entry)?;
+        count += 1;
+        bytes += size;
+    }
+    Ok((count, bytes))
+}
+
+fn write_manifest(dir: &Path, entry: &Entry) -> Result<()> {
+    let path = dir.join(MANIFEST);
+    std::fs::write(&path, serde_json::to_string_pretty(entry)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(kind) if kind.is_dir() => dir_size(&entry.path()),
+            Ok(_) => entry.metadata().map_or(0, |meta| meta.len()),
+            Err(_) => 0,
+        })
+        .sum()
+}
@@ -0,0 +1,29 @@
+//! Records the commit the daemon is built from, so `proj status --verbose`
+//! can tell which build is running
+
+use std::process::Command;
+
+fn main() {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+
+    // Builds outside a checkout (e.g. from a crate tarball) have no hash
+    if let Some(hash) = git(&["rev-parse", "--short=12", "HEAD"]) {
+        println!("cargo:rustc-env=PROJ_GIT_HASH={}", hash);
+    }
+
+    // Rebuilt when HEAD moves: a checkout changes HEAD, a commit its branch
+    println!("cargo:rerun-if-changed=build.rs");
+    if let Some(git_dir) = git(&["rev-parse", "--absolute-git-dir"]) {
+        println!("cargo:rerun-if-changed={}/HEAD", git_dir);
+        if let Some(branch) = git(&["symbolic-ref", "-q", "HEAD"]) {
+            println!("cargo:rerun-if-changed={}/{}", git_dir, branch);
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! Web dashboard and the JSON API behind it, served on the proxy's bare host
+
+use http_body_util::{combinators::BoxBody, BodyExt, Full};
+use hyper::body::{Body, Bytes, Frame, Incoming};
+use hyper::{Method, Request, Response, StatusCode};
+use proj_common::{
+    ApiError, ApiMessage, ApiProject, Config, IpcError, IpcRequest, IpcResponse, LogLine,
+    ProjectFilter,
+};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::{Duration, MissedTickBehavior};
+use uuid::Uuid;
+
+use crate::ipc::{self, DaemonState};
+
+/// The dashboard page
+const DASHBOARD_HTML: &str = include_str!("dashboard.html");
+
+/// Header the dashboard sends with actions. Browsers won't add it to
+/// cross-origin requests without a preflight, which the API never approves.
+const ACTION_HEADER: &str = "x-proj-dashboard";
+
+/// Interval between comments keeping an idle log stream open
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+type ApiResponse = Response<BoxBody<Bytes, hyper::Error>>;
+
+/// Serves the dashboard and its API from the daemon's state
+#[derive(Clone)]
+pub struct Api {
+    state: Arc<DaemonState>,
+}
+
+impl Api {
+    pub fn new(state: Arc<DaemonState>) -> Self {
+        Self { state }
+    }
+
+    /// Answer a request to the proxy's own host from `peer`; `None` for
+    /// paths that aren't part of the dashboard
+    pub async fn handle(
+        &self,
+        req: &Request<Incoming>,
+        config: &Config,
+        peer: SocketAddr,
+    ) -> Option<ApiResponse> {
+        let path = req.uri().path().trim_end_matches('/');
+        let segments: Vec<&str> = path.split('/').skip(1).collect();
+
+        // Logs and process control are for this machine's user; the action
+        // header proves nothing about who sends it
+        if !peer.ip().to_canonical().is_loopback() {
+            return match segments.as_slice() {
+                [] | ["__proj", "api", ..] => Some(error_response(
+                    StatusCode::FORBIDDEN,
+                    "The dashboard is only served to this machine",
+                )),
+                _ => None,
+            };
+        }
+
+        let response = match (req.method(), segments.as_slice()) {
+            (&Method::GET, []) => html_response(DASHBOARD_HTML),
+            (&Method::GET, ["__proj", "api", "projects"]) => self.projects(config).await,
+            (&Method::GET, ["__proj", "api", "projects", name, "logs"]) => self.logs(name).await,
+            (&Method::POST, ["__proj", "api", "processes", id, action]) => {
+                if !req.headers().contains_key(ACTION_HEADER) {
+                    return Some(error_response(
+                        StatusCode::FORBIDDEN,
+                        &format!("Missing {} header", ACTION_HEADER),
+                    ));
+                }
+                self.process_action(id, action).await
+            }
+            _ => return None,
+        };
+        Some(response)
+    }
+
+    /// Projects with their URL, routed port and processes
+    async fn projects(&self, config: &Config) -> ApiResponse {
+        let list = IpcRequest::ListProjects {
+            filter: ProjectFilter::default(),
+        };
+        let projects = match self.send(list).await {
+            IpcResponse::Projects(projects) => projects,
+            other => return ipc_error(other),
+        };
+        let processes = match self
+            .send(IpcRequest::ListProcesses { project_name: None })
+            .await
+        {
+            IpcResponse::Processes(processes) => processes,
+            other => return ipc_error(other),
+        };
+        let routes = self.state.routing_table.read().await.clone();
+
+        let mut projects: Vec<ApiProject> = projects
+            .into_iter()
+            .map(|project| {
+                let mut own: Vec<_> = processes
+                    .iter()
+                    .filter(|p| p.project_name == project.name)
+                    .cloned()
+                    .collect();
+                own.sort_by_key(|p| std::cmp::Reverse(p.started_at));
+                ApiProject {
+                    url: config
+                        .project_url(&project)
+                        .unwrap_or_else(|| config.url(&project.name)),
+                    appearance: project.appearance,
+                    routed_port: routes.get(&project.name).and_then(|routes| routes.port),
+                    running: own.iter().any(|p| p.status.is_alive()),
+                    processes: own,
+                    name: project.name,
+                    root_dir: project.root_dir,
+                }
+            })
+            .collect();
+        projects.sort_by(|a, b| a.name.cmp(&b.name));
+        json_response(StatusCode::OK, &projects)
+    }
+
+    /// Server-sent events: a project's recent output, then every new line
+    async fn logs(&self, project_name: &str) -> ApiResponse {
+        if self.state.registry.read().await.get(project_name).is_none() {
+            return error_response(
+                StatusCode::NOT_FOUND,
+                &format!("Project '{}' not found", project_name),
+            );
+        }
+        let (backlog, mut lines) = self.state.logs.lock().await.subscribe(project_name);
+
+        let (tx, rx) = mpsc::channel(64);
+        let project_name = project_name.to_string();
+        tokio::spawn(async move {
+            for line in backlog {
+                if tx.send(log_event(&line)).await.is_err() {
+                    return;
+                }
+            }
+            let mut keepalive = tokio::time::interval(KEEPALIVE_INTERVAL);
+            keepalive.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                let event = tokio::select! {
+                    line = lines.recv() => match line {
+                        Ok(line) if line.project_name == project_name => log_event(&line),
+                        Ok(_) => continue,
+                        Err(broadcast::error::RecvError::Lagged(missed)) => {
+                            Bytes::from(format!(": skipped {} lines\n\n", missed))
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return,
+                    },
+                    _ = keepalive.tick() => Bytes::from_static(b": keepalive\n\n"),
+                };
+                // A failed send means the browser went away
+                if tx.send(event).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "text/event-stream")
+            .header("Cache-Control", "no-store")
+            .body(EventStream(rx).map_err(|never| match never {}).boxed())
+            .unwrap()
+    }
+
+    /// Stop or restart a process
+    async fn process_action(&self, id: &str, action: &str) -> ApiResponse {
+        let Ok(process_id) = id.parse::<Uuid>() else {
+            return error_response(StatusCode::BAD_REQUEST, "Invalid process ID");
+        };
+        let project_name = match self.state.processes.get(process_id) {
+            Some(process) => process.project_name,
+            None => return error_response(StatusCode::NOT_FOUND, "Process not found"),
+        };
+        let request = match action {
+            "stop" => IpcRequest::StopProcess {
+                project_name,
+                process_id,
+            },
+            "restart" => IpcRequest::RestartProcess {
+                project_name,
+                process_id,
+            },
+            _ => {
+                return error_response(
+                    StatusCode::NOT_FOUND,
+                    &format!("Unknown action '{}'", action),
+                )
+            }
+        };
+        match self.send(request).await {
+            IpcResponse::Success { message } => {
+                json_response(StatusCode::OK, &ApiMessage { message })
+            }
+            other => ipc_error(other),
+        }
+    }
+
+    async fn send(&self, request: IpcRequest) -> IpcResponse {
+        ipc::handle_request(request, self.state.clone()).await
+    }
+}
+
+/// Body fed by a channel of ready-made event chunks
+struct EventStream(mpsc::Receiver<Bytes>);
+
+impl Body for EventStream {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        self.0
+            .poll_recv(cx)
+            .map(|chunk| chunk.map(|bytes| Ok(Frame::data(bytes))))
+    }
+}
+
+/// A log line as an SSE `data` event
+fn log_event(line: &LogLine) -> Bytes {
+    let json = serde_json::to_string(line).unwrap_or_default();
+    Bytes::from(format!("data: {}\n\n", json))
+}
+
+/// Turn an unexpected IPC answer into an error response
+fn ipc_error(response: IpcResponse) -> ApiResponse {
+    match response {
+        IpcResponse::Error(error) => {
+            let status = match error {
+                IpcError::ProjectNotFound { .. }
+                | IpcError::ServiceNotFound { .. }
+                | IpcError::ProcessNotFound { .. }
+                | IpcError::RequestNotFound { .. } => StatusCode::NOT_FOUND,
+                IpcError::ProjectExists { .. }
+                | IpcError::ServiceRunning { .. }
+                | IpcError::AlreadyRunning { .. }
+                | IpcError::PortConflict { .. } => StatusCode::CONFLICT,
+                IpcError::SpawnFailed { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+                IpcError::Unauthorized => StatusCode::UNAUTHORIZED,
+                IpcError::InvalidRequest { .. } | IpcError::Failed { .. } => {
+                    StatusCode::BAD_REQUEST
+                }
+            };
+            error_response(status, &format!("{:#}", anyhow::Error::from(error)))
+        }
+        _ => error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Unexpected response from daemon",
+        ),
+    }
+}
+
+fn error_response(status: StatusCode, message: &str) -> ApiResponse {
+    let error = ApiError {
+        error: message.to_string(),
+    };
+    json_response(status, &error)
+}
+
+fn json_response(status: StatusCode, body: &impl serde::Serialize) -> ApiResponse {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .header("Cache-Control", "no-store")
+        .body(full_body(Bytes::from(
+            serde_json::to_string(body).unwrap_or_default(),
+        )))
+        .unwrap()
+}
+
+fn html_response(html: &'static str) -> ApiResponse {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .body(full_body(Bytes::from_static(html.as_bytes())))
+        .unwrap()
+}
+
+fn full_body(bytes: Bytes) -> BoxBody<Bytes, hyper::Error> {
+    Full::new(bytes).map_err(|never| match never {}).boxed()
+}
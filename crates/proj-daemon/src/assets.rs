@@ -0,0 +1,244 @@
+//! Response sizes - what the proxy served each project, by path
+//!
+//! Successful responses to GETs are counted as they stream to the browser, so
+//! sizes are the bytes actually sent (compressed, if the backend compressed
+//! them) whether or not the backend set `Content-Length`. Query strings are
+//! dropped, so a bundle fetched as `app.js?v=1` and `app.js?v=2` is one entry
+//! whose total shows it being served on every reload.
+
+use chrono::{DateTime, Utc};
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use proj_common::{AssetReport, AssetStats};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::RwLock;
+
+/// Paths tracked per project; past this the smallest is forgotten
+const MAX_PATHS_PER_PROJECT: usize = 2000;
+
+/// Response sizes of one project
+struct Assets {
+    since: DateTime<Utc>,
+    paths: HashMap<String, AssetStats>,
+}
+
+impl Default for Assets {
+    fn default() -> Self {
+        Self {
+            since: Utc::now(),
+            paths: HashMap::new(),
+        }
+    }
+}
+
+/// Response sizes per project, shared by every proxy connection
+#[derive(Clone, Default)]
+pub struct AssetStore {
+    projects: Arc<RwLock<HashMap<String, Assets>>>,
+}
+
+impl AssetStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pass `body` through, recording its size for `path` once it has been sent
+    pub fn measure(
+        &self,
+        project_name: &str,
+        path: &str,
+        content_type: Option<String>,
+        body: BoxBody<Bytes, hyper::Error>,
+    ) -> BoxBody<Bytes, hyper::Error> {
+        let store = self.clone();
+        let project_name = project_name.to_string();
+        let path = path.split('?').next().unwrap_or(path).to_string();
+        counted(body, move |bytes| {
+            tokio::spawn(async move {
+                store.record(&project_name, path, content_type, bytes).await;
+            });
+        })
+    }
+
+    /// Count a response of `bytes` for `path`
+    pub async fn record(
+        &self,
+        project_name: &str,
+        path: String,
+        content_type: Option<String>,
+        bytes: u64,
+    ) {
+        let mut projects = self.projects.write().await;
+        let assets = projects.entry(project_name.to_string()).or_default();
+        if !assets.paths.contains_key(&path) && assets.paths.len() >= MAX_PATHS_PER_PROJECT {
+            let smallest = assets
+                .paths
+                .values()
+                .min_by_key(|stats| stats.total_bytes)
+                .map(|stats| stats.path.clone());
+            if let Some(smallest) = smallest {
+                assets.paths.remove(&smallest);
+            }
+        }
+
+        let stats = assets.paths.entry(path.clone()).or_insert(AssetStats {
+            path,
+            content_type: None,
+            requests: 0,
+            last_bytes: 0,
+            max_bytes: 0,
+            total_bytes: 0,
+        });
+        stats.content_type = content_type.or(stats.content_type.take());
+        stats.requests += 1;
+        stats.last_bytes = bytes;
+        stats.max_bytes = stats.max_bytes.max(bytes);
+        stats.total_bytes += bytes;
+    }
+
+    /// A project's response sizes, largest total first
+    pub async fn report(&self, project_name: &str) -> AssetReport {
+        let projects = self.projects.read().await;
+        let Some(assets) = projects.get(project_name) else {
+            return AssetReport {
+                since: Utc::now(),
+                assets: vec![],
+            };
+        };
+        let mut stats: Vec<AssetStats> = assets.paths.values().cloned().collect();
+        stats.sort_by(|a, b| {
+            b.total_bytes
+                .cmp(&a.total_bytes)
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        AssetReport {
+            since: assets.since,
+            assets: stats,
+        }
+    }
+
+    /// Forget a project's response sizes
+    pub async fn clear(&self, project_name: &str) {
+        self.projects.write().await.remove(project_name);
+    }
+}
+
+/// Pass `body` through, calling `done` with its size once it ends or is dropped
+pub fn counted(
+    body: BoxBody<Bytes, hyper::Error>,
+    done: impl FnOnce(u64) + Send + Sync + 'static,
+) -> BoxBody<Bytes, hyper::Error> {
+    CountedBody {
+        inner: body,
+        bytes: 0,
+        done: Some(Box::new(done)),
+    }
+    .boxed()
+}
+
+/// A body that reports how many bytes passed through it once it ends or is
+/// dropped, e.g. when the browser navigates away mid-download
+struct CountedBody {
+    inner: BoxBody<Bytes, hyper::Error>,
+    bytes: u64,
+    done: Option<Box<dyn FnOnce(u64) + Send + Sync>>,
+}
+
+impl CountedBody {
+    fn finish(&mut self) {
+        if let Some(done) = self.done.take() {
+            done(self.bytes);
+        }
+    }
+}
+
+impl Body for CountedBody {
+    type Data = Bytes;
+    type Error = hyper::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, hyper::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    self.bytes += data.len() as u64;
+                }
+            }
+            Poll::Ready(None) => self.finish(),
+            _ => {}
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for CountedBody {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_report() {
+        let store = AssetStore::new();
+        let js = Some("application/javascript".to_string());
+        for _ in 0..3 {
+            store
+                .record("app", "/app.js.map".to_string(), None, 9 << 20)
+                .await;
+        }
+        store
+            .record("app", "/app.js".to_string(), js.clone(), 1000)
+            .await;
+        store.record("app", "/app.js".to_string(), js, 3000).await;
+
+        let report = store.report("app").await;
+        assert_eq!(report.assets.len(), 2);
+        let map = &report.assets[0];
+        assert_eq!((map.requests, map.total_bytes), (3, 27 << 20));
+        assert_eq!(map.kind(), "sourcemap");
+        let bundle = &report.assets[1];
+        assert_eq!(
+            (bundle.last_bytes, bundle.max_bytes, bundle.total_bytes),
+            (3000, 3000, 4000)
+        );
+        assert_eq!(bundle.kind(), "js");
+
+        store.clear("app").await;
+        assert!(store.report("app").await.assets.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_measure() {
+        let store = AssetStore::new();
+        let body = http_body_util::Full::new(Bytes::from_static(b"hello"))
+            .map_err(|never| match never {})
+            .boxed();
+        let body = store.measure("app", "/hello.txt?v=2", None, body);
+        assert_eq!(body.collect().await.unwrap().to_bytes().len(), 5);
+        // Recorded from a spawned task
+        tokio::task::yield_now().await;
+
+        let report = store.report("app").await;
+        assert_eq!(report.assets[0].path, "/hello.txt");
+        assert_eq!(report.assets[0].total_bytes, 5);
+    }
+}
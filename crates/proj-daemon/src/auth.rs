@@ -0,0 +1,74 @@
+//! Upstream credentials - Authorization headers the proxy attaches to a
+//! project's requests under a path prefix, from secrets in the keychain
+
+use hyper::header::HeaderValue;
+use proj_common::{secrets, Project};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::routes::prefix_matches;
+
+/// Authorization headers per project, by path prefix
+#[derive(Clone, Default)]
+pub struct AuthStore {
+    headers: Arc<RwLock<HashMap<String, BTreeMap<String, HeaderValue>>>>,
+}
+
+impl AuthStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Look up the secrets for a project's credentials, replacing what it had
+    pub async fn load(&self, project: &Project) {
+        if project.proxy.auth.is_empty() {
+            self.remove(&project.name).await;
+            return;
+        }
+        let (name, auth) = (project.name.clone(), project.proxy.auth.clone());
+        let lookup = tokio::task::spawn_blocking(move || {
+            let mut headers = BTreeMap::new();
+            for (prefix, scheme) in auth {
+                let account = secrets::auth_account(&name, &prefix);
+                match secrets::get(&account) {
+                    Ok(Some(secret)) => match HeaderValue::from_str(&scheme.header(&secret)) {
+                        Ok(mut value) => {
+                            value.set_sensitive(true);
+                            headers.insert(prefix, value);
+                        }
+                        Err(_) => tracing::warn!("Secret for {} isn't a valid header", account),
+                    },
+                    Ok(None) => tracing::warn!("No secret stored for {}", account),
+                    Err(e) => tracing::warn!("Failed to read secret for {}: {:#}", account, e),
+                }
+            }
+            headers
+        });
+        match lookup.await {
+            Ok(headers) => {
+                self.headers
+                    .write()
+                    .await
+                    .insert(project.name.clone(), headers);
+            }
+            Err(e) => tracing::error!("Credential lookup for {} failed: {}", project.name, e),
+        }
+    }
+
+    pub async fn remove(&self, project_name: &str) {
+        self.headers.write().await.remove(project_name);
+    }
+
+    /// The Authorization header for a request to `path`, from the longest
+    /// matching prefix
+    pub async fn header(&self, project_name: &str, path: &str) -> Option<HeaderValue> {
+        let headers = self.headers.read().await;
+        headers
+            .get(project_name)?
+            .iter()
+            .filter(|(prefix, _)| prefix_matches(prefix, path))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, value)| value.clone())
+    }
+}
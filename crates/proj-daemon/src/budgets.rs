@@ -0,0 +1,236 @@
+//! Performance budgets - proxied traffic measured against a project's limits
+//!
+//! Latency is the time to response headers, and its 95th percentile is taken
+//! over a project's last requests once there are enough of them. Document
+//! size is that of the latest HTML response to a GET. Every project is
+//! measured, so setting a budget shows where traffic stands right away; a
+//! measurement crossing a budget emits a `budget` event either way, which
+//! notifies (see [`crate::notifications`]) when it goes over.
+
+use proj_common::{BudgetMetric, BudgetStatus, EventKind, PerfBudgets};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::events::EventBus;
+
+/// Requests the latency percentile is taken over
+const LATENCY_WINDOW: usize = 200;
+
+/// Requests needed before the latency percentile means anything
+const MIN_LATENCY_SAMPLES: usize = 20;
+
+/// Recent measurements of one project
+#[derive(Default)]
+struct Measurements {
+    /// Latest latencies in milliseconds, oldest first
+    latencies: VecDeque<u64>,
+    /// Size of the latest main document, and how many have been seen
+    document: Option<(u64, usize)>,
+    /// Budgets currently exceeded
+    exceeded: HashSet<BudgetMetric>,
+}
+
+impl Measurements {
+    fn value(&self, metric: BudgetMetric) -> Option<u64> {
+        match metric {
+            BudgetMetric::P95Latency => p95(&self.latencies),
+            BudgetMetric::DocumentSize => self.document.map(|(size, _)| size),
+        }
+    }
+
+    fn add_document(&mut self, size: u64) {
+        let count = self.document.map_or(0, |(_, count)| count);
+        self.document = Some((size, count + 1));
+    }
+
+    fn samples(&self, metric: BudgetMetric) -> usize {
+        match metric {
+            BudgetMetric::P95Latency => self.latencies.len(),
+            BudgetMetric::DocumentSize => self.document.map_or(0, |(_, count)| count),
+        }
+    }
+}
+
+/// Traffic measurements per project, shared by every proxy connection
+#[derive(Clone)]
+pub struct BudgetTracker {
+    projects: Arc<RwLock<HashMap<String, Measurements>>>,
+    events: EventBus,
+}
+
+impl BudgetTracker {
+    pub fn new(events: EventBus) -> Self {
+        Self {
+            projects: Arc::default(),
+            events,
+        }
+    }
+
+    /// Record a proxied request, and the main document it returned if it did
+    pub async fn record(
+        &self,
+        project_name: &str,
+        budgets: &PerfBudgets,
+        latency_ms: u64,
+        document_bytes: Option<u64>,
+    ) {
+        let mut projects = self.projects.write().await;
+        let measurements = projects.entry(project_name.to_string()).or_default();
+        if measurements.latencies.len() == LATENCY_WINDOW {
+            measurements.latencies.pop_front();
+        }
+        measurements.latencies.push_back(latency_ms);
+        if let Some(size) = document_bytes {
+            measurements.add_document(size);
+        }
+        self.check(project_name, budgets, measurements);
+    }
+
+    /// Record the size of a main document that finished streaming after its
+    /// request was recorded
+    pub async fn record_document(&self, project_name: &str, budgets: &PerfBudgets, bytes: u64) {
+        let mut projects = self.projects.write().await;
+        let measurements = projects.entry(project_name.to_string()).or_default();
+        measurements.add_document(bytes);
+        self.check(project_name, budgets, measurements);
+    }
+
+    /// Report budgets a project's measurements crossed, either way
+    fn check(&self, project_name: &str, budgets: &PerfBudgets, measurements: &mut Measurements) {
+        for metric in BudgetMetric::ALL {
+            let (Some(limit), Some(value)) = (budgets.limit(metric), measurements.value(metric))
+            else {
+                measurements.exceeded.remove(&metric);
+                continue;
+            };
+            let exceeded = value > limit;
+            let changed = if exceeded {
+                measurements.exceeded.insert(metric)
+            } else {
+                measurements.exceeded.remove(&metric)
+            };
+            if !changed {
+                continue;
+            }
+
+            if exceeded {
+                tracing::warn!(
+                    "{}: {} is {}, over its {} budget",
+                    project_name,
+                    metric,
+                    metric.format(value),
+                    metric.format(limit)
+                );
+            } else {
+                tracing::info!("{}: {} is back within budget", project_name, metric);
+            }
+            self.events.emit(
+                project_name,
+                EventKind::Budget {
+                    metric,
+                    value,
+                    limit,
+                    exceeded,
+                },
+            );
+        }
+    }
+
+    /// Where a project's traffic stands against its budgets
+    pub async fn status(&self, project_name: &str, budgets: &PerfBudgets) -> Vec<BudgetStatus> {
+        let projects = self.projects.read().await;
+        let measurements = projects.get(project_name);
+        BudgetMetric::ALL
+            .into_iter()
+            .map(|metric| {
+                let limit = budgets.limit(metric);
+                let value = measurements.and_then(|m| m.value(metric));
+                BudgetStatus {
+                    metric,
+                    limit,
+                    value,
+                    samples: measurements.map_or(0, |m| m.samples(metric)),
+                    exceeded: limit.zip(value).is_some_and(|(limit, value)| value > limit),
+                }
+            })
+            .collect()
+    }
+
+    /// Forget a project's measurements
+    pub async fn clear(&self, project_name: &str) {
+        self.projects.write().await.remove(project_name);
+    }
+}
+
+/// 95th percentile (nearest rank) of enough latencies
+fn p95(latencies: &VecDeque<u64>) -> Option<u64> {
+    if latencies.len() < MIN_LATENCY_SAMPLES {
+        return None;
+    }
+    let mut sorted: Vec<u64> = latencies.iter().copied().collect();
+    sorted.sort_unstable();
+    let rank = (sorted.len() * 95).div_ceil(100);
+    sorted.get(rank.saturating_sub(1)).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_budgets() {
+        let events = EventBus::new();
+        let mut rx = events.subscribe();
+        let tracker = BudgetTracker::new(events);
+        let budgets = PerfBudgets {
+            p95_ms: Some(100),
+            document_bytes: None,
+        };
+
+        for latency in 1..=19 {
+            tracker.record("app", &budgets, latency * 10, None).await;
+        }
+        // Too few requests to judge yet
+        let status = tracker.status("app", &budgets).await;
+        assert_eq!(status[0].value, None);
+        assert_eq!(status[0].samples, 19);
+
+        tracker.record("app", &budgets, 500, Some(2048)).await;
+        let status = tracker.status("app", &budgets).await;
+        assert_eq!((status[0].value, status[0].exceeded), (Some(190), true));
+        assert_eq!((status[1].value, status[1].limit), (Some(2048), None));
+        assert!(matches!(
+            rx.try_recv().unwrap().kind,
+            EventKind::Budget {
+                metric: BudgetMetric::P95Latency,
+                value: 190,
+                exceeded: true,
+                ..
+            }
+        ));
+
+        // Staying over doesn't repeat the event; dropping back under does
+        tracker.record("app", &budgets, 300, None).await;
+        assert!(rx.try_recv().is_err());
+        for _ in 0..LATENCY_WINDOW {
+            tracker.record("app", &budgets, 20, None).await;
+        }
+        assert!(matches!(
+            rx.try_recv().unwrap().kind,
+            EventKind::Budget {
+                exceeded: false,
+                ..
+            }
+        ));
+        assert!(!tracker.status("app", &budgets).await[0].exceeded);
+    }
+
+    #[test]
+    fn test_p95() {
+        let latencies: VecDeque<u64> = (1..=100).collect();
+        assert_eq!(p95(&latencies), Some(95));
+        let latencies: VecDeque<u64> = (1..=20).collect();
+        assert_eq!(p95(&latencies), Some(19));
+    }
+}
@@ -0,0 +1,101 @@
+//! Request capture - keeps recent proxied requests per project for replay
+
+use chrono::Utc;
+use hyper::body::Bytes;
+use hyper::http::request::Parts;
+use proj_common::CapturedRequest;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Maximum number of requests kept per project
+const MAX_CAPTURES_PER_PROJECT: usize = 200;
+
+/// Largest request body that is buffered and captured
+pub const MAX_CAPTURED_BODY: u64 = 64 * 1024;
+
+/// Captured requests per project, newest last
+#[derive(Clone, Default)]
+pub struct CaptureStore {
+    requests: Arc<RwLock<HashMap<String, VecDeque<CapturedRequest>>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl CaptureStore {
+    pub fn new() -> Self {
+        Self {
+            requests: Arc::default(),
+            next_id: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    /// Build a capture record from request parts and an optional buffered body
+    pub fn record(
+        &self,
+        parts: &Parts,
+        body: Option<&Bytes>,
+        upstream_port: u16,
+    ) -> CapturedRequest {
+        let headers = parts
+            .headers
+            .iter()
+            .filter_map(|(name, value)| {
+                value
+                    .to_str()
+                    .ok()
+                    .map(|v| (name.as_str().to_string(), v.to_string()))
+            })
+            .collect();
+
+        CapturedRequest {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            timestamp: Utc::now(),
+            method: parts.method.to_string(),
+            path: parts
+                .uri
+                .path_and_query()
+                .map(|pq| pq.to_string())
+                .unwrap_or_else(|| "/".to_string()),
+            headers,
+            body: body.and_then(|b| String::from_utf8(b.to_vec()).ok()),
+            upstream_port,
+            status: None,
+            duration_ms: None,
+        }
+    }
+
+    /// Store a completed capture for a project
+    pub async fn push(&self, project_name: &str, request: CapturedRequest) {
+        let mut requests = self.requests.write().await;
+        let entries = requests.entry(project_name.to_string()).or_default();
+        if entries.len() >= MAX_CAPTURES_PER_PROJECT {
+            entries.pop_front();
+        }
+        entries.push_back(request);
+    }
+
+    /// Most recent captures for a project, newest first
+    pub async fn list(&self, project_name: &str, limit: usize) -> Vec<CapturedRequest> {
+        let requests = self.requests.read().await;
+        requests
+            .get(project_name)
+            .map(|entries| entries.iter().rev().take(limit).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Drop all captures for a project
+    pub async fn clear(&self, project_name: &str) {
+        self.requests.write().await.remove(project_name);
+    }
+
+    /// Look up a single capture by ID
+    pub async fn get(&self, project_name: &str, id: u64) -> Option<CapturedRequest> {
+        let requests = self.requests.read().await;
+        requests
+            .get(project_name)?
+            .iter()
+            .find(|r| r.id == id)
+            .cloned()
+    }
+}
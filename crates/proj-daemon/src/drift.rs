@@ -0,0 +1,348 @@
+//! API schema drift detection - validates proxied JSON responses against a
+//! project's OpenAPI (JSON) spec and records mismatches
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use proj_common::DriftReport;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::sync::RwLock;
+
+/// Maximum number of drift reports kept per project
+const MAX_REPORTS_PER_PROJECT: usize = 100;
+
+/// Largest response body that is buffered for validation
+pub const MAX_VALIDATED_BODY: u64 = 1024 * 1024;
+
+/// A parsed spec along with the mtime it was loaded at
+struct CachedSpec {
+    modified: Option<SystemTime>,
+    spec: Arc<Value>,
+}
+
+/// Loaded specs and recorded mismatches
+#[derive(Clone, Default)]
+pub struct DriftStore {
+    specs: Arc<RwLock<HashMap<PathBuf, CachedSpec>>>,
+    reports: Arc<RwLock<HashMap<String, VecDeque<DriftReport>>>>,
+}
+
+impl DriftStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Validate a response and record any mismatches for the project
+    pub async fn check(
+        &self,
+        project_name: &str,
+        spec_path: &Path,
+        method: &str,
+        path: &str,
+        status: u16,
+        body: &[u8],
+    ) {
+        let spec = match self.load_spec(spec_path).await {
+            Ok(spec) => spec,
+            Err(e) => {
+                tracing::debug!("Skipping drift check for {}: {}", project_name, e);
+                return;
+            }
+        };
+
+        let body: Option<Value> = serde_json::from_slice(body).ok();
+        let problems = validate_response(&spec, method, path, status, body.as_ref());
+        if problems.is_empty() {
+            return;
+        }
+
+        let mut reports = self.reports.write().await;
+        let entries = reports.entry(project_name.to_string()).or_default();
+        for message in problems {
+            tracing::info!(
+                "API drift in {}: {} {} -> {}",
+                project_name,
+                method,
+                path,
+                message
+            );
+            if entries.len() >= MAX_REPORTS_PER_PROJECT {
+                entries.pop_front();
+            }
+            entries.push_back(DriftReport {
+                timestamp: Utc::now(),
+                method: method.to_string(),
+                path: path.to_string(),
+                status,
+                message,
+            });
+        }
+    }
+
+    /// Recorded mismatches for a project, newest first
+    pub async fn reports(&self, project_name: &str) -> Vec<DriftReport> {
+        let reports = self.reports.read().await;
+        reports
+            .get(project_name)
+            .map(|entries| entries.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Forget recorded mismatches for a project
+    pub async fn clear(&self, project_name: &str) {
+        self.reports.write().await.remove(project_name);
+    }
+
+    /// Load (or reuse a cached copy of) a JSON OpenAPI spec
+    async fn load_spec(&self, path: &Path) -> Result<Arc<Value>> {
+        let modified = tokio::fs::metadata(path)
+            .await
+            .with_context(|| format!("Spec {:?} not readable", path))?
+            .modified()
+            .ok();
+
+        if let Some(cached) = self.specs.read().await.get(path) {
+            if cached.modified == modified {
+                return Ok(cached.spec.clone());
+            }
+        }
+
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .context("Failed to read OpenAPI spec")?;
+        let spec: Value = serde_json::from_str(&content).context("OpenAPI spec must be JSON")?;
+        let spec = Arc::new(spec);
+
+        self.specs.write().await.insert(
+            path.to_path_buf(),
+            CachedSpec {
+                modified,
+                spec: spec.clone(),
+            },
+        );
+        Ok(spec)
+    }
+}
+
+/// Validate a response against the spec, returning human-readable problems
+fn validate_response(
+    spec: &Value,
+    method: &str,
+    path: &str,
+    status: u16,
+    body: Option<&Value>,
+) -> Vec<String> {
+    let path = path.split('?').next().unwrap_or(path);
+    let Some(operation) = find_operation(spec, method, path) else {
+        return vec!["undocumented operation".to_string()];
+    };
+
+    let responses = &operation["responses"];
+    let response = responses
+        .get(status.to_string())
+        .or_else(|| responses.get(format!("{}XX", status / 100)))
+        .or_else(|| responses.get("default"));
+    let Some(response) = response else {
+        return vec![format!("undocumented status {}", status)];
+    };
+
+    let schema = &response["content"]["application/json"]["schema"];
+    match (schema.is_null(), body) {
+        (true, _) => Vec::new(),
+        (false, None) => vec!["response body is not valid JSON".to_string()],
+        (false, Some(body)) => {
+            let mut problems = Vec::new();
+            validate_schema(spec, schema, body, "$", &mut problems);
+            problems
+        }
+    }
+}
+
+/// Find the operation object matching a method and concrete path
+fn find_operation<'a>(spec: &'a Value, method: &str, path: &str) -> Option<&'a Value> {
+    let paths = spec.get("paths")?.as_object()?;
+    let method = method.to_ascii_lowercase();
+
+    paths
+        .iter()
+        .filter(|(template, _)| path_matches(template, path))
+        // Prefer templates with fewer parameters (`/users/me` over `/users/{id}`)
+        .min_by_key(|(template, _)| template.matches('{').count())
+        .and_then(|(_, item)| item.get(&method))
+}
+
+/// Match an OpenAPI path template such as `/users/{id}` against a path
+fn path_matches(template: &str, path: &str) -> bool {
+    let template: Vec<&str> = template.trim_end_matches('/').split('/').collect();
+    let path: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+    template.len() == path.len()
+        && template
+            .iter()
+            .zip(&path)
+            .all(|(t, p)| (t.starts_with('{') && t.ends_with('}') && !p.is_empty()) || t == p)
+}
+
+/// Validate a value against a (subset of) JSON Schema
+fn validate_schema(
+    spec: &Value,
+    schema: &Value,
+    value: &Value,
+    location: &str,
+    problems: &mut Vec<String>,
+) {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        match resolve_ref(spec, reference) {
+            Some(resolved) => validate_schema(spec, resolved, value, location, problems),
+            None => problems.push(format!("{}: unresolved $ref {}", location, reference)),
+        }
+        return;
+    }
+
+    if value.is_null() && schema.get("nullable").and_then(Value::as_bool) == Some(true) {
+        return;
+    }
+
+    for key in ["oneOf", "anyOf"] {
+        if let Some(variants) = schema.get(key).and_then(Value::as_array) {
+            let matches = variants.iter().any(|variant| {
+                let mut nested = Vec::new();
+                validate_schema(spec, variant, value, location, &mut nested);
+                nested.is_empty()
+            });
+            if !matches {
+                problems.push(format!("{}: matches none of {}", location, key));
+            }
+            return;
+        }
+    }
+
+    if let Some(all) = schema.get("allOf").and_then(Value::as_array) {
+        for variant in all {
+            validate_schema(spec, variant, value, location, problems);
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            problems.push(format!("{}: {} is not an allowed value", location, value));
+        }
+    }
+
+    let Some(expected) = schema.get("type").and_then(Value::as_str) else {
+        return;
+    };
+    let actual = json_type(value);
+    let type_ok = expected == actual || (expected == "number" && actual == "integer");
+    if !type_ok {
+        problems.push(format!(
+            "{}: expected {}, got {}",
+            location, expected, actual
+        ));
+        return;
+    }
+
+    match value {
+        Value::Object(map) => {
+            if let Some(required) = schema.get("required").and_then(Value::as_array) {
+                for key in required.iter().filter_map(Value::as_str) {
+                    if !map.contains_key(key) {
+                        problems.push(format!("{}: missing required field '{}'", location, key));
+                    }
+                }
+            }
+            if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+                for (key, property) in properties {
+                    if let Some(field) = map.get(key) {
+                        let nested = format!("{}.{}", location, key);
+                        validate_schema(spec, property, field, &nested, problems);
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    let nested = format!("{}[{}]", location, i);
+                    validate_schema(spec, item_schema, item, &nested, problems);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Resolve a local `#/...` JSON pointer reference
+fn resolve_ref<'a>(spec: &'a Value, reference: &str) -> Option<&'a Value> {
+    spec.pointer(reference.strip_prefix('#')?)
+}
+
+/// JSON Schema type name of a value
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_validate_response() {
+        let spec = json!({
+            "paths": {
+                "/users/{id}": {
+                    "get": {
+                        "responses": {
+                            "200": {
+                                "content": {
+                                    "application/json": {
+                                        "schema": { "$ref": "#/components/schemas/User" }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+            "components": {
+                "schemas": {
+                    "User": {
+                        "type": "object",
+                        "required": ["id", "name"],
+                        "properties": {
+                            "id": { "type": "integer" },
+                            "name": { "type": "string" }
+                        }
+                    }
+                }
+            }
+        });
+
+        let ok = json!({ "id": 1, "name": "ada" });
+        assert!(validate_response(&spec, "GET", "/users/1", 200, Some(&ok)).is_empty());
+
+        let bad = json!({ "id": "1" });
+        let problems = validate_response(&spec, "GET", "/users/1?x=y", 200, Some(&bad));
+        assert_eq!(problems.len(), 2);
+
+        assert_eq!(
+            validate_response(&spec, "GET", "/users/1", 404, None),
+            vec!["undocumented status 404"]
+        );
+        assert_eq!(
+            validate_response(&spec, "DELETE", "/users/1", 200, None),
+            vec!["undocumented operation"]
+        );
+    }
+}
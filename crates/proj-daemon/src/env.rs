@@ -0,0 +1,162 @@
+//! Per-project environment variables, from `env.json`, the project's `.env`
+//! and the shared env profiles it uses
+
+use anyhow::{Context, Result};
+use proj_common::{dotenv, load_env_profiles, project_env_path, EnvSource, EnvVar};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Load the variables stored with `proj <name> env set`
+pub fn load(project_name: &str) -> Result<BTreeMap<String, String>> {
+    let path = project_env_path(project_name)?;
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let content = std::fs::read_to_string(&path).context("Failed to read env file")?;
+    serde_json::from_str(&content).context("Failed to parse env file")
+}
+
+/// Save the stored variables for a project
+pub fn save(project_name: &str, vars: &BTreeMap<String, String>) -> Result<()> {
+    let path = project_env_path(project_name)?;
+    let content = serde_json::to_string_pretty(vars)?;
+    std::fs::write(&path, content).context("Failed to write env file")
+}
+
+/// Effective environment for a project: its env profiles, in order, then its
+/// `.env`, then stored variables
+pub fn resolve(project_name: &str, root_dir: &Path, profiles: &[String]) -> Vec<EnvVar> {
+    let mut vars: BTreeMap<String, EnvVar> = BTreeMap::new();
+
+    if !profiles.is_empty() {
+        match load_env_profiles() {
+            Ok(defined) => {
+                for name in profiles {
+                    let Some(profile) = defined.get(name) else {
+                        tracing::warn!(
+                            "{} uses env profile '{}', which is not defined",
+                            project_name,
+                            name
+                        );
+                        continue;
+                    };
+                    for (key, value) in profile {
+                        let value = expand(value, |var| std::env::var(var).ok());
+                        let source = EnvSource::Profile;
+                        vars.insert(
+                            key.clone(),
+                            EnvVar {
+                                key: key.clone(),
+                                value,
+                                source,
+                            },
+                        );
+                    }
+                }
+            }
+            Err(e) => tracing::warn!("Ignoring env profiles for {}: {:#}", project_name, e),
+        }
+    }
+
+    if let Ok(content) = std::fs::read_to_string(root_dir.join(".env")) {
+        for (key, value) in dotenv::parse(&content) {
+            let source = EnvSource::DotEnv;
+            vars.insert(key.clone(), EnvVar { key, value, source });
+        }
+    }
+
+    match load(project_name) {
+        Ok(stored) => {
+            for (key, value) in stored {
+                let source = EnvSource::Project;
+                vars.insert(key.clone(), EnvVar { key, value, source });
+            }
+        }
+        Err(e) => tracing::warn!("Ignoring env for {}: {}", project_name, e),
+    }
+
+    vars.into_values().collect()
+}
+
+/// Problems with a project's environment worth telling whoever starts a run:
+/// profiles that aren't defined and a `.env.example` without a `.env`. The
+/// `.env` itself isn't opened, as `resolve` already reads it once.
+pub fn warnings(root_dir: &Path, profiles: &[String]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if !profiles.is_empty() {
+        let defined = load_env_profiles().unwrap_or_default();
+        for name in profiles.iter().filter(|name| !defined.contains_key(*name)) {
+            warnings.push(format!("Env profile '{}' is not defined", name));
+        }
+    }
+    if !root_dir.join(".env").exists() && root_dir.join(".env.example").exists() {
+        warnings.push(format!(
+            "No .env in {}, only a .env.example",
+            root_dir.display()
+        ));
+    }
+    warnings
+}
+
+/// Replace `${NAME}` in a profile value with `NAME` from the daemon's own
+/// environment, so a profile can extend a path like `LD_LIBRARY_PATH`. An
+/// unset variable expands to nothing, along with a separator it leaves dangling.
+fn expand(value: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let name = &rest[start + 2..start + len];
+        rest = &rest[start + len + 1..];
+        match lookup(name).filter(|v| !v.is_empty()) {
+            Some(found) => out.push_str(&found),
+            None => {
+                if out.ends_with(':') && (rest.is_empty() || rest.starts_with(':')) {
+                    out.pop();
+                } else if out.is_empty() {
+                    rest = rest.strip_prefix(':').unwrap_or(rest);
+                }
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warnings() {
+        let dir = std::env::temp_dir().join(format!("proj-env-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(warnings(&dir, &[]).is_empty());
+
+        std::fs::write(dir.join(".env.example"), "PORT=\n").unwrap();
+        assert_eq!(
+            warnings(&dir, &[]),
+            [format!("No .env in {}, only a .env.example", dir.display())]
+        );
+
+        std::fs::write(dir.join(".env"), "PORT=3000\n").unwrap();
+        assert!(warnings(&dir, &[]).is_empty());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_expand() {
+        let lookup = |name: &str| (name == "LD_LIBRARY_PATH").then(|| "/usr/lib".to_string());
+        assert_eq!(
+            expand("/usr/local/cuda/lib64:${LD_LIBRARY_PATH}", lookup),
+            "/usr/local/cuda/lib64:/usr/lib"
+        );
+        assert_eq!(expand("/opt/cuda:${UNSET}", lookup), "/opt/cuda");
+        assert_eq!(expand("${UNSET}:/opt/cuda", lookup), "/opt/cuda");
+        assert_eq!(expand("a${UNSET}b", lookup), "ab");
+        assert_eq!(expand("${unterminated", lookup), "${unterminated");
+    }
+}
@@ -0,0 +1,34 @@
+//! Daemon event bus - process, route and project events for subscribed clients
+
+use chrono::Utc;
+use proj_common::{Event, EventKind};
+use tokio::sync::broadcast;
+
+/// Fan-out of daemon events; cheap to clone into every component that emits
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<Event>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self {
+            tx: broadcast::channel(256).0,
+        }
+    }
+
+    /// Publish an event about a project
+    pub fn emit(&self, project_name: &str, kind: EventKind) {
+        // No subscribers is fine
+        let _ = self.tx.send(Event {
+            timestamp: Utc::now(),
+            project_name: project_name.to_string(),
+            kind,
+        });
+    }
+
+    /// Receive every event emitted from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.tx.subscribe()
+    }
+}
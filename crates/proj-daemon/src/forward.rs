@@ -0,0 +1,302 @@
+//! Forward proxy for other devices
+//!
+//! With `forward_proxy` set (e.g. `0.0.0.0:8888`), a phone or tablet on the
+//! network can use this machine as its HTTP or SOCKS5 proxy and open
+//! `http://my-app.localhost` like a browser here would. Project hosts are
+//! answered by the reverse proxy; any other host is reached directly, so the
+//! rest of the device's browsing keeps working. The listener forwards
+//! anywhere for whoever reaches it, so only clients on loopback and private
+//! networks are served, and never to this machine except for its projects.
+
+use anyhow::Result;
+use hyper_util::rt::TokioIo;
+use proj_common::outbound::Feature;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::interfaces;
+use crate::outbound;
+use crate::panics;
+use crate::proxy::{self, Client, ProxyContext};
+
+/// SOCKS protocol version, the first byte of a SOCKS5 greeting
+const SOCKS_VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const NO_ACCEPTABLE_METHOD: u8 = 0xff;
+const CONNECT: u8 = 1;
+
+/// Address types of a SOCKS request
+const IPV4: u8 = 1;
+const DOMAIN: u8 = 3;
+const IPV6: u8 = 4;
+
+/// SOCKS reply codes
+const SUCCEEDED: u8 = 0;
+const GENERAL_FAILURE: u8 = 1;
+const NOT_ALLOWED: u8 = 2;
+const HOST_UNREACHABLE: u8 = 4;
+const CONNECTION_REFUSED: u8 = 5;
+const COMMAND_NOT_SUPPORTED: u8 = 7;
+const ADDRESS_NOT_SUPPORTED: u8 = 8;
+
+/// Accept clients at `forward_proxy`, rebinding when it changes or the
+/// network changes under it
+pub async fn run(ctx: ProxyContext) {
+    let mut config = ctx.config.clone();
+    let mut network = interfaces::Watch::new(ctx.network.clone());
+    loop {
+        let addr = config.borrow_and_update().forward_proxy;
+        let listener = match addr {
+            Some(addr) => bind(addr).await,
+            None => None,
+        };
+
+        loop {
+            tokio::select! {
+                accepted = accept(listener.as_ref()) => match accepted {
+                    Ok((stream, peer)) if is_private(peer.ip()) => {
+                        panics::spawn("forward proxy connection", serve(stream, peer, ctx.clone()));
+                    }
+                    Ok((_, peer)) => {
+                        tracing::warn!("Refused forward proxy client {}: not on a private network", peer);
+                    }
+                    Err(e) => tracing::error!("Forward proxy accept error: {}", e),
+                },
+                changed = config.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                    if config.borrow().forward_proxy != addr {
+                        break;
+                    }
+                }
+                _ = network.rebind_needed(addr.map(|addr| addr.ip()), listener.is_some()) => {
+                    tracing::info!("Network changed; rebinding the forward proxy");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+async fn bind(addr: SocketAddr) -> Option<TcpListener> {
+    match TcpListener::bind(addr).await {
+        Ok(listener) => {
+            tracing::info!("Forward proxy (HTTP and SOCKS5) listening on {}", addr);
+            Some(listener)
+        }
+        Err(e) => {
+            tracing::error!("Forward proxy failed to listen on {}: {}", addr, e);
+            None
+        }
+    }
+}
+
+/// Accept the next client, or wait forever without a listener
+async fn accept(listener: Option<&TcpListener>) -> std::io::Result<(TcpStream, SocketAddr)> {
+    match listener {
+        Some(listener) => listener.accept().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Whether a client is on this machine or a private network
+pub(crate) fn is_private(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+        IpAddr::V6(ip) => ip.is_loopback() || ip.is_unique_local() || ip.is_unicast_link_local(),
+    }
+}
+
+/// Speak SOCKS5 to a client that greets with it, else HTTP
+async fn serve(stream: TcpStream, peer: SocketAddr, ctx: ProxyContext) {
+    let _ = stream.set_nodelay(true);
+    let mut first = [0u8; 1];
+    match stream.peek(&mut first).await {
+        Ok(1) if first[0] == SOCKS_VERSION => {
+            if let Err(e) = socks(stream, peer, &ctx).await {
+                tracing::debug!("SOCKS connection from {} closed: {}", peer, e);
+            }
+        }
+        Ok(1) => {
+            let client = Client {
+                addr: peer,
+                https: false,
+                forward: true,
+            };
+            proxy::serve_connection(TokioIo::new(stream), ctx, client).await;
+        }
+        _ => {}
+    }
+}
+
+/// Serve a SOCKS5 CONNECT (RFC 1928, without authentication), serving
+/// project hosts as the proxy's own listener would
+async fn socks(mut stream: TcpStream, peer: SocketAddr, ctx: &ProxyContext) -> Result<()> {
+    // Greeting: version and the authentication methods the client offers
+    let mut greeting = [0u8; 2];
+    stream.read_exact(&mut greeting).await?;
+    let mut methods = vec![0u8; greeting[1] as usize];
+    stream.read_exact(&mut methods).await?;
+    if !methods.contains(&NO_AUTHENTICATION) {
+        stream
+            .write_all(&[SOCKS_VERSION, NO_ACCEPTABLE_METHOD])
+            .await?;
+        anyhow::bail!("client requires authentication");
+    }
+    stream
+        .write_all(&[SOCKS_VERSION, NO_AUTHENTICATION])
+        .await?;
+
+    // Request: version, command, a reserved byte, then the destination
+    let mut request = [0u8; 4];
+    stream.read_exact(&mut request).await?;
+    let host = match request[3] {
+        IPV4 => {
+            let mut octets = [0u8; 4];
+            stream.read_exact(&mut octets).await?;
+            Ipv4Addr::from(octets).to_string()
+        }
+        IPV6 => {
+            let mut octets = [0u8; 16];
+            stream.read_exact(&mut octets).await?;
+            Ipv6Addr::from(octets).to_string()
+        }
+        DOMAIN => {
+            let mut name = vec![0u8; stream.read_u8().await? as usize];
+            stream.read_exact(&mut name).await?;
+            String::from_utf8(name)?
+        }
+        other => {
+            reply(&mut stream, ADDRESS_NOT_SUPPORTED).await?;
+            anyhow::bail!("unknown address type {}", other);
+        }
+    };
+    let port = stream.read_u16().await?;
+    if request[1] != CONNECT {
+        reply(&mut stream, COMMAND_NOT_SUPPORTED).await?;
+        anyhow::bail!("unsupported command {}", request[1]);
+    }
+
+    let target = match proxy::own_listener(ctx, &host, port).await {
+        Ok(Some(own)) => {
+            reply(&mut stream, SUCCEEDED).await?;
+            let client = Client {
+                addr: peer,
+                https: false,
+                forward: true,
+            };
+            proxy::serve_own(stream, own, ctx.clone(), client).await;
+            return Ok(());
+        }
+        Ok(None) => {
+            let config = ctx.config.borrow().clone();
+            let local = ctx.network.borrow().clone();
+            outbound::connect_remote(&config, Feature::Passthrough, &host, port, &local).await
+        }
+        Err(e) => {
+            reply(&mut stream, GENERAL_FAILURE).await?;
+            return Err(e);
+        }
+    };
+    let target = match target {
+        Ok(target) => target,
+        Err(e) if e.is::<outbound::LocalDestination>() => {
+            reply(&mut stream, NOT_ALLOWED).await?;
+            return Err(e);
+        }
+        Err(e) => {
+            let refused = e
+                .downcast_ref::<outbound::DirectFailure>()
+                .map(|failure| &failure.error)
+                .or_else(|| e.downcast_ref::<std::io::Error>())
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::ConnectionRefused);
+            let code = if refused {
+                CONNECTION_REFUSED
+            } else {
+                HOST_UNREACHABLE
+            };
+            reply(&mut stream, code).await?;
+            return Err(e);
+        }
+    };
+    reply(&mut stream, SUCCEEDED).await?;
+    proxy::tunnel(stream, target, proxy::tunnel_idle_timeout(ctx)).await?;
+    Ok(())
+}
+
+/// Answer a request, with an unspecified bound address
+async fn reply(stream: &mut TcpStream, code: u8) -> std::io::Result<()> {
+    stream
+        .write_all(&[SOCKS_VERSION, code, 0, IPV4, 0, 0, 0, 0, 0, 0])
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proj_common::Config;
+
+    /// A forward proxy serving one client, and a listener on loopback that
+    /// no client of it should reach
+    async fn proxy_and_target() -> (TcpStream, TcpListener) {
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(proxy.local_addr().unwrap());
+        let (client, accepted) = tokio::join!(client, proxy.accept());
+        let (stream, peer) = accepted.unwrap();
+        let ctx = ProxyContext::for_tests(Config::default());
+        tokio::spawn(serve(stream, peer, ctx));
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        (client.unwrap(), target)
+    }
+
+    #[tokio::test]
+    async fn test_socks_refuses_loopback() {
+        let (mut client, target) = proxy_and_target().await;
+        let port = target.local_addr().unwrap().port();
+        client
+            .write_all(&[SOCKS_VERSION, 1, NO_AUTHENTICATION])
+            .await
+            .unwrap();
+        let mut choice = [0u8; 2];
+        client.read_exact(&mut choice).await.unwrap();
+        let mut request = vec![SOCKS_VERSION, CONNECT, 0, IPV4, 127, 0, 0, 1];
+        request.extend(port.to_be_bytes());
+        client.write_all(&request).await.unwrap();
+        let mut answer = [0u8; 10];
+        client.read_exact(&mut answer).await.unwrap();
+        assert_eq!(answer[1], NOT_ALLOWED);
+    }
+
+    #[tokio::test]
+    async fn test_http_connect_refuses_loopback() {
+        let (mut client, target) = proxy_and_target().await;
+        let target = target.local_addr().unwrap();
+        for host in [target.to_string(), format!("0.0.0.0:{}", target.port())] {
+            client
+                .write_all(format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", host).as_bytes())
+                .await
+                .unwrap();
+            let mut head = [0u8; 12];
+            client.read_exact(&mut head).await.unwrap();
+            assert_eq!(&head, b"HTTP/1.1 403", "{}", host);
+            // The rest of the response
+            let mut rest = vec![0u8; 512];
+            let n = client.read(&mut rest).await.unwrap();
+            assert!(String::from_utf8_lossy(&rest[..n]).contains("is this machine"));
+        }
+    }
+
+    #[test]
+    fn test_is_private() {
+        assert!(is_private("127.0.0.1".parse().unwrap()));
+        assert!(is_private("192.168.1.20".parse().unwrap()));
+        assert!(is_private("10.0.0.5".parse().unwrap()));
+        assert!(is_private("fe80::1".parse().unwrap()));
+        assert!(is_private("fd00::7".parse().unwrap()));
+        assert!(is_private("::ffff:192.168.1.20".parse().unwrap()));
+        assert!(!is_private("8.8.8.8".parse().unwrap()));
+        assert!(!is_private("2001:db8::1".parse().unwrap()));
+    }
+}
@@ -0,0 +1,191 @@
+//! GraphQL awareness - per-operation timings for requests hitting GraphQL endpoints
+
+use proj_common::GraphqlOpStats;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Number of recent durations kept per operation for percentile estimates
+const DURATION_WINDOW: usize = 100;
+
+/// Running statistics for one operation
+#[derive(Default)]
+struct OpStats {
+    kind: String,
+    count: u64,
+    errors: u64,
+    total_ms: u64,
+    max_ms: u64,
+    recent_ms: VecDeque<u64>,
+}
+
+/// Operation statistics per project
+#[derive(Clone, Default)]
+pub struct GraphqlStats {
+    ops: Arc<RwLock<HashMap<String, HashMap<String, OpStats>>>>,
+}
+
+impl GraphqlStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a request if it looks like a GraphQL operation
+    pub async fn record(
+        &self,
+        project_name: &str,
+        path: &str,
+        body: Option<&str>,
+        status: Option<u16>,
+        duration_ms: u64,
+    ) {
+        let operations = extract_operations(path, body);
+        if operations.is_empty() {
+            return;
+        }
+
+        let failed = status.is_none_or(|s| s >= 400);
+        let mut ops = self.ops.write().await;
+        let project_ops = ops.entry(project_name.to_string()).or_default();
+        for (kind, name) in operations {
+            let stats = project_ops.entry(name).or_default();
+            stats.kind = kind;
+            stats.count += 1;
+            stats.total_ms += duration_ms;
+            stats.max_ms = stats.max_ms.max(duration_ms);
+            if failed {
+                stats.errors += 1;
+            }
+            if stats.recent_ms.len() >= DURATION_WINDOW {
+                stats.recent_ms.pop_front();
+            }
+            stats.recent_ms.push_back(duration_ms);
+        }
+    }
+
+    /// Drop all statistics for a project
+    pub async fn clear(&self, project_name: &str) {
+        self.ops.write().await.remove(project_name);
+    }
+
+    /// Operation statistics for a project, slowest (by p95) first
+    pub async fn list(&self, project_name: &str) -> Vec<GraphqlOpStats> {
+        let ops = self.ops.read().await;
+        let mut list: Vec<GraphqlOpStats> = ops
+            .get(project_name)
+            .map(|project_ops| {
+                project_ops
+                    .iter()
+                    .map(|(name, stats)| GraphqlOpStats {
+                        name: name.clone(),
+                        kind: stats.kind.clone(),
+                        count: stats.count,
+                        errors: stats.errors,
+                        avg_ms: stats.total_ms / stats.count.max(1),
+                        p95_ms: percentile(&stats.recent_ms, 95),
+                        max_ms: stats.max_ms,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        list.sort_by(|a, b| b.p95_ms.cmp(&a.p95_ms).then(b.count.cmp(&a.count)));
+        list
+    }
+}
+
+/// Nearest-rank percentile of a set of durations
+fn percentile(durations: &VecDeque<u64>, pct: usize) -> u64 {
+    let mut sorted: Vec<u64> = durations.iter().copied().collect();
+    sorted.sort_unstable();
+    match sorted.len() {
+        0 => 0,
+        len => sorted[((len * pct).div_ceil(100)).clamp(1, len) - 1],
+    }
+}
+
+/// Extract (kind, name) for each GraphQL operation in a request
+fn extract_operations(path: &str, body: Option<&str>) -> Vec<(String, String)> {
+    let (path_only, query_string) = path.split_once('?').unwrap_or((path, ""));
+
+    // Batched or single JSON POST bodies
+    if let Some(body) = body.and_then(|b| serde_json::from_str::<Value>(b).ok()) {
+        let requests = match body {
+            Value::Array(items) => items,
+            single => vec![single],
+        };
+        let ops: Vec<_> = requests
+            .iter()
+            .filter_map(|req| {
+                let query = req.get("query")?.as_str()?;
+                let name = req.get("operationName").and_then(Value::as_str);
+                Some(describe_operation(query, name))
+            })
+            .collect();
+        if !ops.is_empty() {
+            return ops;
+        }
+    }
+
+    // GET requests carry the operation in the query string
+    if path_only.ends_with("/graphql") {
+        let params: HashMap<&str, &str> = query_string
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .collect();
+        if let Some(name) = params.get("operationName") {
+            return vec![("query".to_string(), name.to_string())];
+        }
+        if params.contains_key("query") {
+            return vec![("query".to_string(), "(anonymous)".to_string())];
+        }
+    }
+
+    Vec::new()
+}
+
+/// Determine the operation kind and name from a query document
+fn describe_operation(query: &str, operation_name: Option<&str>) -> (String, String) {
+    let mut tokens = query
+        .split(|c: char| c.is_whitespace() || c == '(' || c == '{')
+        .filter(|t| !t.is_empty());
+    let (kind, parsed_name) = match tokens.next() {
+        Some(kind @ ("query" | "mutation" | "subscription")) => (kind, tokens.next()),
+        _ => ("query", None),
+    };
+
+    let name = operation_name
+        .or(parsed_name)
+        .unwrap_or("(anonymous)")
+        .to_string();
+    (kind.to_string(), name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_operations() {
+        let body = r#"{"query":"mutation AddTodo($t: String) { add(t: $t) { id } }"}"#;
+        assert_eq!(
+            extract_operations("/graphql", Some(body)),
+            vec![("mutation".to_string(), "AddTodo".to_string())]
+        );
+
+        let batch = r#"[{"query":"{ me { id } }"},{"query":"query Q { a }","operationName":"Q"}]"#;
+        assert_eq!(
+            extract_operations("/api", Some(batch)),
+            vec![
+                ("query".to_string(), "(anonymous)".to_string()),
+                ("query".to_string(), "Q".to_string())
+            ]
+        );
+
+        assert_eq!(
+            extract_operations("/graphql?operationName=Feed&query=x", None),
+            vec![("query".to_string(), "Feed".to_string())]
+        );
+        assert!(extract_operations("/api/users", Some(r#"{"name":"x"}"#)).is_empty());
+    }
+}
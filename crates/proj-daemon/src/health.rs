@@ -0,0 +1,114 @@
+//! Service health checks - gate routing on a passing HTTP or TCP probe, or
+//! on the project's smoke checks
+
+use proj_common::{HealthCheck, SmokeCheck, SmokeResult};
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::task::AbortHandle;
+use tokio::time::Duration;
+use uuid::Uuid;
+
+use crate::process::ProcessEvent;
+use crate::smoke;
+
+/// Probe `port` on an interval, reporting transitions as [`ProcessEvent::Health`].
+/// `smoke` holds the project's smoke checks, run when the check asks for them.
+///
+/// The first passing probe reports healthy; after that, only
+/// [`HealthCheck::FAILURE_THRESHOLD`] consecutive failures report unhealthy, and
+/// the next pass reports healthy again.
+pub fn monitor(
+    process_id: Uuid,
+    port: u16,
+    check: HealthCheck,
+    smoke: Vec<SmokeCheck>,
+    tx: mpsc::Sender<ProcessEvent>,
+) -> AbortHandle {
+    tokio::spawn(async move {
+        let interval = Duration::from_millis(check.interval_ms.max(100));
+        let mut healthy = false;
+        let mut failures = 0;
+        loop {
+            let passed = probe(port, &check, &smoke).await;
+            failures = if passed { 0 } else { failures + 1 };
+
+            let report = if passed && !healthy {
+                Some(true)
+            } else if healthy && failures >= HealthCheck::FAILURE_THRESHOLD {
+                Some(false)
+            } else {
+                None
+            };
+            if let Some(now_healthy) = report {
+                healthy = now_healthy;
+                let event = ProcessEvent::Health {
+                    process_id,
+                    healthy,
+                };
+                if tx.send(event).await.is_err() {
+                    return;
+                }
+            }
+
+            tokio::time::sleep(interval).await;
+        }
+    })
+    .abort_handle()
+}
+
+/// Run one probe against `127.0.0.1:port`
+async fn probe(port: u16, check: &HealthCheck, smoke: &[SmokeCheck]) -> bool {
+    let timeout = Duration::from_millis(check.timeout_ms);
+    if check.smoke && !smoke.is_empty() {
+        let addr = SocketAddr::from(([127, 0, 0, 1], port));
+        let host = format!("localhost:{}", port);
+        let results = smoke::run(addr, &host, smoke, timeout).await;
+        return results.iter().all(SmokeResult::passed);
+    }
+    let attempt = async {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.ok()?;
+        let Some(path) = &check.path else {
+            return Some(true);
+        };
+
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: localhost:{}\r\nUser-Agent: proj-health\r\nConnection: close\r\n\r\n",
+            path, port
+        );
+        stream.write_all(request.as_bytes()).await.ok()?;
+
+        // The status line fits comfortably in the first read
+        let mut buf = [0u8; 256];
+        let n = stream.read(&mut buf).await.ok()?;
+        let status = parse_status(&String::from_utf8_lossy(&buf[..n]))?;
+        Some((200..400).contains(&status))
+    };
+
+    matches!(tokio::time::timeout(timeout, attempt).await, Ok(Some(true)))
+}
+
+/// Status code from the start of an HTTP response
+fn parse_status(response: &str) -> Option<u16> {
+    let status_line = response.lines().next()?;
+    let mut parts = status_line.split_whitespace();
+    parts.next().filter(|v| v.starts_with("HTTP/"))?;
+    parts.next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status() {
+        assert_eq!(
+            parse_status("HTTP/1.1 204 No Content\r\nServer: x\r\n"),
+            Some(204)
+        );
+        assert_eq!(parse_status("HTTP/1.0 503 Service Unavailable"), Some(503));
+        assert_eq!(parse_status("SSH-2.0-OpenSSH_9.6"), None);
+        assert_eq!(parse_status(""), None);
+    }
+}
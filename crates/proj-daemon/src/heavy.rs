@@ -0,0 +1,136 @@
+//! Global cap on concurrently running `run --heavy` commands, across projects
+
+use std::collections::VecDeque;
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+/// Slots for heavy runs, shared by every project
+#[derive(Default)]
+pub struct HeavySlots {
+    /// Slots held by running processes or reserved for runs about to start
+    taken: usize,
+    holders: Vec<Uuid>,
+    waiters: VecDeque<Waiter>,
+}
+
+struct Waiter {
+    id: Uuid,
+    wake: oneshot::Sender<()>,
+}
+
+impl HeavySlots {
+    /// Take a slot if one is free and nobody is waiting for it
+    pub fn try_acquire(&mut self, limit: usize) -> bool {
+        if self.taken >= limit.max(1) || !self.waiters.is_empty() {
+            return false;
+        }
+        self.taken += 1;
+        true
+    }
+
+    /// Queue for a slot; the receiver fires once one is handed over
+    pub fn enqueue(&mut self) -> (Uuid, oneshot::Receiver<()>) {
+        let (wake, woken) = oneshot::channel();
+        let id = Uuid::new_v4();
+        self.waiters.push_back(Waiter { id, wake });
+        (id, woken)
+    }
+
+    /// 1-based place of a waiter in the queue
+    pub fn position(&self, waiter_id: Uuid) -> Option<usize> {
+        self.waiters
+            .iter()
+            .position(|w| w.id == waiter_id)
+            .map(|i| i + 1)
+    }
+
+    /// Leave the queue. A waiter a slot was already handed to gives it back.
+    pub fn cancel(&mut self, waiter_id: Uuid, limit: usize) {
+        let queued = self.waiters.len();
+        self.waiters.retain(|w| w.id != waiter_id);
+        if self.waiters.len() == queued {
+            self.release(limit);
+        }
+    }
+
+    /// Record the process an acquired slot is held by
+    pub fn hold(&mut self, process_id: Uuid) {
+        self.holders.push(process_id);
+    }
+
+    /// Move the slot `process_id` holds over to `next`
+    pub fn pass_on(&mut self, process_id: Uuid, next: Uuid) {
+        if let Some(holder) = self.holders.iter_mut().find(|h| **h == process_id) {
+            *holder = next;
+        }
+    }
+
+    /// Free the slot `process_id` holds, if any
+    pub fn release_process(&mut self, process_id: Uuid, limit: usize) {
+        let held = self.holders.len();
+        self.holders.retain(|h| *h != process_id);
+        if self.holders.len() != held {
+            self.release(limit);
+        }
+    }
+
+    /// Free a slot and hand free slots to waiters
+    pub fn release(&mut self, limit: usize) {
+        self.taken = self.taken.saturating_sub(1);
+        self.wake(limit);
+    }
+
+    /// Hand free slots to the next waiters still around, e.g. after the
+    /// limit was raised
+    pub fn wake(&mut self, limit: usize) {
+        while self.taken < limit.max(1) {
+            let Some(waiter) = self.waiters.pop_front() else {
+                return;
+            };
+            if waiter.wake.send(()).is_ok() {
+                self.taken += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slot_handoff() {
+        let mut slots = HeavySlots::default();
+        assert!(slots.try_acquire(2));
+        assert!(slots.try_acquire(2));
+        assert!(!slots.try_acquire(2));
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        slots.hold(first);
+        slots.hold(second);
+
+        let (gone, gone_rx) = slots.enqueue();
+        let (waiting, mut waiting_rx) = slots.enqueue();
+        assert_eq!(slots.position(waiting), Some(2));
+        drop(gone_rx);
+        slots.cancel(gone, 2);
+        assert_eq!(slots.position(waiting), Some(1));
+
+        // Processes that never took a slot free nothing
+        slots.release_process(Uuid::new_v4(), 2);
+        assert!(waiting_rx.try_recv().is_err());
+
+        // A chained run's next stage keeps the slot
+        let next_stage = Uuid::new_v4();
+        slots.pass_on(first, next_stage);
+        slots.release_process(first, 2);
+        assert!(waiting_rx.try_recv().is_err());
+
+        slots.release_process(next_stage, 2);
+        assert!(waiting_rx.try_recv().is_ok());
+        assert_eq!(slots.position(waiting), None);
+        assert!(!slots.try_acquire(2));
+
+        // Raising the limit frees a slot right away
+        assert!(slots.try_acquire(3));
+    }
+}
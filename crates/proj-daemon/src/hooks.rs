@@ -0,0 +1,150 @@
+//! Project lifecycle hooks: shell commands run before a start, after a stop
+//! and when a process crashes
+
+use anyhow::{Context, Result};
+use proj_common::{ProcessInfo, Project};
+use std::path::Path;
+use std::process::Stdio;
+use tokio::process::Command;
+use tokio::time::Duration;
+
+use crate::env;
+
+/// Hooks taking longer than this are killed and count as failed
+const HOOK_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Stderr lines of a failed hook quoted in its error
+const ERROR_LINES: usize = 5;
+
+/// Run a project's `pre_run` hook, if it has one. An error means the start
+/// must not go ahead.
+pub async fn pre_run(project: &Project) -> Result<()> {
+    match &project.hooks.pre_run {
+        Some(command) => run(project, "pre_run", command, &[]).await,
+        None => Ok(()),
+    }
+}
+
+/// Start a project's `post_stop` hook in the background
+pub fn post_stop(project: &Project) {
+    if let Some(command) = &project.hooks.post_stop {
+        spawn(project.clone(), "post_stop", command.clone(), Vec::new());
+    }
+}
+
+/// Start a project's `on_crash` hook in the background, telling it which
+/// process failed and where its crash bundle is
+pub fn on_crash(project: &Project, process: &ProcessInfo, bundle: Option<&Path>) {
+    if let Some(command) = &project.hooks.on_crash {
+        let exit_code = process
+            .last_exit_code
+            .map_or(String::new(), |c| c.to_string());
+        let vars = vec![
+            ("PROJECT_EXIT_CODE", exit_code),
+            ("PROJECT_COMMAND", process.command.clone()),
+            (
+                "PROJECT_SERVICE",
+                process.service.clone().unwrap_or_default(),
+            ),
+            (
+                "PROJECT_CRASH_DIR",
+                bundle.map_or(String::new(), |dir| dir.display().to_string()),
+            ),
+        ];
+        spawn(project.clone(), "on_crash", command.clone(), vars);
+    }
+}
+
+fn spawn(project: Project, hook: &'static str, command: String, vars: Vec<(&'static str, String)>) {
+    tokio::spawn(async move {
+        if let Err(e) = run(&project, hook, &command, &vars).await {
+            tracing::warn!("{}: {:#}", project.name, e);
+        }
+    });
+}
+
+/// Run a hook through the shell in the project's directory and environment
+async fn run(
+    project: &Project,
+    hook: &str,
+    command: &str,
+    vars: &[(&'static str, String)],
+) -> Result<()> {
+    tracing::info!("Running {} hook of {}: {}", hook, project.name, command);
+
+    let mut cmd = shell(command);
+    cmd.current_dir(&project.root_dir)
+        .envs(
+            env::resolve(&project.name, &project.root_dir, &project.env_profiles)
+                .into_iter()
+                .map(|var| (var.key, var.value)),
+        )
+        .env("PROJECT_ID", &project.name)
+        .env("PROJECT_HOOK", hook)
+        .envs(vars.iter().map(|(k, v)| (*k, v)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let child = cmd
+        .spawn()
+        .with_context(|| format!("Failed to start {} hook", hook))?;
+    let output = tokio::time::timeout(HOOK_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| {
+            anyhow::anyhow!("{} hook timed out after {}s", hook, HOOK_TIMEOUT.as_secs())
+        })??;
+
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = stderr.lines().collect();
+    let tail = lines[lines.len().saturating_sub(ERROR_LINES)..].join("\n");
+    let status = match output.status.code() {
+        Some(code) => format!("exit code {}", code),
+        None => "a signal".to_string(),
+    };
+    if tail.is_empty() {
+        anyhow::bail!("{} hook `{}` failed with {}", hook, command, status)
+    }
+    anyhow::bail!(
+        "{} hook `{}` failed with {}:\n{}",
+        hook,
+        command,
+        status,
+        tail
+    )
+}
+
+/// Run `command` with the platform's shell
+#[cfg(unix)]
+pub fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(windows)]
+pub fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pre_run_failure() {
+        let mut project = Project::new("hooks-test".into(), std::env::temp_dir());
+        assert!(pre_run(&project).await.is_ok());
+
+        project.hooks.pre_run = Some("echo starting; echo no db >&2; exit 3".into());
+        let message = pre_run(&project).await.unwrap_err().to_string();
+        assert!(message.contains("exit code 3"), "{}", message);
+        assert!(message.ends_with("no db"), "{}", message);
+    }
+}
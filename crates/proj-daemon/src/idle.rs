@@ -0,0 +1,262 @@
+//! Idle shutdown and lazy start, both driven by proxy traffic
+//!
+//! The proxy records when each project last got a request. A project with
+//! `proxy.idle_stop_mins` has its processes stopped once that long passes
+//! without one (or since they started, if none came); a project with
+//! `proxy.lazy_start` is started by the first request that arrives while
+//! nothing runs, which waits until the project is routed, i.e. past its
+//! health check if it has one.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use proj_common::{
+    IpcRequest, IpcResponse, OnConflict, ProcessInfo, Project, ResourceLimits, RestartPolicy,
+    DEFAULT_MAX_RESTARTS,
+};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tokio::time::{Duration, Instant};
+
+use crate::ipc::{self, DaemonState};
+use crate::process;
+
+/// How often idle projects are looked for
+const TICK: Duration = Duration::from_secs(30);
+
+/// How long a request waits for a lazily started project to be routed
+const START_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often a waiting request checks whether the project is routed yet
+const POLL: Duration = Duration::from_millis(100);
+
+/// Periodically stop projects whose traffic has gone quiet
+pub async fn run(state: Arc<DaemonState>) {
+    let mut interval = tokio::time::interval(TICK);
+    loop {
+        interval.tick().await;
+        reconcile(&state).await;
+    }
+}
+
+/// Stop the processes of projects idle for longer than they allow
+async fn reconcile(state: &DaemonState) {
+    let now = Utc::now();
+    let grace = state.stop_grace();
+    let limited: Vec<(String, u64)> = state
+        .registry
+        .read()
+        .await
+        .list()
+        .into_iter()
+        // Requests to a project in direct mode don't pass the proxy to count
+        .filter(|p| !p.is_direct())
+        .filter_map(|p| Some((p.name.clone(), p.proxy.idle_stop_mins?)))
+        .collect();
+
+    for (name, mins) in limited {
+        let last_request = state.activity.read().await.get(&name).copied();
+        let Some(idle_since) = idle_since(&state.processes.list_for_project(&name), last_request)
+        else {
+            continue;
+        };
+        if now - idle_since < chrono::Duration::minutes(mins as i64) {
+            continue;
+        }
+
+        let project_name = name.clone();
+        let Ok(pids) = state
+            .processes
+            .call(move |manager| manager.stop_all(Some(&project_name)))
+            .await
+        else {
+            return;
+        };
+        if !pids.is_empty() {
+            tracing::info!(project = %name, idle_mins = mins, "Stopping idle project");
+        }
+        for pid in pids {
+            tokio::spawn(process::terminate(pid, grace));
+        }
+    }
+}
+
+/// Since when a project has been idle: its last request, or the newest start
+/// of a running process if that came later. `None` if nothing runs.
+fn idle_since(
+    processes: &[ProcessInfo],
+    last_request: Option<DateTime<Utc>>,
+) -> Option<DateTime<Utc>> {
+    let started = processes
+        .iter()
+        .filter(|p| p.status.is_alive())
+        .map(|p| p.started_at)
+        .max()?;
+    Some(last_request.map_or(started, |last| last.max(started)))
+}
+
+/// Starts lazily started projects for the proxy, and finishes setting up
+/// those it knows only from the store's index
+#[derive(Clone)]
+pub struct LazyStarter {
+    state: Arc<DaemonState>,
+    /// Projects being launched, so simultaneous requests start them once
+    launching: Arc<Mutex<HashSet<String>>>,
+}
+
+impl LazyStarter {
+    pub fn new(state: Arc<DaemonState>) -> Self {
+        Self {
+            state,
+            launching: Arc::default(),
+        }
+    }
+
+    /// Apply the parts of a project's proxy setup the index doesn't hold,
+    /// before its first request is served (see [`DaemonState::settle`])
+    pub async fn settle(&self, project_name: &str) {
+        self.state.settle(project_name).await;
+    }
+
+    /// The port a request for `path` goes to once the project is up, starting
+    /// it if nothing runs. `None` if the project doesn't start lazily.
+    pub async fn route(&self, project_name: &str, path: &str) -> Option<Result<u16>> {
+        let project = self
+            .state
+            .registry
+            .read()
+            .await
+            .get(project_name)
+            .cloned()?;
+        if !project.proxy.lazy_start {
+            return None;
+        }
+        Some(self.start_and_wait(&project, path).await)
+    }
+
+    async fn start_and_wait(&self, project: &Project, path: &str) -> Result<u16> {
+        let launch = !self.is_running(&project.name)
+            && self.launching.lock().unwrap().insert(project.name.clone());
+        if launch {
+            tracing::info!(project = %project.name, "Starting on demand");
+            let started = start(&self.state, project).await;
+            self.launching.lock().unwrap().remove(&project.name);
+            started?;
+        }
+
+        let deadline = Instant::now() + START_TIMEOUT;
+        loop {
+            let port = self
+                .state
+                .routing_table
+                .read()
+                .await
+                .get(&project.name)
+                .and_then(|routes| routes.target(path));
+            if let Some(port) = port {
+                return Ok(port);
+            }
+            let launching = self.launching.lock().unwrap().contains(&project.name);
+            if !launching && !self.is_running(&project.name) {
+                anyhow::bail!("{} exited before it was ready", project.name);
+            }
+            if Instant::now() >= deadline {
+                anyhow::bail!(
+                    "{} wasn't ready within {}s",
+                    project.name,
+                    START_TIMEOUT.as_secs()
+                );
+            }
+            tokio::time::sleep(POLL).await;
+        }
+    }
+
+    fn is_running(&self, project_name: &str) -> bool {
+        self.state
+            .processes
+            .list_for_project(project_name)
+            .iter()
+            .any(|p| p.status.is_alive())
+    }
+}
+
+/// Start what `proj up` would: every service, or without services the
+/// command the project was last run with
+async fn start(state: &Arc<DaemonState>, project: &Project) -> Result<()> {
+    let requests: Vec<IpcRequest> = if project.services.is_empty() {
+        let Some((command, args)) = project
+            .run_command
+            .as_deref()
+            .and_then(|command| command.split_first())
+        else {
+            anyhow::bail!("{} has no services or run command to start", project.name);
+        };
+        vec![IpcRequest::RunCommand {
+            project_name: project.name.clone(),
+            command: command.clone(),
+            args: args.to_vec(),
+            restart: RestartPolicy::default(),
+            max_restarts: DEFAULT_MAX_RESTARTS,
+            watch: None,
+            exclusive: false,
+            no_wait: false,
+            heavy: false,
+            output: None,
+            sandbox: None,
+            primary: false,
+            then: None,
+            otherwise: None,
+            limits: ResourceLimits::default(),
+            profile: None,
+            tty: None,
+            cwd: None,
+            on_conflict: OnConflict::Attach,
+        }]
+    } else {
+        project
+            .services
+            .keys()
+            .map(|name| IpcRequest::StartService {
+                project_name: project.name.clone(),
+                name: name.clone(),
+                debug: false,
+            })
+            .collect()
+    };
+
+    for request in requests {
+        if let IpcResponse::Error(e) = ipc::handle_request(request, state.clone()).await {
+            return Err(e.into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proj_common::ProcessStatus;
+
+    #[test]
+    fn test_idle_since() {
+        let started = Utc::now() - chrono::Duration::minutes(30);
+        let mut process: ProcessInfo = serde_json::from_value(serde_json::json!({
+            "id": uuid::Uuid::new_v4(),
+            "project_name": "app",
+            "pid": 1,
+            "command": "npm",
+            "started_at": started,
+            "status": "running",
+        }))
+        .unwrap();
+        assert_eq!(idle_since(&[process.clone()], None), Some(started));
+
+        let request = started + chrono::Duration::minutes(10);
+        assert_eq!(idle_since(&[process.clone()], Some(request)), Some(request));
+        // A restart after the last request counts as activity
+        let before = started - chrono::Duration::minutes(10);
+        assert_eq!(idle_since(&[process.clone()], Some(before)), Some(started));
+
+        process.status = ProcessStatus::Stopped;
+        assert_eq!(idle_since(&[process], Some(request)), None);
+    }
+}
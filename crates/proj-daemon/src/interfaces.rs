@@ -0,0 +1,205 @@
+//! Interface watch - the machine's network addresses, checked every few
+//! seconds so what the daemon shares on the LAN follows Wi-Fi reconnects and
+//! VPNs going up or down.
+//!
+//! Listeners on a wildcard address keep working through such changes, but
+//! the URLs other devices use change with the addresses. A listener on one
+//! specific address stops getting connections when it goes away, and can't
+//! be bound until it's there, as at login before Wi-Fi is up. Changes are
+//! emitted as `network_changed` events with the new LAN URLs, and the proxy,
+//! forward proxy and remote listeners rebind as [`Watch`] tells them to.
+
+use proj_common::{Config, EventKind};
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::MissedTickBehavior;
+
+use crate::events::EventBus;
+use crate::panics;
+
+/// How often the addresses are checked
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The machine's addresses other than loopback ones, sorted
+pub type Addresses = watch::Receiver<Vec<IpAddr>>;
+
+/// Start checking the addresses, emitting changes on `events` along with the
+/// LAN URLs `config` gives for them
+pub fn spawn(events: EventBus, config: watch::Receiver<Config>) -> Addresses {
+    let (tx, rx) = watch::channel(current().unwrap_or_default());
+    panics::spawn("interface watch", async move {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        while !tx.is_closed() {
+            interval.tick().await;
+            // Kept as they were when they can't be read
+            let Ok(Some(next)) = tokio::task::spawn_blocking(current).await else {
+                continue;
+            };
+            let (added, removed) = {
+                let previous = tx.borrow();
+                if *previous == next {
+                    continue;
+                }
+                let added: Vec<IpAddr> = next
+                    .iter()
+                    .filter(|ip| !previous.contains(ip))
+                    .copied()
+                    .collect();
+                let removed: Vec<IpAddr> = previous
+                    .iter()
+                    .filter(|ip| !next.contains(ip))
+                    .copied()
+                    .collect();
+                (added, removed)
+            };
+            let lan_urls = config.borrow().lan_urls(&next);
+            tracing::info!(
+                "Network addresses changed: added {:?}, removed {:?}",
+                added,
+                removed
+            );
+            tx.send_replace(next);
+            events.emit(
+                "",
+                EventKind::NetworkChanged {
+                    added,
+                    removed,
+                    lan_urls,
+                },
+            );
+        }
+    });
+    rx
+}
+
+/// A listener's view of the addresses, telling it when to bind again
+pub struct Watch {
+    addresses: Addresses,
+    seen: Vec<IpAddr>,
+}
+
+impl Watch {
+    pub fn new(mut addresses: Addresses) -> Self {
+        let seen = addresses.borrow_and_update().clone();
+        Self { addresses, seen }
+    }
+
+    /// Wait until a listener on `ip` should be bound again: one that isn't
+    /// bound after any change, one that is when `ip` came or went. Never
+    /// returns without an address, or once the addresses stop being checked.
+    pub async fn rebind_needed(&mut self, ip: Option<IpAddr>, bound: bool) {
+        let Some(ip) = ip else {
+            return std::future::pending().await;
+        };
+        loop {
+            if self.addresses.changed().await.is_err() {
+                return std::future::pending().await;
+            }
+            let now = self.addresses.borrow_and_update().clone();
+            let before = std::mem::replace(&mut self.seen, now);
+            if !bound || needs_rebind(ip, &before, &self.seen) {
+                return;
+            }
+        }
+    }
+}
+
+/// Whether a listener bound to `ip` has to be bound again now that the
+/// addresses went from `before` to `after`: only one on a specific address
+/// that came or went does
+fn needs_rebind(ip: IpAddr, before: &[IpAddr], after: &[IpAddr]) -> bool {
+    !ip.is_unspecified() && !ip.is_loopback() && before.contains(&ip) != after.contains(&ip)
+}
+
+/// Addresses of the interfaces that are up, or `None` if they can't be read
+#[cfg(unix)]
+fn current() -> Option<Vec<IpAddr>> {
+    use nix::net::if_::InterfaceFlags;
+    use std::net::{SocketAddrV4, SocketAddrV6};
+
+    let interfaces = match nix::ifaddrs::getifaddrs() {
+        Ok(interfaces) => interfaces,
+        Err(e) => {
+            tracing::debug!("Failed to list network interfaces: {}", e);
+            return None;
+        }
+    };
+    let mut addrs: Vec<IpAddr> = interfaces
+        .filter(|interface| interface.flags.contains(InterfaceFlags::IFF_UP))
+        .filter_map(|interface| {
+            let address = interface.address?;
+            if let Some(v4) = address.as_sockaddr_in() {
+                Some(IpAddr::V4(*SocketAddrV4::from(*v4).ip()))
+            } else {
+                address
+                    .as_sockaddr_in6()
+                    .map(|v6| IpAddr::V6(*SocketAddrV6::from(*v6).ip()))
+            }
+        })
+        .filter(|ip| !ip.is_loopback())
+        .collect();
+    addrs.sort();
+    addrs.dedup();
+    Some(addrs)
+}
+
+/// The address traffic leaves by, which stands in for all of them here;
+/// connecting a UDP socket sends nothing
+#[cfg(not(unix))]
+fn current() -> Option<Vec<IpAddr>> {
+    let socket = std::net::UdpSocket::bind(("0.0.0.0", 0)).ok()?;
+    let local = socket
+        .connect(("192.0.2.1", 9))
+        .and_then(|()| socket.local_addr())
+        .ok();
+    Some(
+        local
+            .map(|addr| addr.ip())
+            .filter(|ip| !ip.is_loopback() && !ip.is_unspecified())
+            .into_iter()
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_needs_rebind() {
+        let wifi: IpAddr = "192.168.1.5".parse().unwrap();
+        let vpn: IpAddr = "10.8.0.2".parse().unwrap();
+        let any: IpAddr = "0.0.0.0".parse().unwrap();
+        let loopback: IpAddr = "127.0.0.1".parse().unwrap();
+
+        assert!(needs_rebind(wifi, &[wifi], &[]));
+        assert!(needs_rebind(wifi, &[], &[wifi, vpn]));
+        assert!(!needs_rebind(wifi, &[wifi], &[wifi, vpn]));
+        assert!(!needs_rebind(any, &[wifi], &[vpn]));
+        assert!(!needs_rebind(loopback, &[wifi], &[]));
+    }
+
+    #[tokio::test]
+    async fn test_watch() {
+        let wifi: IpAddr = "192.168.1.5".parse().unwrap();
+        let vpn: IpAddr = "10.8.0.2".parse().unwrap();
+        let (tx, rx) = watch::channel(vec![wifi]);
+        let mut watch = Watch::new(rx);
+
+        // A VPN coming up doesn't concern a listener on the Wi-Fi address...
+        tx.send_replace(vec![vpn, wifi]);
+        let waited = tokio::time::timeout(
+            Duration::from_millis(50),
+            watch.rebind_needed(Some(wifi), true),
+        );
+        assert!(waited.await.is_err());
+        // ...but losing that address does
+        tx.send_replace(vec![vpn]);
+        watch.rebind_needed(Some(wifi), true).await;
+        // and one that failed to bind retries on any change
+        tx.send_replace(vec![]);
+        watch.rebind_needed(Some(wifi), false).await;
+    }
+}
@@ -1,60 +1,500 @@
-//! Unix socket IPC server for CLI communication
+//! IPC server for CLI communication (Unix socket, or a named pipe on Windows)
 
 use anyhow::{Context, Result};
-use proj_common::{IpcRequest, IpcResponse, ProcessStatus};
+use proj_common::profile::{self, Profile, ProfileKind};
+use proj_common::store::{IndexEntry, Store};
+use proj_common::transport::{Connection, Ipc, Transport};
+use proj_common::{
+    crash, debug, normalize_route_prefix, Capability, Config, DaemonDiagnostics, EventKind,
+    IpcError, IpcRequest, IpcResponse, JobInfo, JobStage, LogLine, Milestone, OnConflict,
+    PortPolicy, PortUsage, ProcessInfo, ProcessStatus, Project, ProxyOptions, ResourceLimits,
+    RestartPolicy, ServiceInfo, PROTOCOL_VERSION, VERSION,
+};
+use std::collections::{BTreeMap, HashSet};
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::{UnixListener, UnixStream};
-use tokio::sync::Mutex;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
+use tokio::sync::{broadcast, watch, Mutex, RwLock};
+use tokio::time::Duration;
+use uuid::Uuid;
 
-use crate::process::ProcessManager;
-use crate::proxy::RoutingTable;
-use crate::registry::Registry;
+use crate::assets::AssetStore;
+use crate::auth::AuthStore;
+use crate::budgets::BudgetTracker;
+use crate::capture::CaptureStore;
+use crate::drift::DriftStore;
+use crate::env;
+use crate::events::EventBus;
+use crate::graphql::GraphqlStats;
+use crate::heavy::HeavySlots;
+use crate::hooks;
+use crate::interfaces;
+use crate::limits;
+use crate::locks::LockTable;
+use crate::logging;
+use crate::logs::{self, LogStore};
+use crate::maintenance;
+use crate::metrics::ProxyMetrics;
+use crate::notifications::DoNotDisturb;
+use crate::panics;
+use crate::platform;
+use crate::ports;
+use crate::process::{self, ProcessHandle, ProcessManager, SpawnSpec};
+use crate::proxy::{
+    ActivityTable, AliasTable, AppearanceTable, BindErrorCell, ProxyContext, ProxyOptionsTable,
+    RoutingTable, StartingSet,
+};
+use crate::queue::QueueTable;
+use crate::registry::{self, Conflict, Registry};
+use crate::routes;
+use crate::sandbox;
+use crate::scheduler;
+use crate::smoke;
+use crate::stats;
+use crate::tty::Tty;
+use base64::Engine;
+
+/// Wait before bundling a crash, for the output still in its pipes
+const CRASH_OUTPUT_DELAY: Duration = Duration::from_millis(200);
+
+/// How often a queued heavy run checks whether its position changed
+const HEAVY_POSITION_INTERVAL: Duration = Duration::from_secs(1);
 
 /// Shared daemon state
+///
+/// Each part is locked on its own, so requests that touch different parts
+/// don't wait on each other. Processes are reached through
+/// [`ProcessHandle`], whose calls never wait on the other parts. Where a
+/// task needs two locks at once it takes `starting` before the registry
+/// and the registry before the proxy's tables.
 pub struct DaemonState {
-    pub registry: Registry,
-    pub process_manager: ProcessManager,
+    pub registry: RwLock<Registry>,
+    pub processes: ProcessHandle,
     pub routing_table: RoutingTable,
+    /// Projects waiting on a health check before they are routed
+    pub starting: StartingSet,
+    pub options_table: ProxyOptionsTable,
+    pub aliases: AliasTable,
+    pub appearances: AppearanceTable,
+    pub captures: CaptureStore,
+    pub drift: DriftStore,
+    pub budgets: BudgetTracker,
+    pub assets: AssetStore,
+    pub auth: AuthStore,
+    pub graphql: GraphqlStats,
+    pub metrics: ProxyMetrics,
+    /// When each project last got a proxied request
+    pub activity: ActivityTable,
+    /// Why the proxy isn't listening, while it isn't
+    pub proxy_bind_error: BindErrorCell,
+    /// The machine's addresses, for the LAN URLs in `proj status`
+    pub network: interfaces::Addresses,
+    pub logs: Mutex<LogStore>,
+    pub events: EventBus,
+    /// Holds non-critical notifications during foreground sessions
+    pub dnd: Arc<DoNotDisturb>,
+    pub locks: Mutex<LockTable>,
+    pub queues: Mutex<QueueTable>,
+    pub heavy: Mutex<HeavySlots>,
+    /// Live settings from ~/.proj/config.json, watched by the proxy
+    pub config: watch::Sender<Config>,
+    /// Signals the main loop to shut the daemon down, with an optional
+    /// override of the exit policy for managed processes
+    pub shutdown: broadcast::Sender<Option<bool>>,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub store: Store,
+    /// Projects the proxy knows only from the store's index, whose
+    /// `proj.toml` routes, auth secrets, appearance and output passthrough
+    /// are applied when they're first used (see [`DaemonState::settle`])
+    unsettled: std::sync::Mutex<HashSet<String>>,
 }
 
-impl DaemonState {
-    pub async fn new(routing_table: RoutingTable) -> Result<Self> {
-        Ok(Self {
-            registry: Registry::new().await?,
-            process_manager: ProcessManager::new(),
-            routing_table,
-        })
+/// Clients currently streaming a subscription
+static SUBSCRIBERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts a subscriber for as long as it's held
+struct SubscriberGuard;
+
+impl SubscriberGuard {
+    fn new() -> Self {
+        SUBSCRIBERS.fetch_add(1, Ordering::Relaxed);
+        Self
     }
 }
 
-/// Start the IPC server
-pub async fn start_ipc_server(socket_path: &Path, state: Arc<Mutex<DaemonState>>) -> Result<()> {
-    // Remove existing socket file if it exists
-    if socket_path.exists() {
-        tokio::fs::remove_file(socket_path)
+impl Drop for SubscriberGuard {
+    fn drop(&mut self) {
+        SUBSCRIBERS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl DaemonState {
+    /// Daemon state sharing the proxy's tables. The process manager's event
+    /// receiver must have been taken already; the manager moves to its own task.
+    pub async fn new(
+        mut process_manager: ProcessManager,
+        store: Store,
+        proxy: &ProxyContext,
+        config: watch::Sender<Config>,
+    ) -> Result<Self> {
+        if let Err(e) = process_manager.restore().await {
+            tracing::warn!("Failed to restore process records: {}", e);
+        }
+
+        let events = process_manager.events().clone();
+        let registry = Registry::new(events.clone(), store.clone()).await?;
+        let logs = LogStore::new(proj_common::projects_dir()?, &config.borrow());
+        let index = store.project_index()?;
+
+        let state = Self {
+            registry: RwLock::new(registry),
+            processes: ProcessHandle::spawn(process_manager),
+            routing_table: proxy.routing_table.clone(),
+            starting: proxy.starting.clone(),
+            options_table: proxy.options_table.clone(),
+            aliases: proxy.aliases.clone(),
+            appearances: proxy.appearances.clone(),
+            captures: proxy.captures.clone(),
+            drift: proxy.drift.clone(),
+            budgets: proxy.budgets.clone(),
+            assets: proxy.assets.clone(),
+            auth: proxy.auth.clone(),
+            graphql: proxy.graphql.clone(),
+            metrics: proxy.metrics.clone(),
+            activity: proxy.activity.clone(),
+            proxy_bind_error: proxy.bind_error.clone(),
+            network: proxy.network.clone(),
+            logs: Mutex::new(logs),
+            events,
+            dnd: DoNotDisturb::new(),
+            locks: Mutex::default(),
+            queues: Mutex::default(),
+            heavy: Mutex::default(),
+            config,
+            shutdown: broadcast::channel(1).0,
+            started_at: chrono::Utc::now(),
+            store,
+            unsettled: Default::default(),
+        };
+        state.sync_index(index).await;
+        Ok(state)
+    }
+
+    /// Fill the proxy's tables from the store's index, so every project is
+    /// routed without reading any: their options, aliases and the path
+    /// routes set with `proj route`. The rest waits for [`Self::settle`].
+    async fn sync_index(&self, index: Vec<IndexEntry>) {
+        let proxied: Vec<(String, bool)> = index
+            .iter()
+            .map(|entry| (entry.name.clone(), !entry.proxy.overrides.is_empty()))
+            .collect();
+        self.processes
+            .call(move |manager| {
+                for (name, proxied) in &proxied {
+                    manager.set_proxied(name, *proxied);
+                }
+            })
+            .await
+            .unwrap_or_default();
+
+        let mut options_table = self.options_table.write().await;
+        let mut aliases = self.aliases.write().await;
+        let mut table = self.routing_table.write().await;
+        let mut unsettled = self.unsettled.lock().unwrap();
+        for entry in index {
+            for alias in &entry.aliases {
+                aliases.insert(alias.clone(), entry.name.clone());
+            }
+            let paths: BTreeMap<String, u16> = entry
+                .proxy
+                .routes
+                .iter()
+                .filter(|_| !entry.proxy.direct)
+                .filter_map(|(prefix, port)| Some((normalize_route_prefix(prefix).ok()?, *port)))
+                .collect();
+            if !paths.is_empty() {
+                table.entry(entry.name.clone()).or_default().paths = paths;
+            }
+            unsettled.insert(entry.name.clone());
+            options_table.insert(entry.name, entry.proxy);
+        }
+    }
+
+    /// Apply the rest of a project's proxy setup (see [`Self::sync_proxy`])
+    /// the first time the proxy, its processes or their output need it, if
+    /// only the index was read for it so far
+    pub async fn settle(&self, project_name: &str) {
+        if !self.unsettled.lock().unwrap().contains(project_name) {
+            return;
+        }
+        let project = self.registry.read().await.get(project_name).cloned();
+        match project {
+            Some(project) => self.sync_proxy(&project).await,
+            None => {
+                self.unsettled.lock().unwrap().remove(project_name);
+            }
+        }
+    }
+
+    /// Grace period given to processes before they are killed
+    pub fn stop_grace(&self) -> Duration {
+        Duration::from_secs(self.config.borrow().stop_grace_secs)
+    }
+
+    /// Re-read the config file and apply it, returning the names of the
+    /// settings that changed, plus `projects` if any were changed in the store
+    /// by another process
+    pub async fn reload_config(&self) -> Result<Vec<&'static str>> {
+        let projects = self.reload_projects().await?;
+        let config = Config::load()?;
+        let mut changes = config.changes(&self.config.borrow());
+        if projects > 0 {
+            changes.push("projects");
+        }
+        let applied = config.clone();
+        self.processes
+            .call(move |manager| manager.apply_config(&applied))
+            .await?;
+        self.logs.lock().await.apply_config(&config).await;
+        // A raised limit lets queued heavy runs start
+        self.heavy.lock().await.wake(config.max_heavy_jobs);
+        self.config.send_replace(config);
+        Ok(changes)
+    }
+
+    /// Read projects changed in the store outside the registry again, as
+    /// `proj migrate-data` leaves them, returning how many there were
+    async fn reload_projects(&self) -> Result<usize> {
+        let changed = self.registry.write().await.refresh()?;
+        for name in &changed {
+            let project = self.registry.read().await.get(name).cloned();
+            match project {
+                Some(project) => self.sync_proxy(&project).await,
+                None => {
+                    self.options_table.write().await.remove(name);
+                    self.aliases
+                        .write()
+                        .await
+                        .retain(|_, project| project != name);
+                    self.appearances.write().await.remove(name);
+                }
+            }
+        }
+        Ok(changed.len())
+    }
+
+    /// Delete the records and log files of exited processes, of one project
+    /// or all of them, returning how many records and files went and the
+    /// files' size
+    pub async fn prune(&self, project_name: Option<&str>) -> (usize, usize, u64) {
+        let name = project_name.map(str::to_string);
+        let (processes, running) = self
+            .processes
+            .call(move |manager| {
+                let pruned = manager.prune_exited(name.as_deref());
+                let running: HashSet<Uuid> = manager.list().into_iter().map(|p| p.id).collect();
+                (pruned, running)
+            })
+            .await
+            .unwrap_or_default();
+        let (log_files, bytes) = self.logs.lock().await.prune(project_name, &running);
+        tracing::info!(
+            "Pruned {} process records and {} log files ({} bytes)",
+            processes,
+            log_files,
+            bytes
+        );
+        (processes, log_files, bytes)
+    }
+
+    /// Free the exclusive lock and heavy slot held by a run that is done
+    pub async fn release_process(&self, process_id: Uuid) {
+        self.locks.lock().await.release_process(process_id);
+        let limit = self.config.borrow().max_heavy_jobs;
+        self.heavy.lock().await.release_process(process_id, limit);
+    }
+
+    /// Give back the lock and heavy slot claimed for a run that didn't start
+    async fn release_claims(&self, project_name: &str, exclusive: bool, heavy: bool) {
+        if exclusive {
+            self.locks.lock().await.release(project_name);
+        }
+        if heavy {
+            let limit = self.config.borrow().max_heavy_jobs;
+            self.heavy.lock().await.release(limit);
+        }
+    }
+
+    /// Route a project's hostname to `port`, re-reading its path routes in
+    /// case its `proj.toml` changed
+    pub async fn route(&self, project_name: &str, port: u16) {
+        let paths = self
+            .registry
+            .read()
+            .await
+            .get(project_name)
+            .map(routes::declared);
+        let previous = {
+            let mut table = self.routing_table.write().await;
+            let routes = table.entry(project_name.to_string()).or_default();
+            if let Some(paths) = paths {
+                routes.paths = paths;
+            }
+            routes.port.replace(port)
+        };
+        if previous != Some(port) {
+            tracing::info!(
+                "Routing {} -> 127.0.0.1:{}",
+                self.config.borrow().host(project_name),
+                port
+            );
+            self.events
+                .emit(project_name, EventKind::RouteAdded { port });
+        }
+    }
+
+    /// Stop routing a project's hostname to its process; path routes stay
+    pub async fn unroute(&self, project_name: &str) {
+        let previous = {
+            let mut table = self.routing_table.write().await;
+            let Some(routes) = table.get_mut(project_name) else {
+                return;
+            };
+            let previous = routes.port.take();
+            if routes.is_empty() {
+                table.remove(project_name);
+            }
+            previous
+        };
+        if previous.is_some() {
+            tracing::info!("Removed routing for {}", project_name);
+            self.events.emit(project_name, EventKind::RouteRemoved);
+        }
+    }
+
+    /// Point a project's hostname at the process that should serve it now
+    /// ([`ProcessManager::route_target`]), or at nothing, and show the
+    /// "starting up" page while a process waits on its health check
+    pub async fn reroute(&self, project_name: &str) {
+        self.settle(project_name).await;
+        let direct = self
+            .registry
+            .read()
+            .await
+            .get(project_name)
+            .is_some_and(Project::is_direct);
+        // Held throughout, so concurrent reroutes of a project land in order
+        let mut starting = self.starting.write().await;
+        let name = project_name.to_string();
+        let Ok((awaiting_health, target)) = self
+            .processes
+            .call(move |manager| {
+                let target = manager.route_target(&name).and_then(|p| p.port);
+                (manager.awaiting_health(&name), target)
+            })
             .await
-            .context("Failed to remove existing socket")?;
+        else {
+            return;
+        };
+        if awaiting_health {
+            starting.insert(project_name.to_string());
+        } else {
+            starting.remove(project_name);
+        }
+
+        match target {
+            Some(port) => {
+                // A project in direct mode keeps its port, but not a route
+                if direct {
+                    self.unroute(project_name).await;
+                } else {
+                    self.route(project_name, port).await;
+                }
+                drop(starting);
+                let updated = self
+                    .registry
+                    .write()
+                    .await
+                    .update_port(project_name, Some(port))
+                    .await;
+                if let Err(e) = updated {
+                    tracing::error!("Failed to update project port: {}", e);
+                }
+            }
+            None => self.unroute(project_name).await,
+        }
     }
 
-    // Create parent directory if needed
-    if let Some(parent) = socket_path.parent() {
-        tokio::fs::create_dir_all(parent)
+    /// Apply a project's proxy options, path routes and aliases to the proxy,
+    /// have its processes go through it if it overrides hostnames, and echo
+    /// their output if it says so
+    pub async fn sync_proxy(&self, project: &Project) {
+        self.unsettled.lock().unwrap().remove(&project.name);
+        let direct = project.is_direct();
+        let (name, proxied) = (project.name.clone(), !project.proxy.overrides.is_empty());
+        self.processes
+            .call(move |manager| manager.set_proxied(&name, proxied))
+            .await
+            .unwrap_or_default();
+        self.logs
+            .lock()
             .await
-            .context("Failed to create socket directory")?;
+            .set_passthrough(&project.name, project.output_passthrough);
+        // `proxy = false` in proj.toml counts as direct too
+        let options = ProxyOptions {
+            direct,
+            ..project.proxy.clone()
+        };
+        self.options_table
+            .write()
+            .await
+            .insert(project.name.clone(), options);
+        self.auth.load(project).await;
+        self.appearances
+            .write()
+            .await
+            .insert(project.name.clone(), project.appearance.clone());
+
+        {
+            let mut aliases = self.aliases.write().await;
+            aliases.retain(|_, name| *name != project.name);
+            for alias in &project.aliases {
+                aliases.insert(alias.clone(), project.name.clone());
+            }
+        }
+
+        let paths = if direct {
+            BTreeMap::new()
+        } else {
+            routes::declared(project)
+        };
+        let mut table = self.routing_table.write().await;
+        if paths.is_empty() {
+            if let Some(routes) = table.get_mut(&project.name) {
+                routes.paths.clear();
+                if routes.is_empty() {
+                    table.remove(&project.name);
+                }
+            }
+        } else {
+            table.entry(project.name.clone()).or_default().paths = paths;
+        }
     }
+}
 
-    let listener = UnixListener::bind(socket_path).context("Failed to bind Unix socket")?;
+/// Start the IPC server
+pub async fn start_ipc_server(socket_path: &Path, state: Arc<DaemonState>) -> Result<()> {
+    let mut listener = Ipc::bind(socket_path).context("Failed to bind IPC socket")?;
 
     tracing::info!("IPC server listening on {:?}", socket_path);
 
     loop {
-        match listener.accept().await {
-            Ok((stream, _)) => {
+        match Ipc::accept(&mut listener).await {
+            Ok(stream) => {
                 let state = state.clone();
-                tokio::spawn(async move {
-                    if let Err(e) = handle_connection(stream, state).await {
+                panics::spawn("ipc connection", async move {
+                    if let Err(e) = handle_connection(Box::new(stream), state).await {
                         tracing::error!("Connection error: {}", e);
                     }
                 });
@@ -66,224 +506,2605 @@ pub async fn start_ipc_server(socket_path: &Path, state: Arc<Mutex<DaemonState>>
     }
 }
 
+/// The answer to a request that doesn't parse. One whose type this daemon
+/// doesn't know comes from a newer client, which is told so plainly.
+pub(crate) fn invalid_request(line: &str, error: &serde_json::Error) -> IpcResponse {
+    let request_type = serde_json::from_str::<serde_json::Value>(line)
+        .ok()
+        .and_then(|request| request.get("type")?.as_str().map(str::to_string));
+    match request_type {
+        Some(request_type)
+            if error
+                .to_string()
+                .starts_with(&format!("unknown variant `{}`", request_type)) =>
+        {
+            tracing::warn!(
+                "A newer client sent an unsupported '{}' request",
+                request_type
+            );
+            IpcResponse::UnsupportedRequest {
+                request_type,
+                protocol_version: PROTOCOL_VERSION,
+                daemon_version: VERSION.to_string(),
+            }
+        }
+        _ => IpcResponse::Error(IpcError::InvalidRequest {
+            message: format!("Invalid request: {}", error),
+        }),
+    }
+}
+
 /// Handle a single IPC connection
-async fn handle_connection(stream: UnixStream, state: Arc<Mutex<DaemonState>>) -> Result<()> {
-    let (reader, mut writer) = stream.into_split();
+pub async fn handle_connection(stream: Connection, state: Arc<DaemonState>) -> Result<()> {
+    let (reader, mut writer) = tokio::io::split(stream);
     let mut reader = BufReader::new(reader);
     let mut line = String::new();
 
-    // Read one line (one JSON request)
-    reader.read_line(&mut line).await?;
+    // Read one line (one JSON request), after a handshake if the client
+    // opens with one
+    let request = loop {
+        line.clear();
+        reader.read_line(&mut line).await?;
 
-    if line.is_empty() {
-        return Ok(());
-    }
-
-    // Parse request
-    let request: IpcRequest = match serde_json::from_str(&line) {
-        Ok(req) => req,
-        Err(e) => {
-            let response = IpcResponse::Error {
-                message: format!("Invalid request: {}", e),
-            };
-            let json = serde_json::to_string(&response)?;
-            writer.write_all(json.as_bytes()).await?;
-            writer.write_all(b"\n").await?;
+        if line.is_empty() {
             return Ok(());
         }
+
+        // Parse request
+        let request: IpcRequest = match serde_json::from_str(&line) {
+            Ok(req) => req,
+            Err(e) => {
+                let response = invalid_request(&line, &e);
+                let json = serde_json::to_string(&response)?;
+                writer.write_all(json.as_bytes()).await?;
+                writer.write_all(b"\n").await?;
+                return Ok(());
+            }
+        };
+        if !matches!(request, IpcRequest::Hello { .. }) {
+            break request;
+        }
+        let response = handle_request(request, state.clone()).await;
+        write_response(&mut writer, &response).await?;
     };
 
+    match &request {
+        IpcRequest::SubscribeRegistry => {
+            let changes = state.registry.read().await.subscribe();
+            return stream_subscription(reader, writer, "registry changes", vec![], changes, |c| {
+                Some(IpcResponse::RegistryChanged(c))
+            })
+            .await;
+        }
+        IpcRequest::Subscribe { filters } => {
+            if let Some(unknown) = filters
+                .events
+                .iter()
+                .find(|e| !EventKind::NAMES.contains(&e.as_str()))
+            {
+                let response = IpcResponse::Error(IpcError::InvalidRequest {
+                    message: format!(
+                        "Unknown event '{}'. Events: {}",
+                        unknown,
+                        EventKind::NAMES.join(", ")
+                    ),
+                });
+                return write_response(&mut writer, &response).await;
+            }
+            let events = state.events.subscribe();
+            let filters = filters.clone();
+            return stream_subscription(reader, writer, "events", vec![], events, |e| {
+                filters.matches(&e).then_some(IpcResponse::Event(e))
+            })
+            .await;
+        }
+        IpcRequest::SubscribeLogs { project_name } => {
+            if state.registry.read().await.get(project_name).is_none() {
+                let response = IpcResponse::Error(IpcError::ProjectNotFound {
+                    name: project_name.clone(),
+                });
+                return write_response(&mut writer, &response).await;
+            }
+            let (backlog, lines) = state.logs.lock().await.subscribe(project_name);
+            let backlog = backlog.into_iter().map(IpcResponse::Log).collect();
+            let project_name = project_name.clone();
+            return stream_subscription(reader, writer, "logs", backlog, lines, |l: LogLine| {
+                (l.project_name == project_name).then_some(IpcResponse::Log(l))
+            })
+            .await;
+        }
+        IpcRequest::AttachTty { process_id, size } => {
+            let process_id = *process_id;
+            let tty = match state
+                .processes
+                .call(move |manager| manager.tty(process_id))
+                .await
+            {
+                Ok(tty) => tty,
+                Err(e) => return write_response(&mut writer, &IpcResponse::Error(e)).await,
+            };
+            let Some(tty) = tty else {
+                let response = IpcResponse::Error(IpcError::InvalidRequest {
+                    message: format!("Process {} isn't running in a terminal", process_id),
+                });
+                return write_response(&mut writer, &response).await;
+            };
+            if let Err(e) = tty.resize(*size) {
+                tracing::warn!("Failed to resize terminal of {}: {}", process_id, e);
+            }
+            // Notifications are held while someone works in the terminal
+            let _session = state.dnd.session();
+            return stream_tty(reader, writer, &tty).await;
+        }
+        IpcRequest::RunCommand {
+            project_name,
+            command,
+            args,
+            exclusive,
+            no_wait,
+            heavy,
+            ..
+        } => {
+            if *exclusive {
+                let command_line = std::iter::once(command)
+                    .chain(args)
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                if !lock_project(
+                    &mut reader,
+                    &mut writer,
+                    &state,
+                    project_name,
+                    command_line,
+                    *no_wait,
+                )
+                .await?
+                {
+                    return Ok(());
+                }
+            }
+            if *heavy && !claim_heavy_slot(&mut reader, &mut writer, &state, project_name).await? {
+                if *exclusive {
+                    state.locks.lock().await.release(project_name);
+                }
+                return Ok(());
+            }
+        }
+        _ => {}
+    }
+
     // Handle request
-    let response = handle_request(request, state).await;
+    let shutdown = match &request {
+        IpcRequest::Shutdown { stop_processes } => Some(*stop_processes),
+        _ => None,
+    };
+    let response = handle_request(request, state.clone()).await;
 
     // Send response
     let json = serde_json::to_string(&response)?;
     writer.write_all(json.as_bytes()).await?;
     writer.write_all(b"\n").await?;
 
+    // Only shut down once the client has its answer
+    if let Some(stop_processes) = shutdown {
+        writer.flush().await?;
+        let _ = state.shutdown.send(stop_processes);
+    }
+
     Ok(())
 }
 
-/// Handle an IPC request
-async fn handle_request(request: IpcRequest, state: Arc<Mutex<DaemonState>>) -> IpcResponse {
-    match request {
-        IpcRequest::CreateProject { name, root_dir } => {
-            let mut state = state.lock().await;
-            match state.registry.create(name, root_dir).await {
-                Ok(project) => IpcResponse::Project(project),
-                Err(e) => IpcResponse::Error {
-                    message: e.to_string(),
-                },
-            }
-        }
+/// Write a response as one JSON line
+pub(crate) async fn write_response(
+    writer: &mut WriteHalf<Connection>,
+    response: &IpcResponse,
+) -> Result<()> {
+    let json = serde_json::to_string(response)?;
+    writer.write_all(json.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    Ok(())
+}
 
-        IpcRequest::ListProjects => {
-            let state = state.lock().await;
-            let projects: Vec<_> = state.registry.list().into_iter().cloned().collect();
-            IpcResponse::Projects(projects)
+/// Take a project's lock for an exclusive run, queueing unless `no_wait`
+///
+/// Returns false when the run must not go ahead: the client was already
+/// answered or disconnected while queued.
+async fn lock_project(
+    reader: &mut BufReader<ReadHalf<Connection>>,
+    writer: &mut WriteHalf<Connection>,
+    state: &Arc<DaemonState>,
+    project_name: &str,
+    command: String,
+    no_wait: bool,
+) -> Result<bool> {
+    let exists = state.registry.read().await.get(project_name).is_some();
+    let mut locks = state.locks.lock().await;
+    let refusal = if !exists {
+        IpcResponse::Error(IpcError::ProjectNotFound {
+            name: project_name.to_string(),
+        })
+    } else if locks.try_acquire(project_name) {
+        return Ok(true);
+    } else if no_wait {
+        IpcResponse::Locked {
+            message: format!("Another exclusive run holds {}'s lock", project_name),
         }
+    } else {
+        let (waiter_id, mut woken) = locks.enqueue(project_name, command);
+        drop(locks);
 
-        IpcRequest::GetProject { name } => {
-            let state = state.lock().await;
-            match state.registry.get(&name) {
-                Some(project) => IpcResponse::Project(project.clone()),
-                None => IpcResponse::Error {
-                    message: format!("Project '{}' not found", name),
-                },
+        let mut ignored = String::new();
+        loop {
+            tokio::select! {
+                _ = &mut woken => return Ok(true),
+                // Clients send nothing more; EOF means they gave up waiting
+                read = reader.read_line(&mut ignored) => {
+                    if matches!(read, Ok(0) | Err(_)) {
+                        state.locks.lock().await.cancel(project_name, waiter_id);
+                        return Ok(false);
+                    }
+                    ignored.clear();
+                }
             }
         }
+    };
+    drop(locks);
+    write_response(writer, &refusal).await?;
+    Ok(false)
+}
 
-        IpcRequest::RunCommand {
-            project_name,
-            command,
-            args,
-        } => {
-            let mut state = state.lock().await;
+/// Take a heavy-job slot, queueing while all are in use and telling the
+/// client its position whenever it changes
+///
+/// Returns false when the run must not go ahead: the client was already
+/// answered or disconnected while queued.
+async fn claim_heavy_slot(
+    reader: &mut BufReader<ReadHalf<Connection>>,
+    writer: &mut WriteHalf<Connection>,
+    state: &Arc<DaemonState>,
+    project_name: &str,
+) -> Result<bool> {
+    if state.registry.read().await.get(project_name).is_none() {
+        let response = IpcResponse::Error(IpcError::ProjectNotFound {
+            name: project_name.to_string(),
+        });
+        write_response(writer, &response).await?;
+        return Ok(false);
+    }
+    let limit = state.config.borrow().max_heavy_jobs;
+    let mut heavy = state.heavy.lock().await;
+    if heavy.try_acquire(limit) {
+        return Ok(true);
+    }
+    let (waiter_id, mut woken) = heavy.enqueue();
+    drop(heavy);
 
-            // Get project to find working directory
-            let working_dir = match state.registry.get(&project_name) {
-                Some(project) => project.root_dir.clone(),
-                None => {
-                    return IpcResponse::Error {
-                        message: format!("Project '{}' not found", project_name),
-                    };
+    let mut ignored = String::new();
+    let mut reported = None;
+    let mut poll = tokio::time::interval(HEAVY_POSITION_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = &mut woken => return Ok(true),
+            _ = poll.tick() => {
+                let limit = state.config.borrow().max_heavy_jobs;
+                let position = state.heavy.lock().await.position(waiter_id);
+                let Some(position) = position else { continue };
+                if reported == Some((position, limit)) {
+                    continue;
+                }
+                reported = Some((position, limit));
+                let update = IpcResponse::HeavyQueued { position, limit };
+                if let Err(e) = write_response(writer, &update).await {
+                    let limit = state.config.borrow().max_heavy_jobs;
+                    state.heavy.lock().await.cancel(waiter_id, limit);
+                    return Err(e);
                 }
-            };
-
-            // Spawn the process
-            match state
-                .process_manager
-                .spawn(project_name, &command, &args, &working_dir)
-                .await
-            {
-                Ok(process) => IpcResponse::ProcessStarted { process },
-                Err(e) => IpcResponse::Error {
-                    message: e.to_string(),
-                },
             }
-        }
-
-        IpcRequest::StopProcess {
-            project_name: _,
-            process_id,
-        } => {
-            let mut state = state.lock().await;
-            match state.process_manager.stop(process_id) {
-                Ok(()) => IpcResponse::Success {
-                    message: Some(format!("Process {} stopped", process_id)),
-                },
-                Err(e) => IpcResponse::Error {
-                    message: e.to_string(),
-                },
+            // Clients send nothing more; EOF means they gave up waiting
+            read = reader.read_line(&mut ignored) => {
+                if matches!(read, Ok(0) | Err(_)) {
+                    let limit = state.config.borrow().max_heavy_jobs;
+                    state.heavy.lock().await.cancel(waiter_id, limit);
+                    return Ok(false);
+                }
+                ignored.clear();
             }
         }
+    }
+}
 
-        IpcRequest::ListProcesses { project_name } => {
-            let state = state.lock().await;
-            let processes: Vec<_> = match project_name {
-                Some(name) => state
-                    .process_manager
-                    .list_for_project(&name)
-                    .into_iter()
-                    .cloned()
-                    .collect(),
-                None => state.process_manager.list().into_iter().cloned().collect(),
-            };
-            IpcResponse::Processes(processes)
-        }
+/// Acknowledge a subscription, send `backlog`, then push every item that
+/// `to_response` keeps until the client disconnects
+pub(crate) async fn stream_subscription<T: Clone>(
+    mut reader: BufReader<ReadHalf<Connection>>,
+    mut writer: WriteHalf<Connection>,
+    what: &str,
+    backlog: Vec<IpcResponse>,
+    mut items: broadcast::Receiver<T>,
+    to_response: impl Fn(T) -> Option<IpcResponse>,
+) -> Result<()> {
+    let _subscriber = SubscriberGuard::new();
+    let ack = IpcResponse::Success {
+        message: Some(format!("Subscribed to {}", what)),
+    };
+    for response in std::iter::once(ack).chain(backlog) {
+        write_response(&mut writer, &response).await?;
+    }
 
-        IpcRequest::Status => {
-            let state = state.lock().await;
-            IpcResponse::Status {
-                running: true,
-                project_count: state.registry.count(),
-                process_count: state.process_manager.running_count(),
+    let mut ignored = String::new();
+    loop {
+        tokio::select! {
+            item = items.recv() => {
+                let item = match item {
+                    Ok(item) => item,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        tracing::warn!("Subscriber to {} missed {} items", what, missed);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                };
+                let Some(response) = to_response(item) else {
+                    continue;
+                };
+                write_response(&mut writer, &response).await?;
+            }
+            // Clients send nothing more; EOF means they went away
+            read = reader.read_line(&mut ignored) => {
+                if matches!(read, Ok(0) | Err(_)) {
+                    return Ok(());
+                }
+                ignored.clear();
             }
         }
+    }
+}
 
-        IpcRequest::Shutdown => {
-            tracing::info!("Shutdown requested");
-            // We'll handle this specially
-            IpcResponse::Success {
-                message: Some("Shutting down".to_string()),
+/// Acknowledge an attach, then pass the terminal's output to the client and
+/// its keystrokes and resizes to the terminal until either side goes away
+async fn stream_tty(
+    mut reader: BufReader<ReadHalf<Connection>>,
+    mut writer: WriteHalf<Connection>,
+    tty: &Tty,
+) -> Result<()> {
+    let ack = IpcResponse::Success {
+        message: Some("Attached".to_string()),
+    };
+    write_response(&mut writer, &ack).await?;
+    let (backlog, mut output) = tty.attach();
+    if !backlog.is_empty() {
+        write_response(&mut writer, &tty_output(&backlog)).await?;
+    }
+    let mut exited = tty.exited();
+
+    let mut line = String::new();
+    loop {
+        tokio::select! {
+            bytes = output.recv() => match bytes {
+                Ok(bytes) => write_response(&mut writer, &tty_output(&bytes)).await?,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    tracing::warn!("Attached terminal client missed {} chunks", missed);
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+            // Mapped at once: the watch guard mustn't be held across awaits
+            exit_code = async {
+                let exited = exited.wait_for(Option::is_some).await;
+                exited.ok().and_then(|exited| exited.flatten())
+            } => {
+                // Output read before the exit was reported is already queued
+                while let Ok(bytes) = output.try_recv() {
+                    write_response(&mut writer, &tty_output(&bytes)).await?;
+                }
+                return write_response(&mut writer, &IpcResponse::TtyExited { exit_code }).await;
+            }
+            read = reader.read_line(&mut line) => {
+                if matches!(read, Ok(0) | Err(_)) {
+                    // Detached; the process keeps running
+                    return Ok(());
+                }
+                match serde_json::from_str(&line) {
+                    Ok(IpcRequest::TtyInput { data }) => {
+                        match base64::engine::general_purpose::STANDARD.decode(data.trim()) {
+                            Ok(bytes) => tty.write(&bytes)?,
+                            Err(e) => tracing::debug!("Ignoring undecodable terminal input: {}", e),
+                        }
+                    }
+                    Ok(IpcRequest::TtyResize { size }) => {
+                        if let Err(e) = tty.resize(size) {
+                            tracing::warn!("Failed to resize terminal: {}", e);
+                        }
+                    }
+                    _ => tracing::debug!("Ignoring unexpected line on a terminal: {}", line.trim()),
+                }
+                line.clear();
             }
         }
     }
 }
 
-/// Process events from the process manager and update routing table
-pub async fn process_event_handler(
-    state: Arc<Mutex<DaemonState>>,
-    mut event_rx: tokio::sync::mpsc::Receiver<crate::process::ProcessEvent>,
-) {
-    while let Some(event) = event_rx.recv().await {
-        match event {
-            crate::process::ProcessEvent::PortDetected { process_id, port } => {
-                let mut state = state.lock().await;
+fn tty_output(bytes: &[u8]) -> IpcResponse {
+    IpcResponse::TtyOutput {
+        data: base64::engine::general_purpose::STANDARD.encode(bytes),
+    }
+}
 
-                // Update process port
-                state.process_manager.update_port(process_id, port);
+/// Handle an IPC request
+pub async fn handle_request(request: IpcRequest, state: Arc<DaemonState>) -> IpcResponse {
+    match request {
+        IpcRequest::CreateProject {
+            name,
+            root_dir,
+            template,
+            run_command,
+        } => {
+            let project = Project {
+                template,
+                run_command,
+                ..Project::new(name, root_dir)
+            };
+            let tld = state.config.borrow().tld().to_string();
+            let created = state.registry.write().await.create(project, &tld).await;
+            match created {
+                Ok(project) => {
+                    state.sync_proxy(&project).await;
+                    IpcResponse::Project(project)
+                }
+                Err(e) => IpcResponse::Error(e.into()),
+            }
+        }
 
-                // Get project name for this process
-                if let Some(info) = state.process_manager.get(process_id) {
-                    let project_name = info.project_name.clone();
+        IpcRequest::ImportProject { project } => {
+            let tld = state.config.borrow().tld().to_string();
+            let imported = state.registry.write().await.import(*project, &tld).await;
+            match imported {
+                Ok(project) => {
+                    state.sync_proxy(&project).await;
+                    IpcResponse::Project(project)
+                }
+                Err(e) => IpcResponse::Error(e.into()),
+            }
+        }
 
-                    // Update routing table
-                    {
-                        let mut table = state.routing_table.write().await;
-                        table.insert(project_name.clone(), port);
-                    }
+        IpcRequest::ListProjects { filter } => {
+            let projects: Vec<_> = {
+                let registry = state.registry.read().await;
+                registry.list().into_iter().cloned().collect()
+            };
+            let running: HashSet<String> = state
+                .processes
+                .list()
+                .into_iter()
+                .filter(|info| info.status.is_alive())
+                .map(|info| info.project_name)
+                .collect();
+            IpcResponse::Projects(filter.apply(projects, |name| running.contains(name)))
+        }
 
-                    // Update project's port
-                    if let Err(e) = state.registry.update_port(&project_name, Some(port)).await {
-                        tracing::error!("Failed to update project port: {}", e);
-                    }
+        IpcRequest::ProjectsWithProcesses { filter } => {
+            let projects: Vec<_> = {
+                let registry = state.registry.read().await;
+                registry.list().into_iter().cloned().collect()
+            };
+            let processes = state.processes.list();
+            IpcResponse::ProjectSummaries(filter.summaries(projects, &processes))
+        }
 
-                    tracing::info!(
-                        "Routing {} -> 127.0.0.1:{}",
-                        format!("{}.localhost", project_name),
-                        port
-                    );
-                }
+        IpcRequest::GetProject { name } => match state.registry.read().await.get(&name) {
+            Some(project) => IpcResponse::Project(project.clone()),
+            None => IpcResponse::Error(IpcError::ProjectNotFound { name: name.clone() }),
+        },
+
+        IpcRequest::DeleteProject { name, keep_data } => {
+            if state.registry.read().await.get(&name).is_none() {
+                return IpcResponse::Error(IpcError::ProjectNotFound { name: name.clone() });
             }
 
-            crate::process::ProcessEvent::Exited {
-                process_id,
-                exit_code,
-            } => {
-                let mut state = state.lock().await;
+            let project_name = name.clone();
+            let stopped = state
+                .processes
+                .call(move |manager| {
+                    let pids = manager.stop_all(Some(&project_name));
+                    manager.forget_project(&project_name);
+                    pids
+                })
+                .await;
+            let pids = match stopped {
+                Ok(pids) => pids,
+                Err(error) => return IpcResponse::Error(error),
+            };
+            state.logs.lock().await.forget_project(&name);
+            state.queues.lock().await.forget_project(&name);
 
-                // Get project name before updating status
-                let project_name = state
-                    .process_manager
-                    .get(process_id)
-                    .map(|p| p.project_name.clone());
+            state.unroute(&name).await;
+            state.routing_table.write().await.remove(&name);
+            state.options_table.write().await.remove(&name);
+            state
+                .aliases
+                .write()
+                .await
+                .retain(|_, project| *project != name);
+            state.appearances.write().await.remove(&name);
+            state.captures.clear(&name).await;
+            state.drift.clear(&name).await;
+            state.budgets.clear(&name).await;
+            state.assets.clear(&name).await;
+            state.auth.remove(&name).await;
+            state.graphql.clear(&name).await;
+            state.metrics.clear(&name).await;
+            state.activity.write().await.remove(&name);
 
-                // Update process status
-                let status = if exit_code == Some(0) {
-                    ProcessStatus::Stopped
-                } else {
-                    ProcessStatus::Failed
-                };
-                state.process_manager.update_status(process_id, status);
+            let deleted = state.registry.write().await.delete(&name, keep_data).await;
+            if let Err(e) = deleted {
+                return IpcResponse::Error(e.into());
+            }
 
-                // Remove from routing table
-                if let Some(name) = project_name {
-                    let mut table = state.routing_table.write().await;
-                    table.remove(&name);
+            let grace = state.stop_grace();
+            for pid in pids {
+                process::terminate(pid, grace).await;
+            }
+            IpcResponse::Success {
+                message: Some(format!("Project '{}' deleted", name)),
+            }
+        }
 
-                    tracing::info!(
-                        "Process {} exited with code {:?}, removed routing for {}",
-                        process_id,
-                        exit_code,
-                        name
-                    );
+        IpcRequest::SetProxyOptions {
+            name,
+            options,
+            expected_revision,
+        } => {
+            let updated = state
+                .registry
+                .write()
+                .await
+                .update_proxy_options(&name, options, expected_revision)
+                .await;
+            match updated {
+                Ok(project) => {
+                    state.sync_proxy(&project).await;
+                    // Direct mode may have been turned on or off
+                    state.reroute(&project.name).await;
+                    IpcResponse::Project(project)
                 }
+                Err(e) => update_error(e),
             }
+        }
 
-            crate::process::ProcessEvent::Output { .. } => {
-                // Output is already printed to stdout/stderr in process.rs
+        IpcRequest::SetHooks {
+            name,
+            hooks,
+            expected_revision,
+        } => {
+            let updated = state
+                .registry
+                .write()
+                .await
+                .update_hooks(&name, hooks, expected_revision)
+                .await;
+            match updated {
+                Ok(project) => IpcResponse::Project(project),
+                Err(e) => update_error(e),
             }
         }
+
+        IpcRequest::SetDependencies {
+            name,
+            depends_on,
+            expected_revision,
+        } => {
+            let updated = state
+                .registry
+                .write()
+                .await
+                .update_depends_on(&name, depends_on, expected_revision)
+                .await;
+            match updated {
+                Ok(project) => IpcResponse::Project(project),
+                Err(e) => update_error(e),
+            }
+        }
+
+        IpcRequest::SetPriority {
+            name,
+            priority,
+            expected_revision,
+        } => {
+            let updated = state
+                .registry
+                .write()
+                .await
+                .update_priority(&name, priority, expected_revision)
+                .await;
+            let project = match updated {
+                Ok(project) => project,
+                Err(e) => return update_error(e),
+            };
+            let priority = project.priority.clone();
+            let failed = state
+                .processes
+                .call_async(move |manager| {
+                    Box::pin(async move { manager.set_priority(&name, &priority).await })
+                })
+                .await;
+            let failed = match failed {
+                Ok(failed) => failed,
+                Err(error) => return IpcResponse::Error(error),
+            };
+            if failed.is_empty() {
+                return IpcResponse::Project(project);
+            }
+            // Raising priority back up usually needs root
+            let pids: Vec<String> = failed
+                .iter()
+                .map(|(pid, e)| format!("{} ({})", pid, e))
+                .collect();
+            IpcResponse::Error(IpcError::Failed {
+                message: format!(
+                    "Saved; restarted processes will use it, but running ones couldn't be changed: {}",
+                    pids.join(", ")
+                ),
+            })
+        }
+
+        IpcRequest::SetSchedule {
+            name,
+            schedule,
+            expected_revision,
+        } => {
+            let updated = state
+                .registry
+                .write()
+                .await
+                .update_schedule(&name, schedule, expected_revision)
+                .await;
+            match updated {
+                Ok(project) => {
+                    tokio::spawn(scheduler::reconcile(state.clone()));
+                    IpcResponse::Project(project)
+                }
+                Err(e) => update_error(e),
+            }
+        }
+
+        IpcRequest::RunCommand {
+            project_name,
+            command,
+            args,
+            restart,
+            max_restarts,
+            watch,
+            exclusive,
+            no_wait: _,
+            heavy,
+            output,
+            sandbox,
+            primary,
+            then,
+            otherwise,
+            limits,
+            profile,
+            tty,
+            cwd,
+            on_conflict,
+        } => {
+            let project = state.registry.read().await.get(&project_name).cloned();
+            let single_instance = project.as_ref().is_some_and(Project::is_single_instance);
+            let run_command: Vec<String> = std::iter::once(command.clone())
+                .chain(args.iter().cloned())
+                .collect();
+            let mut warnings = Vec::new();
+
+            // Settled before the pre_run hook, which a duplicate doesn't need
+            if matches!(on_conflict, OnConflict::Refuse | OnConflict::Attach) {
+                let running =
+                    conflicting(&state, &project_name, &run_command, single_instance).await;
+                if let Some(process) = running.into_iter().next() {
+                    state.release_claims(&project_name, exclusive, heavy).await;
+                    return conflict_response(&state, &project_name, on_conflict, process).await;
+                }
+            }
+
+            let checked = match (limits.validate(), project) {
+                (Err(e), _) => Err(e.into()),
+                // A restarted process would get a new terminal nobody is attached to
+                (Ok(()), _)
+                    if tty.is_some() && (restart != RestartPolicy::Never || watch.is_some()) =>
+                {
+                    Err(IpcError::InvalidRequest {
+                        message: "Runs in a terminal can't restart or watch files".to_string(),
+                    })
+                }
+                (Ok(()), None) => Err(IpcError::ProjectNotFound {
+                    name: project_name.clone(),
+                }),
+                (Ok(()), Some(project)) => match hooks::pre_run(&project).await {
+                    Ok(()) => Ok(project),
+                    Err(e) => Err(e.into()),
+                },
+            };
+            let project = match checked {
+                Ok(project) => project,
+                Err(error) => {
+                    state.release_claims(&project_name, exclusive, heavy).await;
+                    return IpcResponse::Error(error);
+                }
+            };
+
+            // A run may choose its own confinement, keeping the allowed directories
+            let mut project_sandbox = project.sandbox;
+            if let Some(mode) = sandbox {
+                project_sandbox.mode = mode;
+            }
+
+            if let Some(cwd) = cwd.filter(|cwd| !cwd.starts_with(&project.root_dir)) {
+                warnings.push(format!(
+                    "Runs in {}, not in the current directory {}",
+                    project.root_dir.display(),
+                    cwd.display()
+                ));
+            }
+            let (mut command, mut args, mut env) = (command, args, BTreeMap::new());
+            let mut requested = None;
+            let profile = match profile {
+                Some(kind) => {
+                    requested = Some(run_command.clone());
+                    match profile_run(&project.name, &mut command, &mut args, &mut env, kind) {
+                        Ok(profile) => Some(profile),
+                        Err(e) => {
+                            state.release_claims(&project_name, exclusive, heavy).await;
+                            return IpcResponse::Error(IpcError::InvalidRequest {
+                                message: e.to_string(),
+                            });
+                        }
+                    }
+                }
+                None => None,
+            };
+            // Stages of a chained run share one job ID
+            let job = (then.is_some() || otherwise.is_some()).then(|| JobInfo {
+                id: Uuid::new_v4(),
+                stage: JobStage::Run,
+            });
+            let spec = SpawnSpec {
+                command,
+                args,
+                working_dir: project.root_dir,
+                restart_policy: restart,
+                max_restarts,
+                watch,
+                health: None,
+                smoke: Vec::new(),
+                service: None,
+                env,
+                port: PortPolicy::default(),
+                output,
+                job,
+                priority: project.priority,
+                env_profiles: project.env_profiles,
+                sandbox: project_sandbox,
+                then,
+                otherwise,
+                limits,
+                named_ports: BTreeMap::new(),
+                profile,
+                tty,
+                requested,
+            };
+
+            // Before the claims are taken, so other runs aren't held up
+            let mut prepared = match process::prepare(&project_name, &spec).await {
+                Ok(prepared) => prepared,
+                Err(e) => {
+                    state.release_claims(&project_name, exclusive, heavy).await;
+                    return IpcResponse::Error(e.into());
+                }
+            };
+            let command_path = prepared.command_path.clone();
+            if command_path.is_none() {
+                warnings.push(format!(
+                    "'{}' was not found on the project's PATH",
+                    spec.command
+                ));
+            }
+            warnings.append(&mut prepared.warnings);
+            // Only once the new run is known to launch, and before it does,
+            // so it gets the port
+            if on_conflict == OnConflict::Restart {
+                let running =
+                    conflicting(&state, &project_name, &run_command, single_instance).await;
+                for process in running {
+                    let stopped = state
+                        .processes
+                        .call(move |manager| manager.stop(process.id))
+                        .await
+                        .and_then(|stopped| stopped.map_err(IpcError::from));
+                    match stopped {
+                        Ok(pid) => {
+                            process::terminate(pid, state.stop_grace()).await;
+                            warnings.push(format!(
+                                "Stopped pid {} ({}) first",
+                                pid,
+                                process.command.trim_end()
+                            ));
+                        }
+                        Err(e) => tracing::warn!("Failed to stop {}: {}", process.id, e),
+                    }
+                }
+            }
+            // Claims are handed to the process before its exit can be
+            // handled, which releases them
+            let mut locks = match exclusive {
+                true => Some(state.locks.lock().await),
+                false => None,
+            };
+            let mut slots = match heavy {
+                true => Some(state.heavy.lock().await),
+                false => None,
+            };
+            let name = project_name.clone();
+            let duplicate_of = (on_conflict != OnConflict::Allow).then(|| run_command.clone());
+            let spawned = state
+                .processes
+                .call_async(move |manager| {
+                    Box::pin(async move {
+                        // Checked again along with the spawn, so runs of a
+                        // command made at the same time start it once
+                        if let Some((command, args)) =
+                            duplicate_of.as_ref().and_then(|c| c.split_first())
+                        {
+                            let running =
+                                manager.conflicting(&name, command, args, single_instance);
+                            if let Some(process) = running.first() {
+                                return anyhow::Ok(Err((*process).clone()));
+                            }
+                        }
+                        let mut process = manager.spawn(name.clone(), spec, prepared).await?;
+                        if primary {
+                            match manager.set_primary(&name, Some(process.id)) {
+                                Ok(()) => process.primary = true,
+                                Err(e) => tracing::warn!("Failed to make process primary: {}", e),
+                            }
+                        }
+                        anyhow::Ok(Ok(process))
+                    })
+                })
+                .await
+                .and_then(|spawned| spawned.map_err(IpcError::from));
+            let process = match spawned {
+                Ok(Ok(process)) => process,
+                Ok(Err(running)) => {
+                    drop((locks, slots));
+                    state.release_claims(&project_name, exclusive, heavy).await;
+                    return conflict_response(&state, &project_name, on_conflict, running).await;
+                }
+                Err(error) => {
+                    drop((locks, slots));
+                    state.release_claims(&project_name, exclusive, heavy).await;
+                    return IpcResponse::Error(error);
+                }
+            };
+            if let Some(locks) = &mut locks {
+                locks.hold(&project_name, process.id);
+            }
+            if let Some(slots) = &mut slots {
+                slots.hold(process.id);
+            }
+            drop((locks, slots));
+
+            if primary {
+                state.reroute(&project_name).await;
+            }
+            // Remembered so `proj up` can start the project again
+            record_start(&state, &project_name, Some(run_command)).await;
+            let (url, https_url) = run_urls(&state, &project_name, &process, &mut warnings).await;
+            IpcResponse::ProcessStarted {
+                process,
+                url,
+                https_url,
+                command_path,
+                warnings,
+            }
+        }
+
+        IpcRequest::GetEnv { project_name } => {
+            let registry = state.registry.read().await;
+            match registry.get(&project_name) {
+                Some(project) => IpcResponse::Env(env::resolve(
+                    &project.name,
+                    &project.root_dir,
+                    &project.env_profiles,
+                )),
+                None => IpcResponse::Error(IpcError::ProjectNotFound {
+                    name: project_name.clone(),
+                }),
+            }
+        }
+
+        IpcRequest::SetEnv { project_name, vars } => {
+            let Some(project) = state.registry.read().await.get(&project_name).cloned() else {
+                return IpcResponse::Error(IpcError::ProjectNotFound {
+                    name: project_name.clone(),
+                });
+            };
+            let result = env::load(&project.name).and_then(|mut stored| {
+                stored.extend(vars);
+                env::save(&project.name, &stored)
+            });
+            match result {
+                Ok(()) => IpcResponse::Env(env::resolve(
+                    &project.name,
+                    &project.root_dir,
+                    &project.env_profiles,
+                )),
+                Err(e) => IpcResponse::Error(e.into()),
+            }
+        }
+
+        IpcRequest::UnsetEnv { project_name, keys } => {
+            let Some(project) = state.registry.read().await.get(&project_name).cloned() else {
+                return IpcResponse::Error(IpcError::ProjectNotFound {
+                    name: project_name.clone(),
+                });
+            };
+            let result = env::load(&project.name).and_then(|mut stored| {
+                for key in &keys {
+                    stored.remove(key);
+                }
+                env::save(&project.name, &stored)
+            });
+            match result {
+                Ok(()) => IpcResponse::Env(env::resolve(
+                    &project.name,
+                    &project.root_dir,
+                    &project.env_profiles,
+                )),
+                Err(e) => IpcResponse::Error(e.into()),
+            }
+        }
+
+        IpcRequest::SetEnvProfiles {
+            project_name,
+            profiles,
+            expected_revision,
+        } => {
+            let updated = state
+                .registry
+                .write()
+                .await
+                .update_env_profiles(&project_name, profiles, expected_revision)
+                .await;
+            let project = match updated {
+                Ok(project) => project,
+                Err(e) => return update_error(e),
+            };
+            let profiles = project.env_profiles.clone();
+            let set = state
+                .processes
+                .call(move |manager| manager.set_env_profiles(&project_name, &profiles))
+                .await;
+            if let Err(error) = set {
+                return IpcResponse::Error(error);
+            }
+            IpcResponse::Env(env::resolve(
+                &project.name,
+                &project.root_dir,
+                &project.env_profiles,
+            ))
+        }
+
+        IpcRequest::SetAliases {
+            name,
+            aliases,
+            expected_revision,
+        } => {
+            let tld = state.config.borrow().tld().to_string();
+            let updated = state
+                .registry
+                .write()
+                .await
+                .update_aliases(&name, aliases, &tld, expected_revision)
+                .await;
+            match updated {
+                Ok(project) => {
+                    state.sync_proxy(&project).await;
+                    IpcResponse::Project(project)
+                }
+                Err(e) => update_error(e),
+            }
+        }
+
+        IpcRequest::SetTags {
+            name,
+            tags,
+            expected_revision,
+        } => {
+            let updated = state
+                .registry
+                .write()
+                .await
+                .update_tags(&name, tags, expected_revision)
+                .await;
+            match updated {
+                Ok(project) => IpcResponse::Project(project),
+                Err(e) => update_error(e),
+            }
+        }
+
+        IpcRequest::SetSmokeChecks {
+            name,
+            checks,
+            expected_revision,
+        } => {
+            let updated = state
+                .registry
+                .write()
+                .await
+                .update_smoke_checks(&name, checks, expected_revision)
+                .await;
+            match updated {
+                Ok(project) => IpcResponse::Project(project),
+                Err(e) => update_error(e),
+            }
+        }
+
+        IpcRequest::SetAppearance {
+            name,
+            appearance,
+            expected_revision,
+        } => {
+            let updated = state
+                .registry
+                .write()
+                .await
+                .update_appearance(&name, appearance, expected_revision)
+                .await;
+            match updated {
+                Ok(project) => {
+                    state.sync_proxy(&project).await;
+                    IpcResponse::Project(project)
+                }
+                Err(e) => update_error(e),
+            }
+        }
+
+        IpcRequest::SetOutputPassthrough {
+            name,
+            passthrough,
+            expected_revision,
+        } => {
+            let updated = state
+                .registry
+                .write()
+                .await
+                .update_output_passthrough(&name, passthrough, expected_revision)
+                .await;
+            match updated {
+                Ok(project) => {
+                    state.sync_proxy(&project).await;
+                    IpcResponse::Project(project)
+                }
+                Err(e) => update_error(e),
+            }
+        }
+
+        IpcRequest::SetSandbox {
+            name,
+            sandbox,
+            expected_revision,
+        } => {
+            let updated = state
+                .registry
+                .write()
+                .await
+                .update_sandbox(&name, sandbox, expected_revision)
+                .await;
+            match updated {
+                Ok(project) => {
+                    let sandbox = project.sandbox.clone();
+                    let set = state
+                        .processes
+                        .call(move |manager| manager.set_sandbox(&name, &sandbox))
+                        .await;
+                    match set {
+                        Ok(()) => IpcResponse::Project(project),
+                        Err(error) => IpcResponse::Error(error),
+                    }
+                }
+                Err(e) => update_error(e),
+            }
+        }
+
+        IpcRequest::AdoptProcess {
+            project_name,
+            pid,
+            port,
+        } => {
+            let pid = match (pid, port) {
+                (Some(pid), _) => pid,
+                (None, Some(port)) => {
+                    let owner = ports::listening_sockets()
+                        .await
+                        .into_iter()
+                        .find(|s| s.port == port)
+                        .map(|s| s.pid);
+                    match owner {
+                        Some(Some(pid)) => pid,
+                        Some(None) => {
+                            return IpcResponse::Error(IpcError::Failed {
+                                message: format!(
+                                    "Port {} is held by a process proj can't inspect",
+                                    port
+                                ),
+                            })
+                        }
+                        None => {
+                            return IpcResponse::Error(IpcError::Failed {
+                                message: format!("Nothing is listening on port {}", port),
+                            })
+                        }
+                    }
+                }
+                (None, None) => {
+                    return IpcResponse::Error(IpcError::InvalidRequest {
+                        message: "Specify a PID or --port to adopt".to_string(),
+                    })
+                }
+            };
+
+            if state.registry.read().await.get(&project_name).is_none() {
+                return IpcResponse::Error(IpcError::ProjectNotFound {
+                    name: project_name.clone(),
+                });
+            }
+            let adopted = state
+                .processes
+                .call_async(move |manager| Box::pin(manager.adopt(project_name, pid)))
+                .await
+                .and_then(|adopted| adopted.map_err(IpcError::from));
+            match adopted {
+                Ok(process) => IpcResponse::ProcessStarted {
+                    process,
+                    url: None,
+                    https_url: None,
+                    command_path: None,
+                    warnings: Vec::new(),
+                },
+                Err(error) => IpcResponse::Error(error),
+            }
+        }
+
+        IpcRequest::StopProcess {
+            project_name: _,
+            process_id,
+        } => {
+            let stopped = state
+                .processes
+                .call(move |manager| manager.stop(process_id))
+                .await
+                .and_then(|stopped| stopped.map_err(IpcError::from));
+            let pid = match stopped {
+                Ok(pid) => pid,
+                Err(error) => return IpcResponse::Error(error),
+            };
+
+            process::terminate(pid, state.stop_grace()).await;
+            IpcResponse::Success {
+                message: Some(format!("Process {} stopped", process_id)),
+            }
+        }
+
+        IpcRequest::SetPrimaryProcess {
+            project_name,
+            process_id,
+        } => {
+            let name = project_name.clone();
+            let set = state
+                .processes
+                .call(move |manager| manager.set_primary(&name, process_id))
+                .await
+                .and_then(|set| set.map_err(IpcError::from));
+            if let Err(error) = set {
+                return IpcResponse::Error(error);
+            }
+            state.reroute(&project_name).await;
+            IpcResponse::Processes(state.processes.list_for_project(&project_name))
+        }
+
+        IpcRequest::SetService {
+            project_name,
+            name,
+            service,
+            expected_revision,
+        } => {
+            if let Err(e) = registry::validate_service(&name, &service) {
+                return IpcResponse::Error(e.into());
+            }
+            let updated = state
+                .registry
+                .write()
+                .await
+                .set_service(&project_name, &name, Some(service), expected_revision)
+                .await;
+            match updated {
+                Ok(project) => IpcResponse::Project(project),
+                Err(e) => update_error(e),
+            }
+        }
+
+        IpcRequest::RemoveService {
+            project_name,
+            name,
+            expected_revision,
+        } => {
+            // Don't stop anything for an update that is going to conflict
+            let checked = state
+                .registry
+                .read()
+                .await
+                .check_revision(&project_name, expected_revision);
+            if let Err(e) = checked {
+                return update_error(e);
+            }
+            let pids = stop_service(&state, &project_name, &name).await;
+            let grace = state.stop_grace();
+            for pid in pids {
+                process::terminate(pid, grace).await;
+            }
+
+            let updated = state
+                .registry
+                .write()
+                .await
+                .set_service(&project_name, &name, None, expected_revision)
+                .await;
+            match updated {
+                Ok(project) => IpcResponse::Project(project),
+                Err(e) => update_error(e),
+            }
+        }
+
+        IpcRequest::StartService {
+            project_name,
+            name,
+            debug,
+        } => {
+            let Some(project) = state.registry.read().await.get(&project_name).cloned() else {
+                return IpcResponse::Error(IpcError::ProjectNotFound {
+                    name: project_name.clone(),
+                });
+            };
+            let Some(mut service) = project.services.get(&name).cloned() else {
+                return IpcResponse::Error(IpcError::ServiceNotFound {
+                    project: project_name.clone(),
+                    service: name.clone(),
+                });
+            };
+            if debug {
+                let stack = match debug::Stack::of(&service.command) {
+                    Ok(stack) => stack,
+                    Err(e) => {
+                        return IpcResponse::Error(IpcError::InvalidRequest {
+                            message: e.to_string(),
+                        })
+                    }
+                };
+                let port = (stack.default_port()..)
+                    .take(100)
+                    .find(|&port| ports::is_free(port))
+                    .unwrap_or(stack.default_port());
+                debug::instrument(&mut service, stack, port);
+                // Restarted under the debugger
+                let grace = state.stop_grace();
+                for pid in stop_service(&state, &project_name, &name).await {
+                    process::terminate(pid, grace).await;
+                }
+            }
+            if let Err(e) = hooks::pre_run(&project).await {
+                return IpcResponse::Error(e.into());
+            }
+
+            // Checked when set, but a project stored before that might not be
+            let Some((command, args)) = service.command.split_first() else {
+                return IpcResponse::Error(IpcError::InvalidRequest {
+                    message: format!("Service '{}' has an empty command", name),
+                });
+            };
+            let spec = SpawnSpec {
+                command: command.clone(),
+                args: args.to_vec(),
+                working_dir: project.root_dir.clone(),
+                restart_policy: service.restart,
+                max_restarts: service.max_restarts,
+                watch: service.watch.clone(),
+                health: service.health.clone(),
+                smoke: project.smoke.clone(),
+                service: Some(name.clone()),
+                env: service.env.clone(),
+                port: service.port,
+                output: None,
+                job: None,
+                priority: project.priority.clone(),
+                env_profiles: project.env_profiles.clone(),
+                sandbox: project.sandbox.clone(),
+                then: None,
+                otherwise: None,
+                limits: ResourceLimits::default(),
+                named_ports: service.ports.clone(),
+                profile: None,
+                tty: None,
+                requested: None,
+            };
+            let prepared = match process::prepare(&project_name, &spec).await {
+                Ok(prepared) => prepared,
+                Err(e) => return IpcResponse::Error(e.into()),
+            };
+            // Checked and spawned in one call, so two starts can't both go ahead
+            let spawned = state
+                .processes
+                .call_async(move |manager| {
+                    Box::pin(async move {
+                        if let Some(running) =
+                            manager.running_for_service(&project_name, &name).first()
+                        {
+                            return Err(IpcError::ServiceRunning {
+                                service: name,
+                                pid: running.pid,
+                            });
+                        }
+                        manager
+                            .spawn(project_name, spec, prepared)
+                            .await
+                            .map_err(IpcError::from)
+                    })
+                })
+                .await
+                .and_then(|spawned| spawned);
+            match spawned {
+                Ok(process) => {
+                    record_start(&state, &process.project_name, None).await;
+                    IpcResponse::ProcessStarted {
+                        process,
+                        url: None,
+                        https_url: None,
+                        command_path: None,
+                        warnings: Vec::new(),
+                    }
+                }
+                Err(error) => IpcResponse::Error(error),
+            }
+        }
+
+        IpcRequest::StopService { project_name, name } => {
+            let pids = stop_service(&state, &project_name, &name).await;
+            let grace = state.stop_grace();
+
+            let stopped = pids.len();
+            for pid in pids {
+                process::terminate(pid, grace).await;
+            }
+            IpcResponse::Success {
+                message: Some(format!("Stopped {} process(es) of {}", stopped, name)),
+            }
+        }
+
+        IpcRequest::ListServices { project_name } => {
+            let Some(project) = state.registry.read().await.get(&project_name).cloned() else {
+                return IpcResponse::Error(IpcError::ProjectNotFound {
+                    name: project_name.clone(),
+                });
+            };
+            let processes = state.processes.list_for_project(&project_name);
+            let services = project
+                .services
+                .into_iter()
+                .map(|(name, service)| ServiceInfo {
+                    // Most recent instance
+                    process: processes
+                        .iter()
+                        .filter(|p| p.service.as_deref() == Some(name.as_str()))
+                        .max_by_key(|p| p.started_at)
+                        .cloned(),
+                    name,
+                    service,
+                })
+                .collect();
+            IpcResponse::Services(services)
+        }
+
+        IpcRequest::ListRequests {
+            project_name,
+            limit,
+        } => IpcResponse::Requests(state.captures.list(&project_name, limit).await),
+
+        IpcRequest::GetRequest { project_name, id } => {
+            match state.captures.get(&project_name, id).await {
+                Some(request) => IpcResponse::Request(request),
+                None => IpcResponse::Error(IpcError::RequestNotFound {
+                    project: project_name,
+                    id,
+                }),
+            }
+        }
+
+        IpcRequest::GetApiDrift { project_name } => {
+            IpcResponse::ApiDrift(state.drift.reports(&project_name).await)
+        }
+
+        IpcRequest::ClearApiDrift { project_name } => {
+            state.drift.clear(&project_name).await;
+            IpcResponse::Success {
+                message: Some(format!("Cleared API drift for '{}'", project_name)),
+            }
+        }
+
+        IpcRequest::GetBudgets { project_name } => {
+            let budgets = match state.registry.read().await.get(&project_name) {
+                Some(project) => project.proxy.budgets,
+                None => {
+                    return IpcResponse::Error(IpcError::ProjectNotFound {
+                        name: project_name.clone(),
+                    })
+                }
+            };
+            IpcResponse::Budgets(state.budgets.status(&project_name, &budgets).await)
+        }
+
+        IpcRequest::RunSmokeChecks { project_name } => {
+            let Some(checks) = state
+                .registry
+                .read()
+                .await
+                .get(&project_name)
+                .map(|project| project.smoke.clone())
+            else {
+                return IpcResponse::Error(IpcError::ProjectNotFound { name: project_name });
+            };
+            let (addr, host) = {
+                let config = state.config.borrow();
+                (config.proxy_addr(), config.host(&project_name))
+            };
+            IpcResponse::SmokeResults(smoke::run(addr, &host, &checks, smoke::TIMEOUT).await)
+        }
+
+        IpcRequest::GetAssets { project_name } => {
+            IpcResponse::Assets(state.assets.report(&project_name).await)
+        }
+
+        IpcRequest::ClearAssets { project_name } => {
+            state.assets.clear(&project_name).await;
+            IpcResponse::Success {
+                message: Some(format!("Cleared response sizes for '{}'", project_name)),
+            }
+        }
+
+        IpcRequest::GetGraphqlOps { project_name } => {
+            IpcResponse::GraphqlOps(state.graphql.list(&project_name).await)
+        }
+
+        IpcRequest::GetTraffic { project_name } => {
+            IpcResponse::Traffic(state.metrics.traffic(&project_name).await)
+        }
+
+        IpcRequest::ListProcesses { project_name } => IpcResponse::Processes(match project_name {
+            Some(name) => state.processes.list_for_project(&name),
+            None => state.processes.list(),
+        }),
+
+        IpcRequest::GetHistory {
+            project_name,
+            limit,
+        } => {
+            if state.registry.read().await.get(&project_name).is_none() {
+                return IpcResponse::Error(IpcError::ProjectNotFound {
+                    name: project_name.clone(),
+                });
+            }
+            match state.processes.runs(&project_name, limit) {
+                Ok(runs) => IpcResponse::Runs(runs),
+                Err(e) => IpcResponse::Error(e.into()),
+            }
+        }
+
+        IpcRequest::GetLogs {
+            project_name,
+            query,
+        } => {
+            if state.registry.read().await.get(&project_name).is_none() {
+                return IpcResponse::Error(IpcError::ProjectNotFound {
+                    name: project_name.clone(),
+                });
+            }
+            let root = state.logs.lock().await.root().to_path_buf();
+            let read =
+                tokio::task::spawn_blocking(move || logs::read(&root, &project_name, &query)).await;
+            match read {
+                Ok(Ok(lines)) => IpcResponse::Logs(lines),
+                Ok(Err(e)) => IpcResponse::Error(e.into()),
+                Err(e) => IpcResponse::Error(IpcError::Failed {
+                    message: e.to_string(),
+                }),
+            }
+        }
+
+        IpcRequest::Prune { project_name } => {
+            if let Some(name) = &project_name {
+                if state.registry.read().await.get(name).is_none() {
+                    return IpcResponse::Error(IpcError::ProjectNotFound { name: name.clone() });
+                }
+            }
+            let (processes, log_files, bytes) = state.prune(project_name.as_deref()).await;
+            IpcResponse::Pruned {
+                processes,
+                log_files,
+                bytes,
+            }
+        }
+
+        IpcRequest::RunMaintenance => IpcResponse::Maintenance(maintenance::run_once(&state).await),
+
+        IpcRequest::ListLocks { project_name } => {
+            IpcResponse::Locks(state.locks.lock().await.list(project_name.as_deref()))
+        }
+
+        IpcRequest::QueueAdd {
+            project_name,
+            command,
+        } => {
+            if command.is_empty() {
+                return IpcResponse::Error(IpcError::InvalidRequest {
+                    message: "Job command is empty".to_string(),
+                });
+            }
+            let Some(project) = state.registry.read().await.get(&project_name).cloned() else {
+                return IpcResponse::Error(IpcError::ProjectNotFound {
+                    name: project_name.clone(),
+                });
+            };
+            let mut queues = state.queues.lock().await;
+            queues.push(&project_name, command);
+            start_queued(&state, &mut queues, &project).await;
+            IpcResponse::Queue(queues.list(&project_name))
+        }
+
+        IpcRequest::ListQueue { project_name } => {
+            if state.registry.read().await.get(&project_name).is_none() {
+                return IpcResponse::Error(IpcError::ProjectNotFound {
+                    name: project_name.clone(),
+                });
+            }
+            IpcResponse::Queue(state.queues.lock().await.list(&project_name))
+        }
+
+        IpcRequest::ClearQueue { project_name } => {
+            let cleared = state.queues.lock().await.clear(&project_name);
+            IpcResponse::Success {
+                message: Some(format!(
+                    "Removed {} pending job(s) from {}",
+                    cleared, project_name
+                )),
+            }
+        }
+
+        IpcRequest::ListPorts => {
+            let sockets = ports::listening_sockets().await;
+
+            let processes = state.processes.list();
+            let owner = |port: u16| {
+                processes
+                    .iter()
+                    .find(|p| p.status.is_alive() && p.ports.contains(&port))
+                    .map(|p| p.project_name.clone())
+            };
+
+            let mut usage: Vec<PortUsage> = sockets
+                .into_iter()
+                .map(|socket| PortUsage {
+                    port: socket.port,
+                    listening: true,
+                    pid: socket.pid,
+                    process_name: socket.process_name,
+                    project_name: owner(socket.port),
+                })
+                .collect();
+
+            // Ports projects last ran on, even if nothing listens there now
+            for project in state.registry.read().await.list() {
+                if let Some(port) = project.port {
+                    if !usage.iter().any(|u| u.port == port) {
+                        usage.push(PortUsage {
+                            port,
+                            listening: false,
+                            pid: None,
+                            process_name: None,
+                            project_name: Some(project.name.clone()),
+                        });
+                    }
+                }
+            }
+            usage.sort_by_key(|u| u.port);
+
+            IpcResponse::Ports(usage)
+        }
+
+        IpcRequest::Hello {
+            protocol_version,
+            client_version,
+        } => {
+            if protocol_version != PROTOCOL_VERSION {
+                tracing::warn!(
+                    "proj {} speaks protocol {}, this daemon ({}) speaks {}",
+                    client_version,
+                    protocol_version,
+                    VERSION,
+                    PROTOCOL_VERSION
+                );
+            }
+            IpcResponse::Hello {
+                protocol_version: PROTOCOL_VERSION,
+                daemon_version: VERSION.to_string(),
+                keeps_connection: true,
+            }
+        }
+
+        // Only TCP connections check the token; local clients already are
+        // the daemon's user
+        IpcRequest::Authenticate { .. } => IpcResponse::Success { message: None },
+
+        IpcRequest::Status => {
+            // Not held across awaits: the receiver's borrow blocks reloads
+            let (proxy_addr, lan_urls) = {
+                let config = state.config.borrow();
+                (
+                    config.proxy_addr(),
+                    config.lan_urls(&state.network.borrow()),
+                )
+            };
+            IpcResponse::Status {
+                running: true,
+                project_count: state.registry.read().await.count(),
+                process_count: state.processes.running_count(),
+                protocol_version: PROTOCOL_VERSION,
+                daemon_version: VERSION.to_string(),
+                panics: panics::recent(),
+                proxy_error: state.proxy_bind_error.read().await.clone(),
+                git_hash: option_env!("PROJ_GIT_HASH").map(str::to_string),
+                started_at: Some(state.started_at),
+                proxy_addr: Some(proxy_addr),
+                socket_path: proj_common::socket_path().ok(),
+                route_count: state.routing_table.read().await.len(),
+                subscriber_count: SUBSCRIBERS.load(Ordering::Relaxed),
+                lan_urls,
+            }
+        }
+
+        IpcRequest::Capabilities => IpcResponse::Capabilities(vec![
+            Capability::binary("binary"),
+            platform::capability(),
+            ports::capability().await,
+            sandbox::capability(),
+            limits::capability().await,
+        ]),
+
+        IpcRequest::Diagnostics => IpcResponse::Diagnostics(DaemonDiagnostics {
+            open_fds: stats::open_fds(),
+            memory_bytes: stats::own_memory(),
+            tasks: tokio::runtime::Handle::current()
+                .metrics()
+                .num_alive_tasks(),
+            routes: state.routing_table.read().await.keys().cloned().collect(),
+            process_records: state.processes.record_count(),
+        }),
+
+        IpcRequest::RestartProcess {
+            project_name: _,
+            process_id,
+        } => {
+            let restarted = state
+                .processes
+                .call(move |manager| {
+                    let restartable = manager
+                        .get(process_id)
+                        .map(|p| p.status.is_alive() && manager.can_respawn(process_id));
+                    match restartable {
+                        None => Err(IpcError::ProcessNotFound {
+                            id: process_id.to_string(),
+                        }),
+                        Some(false) => Err(IpcError::InvalidRequest {
+                            message: "Only running processes started by proj can be restarted"
+                                .to_string(),
+                        }),
+                        Some(true) => manager.request_restart(process_id).map_err(IpcError::from),
+                    }
+                })
+                .await
+                .and_then(|restarted| restarted);
+            match restarted {
+                Ok(()) => IpcResponse::Success {
+                    message: Some(format!("Process {} restarting", process_id)),
+                },
+                Err(error) => IpcResponse::Error(error),
+            }
+        }
+
+        IpcRequest::SubscribeRegistry
+        | IpcRequest::SubscribeLogs { .. }
+        | IpcRequest::Subscribe { .. } => IpcResponse::Error(IpcError::InvalidRequest {
+            message: "Subscriptions are handled per connection".to_string(),
+        }),
+
+        IpcRequest::AttachTty { .. } => IpcResponse::Error(IpcError::InvalidRequest {
+            message: "Terminals are attached per connection".to_string(),
+        }),
+        IpcRequest::TtyInput { .. } | IpcRequest::TtyResize { .. } => {
+            IpcResponse::Error(IpcError::InvalidRequest {
+                message: "Not attached to a terminal".to_string(),
+            })
+        }
+
+        IpcRequest::ReloadConfig => match state.reload_config().await {
+            Ok(changes) if changes.is_empty() => IpcResponse::Success {
+                message: Some("Config unchanged".to_string()),
+            },
+            Ok(changes) => {
+                tracing::info!("Reloaded config: {}", changes.join(", "));
+                IpcResponse::Success {
+                    message: Some(format!("Applied {}", changes.join(", "))),
+                }
+            }
+            Err(e) => IpcResponse::Error(e.into()),
+        },
+
+        IpcRequest::SetDnd { mode } => {
+            if let Some(mode) = mode {
+                state.dnd.set_mode(mode);
+                tracing::info!("Do-not-disturb set to {}", mode);
+            }
+            IpcResponse::Dnd(state.dnd.status())
+        }
+
+        IpcRequest::SetLogLevels { levels, reset } => {
+            let filter = match (reset, levels.is_empty()) {
+                (true, _) => logging::reset_levels().and_then(|_| logging::set_levels(&levels)),
+                (false, true) => Ok(logging::current()),
+                (false, false) => logging::set_levels(&levels),
+            };
+            match filter {
+                Ok(filter) => {
+                    if reset || !levels.is_empty() {
+                        tracing::info!(filter = %filter, "Log levels changed");
+                    }
+                    IpcResponse::Success {
+                        message: Some(filter),
+                    }
+                }
+                Err(e) => IpcResponse::Error(IpcError::InvalidRequest {
+                    message: format!("{:#}", e),
+                }),
+            }
+        }
+
+        IpcRequest::Shutdown { .. } => {
+            tracing::info!("Shutdown requested");
+            // The main loop stops processes and cleans up once this is sent
+            IpcResponse::Success {
+                message: Some("Shutting down".to_string()),
+            }
+        }
+    }
+}
+
+/// Response for a failed project update, telling revision conflicts apart
+fn update_error(e: anyhow::Error) -> IpcResponse {
+    match e.downcast_ref::<Conflict>() {
+        Some(conflict) => IpcResponse::Conflict {
+            message: e.to_string(),
+            revision: conflict.current,
+        },
+        None => IpcResponse::Error(e.into()),
+    }
+}
+
+/// Ask a service's running processes to stop, returning their PIDs to await
+async fn stop_service(state: &DaemonState, project_name: &str, name: &str) -> Vec<u32> {
+    let (project_name, name) = (project_name.to_string(), name.to_string());
+    state
+        .processes
+        .call(move |manager| {
+            let ids: Vec<_> = manager
+                .running_for_service(&project_name, &name)
+                .iter()
+                .map(|p| p.id)
+                .collect();
+            ids.into_iter()
+                .filter_map(|id| manager.stop(id).ok())
+                .collect()
+        })
+        .await
+        .unwrap_or_default()
+}
+
+/// Wrap a run's command and environment to profile it, into a new
+/// directory under the project's profiles
+fn profile_run(
+    project_name: &str,
+    command: &mut String,
+    args: &mut Vec<String>,
+    env: &mut BTreeMap<String, String>,
+    kind: ProfileKind,
+) -> anyhow::Result<Profile> {
+    let dir = profile::profiles_dir(project_name)?.join(format!(
+        "{}-{}",
+        chrono::Local::now().format("%Y%m%d-%H%M%S"),
+        kind
+    ));
+    let mut argv: Vec<String> = std::iter::once(command.clone())
+        .chain(args.drain(..))
+        .collect();
+    let profile = profile::instrument(&mut argv, env, kind, &dir, platform::on_path)?;
+    profile::prepare(&dir)?;
+    *args = argv.split_off(1);
+    *command = argv.remove(0);
+    Ok(profile)
+}
+
+/// Record that a project was started, and with what command if it was a run
+async fn record_start(state: &DaemonState, project_name: &str, command: Option<Vec<String>>) {
+    let recorded = state
+        .registry
+        .write()
+        .await
+        .record_start(project_name, command)
+        .await;
+    if let Err(e) = recorded {
+        tracing::warn!("Failed to record start of {}: {}", project_name, e);
+    }
+}
+
+/// Where a run's process is reached, and (in `warnings`) why not yet if it
+/// has no port
+async fn run_urls(
+    state: &DaemonState,
+    project_name: &str,
+    process: &ProcessInfo,
+    warnings: &mut Vec<String>,
+) -> (Option<String>, Option<String>) {
+    let direct = state
+        .registry
+        .read()
+        .await
+        .get(project_name)
+        .is_some_and(Project::is_direct);
+    if process.port.is_none() {
+        warnings.push(if direct {
+            "No port yet; in direct mode it's at http://localhost:<port> once it listens, and `info` shows the port".to_string()
+        } else {
+            "No port yet; the URL works once the process listens".to_string()
+        });
+    }
+    if direct {
+        (process.port.map(proj_common::direct_url), None)
+    } else {
+        let config = state.config.borrow();
+        (
+            Some(config.url(project_name)),
+            config.https_url(project_name),
+        )
+    }
+}
+
+/// Copies of the running processes of a project that a run of `run_command`
+/// would duplicate (see [`ProcessManager::conflicting`])
+async fn conflicting(
+    state: &DaemonState,
+    project_name: &str,
+    run_command: &[String],
+    single_instance: bool,
+) -> Vec<ProcessInfo> {
+    let Some((command, args)) = run_command.split_first() else {
+        return Vec::new();
+    };
+    let (name, command, args) = (project_name.to_string(), command.clone(), args.to_vec());
+    state
+        .processes
+        .call(move |manager| {
+            manager
+                .conflicting(&name, &command, &args, single_instance)
+                .into_iter()
+                .cloned()
+                .collect()
+        })
+        .await
+        .unwrap_or_default()
+}
+
+/// Answer a run that would duplicate `running`: refused, or with `running`
+/// as if it had just been started
+async fn conflict_response(
+    state: &DaemonState,
+    project_name: &str,
+    on_conflict: OnConflict,
+    running: ProcessInfo,
+) -> IpcResponse {
+    if on_conflict == OnConflict::Refuse {
+        return IpcResponse::Error(IpcError::AlreadyRunning {
+            command: running.command.trim_end().to_string(),
+            pid: running.pid,
+        });
+    }
+    let mut warnings = vec![format!(
+        "Already running as pid {} since {}; not started again",
+        running.pid,
+        running
+            .started_at
+            .with_timezone(&chrono::Local)
+            .format("%H:%M:%S")
+    )];
+    let (url, https_url) = run_urls(state, project_name, &running, &mut warnings).await;
+    IpcResponse::ProcessStarted {
+        process: running,
+        url,
+        https_url,
+        command_path: None,
+        warnings,
+    }
+}
+
+/// Start the next job of a project's queue if none is running. The queue
+/// stays locked until the job is known to be started, so its exit can't be
+/// handled before then.
+async fn start_queued(state: &DaemonState, queues: &mut QueueTable, project: &Project) {
+    let project_name = &project.name;
+    while let Some(job) = queues.next(project_name) {
+        let (command, args) = job.command.split_first().expect("validated on add");
+        let spec = SpawnSpec {
+            command: command.clone(),
+            args: args.to_vec(),
+            working_dir: project.root_dir.clone(),
+            restart_policy: RestartPolicy::Never,
+            max_restarts: 0,
+            watch: None,
+            health: None,
+            smoke: Vec::new(),
+            service: None,
+            env: BTreeMap::new(),
+            // Background jobs get no PORT of their own
+            port: PortPolicy::Detect,
+            output: None,
+            job: None,
+            priority: project.priority.clone(),
+            env_profiles: project.env_profiles.clone(),
+            sandbox: project.sandbox.clone(),
+            then: None,
+            otherwise: None,
+            limits: ResourceLimits::default(),
+            named_ports: BTreeMap::new(),
+            profile: None,
+            tty: None,
+            requested: None,
+        };
+        let spawned = state.processes.start(project_name.clone(), spec).await;
+        match spawned {
+            Ok(process) => {
+                tracing::info!(
+                    "Started queued job {} of {} as process {}",
+                    job.id,
+                    project_name,
+                    process.id
+                );
+                queues.started(project_name, process.id);
+                return;
+            }
+            Err(e) => {
+                tracing::warn!("Failed to start queued job {}: {}", job.id, e);
+                queues.finish(project_name, None);
+            }
+        }
+    }
+}
+
+/// Bundle a failed process's output, announce the bundle, run the configured
+/// quick actions on it and start the project's `on_crash` hook
+async fn record_crash(state: Arc<DaemonState>, project: Project, process: ProcessInfo) {
+    tokio::time::sleep(CRASH_OUTPUT_DELAY).await;
+
+    let output: Vec<String> = state
+        .logs
+        .lock()
+        .await
+        .for_process(&project.name, process.id)
+        .into_iter()
+        .map(|line| line.line)
+        .collect();
+    let actions = state.config.borrow().crash_actions.clone();
+
+    let bundle = match crash::write_bundle(&process, &output, &project.root_dir) {
+        Ok(bundle) => bundle,
+        Err(e) => {
+            tracing::warn!("Failed to write crash bundle for {}: {:#}", project.name, e);
+            hooks::on_crash(&project, &process, None);
+            return;
+        }
+    };
+    tracing::info!(
+        "Crash bundle for {}: {}",
+        project.name,
+        bundle.dir.display()
+    );
+    state.events.emit(
+        &project.name,
+        EventKind::CrashBundle {
+            process_id: process.id,
+            path: bundle.dir.clone(),
+            location: bundle.info.location.as_ref().map(|l| l.to_string()),
+        },
+    );
+    hooks::on_crash(&project, &process, Some(&bundle.dir));
+
+    if !actions.is_empty() {
+        // Editors and clipboard tools can take a while; keep them off the runtime
+        tokio::task::spawn_blocking(move || {
+            for action in actions {
+                if let Err(e) = bundle.run(action) {
+                    tracing::warn!("Crash action {} for {}: {:#}", action, project.name, e);
+                }
+            }
+        });
+    }
+}
+
+/// Process events from the process manager and update routing table
+pub async fn process_event_handler(
+    state: Arc<DaemonState>,
+    mut event_rx: tokio::sync::mpsc::Receiver<crate::process::ProcessEvent>,
+) {
+    while let Some(event) = event_rx.recv().await {
+        match event {
+            crate::process::ProcessEvent::PortDetected {
+                process_id,
+                port,
+                ports,
+                bound,
+            } => {
+                let updated = state
+                    .processes
+                    .call(move |manager| {
+                        manager.update_port(process_id, port, ports);
+                        if bound {
+                            manager.reach_milestone(process_id, Milestone::Listening);
+                        }
+                        // Services with a health check are routed once it passes
+                        let checked = manager.start_health_check(process_id, port);
+                        let project_name = manager.get(process_id)?.project_name.clone();
+                        Some((project_name, checked))
+                    })
+                    .await
+                    .ok()
+                    .flatten();
+                if let Some((project_name, checked)) = updated {
+                    if checked {
+                        tracing::info!("Waiting for {} to pass its health check", project_name);
+                    }
+                    state.reroute(&project_name).await;
+                }
+            }
+
+            crate::process::ProcessEvent::Exited {
+                process_id,
+                exit_code,
+                signal,
+            } => {
+                // Update process status and consult its restart policy
+                let Ok((restart_delay, info, stop_requested)) = state
+                    .processes
+                    .call(move |manager| {
+                        let restart_delay = manager.record_exit(process_id, exit_code, signal);
+                        let info = manager.get(process_id).cloned();
+                        (restart_delay, info, manager.stop_requested(process_id))
+                    })
+                    .await
+                else {
+                    continue;
+                };
+                state.logs.lock().await.close(process_id).await;
+                let project_name = info.as_ref().map(|p| p.project_name.clone());
+                let pid = info.as_ref().map(|p| p.pid);
+
+                // Crashes and completed stops trigger the project's hooks
+                let project = match &project_name {
+                    Some(name) => state.registry.read().await.get(name).cloned(),
+                    None => None,
+                };
+                if let (Some(project), Some(info)) = (project, info) {
+                    if info.status == ProcessStatus::Failed {
+                        tokio::spawn(record_crash(state.clone(), project, info));
+                    } else if stop_requested
+                        && !state
+                            .processes
+                            .list_for_project(&project.name)
+                            .iter()
+                            .any(|p| p.status.is_alive())
+                    {
+                        hooks::post_stop(&project);
+                    }
+                }
+
+                // Another of the project's processes takes over its route, if any can
+                if let Some(name) = &project_name {
+                    tracing::info!(
+                        project = %name,
+                        process_id = %process_id,
+                        ?exit_code,
+                        "Process exited"
+                    );
+                    state.reroute(name).await;
+                }
+
+                if let Some(delay) = restart_delay {
+                    tracing::info!(process_id = %process_id, ?delay, "Restarting process");
+                    let state = state.clone();
+                    let grace = state.stop_grace();
+                    tokio::spawn(async move {
+                        // Children of the old process can outlive it and hold its port
+                        if let Some(pid) = pid {
+                            process::terminate(pid, grace).await;
+                        }
+                        tokio::time::sleep(delay).await;
+                        let respawned = state.processes.respawn(process_id).await;
+                        if let Err(e) = respawned {
+                            tracing::warn!(process_id = %process_id, "Failed to restart process: {}", e);
+                            state.release_process(process_id).await;
+                        }
+                    });
+                } else {
+                    // The job goes on with its next stage, which inherits any lock or slot
+                    let project_name = project_name.unwrap_or_default();
+                    let name = project_name.clone();
+                    let next = match state
+                        .processes
+                        .call(move |manager| manager.next_stage(process_id))
+                        .await
+                        .ok()
+                        .flatten()
+                    {
+                        Some(spec) => Some(state.processes.start(name, spec).await),
+                        None => None,
+                    };
+                    match next {
+                        Some(Ok(next)) => {
+                            if let Some(job) = next.job {
+                                tracing::info!(
+                                    project = %project_name,
+                                    process_id = %next.id,
+                                    "Started '{}' stage of the job",
+                                    job.stage
+                                );
+                            }
+                            state.locks.lock().await.pass_on(process_id, next.id);
+                            state.heavy.lock().await.pass_on(process_id, next.id);
+                        }
+                        Some(Err(e)) => {
+                            tracing::warn!("Failed to start next stage of {}: {}", project_name, e);
+                            state.release_process(process_id).await;
+                        }
+                        // Exclusive and heavy runs keep their claims across restarts
+                        None => state.release_process(process_id).await,
+                    }
+                }
+
+                // A finished background job makes way for the next one
+                if restart_delay.is_none() {
+                    let finished = state.queues.lock().await.project_of(process_id);
+                    if let Some(project_name) = finished {
+                        let project = state.registry.read().await.get(&project_name).cloned();
+                        let mut queues = state.queues.lock().await;
+                        queues.finish(&project_name, exit_code);
+                        if let Some(project) = project {
+                            start_queued(&state, &mut queues, &project).await;
+                        }
+                    }
+                }
+            }
+
+            crate::process::ProcessEvent::FilesChanged { process_id } => {
+                let requested = state
+                    .processes
+                    .call(move |manager| manager.request_restart(process_id))
+                    .await
+                    .and_then(|requested| requested.map_err(IpcError::from));
+                if let Err(e) = requested {
+                    tracing::warn!(process_id = %process_id, "Failed to restart process: {}", e);
+                }
+            }
+
+            crate::process::ProcessEvent::Health {
+                process_id,
+                healthy,
+            } => {
+                let updated = state
+                    .processes
+                    .call(move |manager| {
+                        let info = manager.get(process_id)?;
+                        let (project_name, port) = (info.project_name.clone(), info.port);
+                        // A process that already exited keeps its final status
+                        if !info.status.is_alive() {
+                            return None;
+                        }
+                        manager.update_health(process_id, healthy);
+                        Some((project_name, port))
+                    })
+                    .await
+                    .ok()
+                    .flatten();
+                let Some((project_name, port)) = updated else {
+                    continue;
+                };
+                if healthy {
+                    tracing::info!(project = %project_name, ?port, "Healthy");
+                } else {
+                    tracing::warn!(project = %project_name, "Failed its health check");
+                }
+                state.reroute(&project_name).await;
+            }
+
+            crate::process::ProcessEvent::Output(line) => {
+                // Its passthrough setting decides where the line goes
+                state.settle(&line.project_name).await;
+                let (process_id, observed) = (line.process_id, line.line.clone());
+                state
+                    .processes
+                    .cast(move |manager| manager.observe_output(process_id, &observed))
+                    .await;
+                state.logs.lock().await.push(line).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Taken by every test that points `HOME` at its scratch directory, which
+    /// the tests running alongside it would otherwise see change under them
+    static HOME_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    /// Daemon state over a scratch home directory, with its event handler
+    /// running, and the hold on `HOME` to keep until the test is done
+    async fn test_state() -> (
+        Arc<DaemonState>,
+        std::path::PathBuf,
+        tokio::sync::MutexGuard<'static, ()>,
+    ) {
+        let lock = HOME_LOCK.lock().await;
+        let home = std::env::temp_dir().join(format!("proj-ipc-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&home).unwrap();
+        std::env::set_var("HOME", &home);
+        (state_at(&home).await, home, lock)
+    }
+
+    /// Daemon state over the store in `home`
+    async fn state_at(home: &Path) -> Arc<DaemonState> {
+        let config = Config::default();
+        let events = EventBus::new();
+        let store = Store::open(&home.join("proj.db")).unwrap();
+        let proxy = ProxyContext::for_tests(config.clone());
+        let mut manager = ProcessManager::new(&config, events, store.clone());
+        let event_rx = manager.take_event_receiver().unwrap();
+        let state = DaemonState::new(manager, store, &proxy, watch::channel(config).0)
+            .await
+            .unwrap();
+        let state = Arc::new(state);
+        tokio::spawn(process_event_handler(state.clone(), event_rx));
+        state
+    }
+
+    fn run(project_name: &str, script: String) -> IpcRequest {
+        IpcRequest::RunCommand {
+            project_name: project_name.to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script],
+            restart: RestartPolicy::Never,
+            max_restarts: 0,
+            watch: None,
+            exclusive: false,
+            no_wait: false,
+            heavy: false,
+            output: None,
+            then: None,
+            otherwise: None,
+            sandbox: None,
+            primary: false,
+            limits: ResourceLimits::default(),
+            profile: None,
+            tty: None,
+            cwd: None,
+            on_conflict: OnConflict::Refuse,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_proxy_tables_from_index() {
+        let (state, home, _home) = test_state().await;
+        let mut web = Project::new("web".to_string(), home.clone());
+        web.aliases = vec!["web.test".to_string()];
+        web.proxy.routes.insert("/api".to_string(), 4000);
+        web.appearance.color = Some("#e5484d".to_string());
+        state.store.save_project(&web).unwrap();
+
+        // As after a restart: routed without reading the project
+        let state = state_at(&home).await;
+        assert_eq!(state.registry.read().await.loaded(), 0);
+        assert_eq!(state.aliases.read().await.get("web.test").unwrap(), "web");
+        assert!(state.options_table.read().await.contains_key("web"));
+        let routes = state.routing_table.read().await.get("web").cloned();
+        assert_eq!(routes.unwrap().target("/api/users"), Some(4000));
+        assert!(!state.appearances.read().await.contains_key("web"));
+
+        // The rest once it's used
+        state.settle("web").await;
+        assert_eq!(state.registry.read().await.loaded(), 1);
+        let appearances = state.appearances.read().await;
+        assert_eq!(appearances["web"].color.as_deref(), Some("#e5484d"));
+        drop(appearances);
+        let _ = std::fs::remove_dir_all(home);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_parallel_requests() {
+        const RUNS: usize = 16;
+        let (state, home, _home) = test_state().await;
+        let created = handle_request(
+            IpcRequest::CreateProject {
+                name: "stress".to_string(),
+                root_dir: home.clone(),
+                template: None,
+                run_command: None,
+            },
+            state.clone(),
+        )
+        .await;
+        assert!(matches!(created, IpcResponse::Project(_)));
+        let (_, mut lines) = state.logs.lock().await.subscribe("stress");
+
+        let mut runs = tokio::task::JoinSet::new();
+        for n in 0..RUNS {
+            let request = run("stress", format!("echo line-{}; sleep 0.3", n));
+            runs.spawn(handle_request(request, state.clone()));
+        }
+        // Status is answered while spawns are in flight, without waiting on them
+        let mut statuses = tokio::task::JoinSet::new();
+        for _ in 0..RUNS * 4 {
+            let state = state.clone();
+            statuses.spawn(async move {
+                let status = tokio::time::timeout(
+                    Duration::from_secs(2),
+                    handle_request(IpcRequest::Status, state),
+                );
+                status.await.expect("status held up")
+            });
+        }
+
+        while let Some(status) = statuses.join_next().await {
+            let IpcResponse::Status {
+                project_count,
+                process_count,
+                ..
+            } = status.unwrap()
+            else {
+                panic!("expected a status");
+            };
+            assert_eq!(project_count, 1);
+            assert!(process_count <= RUNS);
+        }
+        while let Some(started) = runs.join_next().await {
+            assert!(matches!(
+                started.unwrap(),
+                IpcResponse::ProcessStarted { .. }
+            ));
+        }
+
+        // Every run's output reaches the log stream
+        let mut seen = HashSet::new();
+        while seen.len() < RUNS {
+            let line = tokio::time::timeout(Duration::from_secs(10), lines.recv())
+                .await
+                .expect("output missing from the log stream")
+                .unwrap();
+            seen.insert(line.line);
+        }
+        assert!((0..RUNS).all(|n| seen.contains(&format!("line-{}", n))));
+
+        let listed = handle_request(
+            IpcRequest::ListProcesses {
+                project_name: Some("stress".to_string()),
+            },
+            state.clone(),
+        )
+        .await;
+        let IpcResponse::Processes(processes) = listed else {
+            panic!("expected processes");
+        };
+        assert_eq!(processes.len(), RUNS);
+
+        let pids = state.processes.call(|manager| manager.stop_all(None)).await;
+        for pid in pids.unwrap() {
+            process::terminate(pid, Duration::from_secs(1)).await;
+        }
+        let _ = std::fs::remove_dir_all(home);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_slow_spawn_holds_up_no_other() {
+        let (state, home, _home) = test_state().await;
+        for name in ["slow", "fast"] {
+            std::fs::create_dir_all(home.join(name)).unwrap();
+            let created = handle_request(
+                IpcRequest::CreateProject {
+                    name: name.to_string(),
+                    root_dir: home.join(name),
+                    template: None,
+                    run_command: None,
+                },
+                state.clone(),
+            )
+            .await;
+            assert!(matches!(created, IpcResponse::Project(_)));
+        }
+        // Reading this .env blocks until something writes to it
+        let fifo = home.join("slow/.env");
+        let made = std::process::Command::new("mkfifo").arg(&fifo).status();
+        assert!(made.unwrap().success());
+
+        let request = run("slow", "true".to_string());
+        let slow = tokio::spawn(handle_request(request, state.clone()));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!slow.is_finished());
+        let fast = tokio::time::timeout(
+            Duration::from_secs(2),
+            handle_request(run("fast", "true".to_string()), state.clone()),
+        );
+        let fast = fast.await;
+        // Unblocked first, so a failure doesn't hang the runtime
+        tokio::task::spawn_blocking(move || std::fs::write(fifo, "GREETING=hi\n"))
+            .await
+            .unwrap()
+            .unwrap();
+        let fast = fast.expect("run held up by another one's spawn");
+        assert!(matches!(fast, IpcResponse::ProcessStarted { .. }));
+        let slow = tokio::time::timeout(Duration::from_secs(5), slow).await;
+        assert!(matches!(
+            slow.unwrap().unwrap(),
+            IpcResponse::ProcessStarted { .. }
+        ));
+        let _ = std::fs::remove_dir_all(home);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_duplicate_runs() {
+        let (state, home, _home) = test_state().await;
+        let created = handle_request(
+            IpcRequest::CreateProject {
+                name: "dup".to_string(),
+                root_dir: home.clone(),
+                template: None,
+                run_command: None,
+            },
+            state.clone(),
+        )
+        .await;
+        assert!(matches!(created, IpcResponse::Project(_)));
+        let run_with = |on_conflict| {
+            let mut request = run("dup", "sleep 30".to_string());
+            if let IpcRequest::RunCommand { on_conflict: c, .. } = &mut request {
+                *c = on_conflict;
+            }
+            request
+        };
+        let started = |response: IpcResponse| match response {
+            IpcResponse::ProcessStarted { process, .. } => process.id,
+            other => panic!("expected a started process, got {:?}", other),
+        };
+
+        // Runs made at the same time start the command once
+        let mut runs = tokio::task::JoinSet::new();
+        for _ in 0..4 {
+            runs.spawn(handle_request(run_with(OnConflict::Attach), state.clone()));
+        }
+        let mut ids = HashSet::new();
+        while let Some(response) = runs.join_next().await {
+            ids.insert(started(response.unwrap()));
+        }
+        assert_eq!(ids.len(), 1);
+        let first = ids.into_iter().next().unwrap();
+
+        let refused = handle_request(run_with(OnConflict::Refuse), state.clone()).await;
+        assert!(matches!(
+            refused,
+            IpcResponse::Error(IpcError::AlreadyRunning { .. })
+        ));
+        // Another command isn't a duplicate
+        let other = handle_request(run("dup", "sleep 31".to_string()), state.clone()).await;
+        started(other);
+
+        let running = || {
+            state.processes.call(|manager| {
+                let args = ["-c".to_string(), "sleep 30".to_string()];
+                let running = manager.conflicting("dup", "sh", &args, false);
+                running
+                    .into_iter()
+                    .map(|process| process.id)
+                    .collect::<Vec<_>>()
+            })
+        };
+        // A restart that can't go ahead leaves the running one be
+        let mut invalid = run_with(OnConflict::Restart);
+        if let IpcRequest::RunCommand { tty, restart, .. } = &mut invalid {
+            *tty = Some(proj_common::TerminalSize::default());
+            *restart = RestartPolicy::Always;
+        }
+        let refused = handle_request(invalid, state.clone()).await;
+        assert!(matches!(
+            refused,
+            IpcResponse::Error(IpcError::InvalidRequest { .. })
+        ));
+        assert_eq!(running().await.unwrap(), [first]);
+
+        let restarted = started(handle_request(run_with(OnConflict::Restart), state.clone()).await);
+        assert_ne!(restarted, first);
+        assert_eq!(running().await.unwrap(), [restarted]);
+
+        let pids = state.processes.call(|manager| manager.stop_all(None)).await;
+        for pid in pids.unwrap() {
+            process::terminate(pid, Duration::from_secs(1)).await;
+        }
+        let _ = std::fs::remove_dir_all(home);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_restore_checks_pid_owner() {
+        let (state, home, _home) = test_state().await;
+        let created = handle_request(
+            IpcRequest::CreateProject {
+                name: "kept".to_string(),
+                root_dir: home.clone(),
+                template: None,
+                run_command: None,
+            },
+            state.clone(),
+        )
+        .await;
+        assert!(matches!(created, IpcResponse::Project(_)));
+        let process = match handle_request(run("kept", "sleep 30".to_string()), state.clone()).await
+        {
+            IpcResponse::ProcessStarted { process, .. } => process,
+            other => panic!("expected a started process, got {:?}", other),
+        };
+        // An older run whose PID the OS has since given to this one
+        let mut reused = process.clone();
+        reused.id = Uuid::new_v4();
+        reused.started_at -= chrono::TimeDelta::days(1);
+        state.store.save_processes(&[&process, &reused]).unwrap();
+
+        let restored = state_at(&home).await;
+        let status = |id| {
+            restored
+                .processes
+                .call(move |manager| manager.get(id).map(|p| p.status.clone()))
+        };
+        assert_eq!(
+            status(process.id).await.unwrap(),
+            Some(ProcessStatus::Running)
+        );
+        assert_eq!(
+            status(reused.id).await.unwrap(),
+            Some(ProcessStatus::Stopped)
+        );
+
+        process::terminate(process.pid, Duration::from_secs(1)).await;
+        let _ = std::fs::remove_dir_all(home);
     }
 }
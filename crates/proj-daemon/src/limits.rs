@@ -0,0 +1,236 @@
+//! Resource caps on runs (`run --mem 2G --cpus 2 --files 1024`)
+//!
+//! On Linux with cgroups v2 and a systemd user manager, the command runs in a
+//! transient scope (`systemd-run --user --scope`) whose memory and CPU quota
+//! the kernel enforces, killing the tree when it outgrows its memory.
+//! Elsewhere the usage sampler kills a tree whose resident memory passes the
+//! cap (an address-space rlimit would break runtimes like V8 and the JVM,
+//! which reserve far more than they use), and a CPU cap can only lower the
+//! tree's priority. The open-file cap is an rlimit everywhere.
+
+use proj_common::{
+    format_size, Capability, CapabilityStatus, Priority, ResourceLimits, ResourceUsage,
+};
+use tokio::process::Command;
+use tokio::sync::OnceCell;
+
+/// SIGKILL, which the kernel's OOM killer sends
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+const SIGKILL: i32 = 9;
+
+/// Niceness a CPU cap falls back to without cgroups
+const CPU_FALLBACK_NICE: i32 = 10;
+
+/// Whether cgroup-backed scopes work here, probed once
+static CGROUPS: OnceCell<bool> = OnceCell::const_new();
+
+/// The program and arguments that run `command` inside a scope enforcing
+/// `limits`, when cgroups can
+pub async fn wrap(
+    command: &str,
+    args: &[String],
+    limits: &ResourceLimits,
+) -> (String, Vec<String>) {
+    if limits.memory_bytes.is_none() && limits.cpus.is_none() || !cgroups_available().await {
+        return (command.to_string(), args.to_vec());
+    }
+
+    let mut wrapped: Vec<String> = ["--user", "--scope", "--quiet", "--collect"]
+        .map(str::to_string)
+        .into();
+    if let Some(bytes) = limits.memory_bytes {
+        // Without swap the tree is killed at the cap instead of thrashing
+        wrapped.extend([
+            "-p".to_string(),
+            format!("MemoryMax={}", bytes),
+            "-p".to_string(),
+            "MemorySwapMax=0".to_string(),
+        ]);
+    }
+    if let Some(cpus) = limits.cpus {
+        wrapped.extend([
+            "-p".to_string(),
+            format!("CPUQuota={}%", (cpus * 100.0).round() as u64),
+        ]);
+    }
+    wrapped.push("--".to_string());
+    wrapped.push(command.to_string());
+    wrapped.extend(args.iter().cloned());
+    ("systemd-run".to_string(), wrapped)
+}
+
+/// How memory and CPU caps are enforced here, for `proj doctor`
+pub async fn capability() -> Capability {
+    const NAME: &str = "resource limits";
+    if cgroups_available().await {
+        Capability::new(
+            NAME,
+            CapabilityStatus::Ok,
+            "cgroups v2 scopes through systemd-run",
+        )
+    } else {
+        Capability::new(
+            NAME,
+            CapabilityStatus::Degraded,
+            "no cgroups v2 scopes: memory caps are enforced by sampling, and CPU caps only lower priority",
+        )
+    }
+}
+
+/// Whether the kernel enforces `limits`' memory and CPU caps
+pub fn in_cgroup(limits: &ResourceLimits) -> bool {
+    (limits.memory_bytes.is_some() || limits.cpus.is_some()) && CGROUPS.get() == Some(&true)
+}
+
+/// Cap the open files of the process `cmd` starts
+#[cfg(unix)]
+pub fn set_open_files(cmd: &mut Command, limits: &ResourceLimits) {
+    use nix::sys::resource::{setrlimit, Resource};
+
+    let Some(files) = limits.open_files else {
+        return;
+    };
+    // SAFETY: setrlimit is async-signal-safe
+    unsafe {
+        cmd.pre_exec(move || {
+            setrlimit(Resource::RLIMIT_NOFILE, files, files)?;
+            Ok(())
+        });
+    }
+}
+
+#[cfg(windows)]
+pub fn set_open_files(_cmd: &mut Command, limits: &ResourceLimits) {
+    if limits.open_files.is_some() {
+        tracing::warn!("Open file limits aren't supported on Windows");
+    }
+}
+
+/// Priority a run gets: a CPU cap the kernel can't enforce lowers it,
+/// unless the project sets its own niceness
+pub fn priority(limits: &ResourceLimits, priority: &Priority) -> Priority {
+    if limits.cpus.is_none() || in_cgroup(limits) || priority.nice.is_some() {
+        return priority.clone();
+    }
+    Priority {
+        nice: Some(CPU_FALLBACK_NICE),
+        ..priority.clone()
+    }
+}
+
+/// Why a tree must be killed, when its memory passed a cap the kernel
+/// doesn't enforce
+pub fn over_memory(limits: &ResourceLimits, usage: &ResourceUsage) -> Option<String> {
+    let cap = limits.memory_bytes?;
+    if in_cgroup(limits) || usage.memory_bytes <= cap {
+        return None;
+    }
+    Some(format!(
+        "Killed for exceeding its {} memory limit (using {})",
+        format_size(cap),
+        format_size(usage.memory_bytes)
+    ))
+}
+
+/// Why a process is failing, when its output shows it ran out of files
+pub fn reason_from_output(limits: &ResourceLimits, line: &str) -> Option<String> {
+    let files = limits.open_files?;
+    let line = line.to_lowercase();
+    (line.contains("too many open files") || line.contains("emfile")).then(|| {
+        format!(
+            "Ran out of file descriptors at its {} open file limit",
+            files
+        )
+    })
+}
+
+/// Why a process died, when its exit signal shows the kernel killed it at
+/// its memory cap
+pub fn reason_from_exit(limits: &ResourceLimits, signal: Option<i32>) -> Option<String> {
+    let cap = limits.memory_bytes?;
+    (in_cgroup(limits) && signal == Some(SIGKILL)).then(|| {
+        format!(
+            "Killed by the kernel for exceeding its {} memory limit",
+            format_size(cap)
+        )
+    })
+}
+
+async fn cgroups_available() -> bool {
+    *CGROUPS
+        .get_or_init(|| async {
+            let available = probe().await;
+            if !available {
+                tracing::info!("cgroups v2 unavailable; memory limits are enforced by sampling, CPU limits lower priority");
+            }
+            available
+        })
+        .await
+}
+
+/// Start a throwaway scope with caps and check they reached its cgroup; a
+/// user manager without delegated controllers silently ignores them
+#[cfg(target_os = "linux")]
+async fn probe() -> bool {
+    if !std::path::Path::new("/sys/fs/cgroup/cgroup.controllers").exists() {
+        return false;
+    }
+    let output = Command::new("systemd-run")
+        .args([
+            "--user",
+            "--scope",
+            "--quiet",
+            "--collect",
+            "-p",
+            "MemoryMax=1G",
+            "-p",
+            "CPUQuota=100%",
+            "--",
+            "sh",
+            "-c",
+            "d=/sys/fs/cgroup$(cut -d: -f3 /proc/self/cgroup); cat $d/memory.max $d/cpu.max",
+        ])
+        .output()
+        .await;
+    output.is_ok_and(|output| {
+        let caps = String::from_utf8_lossy(&output.stdout);
+        output.status.success() && caps.contains("1073741824") && caps.contains("100000 100000")
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn probe() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_without_cgroups() {
+        let limits = ResourceLimits {
+            memory_bytes: Some(1 << 30),
+            cpus: Some(2.0),
+            open_files: Some(256),
+        };
+        let usage = |memory_bytes| ResourceUsage {
+            cpu_percent: 0.0,
+            memory_bytes,
+        };
+        assert_eq!(over_memory(&limits, &usage(1 << 29)), None);
+        assert_eq!(
+            over_memory(&limits, &usage(3 << 29)).as_deref(),
+            Some("Killed for exceeding its 1 GiB memory limit (using 1.5 GiB)")
+        );
+        assert_eq!(priority(&limits, &Priority::default()).nice, Some(10));
+        let niced = Priority {
+            nice: Some(5),
+            io: None,
+        };
+        assert_eq!(priority(&limits, &niced), niced);
+        assert!(reason_from_output(&limits, "Error: EMFILE: too many open files, watch").is_some());
+        assert!(reason_from_output(&ResourceLimits::default(), "EMFILE").is_none());
+        assert_eq!(reason_from_exit(&limits, Some(SIGKILL)), None);
+    }
+}
@@ -0,0 +1,165 @@
+//! Per-project locks serializing `run --exclusive` commands
+
+use chrono::Utc;
+use proj_common::{LockWaiter, ProjectLock};
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+/// Exclusive-run locks by project name
+#[derive(Default)]
+pub struct LockTable {
+    locks: HashMap<String, Lock>,
+}
+
+#[derive(Default)]
+struct Lock {
+    /// Taken by a running process, or reserved for a run about to start
+    held: bool,
+    holder: Option<Uuid>,
+    waiters: VecDeque<Waiter>,
+}
+
+struct Waiter {
+    id: Uuid,
+    info: LockWaiter,
+    wake: oneshot::Sender<()>,
+}
+
+impl LockTable {
+    /// Take a project's lock if nobody holds or is waiting for it
+    pub fn try_acquire(&mut self, project_name: &str) -> bool {
+        let lock = self.locks.entry(project_name.to_string()).or_default();
+        if lock.held || !lock.waiters.is_empty() {
+            return false;
+        }
+        lock.held = true;
+        true
+    }
+
+    /// Queue for a project's lock; the receiver fires once it is handed over
+    pub fn enqueue(
+        &mut self,
+        project_name: &str,
+        command: String,
+    ) -> (Uuid, oneshot::Receiver<()>) {
+        let (wake, woken) = oneshot::channel();
+        let id = Uuid::new_v4();
+        let lock = self.locks.entry(project_name.to_string()).or_default();
+        lock.waiters.push_back(Waiter {
+            id,
+            info: LockWaiter {
+                command,
+                queued_at: Utc::now(),
+            },
+            wake,
+        });
+        (id, woken)
+    }
+
+    /// Leave the queue. A waiter the lock was already handed to passes it on.
+    pub fn cancel(&mut self, project_name: &str, waiter_id: Uuid) {
+        let Some(lock) = self.locks.get_mut(project_name) else {
+            return;
+        };
+        let queued = lock.waiters.len();
+        lock.waiters.retain(|w| w.id != waiter_id);
+        if lock.waiters.len() == queued {
+            self.release(project_name);
+        }
+    }
+
+    /// Record the process an acquired lock is held by
+    pub fn hold(&mut self, project_name: &str, process_id: Uuid) {
+        if let Some(lock) = self.locks.get_mut(project_name) {
+            lock.holder = Some(process_id);
+        }
+    }
+
+    /// Move whichever lock `process_id` holds over to `next`
+    pub fn pass_on(&mut self, process_id: Uuid, next: Uuid) {
+        if let Some(lock) = self
+            .locks
+            .values_mut()
+            .find(|lock| lock.holder == Some(process_id))
+        {
+            lock.holder = Some(next);
+        }
+    }
+
+    /// Release whichever lock `process_id` holds
+    pub fn release_process(&mut self, process_id: Uuid) {
+        let project_name = self
+            .locks
+            .iter()
+            .find(|(_, lock)| lock.holder == Some(process_id))
+            .map(|(name, _)| name.clone());
+        if let Some(name) = project_name {
+            self.release(&name);
+        }
+    }
+
+    /// Hand a project's lock to the next waiter still around, or free it
+    pub fn release(&mut self, project_name: &str) {
+        let Some(lock) = self.locks.get_mut(project_name) else {
+            return;
+        };
+        lock.holder = None;
+        while let Some(waiter) = lock.waiters.pop_front() {
+            if waiter.wake.send(()).is_ok() {
+                return;
+            }
+        }
+        self.locks.remove(project_name);
+    }
+
+    /// Holders and queues of held locks
+    pub fn list(&self, project_name: Option<&str>) -> Vec<ProjectLock> {
+        let mut locks: Vec<ProjectLock> = self
+            .locks
+            .iter()
+            .filter(|(name, lock)| lock.held && project_name.is_none_or(|p| p == *name))
+            .map(|(name, lock)| ProjectLock {
+                project_name: name.clone(),
+                holder: lock.holder,
+                waiters: lock.waiters.iter().map(|w| w.info.clone()).collect(),
+            })
+            .collect();
+        locks.sort_by(|a, b| a.project_name.cmp(&b.project_name));
+        locks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_handoff() {
+        let mut locks = LockTable::default();
+        assert!(locks.try_acquire("app"));
+        let holder = Uuid::new_v4();
+        locks.hold("app", holder);
+        assert!(!locks.try_acquire("app"));
+
+        let (gone, gone_rx) = locks.enqueue("app", "first".into());
+        let (_, mut next_rx) = locks.enqueue("app", "second".into());
+        drop(gone_rx);
+        locks.cancel("app", gone);
+        assert_eq!(locks.list(Some("app"))[0].waiters.len(), 1);
+
+        // A chained run's next stage carries the lock on
+        let next_stage = Uuid::new_v4();
+        locks.pass_on(holder, next_stage);
+        assert_eq!(locks.list(Some("app"))[0].holder, Some(next_stage));
+
+        // Exiting holder wakes the next waiter, which keeps the lock reserved
+        locks.release_process(next_stage);
+        assert!(next_rx.try_recv().is_ok());
+        assert!(!locks.try_acquire("app"));
+
+        locks.release("app");
+        assert!(locks.list(None).is_empty());
+        assert!(locks.try_acquire("app"));
+    }
+}
@@ -0,0 +1,230 @@
+//! The daemon's own log and its filter
+//!
+//! Events carry `project` and `process_id` fields where they concern one, and
+//! their target names the subsystem that logged them: a module of the daemon
+//! (`proj_daemon::proxy`), or `access` and `output` for the proxy's request
+//! lines and passed-through process output. `RUST_LOG` (default `info`) sets
+//! the levels at start; `proj daemon log-level proxy=debug` overrides one
+//! subsystem's level until the daemon restarts or the override is reset.
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+/// Subsystems whose level can be set, each a module of the daemon except
+/// `access` and `output`
+pub const SUBSYSTEMS: &[&str] = &[
+    "access",
+    "api",
+    "assets",
+    "auth",
+    "budgets",
+    "capture",
+    "drift",
+    "env",
+    "events",
+    "forward",
+    "graphql",
+    "health",
+    "heavy",
+    "hooks",
+    "idle",
+    "interfaces",
+    "ipc",
+    "limits",
+    "locks",
+    "logging",
+    "logs",
+    "maintenance",
+    "metrics",
+    "milestones",
+    "mock",
+    "notifications",
+    "outbound",
+    "output",
+    "panics",
+    "placeholders",
+    "platform",
+    "ports",
+    "process",
+    "proxy",
+    "queue",
+    "reaper",
+    "redirects",
+    "registry",
+    "remedies",
+    "remote",
+    "rewrites",
+    "routes",
+    "sandbox",
+    "scheduler",
+    "shedding",
+    "shutdown",
+    "smoke",
+    "stats",
+    "tls",
+    "tty",
+    "upstream",
+    "watcher",
+];
+
+/// Levels a subsystem can be set to
+const LEVELS: &[&str] = &["off", "error", "warn", "info", "debug", "trace"];
+
+static FILTER: OnceLock<Filter> = OnceLock::new();
+
+struct Filter {
+    /// Directives from `RUST_LOG`, or `info`
+    base: String,
+    /// Level by subsystem, set at runtime
+    overrides: Mutex<BTreeMap<&'static str, &'static str>>,
+    handle: reload::Handle<EnvFilter, Registry>,
+}
+
+/// Start logging to stderr
+pub fn init() {
+    let base = std::env::var("RUST_LOG")
+        .ok()
+        .filter(|directives| EnvFilter::try_new(directives).is_ok())
+        .unwrap_or_else(|| "info".to_string());
+    let (filter, handle) = reload::Layer::new(EnvFilter::new(&base));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+    let _ = FILTER.set(Filter {
+        base,
+        overrides: Mutex::default(),
+        handle,
+    });
+}
+
+/// Apply `subsystem=level` directives (`subsystem=default` drops an
+/// override), returning the filter in effect
+pub fn set_levels(directives: &[String]) -> Result<String> {
+    let parsed = directives
+        .iter()
+        .map(|directive| parse_directive(directive))
+        .collect::<Result<Vec<_>>>()?;
+    let Some(filter) = FILTER.get() else {
+        anyhow::bail!("Logging isn't initialized");
+    };
+
+    let mut overrides = filter.overrides.lock().unwrap();
+    for (subsystem, level) in parsed {
+        match level {
+            Some(level) => overrides.insert(subsystem, level),
+            None => overrides.remove(subsystem),
+        };
+    }
+    let directives = filter_directives(&filter.base, &overrides);
+    filter.handle.reload(EnvFilter::try_new(&directives)?)?;
+    Ok(directives)
+}
+
+/// Drop every override, going back to `RUST_LOG`
+pub fn reset_levels() -> Result<String> {
+    let Some(filter) = FILTER.get() else {
+        anyhow::bail!("Logging isn't initialized");
+    };
+    filter.overrides.lock().unwrap().clear();
+    filter.handle.reload(EnvFilter::try_new(&filter.base)?)?;
+    Ok(filter.base.clone())
+}
+
+/// The filter in effect
+pub fn current() -> String {
+    match FILTER.get() {
+        Some(filter) => filter_directives(&filter.base, &filter.overrides.lock().unwrap()),
+        None => String::new(),
+    }
+}
+
+/// `proxy=debug` as a subsystem and level, with `default` as no level
+fn parse_directive(directive: &str) -> Result<(&'static str, Option<&'static str>)> {
+    let Some((subsystem, level)) = directive.split_once('=') else {
+        anyhow::bail!("Expected <subsystem>=<level>, got '{}'", directive);
+    };
+    let Some(subsystem) = SUBSYSTEMS.iter().find(|s| **s == subsystem) else {
+        anyhow::bail!(
+            "Unknown subsystem '{}' (one of: {})",
+            subsystem,
+            SUBSYSTEMS.join(", ")
+        );
+    };
+    let level = level.to_ascii_lowercase();
+    if level == "default" {
+        return Ok((subsystem, None));
+    }
+    match LEVELS.iter().find(|l| **l == level) {
+        Some(level) => Ok((subsystem, Some(level))),
+        None => anyhow::bail!(
+            "Unknown level '{}' (one of: {}, default)",
+            level,
+            LEVELS.join(", ")
+        ),
+    }
+}
+
+/// The tracing target a subsystem logs under
+fn target(subsystem: &str) -> String {
+    match subsystem {
+        "output" => "proj::output".to_string(),
+        other => format!("proj_daemon::{}", other),
+    }
+}
+
+/// `base` with the overrides appended, minus base directives for the same
+/// targets so the override is the only match
+fn filter_directives(base: &str, overrides: &BTreeMap<&'static str, &'static str>) -> String {
+    let targets: BTreeMap<String, &str> = overrides
+        .iter()
+        .map(|(subsystem, level)| (target(subsystem), *level))
+        .collect();
+    base.split(',')
+        .filter(|directive| {
+            let directive_target = directive.split('=').next().unwrap_or_default();
+            !targets.contains_key(directive_target.trim())
+        })
+        .map(str::to_string)
+        .chain(
+            targets
+                .iter()
+                .map(|(target, level)| format!("{}={}", target, level)),
+        )
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_directive() {
+        assert_eq!(
+            parse_directive("proxy=DEBUG").unwrap(),
+            ("proxy", Some("debug"))
+        );
+        assert_eq!(
+            parse_directive("process=default").unwrap(),
+            ("process", None)
+        );
+        assert!(parse_directive("proxy").is_err());
+        assert!(parse_directive("proxx=debug").is_err());
+        assert!(parse_directive("proxy=loud").is_err());
+    }
+
+    #[test]
+    fn test_filter_directives() {
+        let overrides = BTreeMap::from([("proxy", "debug"), ("output", "off")]);
+        assert_eq!(
+            filter_directives("info,proj_daemon::proxy=warn", &overrides),
+            "info,proj::output=off,proj_daemon::proxy=debug"
+        );
+        assert_eq!(filter_directives("info", &BTreeMap::new()), "info");
+    }
+}
@@ -0,0 +1,695 @@
+//! Process output - recent lines per project, a live feed for subscribers
+//! and a log file per process
+//!
+//! Each process's lines are appended as JSON to
+//! `~/.proj/projects/<name>/logs/<id>.log`. A file that reaches
+//! `log_max_bytes` is rotated to `<id>.log.1`, older rotations moving up one
+//! number and the one past `log_max_files` being deleted. Files without a new
+//! line for `log_retention_days` are deleted hourly, and `proj prune` deletes
+//! those of exited processes right away.
+//!
+//! With `log_collapse_repeats` on, a line a process already printed among its
+//! last few is left out, so banners and warnings re-printed on every hot
+//! reload don't bury the rest. A note counting what was left out takes its
+//! place once the process prints something new, exits, or keeps repeating
+//! for a while.
+//!
+//! Stored lines then go on to live subscribers and, with
+//! `output_passthrough` on (or a project's override), to the daemon's own
+//! log (see [`crate::output`]).
+
+use anyhow::{Context, Result};
+use proj_common::{Config, LogLine, LogQuery};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::ipc::DaemonState;
+use crate::output::{Fanout, Live, Passthrough, LIVE_CAPACITY};
+
+/// Maximum number of output lines kept per project
+const MAX_LINES_PER_PROJECT: usize = 1000;
+
+/// How often log files past the retention period are looked for
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// How many of a process's last stored lines a repeat is looked for in
+const COLLAPSE_WINDOW: usize = 16;
+
+/// How long repeats are left out before a note about them is stored anyway
+const COLLAPSE_NOTE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Rotation and retention settings
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    /// Size a file is rotated at (0 = never)
+    max_bytes: u64,
+    /// Rotated files kept besides the current one
+    max_files: usize,
+    /// How long a file is kept after its last line (`None` = until pruned)
+    retention: Option<Duration>,
+}
+
+impl Limits {
+    fn from_config(config: &Config) -> Self {
+        Self {
+            max_bytes: config.log_max_bytes,
+            max_files: config.log_max_files,
+            retention: (config.log_retention_days > 0)
+                .then(|| Duration::from_secs(config.log_retention_days * 24 * 3600)),
+        }
+    }
+}
+
+/// The log file a process's lines are appended to
+struct LogFile {
+    project_name: String,
+    path: PathBuf,
+    /// `None` once opening or writing failed; the process's output is then
+    /// only buffered
+    file: Option<File>,
+    size: u64,
+}
+
+impl LogFile {
+    fn open(root: &Path, project_name: &str, process_id: Uuid) -> Self {
+        let path = logs_dir(root, project_name).join(format!("{}.log", process_id));
+        let mut log = Self {
+            project_name: project_name.to_string(),
+            path,
+            file: None,
+            size: 0,
+        };
+        if let Err(e) = log.reopen() {
+            tracing::warn!("Not storing output of {}: {:#}", process_id, e);
+        }
+        log
+    }
+
+    /// Open the file for appending, creating it and its directory if needed
+    fn reopen(&mut self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        self.size = file.metadata()?.len();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn append(&mut self, line: &LogLine, limits: Limits) -> Result<()> {
+        let mut json = serde_json::to_string(line)?;
+        json.push('\n');
+        let len = json.len() as u64;
+        if limits.max_bytes > 0 && self.size > 0 && self.size + len > limits.max_bytes {
+            self.rotate(limits.max_files)?;
+        }
+        if let Some(file) = &mut self.file {
+            file.write_all(json.as_bytes())
+                .with_context(|| format!("Failed to write {}", self.path.display()))?;
+            self.size += len;
+        }
+        Ok(())
+    }
+
+    /// Move the current file to `.1`, shifting older rotations up and
+    /// dropping the one past `keep`, and start a new one
+    fn rotate(&mut self, keep: usize) -> Result<()> {
+        let rotated = |n: usize| PathBuf::from(format!("{}.{}", self.path.display(), n));
+        // Closed first, as Windows can't rename open files
+        self.file = None;
+        let oldest = rotated(keep);
+        if keep == 0 || oldest.exists() {
+            let oldest = if keep == 0 { self.path.clone() } else { oldest };
+            std::fs::remove_file(&oldest)
+                .with_context(|| format!("Failed to remove {}", oldest.display()))?;
+        }
+        for n in (1..=keep).rev() {
+            let from = if n == 1 {
+                self.path.clone()
+            } else {
+                rotated(n - 1)
+            };
+            if from.exists() {
+                std::fs::rename(&from, rotated(n))
+                    .with_context(|| format!("Failed to rotate {}", from.display()))?;
+            }
+        }
+        self.reopen()
+    }
+}
+
+/// A process's recent lines and the repeats left out since the last one
+#[derive(Default)]
+struct Repeats {
+    /// Stream and text of the last stored lines, newest last
+    recent: VecDeque<(bool, String)>,
+    /// Lines left out since then
+    left_out: u64,
+    /// Whether every line left out was the last stored one again
+    all_last: bool,
+    /// When the first of them came
+    since: Option<Instant>,
+    /// The last of them, which the note takes the place of
+    latest: Option<LogLine>,
+}
+
+impl Repeats {
+    /// Whether a line repeats a recent one, counting it if so
+    fn is_repeat(&mut self, line: &LogLine) -> bool {
+        let key = (line.is_stderr, line.line.clone());
+        if !self.recent.contains(&key) {
+            return false;
+        }
+        let is_last = self.recent.back() == Some(&key);
+        self.all_last = is_last && (self.left_out == 0 || self.all_last);
+        self.left_out += 1;
+        self.since.get_or_insert_with(Instant::now);
+        self.latest = Some(line.clone());
+        true
+    }
+
+    fn remember(&mut self, line: &LogLine) {
+        if self.recent.len() >= COLLAPSE_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((line.is_stderr, line.line.clone()));
+    }
+
+    /// Whether repeats have been left out for long enough to note them
+    fn is_due(&self) -> bool {
+        self.since
+            .is_some_and(|since| since.elapsed() >= COLLAPSE_NOTE_INTERVAL)
+    }
+
+    /// A note for the repeats left out so far, if any
+    fn take_note(&mut self) -> Option<LogLine> {
+        let latest = self.latest.take()?;
+        let left_out = std::mem::take(&mut self.left_out);
+        self.since = None;
+        let line = if self.all_last {
+            format!("last line repeated {}×", left_out)
+        } else {
+            format!("{} repeated lines left out", left_out)
+        };
+        Some(LogLine {
+            is_stderr: false,
+            line,
+            collapsed: Some(left_out),
+            ..latest
+        })
+    }
+}
+
+/// Output lines per project, oldest first
+pub struct LogStore {
+    lines: HashMap<String, VecDeque<LogLine>>,
+    live: Live,
+    /// Where stored lines go next: live subscribers and passthrough
+    sinks: Fanout,
+    /// Directory holding each project's data directory
+    root: PathBuf,
+    /// Log file of each process that printed since it last started
+    files: HashMap<Uuid, LogFile>,
+    limits: Limits,
+    /// Recent lines of each running process, when repeats are collapsed
+    repeats: Option<HashMap<Uuid, Repeats>>,
+    passthrough: Passthrough,
+}
+
+impl LogStore {
+    pub fn new(root: PathBuf, config: &Config) -> Self {
+        let live = Live::new(LIVE_CAPACITY);
+        let passthrough = Passthrough::new(config.output_passthrough);
+        let mut sinks = Fanout::default();
+        sinks.push(live.clone());
+        sinks.push(passthrough.clone());
+        Self {
+            lines: HashMap::new(),
+            live,
+            sinks,
+            root,
+            files: HashMap::new(),
+            limits: Limits::from_config(config),
+            repeats: config.log_collapse_repeats.then(HashMap::new),
+            passthrough,
+        }
+    }
+
+    /// Take up changed rotation, retention, collapsing and passthrough
+    /// settings
+    pub async fn apply_config(&mut self, config: &Config) {
+        self.limits = Limits::from_config(config);
+        self.passthrough.set_enabled(config.output_passthrough);
+        match (config.log_collapse_repeats, &mut self.repeats) {
+            (true, None) => self.repeats = Some(HashMap::new()),
+            (false, Some(repeats)) => {
+                let notes: Vec<LogLine> = repeats
+                    .values_mut()
+                    .filter_map(Repeats::take_note)
+                    .collect();
+                self.repeats = None;
+                for note in notes {
+                    self.store(note).await;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Directory holding each project's data directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Set whether a project's lines are echoed, or (with `None`) leave it to
+    /// the config
+    pub fn set_passthrough(&mut self, project_name: &str, passthrough: Option<bool>) {
+        self.passthrough.set_override(project_name, passthrough);
+    }
+
+    /// Take a line a process printed, unless it's a repeat being left out
+    pub async fn push(&mut self, line: LogLine) {
+        let Some(repeats) = &mut self.repeats else {
+            return self.store(line).await;
+        };
+        let repeats = repeats.entry(line.process_id).or_default();
+        let is_repeat = repeats.is_repeat(&line);
+        let note = if !is_repeat || repeats.is_due() {
+            repeats.take_note()
+        } else {
+            None
+        };
+        if !is_repeat {
+            repeats.remember(&line);
+        }
+        if let Some(note) = note {
+            self.store(note).await;
+        }
+        if !is_repeat {
+            self.store(line).await;
+        }
+    }
+
+    /// Store a line, append it to its process's log file and write it to
+    /// the sinks after the store
+    async fn store(&mut self, line: LogLine) {
+        let entries = self.lines.entry(line.project_name.clone()).or_default();
+        if entries.len() >= MAX_LINES_PER_PROJECT {
+            entries.pop_front();
+        }
+        entries.push_back(line.clone());
+
+        let file = self
+            .files
+            .entry(line.process_id)
+            .or_insert_with(|| LogFile::open(&self.root, &line.project_name, line.process_id));
+        if file.file.is_some() {
+            if let Err(e) = file.append(&line, self.limits) {
+                tracing::warn!("Not storing output of {}: {:#}", line.process_id, e);
+                file.file = None;
+            }
+        }
+
+        self.sinks.write(&line).await;
+    }
+
+    /// Close the log file of a process that exited, noting repeats left out
+    /// before it did; a restart appends to it
+    pub async fn close(&mut self, process_id: Uuid) {
+        let note = self
+            .repeats
+            .as_mut()
+            .and_then(|repeats| repeats.remove(&process_id))
+            .and_then(|mut repeats| repeats.take_note());
+        if let Some(note) = note {
+            self.store(note).await;
+        }
+        self.files.remove(&process_id);
+    }
+
+    /// A project's buffered lines, plus a receiver for every line after them
+    pub fn subscribe(&self, project_name: &str) -> (Vec<LogLine>, broadcast::Receiver<LogLine>) {
+        let backlog = self
+            .lines
+            .get(project_name)
+            .map(|lines| lines.iter().cloned().collect())
+            .unwrap_or_default();
+        (backlog, self.live.subscribe())
+    }
+
+    /// A process's buffered lines, oldest first
+    pub fn for_process(&self, project_name: &str, process_id: Uuid) -> Vec<LogLine> {
+        self.lines
+            .get(project_name)
+            .map(|lines| {
+                lines
+                    .iter()
+                    .filter(|line| line.process_id == process_id)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Delete the log files of processes not in `keep`, of one project or
+    /// all of them, returning how many files and bytes went
+    pub fn prune(&mut self, project_name: Option<&str>, keep: &HashSet<Uuid>) -> (usize, u64) {
+        self.files.retain(|id, file| {
+            keep.contains(id) || project_name.is_some_and(|name| file.project_name != name)
+        });
+        remove_files(&log_dirs(&self.root, project_name), |file| {
+            !keep.contains(&file.process_id)
+        })
+    }
+
+    /// Drop a deleted project's buffered output
+    pub fn forget_project(&mut self, project_name: &str) {
+        self.lines.remove(project_name);
+        self.passthrough.set_override(project_name, None);
+        self.files
+            .retain(|_, file| file.project_name != project_name);
+    }
+}
+
+/// A project's stored lines matching `query`, oldest first
+pub fn read(root: &Path, project_name: &str, query: &LogQuery) -> Result<Vec<LogLine>> {
+    let mut lines = Vec::new();
+    for stored in stored_files(&logs_dir(root, project_name))? {
+        // Nothing in a file last written before `since` can match
+        if query
+            .since
+            .is_some_and(|since| SystemTime::from(since) > stored.modified)
+        {
+            continue;
+        }
+        // Rotated away or pruned since it was listed
+        let Ok(file) = File::open(&stored.path) else {
+            continue;
+        };
+        for line in BufReader::new(file).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            match serde_json::from_str::<LogLine>(&line) {
+                Ok(line) if query.matches(&line) => lines.push(line),
+                _ => {}
+            }
+        }
+    }
+
+    // Files are read oldest rotation first, so this keeps each process's order
+    lines.sort_by_key(|line| line.timestamp);
+    if let Some(limit) = query.limit {
+        lines.drain(..lines.len().saturating_sub(limit));
+    }
+    Ok(lines)
+}
+
+/// Periodically delete log files that got no new line within the retention
+/// period. Current files of running processes are left alone.
+pub async fn expire(state: Arc<DaemonState>) {
+    let mut interval = tokio::time::interval(EXPIRE_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let (root, retention, open) = {
+            let logs = state.logs.lock().await;
+            let open: HashSet<Uuid> = logs.files.keys().copied().collect();
+            (logs.root.clone(), logs.limits.retention, open)
+        };
+        let Some(retention) = retention else {
+            continue;
+        };
+        let Some(cutoff) = SystemTime::now().checked_sub(retention) else {
+            continue;
+        };
+
+        let removed = tokio::task::spawn_blocking(move || {
+            remove_files(&log_dirs(&root, None), |file| {
+                file.modified < cutoff && (file.rotation > 0 || !open.contains(&file.process_id))
+            })
+        })
+        .await;
+        if let Ok((files, bytes)) = removed {
+            if files > 0 {
+                tracing::info!("Removed {} expired log files ({} bytes)", files, bytes);
+            }
+        }
+    }
+}
+
+/// A log file on disk
+struct StoredFile {
+    path: PathBuf,
+    process_id: Uuid,
+    /// 0 for the current file, n for `<id>.log.<n>`
+    rotation: usize,
+    modified: SystemTime,
+    size: u64,
+}
+
+fn logs_dir(root: &Path, project_name: &str) -> PathBuf {
+    root.join(project_name).join("logs")
+}
+
+/// Log directories of one project, or of every project with a data directory
+fn log_dirs(root: &Path, project_name: Option<&str>) -> Vec<PathBuf> {
+    match project_name {
+        Some(name) => vec![logs_dir(root, name)],
+        None => std::fs::read_dir(root)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path().join("logs"))
+                    .filter(|dir| dir.is_dir())
+                    .collect()
+            })
+            .unwrap_or_default(),
+    }
+}
+
+/// The log files in `dir`, each process's oldest rotation first
+fn stored_files(dir: &Path) -> Result<Vec<StoredFile>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+    let mut files: Vec<StoredFile> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let (process_id, rotation) = parse_file_name(entry.file_name().to_str()?)?;
+            let metadata = entry.metadata().ok()?;
+            Some(StoredFile {
+                path: entry.path(),
+                process_id,
+                rotation,
+                modified: metadata.modified().ok()?,
+                size: metadata.len(),
+            })
+        })
+        .collect();
+    files.sort_by_key(|file| (file.process_id, std::cmp::Reverse(file.rotation)));
+    Ok(files)
+}
+
+/// Process ID and rotation of a log file name: `<id>.log` or `<id>.log.<n>`
+fn parse_file_name(name: &str) -> Option<(Uuid, usize)> {
+    let (id, rest) = name.split_once(".log")?;
+    let rotation = match rest {
+        "" => 0,
+        rest => rest.strip_prefix('.')?.parse().ok()?,
+    };
+    Some((id.parse().ok()?, rotation))
+}
+
+/// Delete the log files in `dirs` that `remove` picks, returning how many
+/// files and bytes went
+fn remove_files(dirs: &[PathBuf], remove: impl Fn(&StoredFile) -> bool) -> (usize, u64) {
+    let (mut files, mut bytes) = (0, 0);
+    for dir in dirs {
+        let stored = match stored_files(dir) {
+            Ok(stored) => stored,
+            Err(e) => {
+                tracing::warn!("{:#}", e);
+                continue;
+            }
+        };
+        for file in stored.iter().filter(|file| remove(file)) {
+            match std::fs::remove_file(&file.path) {
+                Ok(()) => {
+                    files += 1;
+                    bytes += file.size;
+                }
+                Err(e) => tracing::warn!("Failed to remove {}: {}", file.path.display(), e),
+            }
+        }
+    }
+    (files, bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn line(project: &str, process_id: Uuid, n: usize) -> LogLine {
+        LogLine {
+            project_name: project.to_string(),
+            process_id,
+            service: None,
+            timestamp: Utc::now(),
+            is_stderr: false,
+            line: n.to_string(),
+            stage: None,
+            collapsed: None,
+        }
+    }
+
+    fn temp_root() -> PathBuf {
+        std::env::temp_dir().join(format!("proj-logs-{}", Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn test_keeps_recent_lines() {
+        let root = temp_root();
+        let mut store = LogStore::new(root.clone(), &Config::default());
+        for n in 0..MAX_LINES_PER_PROJECT + 5 {
+            store.push(line("app", Uuid::nil(), n)).await;
+        }
+        store.push(line("other", Uuid::nil(), 0)).await;
+        assert_eq!(store.for_process("other", Uuid::nil()).len(), 1);
+        assert!(store.for_process("other", Uuid::new_v4()).is_empty());
+
+        let (backlog, _) = store.subscribe("app");
+        assert_eq!(backlog.len(), MAX_LINES_PER_PROJECT);
+        assert_eq!(backlog[0].line, "5");
+
+        store.forget_project("app");
+        assert!(store.subscribe("app").0.is_empty());
+        assert_eq!(store.subscribe("other").0.len(), 1);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_log_files() {
+        let root = temp_root();
+        let config = Config {
+            log_max_bytes: 1000,
+            log_max_files: 2,
+            ..Config::default()
+        };
+        let mut store = LogStore::new(root.clone(), &config);
+        let (running, exited) = (Uuid::new_v4(), Uuid::new_v4());
+        for n in 0..100 {
+            store.push(line("app", running, n)).await;
+        }
+        store.push(line("app", exited, 0)).await;
+        store.close(exited).await;
+
+        // Two rotations kept; the oldest lines are gone with the third
+        let files = stored_files(&logs_dir(&root, "app")).unwrap();
+        let rotations: Vec<usize> = files
+            .iter()
+            .filter(|file| file.process_id == running)
+            .map(|file| file.rotation)
+            .collect();
+        assert_eq!(rotations, vec![2, 1, 0]);
+        assert!(files.iter().all(|file| file.size <= 1000));
+
+        let stored = read(&root, "app", &LogQuery::default()).unwrap();
+        assert!(stored.len() < 100);
+        assert_eq!(stored.last().unwrap().process_id, exited);
+        let numbers: Vec<usize> = stored
+            .iter()
+            .filter(|line| line.process_id == running)
+            .map(|line| line.line.parse().unwrap())
+            .collect();
+        assert_eq!(numbers.last(), Some(&99));
+        assert!(numbers.windows(2).all(|pair| pair[1] == pair[0] + 1));
+
+        let query = LogQuery {
+            grep: Some("9".to_string()),
+            limit: Some(3),
+            ..LogQuery::default()
+        };
+        let matching: Vec<String> = read(&root, "app", &query)
+            .unwrap()
+            .into_iter()
+            .map(|line| line.line)
+            .collect();
+        assert_eq!(matching, vec!["97", "98", "99"]);
+        assert!(read(&root, "missing", &LogQuery::default())
+            .unwrap()
+            .is_empty());
+
+        let (removed, bytes) = store.prune(Some("app"), &HashSet::from([running]));
+        assert_eq!(removed, 1);
+        assert!(bytes > 0);
+        assert!(read(&root, "app", &LogQuery::default())
+            .unwrap()
+            .iter()
+            .all(|line| line.process_id == running));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_collapse_repeats() {
+        let root = temp_root();
+        let config = Config {
+            log_collapse_repeats: true,
+            ..Config::default()
+        };
+        let mut store = LogStore::new(root.clone(), &config);
+        let (app, other) = (Uuid::new_v4(), Uuid::new_v4());
+        // A two-line banner printed on each of three reloads, and the same
+        // line from another process
+        for n in [1, 2, 1, 2, 1, 2, 3, 3, 3] {
+            store.push(line("app", app, n)).await;
+        }
+        store.push(line("app", other, 1)).await;
+        store.close(app).await;
+
+        let lines: Vec<(String, Option<u64>)> = store
+            .subscribe("app")
+            .0
+            .into_iter()
+            .map(|line| (line.line, line.collapsed))
+            .collect();
+        let expected = [
+            ("1", None),
+            ("2", None),
+            ("4 repeated lines left out", Some(4)),
+            ("3", None),
+            ("1", None),
+            ("last line repeated 2×", Some(2)),
+        ];
+        let expected: Vec<(String, Option<u64>)> = expected
+            .into_iter()
+            .map(|(line, collapsed)| (line.to_string(), collapsed))
+            .collect();
+        assert_eq!(lines, expected);
+        assert_eq!(read(&root, "app", &LogQuery::default()).unwrap().len(), 6);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_parse_file_name() {
+        let id = Uuid::new_v4();
+        assert_eq!(parse_file_name(&format!("{}.log", id)), Some((id, 0)));
+        assert_eq!(parse_file_name(&format!("{}.log.3", id)), Some((id, 3)));
+        assert_eq!(parse_file_name(&format!("{}.log.x", id)), None);
+        assert_eq!(parse_file_name("notes.log"), None);
+    }
+}
@@ -1,27 +1,91 @@
 //! proj-daemon - Background daemon for project management
 
+// Process control, port detection and the IPC socket have Unix and Windows
+// implementations only
+#[cfg(not(any(unix, windows)))]
+compile_error!("proj-daemon builds for Unix and Windows only");
+
+mod api;
+mod assets;
+mod auth;
+mod budgets;
+mod capture;
+mod drift;
+mod env;
+mod events;
+mod forward;
+mod graphql;
+mod health;
+mod heavy;
+mod hooks;
+mod idle;
+mod interfaces;
 mod ipc;
+mod limits;
+mod locks;
+mod logging;
+mod logs;
+mod maintenance;
+mod metrics;
+mod milestones;
+mod mock;
+mod notifications;
+mod outbound;
+mod output;
+mod panics;
+mod placeholders;
+mod platform;
+mod ports;
 mod process;
 mod proxy;
+mod queue;
+mod reaper;
+mod redirects;
 mod registry;
+mod remedies;
+mod remote;
+mod rewrites;
+mod routes;
+mod sandbox;
+mod scheduler;
+mod shedding;
+mod shutdown;
+mod smoke;
+mod stats;
+mod tls;
+mod tty;
+mod upstream;
+mod watcher;
 
 use anyhow::{Context, Result};
-use proj_common::{pid_file_path, proj_dir, socket_path};
+use proj_common::store::Store;
+use proj_common::transport::{Ipc, Transport};
+use proj_common::{pid_file_path, processes_path, proj_dir, projects_dir, socket_path, Config};
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing_subscriber::EnvFilter;
+use tokio::sync::watch;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize logging
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
-        )
-        .init();
+    logging::init();
+
+    // `--mock <scenario.json>` serves canned answers instead
+    let mut args = std::env::args().skip(1);
+    match (args.next().as_deref(), args.next(), args.next()) {
+        (None, _, _) => {}
+        (Some("--mock"), Some(scenario), None) => {
+            return mock::run(std::path::Path::new(&scenario)).await
+        }
+        _ => anyhow::bail!("Usage: proj-daemon [--mock <scenario.json>]"),
+    }
 
     tracing::info!("Starting proj-daemon");
 
+    let config = Config::load().unwrap_or_else(|e| {
+        tracing::warn!("Using default config: {}", e);
+        Config::default()
+    });
+
     // Ensure proj directory exists
     let proj_path = proj_dir()?;
     tokio::fs::create_dir_all(&proj_path)
@@ -37,56 +101,152 @@ async fn main() -> Result<()> {
 
     tracing::info!("Daemon PID: {} (written to {:?})", pid, pid_path);
 
-    // Create routing table for proxy
-    let routing_table = proxy::new_routing_table();
+    // Projects, process records and run history; files written before the
+    // store existed are brought over first
+    let store = Store::open_default()?;
+    let imported = store
+        .import_files(&projects_dir()?, &processes_path()?)
+        .context("Failed to import project files")?;
+    if !imported.projects.is_empty() || imported.processes > 0 {
+        tracing::info!(
+            "Imported {} project files and {} process records into the store",
+            imported.projects.len(),
+            imported.processes
+        );
+    }
+
+    // Settings that `proj daemon reload` (or SIGHUP) can change while running
+    let (config_tx, config_rx) = watch::channel(config.clone());
+
+    // Panics in the daemon's tasks are logged, kept for `proj status` and emitted
+    let events = events::EventBus::new();
+    panics::install(events.clone());
+
+    // Addresses of the machine, so LAN listeners follow network changes
+    let network = interfaces::spawn(events.clone(), config_rx.clone());
+
+    // Create routing and options tables for proxy
+    let appearances = proxy::new_appearance_table();
+    let proxy_ctx = proxy::ProxyContext {
+        routing_table: proxy::new_routing_table(),
+        starting: proxy::new_starting_set(),
+        options_table: proxy::new_proxy_options_table(),
+        aliases: proxy::new_alias_table(),
+        appearances,
+        tls: proxy::new_tls_acceptor_cell(),
+        captures: capture::CaptureStore::new(),
+        drift: drift::DriftStore::new(),
+        budgets: budgets::BudgetTracker::new(events.clone()),
+        assets: assets::AssetStore::new(),
+        auth: auth::AuthStore::new(),
+        graphql: graphql::GraphqlStats::new(),
+        metrics: metrics::ProxyMetrics::new(),
+        activity: proxy::new_activity_table(),
+        bind_error: proxy::new_bind_error_cell(),
+        shedder: shedding::LoadShedder::new(),
+        upstream: upstream::Upstream::new(config_rx.clone()),
+        network,
+        api: None,
+        starter: None,
+        config: config_rx,
+    };
+
+    // Take the event receiver before the process manager moves to its task
+    let mut process_manager = process::ProcessManager::new(&config, events, store.clone());
+    let event_rx = process_manager.take_event_receiver();
 
     // Create shared daemon state
-    let state = Arc::new(Mutex::new(
-        ipc::DaemonState::new(routing_table.clone()).await?,
-    ));
-
-    // Take the event receiver from process manager
-    let event_rx = {
-        let mut s = state.lock().await;
-        s.process_manager.take_event_receiver()
+    let state =
+        Arc::new(ipc::DaemonState::new(process_manager, store, &proxy_ctx, config_tx).await?);
+
+    // The dashboard reads and acts on the state it was built from
+    let proxy_ctx = proxy::ProxyContext {
+        api: Some(api::Api::new(state.clone())),
+        starter: Some(idle::LazyStarter::new(state.clone())),
+        ..proxy_ctx
     };
 
+    // Crash, budget and panic notifications, to the configured backends
+    panics::spawn(
+        "notifications",
+        notifications::run(
+            state.events.clone(),
+            proxy_ctx.config.clone(),
+            state.appearances.clone(),
+            state.dnd.clone(),
+        ),
+    );
+
     // Start event handler
     if let Some(rx) = event_rx {
         let state_clone = state.clone();
-        tokio::spawn(async move {
+        panics::spawn("event handler", async move {
             ipc::process_event_handler(state_clone, rx).await;
         });
     }
 
+    // Start and stop projects with maintenance windows
+    panics::spawn("scheduler", scheduler::run(state.clone()));
+    panics::spawn("idle shutdown", idle::run(state.clone()));
+
+    // Sample CPU and memory of running processes
+    panics::spawn("stats", stats::run(state.clone()));
+
+    // Delete log files past their retention period
+    panics::spawn("log expiry", logs::expire(state.clone()));
+
+    // Drop old records of exited processes and fix stale routes
+    panics::spawn("reaper", reaper::run(state.clone()));
+
+    // Clean up exited processes, browser caches and the database daily
+    panics::spawn("maintenance", maintenance::run(state.clone()));
+
     // Get socket path
     let socket = socket_path()?;
 
     // Start IPC server and proxy in parallel
     let ipc_state = state.clone();
-    let ipc_handle = tokio::spawn(async move {
+    let ipc_handle = panics::spawn("ipc server", async move {
         if let Err(e) = ipc::start_ipc_server(&socket, ipc_state).await {
             tracing::error!("IPC server error: {}", e);
         }
     });
 
-    // Default proxy port
-    let proxy_port = 8080;
-    let proxy_handle = tokio::spawn(async move {
-        if let Err(e) = proxy::start_proxy(proxy_port, routing_table).await {
+    // Authenticated TCP clients, when `listen_tcp` is set
+    panics::spawn("remote control", remote::run(state.clone()));
+
+    // HTTP and SOCKS5 proxy for other devices, when `forward_proxy` is set
+    panics::spawn("forward proxy", forward::run(proxy_ctx.clone()));
+
+    // HTTP and HTTPS listeners, rebound when their addresses change
+    let proxy_handle = panics::spawn("proxy", async move {
+        if let Err(e) = proxy::run(proxy_ctx).await {
             tracing::error!("Proxy error: {}", e);
         }
     });
 
+    #[cfg(unix)]
+    tokio::spawn(reload_on_sighup(state.clone()));
+
     tracing::info!("Daemon ready");
     tracing::info!("  IPC socket: {:?}", socket_path()?);
-    tracing::info!("  Proxy: http://127.0.0.1:{}", proxy_port);
+    tracing::info!("  Projects: {}", config.url("<name>"));
 
     // Wait for shutdown signal
+    let mut shutdown_rx = state.shutdown.subscribe();
+    let terminated = terminated()?;
+    let mut stop_processes = None;
     tokio::select! {
         _ = tokio::signal::ctrl_c() => {
             tracing::info!("Received Ctrl+C, shutting down");
         }
+        _ = terminated => {
+            tracing::info!("Asked to terminate, shutting down");
+        }
+        requested = shutdown_rx.recv() => {
+            tracing::info!("Shutdown requested, shutting down");
+            stop_processes = requested.ok().flatten();
+        }
         _ = ipc_handle => {
             tracing::error!("IPC server exited unexpectedly");
         }
@@ -95,17 +255,61 @@ async fn main() -> Result<()> {
         }
     }
 
+    let on_exit = state.config.borrow().on_daemon_exit;
+    shutdown::stop_processes(&state, on_exit, stop_processes).await;
+
     // Cleanup
     let pid_path = pid_file_path()?;
     if pid_path.exists() {
         let _ = tokio::fs::remove_file(&pid_path).await;
     }
 
-    let socket = socket_path()?;
-    if socket.exists() {
-        let _ = tokio::fs::remove_file(&socket).await;
-    }
+    Ipc::unbind(&socket_path()?);
 
     tracing::info!("Daemon stopped");
     Ok(())
 }
+
+/// Reload config.json whenever the daemon gets SIGHUP
+#[cfg(unix)]
+async fn reload_on_sighup(state: Arc<ipc::DaemonState>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let Ok(mut hangups) = signal(SignalKind::hangup()) else {
+        tracing::warn!("Failed to handle SIGHUP; use `proj daemon reload` instead");
+        return;
+    };
+    while hangups.recv().await.is_some() {
+        match state.reload_config().await {
+            Ok(changes) if changes.is_empty() => tracing::info!("SIGHUP: config unchanged"),
+            Ok(changes) => tracing::info!("SIGHUP: reloaded {}", changes.join(", ")),
+            Err(e) => tracing::error!("SIGHUP: failed to reload config: {:#}", e),
+        }
+    }
+}
+
+/// Resolves when the OS asks the daemon to exit: SIGTERM on Unix, logoff or
+/// shutdown on Windows
+#[cfg(unix)]
+fn terminated() -> Result<impl std::future::Future<Output = ()>> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigterm = signal(SignalKind::terminate()).context("Failed to handle SIGTERM")?;
+    Ok(async move {
+        sigterm.recv().await;
+    })
+}
+
+#[cfg(windows)]
+fn terminated() -> Result<impl std::future::Future<Output = ()>> {
+    use tokio::signal::windows::{ctrl_close, ctrl_shutdown};
+
+    let mut close = ctrl_close().context("Failed to handle console close")?;
+    let mut shutdown = ctrl_shutdown().context("Failed to handle system shutdown")?;
+    Ok(async move {
+        tokio::select! {
+            _ = close.recv() => {}
+            _ = shutdown.recv() => {}
+        }
+    })
+}
@@ -0,0 +1,258 @@
+//! Scheduled maintenance - at the `maintenance_at` times of day the daemon
+//! cleans up after itself: records and log files of exited processes (what
+//! `proj prune` does), caches of project browser profiles that aren't open,
+//! old project backups in the database, old trash entries, and proxy
+//! statistics of projects that are gone. A summary goes out as a
+//! `maintenance` event.
+//!
+//! The clock is checked every minute rather than slept on, so a time missed
+//! while the machine was asleep is caught up on when it wakes.
+
+use chrono::{Local, NaiveDateTime, NaiveTime, Utc};
+use proj_common::{project_dir, trash, EventKind, MaintenanceReport};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::time::{Duration, Instant, MissedTickBehavior};
+
+use crate::ipc::DaemonState;
+
+/// How often the clock is checked
+const TICK: Duration = Duration::from_secs(60);
+
+/// Age at which the copies kept of upgraded projects are dropped
+const BACKUP_RETENTION_DAYS: i64 = 30;
+
+/// Directories of Chromium-based browsers' profiles in a project's data
+/// directory (see `proj open`)
+const CHROMIUM_PROFILES: [&str; 4] = ["chrome", "chromium", "brave", "edge"];
+
+/// Caches in a Chromium user data directory, rebuilt as needed
+const USER_DATA_CACHES: [&str; 3] = ["GrShaderCache", "ShaderCache", "GraphiteDawnCache"];
+
+/// Caches in each profile of a user data directory
+const PROFILE_CACHES: [&str; 4] = ["Cache", "Code Cache", "GPUCache", "DawnWebGPUCache"];
+
+/// Run a maintenance pass whenever one of the configured times goes by
+pub async fn run(state: Arc<DaemonState>) {
+    let mut interval = tokio::time::interval(TICK);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut checked = Local::now().naive_local();
+    loop {
+        interval.tick().await;
+        let now = Local::now().naive_local();
+        let due = is_due(checked, now, &state.config.borrow().maintenance_at);
+        checked = now;
+        if due {
+            run_once(&state).await;
+        }
+    }
+}
+
+/// Clean up now, emitting and returning what was done
+pub async fn run_once(state: &DaemonState) -> MaintenanceReport {
+    let started = Instant::now();
+    let mut report = MaintenanceReport::default();
+
+    (report.processes, report.log_files, report.log_bytes) = state.prune(None).await;
+
+    let projects: HashSet<String> = state
+        .registry
+        .read()
+        .await
+        .list()
+        .into_iter()
+        .map(|project| project.name.clone())
+        .collect();
+    let names = projects.clone();
+    match tokio::task::spawn_blocking(move || trim_profiles(&names)).await {
+        Ok((profiles, bytes)) => (report.profiles, report.profile_bytes) = (profiles, bytes),
+        Err(e) => report.errors.push(format!("Browser caches: {}", e)),
+    }
+
+    let store = state.store.clone();
+    let cutoff = Utc::now() - chrono::Duration::days(BACKUP_RETENTION_DAYS);
+    match tokio::task::spawn_blocking(move || store.compact(cutoff)).await {
+        Ok(Ok(compacted)) => report.store_bytes = compacted.bytes,
+        Ok(Err(e)) => report.errors.push(format!("Database: {:#}", e)),
+        Err(e) => report.errors.push(format!("Database: {}", e)),
+    }
+
+    let cutoff = Utc::now() - chrono::Duration::days(trash::RETENTION_DAYS);
+    match tokio::task::spawn_blocking(move || trash::empty(Some(cutoff))).await {
+        Ok(Ok((entries, bytes))) => (report.trash, report.trash_bytes) = (entries, bytes),
+        Ok(Err(e)) => report.errors.push(format!("Trash: {:#}", e)),
+        Err(e) => report.errors.push(format!("Trash: {}", e)),
+    }
+
+    report.stale_metrics = state.metrics.retain(&projects).await;
+    report.duration_ms = started.elapsed().as_millis() as u64;
+
+    tracing::info!("Maintenance {}", report);
+    for error in &report.errors {
+        tracing::warn!("Maintenance step failed: {}", error);
+    }
+    state
+        .events
+        .emit("", EventKind::Maintenance(report.clone()));
+    report
+}
+
+/// Whether one of `times` fell after `checked` and up to `now`, on any day
+/// in between
+fn is_due(checked: NaiveDateTime, now: NaiveDateTime, times: &[NaiveTime]) -> bool {
+    checked
+        .date()
+        .iter_days()
+        .take_while(|date| *date <= now.date())
+        .any(|date| {
+            times.iter().any(|&time| {
+                let at = date.and_time(time);
+                checked < at && at <= now
+            })
+        })
+}
+
+/// Clear the caches of the projects' Chromium profiles that aren't open,
+/// returning how many were trimmed and the space freed
+fn trim_profiles(projects: &HashSet<String>) -> (usize, u64) {
+    let (mut profiles, mut bytes) = (0, 0);
+    for project in projects {
+        let Ok(dir) = project_dir(project) else {
+            continue;
+        };
+        for browser in CHROMIUM_PROFILES {
+            match trim_user_data(&dir.join(browser)) {
+                Some(0) | None => {}
+                Some(freed) => {
+                    profiles += 1;
+                    bytes += freed;
+                }
+            }
+        }
+    }
+    (profiles, bytes)
+}
+
+/// Clear the caches of a Chromium user data directory, returning the space
+/// freed; `None` when there is none or the browser has it open
+fn trim_user_data(user_data: &Path) -> Option<u64> {
+    if !user_data.is_dir() {
+        return None;
+    }
+    // Chromium holds these while it runs: a symlink on Unix, a file on Windows
+    let in_use = ["SingletonLock", "lockfile"]
+        .iter()
+        .any(|lock| user_data.join(lock).symlink_metadata().is_ok());
+    if in_use {
+        return None;
+    }
+
+    let mut freed: u64 = USER_DATA_CACHES
+        .iter()
+        .map(|cache| remove_dir(&user_data.join(cache)))
+        .sum();
+    for entry in std::fs::read_dir(user_data).ok()?.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name == "Default" || name.starts_with("Profile ") {
+            freed += PROFILE_CACHES
+                .iter()
+                .map(|cache| remove_dir(&entry.path().join(cache)))
+                .sum::<u64>();
+        }
+    }
+    Some(freed)
+}
+
+/// Delete a directory tree, returning the size of the files in it; 0 when it
+/// doesn't exist or couldn't be deleted
+fn remove_dir(path: &Path) -> u64 {
+    let size = dir_size(path);
+    match std::fs::remove_dir_all(path) {
+        Ok(()) => size,
+        Err(_) => 0,
+    }
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(kind) if kind.is_dir() => dir_size(&entry.path()),
+            Ok(_) => entry.metadata().map_or(0, |meta| meta.len()),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_is_due() {
+        let times = [NaiveTime::from_hms_opt(4, 0, 0).unwrap()];
+        assert!(is_due(
+            at("2026-10-16 03:59"),
+            at("2026-10-16 04:00"),
+            &times
+        ));
+        assert!(!is_due(
+            at("2026-10-16 04:00"),
+            at("2026-10-16 04:01"),
+            &times
+        ));
+        assert!(!is_due(
+            at("2026-10-16 04:01"),
+            at("2026-10-16 23:59"),
+            &times
+        ));
+        // Asleep over the time
+        assert!(is_due(
+            at("2026-10-15 23:00"),
+            at("2026-10-16 09:30"),
+            &times
+        ));
+        assert!(is_due(
+            at("2026-10-12 12:00"),
+            at("2026-10-16 01:00"),
+            &times
+        ));
+        assert!(!is_due(at("2026-10-16 03:00"), at("2026-10-16 03:01"), &[]));
+    }
+
+    #[test]
+    fn test_trim_user_data() {
+        let dir = std::env::temp_dir().join(format!("proj-maintenance-{}", uuid::Uuid::new_v4()));
+        let write = |path: &str, size: usize| {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, vec![0; size]).unwrap();
+        };
+        write("ShaderCache/data_0", 100);
+        write("Default/Cache/Cache_Data/index", 50);
+        write("Profile 1/Code Cache/js/index", 25);
+        write("Default/Cookies", 10);
+
+        assert_eq!(trim_user_data(&dir), Some(175));
+        assert!(!dir.join("Default/Cache").exists());
+        assert!(dir.join("Default/Cookies").exists());
+        assert_eq!(trim_user_data(&dir), Some(0));
+
+        write("Default/Cache/index", 5);
+        write("lockfile", 0);
+        assert_eq!(trim_user_data(&dir), None);
+        assert!(dir.join("Default/Cache/index").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(trim_user_data(&dir), None);
+    }
+}
@@ -0,0 +1,224 @@
+//! Proxy metrics - request counters and latency histograms in Prometheus format
+
+use proj_common::ProxyTraffic;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio::time::Duration;
+
+/// Upper bounds (seconds) of the latency histogram buckets
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Counters for one project
+#[derive(Default)]
+struct ProjectMetrics {
+    /// Responses by status code; `None` when no response could be produced
+    responses: BTreeMap<Option<u16>, u64>,
+    /// 5xx responses and requests that failed outright
+    errors: u64,
+    /// Non-cumulative counts per bucket, plus one for slower requests
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    latency_sum: f64,
+}
+
+/// Proxy traffic counters, shared by every connection
+#[derive(Clone, Default)]
+pub struct ProxyMetrics {
+    projects: Arc<RwLock<HashMap<String, ProjectMetrics>>>,
+    connections: Arc<AtomicI64>,
+}
+
+/// Counts a client connection as active until dropped
+pub struct ConnectionGuard(Arc<AtomicI64>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl ProxyMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Track an accepted client connection
+    pub fn connection(&self) -> ConnectionGuard {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard(self.connections.clone())
+    }
+
+    /// Record a request to a project; `latency` is the time to response headers
+    pub async fn record(&self, project_name: &str, status: Option<u16>, latency: Duration) {
+        let secs = latency.as_secs_f64();
+        let mut projects = self.projects.write().await;
+        let metrics = projects.entry(project_name.to_string()).or_default();
+        *metrics.responses.entry(status).or_default() += 1;
+        if status.is_none_or(|s| s >= 500) {
+            metrics.errors += 1;
+        }
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|&bound| secs <= bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        metrics.buckets[bucket] += 1;
+        metrics.latency_sum += secs;
+    }
+
+    /// Request and error counts of a project
+    pub async fn traffic(&self, project_name: &str) -> ProxyTraffic {
+        match self.projects.read().await.get(project_name) {
+            Some(metrics) => ProxyTraffic {
+                requests: metrics.responses.values().sum(),
+                errors: metrics.errors,
+            },
+            None => ProxyTraffic::default(),
+        }
+    }
+
+    /// Drop a deleted project's counters
+    pub async fn clear(&self, project_name: &str) {
+        self.projects.write().await.remove(project_name);
+    }
+
+    /// Forget the counters of projects not in `projects`, returning how many
+    pub async fn retain(&self, projects: &HashSet<String>) -> usize {
+        let mut metrics = self.projects.write().await;
+        let before = metrics.len();
+        metrics.retain(|name, _| projects.contains(name));
+        before - metrics.len()
+    }
+
+    /// All metrics in the Prometheus text exposition format
+    pub async fn render(&self) -> String {
+        let projects = self.projects.read().await;
+        let mut names: Vec<&String> = projects.keys().collect();
+        names.sort();
+
+        let mut out = String::new();
+        out.push_str(
+            "# HELP proj_proxy_requests_total Requests proxied to a project, by response status.\n",
+        );
+        out.push_str("# TYPE proj_proxy_requests_total counter\n");
+        for name in &names {
+            for (status, count) in &projects[*name].responses {
+                let status = status.map_or("error".to_string(), |s| s.to_string());
+                let _ = writeln!(
+                    out,
+                    "proj_proxy_requests_total{{project=\"{}\",status=\"{}\"}} {}",
+                    escape(name),
+                    status,
+                    count
+                );
+            }
+        }
+
+        out.push_str(
+            "# HELP proj_proxy_request_errors_total Requests that failed or got a 5xx response.\n",
+        );
+        out.push_str("# TYPE proj_proxy_request_errors_total counter\n");
+        for name in &names {
+            let _ = writeln!(
+                out,
+                "proj_proxy_request_errors_total{{project=\"{}\"}} {}",
+                escape(name),
+                projects[*name].errors
+            );
+        }
+
+        out.push_str("# HELP proj_proxy_request_duration_seconds Time until response headers.\n");
+        out.push_str("# TYPE proj_proxy_request_duration_seconds histogram\n");
+        for name in &names {
+            let metrics = &projects[*name];
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(&metrics.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "proj_proxy_request_duration_seconds_bucket{{project=\"{}\",le=\"{}\"}} {}",
+                    escape(name),
+                    bound,
+                    cumulative
+                );
+            }
+            let total: u64 = metrics.buckets.iter().sum();
+            let _ = writeln!(
+                out,
+                "proj_proxy_request_duration_seconds_bucket{{project=\"{}\",le=\"+Inf\"}} {}",
+                escape(name),
+                total
+            );
+            let _ = writeln!(
+                out,
+                "proj_proxy_request_duration_seconds_sum{{project=\"{}\"}} {}",
+                escape(name),
+                metrics.latency_sum
+            );
+            let _ = writeln!(
+                out,
+                "proj_proxy_request_duration_seconds_count{{project=\"{}\"}} {}",
+                escape(name),
+                total
+            );
+        }
+
+        out.push_str("# HELP proj_proxy_active_connections Open client connections.\n");
+        out.push_str("# TYPE proj_proxy_active_connections gauge\n");
+        let _ = writeln!(
+            out,
+            "proj_proxy_active_connections {}",
+            self.connections.load(Ordering::Relaxed)
+        );
+        out
+    }
+}
+
+/// Escape a Prometheus label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_render() {
+        let metrics = ProxyMetrics::new();
+        let _connection = metrics.connection();
+        metrics
+            .record("app", Some(200), Duration::from_millis(3))
+            .await;
+        metrics
+            .record("app", Some(502), Duration::from_millis(30))
+            .await;
+        metrics.record("app", None, Duration::from_secs(20)).await;
+
+        let text = metrics.render().await;
+        assert!(text.contains("proj_proxy_requests_total{project=\"app\",status=\"200\"} 1"));
+        assert!(text.contains("proj_proxy_requests_total{project=\"app\",status=\"error\"} 1"));
+        assert!(text.contains("proj_proxy_request_errors_total{project=\"app\"} 2"));
+        assert!(text.contains("le=\"0.005\"} 1"));
+        assert!(text.contains("le=\"0.05\"} 2"));
+        assert!(text.contains("le=\"10\"} 2"));
+        assert!(text.contains("le=\"+Inf\"} 3"));
+        assert!(text.contains("proj_proxy_active_connections 1"));
+        assert_eq!(
+            metrics.traffic("app").await,
+            ProxyTraffic {
+                requests: 3,
+                errors: 2
+            }
+        );
+
+        metrics.clear("app").await;
+        assert!(!metrics.render().await.contains("app"));
+    }
+}
@@ -0,0 +1,114 @@
+//! Startup milestones recognized in process output
+//!
+//! Dev servers announce that they compiled, opened their port and are ready
+//! to serve, each framework in its own words. Lines are matched without color
+//! codes and ignoring case against the phrases below; the first line reaching
+//! each milestone times it from the start of the run. A detected port counts
+//! as listening too, so a server printing nothing recognizable still gets a
+//! startup time.
+
+use proj_common::Milestone;
+
+/// Phrases marking a milestone; a line matches an entry containing all of
+/// its parts, in any order
+const PATTERNS: &[(Milestone, &[&str])] = &[
+    // webpack, Create React App, Next.js, Angular
+    (Milestone::Compiled, &["compiled successfully"]),
+    (Milestone::Compiled, &["webpack", "compiled"]),
+    (Milestone::Compiled, &["✓ compiled"]),
+    // tsc --watch
+    (
+        Milestone::Compiled,
+        &["found 0 errors", "watching for file changes"],
+    ),
+    // cargo
+    (Milestone::Compiled, &["finished", "target(s) in"]),
+    // Vite, Next.js, Express-style servers, Puma
+    (Milestone::Listening, &["local:", "http"]),
+    (Milestone::Listening, &["listening on"]),
+    (Milestone::Listening, &["listening at"]),
+    (Milestone::Listening, &["server running at"]),
+    // uvicorn, Flask, Django, Spring Boot
+    (Milestone::Listening, &["uvicorn running on"]),
+    (Milestone::Listening, &["running on http"]),
+    (Milestone::Listening, &["starting development server at"]),
+    (Milestone::Listening, &["tomcat started on port"]),
+    // Vite, Next.js 13+, Next.js 12
+    (Milestone::Ready, &["ready in"]),
+    (Milestone::Ready, &["ready - started server"]),
+    // uvicorn, Create React App, Django, Puma, Spring Boot
+    (Milestone::Ready, &["application startup complete"]),
+    (Milestone::Ready, &["you can now view", "in the browser"]),
+    (Milestone::Ready, &["quit the server with"]),
+    (Milestone::Ready, &["use ctrl-c to stop"]),
+    (Milestone::Ready, &["started ", " in ", " seconds"]),
+];
+
+/// The milestone a line of output announces, if any
+pub fn detect(line: &str) -> Option<Milestone> {
+    let line = strip_ansi(line).to_lowercase();
+    PATTERNS
+        .iter()
+        .find(|(_, parts)| parts.iter().all(|part| line.contains(part)))
+        .map(|(milestone, _)| *milestone)
+}
+
+/// A line without its terminal escape sequences
+fn strip_ansi(line: &str) -> String {
+    let mut plain = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            plain.push(c);
+            continue;
+        }
+        // A CSI sequence runs to its final byte; other escapes are two characters
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+    plain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let cases = [
+            (
+                "\x1b[32m\x1b[1mVITE\x1b[22m v5.0.0\x1b[39m  \x1b[2mready in \x1b[0m\x1b[1m312\x1b[22m\x1b[2m ms\x1b[22m",
+                Some(Milestone::Ready),
+            ),
+            ("  ➜  Local:   http://localhost:5173/", Some(Milestone::Listening)),
+            (" ✓ Ready in 2.1s", Some(Milestone::Ready)),
+            (" ✓ Compiled /page in 1.4s (512 modules)", Some(Milestone::Compiled)),
+            ("webpack 5.88.0 compiled successfully in 812 ms", Some(Milestone::Compiled)),
+            (
+                "    Finished `dev` profile [unoptimized + debuginfo] target(s) in 9.13s",
+                Some(Milestone::Compiled),
+            ),
+            (
+                "INFO:     Uvicorn running on http://127.0.0.1:8000 (Press CTRL+C to quit)",
+                Some(Milestone::Listening),
+            ),
+            ("INFO:     Application startup complete.", Some(Milestone::Ready)),
+            ("Server listening on port 3000", Some(Milestone::Listening)),
+            ("Quit the server with CONTROL-C.", Some(Milestone::Ready)),
+            (
+                "Started DemoApplication in 3.214 seconds (process running for 3.6)",
+                Some(Milestone::Ready),
+            ),
+            ("GET /api/users 200 12ms", None),
+            ("Compiling proj v0.1.0", None),
+        ];
+        for (line, expected) in cases {
+            assert_eq!(detect(line), expected, "{:?}", line);
+        }
+    }
+}
@@ -0,0 +1,461 @@
+//! Simulation mode: `proj-daemon --mock <scenario.json>`
+//!
+//! Serves a scenario's projects, processes, logs and events over the IPC
+//! socket without spawning anything, starting the proxy or opening the
+//! store, so clients (the TUI, editor integrations, plugins, CLI snapshot
+//! tests) can be tested against a daemon that always answers the same way.
+//! A scenario looks like:
+//!
+//! ```json
+//! {
+//!   "projects": [{"name": "web", "root_dir": "/src/web", "tags": ["frontend"]}],
+//!   "processes": [{"project_name": "web", "pid": 4242, "command": "npm run dev", "port": 4000}],
+//!   "logs": [{"project_name": "web", "line": "ready in 300ms"}],
+//!   "events": [{"after_ms": 500, "project_name": "web", "event": "route_added", "port": 4000}],
+//!   "responses": {"list_ports": {"type": "ports", "data": []}}
+//! }
+//! ```
+//!
+//! Projects, processes and log lines take the fields stored ones have; IDs
+//! left out count up from 1, timestamps default to when the mock started and
+//! processes to running. Every subscriber gets the events replayed, each
+//! `after_ms` after it subscribed. `responses` answers requests by type,
+//! ahead of the built-in answers; any other request gets an error saying the
+//! mock doesn't simulate it.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use proj_common::transport::{Connection, Ipc, Transport};
+use proj_common::{
+    socket_path, Event, IpcError, IpcRequest, IpcResponse, LogLine, ProcessInfo, Project,
+    PROTOCOL_VERSION, VERSION,
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::{broadcast, Notify};
+use tokio::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::{ipc, panics};
+
+/// A scenario as written
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScenarioFile {
+    #[serde(default)]
+    projects: Vec<Map<String, Value>>,
+    #[serde(default)]
+    processes: Vec<Map<String, Value>>,
+    #[serde(default)]
+    logs: Vec<Map<String, Value>>,
+    #[serde(default)]
+    events: Vec<Map<String, Value>>,
+    #[serde(default)]
+    responses: HashMap<String, IpcResponse>,
+}
+
+/// What the mock daemon answers with
+pub struct Scenario {
+    projects: Vec<Project>,
+    processes: Vec<ProcessInfo>,
+    logs: Vec<LogLine>,
+    /// Events with their delay after subscribing; the timestamp is filled
+    /// in when they're sent unless the scenario sets it
+    events: Vec<(Duration, Map<String, Value>)>,
+    responses: HashMap<String, IpcResponse>,
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&json, Utc::now())
+            .with_context(|| format!("Invalid scenario {}", path.display()))
+    }
+
+    fn parse(json: &str, now: DateTime<Utc>) -> Result<Self> {
+        let file: ScenarioFile = serde_json::from_str(json)?;
+
+        let projects = file
+            .projects
+            .into_iter()
+            .enumerate()
+            .map(|(i, project)| {
+                let defaults = [("id", id(i)), ("created_at", timestamp(now))];
+                serde_json::from_value(with_defaults(project, defaults))
+                    .with_context(|| format!("Project {}", i + 1))
+            })
+            .collect::<Result<Vec<Project>>>()?;
+
+        let processes = file
+            .processes
+            .into_iter()
+            .enumerate()
+            .map(|(i, process)| {
+                let defaults = [
+                    ("id", id(i)),
+                    ("started_at", timestamp(now)),
+                    ("status", Value::from("running")),
+                ];
+                serde_json::from_value(with_defaults(process, defaults))
+                    .with_context(|| format!("Process {}", i + 1))
+            })
+            .collect::<Result<Vec<ProcessInfo>>>()?;
+
+        let logs = file
+            .logs
+            .into_iter()
+            .enumerate()
+            .map(|(i, line)| {
+                // Lines belong to their project's first process unless they say
+                let process_id = processes
+                    .iter()
+                    .find(|p| Some(p.project_name.as_str()) == line["project_name"].as_str())
+                    .map_or(Uuid::nil(), |p| p.id);
+                let defaults = [
+                    ("process_id", Value::from(process_id.to_string())),
+                    ("timestamp", timestamp(now)),
+                ];
+                serde_json::from_value(with_defaults(line, defaults))
+                    .with_context(|| format!("Log line {}", i + 1))
+            })
+            .collect::<Result<Vec<LogLine>>>()?;
+
+        let events = file
+            .events
+            .into_iter()
+            .enumerate()
+            .map(|(i, mut event)| {
+                let after_ms = match event.remove("after_ms") {
+                    None => 0,
+                    Some(after_ms) => after_ms
+                        .as_u64()
+                        .with_context(|| format!("Event {}: after_ms isn't a number", i + 1))?,
+                };
+                // Checked now rather than when a client is waiting for it
+                to_event(event.clone()).with_context(|| format!("Event {}", i + 1))?;
+                Ok((Duration::from_millis(after_ms), event))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            projects,
+            processes,
+            logs,
+            events,
+            responses: file.responses,
+        })
+    }
+
+    /// The answer to a request that doesn't stream
+    fn answer(&self, request: IpcRequest) -> IpcResponse {
+        let request_type = request_type(&request);
+        if let Some(response) = self.responses.get(&request_type) {
+            return response.clone();
+        }
+        let not_found = |name: &str| {
+            IpcResponse::Error(IpcError::ProjectNotFound {
+                name: name.to_string(),
+            })
+        };
+        match request {
+            IpcRequest::Hello { .. } => IpcResponse::Hello {
+                protocol_version: PROTOCOL_VERSION,
+                daemon_version: VERSION.to_string(),
+                keeps_connection: false,
+            },
+            IpcRequest::Status => IpcResponse::Status {
+                running: true,
+                project_count: self.projects.len(),
+                process_count: self.processes.iter().filter(|p| p.status.is_alive()).count(),
+                protocol_version: PROTOCOL_VERSION,
+                daemon_version: VERSION.to_string(),
+                panics: Vec::new(),
+                proxy_error: None,
+                git_hash: None,
+                started_at: None,
+                proxy_addr: None,
+                socket_path: None,
+                route_count: 0,
+                subscriber_count: 0,
+                lan_urls: Vec::new(),
+            },
+            IpcRequest::ListProjects { filter } => {
+                IpcResponse::Projects(filter.apply(self.projects.clone(), |name| self.running(name)))
+            }
+            IpcRequest::ProjectsWithProcesses { filter } => IpcResponse::ProjectSummaries(
+                filter.summaries(self.projects.clone(), &self.processes),
+            ),
+            IpcRequest::GetProject { name } => match self.project(&name) {
+                Some(project) => IpcResponse::Project(project.clone()),
+                None => not_found(&name),
+            },
+            IpcRequest::ListProcesses { project_name } => IpcResponse::Processes(
+                self.processes
+                    .iter()
+                    .filter(|p| project_name.as_ref().is_none_or(|name| p.project_name == *name))
+                    .cloned()
+                    .collect(),
+            ),
+            IpcRequest::GetLogs {
+                project_name,
+                query,
+            } => {
+                if self.project(&project_name).is_none() {
+                    return not_found(&project_name);
+                }
+                let lines: Vec<LogLine> = self
+                    .project_logs(&project_name)
+                    .filter(|line| query.matches(line))
+                    .collect();
+                let skip = query.limit.map_or(0, |limit| lines.len().saturating_sub(limit));
+                IpcResponse::Logs(lines.into_iter().skip(skip).collect())
+            }
+            _ => IpcResponse::Error(IpcError::InvalidRequest {
+                message: format!(
+                    "The mock daemon doesn't simulate '{}'; answer it under \"responses\" in the scenario",
+                    request_type
+                ),
+            }),
+        }
+    }
+
+    fn project(&self, name: &str) -> Option<&Project> {
+        self.projects.iter().find(|project| project.name == name)
+    }
+
+    fn running(&self, project_name: &str) -> bool {
+        self.processes
+            .iter()
+            .any(|p| p.project_name == project_name && p.status.is_alive())
+    }
+
+    fn project_logs<'a>(&'a self, project_name: &'a str) -> impl Iterator<Item = LogLine> + 'a {
+        self.logs
+            .iter()
+            .filter(move |line| line.project_name == project_name)
+            .cloned()
+    }
+}
+
+/// Serve a scenario on the IPC socket until asked to shut down
+pub async fn run(path: &Path) -> Result<()> {
+    let scenario = Arc::new(Scenario::load(path)?);
+    let socket = socket_path()?;
+    if let Some(dir) = socket.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .context("Failed to create proj directory")?;
+    }
+    let mut listener = Ipc::bind(&socket).context("Failed to bind IPC socket")?;
+    tracing::info!(
+        "Mock daemon serving {} ({} projects, {} processes, {} events) on {:?}",
+        path.display(),
+        scenario.projects.len(),
+        scenario.processes.len(),
+        scenario.events.len(),
+        socket
+    );
+
+    let shutdown = Arc::new(Notify::new());
+    loop {
+        tokio::select! {
+            accepted = Ipc::accept(&mut listener) => match accepted {
+                Ok(stream) => {
+                    let scenario = scenario.clone();
+                    let shutdown = shutdown.clone();
+                    panics::spawn("ipc connection", async move {
+                        if let Err(e) = handle_connection(Box::new(stream), scenario, shutdown).await {
+                            tracing::error!("Connection error: {}", e);
+                        }
+                    });
+                }
+                Err(e) => tracing::error!("Accept error: {}", e),
+            },
+            _ = shutdown.notified() => break,
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    Ipc::unbind(&socket);
+    tracing::info!("Mock daemon stopped");
+    Ok(())
+}
+
+async fn handle_connection(
+    stream: Connection,
+    scenario: Arc<Scenario>,
+    shutdown: Arc<Notify>,
+) -> Result<()> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    if line.is_empty() {
+        return Ok(());
+    }
+
+    let request: IpcRequest = match serde_json::from_str(&line) {
+        Ok(request) => request,
+        Err(e) => return ipc::write_response(&mut writer, &ipc::invalid_request(&line, &e)).await,
+    };
+    if scenario.responses.contains_key(&request_type(&request)) {
+        return ipc::write_response(&mut writer, &scenario.answer(request)).await;
+    }
+
+    match request {
+        IpcRequest::Subscribe { filters } => {
+            let (events, receiver) = broadcast::channel(scenario.events.len().max(1));
+            let replay = tokio::spawn({
+                let scenario = scenario.clone();
+                async move {
+                    let start = Instant::now();
+                    for (after, event) in &scenario.events {
+                        tokio::time::sleep_until(start + *after).await;
+                        if let Ok(event) = to_event(event.clone()) {
+                            let _ = events.send(event);
+                        }
+                    }
+                    // The stream stays open, as the daemon's does
+                    std::future::pending::<()>().await;
+                }
+            });
+            let streamed =
+                ipc::stream_subscription(reader, writer, "events", vec![], receiver, |e| {
+                    filters.matches(&e).then_some(IpcResponse::Event(e))
+                })
+                .await;
+            replay.abort();
+            streamed
+        }
+        IpcRequest::SubscribeLogs { project_name } => {
+            if scenario.project(&project_name).is_none() {
+                let response = IpcResponse::Error(IpcError::ProjectNotFound { name: project_name });
+                return ipc::write_response(&mut writer, &response).await;
+            }
+            let backlog = scenario
+                .project_logs(&project_name)
+                .map(IpcResponse::Log)
+                .collect();
+            // No new lines ever come, but the stream stays open
+            let (_lines, receiver) = broadcast::channel::<LogLine>(1);
+            ipc::stream_subscription(reader, writer, "logs", backlog, receiver, |l| {
+                Some(IpcResponse::Log(l))
+            })
+            .await
+        }
+        IpcRequest::Shutdown { .. } => {
+            let response = IpcResponse::Success {
+                message: Some("Mock daemon shutting down".to_string()),
+            };
+            ipc::write_response(&mut writer, &response).await?;
+            shutdown.notify_one();
+            Ok(())
+        }
+        request => ipc::write_response(&mut writer, &scenario.answer(request)).await,
+    }
+}
+
+/// A request's `type`, as `responses` is keyed
+fn request_type(request: &IpcRequest) -> String {
+    serde_json::to_value(request)
+        .ok()
+        .and_then(|json| json.get("type")?.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// An event from the scenario, stamped now unless it has a timestamp
+fn to_event(event: Map<String, Value>) -> Result<Event> {
+    let event = with_defaults(event, [("timestamp", timestamp(Utc::now()))]);
+    Ok(serde_json::from_value(event)?)
+}
+
+fn with_defaults<const N: usize>(
+    mut object: Map<String, Value>,
+    defaults: [(&str, Value); N],
+) -> Value {
+    for (key, value) in defaults {
+        object.entry(key).or_insert(value);
+    }
+    Value::Object(object)
+}
+
+/// The `n`th scenario ID, counting from 1
+fn id(n: usize) -> Value {
+    Value::from(Uuid::from_u128(n as u128 + 1).to_string())
+}
+
+fn timestamp(time: DateTime<Utc>) -> Value {
+    Value::from(time.to_rfc3339())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proj_common::{LogQuery, ProjectFilter};
+
+    const SCENARIO: &str = r#"{
+        "projects": [
+            {"name": "web", "root_dir": "/src/web", "tags": ["frontend"]},
+            {"name": "api", "root_dir": "/src/api"}
+        ],
+        "processes": [{"project_name": "web", "pid": 4242, "command": "npm run dev", "port": 4000}],
+        "logs": [
+            {"project_name": "web", "line": "compiling"},
+            {"project_name": "web", "line": "ready in 300ms"}
+        ],
+        "events": [{"after_ms": 10, "project_name": "web", "event": "route_added", "port": 4000}],
+        "responses": {"list_ports": {"type": "ports", "data": []}}
+    }"#;
+
+    #[test]
+    fn test_answers() {
+        let scenario = Scenario::parse(SCENARIO, Utc::now()).unwrap();
+        assert_eq!(scenario.projects[1].id, Uuid::from_u128(2));
+        assert_eq!(scenario.logs[0].process_id, scenario.processes[0].id);
+
+        let running = ProjectFilter {
+            running: true,
+            ..Default::default()
+        };
+        match scenario.answer(IpcRequest::ListProjects { filter: running }) {
+            IpcResponse::Projects(projects) => assert_eq!(projects.len(), 1),
+            other => panic!("unexpected {:?}", other),
+        }
+        let query = LogQuery {
+            limit: Some(1),
+            ..Default::default()
+        };
+        match scenario.answer(IpcRequest::GetLogs {
+            project_name: "web".to_string(),
+            query,
+        }) {
+            IpcResponse::Logs(lines) => assert_eq!(lines[0].line, "ready in 300ms"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(
+            scenario.answer(IpcRequest::GetProject {
+                name: "nope".to_string()
+            }),
+            IpcResponse::Error(IpcError::ProjectNotFound { .. })
+        ));
+        assert!(matches!(
+            scenario.answer(IpcRequest::ListPorts),
+            IpcResponse::Ports(_)
+        ));
+        assert!(matches!(
+            scenario.answer(IpcRequest::ReloadConfig),
+            IpcResponse::Error(IpcError::InvalidRequest { .. })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_event() {
+        let scenario = r#"{"events": [{"project_name": "web", "event": "no_such_event"}]}"#;
+        assert!(Scenario::parse(scenario, Utc::now()).is_err());
+    }
+}
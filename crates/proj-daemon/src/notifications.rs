@@ -0,0 +1,777 @@
+//! Notifications - events worth knowing about, sent to the backends the
+//! `notifications` setting gives them (see [`proj_common::notifications`])
+//!
+//! A process failing, a performance budget going over and a panic in the
+//! daemon notify by default; other event types do once they're listed. Each
+//! backend is a [`Notifier`], and one failing is logged without holding up
+//! the others. The same event type about the same project notifies at most
+//! once per [`MIN_INTERVAL`], so a crash loop doesn't flood a phone.
+//!
+//! [`DoNotDisturb`] holds all but critical notifications while it's on, which
+//! it is by default while a terminal is attached to a process, and sends
+//! them as a summary per backend once it's off again.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::header::{CONTENT_TYPE, HOST, USER_AGENT};
+use hyper::Request;
+use hyper_util::rt::TokioIo;
+use proj_common::notifications::{Backend, DndMode, DndStatus, WebhookUrl, CRITICAL_EVENTS};
+use proj_common::outbound::{Feature, Route};
+use proj_common::{Config, Event, EventKind, ProcessStatus};
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::watch;
+use tokio::time::{Duration, Instant};
+use tokio_rustls::rustls::crypto::ring::default_provider;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+use crate::events::EventBus;
+use crate::process::BoxFuture;
+use crate::proxy::AppearanceTable;
+use crate::{hooks, outbound, panics};
+
+/// Least time between notifications of one event type about one project
+const MIN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Held notifications listed in a do-not-disturb summary
+const SUMMARY_LINES: usize = 10;
+
+/// Webhooks and commands taking longer than this count as failed
+const SEND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// CA bundles tried for HTTPS webhooks after `SSL_CERT_FILE`
+const CA_BUNDLES: [&str; 4] = [
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/cert.pem",
+    "/usr/local/etc/openssl/cert.pem",
+];
+
+/// What a backend is given
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub title: String,
+    pub message: String,
+    /// Type of the event it's about
+    pub event: &'static str,
+    /// Empty for notifications about the daemon itself
+    pub project_name: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Somewhere notifications go
+pub trait Notifier: Send + Sync {
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>>;
+}
+
+/// The notifier of a configured backend
+fn notifier(backend: &Backend, events: &EventBus, config: &Config) -> Box<dyn Notifier> {
+    match backend {
+        Backend::Desktop => Box::new(Desktop),
+        Backend::Bell => Box::new(Bell(events.clone())),
+        Backend::Webhook { url } => Box::new(Webhook {
+            url: url.clone(),
+            config: config.clone(),
+        }),
+        Backend::Command { command } => Box::new(ShellCommand(command.clone())),
+    }
+}
+
+/// Send notifications for the events on `events` until the daemon exits,
+/// holding non-critical ones while `dnd` is active
+pub async fn run(
+    events: EventBus,
+    config: watch::Receiver<Config>,
+    appearances: AppearanceTable,
+    dnd: Arc<DoNotDisturb>,
+) {
+    let mut rx = events.subscribe();
+    let mut active = dnd.active.subscribe();
+    let mut last_sent: HashMap<(&'static str, String), Instant> = HashMap::new();
+    loop {
+        let received = tokio::select! {
+            received = rx.recv() => received,
+            Ok(()) = active.changed() => {
+                if !*active.borrow_and_update() {
+                    send_summaries(dnd.take_held(), &events, &config.borrow());
+                }
+                continue;
+            }
+        };
+        let event = match received {
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => {
+                tracing::warn!("Missed {} events to notify of", missed);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        let name = event.kind.name();
+        let (backends, config) = {
+            let config = config.borrow();
+            let backends = config.notifications.backends_for(name).to_vec();
+            (backends, config.clone())
+        };
+        if backends.is_empty() {
+            continue;
+        }
+        let label = match appearances.read().await.get(&event.project_name) {
+            Some(appearance) => appearance.label(&event.project_name),
+            None => event.project_name.clone(),
+        };
+        let Some(notification) = notification(&event, &label) else {
+            continue;
+        };
+
+        let key = (name, event.project_name.clone());
+        let now = Instant::now();
+        if last_sent
+            .get(&key)
+            .is_some_and(|sent| now.duration_since(*sent) < MIN_INTERVAL)
+        {
+            tracing::debug!("Not notifying of {} again so soon", name);
+            continue;
+        }
+        last_sent.insert(key, now);
+
+        if !CRITICAL_EVENTS.contains(&name) && dnd.hold(&backends, &notification) {
+            tracing::debug!("Holding {} notification for do-not-disturb", name);
+            continue;
+        }
+        deliver(&backends, Arc::new(notification), &events, &config);
+    }
+}
+
+/// Send a notification to each of `backends` in the background
+fn deliver(
+    backends: &[Backend],
+    notification: Arc<Notification>,
+    events: &EventBus,
+    config: &Config,
+) {
+    for backend in backends {
+        let notifier = notifier(backend, events, config);
+        let name = backend.name();
+        let notification = notification.clone();
+        panics::spawn("notification", async move {
+            let sent = tokio::time::timeout(SEND_TIMEOUT, notifier.send(&notification));
+            match sent.await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => tracing::warn!("{} notification failed: {:#}", name, e),
+                Err(_) => tracing::warn!(
+                    "{} notification took over {}s",
+                    name,
+                    SEND_TIMEOUT.as_secs()
+                ),
+            }
+        });
+    }
+}
+
+/// Send what do-not-disturb held, one summary per backend
+fn send_summaries(held: Vec<(Vec<Backend>, Notification)>, events: &EventBus, config: &Config) {
+    let mut per_backend: Vec<(Backend, Vec<&Notification>)> = Vec::new();
+    for (backends, notification) in &held {
+        for backend in backends {
+            match per_backend.iter_mut().find(|(b, _)| b == backend) {
+                Some((_, notifications)) => notifications.push(notification),
+                None => per_backend.push((backend.clone(), vec![notification])),
+            }
+        }
+    }
+    for (backend, notifications) in per_backend {
+        deliver(
+            std::slice::from_ref(&backend),
+            Arc::new(summary(&notifications)),
+            events,
+            config,
+        );
+    }
+}
+
+/// One notification standing for several held ones
+fn summary(held: &[&Notification]) -> Notification {
+    let mut lines: Vec<String> = held
+        .iter()
+        .take(SUMMARY_LINES)
+        .map(|n| format!("{}: {}", n.title.trim_start_matches("proj: "), n.message))
+        .collect();
+    if held.len() > SUMMARY_LINES {
+        lines.push(format!("and {} more", held.len() - SUMMARY_LINES));
+    }
+    let projects: BTreeSet<&str> = held.iter().map(|n| n.project_name.as_str()).collect();
+    Notification {
+        title: format!(
+            "proj: {} notification{} while you were busy",
+            held.len(),
+            if held.len() == 1 { "" } else { "s" }
+        ),
+        message: lines.join("\n"),
+        event: "summary",
+        project_name: match projects.into_iter().collect::<Vec<_>>().as_slice() {
+            [project] => project.to_string(),
+            _ => String::new(),
+        },
+        timestamp: Utc::now(),
+    }
+}
+
+/// Do-not-disturb: whether non-critical notifications are held, and those
+/// held until it ends
+pub struct DoNotDisturb {
+    state: Mutex<DndState>,
+    active: watch::Sender<bool>,
+}
+
+#[derive(Default)]
+struct DndState {
+    mode: DndMode,
+    sessions: usize,
+    held: Vec<(Vec<Backend>, Notification)>,
+}
+
+impl DndState {
+    fn active(&self) -> bool {
+        match self.mode {
+            DndMode::Auto => self.sessions > 0,
+            DndMode::On => true,
+            DndMode::Off => false,
+        }
+    }
+}
+
+impl DoNotDisturb {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::default(),
+            active: watch::channel(false).0,
+        })
+    }
+
+    pub fn set_mode(&self, mode: DndMode) {
+        let mut state = self.state.lock().unwrap();
+        state.mode = mode;
+        self.update(&state);
+    }
+
+    /// Count a foreground session until the returned guard drops
+    pub fn session(self: &Arc<Self>) -> Session {
+        let mut state = self.state.lock().unwrap();
+        state.sessions += 1;
+        self.update(&state);
+        Session(self.clone())
+    }
+
+    pub fn status(&self) -> DndStatus {
+        let state = self.state.lock().unwrap();
+        let active = state.active();
+        DndStatus {
+            mode: state.mode,
+            sessions: state.sessions,
+            active,
+            // Once it's off they're on their way
+            held: if active { state.held.len() } else { 0 },
+        }
+    }
+
+    /// Keep a notification for the summary if do-not-disturb is active
+    fn hold(&self, backends: &[Backend], notification: &Notification) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if !state.active() {
+            return false;
+        }
+        state.held.push((backends.to_vec(), notification.clone()));
+        true
+    }
+
+    fn take_held(&self) -> Vec<(Vec<Backend>, Notification)> {
+        std::mem::take(&mut self.state.lock().unwrap().held)
+    }
+
+    fn update(&self, state: &DndState) {
+        let active = state.active();
+        self.active.send_if_modified(|current| {
+            let changed = *current != active;
+            *current = active;
+            changed
+        });
+    }
+}
+
+/// An attached foreground session, counted while it lives
+pub struct Session(Arc<DoNotDisturb>);
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap();
+        state.sessions -= 1;
+        self.0.update(&state);
+    }
+}
+
+/// What an event says as a notification, with the project named by `label`;
+/// `None` for processes that didn't fail, budgets that aren't exceeded and
+/// bell notifications
+fn notification(event: &Event, label: &str) -> Option<Notification> {
+    let (title, message) = match &event.kind {
+        EventKind::ProcessStarted { command, .. } => {
+            (format!("{} started", label), command.clone())
+        }
+        EventKind::ProcessExited {
+            exit_code, status, ..
+        } => {
+            if *status != ProcessStatus::Failed {
+                return None;
+            }
+            let message = match exit_code {
+                Some(code) => format!("Exited with code {}", code),
+                None => "Killed by a signal".to_string(),
+            };
+            (format!("{} crashed", label), message)
+        }
+        EventKind::PortDetected { port, .. } => (
+            format!("{} is listening", label),
+            format!("On port {}", port),
+        ),
+        EventKind::RouteAdded { port } => {
+            (format!("{} is routed", label), format!("To port {}", port))
+        }
+        EventKind::RouteRemoved => (format!("{} isn't routed", label), String::new()),
+        EventKind::ProjectCreated => (format!("{} was created", label), String::new()),
+        EventKind::ProjectDeleted => (format!("{} was removed", label), String::new()),
+        EventKind::CrashBundle { path, location, .. } => {
+            let message = match location {
+                Some(location) => {
+                    format!("Trace points at {}; bundle in {}", location, path.display())
+                }
+                None => format!("Bundle in {}", path.display()),
+            };
+            (format!("{} crash bundle", label), message)
+        }
+        EventKind::Milestone {
+            milestone,
+            after_ms,
+            ..
+        } => (
+            format!("{} is {}", label, milestone),
+            format!("After {}ms", after_ms),
+        ),
+        EventKind::Budget {
+            metric,
+            value,
+            limit,
+            exceeded,
+        } => {
+            if !exceeded {
+                return None;
+            }
+            (
+                format!("{} over budget", label),
+                format!(
+                    "{} is {}, over its {} budget",
+                    metric,
+                    metric.format(*value),
+                    metric.format(*limit)
+                ),
+            )
+        }
+        EventKind::Panic {
+            task,
+            message,
+            location,
+        } => {
+            let message = match location {
+                Some(location) => format!("In {}: {} at {}", task, message, location),
+                None => format!("In {}: {}", task, message),
+            };
+            ("Daemon task panicked".to_string(), message)
+        }
+        EventKind::Maintenance(report) => ("Maintenance done".to_string(), report.to_string()),
+        EventKind::NetworkChanged { lan_urls, .. } => {
+            let message = match lan_urls.as_slice() {
+                [] => "Not reachable from the LAN".to_string(),
+                urls => format!("LAN: {}", urls.join(", ")),
+            };
+            ("Network changed".to_string(), message)
+        }
+        EventKind::Notification { .. } => return None,
+    };
+    Some(Notification {
+        title: format!("proj: {}", title),
+        message,
+        event: event.kind.name(),
+        project_name: event.project_name.clone(),
+        timestamp: event.timestamp,
+    })
+}
+
+/// A desktop popup, if the platform has a way to show one
+struct Desktop;
+
+impl Notifier for Desktop {
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if cfg!(test) {
+                return Ok(());
+            }
+            let (title, message) = (&notification.title, &notification.message);
+            let mut command = if cfg!(target_os = "macos") {
+                let script = format!("display notification {:?} with title {:?}", message, title);
+                let mut command = tokio::process::Command::new("osascript");
+                command.args(["-e", &script]);
+                command
+            } else if cfg!(target_os = "linux") {
+                let mut command = tokio::process::Command::new("notify-send");
+                command.args([title, message]);
+                command
+            } else {
+                return Ok(());
+            };
+            // A missing notifier just means no popup
+            let _ = command
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .await;
+            Ok(())
+        })
+    }
+}
+
+/// A `notification` event, which `proj watch` rings the bell for
+struct Bell(EventBus);
+
+impl Notifier for Bell {
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>> {
+        self.0.emit(
+            &notification.project_name,
+            EventKind::Notification {
+                title: notification.title.clone(),
+                message: notification.message.clone(),
+                about: notification.event.to_string(),
+            },
+        );
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// A shell command, given the message on stdin and everything in
+/// `PROJ_NOTIFY_*` variables
+struct ShellCommand(String);
+
+impl Notifier for ShellCommand {
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut child = hooks::shell(&self.0)
+                .env("PROJ_NOTIFY_TITLE", &notification.title)
+                .env("PROJ_NOTIFY_MESSAGE", &notification.message)
+                .env("PROJ_NOTIFY_EVENT", notification.event)
+                .env("PROJ_NOTIFY_PROJECT", &notification.project_name)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn()
+                .with_context(|| format!("Failed to run `{}`", self.0))?;
+            if let Some(mut stdin) = child.stdin.take() {
+                // Commands that don't read it are fine
+                let _ = stdin.write_all(notification.message.as_bytes()).await;
+            }
+            let output = child.wait_with_output().await?;
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                anyhow::bail!(
+                    "`{}` failed with {}: {}",
+                    self.0,
+                    output.status,
+                    stderr.trim()
+                );
+            }
+            Ok(())
+        })
+    }
+}
+
+/// A JSON POST, through the outbound proxy when there is one
+struct Webhook {
+    url: String,
+    config: Config,
+}
+
+impl Notifier for Webhook {
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let url = WebhookUrl::parse(&self.url)?;
+            let body = serde_json::json!({
+                "title": notification.title,
+                "message": notification.message,
+                // What Slack and Mattermost show
+                "text": format!("{}: {}", notification.title, notification.message),
+                "event": notification.event,
+                "project_name": notification.project_name,
+                "timestamp": notification.timestamp,
+            });
+            let request = |uri: String| {
+                Request::post(uri)
+                    .header(HOST, url.authority())
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(USER_AGENT, concat!("proj/", env!("CARGO_PKG_VERSION")))
+                    .body(
+                        Full::new(Bytes::from(body.to_string()))
+                            .map_err(|never| match never {})
+                            .boxed(),
+                    )
+            };
+
+            let status = if url.tls {
+                let stream =
+                    outbound::connect(&self.config, Feature::Notifications, &url.host, url.port)
+                        .await?;
+                let server_name = ServerName::try_from(url.host.clone())
+                    .with_context(|| format!("Invalid webhook host '{}'", url.host))?;
+                let stream = TlsConnector::from(tls_config()?)
+                    .connect(server_name, stream)
+                    .await
+                    .with_context(|| format!("TLS with {} failed", url.authority()))?;
+                post(stream, request(url.path.clone())?).await?
+            } else {
+                match outbound::route(&self.config, Feature::Notifications, &url.host, false)? {
+                    Route::Direct => {
+                        let stream =
+                            outbound::connect_direct(&self.config, &url.host, url.port).await?;
+                        post(stream, request(url.path.clone())?).await?
+                    }
+                    Route::Proxy(proxy) => {
+                        let uri = format!("http://{}{}", url.authority(), url.path);
+                        outbound::send_through(&proxy, request(uri)?)
+                            .await?
+                            .status()
+                    }
+                }
+            };
+            if !status.is_success() {
+                anyhow::bail!("Webhook {} answered {}", self.url, status);
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Send one request over a new connection, returning the response's status
+async fn post<S>(
+    stream: S,
+    request: Request<http_body_util::combinators::BoxBody<Bytes, hyper::Error>>,
+) -> Result<hyper::StatusCode>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) =
+        hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            tracing::debug!("Webhook connection closed: {}", e);
+        }
+    });
+    Ok(sender.send_request(request).await?.status())
+}
+
+/// Client TLS settings trusting the system's CAs, loaded on first use
+fn tls_config() -> Result<Arc<ClientConfig>> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    if let Some(config) = CONFIG.get() {
+        return Ok(config.clone());
+    }
+    let config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(system_roots()?)
+        .with_no_client_auth();
+    Ok(CONFIG.get_or_init(|| Arc::new(config)).clone())
+}
+
+/// The CAs of the first bundle found
+fn system_roots() -> Result<RootCertStore> {
+    let bundles = std::env::var_os("SSL_CERT_FILE")
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(CA_BUNDLES.iter().map(PathBuf::from));
+    for bundle in bundles {
+        let Ok(certs) = CertificateDer::pem_file_iter(&bundle) else {
+            continue;
+        };
+        let mut roots = RootCertStore::empty();
+        // Bundles can hold a few certificates rustls rejects
+        roots.add_parsable_certificates(certs.flatten());
+        if !roots.is_empty() {
+            return Ok(roots);
+        }
+    }
+    anyhow::bail!("No CA certificates found for HTTPS webhooks; set SSL_CERT_FILE for the daemon")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proj_common::BudgetMetric;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+    use tokio::net::TcpListener;
+    use uuid::Uuid;
+
+    fn event(project_name: &str, kind: EventKind) -> Event {
+        Event {
+            timestamp: Utc::now(),
+            project_name: project_name.to_string(),
+            kind,
+        }
+    }
+
+    #[test]
+    fn test_notification() {
+        let exited = |status| EventKind::ProcessExited {
+            process_id: Uuid::new_v4(),
+            exit_code: Some(1),
+            status,
+        };
+        let crashed = notification(&event("web", exited(ProcessStatus::Failed)), "🚀 web").unwrap();
+        assert_eq!(crashed.title, "proj: 🚀 web crashed");
+        assert_eq!(crashed.message, "Exited with code 1");
+        assert_eq!(crashed.event, "process_exited");
+        assert!(notification(&event("web", exited(ProcessStatus::Stopped)), "web").is_none());
+
+        let budget = |exceeded| EventKind::Budget {
+            metric: BudgetMetric::P95Latency,
+            value: 900,
+            limit: 500,
+            exceeded,
+        };
+        assert!(notification(&event("web", budget(false)), "web").is_none());
+        let over = notification(&event("web", budget(true)), "web").unwrap();
+        assert_eq!(over.title, "proj: web over budget");
+
+        let bell = EventKind::Notification {
+            title: "t".into(),
+            message: "m".into(),
+            about: "panic".into(),
+        };
+        assert!(notification(&event("", bell), "").is_none());
+    }
+
+    fn sample() -> Notification {
+        Notification {
+            title: "proj: web crashed".into(),
+            message: "Exited with code 1".into(),
+            event: "process_exited",
+            project_name: "web".into(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_dnd() {
+        let dnd = DoNotDisturb::new();
+        let backends = [Backend::Desktop];
+        assert!(!dnd.hold(&backends, &sample()));
+
+        let session = dnd.session();
+        assert!(dnd.status().active);
+        assert!(dnd.hold(&backends, &sample()));
+        assert_eq!(dnd.status().held, 1);
+        dnd.set_mode(DndMode::Off);
+        assert!(!dnd.hold(&backends, &sample()));
+        dnd.set_mode(DndMode::Auto);
+        drop(session);
+        let status = dnd.status();
+        assert!(!status.active);
+        assert_eq!(status.sessions, 0);
+        dnd.set_mode(DndMode::On);
+        assert!(dnd.status().active);
+
+        let held = dnd.take_held();
+        assert_eq!(held.len(), 1);
+        let summary = summary(&[&held[0].1, &held[0].1]);
+        assert_eq!(summary.title, "proj: 2 notifications while you were busy");
+        assert_eq!(
+            summary.message,
+            "web crashed: Exited with code 1\nweb crashed: Exited with code 1"
+        );
+        assert_eq!(summary.project_name, "web");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_command() {
+        let dir = std::env::temp_dir().join(format!("proj-notify-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let out = dir.join("out");
+        let command = ShellCommand(format!(
+            "{{ echo \"$PROJ_NOTIFY_PROJECT/$PROJ_NOTIFY_EVENT\"; cat; }} > {}",
+            out.display()
+        ));
+        command.send(&sample()).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            "web/process_exited\nExited with code 1"
+        );
+
+        let error = ShellCommand("echo nope >&2; exit 2".into())
+            .send(&sample())
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.ends_with("nope"), "{}", error);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_webhook() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook?token=x", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = tokio::io::BufReader::new(stream);
+            let mut head = String::new();
+            while !head.ends_with("\r\n\r\n") {
+                stream.read_line(&mut head).await.unwrap();
+            }
+            let length: usize = head
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length: "))
+                .unwrap()
+                .parse()
+                .unwrap();
+            let mut body = vec![0; length];
+            stream.read_exact(&mut body).await.unwrap();
+            stream
+                .get_mut()
+                .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .await
+                .unwrap();
+            (head, body)
+        });
+
+        let webhook = Webhook {
+            url,
+            config: Config::default(),
+        };
+        webhook.send(&sample()).await.unwrap();
+        let (head, body) = server.await.unwrap();
+        assert!(
+            head.starts_with("POST /hook?token=x HTTP/1.1\r\n"),
+            "{}",
+            head
+        );
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["title"], "proj: web crashed");
+        assert_eq!(body["event"], "process_exited");
+    }
+}
@@ -0,0 +1,327 @@
+//! Where process output goes - each line a process prints is written to a
+//! [`Fanout`] of [`OutputSink`]s
+//!
+//! The readers of a process's stdout and stderr (or of its terminal) each
+//! write to a fanout holding the process's `--output` file, if it has one,
+//! and a [`Forward`] to the daemon's event loop, which hands the line to the
+//! log store. What the log store keeps goes on to its own fanout: [`Live`]
+//! subscribers and [`Passthrough`] to the daemon's log.
+//!
+//! A sink that fails is dropped with a warning, and the others carry on.
+//! Live subscribers share a bounded buffer, so one that falls behind loses
+//! its oldest lines (and is told how many) rather than holding up the
+//! process or the other subscribers.
+
+use anyhow::{Context, Result};
+use proj_common::LogLine;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{broadcast, mpsc};
+
+use crate::process::{BoxFuture, ProcessEvent};
+
+/// Lines buffered for live subscribers before the slowest starts losing them
+pub const LIVE_CAPACITY: usize = 1024;
+
+/// Somewhere output lines go
+pub trait OutputSink: Send {
+    /// What the sink is, for the warning when it's dropped
+    fn name(&self) -> &str;
+
+    fn write<'a>(&'a mut self, line: &'a LogLine) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Sinks a line is written to in turn
+#[derive(Default)]
+pub struct Fanout {
+    sinks: Vec<Box<dyn OutputSink>>,
+}
+
+impl Fanout {
+    pub fn push(&mut self, sink: impl OutputSink + 'static) {
+        self.sinks.push(Box::new(sink));
+    }
+
+    /// Write a line to every sink, dropping those that fail
+    pub async fn write(&mut self, line: &LogLine) {
+        let mut index = 0;
+        while index < self.sinks.len() {
+            match self.sinks[index].write(line).await {
+                Ok(()) => index += 1,
+                Err(e) => {
+                    let sink = self.sinks.remove(index);
+                    tracing::warn!(
+                        "Not writing output of {} to {} any more: {:#}",
+                        line.process_id,
+                        sink.name(),
+                        e
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// A process's `--output` file, with its own handle per stream
+pub struct FileTee(tokio::fs::File);
+
+impl FileTee {
+    /// Share `file`, unless it can't be
+    pub fn share(file: &std::fs::File) -> Option<Self> {
+        match file.try_clone() {
+            Ok(file) => Some(Self(tokio::fs::File::from_std(file))),
+            Err(e) => {
+                tracing::warn!("Failed to share output file: {}", e);
+                None
+            }
+        }
+    }
+}
+
+impl OutputSink for FileTee {
+    fn name(&self) -> &str {
+        "the output file"
+    }
+
+    fn write<'a>(&'a mut self, line: &'a LogLine) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let bytes = format!("{}\n", line.line);
+            self.0.write_all(bytes.as_bytes()).await?;
+            // Otherwise the write may still be under way when the process
+            // is reported as exited
+            self.0.flush().await?;
+            Ok(())
+        })
+    }
+}
+
+/// The daemon's event loop, which stores lines in the log store; waits when
+/// the loop is behind, so a process can't print faster than it's stored
+pub struct Forward(pub mpsc::Sender<ProcessEvent>);
+
+impl OutputSink for Forward {
+    fn name(&self) -> &str {
+        "the log store"
+    }
+
+    fn write<'a>(&'a mut self, line: &'a LogLine) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.0
+                .send(ProcessEvent::Output(line.clone()))
+                .await
+                .ok()
+                .context("the daemon's event loop has stopped")
+        })
+    }
+}
+
+/// Clients following output as it's stored
+#[derive(Clone)]
+pub struct Live(broadcast::Sender<LogLine>);
+
+impl Live {
+    pub fn new(capacity: usize) -> Self {
+        Self(broadcast::channel(capacity).0)
+    }
+
+    /// A receiver for every line written after this
+    pub fn subscribe(&self) -> broadcast::Receiver<LogLine> {
+        self.0.subscribe()
+    }
+}
+
+impl OutputSink for Live {
+    fn name(&self) -> &str {
+        "live subscribers"
+    }
+
+    fn write<'a>(&'a mut self, line: &'a LogLine) -> BoxFuture<'a, Result<()>> {
+        // No subscribers is fine, and a full buffer drops the oldest line
+        let _ = self.0.send(line.clone());
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Echo in the daemon's own log, through tracing under the `proj::output`
+/// target; shared with the log store, which sets who it's on for
+#[derive(Clone, Default)]
+pub struct Passthrough(Arc<Mutex<PassthroughSettings>>);
+
+#[derive(Default)]
+struct PassthroughSettings {
+    /// Whether lines are echoed, unless overridden
+    enabled: bool,
+    /// Projects overriding `enabled`
+    overrides: HashMap<String, bool>,
+}
+
+impl Passthrough {
+    pub fn new(enabled: bool) -> Self {
+        let passthrough = Self::default();
+        passthrough.set_enabled(enabled);
+        passthrough
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.settings().enabled = enabled;
+    }
+
+    /// Set whether a project's lines are echoed, or (with `None`) leave it
+    /// to `enabled`
+    pub fn set_override(&self, project_name: &str, passthrough: Option<bool>) {
+        let overrides = &mut self.settings().overrides;
+        match passthrough {
+            Some(passthrough) => {
+                overrides.insert(project_name.to_string(), passthrough);
+            }
+            None => {
+                overrides.remove(project_name);
+            }
+        }
+    }
+
+    fn is_on(&self, project_name: &str) -> bool {
+        let settings = self.settings();
+        settings
+            .overrides
+            .get(project_name)
+            .copied()
+            .unwrap_or(settings.enabled)
+    }
+
+    fn settings(&self) -> std::sync::MutexGuard<'_, PassthroughSettings> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl OutputSink for Passthrough {
+    fn name(&self) -> &str {
+        "the daemon's log"
+    }
+
+    fn write<'a>(&'a mut self, line: &'a LogLine) -> BoxFuture<'a, Result<()>> {
+        if self.is_on(&line.project_name) {
+            let stream = if line.is_stderr { "stderr" } else { "stdout" };
+            tracing::info!(
+                target: "proj::output",
+                project = %line.project_name,
+                process_id = %line.process_id,
+                stream = %stream,
+                "{}",
+                line.line
+            );
+        }
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::time::Duration;
+    use tokio::sync::broadcast::error::TryRecvError;
+    use uuid::Uuid;
+
+    fn line(n: usize) -> LogLine {
+        LogLine {
+            project_name: "app".to_string(),
+            process_id: Uuid::nil(),
+            service: None,
+            timestamp: Utc::now(),
+            is_stderr: false,
+            line: n.to_string(),
+            stage: None,
+            collapsed: None,
+        }
+    }
+
+    /// Keeps what it's given, failing from the `fail_at`th line on
+    struct Collect {
+        lines: Arc<Mutex<Vec<String>>>,
+        fail_at: Option<usize>,
+    }
+
+    impl OutputSink for Collect {
+        fn name(&self) -> &str {
+            "a test sink"
+        }
+
+        fn write<'a>(&'a mut self, line: &'a LogLine) -> BoxFuture<'a, Result<()>> {
+            let mut lines = self.lines.lock().unwrap();
+            let result = if self.fail_at == Some(lines.len()) {
+                Err(anyhow::anyhow!("full"))
+            } else {
+                lines.push(line.line.clone());
+                Ok(())
+            };
+            Box::pin(async move { result })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fanout() {
+        let kept = Arc::new(Mutex::new(Vec::new()));
+        let failing = Arc::new(Mutex::new(Vec::new()));
+        let mut fanout = Fanout::default();
+        fanout.push(Collect {
+            lines: failing.clone(),
+            fail_at: Some(2),
+        });
+        fanout.push(Collect {
+            lines: kept.clone(),
+            fail_at: None,
+        });
+        for n in 0..5 {
+            fanout.write(&line(n)).await;
+        }
+
+        // The failing sink is dropped and the other one misses nothing
+        assert_eq!(fanout.sinks.len(), 1);
+        assert_eq!(*failing.lock().unwrap(), ["0", "1"]);
+        assert_eq!(*kept.lock().unwrap(), ["0", "1", "2", "3", "4"]);
+    }
+
+    #[tokio::test]
+    async fn test_slow_subscriber() {
+        let live = Live::new(4);
+        let mut slow = live.subscribe();
+        let mut fast = live.subscribe();
+        let mut fanout = Fanout::default();
+        fanout.push(live);
+
+        let mut seen = Vec::new();
+        for n in 0..10 {
+            // A subscriber that never reads doesn't hold up the writer
+            tokio::time::timeout(Duration::from_secs(1), fanout.write(&line(n)))
+                .await
+                .unwrap();
+            seen.push(fast.recv().await.unwrap().line);
+        }
+        assert_eq!(seen.len(), 10);
+        assert_eq!(fanout.sinks.len(), 1);
+
+        // The slow one is told what it missed, then gets the latest lines
+        assert!(matches!(slow.try_recv(), Err(TryRecvError::Lagged(6))));
+        let rest: Vec<String> = std::iter::from_fn(|| slow.try_recv().ok())
+            .map(|line| line.line)
+            .collect();
+        assert_eq!(rest, ["6", "7", "8", "9"]);
+    }
+
+    #[tokio::test]
+    async fn test_file_tee() {
+        let path = std::env::temp_dir().join(format!("proj-output-{}", Uuid::new_v4()));
+        let file = std::fs::File::create(&path).unwrap();
+        let mut fanout = Fanout::default();
+        fanout.push(FileTee::share(&file).unwrap());
+        fanout.push(FileTee::share(&file).unwrap());
+        fanout.write(&line(1)).await;
+        fanout.write(&line(2)).await;
+        drop(fanout);
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "1\n1\n2\n2\n");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,134 @@
+//! Panics in the daemon's tasks
+//!
+//! A panic only ends the task it happens in, so a bug in a proxy connection
+//! or the event handler would otherwise leave the daemon quietly missing a
+//! piece. The panic hook records each one with the task it happened in (as
+//! named where it was started with [`spawn`]) and where in the source, logs
+//! it, and emits a `panic` event; `Status` reports those of the last hour.
+
+use chrono::{DateTime, Utc};
+use proj_common::{DaemonPanic, EventKind};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::panic::PanicHookInfo;
+use std::sync::{Mutex, OnceLock};
+use tokio::task::JoinHandle;
+
+use crate::events::EventBus;
+
+/// Panics kept for `Status`, newest last
+const MAX_KEPT: usize = 50;
+
+/// How far back `Status` looks
+const WINDOW: chrono::Duration = chrono::Duration::hours(1);
+
+tokio::task_local! {
+    /// What the current task does, for panic reports
+    static TASK: &'static str;
+}
+
+static PANICS: Mutex<VecDeque<DaemonPanic>> = Mutex::new(VecDeque::new());
+static EVENTS: OnceLock<EventBus> = OnceLock::new();
+
+/// Record panics from now on, emitting them on `events`
+pub fn install(events: EventBus) {
+    let _ = EVENTS.set(events);
+    std::panic::set_hook(Box::new(record));
+}
+
+/// Start a task whose panics are reported as happening in `task`
+pub fn spawn<F>(task: &'static str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(TASK.scope(task, future))
+}
+
+/// Panics within the last hour, oldest first
+pub fn recent() -> Vec<DaemonPanic> {
+    since(&PANICS.lock().unwrap(), Utc::now() - WINDOW)
+}
+
+fn since(panics: &VecDeque<DaemonPanic>, cutoff: DateTime<Utc>) -> Vec<DaemonPanic> {
+    panics
+        .iter()
+        .filter(|p| p.timestamp >= cutoff)
+        .cloned()
+        .collect()
+}
+
+/// The running task's name, else the thread's
+fn current_task() -> String {
+    TASK.try_with(|task| task.to_string())
+        .ok()
+        .or_else(|| std::thread::current().name().map(str::to_string))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn record(info: &PanicHookInfo) {
+    let task = current_task();
+    let message = info
+        .payload()
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Box<dyn Any>".to_string());
+    let location = info
+        .location()
+        .map(|l| format!("{}:{}", l.file(), l.line()));
+
+    tracing::error!(
+        task = %task,
+        location = location.as_deref().unwrap_or("unknown"),
+        "Task panicked: {}",
+        message
+    );
+    if let Some(events) = EVENTS.get() {
+        events.emit(
+            "",
+            EventKind::Panic {
+                task: task.clone(),
+                message: message.clone(),
+                location: location.clone(),
+            },
+        );
+    }
+
+    // A panic while the lock was held mustn't turn into a second one
+    let Ok(mut panics) = PANICS.try_lock() else {
+        return;
+    };
+    panics.push_back(DaemonPanic {
+        timestamp: Utc::now(),
+        task,
+        message,
+        location,
+    });
+    if panics.len() > MAX_KEPT {
+        panics.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_current_task() {
+        let task = spawn("proxy connection", async { current_task() });
+        assert_eq!(task.await.unwrap(), "proxy connection");
+    }
+
+    #[test]
+    fn test_since() {
+        let panic = |mins_ago| DaemonPanic {
+            timestamp: Utc::now() - chrono::Duration::minutes(mins_ago),
+            task: "proxy connection".to_string(),
+            message: "boom".to_string(),
+            location: None,
+        };
+        let panics = VecDeque::from([panic(90), panic(30), panic(5)]);
+        assert_eq!(since(&panics, Utc::now() - WINDOW).len(), 2);
+    }
+}
@@ -0,0 +1,94 @@
+//! Placeholder answers for paths browsers ask every site for
+//!
+//! Browsers fetch `/favicon.ico` (and Safari `/apple-touch-icon.png`) for
+//! every page, and Chrome's DevTools asks for its workspace file under
+//! `/.well-known`; most dev servers 404 them, filling the console and the
+//! access log. When the backend answers one of these with an error, or isn't
+//! running, the proxy serves an empty placeholder instead. Placeholders carry
+//! an `ETag` with `Cache-Control: no-cache`, so browsers revalidate them for
+//! a 304 and pick up the real file as soon as the backend has one.
+//! `proxy_placeholders` in the config turns them off.
+
+use hyper::StatusCode;
+
+/// A stand-in for a file the backend doesn't have
+pub struct Placeholder {
+    pub content_type: &'static str,
+    pub body: &'static [u8],
+    pub etag: &'static str,
+}
+
+/// A transparent 1×1 icon
+const ICON: Placeholder = Placeholder {
+    content_type: "image/x-icon",
+    body: include_bytes!("placeholder.ico"),
+    etag: "\"proj-placeholder-ico-1\"",
+};
+
+/// A transparent 1×1 PNG
+const TOUCH_ICON: Placeholder = Placeholder {
+    content_type: "image/png",
+    body: include_bytes!("placeholder.png"),
+    etag: "\"proj-placeholder-png-1\"",
+};
+
+/// No DevTools workspace
+const DEVTOOLS_WORKSPACE: Placeholder = Placeholder {
+    content_type: "application/json",
+    body: b"{}\n",
+    etag: "\"proj-placeholder-json-1\"",
+};
+
+/// The placeholder for a request path, if it has one
+pub fn for_path(path: &str) -> Option<&'static Placeholder> {
+    match path {
+        "/favicon.ico" => Some(&ICON),
+        "/apple-touch-icon.png" | "/apple-touch-icon-precomposed.png" => Some(&TOUCH_ICON),
+        "/.well-known/appspecific/com.chrome.devtools.json" => Some(&DEVTOOLS_WORKSPACE),
+        _ => None,
+    }
+}
+
+/// Whether the backend's answer means it has nothing to serve: not found,
+/// or down
+pub fn replaces(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::NOT_FOUND | StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE
+    )
+}
+
+impl Placeholder {
+    /// Whether a client sending this `If-None-Match` already has it
+    pub fn is_fresh(&self, if_none_match: Option<&str>) -> bool {
+        if_none_match.is_some_and(|tags| {
+            tags.split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == "*" || tag == self.etag)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_path() {
+        assert_eq!(
+            for_path("/favicon.ico").unwrap().content_type,
+            "image/x-icon"
+        );
+        assert!(for_path("/favicon.ico/").is_none());
+        assert!(for_path("/app.js").is_none());
+    }
+
+    #[test]
+    fn test_is_fresh() {
+        assert!(ICON.is_fresh(Some("\"proj-placeholder-ico-1\"")));
+        assert!(ICON.is_fresh(Some("\"other\", W/\"proj-placeholder-ico-1\"")));
+        assert!(ICON.is_fresh(Some("*")));
+        assert!(!ICON.is_fresh(Some("\"proj-placeholder-png-1\"")));
+        assert!(!ICON.is_fresh(None));
+    }
+}
@@ -0,0 +1,372 @@
+//! Platform-specific process control
+//!
+//! On Unix each managed process leads its own session and the whole process
+//! group is signalled. On Windows it gets its own process group and trees are
+//! stopped with `taskkill /T`.
+
+use chrono::{DateTime, Utc};
+use proj_common::{Capability, CapabilityStatus, Priority};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// How hard to ask a process tree to stop
+#[derive(Debug, Clone, Copy)]
+pub enum Stop {
+    /// SIGTERM, or a close request on Windows
+    Terminate,
+    /// SIGKILL, or `taskkill /F`
+    Kill,
+}
+
+/// Start `cmd` detached from the daemon's group, so stopping it reaches the
+/// whole tree (e.g. the dev server `npm run dev` spawns)
+#[cfg(unix)]
+pub fn isolate(cmd: &mut Command) {
+    // SAFETY: setsid is async-signal-safe
+    unsafe {
+        cmd.pre_exec(|| {
+            nix::unistd::setsid()?;
+            Ok(())
+        });
+    }
+}
+
+#[cfg(windows)]
+pub fn isolate(cmd: &mut Command) {
+    const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    cmd.creation_flags(CREATE_NEW_PROCESS_GROUP | CREATE_NO_WINDOW);
+}
+
+/// Signal that ended a process, if one did
+#[cfg(unix)]
+pub fn exit_signal(status: &std::process::ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+
+    status.signal()
+}
+
+#[cfg(windows)]
+pub fn exit_signal(_status: &std::process::ExitStatus) -> Option<i32> {
+    None
+}
+
+/// Signal the process group led by `pid`, or just `pid` if it has no group of its own
+/// (e.g. processes adopted from a daemon that predates process groups)
+#[cfg(unix)]
+pub fn stop_tree(pid: u32, how: Stop) -> std::io::Result<()> {
+    use nix::sys::signal::{self, Signal};
+    use nix::unistd::Pid;
+
+    let sig = match how {
+        Stop::Terminate => Signal::SIGTERM,
+        Stop::Kill => Signal::SIGKILL,
+    };
+    let pid = Pid::from_raw(pid as i32);
+    signal::killpg(pid, sig)
+        .or_else(|_| signal::kill(pid, sig))
+        .map_err(std::io::Error::from)
+}
+
+/// Stop `pid` and its descendants with `taskkill`
+#[cfg(windows)]
+pub fn stop_tree(pid: u32, how: Stop) -> std::io::Result<()> {
+    let mut taskkill = std::process::Command::new("taskkill");
+    taskkill.args(["/PID", &pid.to_string(), "/T"]);
+    if matches!(how, Stop::Kill) {
+        taskkill.arg("/F");
+    }
+    let output = taskkill.output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+/// How process trees are stopped here, for `proj doctor`
+pub fn capability() -> Capability {
+    const NAME: &str = "process control";
+    if cfg!(unix) {
+        Capability::new(
+            NAME,
+            CapabilityStatus::Ok,
+            "process groups, sent SIGTERM then SIGKILL",
+        )
+    } else if on_path("taskkill") {
+        Capability::new(
+            NAME,
+            CapabilityStatus::Ok,
+            "process trees, stopped with taskkill /T",
+        )
+    } else {
+        Capability::new(
+            NAME,
+            CapabilityStatus::Unavailable,
+            "taskkill isn't on the PATH, so processes can't be stopped",
+        )
+    }
+}
+
+/// Check whether a PID refers to a live process
+#[cfg(unix)]
+pub fn is_alive(pid: u32) -> bool {
+    use nix::sys::signal;
+    use nix::unistd::Pid;
+
+    signal::kill(Pid::from_raw(pid as i32), None).is_ok()
+}
+
+#[cfg(windows)]
+pub fn is_alive(pid: u32) -> bool {
+    std::process::Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/FO", "CSV", "/NH"])
+        .output()
+        .is_ok_and(|output| {
+            String::from_utf8_lossy(&output.stdout).contains(&format!("\"{}\"", pid))
+        })
+}
+
+/// Whether any process in the tree led by `pid` is still running
+#[cfg(unix)]
+pub fn tree_alive(pid: u32) -> bool {
+    use nix::sys::signal;
+    use nix::unistd::Pid;
+
+    signal::killpg(Pid::from_raw(pid as i32), None).is_ok() || is_alive(pid)
+}
+
+/// `taskkill /T` takes the tree down with its root, so the root is enough
+#[cfg(windows)]
+pub fn tree_alive(pid: u32) -> bool {
+    is_alive(pid)
+}
+
+/// Apply `priority` to the process group led by `pid`; processes started in
+/// it later inherit it
+#[cfg(target_os = "linux")]
+pub async fn set_priority(pid: u32, priority: &Priority) -> std::io::Result<()> {
+    use proj_common::IoClass;
+
+    let pgid = pid.to_string();
+    if let Some(nice) = priority.nice {
+        run(Command::new("renice").args(["-n", &nice.to_string(), "-g", &pgid])).await?;
+    }
+    if let Some(io) = priority.io {
+        let class = match io {
+            IoClass::BestEffort => "2",
+            IoClass::Idle => "3",
+        };
+        run(Command::new("ionice").args(["-c", class, "-P", &pgid])).await?;
+    }
+    Ok(())
+}
+
+/// Apply `priority` with BSD `renice`, and `taskpolicy`'s background state
+/// for the I/O class (macOS throttles background tasks' disk and CPU)
+#[cfg(not(any(target_os = "linux", windows)))]
+pub async fn set_priority(pid: u32, priority: &Priority) -> std::io::Result<()> {
+    use proj_common::IoClass;
+
+    let pid = pid.to_string();
+    if let Some(nice) = priority.nice {
+        run(Command::new("renice").args([&nice.to_string(), "-g", &pid])).await?;
+    }
+    if let Some(io) = priority.io {
+        let flag = match io {
+            IoClass::BestEffort => "-B",
+            IoClass::Idle => "-b",
+        };
+        run(Command::new("taskpolicy").args([flag, "-p", &pid])).await?;
+    }
+    Ok(())
+}
+
+/// Windows has priority classes rather than niceness; the nearest one is
+/// used and the I/O class is left alone
+#[cfg(windows)]
+pub async fn set_priority(pid: u32, priority: &Priority) -> std::io::Result<()> {
+    let Some(nice) = priority.nice else {
+        return Ok(());
+    };
+    let class = match nice {
+        n if n < 0 => "AboveNormal",
+        0 => "Normal",
+        n if n < 10 => "BelowNormal",
+        _ => "Idle",
+    };
+    let script = format!("(Get-Process -Id {}).PriorityClass = '{}'", pid, class);
+    run(Command::new("powershell").args(["-NoProfile", "-Command", &script])).await
+}
+
+/// Run a command, failing with its stderr if it fails
+async fn run(cmd: &mut Command) -> std::io::Result<()> {
+    let output = cmd.output().await?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+/// Full command line of a process, as reported by `ps`
+#[cfg(unix)]
+pub async fn command_line(pid: u32) -> Option<String> {
+    let output = Command::new("ps")
+        .args(["-o", "command=", "-p", &pid.to_string()])
+        .output()
+        .await
+        .ok()?;
+    let command = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!command.is_empty()).then_some(command)
+}
+
+/// Full command line of a process, from WMI
+#[cfg(windows)]
+pub async fn command_line(pid: u32) -> Option<String> {
+    let query = format!(
+        "(Get-CimInstance Win32_Process -Filter 'ProcessId={}').CommandLine",
+        pid
+    );
+    let output = Command::new("powershell")
+        .args(["-NoProfile", "-Command", &query])
+        .output()
+        .await
+        .ok()?;
+    let command = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!command.is_empty()).then_some(command)
+}
+
+/// When a process started, from its start time in clock ticks after boot
+#[cfg(target_os = "linux")]
+pub async fn started_at(pid: u32) -> Option<DateTime<Utc>> {
+    use nix::unistd::{sysconf, SysconfVar};
+
+    let stat = tokio::fs::read_to_string(format!("/proc/{}/stat", pid))
+        .await
+        .ok()?;
+    // Fields are counted after the command name, which can hold spaces
+    let ticks: i64 = stat
+        .rsplit_once(')')?
+        .1
+        .split_whitespace()
+        .nth(19)?
+        .parse()
+        .ok()?;
+    let boot: i64 = tokio::fs::read_to_string("/proc/stat")
+        .await
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("btime "))?
+        .trim()
+        .parse()
+        .ok()?;
+    let per_second = sysconf(SysconfVar::CLK_TCK).ok()??;
+    DateTime::from_timestamp_millis(boot * 1000 + ticks * 1000 / per_second)
+}
+
+/// When a process started, from how long `ps` says it has been running
+#[cfg(all(unix, not(target_os = "linux")))]
+pub async fn started_at(pid: u32) -> Option<DateTime<Utc>> {
+    let output = Command::new("ps")
+        .args(["-o", "etime=", "-p", &pid.to_string()])
+        .output()
+        .await
+        .ok()?;
+    let elapsed = parse_elapsed(String::from_utf8_lossy(&output.stdout).trim())?;
+    Utc::now().checked_sub_signed(elapsed)
+}
+
+/// When a process started, from `Get-Process`
+#[cfg(windows)]
+pub async fn started_at(pid: u32) -> Option<DateTime<Utc>> {
+    let query = format!(
+        "(Get-Process -Id {}).StartTime.ToUniversalTime().ToString('o')",
+        pid
+    );
+    let output = Command::new("powershell")
+        .args(["-NoProfile", "-Command", &query])
+        .output()
+        .await
+        .ok()?;
+    DateTime::parse_from_rfc3339(String::from_utf8_lossy(&output.stdout).trim())
+        .ok()
+        .map(|started| started.with_timezone(&Utc))
+}
+
+/// Parse `ps`'s elapsed time, `[[dd-]hh:]mm:ss`
+#[cfg(all(unix, not(target_os = "linux")))]
+fn parse_elapsed(etime: &str) -> Option<chrono::TimeDelta> {
+    let (days, clock) = match etime.split_once('-') {
+        Some((days, clock)) => (days.parse::<i64>().ok()?, clock),
+        None => (0, etime),
+    };
+    let mut seconds = 0;
+    let mut fields = 0;
+    for field in clock.split(':') {
+        seconds = seconds * 60 + field.parse::<i64>().ok()?;
+        fields += 1;
+    }
+    if !(2..=3).contains(&fields) {
+        return None;
+    }
+    chrono::TimeDelta::try_days(days)?.checked_add(&chrono::TimeDelta::try_seconds(seconds)?)
+}
+
+/// Whether a program is on the daemon's PATH
+pub fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+/// Where `program` resolves to when run in `dir` with `path` as its PATH: a
+/// program with a directory part is taken relative to `dir`, a bare name is
+/// the first match on the path
+pub fn resolve_program(program: &str, dir: &Path, path: Option<&OsStr>) -> Option<PathBuf> {
+    let candidates = |base: PathBuf| {
+        let mut found = vec![base.clone()];
+        if cfg!(windows) && base.extension().is_none() {
+            found.push(base.with_extension("exe"));
+        }
+        found.into_iter().find(|candidate| candidate.is_file())
+    };
+    let as_path = Path::new(program);
+    if as_path.components().count() > 1 || as_path.is_absolute() {
+        return candidates(dir.join(as_path));
+    }
+    std::env::split_paths(path?).find_map(|entry| candidates(entry.join(program)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    #[cfg(all(unix, not(target_os = "linux")))]
+    #[test]
+    fn test_parse_elapsed() {
+        assert_eq!(parse_elapsed("00:07"), Some(TimeDelta::seconds(7)));
+        assert_eq!(parse_elapsed("01:02:03"), Some(TimeDelta::seconds(3723)));
+        assert_eq!(
+            parse_elapsed("2-01:00:00"),
+            Some(TimeDelta::days(2) + TimeDelta::hours(1))
+        );
+        assert_eq!(parse_elapsed("7"), None);
+        assert_eq!(parse_elapsed(""), None);
+    }
+
+    #[tokio::test]
+    async fn test_started_at() {
+        let started = started_at(std::process::id()).await.unwrap();
+        let age = Utc::now() - started;
+        assert!(
+            age >= -TimeDelta::seconds(1) && age < TimeDelta::hours(1),
+            "{age}"
+        );
+    }
+}
@@ -0,0 +1,588 @@
+//! Port detection - finds the TCP ports a process tree is listening on
+//!
+//! Uses procfs on Linux, libproc on macOS and `netstat` on Windows, falling
+//! back to `lsof` when native detection is unavailable. Also allocates the ports handed to
+//! spawned processes, and picks out the debugger ports processes announce.
+
+use proj_common::{Capability, CapabilityStatus, PortRange};
+use std::collections::BTreeSet;
+use std::net::TcpListener;
+
+/// Port of a debugger a line of output announces: Node's inspector
+/// ("Debugger listening on ws://127.0.0.1:9229/…") or Delve
+/// ("API server listening at: 127.0.0.1:2345")
+pub fn announced_debugger_port(line: &str) -> Option<u16> {
+    let rest = ["Debugger listening on ws://", "API server listening at: "]
+        .iter()
+        .find_map(|prefix| line.split_once(prefix).map(|(_, rest)| rest))?;
+    let authority = rest.split('/').next()?;
+    authority.rsplit_once(':')?.1.trim().parse().ok()
+}
+
+/// Pick a port for a project from `range`.
+///
+/// The search starts at an offset derived from the project name, so a project
+/// gets the same port across runs as long as it is free.
+pub fn allocate(project_name: &str, range: PortRange, taken: impl Fn(u16) -> bool) -> Option<u16> {
+    let (start, end) = (range.start.min(range.end), range.start.max(range.end));
+    let len = u32::from(end - start) + 1;
+
+    // FNV-1a: stable across builds, unlike std's hasher
+    let hash = project_name.bytes().fold(0x811c9dc5u32, |h, b| {
+        (h ^ u32::from(b)).wrapping_mul(0x01000193)
+    });
+
+    (0..len)
+        .map(|i| start + ((hash.wrapping_add(i)) % len) as u16)
+        .find(|&port| !taken(port))
+}
+
+/// Whether nothing is listening on `port`, on IPv4 or IPv6
+pub fn is_free(port: u16) -> bool {
+    let v4 = TcpListener::bind(("0.0.0.0", port)).is_ok();
+    // Hosts without IPv6 can't bind `::` at all; only a conflict counts
+    v4 && !matches!(
+        TcpListener::bind(("::", port)),
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse
+    )
+}
+
+/// A listening TCP socket and the process holding it (if visible to us)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ListeningSocket {
+    pub port: u16,
+    pub pid: Option<u32>,
+    pub process_name: Option<String>,
+}
+
+/// List every listening TCP port on the machine, sorted by port
+pub async fn listening_sockets() -> Vec<ListeningSocket> {
+    let native = tokio::task::spawn_blocking(native_listening_sockets)
+        .await
+        .ok()
+        .flatten();
+
+    match native {
+        Some(sockets) => sockets,
+        None => lsof_listening_sockets().await,
+    }
+}
+
+/// Detect all ports a process (or any of its descendants) listens on, sorted
+pub async fn detect_ports(pid: u32) -> Vec<u16> {
+    let native = tokio::task::spawn_blocking(move || native_listening_ports(pid))
+        .await
+        .ok()
+        .flatten();
+
+    match native {
+        Some(ports) => ports,
+        None => lsof_listening_ports(pid).await,
+    }
+}
+
+/// Where native detection reads ports from
+#[cfg(target_os = "linux")]
+const NATIVE_SOURCE: &str = "procfs";
+#[cfg(target_os = "macos")]
+const NATIVE_SOURCE: &str = "libproc";
+#[cfg(windows)]
+const NATIVE_SOURCE: &str = "netstat";
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+const NATIVE_SOURCE: &str = "nothing";
+
+/// How ports are detected here, for `proj doctor`
+pub async fn capability() -> Capability {
+    const NAME: &str = "port detection";
+    let pid = std::process::id();
+    let (per_process, all) = tokio::task::spawn_blocking(move || {
+        (
+            native_listening_ports(pid).is_some(),
+            native_listening_sockets().is_some(),
+        )
+    })
+    .await
+    .unwrap_or_default();
+    let lsof = crate::platform::on_path("lsof");
+    match (per_process, all, lsof) {
+        (true, true, _) => Capability::new(NAME, CapabilityStatus::Ok, NATIVE_SOURCE),
+        (true, false, true) => Capability::new(
+            NAME,
+            CapabilityStatus::Ok,
+            format!("{}, and lsof for `proj ports`", NATIVE_SOURCE),
+        ),
+        (true, false, false) => Capability::new(
+            NAME,
+            CapabilityStatus::Degraded,
+            format!(
+                "{} for processes, but `proj ports` needs lsof, which isn't installed",
+                NATIVE_SOURCE
+            ),
+        ),
+        (false, _, true) => Capability::new(
+            NAME,
+            CapabilityStatus::Degraded,
+            "lsof, which is slower than native detection",
+        ),
+        (false, _, false) => Capability::new(
+            NAME,
+            CapabilityStatus::Unavailable,
+            "no native detection here and lsof isn't installed, so processes' ports aren't found and they get no route",
+        ),
+    }
+}
+
+/// Collect a process and all of its descendants
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+pub fn process_tree(root: u32, children_of: impl Fn(u32) -> Vec<u32>) -> Vec<u32> {
+    let mut pids = vec![root];
+    let mut i = 0;
+    while i < pids.len() {
+        for child in children_of(pids[i]) {
+            if !pids.contains(&child) {
+                pids.push(child);
+            }
+        }
+        i += 1;
+    }
+    pids
+}
+
+/// Parent -> children map of every process, from `/proc/<pid>/stat`
+#[cfg(target_os = "linux")]
+pub fn children_map() -> Option<std::collections::HashMap<u32, Vec<u32>>> {
+    let mut children: std::collections::HashMap<u32, Vec<u32>> = Default::default();
+    for entry in std::fs::read_dir("/proc").ok()?.flatten() {
+        let Some(child) = entry
+            .file_name()
+            .to_str()
+            .and_then(|n| n.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if let Some(ppid) = std::fs::read_to_string(entry.path().join("stat"))
+            .ok()
+            .and_then(|stat| parse_ppid(&stat))
+        {
+            children.entry(ppid).or_default().push(child);
+        }
+    }
+    Some(children)
+}
+
+#[cfg(target_os = "linux")]
+fn native_listening_ports(pid: u32) -> Option<Vec<u16>> {
+    let children = children_map()?;
+    let pids = process_tree(pid, |p| children.get(&p).cloned().unwrap_or_default());
+
+    // Socket inodes held open by the process tree
+    let mut inodes = BTreeSet::new();
+    for pid in pids {
+        let Ok(fds) = std::fs::read_dir(format!("/proc/{}/fd", pid)) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            if let Ok(target) = std::fs::read_link(fd.path()) {
+                let target = target.to_string_lossy();
+                if let Some(inode) = target
+                    .strip_prefix("socket:[")
+                    .and_then(|rest| rest.strip_suffix(']'))
+                    .and_then(|inode| inode.parse::<u64>().ok())
+                {
+                    inodes.insert(inode);
+                }
+            }
+        }
+    }
+
+    let mut ports = BTreeSet::new();
+    for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        if let Ok(content) = std::fs::read_to_string(table) {
+            ports.extend(
+                parse_proc_net_tcp(&content)
+                    .into_iter()
+                    .filter(|(inode, _)| inodes.contains(inode))
+                    .map(|(_, port)| port),
+            );
+        }
+    }
+
+    Some(ports.into_iter().collect())
+}
+
+#[cfg(target_os = "linux")]
+fn native_listening_sockets() -> Option<Vec<ListeningSocket>> {
+    use std::collections::HashMap;
+
+    let mut ports_by_inode: HashMap<u64, u16> = HashMap::new();
+    for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        if let Ok(content) = std::fs::read_to_string(table) {
+            ports_by_inode.extend(parse_proc_net_tcp(&content));
+        }
+    }
+
+    let mut sockets = BTreeSet::new();
+    let mut owned = BTreeSet::new();
+    for entry in std::fs::read_dir("/proc").ok()?.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|n| n.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        // Other users' processes are unreadable; their sockets are listed without an owner
+        let Ok(fds) = std::fs::read_dir(entry.path().join("fd")) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            let Some(&port) = std::fs::read_link(fd.path()).ok().and_then(|target| {
+                let target = target.to_string_lossy();
+                let inode = target
+                    .strip_prefix("socket:[")?
+                    .strip_suffix(']')?
+                    .parse::<u64>()
+                    .ok()?;
+                ports_by_inode.get(&inode)
+            }) else {
+                continue;
+            };
+            let process_name = std::fs::read_to_string(entry.path().join("comm"))
+                .ok()
+                .map(|name| name.trim().to_string());
+            owned.insert(port);
+            sockets.insert(ListeningSocket {
+                port,
+                pid: Some(pid),
+                process_name,
+            });
+        }
+    }
+
+    for &port in ports_by_inode.values() {
+        if !owned.contains(&port) {
+            sockets.insert(ListeningSocket {
+                port,
+                pid: None,
+                process_name: None,
+            });
+        }
+    }
+
+    Some(sockets.into_iter().collect())
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn native_listening_sockets() -> Option<Vec<ListeningSocket>> {
+    None
+}
+
+/// Extract the parent PID from the contents of `/proc/<pid>/stat`
+#[cfg(target_os = "linux")]
+fn parse_ppid(stat: &str) -> Option<u32> {
+    // The command name is parenthesized and may contain spaces
+    let after_comm = &stat[stat.rfind(')')? + 1..];
+    after_comm.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Parse `/proc/net/tcp{,6}` into (inode, port) pairs for LISTEN sockets
+#[cfg(any(target_os = "linux", test))]
+fn parse_proc_net_tcp(content: &str) -> Vec<(u64, u16)> {
+    const TCP_LISTEN: &str = "0A";
+
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 || fields[3] != TCP_LISTEN {
+                return None;
+            }
+            let port = u16::from_str_radix(fields[1].rsplit(':').next()?, 16).ok()?;
+            let inode = fields[9].parse().ok()?;
+            Some((inode, port))
+        })
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+fn native_listening_ports(pid: u32) -> Option<Vec<u16>> {
+    use libproc::libproc::bsd_info::BSDInfo;
+    use libproc::libproc::file_info::{pidfdinfo, ListFDs, ProcFDType};
+    use libproc::libproc::net_info::{SocketFDInfo, SocketInfoKind, TcpSIState};
+    use libproc::libproc::proc_pid::{listpidinfo, pidinfo};
+    use libproc::processes::{pids_by_type, ProcFilter};
+
+    let pids = process_tree(pid, |ppid| {
+        pids_by_type(ProcFilter::ByParentProcess { ppid }).unwrap_or_default()
+    });
+
+    let mut ports = BTreeSet::new();
+    for pid in pids {
+        let pid = pid as i32;
+        let Ok(info) = pidinfo::<BSDInfo>(pid, 0) else {
+            continue;
+        };
+        let Ok(fds) = listpidinfo::<ListFDs>(pid, info.pbi_nfiles as usize) else {
+            continue;
+        };
+        for fd in fds {
+            if !matches!(ProcFDType::from(fd.proc_fdtype), ProcFDType::Socket) {
+                continue;
+            }
+            let Ok(socket) = pidfdinfo::<SocketFDInfo>(pid, fd.proc_fd) else {
+                continue;
+            };
+            if !matches!(
+                SocketInfoKind::from(socket.psi.soi_kind),
+                SocketInfoKind::Tcp
+            ) {
+                continue;
+            }
+            // SAFETY: soi_kind says the protocol union holds TCP info
+            let tcp = unsafe { &socket.psi.soi_proto.pri_tcp };
+            if matches!(TcpSIState::from(tcp.tcpsi_state), TcpSIState::Listen) {
+                ports.insert(u16::from_be(tcp.tcpsi_ini.insi_lport as u16));
+            }
+        }
+    }
+
+    Some(ports.into_iter().collect())
+}
+
+/// Parent -> children map of every process, from WMI
+#[cfg(windows)]
+fn children_map() -> Option<std::collections::HashMap<u32, Vec<u32>>> {
+    let output = std::process::Command::new("powershell")
+        .args([
+            "-NoProfile",
+            "-Command",
+            "Get-CimInstance Win32_Process | ForEach-Object { \"$($_.ProcessId) $($_.ParentProcessId)\" }",
+        ])
+        .output()
+        .ok()?;
+    let mut children: std::collections::HashMap<u32, Vec<u32>> = Default::default();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let mut ids = line.split_whitespace().map(|id| id.parse::<u32>().ok());
+        if let (Some(Some(child)), Some(Some(ppid))) = (ids.next(), ids.next()) {
+            // The idle process is its own parent
+            if child != ppid {
+                children.entry(ppid).or_default().push(child);
+            }
+        }
+    }
+    Some(children)
+}
+
+/// Listening TCP sockets as (port, pid), from `netstat -ano`
+#[cfg(windows)]
+fn netstat_listening() -> Option<Vec<(u16, u32)>> {
+    let output = std::process::Command::new("netstat")
+        .args(["-ano", "-p", "TCP"])
+        .output()
+        .ok()?;
+    let mut sockets = parse_netstat(&String::from_utf8_lossy(&output.stdout));
+    // `-p TCP` covers IPv4 only
+    if let Ok(output) = std::process::Command::new("netstat")
+        .args(["-ano", "-p", "TCPv6"])
+        .output()
+    {
+        sockets.extend(parse_netstat(&String::from_utf8_lossy(&output.stdout)));
+    }
+    Some(sockets)
+}
+
+#[cfg(windows)]
+fn native_listening_ports(pid: u32) -> Option<Vec<u16>> {
+    let children = children_map()?;
+    let pids = process_tree(pid, |p| children.get(&p).cloned().unwrap_or_default());
+
+    let ports: BTreeSet<u16> = netstat_listening()?
+        .into_iter()
+        .filter(|(_, owner)| pids.contains(owner))
+        .map(|(port, _)| port)
+        .collect();
+    Some(ports.into_iter().collect())
+}
+
+#[cfg(windows)]
+fn native_listening_sockets() -> Option<Vec<ListeningSocket>> {
+    use std::collections::HashMap;
+
+    // `tasklist /FO CSV /NH`: "name.exe","pid",...
+    let names: HashMap<u32, String> = std::process::Command::new("tasklist")
+        .args(["/FO", "CSV", "/NH"])
+        .output()
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter_map(|line| {
+                    let mut fields = line.split("\",\"");
+                    let name = fields.next()?.trim_start_matches('"').to_string();
+                    Some((fields.next()?.parse().ok()?, name))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let sockets: BTreeSet<ListeningSocket> = netstat_listening()?
+        .into_iter()
+        .map(|(port, pid)| ListeningSocket {
+            port,
+            pid: Some(pid),
+            process_name: names.get(&pid).cloned(),
+        })
+        .collect();
+    Some(sockets.into_iter().collect())
+}
+
+/// Parse `netstat -ano` into (port, pid) pairs for LISTENING sockets
+///
+/// Rows look like `TCP    0.0.0.0:3000    0.0.0.0:0    LISTENING    1234`;
+/// IPv6 addresses are bracketed (`[::]:3000`).
+#[cfg(any(windows, test))]
+fn parse_netstat(content: &str) -> Vec<(u16, u32)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 5 || fields[0] != "TCP" || fields[3] != "LISTENING" {
+                return None;
+            }
+            let port = fields[1].rsplit(':').next()?.parse().ok()?;
+            let pid = fields[4].parse().ok()?;
+            Some((port, pid))
+        })
+        .collect()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn native_listening_ports(_pid: u32) -> Option<Vec<u16>> {
+    None
+}
+
+/// Detect listening ports using lsof (fallback)
+async fn lsof_listening_ports(pid: u32) -> Vec<u16> {
+    let Ok(output) = tokio::process::Command::new("lsof")
+        .args(["-i", "-P", "-n", "-a", "-p", &pid.to_string()])
+        .output()
+        .await
+    else {
+        return Vec::new();
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    // Parse lsof output to find LISTEN ports
+    // Format: COMMAND PID USER FD TYPE DEVICE SIZE/OFF NODE NAME
+    // Example: Python  93214 preetham    4u  IPv6 0x... 0t0  TCP *:3002 (LISTEN)
+    let mut ports = BTreeSet::new();
+    for line in stdout.lines().filter(|line| line.contains("(LISTEN)")) {
+        // The NAME column is the last one before "(LISTEN)": host:port
+        let port = line
+            .split_whitespace()
+            .rev()
+            .find(|part| *part != "(LISTEN)")
+            .and_then(|name| name.rsplit(':').next())
+            .and_then(|port| port.parse::<u16>().ok());
+        ports.extend(port);
+    }
+
+    ports.into_iter().collect()
+}
+
+/// List all listening sockets using lsof (fallback)
+async fn lsof_listening_sockets() -> Vec<ListeningSocket> {
+    let Ok(output) = tokio::process::Command::new("lsof")
+        .args(["-nP", "-iTCP", "-sTCP:LISTEN", "-F", "pcn"])
+        .output()
+        .await
+    else {
+        return Vec::new();
+    };
+
+    // Field output: `p<pid>` and `c<command>` start a process, `n<host:port>` per socket
+    let mut sockets = BTreeSet::new();
+    let (mut pid, mut command) = (None, None);
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let (field, value) = line.split_at(line.len().min(1));
+        match field {
+            "p" => pid = value.parse().ok(),
+            "c" => command = Some(value.to_string()),
+            "n" => {
+                if let Some(port) = value.rsplit(':').next().and_then(|p| p.parse().ok()) {
+                    sockets.insert(ListeningSocket {
+                        port,
+                        pid,
+                        process_name: command.clone(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    sockets.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocate() {
+        let range = PortRange {
+            start: 4000,
+            end: 4009,
+        };
+
+        // Same project, same port
+        let port = allocate("my-app", range, |_| false).unwrap();
+        assert!((4000..=4009).contains(&port));
+        assert_eq!(allocate("my-app", range, |_| false), Some(port));
+
+        // Taken ports are skipped, wrapping around the range
+        let next = allocate("my-app", range, |p| p == port).unwrap();
+        assert_ne!(next, port);
+        assert!((4000..=4009).contains(&next));
+
+        assert_eq!(allocate("my-app", range, |_| true), None);
+    }
+
+    #[test]
+    fn test_announced_debugger_port() {
+        let node = "Debugger listening on ws://127.0.0.1:9229/5c2f8c4e-0f7a-4c1b-9d7e-3b1f2a6d9e10";
+        assert_eq!(announced_debugger_port(node), Some(9229));
+        let delve = "API server listening at: [::]:2345";
+        assert_eq!(announced_debugger_port(delve), Some(2345));
+        let server = "Listening on http://localhost:3000";
+        assert_eq!(announced_debugger_port(server), None);
+    }
+
+    #[test]
+    fn test_parse_proc_net_tcp() {
+        let content = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:0BB8 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 41234 1 0000000000000000 100 0 0 10 0
+   1: 0100007F:0BB8 0100007F:D2F0 01 00000000:00000000 00:00000000 00000000  1000        0 41299 1 0000000000000000 20 4 30 10 -1
+   2: 00000000:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 50000 1 0000000000000000 100 0 0 10 0";
+
+        assert_eq!(
+            parse_proc_net_tcp(content),
+            vec![(41234, 3000), (50000, 8080)]
+        );
+    }
+
+    #[test]
+    fn test_parse_netstat() {
+        let content = "
+Active Connections
+
+  Proto  Local Address          Foreign Address        State           PID
+  TCP    0.0.0.0:3000           0.0.0.0:0              LISTENING       4412
+  TCP    127.0.0.1:3000         127.0.0.1:51234        ESTABLISHED     4412
+  TCP    [::]:8080              [::]:0                 LISTENING       916
+  UDP    0.0.0.0:5353           *:*                                    2100";
+
+        assert_eq!(parse_netstat(content), vec![(3000, 4412), (8080, 916)]);
+    }
+}
@@ -1,41 +1,223 @@
 //! Process management - spawning, monitoring, and port detection
 
 use anyhow::{Context, Result};
-use chrono::Utc;
-use nix::sys::signal::{self, Signal};
-use nix::unistd::Pid;
-use proj_common::{ProcessInfo, ProcessStatus};
-use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use proj_common::profile::Profile;
+use proj_common::store::Store;
+use proj_common::{
+    project_dir, Config, EventKind, HealthCheck, IpcError, JobInfo, JobStage, LogLine, Milestone,
+    PortPolicy, PortRange, Priority, ProcessInfo, ProcessStatus, RemoteError, ResourceLimits,
+    ResourceUsage, RestartPolicy, RunRecord, Sandbox, SmokeCheck, TerminalSize, WatchOptions,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::pin::Pin;
 use std::process::Stdio;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::{Child, Command};
-use tokio::sync::mpsc;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::AbortHandle;
+use tokio::time::Duration;
 use uuid::Uuid;
 
+use crate::platform::{self, command_line, is_alive, tree_alive, Stop};
+
+use crate::env;
+use crate::events::EventBus;
+use crate::health;
+use crate::limits;
+use crate::milestones;
+use crate::output::{Fanout, FileTee, Forward};
+use crate::ports;
+use crate::remedies;
+use crate::sandbox;
+use crate::tty::{self, Tty};
+use crate::watcher::{self, WatchHandle};
+
+/// Initial delay before restarting a crashed process
+const RESTART_BACKOFF_BASE: Duration = Duration::from_secs(1);
+
+/// Upper bound on the delay between restarts
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// How long a terminal's output is still read after its process exits
+const TTY_DRAIN_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How long to wait for a process group to die after SIGKILL
+const KILL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Calls on the process manager that can wait before callers are held up
+const CALL_QUEUE: usize = 256;
+
+/// How far the start time the OS reports for a PID may be from a record's
+/// and still be its process: `ps` counts whole seconds, and a spawn is
+/// recorded just after the launch
+const START_SLACK_SECS: i64 = 5;
+
 /// Event from a managed process
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub enum ProcessEvent {
-    /// Process output (stdout or stderr)
-    Output {
-        process_id: Uuid,
-        line: String,
-        is_stderr: bool,
-    },
+    /// A line of process output (stdout or stderr)
+    Output(LogLine),
     /// Process exited
     Exited {
         process_id: Uuid,
         exit_code: Option<i32>,
+        /// Signal that killed it, on Unix
+        signal: Option<i32>,
     },
-    /// Port detected
-    PortDetected { process_id: Uuid, port: u16 },
+    /// Port detected (`port` is the primary one out of all listening `ports`)
+    PortDetected {
+        process_id: Uuid,
+        port: u16,
+        ports: Vec<u16>,
+        /// Whether it was seen listening, rather than assigned ahead of that
+        bound: bool,
+    },
+    /// Watched project files changed
+    FilesChanged { process_id: Uuid },
+    /// A service's health check started passing or failing
+    Health { process_id: Uuid, healthy: bool },
 }
 
 /// A managed child process
 struct ManagedProcess {
     info: ProcessInfo,
-    #[allow(dead_code)]
-    child: Child,
+    /// How the process was launched; `None` for re-adopted processes
+    spec: Option<SpawnSpec>,
+    /// Set when the user stopped the process, suppressing restarts
+    stop_requested: bool,
+    /// Set when the process is being restarted on purpose (e.g. file changes)
+    restart_pending: bool,
+    /// Active file watcher for `--watch` runs
+    watcher: Option<WatchHandle>,
+    /// Running health check and the port it probes
+    health: Option<(u16, AbortHandle)>,
+    /// Startup milestones the current run has reached
+    milestones: HashSet<Milestone>,
+    /// Resource limit the current run hit, explaining its failure
+    limit_reason: Option<String>,
+    /// Terminal of a run with `tty`
+    tty: Option<Arc<Tty>>,
+    /// When the process last exited, for pruning its record; `None` while
+    /// it runs
+    exited_at: Option<DateTime<Utc>>,
+}
+
+/// Everything needed to launch (or relaunch) a process
+#[derive(Debug, Clone)]
+pub struct SpawnSpec {
+    pub command: String,
+    pub args: Vec<String>,
+    pub working_dir: PathBuf,
+    pub restart_policy: RestartPolicy,
+    pub max_restarts: u32,
+    pub watch: Option<WatchOptions>,
+    /// Probe gating the process's route
+    pub health: Option<HealthCheck>,
+    /// The project's smoke checks, for a health check that runs them
+    pub smoke: Vec<SmokeCheck>,
+    /// Service this process is an instance of
+    pub service: Option<String>,
+    /// Variables layered over the project environment
+    pub env: BTreeMap<String, String>,
+    pub port: PortPolicy,
+    /// File stdout and stderr are appended to, besides the log store
+    pub output: Option<PathBuf>,
+    /// Chained job this run is a stage of
+    pub job: Option<JobInfo>,
+    /// CPU and I/O priority, from the project
+    pub priority: Priority,
+    /// Env profiles layered under the project's variables, from the project
+    pub env_profiles: Vec<String>,
+    /// Confinement, from the project or the run
+    pub sandbox: Sandbox,
+    /// Command run after a successful exit
+    pub then: Option<Vec<String>>,
+    /// Command run after a failed exit
+    pub otherwise: Option<Vec<String>>,
+    /// Resource caps, from the run
+    pub limits: ResourceLimits,
+    /// Ports named by the service, besides the routed one
+    pub named_ports: BTreeMap<String, u16>,
+    /// Profile the run writes, from the run
+    pub profile: Option<Profile>,
+    /// Size of the terminal to run in, from the run; `None` pipes the output
+    pub tty: Option<TerminalSize>,
+    /// The command line the run asked for, when a profiler wrapping it
+    /// changed `command` and `args`
+    pub requested: Option<Vec<String>>,
+}
+
+impl SpawnSpec {
+    /// Whether this is a run of `command` with `args`, as it was asked for
+    fn runs(&self, command: &str, args: &[String]) -> bool {
+        match &self.requested {
+            Some(argv) => argv
+                .split_first()
+                .is_some_and(|(c, a)| c == command && a == args),
+            None => self.command == command && self.args == args,
+        }
+    }
+}
+
+/// What launching a spec takes that doesn't need the manager: its
+/// environment and its command wrapped in confinement and resource caps.
+/// Worked out before calling the manager, so reading env files or probing
+/// cgroups doesn't hold up its other callers.
+pub struct Prepared {
+    vars: BTreeMap<String, String>,
+    program: String,
+    args: Vec<String>,
+    /// Where the command resolves to, if it could be found
+    pub command_path: Option<PathBuf>,
+    /// Problems found along the way that don't stop the launch
+    pub warnings: Vec<String>,
+    /// What to try instead if the command can't be found
+    suggestions: Vec<String>,
+}
+
+/// Prepare `spec` to be launched as a process of `project_name`
+pub async fn prepare(project_name: &str, spec: &SpawnSpec) -> Result<Prepared> {
+    // Project variables are re-read on every launch, with the service's on
+    // top; a PORT set in either wins over the port policy
+    let mut vars: BTreeMap<String, String> =
+        env::resolve(project_name, &spec.working_dir, &spec.env_profiles)
+            .into_iter()
+            .map(|var| (var.key, var.value))
+            .collect();
+    vars.extend(spec.env.clone());
+    let warnings = env::warnings(&spec.working_dir, &spec.env_profiles);
+    let path = vars
+        .get("PATH")
+        .map(OsString::from)
+        .or_else(|| std::env::var_os("PATH"));
+    let command_path = platform::resolve_program(&spec.command, &spec.working_dir, path.as_deref());
+    let suggestions = match command_path {
+        Some(_) => Vec::new(),
+        None => remedies::not_found(&spec.command, &spec.working_dir, path.as_deref()),
+    };
+    // Sandboxed processes may write to their directory and the project's data
+    let (program, args) = sandbox::wrap(
+        &spec.command,
+        &spec.args,
+        &spec.sandbox,
+        &[spec.working_dir.clone(), project_dir(project_name)?],
+    )?;
+    let (program, args) = limits::wrap(&program, &args, &spec.limits).await;
+    Ok(Prepared {
+        vars,
+        program,
+        args,
+        command_path,
+        warnings,
+        suggestions,
+    })
 }
 
 /// Process manager handles spawning and monitoring processes
@@ -43,97 +225,279 @@ pub struct ProcessManager {
     processes: HashMap<Uuid, ManagedProcess>,
     event_tx: mpsc::Sender<ProcessEvent>,
     event_rx: Option<mpsc::Receiver<ProcessEvent>>,
+    /// Time between SIGTERM and SIGKILL when stopping a process
+    stop_grace: Duration,
+    /// Ports handed out to spawned processes
+    port_range: PortRange,
+    /// Domain for `PROJECT_HOST`
+    tld: String,
+    /// Where the proxy listens, for `HTTP_PROXY`
+    proxy_addr: SocketAddr,
+    /// Projects whose processes go through the proxy, for its hostname overrides
+    proxied: HashSet<String>,
+    events: EventBus,
+    /// Where process records and run history are kept
+    store: Store,
 }
 
 impl ProcessManager {
-    pub fn new() -> Self {
+    pub fn new(config: &Config, events: EventBus, store: Store) -> Self {
         let (event_tx, event_rx) = mpsc::channel(100);
-        Self {
+        let mut manager = Self {
             processes: HashMap::new(),
             event_tx,
             event_rx: Some(event_rx),
+            stop_grace: Duration::default(),
+            port_range: PortRange::default(),
+            tld: String::new(),
+            proxy_addr: config.proxy_addr(),
+            proxied: HashSet::new(),
+            events,
+            store,
+        };
+        manager.apply_config(config);
+        manager
+    }
+
+    /// Take up changed settings; running processes keep what they started with
+    pub fn apply_config(&mut self, config: &Config) {
+        self.stop_grace = Duration::from_secs(config.stop_grace_secs);
+        self.port_range = config.port_range;
+        self.tld = config.tld().to_string();
+        self.proxy_addr = config.proxy_addr();
+    }
+
+    /// Set whether a project's processes send their HTTP(S) requests through
+    /// the proxy, applied to processes started from now on
+    pub fn set_proxied(&mut self, project_name: &str, proxied: bool) {
+        if proxied {
+            self.proxied.insert(project_name.to_string());
+        } else {
+            self.proxied.remove(project_name);
         }
     }
 
+    /// Bus that process lifecycle events are published on
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
     /// Take the event receiver (can only be called once)
     pub fn take_event_receiver(&mut self) -> Option<mpsc::Receiver<ProcessEvent>> {
         self.event_rx.take()
     }
 
-    /// Spawn a new process for a project
+    /// Spawn a new process for a project, from a spec [`prepare`]d for it
     pub async fn spawn(
         &mut self,
         project_name: String,
-        command: &str,
-        args: &[String],
-        working_dir: &std::path::Path,
+        spec: SpawnSpec,
+        prepared: Prepared,
     ) -> Result<ProcessInfo> {
         let process_id = Uuid::new_v4();
 
-        // Build the command
-        let mut cmd = Command::new(command);
-        cmd.args(args)
-            .current_dir(working_dir)
-            .env("PROJECT_ID", &project_name)
-            .env("PROJECT_HOST", format!("{}.localhost", project_name))
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .kill_on_drop(true);
+        // Start every run with an empty file; restarts and later stages of
+        // its job append to it
+        let first_stage = spec.job.is_none_or(|job| job.stage == JobStage::Run);
+        if let (Some(path), true) = (&spec.output, first_stage) {
+            std::fs::File::create(path)
+                .with_context(|| format!("Failed to create output file {:?}", path))?;
+        }
 
-        let mut child = cmd.spawn().context("Failed to spawn process")?;
+        let (pid, port, tty) = self
+            .launch(process_id, &project_name, &spec, prepared)
+            .await?;
 
-        let pid = child.id().context("Failed to get process ID")?;
+        let watcher = match &spec.watch {
+            Some(options) => Some(watcher::watch(
+                process_id,
+                &spec.working_dir,
+                options,
+                self.event_tx.clone(),
+            )?),
+            None => None,
+        };
 
         let info = ProcessInfo {
             id: process_id,
             project_name: project_name.clone(),
             pid,
-            command: format!("{} {}", command, args.join(" ")),
+            command: format!("{} {}", spec.command, spec.args.join(" ")),
+            service: spec.service.clone(),
             started_at: Utc::now(),
-            port: None,
+            port,
+            ports: port.into_iter().collect(),
+            named_ports: named_ports(&spec, port),
             status: ProcessStatus::Running,
+            healthy: None,
+            restart_policy: spec.restart_policy,
+            restart_count: 0,
+            last_exit_code: None,
+            watching: watcher.is_some(),
+            usage: None,
+            output: spec.output.clone(),
+            job: spec.job,
+            primary: false,
+            limits: spec.limits,
+            failure_reason: None,
+            profile: spec.profile.clone(),
         };
 
-        // Capture stdout
-        if let Some(stdout) = child.stdout.take() {
-            let tx = self.event_tx.clone();
-            let id = process_id;
+        let managed = ManagedProcess {
+            info: info.clone(),
+            spec: Some(spec),
+            stop_requested: false,
+            restart_pending: false,
+            watcher,
+            health: None,
+            milestones: HashSet::new(),
+            limit_reason: None,
+            tty,
+            exited_at: None,
+        };
+        self.processes.insert(process_id, managed);
+        self.persist();
+
+        tracing::info!(
+            project = %project_name,
+            process_id = %process_id,
+            pid,
+            "Spawned process"
+        );
+        self.emit_started(&info);
+
+        Ok(info)
+    }
+
+    /// Start the OS process for a spec and attach output, exit and port monitors.
+    ///
+    /// Returns the PID, the port assigned through `PORT` if any, and the
+    /// terminal of a run with `tty`.
+    async fn launch(
+        &self,
+        process_id: Uuid,
+        project_name: &str,
+        spec: &SpawnSpec,
+        prepared: Prepared,
+    ) -> Result<(u32, Option<u16>, Option<Arc<Tty>>)> {
+        let Prepared {
+            mut vars,
+            program,
+            args,
+            suggestions,
+            ..
+        } = prepared;
+        // The user in the proxy URL tells the proxy whose overrides apply
+        if self.proxied.contains(project_name) {
+            let url = format!("http://{}@{}", project_name, self.proxy_addr);
+            for key in ["HTTP_PROXY", "HTTPS_PROXY", "http_proxy", "https_proxy"] {
+                vars.entry(key.to_string()).or_insert_with(|| url.clone());
+            }
+            for key in ["NO_PROXY", "no_proxy"] {
+                vars.entry(key.to_string())
+                    .or_insert_with(|| "localhost,127.0.0.1,::1".to_string());
+            }
+        }
+        let port = match (vars.get("PORT"), spec.port) {
+            (Some(value), _) => value.parse().ok(),
+            (None, PortPolicy::Auto) => {
+                let key = match &spec.service {
+                    Some(service) => format!("{}/{}", project_name, service),
+                    None => project_name.to_string(),
+                };
+                self.allocate_port(process_id, &key)
+            }
+            (None, PortPolicy::Fixed(port)) => {
+                self.check_port(process_id, port).await?;
+                Some(port)
+            }
+            (None, PortPolicy::Detect) => None,
+        };
+
+        let output = match &spec.output {
+            Some(path) => Some(
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Failed to open output file {:?}", path))?,
+            ),
+            None => None,
+        };
+
+        if let Some(size) = spec.tty {
+            if let Some(port) = port {
+                vars.insert("PORT".to_string(), port.to_string());
+                vars.insert("PROJECT_PORT".to_string(), port.to_string());
+            }
+            vars.insert("PROJECT_ID".to_string(), project_name.to_string());
+            vars.insert(
+                "PROJECT_HOST".to_string(),
+                format!("{}.{}", project_name, self.tld),
+            );
+            let spawned =
+                tty::spawn(&program, &args, &spec.working_dir, &vars, size).map_err(|e| {
+                    IpcError::SpawnFailed {
+                        command: spec.command.clone(),
+                        source: RemoteError(format!("{:#}", e)),
+                        suggestions,
+                    }
+                })?;
+            let pid = spawned.pid;
+            let tty = spawned.tty.clone();
+            let sinks = self.output_sinks(output.as_ref());
+            self.monitor_tty(spawned, output_line(process_id, project_name, spec), sinks);
+            self.start_port_detection(process_id, pid, port).await;
+            return Ok((pid, port, Some(tty)));
+        }
+
+        let mut cmd = Command::new(&program);
+        cmd.args(&args).current_dir(&spec.working_dir);
+        if let Some(port) = port {
+            cmd.env("PORT", port.to_string())
+                .env("PROJECT_PORT", port.to_string());
+        }
+        cmd.envs(vars)
+            .env("PROJECT_ID", project_name)
+            .env("PROJECT_HOST", format!("{}.{}", project_name, self.tld))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        // No kill_on_drop: whether processes outlive the daemon is decided by
+        // the exit policy in `shutdown`, and survivors are re-adopted on start
+
+        platform::isolate(&mut cmd);
+        limits::set_open_files(&mut cmd, &spec.limits);
+
+        let mut child = cmd.spawn().map_err(|e| IpcError::SpawnFailed {
+            command: spec.command.clone(),
+            source: RemoteError(e.to_string()),
+            suggestions,
+        })?;
+
+        let pid = child.id().context("Failed to get process ID")?;
+        let priority = limits::priority(&spec.limits, &spec.priority);
+        if priority != Priority::default() {
+            // Runs renice and the like, so it's left to finish on its own
             tokio::spawn(async move {
-                let reader = BufReader::new(stdout);
-                let mut lines = reader.lines();
-                while let Ok(Some(line)) = lines.next_line().await {
-                    // Print to daemon stdout for visibility
-                    println!("[{}] {}", id, line);
-                    let _ = tx
-                        .send(ProcessEvent::Output {
-                            process_id: id,
-                            line,
-                            is_stderr: false,
-                        })
-                        .await;
+                if let Err(e) = platform::set_priority(pid, &priority).await {
+                    tracing::warn!("Failed to set priority of {}: {}", pid, e);
                 }
             });
         }
 
-        // Capture stderr
+        // Capture stdout and stderr
+        let line = output_line(process_id, project_name, spec);
+        if let Some(stdout) = child.stdout.take() {
+            let sinks = self.output_sinks(output.as_ref());
+            tokio::spawn(read_output(stdout, line.clone(), sinks));
+        }
         if let Some(stderr) = child.stderr.take() {
-            let tx = self.event_tx.clone();
-            let id = process_id;
-            tokio::spawn(async move {
-                let reader = BufReader::new(stderr);
-                let mut lines = reader.lines();
-                while let Ok(Some(line)) = lines.next_line().await {
-                    // Print to daemon stderr for visibility
-                    eprintln!("[{}] {}", id, line);
-                    let _ = tx
-                        .send(ProcessEvent::Output {
-                            process_id: id,
-                            line,
-                            is_stderr: true,
-                        })
-                        .await;
-                }
-            });
+            let sinks = self.output_sinks(output.as_ref());
+            let line = LogLine {
+                is_stderr: true,
+                ..line
+            };
+            tokio::spawn(read_output(stderr, line, sinks));
         }
 
         // Monitor for process exit
@@ -141,76 +505,711 @@ impl ProcessManager {
         let id = process_id;
         let mut child_for_wait = child;
         tokio::spawn(async move {
-            let status = child_for_wait.wait().await;
-            let exit_code = status.ok().and_then(|s| s.code());
+            let status = child_for_wait.wait().await.ok();
+            let exit_code = status.and_then(|s| s.code());
+            let signal = status.and_then(|s| platform::exit_signal(&s));
             let _ = tx
                 .send(ProcessEvent::Exited {
                     process_id: id,
                     exit_code,
+                    signal,
                 })
                 .await;
         });
 
         // Start port detection
-        self.start_port_detection(process_id, pid).await;
+        self.start_port_detection(process_id, pid, port).await;
 
-        // We can't store the child after spawning wait task, so create a dummy
-        // In a real implementation, we'd use a different approach
-        let dummy_child = Command::new("true").spawn()?;
+        Ok((pid, port, None))
+    }
 
-        let managed = ManagedProcess {
-            info: info.clone(),
-            child: dummy_child,
+    /// Where one stream of a process's output goes: its output file, if it
+    /// has one, and the log store
+    fn output_sinks(&self, output: Option<&std::fs::File>) -> Fanout {
+        let mut sinks = Fanout::default();
+        if let Some(tee) = output.and_then(FileTee::share) {
+            sinks.push(tee);
+        }
+        sinks.push(Forward(self.event_tx.clone()));
+        sinks
+    }
+
+    /// Write a terminal's output to `sinks` as lines like `template` and
+    /// pass it to attached clients, then report the exit of its process
+    fn monitor_tty(&self, spawned: tty::Spawned, template: LogLine, mut sinks: Fanout) {
+        let process_id = template.process_id;
+        let tx = self.event_tx.clone();
+        let tty::Spawned {
+            tty,
+            mut child,
+            mut output,
+            ..
+        } = spawned;
+        tokio::spawn(async move {
+            let mut wait = tokio::task::spawn_blocking(move || child.wait());
+            let mut lines = tty::Lines::default();
+            let mut status = None;
+            loop {
+                let bytes = tokio::select! {
+                    bytes = output.recv() => bytes,
+                    exited = &mut wait, if status.is_none() => {
+                        status = Some(exited);
+                        continue;
+                    }
+                    // Children it left behind may hold the terminal open
+                    _ = tokio::time::sleep(TTY_DRAIN_TIMEOUT), if status.is_some() => None,
+                };
+                let Some(bytes) = bytes else { break };
+                tty.push(&bytes);
+                for line in lines.push(&bytes) {
+                    sinks.write(&printed(&template, line)).await;
+                }
+            }
+            if let Some(line) = lines.finish() {
+                sinks.write(&printed(&template, line)).await;
+            }
+
+            let status = match status {
+                Some(status) => status,
+                None => wait.await,
+            };
+            // A process killed by a signal has no exit code
+            let exit_code = match status {
+                Ok(Ok(status)) if status.signal().is_none() => Some(status.exit_code() as i32),
+                _ => None,
+            };
+            tty.close(exit_code);
+            let _ = tx
+                .send(ProcessEvent::Exited {
+                    process_id,
+                    exit_code,
+                    signal: None,
+                })
+                .await;
+        });
+    }
+
+    /// The terminal of a process run with `tty`, while it's running
+    pub fn tty(&self, process_id: Uuid) -> Option<Arc<Tty>> {
+        let managed = self.processes.get(&process_id)?;
+        managed
+            .tty
+            .clone()
+            .filter(|_| managed.info.status.is_alive())
+    }
+
+    /// Fail with [`IpcError::PortConflict`] if something else holds a fixed port
+    async fn check_port(&self, process_id: Uuid, port: u16) -> Result<()> {
+        let managed = self.processes.values().find(|m| {
+            m.info.id != process_id
+                && m.info.status.is_alive()
+                && (m.info.port == Some(port) || m.info.ports.contains(&port))
+        });
+        let pid = match managed {
+            Some(managed) => Some(managed.info.pid),
+            None if ports::is_free(port) => return Ok(()),
+            None => ports::listening_sockets()
+                .await
+                .into_iter()
+                .find(|socket| socket.port == port)
+                .and_then(|socket| socket.pid),
         };
-        self.processes.insert(process_id, managed);
+        Err(IpcError::PortConflict { port, pid }.into())
+    }
 
+    /// Pick a free port for a process, avoiding ports other processes use.
+    ///
+    /// `key` (the project, or `project/service`) picks where the search starts.
+    fn allocate_port(&self, process_id: Uuid, key: &str) -> Option<u16> {
+        let in_use: Vec<u16> = self
+            .processes
+            .values()
+            .filter(|m| m.info.id != process_id && m.info.status.is_alive())
+            .flat_map(|m| m.info.port.into_iter().chain(m.info.ports.iter().copied()))
+            .collect();
+
+        let port = ports::allocate(key, self.port_range, |port| {
+            in_use.contains(&port) || !ports::is_free(port)
+        });
+        if port.is_none() {
+            tracing::warn!(
+                "No free port in {}-{} for {}",
+                self.port_range.start,
+                self.port_range.end,
+                key
+            );
+        }
+        port
+    }
+
+    /// Record an exit and decide whether the restart policy wants a respawn.
+    ///
+    /// Returns the backoff delay to wait before calling [`Self::respawn`].
+    pub fn record_exit(
+        &mut self,
+        process_id: Uuid,
+        exit_code: Option<i32>,
+        signal: Option<i32>,
+    ) -> Option<Duration> {
+        let managed = self.processes.get_mut(&process_id)?;
+
+        if let Some((_, check)) = managed.health.take() {
+            check.abort();
+        }
+
+        managed.info.last_exit_code = exit_code;
+        managed.info.usage = None;
+        managed.exited_at = Some(Utc::now());
+        managed.info.failure_reason = None;
+        let limit_reason = managed
+            .limit_reason
+            .take()
+            .or_else(|| limits::reason_from_exit(&managed.info.limits, signal));
+
+        if managed.restart_pending && !managed.stop_requested {
+            managed.restart_pending = false;
+            managed.info.status = ProcessStatus::Stopped;
+            let info = managed.info.clone();
+            self.emit_exited(&info);
+            self.persist();
+            return Some(Duration::ZERO);
+        }
+
+        managed.info.status = if exit_code == Some(0) || managed.stop_requested {
+            ProcessStatus::Stopped
+        } else {
+            ProcessStatus::Failed
+        };
+        if managed.info.status == ProcessStatus::Failed {
+            if let Some(reason) = limit_reason {
+                tracing::warn!(
+                    project = %managed.info.project_name,
+                    process_id = %managed.info.id,
+                    "{}",
+                    reason
+                );
+                managed.info.failure_reason = Some(reason);
+            }
+        }
+
+        let wants_restart = match managed.info.restart_policy {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => exit_code != Some(0),
+            RestartPolicy::Always => true,
+        };
+        let max_restarts = managed.spec.as_ref().map(|s| s.max_restarts).unwrap_or(0);
+        let restart =
+            wants_restart && !managed.stop_requested && managed.info.restart_count < max_restarts;
+
+        let delay = restart.then(|| restart_backoff(managed.info.restart_count));
+        let info = managed.info.clone();
+        self.emit_exited(&info);
+        self.persist();
+        delay
+    }
+
+    /// Spec of the stage to launch after a chained run exited for good:
+    /// `then` after exit code 0, `else` otherwise. Stopped runs end their job.
+    pub fn next_stage(&self, process_id: Uuid) -> Option<SpawnSpec> {
+        let managed = self.processes.get(&process_id)?;
+        let spec = managed.spec.as_ref()?;
+        let job = spec.job?;
+        if managed.stop_requested || managed.info.status.is_alive() {
+            return None;
+        }
+
+        let (stage, command) = if managed.info.last_exit_code == Some(0) {
+            (JobStage::Then, spec.then.as_ref()?)
+        } else {
+            (JobStage::Else, spec.otherwise.as_ref()?)
+        };
+        let (program, args) = command.split_first()?;
+        Some(SpawnSpec {
+            command: program.clone(),
+            args: args.to_vec(),
+            working_dir: spec.working_dir.clone(),
+            restart_policy: RestartPolicy::Never,
+            max_restarts: 0,
+            watch: None,
+            health: None,
+            smoke: Vec::new(),
+            service: spec.service.clone(),
+            env: spec.env.clone(),
+            port: spec.port,
+            output: spec.output.clone(),
+            job: Some(JobInfo { id: job.id, stage }),
+            priority: spec.priority.clone(),
+            env_profiles: spec.env_profiles.clone(),
+            sandbox: spec.sandbox.clone(),
+            then: None,
+            otherwise: None,
+            limits: spec.limits,
+            named_ports: spec.named_ports.clone(),
+            profile: None,
+            tty: None,
+            requested: None,
+        })
+    }
+
+    /// Terminate a running process so that it is respawned once it exits
+    pub fn request_restart(&mut self, process_id: Uuid) -> Result<()> {
+        let managed = self
+            .processes
+            .get_mut(&process_id)
+            .ok_or_else(|| process_not_found(process_id))?;
+        if !managed.info.status.is_alive() || managed.restart_pending {
+            return Ok(());
+        }
+
+        managed.restart_pending = true;
+        tokio::spawn(terminate(managed.info.pid, self.stop_grace));
         tracing::info!(
-            "Spawned process {} (pid: {}) for project {}",
-            process_id,
+            project = %managed.info.project_name,
+            process_id = %process_id,
+            "Restarting process"
+        );
+        Ok(())
+    }
+
+    /// Whether a process has a spawn spec to be respawned from (adopted ones don't)
+    pub fn can_respawn(&self, process_id: Uuid) -> bool {
+        self.processes
+            .get(&process_id)
+            .is_some_and(|m| m.spec.is_some())
+    }
+
+    /// The project and spec an exited process is respawned with, unless it
+    /// was stopped
+    pub fn respawn_spec(&self, process_id: Uuid) -> Result<(String, SpawnSpec)> {
+        let managed = self
+            .processes
+            .get(&process_id)
+            .ok_or_else(|| process_not_found(process_id))?;
+        if managed.stop_requested {
+            anyhow::bail!("Process {} was stopped", process_id);
+        }
+        let spec = managed
+            .spec
+            .clone()
+            .context("Process has no spawn spec to restart from")?;
+        Ok((managed.info.project_name.clone(), spec))
+    }
+
+    /// Respawn an exited process with its original command, keeping its ID,
+    /// from its [`Self::respawn_spec`] [`prepare`]d again
+    pub async fn respawn(&mut self, process_id: Uuid, prepared: Prepared) -> Result<ProcessInfo> {
+        // Stopped while it was being prepared, perhaps
+        let (project_name, spec) = self.respawn_spec(process_id)?;
+
+        let (pid, port, tty) = self
+            .launch(process_id, &project_name, &spec, prepared)
+            .await?;
+
+        let managed = self
+            .processes
+            .get_mut(&process_id)
+            .ok_or_else(|| process_not_found(process_id))?;
+        managed.info.pid = pid;
+        managed.info.started_at = Utc::now();
+        managed.info.port = port;
+        managed.info.ports = port.into_iter().collect();
+        managed.info.named_ports = named_ports(&spec, port);
+        managed.info.status = ProcessStatus::Running;
+        managed.info.healthy = None;
+        managed.info.restart_count += 1;
+        managed.milestones.clear();
+        managed.limit_reason = None;
+        managed.tty = tty;
+        managed.exited_at = None;
+        let info = managed.info.clone();
+        self.persist();
+
+        tracing::info!(
+            project = %info.project_name,
+            process_id = %process_id,
             pid,
-            project_name
+            restart = info.restart_count,
+            "Restarted process"
         );
+        self.emit_started(&info);
+        Ok(info)
+    }
+
+    /// Restore process records persisted by a previous daemon instance.
+    ///
+    /// Processes still running under their PID are re-adopted: their
+    /// liveness is polled and port detection is re-run so the routing table
+    /// gets repopulated. The rest are marked as stopped, including those
+    /// whose PID the OS has since given to another process.
+    pub async fn restore(&mut self) -> Result<()> {
+        let records = self.store.processes()?;
+
+        let mut adopted = 0;
+        for mut info in records {
+            if info.status.is_alive() {
+                if still_running(&info).await {
+                    // Health checks aren't re-run for adopted processes
+                    info.status = ProcessStatus::Running;
+                    self.watch_adopted(info.id, info.pid);
+                    self.start_port_detection(info.id, info.pid, None).await;
+                    adopted += 1;
+                } else {
+                    if is_alive(info.pid) {
+                        tracing::info!(
+                            project = %info.project_name,
+                            process_id = %info.id,
+                            pid = info.pid,
+                            "PID now belongs to another process, not re-adopting"
+                        );
+                    }
+                    info.status = ProcessStatus::Stopped;
+                    self.record_run_end(&info);
+                }
+            }
+            let alive = info.status.is_alive();
+            self.processes.insert(
+                info.id,
+                ManagedProcess {
+                    info,
+                    spec: None,
+                    stop_requested: false,
+                    restart_pending: false,
+                    watcher: None,
+                    health: None,
+                    // Started before this daemon saw it, so there's no startup to time
+                    milestones: Milestone::ALL.into(),
+                    limit_reason: None,
+                    tty: None,
+                    // Counted from now, so records the last daemon kept stay a while
+                    exited_at: (!alive).then(Utc::now),
+                },
+            );
+        }
+        self.persist();
+
+        tracing::info!(
+            "Restored {} process records ({} re-adopted)",
+            self.processes.len(),
+            adopted
+        );
+        Ok(())
+    }
+
+    /// Start managing a process that was launched outside proj
+    pub async fn adopt(&mut self, project_name: String, pid: u32) -> Result<ProcessInfo> {
+        if !is_alive(pid) {
+            anyhow::bail!("No running process with PID {}", pid);
+        }
+        if self
+            .processes
+            .values()
+            .any(|m| m.info.pid == pid && m.info.status.is_alive())
+        {
+            anyhow::bail!("Process {} is already managed", pid);
+        }
+
+        let process_id = Uuid::new_v4();
+        let info = ProcessInfo {
+            id: process_id,
+            project_name: project_name.clone(),
+            pid,
+            command: command_line(pid).await.unwrap_or_else(|| pid.to_string()),
+            service: None,
+            // What a later daemon checks the PID against before re-adopting it
+            started_at: platform::started_at(pid).await.unwrap_or_else(Utc::now),
+            port: None,
+            ports: Vec::new(),
+            named_ports: BTreeMap::new(),
+            status: ProcessStatus::Running,
+            healthy: None,
+            restart_policy: RestartPolicy::Never,
+            restart_count: 0,
+            last_exit_code: None,
+            watching: false,
+            usage: None,
+            output: None,
+            job: None,
+            primary: false,
+            limits: ResourceLimits::default(),
+            failure_reason: None,
+            profile: None,
+        };
+
+        self.processes.insert(
+            process_id,
+            ManagedProcess {
+                info: info.clone(),
+                spec: None,
+                stop_requested: false,
+                restart_pending: false,
+                watcher: None,
+                health: None,
+                milestones: Milestone::ALL.into(),
+                limit_reason: None,
+                tty: None,
+                exited_at: None,
+            },
+        );
+        self.watch_adopted(process_id, pid);
+        self.start_port_detection(process_id, pid, None).await;
+        self.persist();
 
+        tracing::info!(
+            project = %project_name,
+            process_id = %process_id,
+            pid,
+            "Adopted process"
+        );
+        self.emit_started(&info);
         Ok(info)
     }
 
-    /// Start port detection for a process
-    async fn start_port_detection(&self, process_id: Uuid, pid: u32) {
+    /// Poll an adopted process (which is not our child) until it exits
+    fn watch_adopted(&self, process_id: Uuid, pid: u32) {
+        let tx = self.event_tx.clone();
+        tokio::spawn(async move {
+            while is_alive(pid) {
+                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+            }
+            let _ = tx
+                .send(ProcessEvent::Exited {
+                    process_id,
+                    exit_code: None,
+                    signal: None,
+                })
+                .await;
+        });
+    }
+
+    /// Store process records so they survive daemon restarts
+    fn persist(&self) {
+        let records: Vec<&ProcessInfo> = self.processes.values().map(|m| &m.info).collect();
+        if let Err(e) = self.store.save_processes(&records) {
+            tracing::warn!("Failed to persist process records: {}", e);
+        }
+    }
+
+    fn record_run_end(&self, info: &ProcessInfo) {
+        if let Err(e) = self.store.end_run(info) {
+            tracing::warn!("Failed to record end of run: {}", e);
+        }
+    }
+
+    /// Start port detection for a process.
+    ///
+    /// An assigned port is routed right away; detection then only changes the
+    /// route if the process ends up listening somewhere else.
+    async fn start_port_detection(&self, process_id: Uuid, pid: u32, assigned: Option<u16>) {
         let tx = self.event_tx.clone();
 
         tokio::spawn(async move {
+            if let Some(port) = assigned {
+                let _ = tx
+                    .send(ProcessEvent::PortDetected {
+                        process_id,
+                        port,
+                        ports: vec![port],
+                        bound: false,
+                    })
+                    .await;
+            }
+
             // Give the process time to bind to a port
             tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
 
-            // Poll for port for up to 30 seconds
+            // Poll for port for up to 30 seconds, or until the process exits
             for _ in 0..60 {
-                if let Some(port) = detect_port(pid).await {
-                    tracing::info!("Detected port {} for process {}", port, process_id);
+                if !is_alive(pid) {
+                    return;
+                }
+                let ports = ports::detect_ports(pid).await;
+                if let Some(&first) = ports.first() {
+                    let port = match assigned {
+                        Some(port) if ports.contains(&port) => port,
+                        Some(port) => {
+                            tracing::info!(
+                                process_id = %process_id,
+                                assigned = port,
+                                port = first,
+                                "Process ignored its assigned port"
+                            );
+                            first
+                        }
+                        None => first,
+                    };
+                    tracing::info!(process_id = %process_id, ?ports, "Detected ports");
                     let _ = tx
-                        .send(ProcessEvent::PortDetected { process_id, port })
+                        .send(ProcessEvent::PortDetected {
+                            process_id,
+                            port,
+                            ports,
+                            bound: true,
+                        })
                         .await;
                     return;
                 }
                 tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
             }
-            tracing::debug!("No port detected for process {}", process_id);
+            tracing::debug!(process_id = %process_id, "No port detected");
         });
     }
 
-    /// Stop a process
-    pub fn stop(&mut self, process_id: Uuid) -> Result<()> {
+    /// Give a project's processes a new priority: running ones now, and
+    /// restarted ones from then on. Returns the PIDs it couldn't be applied to.
+    pub async fn set_priority(
+        &mut self,
+        project_name: &str,
+        priority: &Priority,
+    ) -> Vec<(u32, std::io::Error)> {
+        let mut pids = Vec::new();
+        for managed in self.processes.values_mut() {
+            if managed.info.project_name != project_name {
+                continue;
+            }
+            if let Some(spec) = &mut managed.spec {
+                spec.priority = priority.clone();
+            }
+            if managed.info.status.is_alive() {
+                pids.push(managed.info.pid);
+            }
+        }
+
+        let mut failed = Vec::new();
+        for pid in pids {
+            if let Err(e) = platform::set_priority(pid, priority).await {
+                failed.push((pid, e));
+            }
+        }
+        failed
+    }
+
+    /// Have a project's processes get `profiles` from their next (re)start on
+    pub fn set_env_profiles(&mut self, project_name: &str, profiles: &[String]) {
+        for managed in self.processes.values_mut() {
+            if managed.info.project_name != project_name {
+                continue;
+            }
+            if let Some(spec) = &mut managed.spec {
+                spec.env_profiles = profiles.to_vec();
+            }
+        }
+    }
+
+    /// Have a project's processes run confined by `sandbox` from their next
+    /// (re)start on
+    pub fn set_sandbox(&mut self, project_name: &str, sandbox: &Sandbox) {
+        for managed in self.processes.values_mut() {
+            if managed.info.project_name != project_name {
+                continue;
+            }
+            if let Some(spec) = &mut managed.spec {
+                spec.sandbox = sandbox.clone();
+            }
+        }
+    }
+
+    /// Ask a process to stop by sending SIGTERM to its process group.
+    ///
+    /// The process is marked stopped once its exit is recorded; callers should
+    /// await [`terminate`] with the returned PID to escalate to SIGKILL.
+    pub fn stop(&mut self, process_id: Uuid) -> Result<u32> {
         let managed = self
             .processes
             .get_mut(&process_id)
-            .context("Process not found")?;
+            .ok_or_else(|| process_not_found(process_id))?;
 
-        // Send SIGTERM
-        let pid = Pid::from_raw(managed.info.pid as i32);
-        signal::kill(pid, Signal::SIGTERM).context("Failed to send SIGTERM")?;
+        let pid = managed.info.pid;
+        platform::stop_tree(pid, Stop::Terminate).context("Failed to stop process")?;
 
-        managed.info.status = ProcessStatus::Stopped;
-        tracing::info!("Stopped process {}", process_id);
-        Ok(())
+        managed.stop_requested = true;
+        managed.watcher = None;
+        tracing::info!(
+            project = %managed.info.project_name,
+            process_id = %process_id,
+            pid,
+            "Stopping process"
+        );
+        Ok(pid)
+    }
+
+    /// Ask every running process, of one project or all of them, to stop,
+    /// returning their PIDs for [`terminate`]
+    pub fn stop_all(&mut self, project_name: Option<&str>) -> Vec<u32> {
+        let running: Vec<Uuid> = self
+            .processes
+            .values()
+            .filter(|m| {
+                m.info.status.is_alive()
+                    && project_name.is_none_or(|name| m.info.project_name == name)
+            })
+            .map(|m| m.info.id)
+            .collect();
+
+        running
+            .into_iter()
+            .filter_map(|id| match self.stop(id) {
+                Ok(pid) => Some(pid),
+                Err(e) => {
+                    tracing::warn!(process_id = %id, "Failed to stop process: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Drop all records for a project's processes
+    pub fn forget_project(&mut self, project_name: &str) {
+        self.processes
+            .retain(|_, m| m.info.project_name != project_name);
+        self.proxied.remove(project_name);
+        self.persist();
+    }
+
+    /// Drop the records of exited processes, of one project or all of them,
+    /// returning how many went
+    pub fn prune_exited(&mut self, project_name: Option<&str>) -> usize {
+        let before = self.processes.len();
+        self.processes.retain(|_, m| {
+            m.info.status.is_alive() || project_name.is_some_and(|name| m.info.project_name != name)
+        });
+        let pruned = before - self.processes.len();
+        if pruned > 0 {
+            self.persist();
+        }
+        pruned
+    }
+
+    /// Drop records of processes that exited before `cutoff`, returning how
+    /// many went
+    pub fn prune_expired(&mut self, cutoff: DateTime<Utc>) -> usize {
+        let before = self.processes.len();
+        self.processes.retain(|_, m| {
+            m.info.status.is_alive() || m.exited_at.is_none_or(|exited| exited >= cutoff)
+        });
+        let pruned = before - self.processes.len();
+        if pruned > 0 {
+            self.persist();
+        }
+        pruned
+    }
+
+    /// Processes recorded as running whose PID is gone
+    pub fn vanished(&self) -> Vec<Uuid> {
+        self.processes
+            .values()
+            .filter(|m| m.info.status.is_alive() && !is_alive(m.info.pid))
+            .map(|m| m.info.id)
+            .collect()
+    }
+
+    /// Report the exit of a process whose exit monitor never did, so it's
+    /// handled like any other
+    pub fn report_vanished(&self, process_id: Uuid) {
+        let _ = self.event_tx.try_send(ProcessEvent::Exited {
+            process_id,
+            exit_code: None,
+            signal: None,
+        });
     }
 
     /// Get process info
@@ -218,6 +1217,13 @@ impl ProcessManager {
         self.processes.get(&process_id).map(|m| &m.info)
     }
 
+    /// Whether a process was asked to stop rather than exiting on its own
+    pub fn stop_requested(&self, process_id: Uuid) -> bool {
+        self.processes
+            .get(&process_id)
+            .is_some_and(|m| m.stop_requested)
+    }
+
     /// Get mutable process info
     #[allow(dead_code)]
     pub fn get_mut(&mut self, process_id: Uuid) -> Option<&mut ProcessInfo> {
@@ -238,73 +1244,565 @@ impl ProcessManager {
             .collect()
     }
 
-    /// Get running process count
-    pub fn running_count(&self) -> usize {
+    /// Running processes of a project that a run of `command` with `args`
+    /// would duplicate: those started with the same command line, or with
+    /// `single_instance`, all of them
+    pub fn conflicting(
+        &self,
+        project_name: &str,
+        command: &str,
+        args: &[String],
+        single_instance: bool,
+    ) -> Vec<&ProcessInfo> {
         self.processes
             .values()
-            .filter(|m| m.info.status == ProcessStatus::Running)
-            .count()
+            .filter(|m| {
+                m.info.project_name == project_name
+                    && m.info.status.is_alive()
+                    && !m.stop_requested
+                    && (single_instance
+                        || m.spec.as_ref().is_some_and(|spec| spec.runs(command, args)))
+            })
+            .map(|m| &m.info)
+            .collect()
+    }
+
+    /// Running processes of a project's service
+    pub fn running_for_service(&self, project_name: &str, service: &str) -> Vec<&ProcessInfo> {
+        self.processes
+            .values()
+            .filter(|m| {
+                m.info.project_name == project_name
+                    && m.info.service.as_deref() == Some(service)
+                    && m.info.status.is_alive()
+            })
+            .map(|m| &m.info)
+            .collect()
     }
 
     /// Update process status
+    #[allow(dead_code)]
     pub fn update_status(&mut self, process_id: Uuid, status: ProcessStatus) {
         if let Some(managed) = self.processes.get_mut(&process_id) {
             managed.info.status = status;
+            self.persist();
+        }
+    }
+
+    /// Record a health check result, marking the process unhealthy while it fails
+    pub fn update_health(&mut self, process_id: Uuid, healthy: bool) {
+        if let Some(managed) = self.processes.get_mut(&process_id) {
+            managed.info.healthy = Some(healthy);
+            managed.info.status = if healthy {
+                ProcessStatus::Running
+            } else {
+                ProcessStatus::Unhealthy
+            };
+            self.persist();
         }
     }
 
     /// Update process port
-    pub fn update_port(&mut self, process_id: Uuid, port: u16) {
+    pub fn update_port(&mut self, process_id: Uuid, port: u16, ports: Vec<u16>) {
         if let Some(managed) = self.processes.get_mut(&process_id) {
             managed.info.port = Some(port);
+            managed.info.ports = ports.clone();
+            name_port(&mut managed.info, managed.spec.as_ref(), "web", port);
+            self.events.emit(
+                &managed.info.project_name,
+                EventKind::PortDetected {
+                    process_id,
+                    port,
+                    ports,
+                },
+            );
+            if let Err(e) = self.store.set_run_port(&managed.info) {
+                tracing::warn!("Failed to record run port: {}", e);
+            }
+            self.persist();
         }
     }
 
-    /// Find process by project name (returns the most recent running one)
-    #[allow(dead_code)]
-    pub fn find_by_project(&self, project_name: &str) -> Option<&ProcessInfo> {
+    /// Time the startup milestone a line of a process's output announces,
+    /// and note a resource limit it shows the process running into
+    pub fn observe_output(&mut self, process_id: Uuid, line: &str) {
+        if let Some(milestone) = milestones::detect(line) {
+            self.reach_milestone(process_id, milestone);
+        }
+        let Some(managed) = self.processes.get_mut(&process_id) else {
+            return;
+        };
+        if managed.limit_reason.is_none() {
+            managed.limit_reason = limits::reason_from_output(&managed.info.limits, line);
+        }
+        if let Some(port) = ports::announced_debugger_port(line) {
+            name_port(&mut managed.info, managed.spec.as_ref(), "debug", port);
+            self.persist();
+        }
+    }
+
+    /// Record how long the current run took to first reach `milestone`
+    pub fn reach_milestone(&mut self, process_id: Uuid, milestone: Milestone) {
+        let Some(managed) = self.processes.get_mut(&process_id) else {
+            return;
+        };
+        if !managed.milestones.insert(milestone) {
+            return;
+        }
+        let after_ms = (Utc::now() - managed.info.started_at)
+            .num_milliseconds()
+            .max(0) as u64;
+        tracing::info!(
+            project = %managed.info.project_name,
+            process_id = %managed.info.id,
+            after_ms,
+            "Reached {}",
+            milestone
+        );
+        if let Err(e) = self
+            .store
+            .set_run_milestone(&managed.info, milestone, after_ms)
+        {
+            tracing::warn!("Failed to record {} milestone: {}", milestone, e);
+        }
+        self.events.emit(
+            &managed.info.project_name,
+            EventKind::Milestone {
+                process_id,
+                milestone,
+                after_ms,
+            },
+        );
+    }
+
+    fn emit_started(&self, info: &ProcessInfo) {
+        let argv: Vec<String> = self
+            .processes
+            .get(&info.id)
+            .and_then(|managed| managed.spec.as_ref())
+            .map(|spec| {
+                std::iter::once(spec.command.clone())
+                    .chain(spec.args.clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+        if let Err(e) = self.store.start_run(info, &argv) {
+            tracing::warn!("Failed to record run: {}", e);
+        }
+        self.events.emit(
+            &info.project_name,
+            EventKind::ProcessStarted {
+                process_id: info.id,
+                pid: info.pid,
+                service: info.service.clone(),
+                command: info.command.clone(),
+            },
+        );
+    }
+
+    fn emit_exited(&self, info: &ProcessInfo) {
+        self.record_run_end(info);
+        self.events.emit(
+            &info.project_name,
+            EventKind::ProcessExited {
+                process_id: info.id,
+                exit_code: info.last_exit_code,
+                status: info.status.clone(),
+            },
+        );
+    }
+
+    /// Record a resource usage sample, killing a tree that outgrew its
+    /// memory limit; samples are too frequent to persist
+    pub fn update_usage(&mut self, process_id: Uuid, usage: ResourceUsage) {
+        let Some(managed) = self.processes.get_mut(&process_id) else {
+            return;
+        };
+        if !managed.info.status.is_alive() {
+            return;
+        }
+        if managed.limit_reason.is_none() {
+            if let Some(reason) = limits::over_memory(&managed.info.limits, &usage) {
+                if let Err(e) = platform::stop_tree(managed.info.pid, Stop::Kill) {
+                    tracing::warn!("Failed to kill {}: {}", managed.info.pid, e);
+                }
+                managed.limit_reason = Some(reason);
+            }
+        }
+        managed.info.usage = Some(usage);
+    }
+
+    /// Start health-checking a process on `port`, replacing a check on another
+    /// port. Returns whether the process has a health check gating its route.
+    pub fn start_health_check(&mut self, process_id: Uuid, port: u16) -> bool {
+        let tx = self.event_tx.clone();
+        let Some(managed) = self.processes.get_mut(&process_id) else {
+            return false;
+        };
+        let Some(spec) = managed.spec.as_ref() else {
+            return false;
+        };
+        let Some(check) = spec.health.clone() else {
+            return false;
+        };
+        let smoke = spec.smoke.clone();
+
+        match &managed.health {
+            Some((checked_port, _)) if *checked_port == port => {}
+            _ => {
+                if let Some((_, old)) = managed.health.take() {
+                    old.abort();
+                }
+                managed.health = Some((port, health::monitor(process_id, port, check, smoke, tx)));
+            }
+        }
+        true
+    }
+
+    /// The process a project's hostname should go to: its primary one if it
+    /// can take requests, else the newest that can. Processes can take
+    /// requests once they listen on a port and pass any health check.
+    pub fn route_target(&self, project_name: &str) -> Option<&ProcessInfo> {
         self.processes
             .values()
             .filter(|m| {
-                m.info.project_name == project_name && m.info.status == ProcessStatus::Running
+                let has_check = m.spec.as_ref().is_some_and(|s| s.health.is_some());
+                m.info.project_name == project_name
+                    && m.info.status.is_alive()
+                    && m.info.port.is_some()
+                    && (!has_check || m.info.healthy == Some(true))
             })
             .map(|m| &m.info)
-            .max_by_key(|p| p.started_at)
+            .max_by_key(|p| (p.primary, p.started_at))
+    }
+
+    /// Whether any of a project's processes is waiting to pass its health check
+    pub fn awaiting_health(&self, project_name: &str) -> bool {
+        self.processes.values().any(|m| {
+            m.info.project_name == project_name
+                && m.info.status.is_alive()
+                && m.spec.as_ref().is_some_and(|s| s.health.is_some())
+                && m.info.healthy != Some(true)
+        })
+    }
+
+    /// Make `process_id` the project's primary process, or with `None` have
+    /// none
+    pub fn set_primary(&mut self, project_name: &str, process_id: Option<Uuid>) -> Result<()> {
+        if let Some(id) = process_id {
+            let managed = self
+                .processes
+                .get(&id)
+                .ok_or_else(|| process_not_found(id))?;
+            if managed.info.project_name != project_name {
+                anyhow::bail!("Process {} isn't one of {}'s", id, project_name);
+            }
+        }
+        for managed in self.processes.values_mut() {
+            if managed.info.project_name == project_name {
+                managed.info.primary = Some(managed.info.id) == process_id;
+            }
+        }
+        self.persist();
+        Ok(())
     }
 }
 
-/// Detect which port a process is listening on using lsof
-async fn detect_port(pid: u32) -> Option<u16> {
-    let output = tokio::process::Command::new("lsof")
-        .args(["-i", "-P", "-n", "-a", "-p", &pid.to_string()])
-        .output()
-        .await
-        .ok()?;
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-
-    // Parse lsof output to find LISTEN ports
-    // Format: COMMAND PID USER FD TYPE DEVICE SIZE/OFF NODE NAME
-    // Example: Python  93214 preetham    4u  IPv6 0x... 0t0  TCP *:3002 (LISTEN)
-    for line in stdout.lines() {
-        if line.contains("(LISTEN)") {
-            // The line contains something like: TCP *:3002 (LISTEN)
-            // Find the part before "(LISTEN)" and extract the port
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            // Look for the NAME column which contains host:port
-            for part in parts.iter().rev() {
-                if *part == "(LISTEN)" {
-                    continue;
-                }
-                // This should be host:port like "*:3002" or "127.0.0.1:3002"
-                if let Some(port_str) = part.rsplit(':').next() {
-                    if let Ok(port) = port_str.parse::<u16>() {
-                        return Some(port);
-                    }
+/// Future a call on the process manager returns, borrowing the manager
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A call on the process manager; what it returns sends the caller its answer
+type Call = Box<dyn for<'a> FnOnce(&'a mut ProcessManager) -> BoxFuture<'a, Reply> + Send>;
+
+type Reply = Box<dyn FnOnce() + Send>;
+
+/// Shared handle on the process manager, which runs on a task of its own
+///
+/// Calls are handled one at a time in the order they were made, so a check
+/// and the change it allows (no instance of a service is running, so spawn
+/// one) go in one call to see nothing change in between. Calls never wait on
+/// the rest of the daemon's state, so one can be made while holding a lock on
+/// it. Reads come from a snapshot taken after every call, so status queries
+/// don't wait behind a slow spawn; a caller's own change is in the snapshot
+/// by the time its call returns.
+#[derive(Clone)]
+pub struct ProcessHandle {
+    calls: mpsc::Sender<Call>,
+    snapshot: watch::Receiver<HashMap<Uuid, ProcessInfo>>,
+    /// Run history is read straight from the store
+    store: Store,
+}
+
+impl ProcessHandle {
+    /// Run `manager` on its own task
+    pub fn spawn(mut manager: ProcessManager) -> Self {
+        let (calls, mut rx) = mpsc::channel::<Call>(CALL_QUEUE);
+        let (snapshot_tx, snapshot) = watch::channel(HashMap::new());
+        manager.publish(&snapshot_tx);
+        let store = manager.store.clone();
+        tokio::spawn(async move {
+            while let Some(call) = rx.recv().await {
+                let reply = call(&mut manager).await;
+                manager.publish(&snapshot_tx);
+                reply();
+            }
+        });
+        Self {
+            calls,
+            snapshot,
+            store,
+        }
+    }
+
+    /// Run `f` on the manager, returning its result, or an error if the
+    /// manager's task is gone
+    pub async fn call<T, F>(&self, f: F) -> Result<T, IpcError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut ProcessManager) -> T + Send + 'static,
+    {
+        self.call_async(|manager| Box::pin(std::future::ready(f(manager))))
+            .await
+    }
+
+    /// Run `f` on the manager and await the future it returns, e.g. a spawn
+    pub async fn call_async<T, F>(&self, f: F) -> Result<T, IpcError>
+    where
+        T: Send + 'static,
+        F: for<'a> FnOnce(&'a mut ProcessManager) -> BoxFuture<'a, T> + Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.send(boxed_call(move |manager| {
+            Box::pin(async move {
+                let value = f(manager).await;
+                Box::new(move || {
+                    let _ = tx.send(value);
+                }) as Reply
+            })
+        }))
+        .await?;
+        rx.await.map_err(|_| manager_unavailable())
+    }
+
+    /// Spawn a process for a project, preparing it before calling the manager
+    pub async fn start(&self, project_name: String, spec: SpawnSpec) -> Result<ProcessInfo> {
+        let prepared = prepare(&project_name, &spec).await?;
+        self.call_async(move |manager| Box::pin(manager.spawn(project_name, spec, prepared)))
+            .await?
+    }
+
+    /// Respawn an exited process, preparing it before calling the manager
+    pub async fn respawn(&self, process_id: Uuid) -> Result<ProcessInfo> {
+        let (project_name, spec) = self
+            .call(move |manager| manager.respawn_spec(process_id))
+            .await??;
+        let prepared = prepare(&project_name, &spec).await?;
+        self.call_async(move |manager| Box::pin(manager.respawn(process_id, prepared)))
+            .await?
+    }
+
+    /// Run `f` on the manager without waiting for it to be done; with the
+    /// manager's task gone there's nothing left to do it on
+    pub async fn cast<F>(&self, f: F)
+    where
+        F: FnOnce(&mut ProcessManager) + Send + 'static,
+    {
+        let _ = self
+            .send(boxed_call(|manager| {
+                f(manager);
+                Box::pin(std::future::ready(Box::new(|| {}) as Reply))
+            }))
+            .await;
+    }
+
+    async fn send(&self, call: Call) -> Result<(), IpcError> {
+        self.calls
+            .send(call)
+            .await
+            .map_err(|_| manager_unavailable())
+    }
+
+    /// Get process info
+    pub fn get(&self, process_id: Uuid) -> Option<ProcessInfo> {
+        self.snapshot.borrow().get(&process_id).cloned()
+    }
+
+    /// List all processes
+    pub fn list(&self) -> Vec<ProcessInfo> {
+        self.snapshot.borrow().values().cloned().collect()
+    }
+
+    /// List processes for a specific project
+    pub fn list_for_project(&self, project_name: &str) -> Vec<ProcessInfo> {
+        self.snapshot
+            .borrow()
+            .values()
+            .filter(|p| p.project_name == project_name)
+            .cloned()
+            .collect()
+    }
+
+    /// Get running process count
+    /// Process records kept, running or exited
+    pub fn record_count(&self) -> usize {
+        self.snapshot.borrow().len()
+    }
+
+    pub fn running_count(&self) -> usize {
+        self.snapshot
+            .borrow()
+            .values()
+            .filter(|p| p.status.is_alive())
+            .count()
+    }
+
+    /// A project's most recent runs, newest first
+    pub fn runs(&self, project_name: &str, limit: usize) -> Result<Vec<RunRecord>> {
+        self.store.runs(project_name, limit)
+    }
+}
+
+/// Type a closure as a [`Call`], which inference can't do through `Box::new`
+fn boxed_call<F>(f: F) -> Call
+where
+    F: for<'a> FnOnce(&'a mut ProcessManager) -> BoxFuture<'a, Reply> + Send + 'static,
+{
+    Box::new(f)
+}
+
+impl ProcessManager {
+    /// Bring the snapshot read through [`ProcessHandle`] up to date, touching
+    /// only what changed
+    fn publish(&self, snapshot: &watch::Sender<HashMap<Uuid, ProcessInfo>>) {
+        snapshot.send_if_modified(|published| {
+            let before = published.len();
+            published.retain(|id, _| self.processes.contains_key(id));
+            let mut modified = published.len() != before;
+            for (id, managed) in &self.processes {
+                if published.get(id) != Some(&managed.info) {
+                    published.insert(*id, managed.info.clone());
+                    modified = true;
                 }
             }
+            modified
+        });
+    }
+}
+
+/// Names for a new run's ports: the service's, and `web` for the routed one
+fn named_ports(spec: &SpawnSpec, port: Option<u16>) -> BTreeMap<String, u16> {
+    let mut named = spec.named_ports.clone();
+    if let Some(port) = port {
+        named.entry("web".to_string()).or_insert(port);
+    }
+    named
+}
+
+/// Name a port the process was seen using, unless its service gave the name
+/// to a port of its own
+fn name_port(info: &mut ProcessInfo, spec: Option<&SpawnSpec>, name: &str, port: u16) {
+    if !spec.is_some_and(|spec| spec.named_ports.contains_key(name)) {
+        info.named_ports.insert(name.to_string(), port);
+    }
+}
+
+/// What calls on the process manager get once its task has stopped, as it
+/// does when the daemon shuts down or the task panicked
+fn manager_unavailable() -> IpcError {
+    IpcError::Failed {
+        message: "Process manager unavailable".to_string(),
+    }
+}
+
+fn process_not_found(process_id: Uuid) -> anyhow::Error {
+    IpcError::ProcessNotFound {
+        id: process_id.to_string(),
+    }
+    .into()
+}
+
+/// A line of a process's output, to fill in with what it printed
+fn output_line(process_id: Uuid, project_name: &str, spec: &SpawnSpec) -> LogLine {
+    LogLine {
+        project_name: project_name.to_string(),
+        process_id,
+        service: spec.service.clone(),
+        timestamp: Utc::now(),
+        is_stderr: false,
+        line: String::new(),
+        stage: spec.job.as_ref().map(|job| job.stage),
+        collapsed: None,
+    }
+}
+
+/// `template` with a line the process just printed
+fn printed(template: &LogLine, line: String) -> LogLine {
+    LogLine {
+        timestamp: Utc::now(),
+        line,
+        ..template.clone()
+    }
+}
+
+/// Write each line of a process's stdout or stderr to `sinks`
+async fn read_output(stream: impl AsyncRead + Unpin, template: LogLine, mut sinks: Fanout) {
+    let mut lines = BufReader::new(stream).lines();
+    // Read to the end even with no sinks left, so the process never blocks
+    // on a full pipe
+    while let Ok(Some(line)) = lines.next_line().await {
+        sinks.write(&printed(&template, line)).await;
+    }
+}
+
+/// Exponential backoff for the given number of previous restarts
+fn restart_backoff(restart_count: u32) -> Duration {
+    RESTART_BACKOFF_BASE
+        .saturating_mul(2u32.saturating_pow(restart_count))
+        .min(RESTART_BACKOFF_MAX)
+}
+
+/// Poll until the process tree exits; returns false on timeout
+async fn wait_for_exit(pid: u32, timeout: Duration) -> bool {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while tree_alive(pid) {
+        if tokio::time::Instant::now() >= deadline {
+            return false;
         }
+        tokio::time::sleep(Duration::from_millis(100)).await;
     }
+    true
+}
 
-    None
+/// Whether a recorded process is still running under its PID, rather than
+/// having exited and left the PID to an unrelated process. Its start time is
+/// compared where the OS reports one, otherwise its command line.
+async fn still_running(info: &ProcessInfo) -> bool {
+    if !is_alive(info.pid) {
+        return false;
+    }
+    match platform::started_at(info.pid).await {
+        Some(started) => (started - info.started_at).num_seconds().abs() <= START_SLACK_SECS,
+        None => command_line(info.pid).await.as_deref() == Some(info.command.as_str()),
+    }
+}
+
+/// SIGTERM a process tree and wait up to `grace` for it to exit before
+/// escalating to SIGKILL. Returns once nothing in the tree is left running.
+pub async fn terminate(pid: u32, grace: Duration) {
+    if platform::stop_tree(pid, Stop::Terminate).is_err() {
+        return;
+    }
+    if wait_for_exit(pid, grace).await {
+        return;
+    }
+
+    tracing::warn!(
+        "Process {} did not exit within {:?}, sending SIGKILL",
+        pid,
+        grace
+    );
+    let _ = platform::stop_tree(pid, Stop::Kill);
+    if !wait_for_exit(pid, KILL_TIMEOUT).await {
+        tracing::error!("Process {} survived SIGKILL", pid);
+    }
 }
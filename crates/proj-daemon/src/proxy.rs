@@ -1,61 +1,508 @@
 //! HTTP reverse proxy - routes requests based on Host header
+//!
+//! Clients using it as a forward proxy (`HTTP_PROXY`, or the PAC script) also
+//! reach hosts outside the TLD through it: a host a project overrides goes to
+//! the address the project sets, any other to the host itself. HTTPS to an
+//! overridden host is decrypted with a certificate from the local CA, and a
+//! tunnel to a project host ends at the proxy's own listener. Only clients on
+//! this machine and private networks get that far, and only the
+//! `forward_proxy` listener or a process on this machine reaches other hosts.
 
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full};
 use hyper::body::{Bytes, Incoming};
+use hyper::header::{HeaderMap, HeaderValue};
+use hyper::http::uri::Authority;
 use hyper::server::conn::http1;
 use hyper::service::service_fn;
 use hyper::{Request, Response, StatusCode};
 use hyper_util::rt::TokioIo;
-use std::collections::HashMap;
+use proj_common::outbound::{Feature, Route};
+use proj_common::{
+    naming, tls_dir, Appearance, Config, ProxyBindError, ProxyOptions, Rewrite,
+    CLEAR_SITE_DATA_PATH,
+};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::RwLock;
+use tokio::sync::{watch, RwLock};
+use tokio::time::{Duration, Instant};
+use tokio_rustls::TlsAcceptor;
 
-/// Routing table mapping project names to ports
-pub type RoutingTable = Arc<RwLock<HashMap<String, u16>>>;
+use crate::api::Api;
+use crate::assets::{self, AssetStore};
+use crate::auth::AuthStore;
+use crate::budgets::BudgetTracker;
+use crate::capture::{CaptureStore, MAX_CAPTURED_BODY};
+use crate::drift::{DriftStore, MAX_VALIDATED_BODY};
+use crate::forward;
+use crate::graphql::GraphqlStats;
+use crate::idle::LazyStarter;
+use crate::interfaces;
+use crate::metrics::ProxyMetrics;
+use crate::outbound;
+use crate::panics;
+use crate::placeholders::{self, Placeholder};
+use crate::ports;
+use crate::redirects;
+use crate::rewrites::{self, MAX_REWRITTEN_BODY};
+use crate::routes::ProjectRoutes;
+use crate::shedding::{Admission, LoadShedder};
+use crate::tls;
+use crate::upstream::Upstream;
+
+/// Routing table mapping project names to where their requests go
+pub type RoutingTable = Arc<RwLock<HashMap<String, ProjectRoutes>>>;
+
+/// Projects with a process that hasn't passed its health check yet
+pub type StartingSet = Arc<RwLock<HashSet<String>>>;
+
+/// When each project last got a proxied request, for idle shutdown
+pub type ActivityTable = Arc<RwLock<HashMap<String, DateTime<Utc>>>>;
+
+/// Why the proxy isn't listening, while it isn't
+pub type BindErrorCell = Arc<RwLock<Option<ProxyBindError>>>;
+
+/// Per-project proxy options, kept in sync with the registry
+pub type ProxyOptionsTable = Arc<RwLock<HashMap<String, ProxyOptions>>>;
+
+/// Hostname aliases mapped to the projects they route to, kept in sync with
+/// the registry
+pub type AliasTable = Arc<RwLock<HashMap<String, String>>>;
+
+/// Icon and color of each project, for the proxy's pages and notifications,
+/// kept in sync with the registry
+pub type AppearanceTable = Arc<RwLock<HashMap<String, Appearance>>>;
+
+/// TLS acceptor issuing certificates from the local CA, created on first use
+pub type TlsAcceptorCell = Arc<OnceLock<TlsAcceptor>>;
+
+/// Response headers that prevent embedding a page in another origin
+const EMBEDDING_HEADERS: [&str; 3] = [
+    "content-security-policy",
+    "content-security-policy-report-only",
+    "x-frame-options",
+];
+
+/// Response headers that let a browser cache or revalidate a response
+const CACHING_HEADERS: [&str; 5] = ["cache-control", "etag", "expires", "last-modified", "age"];
+
+/// Request headers that let a backend answer 304 Not Modified
+const CONDITIONAL_HEADERS: [&str; 2] = ["if-none-match", "if-modified-since"];
+
+/// How often the proxy tries again to listen when it couldn't
+const BIND_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often the proxy checks for projects, with `proxy_wait_for_projects`
+const PROJECT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where a proxied request came from
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Client {
+    pub addr: SocketAddr,
+    /// Whether it came in over the HTTPS listener
+    pub https: bool,
+    /// Whether it came in over the `forward_proxy` listener, which passes
+    /// requests on to any host
+    pub forward: bool,
+}
+
+impl Client {
+    /// Whether this client may use the proxy to reach a host that's neither
+    /// its own nor overridden: over the `forward_proxy` listener, or from a
+    /// process on this machine, which `HTTP_PROXY` sends here
+    fn may_pass_through(&self) -> bool {
+        self.forward || self.addr.ip().to_canonical().is_loopback()
+    }
+
+    /// Whether this client may tunnel to the proxy's own or an overridden
+    /// host: over the `forward_proxy` listener, or from this machine or a
+    /// private network
+    fn may_tunnel(&self) -> bool {
+        self.forward || forward::is_private(self.addr.ip())
+    }
+}
 
 /// Create a new routing table
 pub fn new_routing_table() -> RoutingTable {
     Arc::new(RwLock::new(HashMap::new()))
 }
 
-/// Start the reverse proxy server
-pub async fn start_proxy(port: u16, routing_table: RoutingTable) -> Result<()> {
-    let addr = SocketAddr::from(([127, 0, 0, 1], port));
-    let listener = TcpListener::bind(addr).await?;
+/// Create a new set of starting projects
+pub fn new_starting_set() -> StartingSet {
+    Arc::new(RwLock::new(HashSet::new()))
+}
+
+/// Create a new activity table
+pub fn new_activity_table() -> ActivityTable {
+    Arc::new(RwLock::new(HashMap::new()))
+}
+
+/// Create an empty bind error cell
+pub fn new_bind_error_cell() -> BindErrorCell {
+    Arc::new(RwLock::new(None))
+}
+
+/// Create a new proxy options table
+pub fn new_proxy_options_table() -> ProxyOptionsTable {
+    Arc::new(RwLock::new(HashMap::new()))
+}
+
+/// Create a new alias table
+pub fn new_alias_table() -> AliasTable {
+    Arc::new(RwLock::new(HashMap::new()))
+}
+
+/// Create a new appearance table
+pub fn new_appearance_table() -> AppearanceTable {
+    Arc::new(RwLock::new(HashMap::new()))
+}
+
+/// Create an empty TLS acceptor cell
+pub fn new_tls_acceptor_cell() -> TlsAcceptorCell {
+    Arc::new(OnceLock::new())
+}
+
+/// Shared state handed to every proxied request
+#[derive(Clone)]
+pub struct ProxyContext {
+    pub routing_table: RoutingTable,
+    pub starting: StartingSet,
+    pub options_table: ProxyOptionsTable,
+    pub aliases: AliasTable,
+    pub appearances: AppearanceTable,
+    /// Shared by the HTTPS listener and intercepted HTTPS
+    pub tls: TlsAcceptorCell,
+    pub captures: CaptureStore,
+    pub drift: DriftStore,
+    pub budgets: BudgetTracker,
+    pub assets: AssetStore,
+    /// Credentials attached to requests, by project and path prefix
+    pub auth: AuthStore,
+    pub graphql: GraphqlStats,
+    pub metrics: ProxyMetrics,
+    pub activity: ActivityTable,
+    /// Set while the listeners can't be bound, for `proj status`
+    pub bind_error: BindErrorCell,
+    pub shedder: LoadShedder,
+    /// Pooled connections to backends
+    pub upstream: Upstream,
+    /// The machine's addresses, for rebinding after network changes
+    pub network: interfaces::Addresses,
+    /// Dashboard served on the bare host, once the daemon state exists
+    pub api: Option<Api>,
+    /// Starts lazily started projects, once the daemon state exists
+    pub starter: Option<LazyStarter>,
+    /// Live settings: listen addresses, TLD and idle timeout
+    pub config: watch::Receiver<Config>,
+}
+
+impl ProxyContext {
+    /// The acceptor for HTTPS connections, creating the local CA if needed
+    fn tls_acceptor(&self) -> Result<TlsAcceptor> {
+        if let Some(acceptor) = self.tls.get() {
+            return Ok(acceptor.clone());
+        }
+        let config = tls::server_config(
+            &tls_dir()?,
+            self.config.clone(),
+            self.aliases.clone(),
+            self.options_table.clone(),
+        )?;
+        Ok(self.tls.get_or_init(|| TlsAcceptor::from(config)).clone())
+    }
+}
+
+#[cfg(test)]
+impl ProxyContext {
+    /// A context with empty tables and `config`, as the daemon's is before
+    /// any project is loaded
+    pub(crate) fn for_tests(config: Config) -> Self {
+        ProxyContext {
+            routing_table: new_routing_table(),
+            starting: new_starting_set(),
+            options_table: new_proxy_options_table(),
+            aliases: new_alias_table(),
+            appearances: new_appearance_table(),
+            tls: new_tls_acceptor_cell(),
+            captures: CaptureStore::new(),
+            drift: DriftStore::new(),
+            budgets: BudgetTracker::new(crate::events::EventBus::new()),
+            assets: AssetStore::new(),
+            auth: AuthStore::new(),
+            graphql: GraphqlStats::new(),
+            metrics: ProxyMetrics::new(),
+            activity: new_activity_table(),
+            bind_error: new_bind_error_cell(),
+            shedder: LoadShedder::new(),
+            upstream: Upstream::new(watch::channel(config.clone()).1),
+            network: watch::channel(Vec::new()).1,
+            api: None,
+            starter: None,
+            config: watch::channel(config).1,
+        }
+    }
+}
+
+/// Where the proxy listens, as set in the config
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ListenAddrs {
+    http: SocketAddr,
+    /// `None` when HTTPS is disabled
+    https: Option<SocketAddr>,
+}
+
+impl ListenAddrs {
+    fn from_config(config: &Config) -> Self {
+        Self {
+            http: SocketAddr::new(config.bind_addr, config.proxy_port),
+            https: (config.https_port > 0)
+                .then(|| SocketAddr::new(config.bind_addr, config.https_port)),
+        }
+    }
+}
+
+/// Bound listeners, plus the TLS acceptor for the HTTPS one
+struct Listeners {
+    http: TcpListener,
+    https: Option<(TcpListener, TlsAcceptor)>,
+}
+
+impl Listeners {
+    async fn bind(addrs: ListenAddrs, ctx: &ProxyContext) -> Result<Self> {
+        let http = TcpListener::bind(addrs.http).await?;
+        tracing::info!("Reverse proxy listening on http://{}", addrs.http);
+
+        // A broken TLS setup shouldn't take down plain HTTP
+        let https = match addrs.https {
+            Some(addr) => {
+                let bound = async {
+                    let acceptor = ctx.tls_acceptor()?;
+                    let listener = TcpListener::bind(addr).await?;
+                    anyhow::Ok((listener, acceptor))
+                };
+                match bound.await {
+                    Ok(bound) => {
+                        tracing::info!("Reverse proxy listening on https://{}", addr);
+                        Some(bound)
+                    }
+                    Err(e) => {
+                        tracing::warn!("HTTPS disabled: {}", e);
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+
+        Ok(Self { http, https })
+    }
+
+    /// Accept the next HTTPS connection, or wait forever without a listener
+    async fn accept_https(&self) -> std::io::Result<(TcpStream, SocketAddr, TlsAcceptor)> {
+        match &self.https {
+            Some((listener, acceptor)) => {
+                let (stream, addr) = listener.accept().await?;
+                Ok((stream, addr, acceptor.clone()))
+            }
+            None => std::future::pending().await,
+        }
+    }
+}
 
-    tracing::info!("Reverse proxy listening on http://{}", addr);
+/// Run the HTTP and HTTPS listeners, rebinding them when the configured
+/// addresses change or the network changes under them. Fails only if the
+/// initial bind does.
+pub async fn run(ctx: ProxyContext) -> Result<()> {
+    let mut config = ctx.config.clone();
+    let mut network = interfaces::Watch::new(ctx.network.clone());
+    let mut addrs = ListenAddrs::from_config(&config.borrow_and_update());
+    let mut listeners = if awaiting_project(&ctx).await {
+        tracing::info!(
+            "Not listening on {} until a project is registered",
+            addrs.http
+        );
+        None
+    } else {
+        bind_or_record(addrs, &ctx).await
+    };
 
     loop {
-        let (stream, _) = listener.accept().await?;
-        let io = TokioIo::new(stream);
-        let table = routing_table.clone();
+        let Some(bound) = &listeners else {
+            // Nothing bound, e.g. another server holds the port or there's no
+            // project yet: retry now and then, and with each new config
+            let waiting = awaiting_project(&ctx).await;
+            let retry = if waiting {
+                PROJECT_CHECK_INTERVAL
+            } else {
+                BIND_RETRY_INTERVAL
+            };
+            tokio::select! {
+                changed = config.changed() => {
+                    if changed.is_err() {
+                        return Ok(());
+                    }
+                    addrs = ListenAddrs::from_config(&config.borrow_and_update());
+                }
+                _ = network.rebind_needed(Some(addrs.http.ip()), false) => {}
+                _ = tokio::time::sleep(retry) => {}
+            }
+            if !awaiting_project(&ctx).await {
+                listeners = bind_or_record(addrs, &ctx).await;
+            }
+            continue;
+        };
 
-        tokio::spawn(async move {
-            let service = service_fn(move |req| {
-                let table = table.clone();
-                async move { handle_request(req, table).await }
-            });
-
-            if let Err(e) = http1::Builder::new()
-                .preserve_header_case(true)
-                .serve_connection(io, service)
-                .with_upgrades()
-                .await
-            {
-                tracing::debug!("Connection error: {}", e);
+        tokio::select! {
+            accepted = bound.http.accept() => {
+                let (stream, addr) = accepted?;
+                let client = Client { addr, https: false, forward: false };
+                let connection = serve_connection(TokioIo::new(stream), ctx.clone(), client);
+                panics::spawn("proxy connection", connection);
             }
+            accepted = bound.accept_https() => {
+                let (stream, addr, acceptor) = accepted?;
+                let client = Client { addr, https: true, forward: false };
+                let ctx = ctx.clone();
+                panics::spawn("proxy connection", async move {
+                    match acceptor.accept(stream).await {
+                        Ok(tls) => serve_connection(TokioIo::new(tls), ctx, client).await,
+                        Err(e) => tracing::debug!("TLS handshake failed: {}", e),
+                    }
+                });
+            }
+            changed = config.changed() => {
+                if changed.is_err() {
+                    return Ok(());
+                }
+                let next = ListenAddrs::from_config(&config.borrow_and_update());
+                if next == addrs {
+                    continue;
+                }
+                // Release the old ports first: the new address may overlap them
+                drop(listeners.take());
+                match Listeners::bind(next, &ctx).await {
+                    Ok(bound) => {
+                        listeners = Some(bound);
+                        addrs = next;
+                        *ctx.bind_error.write().await = None;
+                    }
+                    Err(e) => {
+                        let failure = bind_failure(next.http, &e).await;
+                        tracing::error!("{}; keeping {}", failure, addrs.http);
+                        listeners = bind_or_record(addrs, &ctx).await;
+                        // Still not where the config says
+                        *ctx.bind_error.write().await = Some(failure);
+                    }
+                }
+            }
+            _ = network.rebind_needed(Some(addrs.http.ip()), true) => {
+                tracing::info!("Network changed; rebinding the proxy on {}", addrs.http.ip());
+                drop(listeners.take());
+                listeners = bind_or_record(addrs, &ctx).await;
+            }
+            _ = tokio::time::sleep(PROJECT_CHECK_INTERVAL), if config.borrow().proxy_wait_for_projects => {
+                if awaiting_project(&ctx).await {
+                    tracing::info!("No projects left; closing the proxy on {}", addrs.http);
+                    drop(listeners.take());
+                }
+            }
+        }
+    }
+}
+
+/// Whether `proxy_wait_for_projects` keeps the proxy closed: it's set and
+/// no project is registered
+async fn awaiting_project(ctx: &ProxyContext) -> bool {
+    ctx.config.borrow().proxy_wait_for_projects && ctx.options_table.read().await.is_empty()
+}
+
+/// Bind the listeners, or record why they can't be for `proj status`
+async fn bind_or_record(addrs: ListenAddrs, ctx: &ProxyContext) -> Option<Listeners> {
+    match Listeners::bind(addrs, ctx).await {
+        Ok(bound) => {
+            *ctx.bind_error.write().await = None;
+            Some(bound)
+        }
+        Err(e) => {
+            let failure = bind_failure(addrs.http, &e).await;
+            let mut recorded = ctx.bind_error.write().await;
+            // Logged once rather than with every retry
+            if recorded.as_ref() != Some(&failure) {
+                tracing::error!("{}", failure);
+            }
+            *recorded = Some(failure);
+            None
+        }
+    }
+}
+
+/// What went wrong binding `addr`: for a port in use, which process holds it
+/// and a free one nearby
+async fn bind_failure(addr: SocketAddr, error: &anyhow::Error) -> ProxyBindError {
+    let in_use = error
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::AddrInUse);
+    let mut failure = ProxyBindError {
+        addr,
+        message: error.to_string(),
+        holder: None,
+        free_port: None,
+    };
+    if !in_use {
+        return failure;
+    }
+    let own_pid = std::process::id();
+    failure.holder = ports::listening_sockets()
+        .await
+        .into_iter()
+        .find(|socket| socket.port == addr.port() && socket.pid != Some(own_pid))
+        .and_then(|socket| match (socket.process_name, socket.pid) {
+            (Some(name), Some(pid)) => Some(format!("{} (pid {})", name, pid)),
+            (None, Some(pid)) => Some(format!("pid {}", pid)),
+            (name, None) => name,
         });
+    failure.free_port = (1..=100)
+        .filter_map(|offset| addr.port().checked_add(offset))
+        .find(|&port| ports::is_free(port));
+    failure
+}
+
+/// Serve proxied HTTP/1.1 requests on an accepted connection
+pub(crate) async fn serve_connection<I>(io: I, ctx: ProxyContext, client: Client)
+where
+    I: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+{
+    let _connection = ctx.metrics.connection();
+    let service = service_fn(move |req: Request<Incoming>| {
+        let ctx = ctx.clone();
+        async move { handle_request(req, ctx, client).await }
+    });
+
+    if let Err(e) = http1::Builder::new()
+        .preserve_header_case(true)
+        .serve_connection(io, service)
+        .with_upgrades()
+        .await
+    {
+        tracing::debug!("Connection error: {}", e);
     }
 }
 
 /// Handle an incoming HTTP request
 async fn handle_request(
     req: Request<Incoming>,
-    routing_table: RoutingTable,
+    ctx: ProxyContext,
+    client: Client,
 ) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
+    if req.method() == hyper::Method::CONNECT {
+        return Ok(connect_response(req, ctx, client).await);
+    }
+
     // Extract project name from Host header
     let host = req
         .headers()
@@ -63,36 +510,313 @@ async fn handle_request(
         .and_then(|h| h.to_str().ok())
         .unwrap_or("");
 
-    // Parse project name from host (e.g., "my-app.localhost:8080" -> "my-app")
-    let project_name = host.split('.').next().unwrap_or("").to_string();
+    // Not held across awaits: the receiver's borrow blocks reloads
+    let config = ctx.config.borrow().clone();
 
-    if project_name.is_empty() || project_name == "localhost" {
-        return Ok(not_found_response(
-            "No project specified. Use <project>.localhost:8080",
-        ));
+    // An alias, else the name in the host (e.g., "my-app.localhost:8080" -> "my-app")
+    let alias = ctx.aliases.read().await.get(&hostname(host)).cloned();
+
+    // An absolute URL for another host comes from a forward-proxy client
+    let foreign = req
+        .uri()
+        .authority()
+        .filter(|authority| alias.is_none() && !is_proxy_host(authority.host(), config.tld()))
+        .cloned();
+    if let Some(authority) = foreign {
+        if !client.may_tunnel() {
+            return Ok(unknown_host_response());
+        }
+        return Ok(forward_proxy_request(req, &ctx, client, &authority).await);
+    }
+
+    let project_name = alias
+        .or_else(|| parse_project_name(host, config.tld()))
+        .unwrap_or_default();
+
+    // A host outside the TLD that isn't an alias addresses nothing here, and
+    // doesn't get the dashboard either
+    if project_name.is_empty() && !is_proxy_host(&hostname(host), config.tld()) {
+        return Ok(unknown_host_response());
+    }
+
+    if project_name.is_empty() {
+        // Serve proxy auto-config for browsers with fixed proxy settings
+        match req.uri().path() {
+            "/proxy.pac" => return Ok(pac_response(&ctx, &config).await),
+            "/__proj/hosts.json" => return Ok(hosts_response(&ctx, &config).await),
+            "/__proj/metrics" => return Ok(metrics_response(&ctx.metrics).await),
+            _ => {}
+        }
+        if let Some(api) = &ctx.api {
+            if let Some(resp) = api.handle(&req, &config, client.addr).await {
+                return Ok(resp);
+            }
+        }
+        return Ok(not_found_response(&format!(
+            "No project specified. Use {}",
+            config.url("<project>")
+        )));
+    }
+
+    // Any other host gets a bare 404 rather than one repeating it back
+    if !ctx.options_table.read().await.contains_key(&project_name) {
+        return Ok(unknown_host_response());
     }
+    if let Some(starter) = &ctx.starter {
+        starter.settle(&project_name).await;
+    }
+
+    let appearance = ctx
+        .appearances
+        .read()
+        .await
+        .get(&project_name)
+        .cloned()
+        .unwrap_or_default();
+
+    // Answered for every project, running or not, to escape a broken service worker
+    if req.uri().path() == CLEAR_SITE_DATA_PATH {
+        return Ok(clear_site_data_response(&project_name, &appearance));
+    }
+    let direct = ctx
+        .options_table
+        .read()
+        .await
+        .get(&project_name)
+        .is_some_and(|options| options.direct);
+    if direct {
+        return Ok(direct_response(&project_name, &appearance));
+    }
+
+    let started = Instant::now();
+    let method = req.method().clone();
+    let host = host.to_string();
+    let placeholder = (config.proxy_placeholders
+        && matches!(method, hyper::Method::GET | hyper::Method::HEAD))
+    .then(|| placeholders::for_path(req.uri().path()))
+    .flatten();
+    let if_none_match = req
+        .headers()
+        .get(hyper::header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
 
-    // Look up the target port
+    // Look up the target port: a path route, else the project's process
     let target_port = {
-        let table = routing_table.read().await;
-        table.get(&project_name).copied()
+        let table = ctx.routing_table.read().await;
+        table
+            .get(&project_name)
+            .and_then(|routes| routes.target(req.uri().path()))
     };
 
-    let target_port = match target_port {
-        Some(port) => port,
-        None => {
-            return Ok(not_found_response(&format!(
-                "Project '{}' not found or has no running process",
-                project_name
-            )));
+    // A lazily started project holds the request until it's up
+    let lazy = match (target_port, &ctx.starter) {
+        (None, Some(starter)) => starter.route(&project_name, req.uri().path()).await,
+        _ => None,
+    };
+    let target_port = target_port.or_else(|| lazy.as_ref()?.as_ref().ok().copied());
+    if target_port.is_some() {
+        ctx.activity
+            .write()
+            .await
+            .insert(project_name.clone(), Utc::now());
+    }
+
+    let result = match (target_port, lazy) {
+        (Some(port), _) => match admit(&ctx, &project_name).await {
+            Some(Admission::Shed { position, queued }) => Ok(shed_response(
+                &project_name,
+                &appearance,
+                position,
+                queued,
+                accepts_html(req.headers()),
+            )),
+            Some(Admission::Admitted(permit)) => {
+                let result = proxy_request(req, &ctx, client, &project_name, port, &config).await;
+                // The slot is held until the response headers are in
+                drop(permit);
+                result
+            }
+            None => proxy_request(req, &ctx, client, &project_name, port, &config).await,
+        },
+        (None, Some(Err(e))) => Ok(error_response(&format!(
+            "{} failed to start: {:#}",
+            project_name, e
+        ))),
+        (None, _) if ctx.starting.read().await.contains(&project_name) => {
+            Ok(starting_response(&project_name, &appearance))
+        }
+        (None, _) => Ok(not_found_response(&format!(
+            "Project '{}' has no running process",
+            project_name
+        ))),
+    };
+    let result = match (result, placeholder) {
+        (Ok(resp), Some(placeholder)) if placeholders::replaces(resp.status()) => {
+            Ok(placeholder_response(placeholder, if_none_match.as_deref()))
+        }
+        (result, _) => result,
+    };
+
+    let latency = started.elapsed();
+    let status = result.as_ref().ok().map(|resp| resp.status().as_u16());
+    ctx.metrics.record(&project_name, status, latency).await;
+    tracing::info!(
+        target: "proj_daemon::access",
+        project = %project_name,
+        method = %method,
+        host = %host,
+        status,
+        latency_ms = latency.as_millis() as u64,
+        upstream_port = target_port,
+        "{} {} {}",
+        method,
+        host,
+        status.map_or("error".to_string(), |s| s.to_string())
+    );
+    result
+}
+
+/// A slot under the project's in-flight cap, if it has one
+async fn admit(ctx: &ProxyContext, project_name: &str) -> Option<Admission> {
+    let limit = ctx
+        .options_table
+        .read()
+        .await
+        .get(project_name)?
+        .max_in_flight?;
+    Some(ctx.shedder.admit(project_name, limit).await)
+}
+
+/// Forward a request to the port a project is routed to, applying its proxy options
+async fn proxy_request(
+    mut req: Request<Incoming>,
+    ctx: &ProxyContext,
+    client: Client,
+    project_name: &str,
+    target_port: u16,
+    config: &Config,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
+    let options = {
+        let table = ctx.options_table.read().await;
+        table.get(project_name).cloned().unwrap_or_default()
+    };
+
+    // Only pages served through the proxy get credentials, stored or their own
+    let trusted = is_trusted_origin(ctx, req.headers(), config.tld()).await;
+    // Backends rarely answer preflights themselves
+    if options.cors && is_preflight(&req) {
+        return Ok(preflight_response(req.headers(), trusted));
+    }
+    if options.kill_service_workers && is_service_worker_script(&req) {
+        return Ok(service_worker_kill_response());
+    }
+    if matches!(*req.method(), hyper::Method::GET | hyper::Method::HEAD) {
+        if let Some(location) = redirects::trailing_slash(req.uri(), options.trailing_slash) {
+            return Ok(redirect_response(&location));
         }
+    }
+    let origin = req.headers().get(hyper::header::ORIGIN).cloned();
+    // Where the client reached the project, for redirects to point back at
+    let proxied_origin = req
+        .headers()
+        .get(hyper::header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .map(|host| format!("{}://{}", if client.https { "https" } else { "http" }, host));
+
+    // Take the client side of a protocol upgrade (e.g. WebSocket) so it can be
+    // bridged to the backend once it answers 101 Switching Protocols
+    let client_upgrade = is_upgrade_request(&req).then(|| hyper::upgrade::on(&mut req));
+
+    // Buffer small bodies so they can be captured for replay
+    let (parts, body) = req.into_parts();
+    let content_length = parts
+        .headers
+        .get(hyper::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    let (body, captured_body) = match content_length {
+        Some(len) if len <= MAX_CAPTURED_BODY => {
+            let bytes = body.collect().await?.to_bytes();
+            (full_body(bytes.clone()), Some(bytes))
+        }
+        _ => (body.boxed(), None),
     };
+    let mut capture = ctx
+        .captures
+        .record(&parts, captured_body.as_ref(), target_port);
+    let mut parts = parts;
+    apply_request_options(&mut parts, &options, client, target_port);
+    if trusted && !parts.headers.contains_key(hyper::header::AUTHORIZATION) {
+        if let Some(value) = ctx.auth.header(project_name, parts.uri.path()).await {
+            parts.headers.insert(hyper::header::AUTHORIZATION, value);
+        }
+    }
+    let req = Request::from_parts(parts, body);
+    let started = Instant::now();
 
     // Forward the request to the target
-    match forward_request(req, target_port).await {
-        Ok(resp) => Ok(resp),
+    let result = forward_request(&ctx.upstream, req, &options.upstream(target_port)).await;
+
+    let latency_ms = started.elapsed().as_millis() as u64;
+    capture.duration_ms = Some(latency_ms);
+    capture.status = result.as_ref().ok().map(|resp| resp.status().as_u16());
+    let (method, path) = (capture.method.clone(), capture.path.clone());
+    ctx.graphql
+        .record(
+            project_name,
+            &capture.path,
+            capture.body.as_deref(),
+            capture.status,
+            capture.duration_ms.unwrap_or(0),
+        )
+        .await;
+    ctx.captures.push(project_name, capture).await;
+
+    match result {
+        Ok(mut resp) => {
+            if resp.status() == StatusCode::SWITCHING_PROTOCOLS {
+                if let Some(client_upgrade) = client_upgrade {
+                    let backend_upgrade = hyper::upgrade::on(&mut resp);
+                    tokio::spawn(bridge_upgrade(
+                        client_upgrade,
+                        backend_upgrade,
+                        (config.proxy_idle_timeout_secs > 0)
+                            .then(|| Duration::from_secs(config.proxy_idle_timeout_secs)),
+                    ));
+                }
+                return Ok(resp);
+            }
+
+            apply_response_options(&mut resp, &options, origin.as_ref(), trusted);
+            if let Some(proxied_origin) = proxied_origin.filter(|_| !options.keep_redirects) {
+                rewrite_redirect(&mut resp, &options.upstream(target_port), &proxied_origin);
+            }
+            if let Some(spec) = &options.openapi_spec {
+                resp = check_drift(ctx, project_name, spec, &method, &path, resp).await?;
+            }
+            if !options.rewrites.is_empty() {
+                resp = rewrite_response(&options.rewrites, &method, resp).await?;
+            }
+            let (mut resp, document_bytes) =
+                measure_document(ctx, project_name, &options, &method, resp);
+            ctx.budgets
+                .record(project_name, &options.budgets, latency_ms, document_bytes)
+                .await;
+            if method == "GET" && resp.status().is_success() {
+                let content_type = resp
+                    .headers()
+                    .get(hyper::header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string);
+                let (parts, body) = resp.into_parts();
+                let body = ctx.assets.measure(project_name, &path, content_type, body);
+                resp = Response::from_parts(parts, body);
+            }
+            Ok(resp)
+        }
         Err(e) => {
-            tracing::error!("Failed to forward request: {}", e);
+            tracing::error!(project = %project_name, "Failed to forward request: {}", e);
             Ok(error_response(&format!(
                 "Failed to connect to backend: {}",
                 e
@@ -101,35 +825,998 @@ async fn handle_request(
     }
 }
 
-/// Forward a request to the target port
-async fn forward_request(
+/// Pass on a request from a forward-proxy client for a host outside the TLD:
+/// to where the client's project overrides the host, else to the host itself,
+/// or through the outbound proxy
+async fn forward_proxy_request(
+    mut req: Request<Incoming>,
+    ctx: &ProxyContext,
+    client: Client,
+    authority: &Authority,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    if let Some(target) = override_target(ctx, req.headers(), authority.host()).await {
+        tracing::debug!("Overriding {} -> {}", authority, target);
+        add_forwarded_headers(req.headers_mut(), client);
+        return forward_to(&ctx.upstream, req, &target).await;
+    }
+    if !client.may_pass_through() {
+        return forbidden_response(&format!("not a proxy for {}", authority.host()));
+    }
+
+    let route = outbound::route(
+        &ctx.config.borrow(),
+        Feature::Passthrough,
+        authority.host(),
+        false,
+    );
+    match route {
+        Ok(Route::Direct) => {
+            let port = authority.port_u16().unwrap_or(80);
+            let local = ctx.network.borrow().clone();
+            match outbound::resolve_remote(authority.host(), port, &local).await {
+                Ok(addrs) => match addrs.first() {
+                    Some(addr) => forward_to(&ctx.upstream, req, &addr.to_string()).await,
+                    None => error_response(&format!("{} has no addresses", authority.host())),
+                },
+                Err(e) => outbound_error_response(e),
+            }
+        }
+        Ok(Route::Proxy(proxy)) => {
+            let (mut parts, body) = req.into_parts();
+            parts.headers.remove(hyper::header::PROXY_AUTHORIZATION);
+            parts.headers.remove("proxy-connection");
+            let req = Request::from_parts(parts, body.boxed());
+            match outbound::send_through(&proxy, req).await {
+                Ok(resp) => {
+                    let (parts, body) = resp.into_parts();
+                    Response::from_parts(parts, body.boxed())
+                }
+                Err(e) => error_response(&format!("{:#}", e)),
+            }
+        }
+        Err(e) => error_response(&format!("{:#}", e)),
+    }
+}
+
+/// Where a tunnel asked for with CONNECT ends
+enum Tunnel {
+    /// An overridden host: decrypt and send each request to the target
+    Intercept(TlsAcceptor, String),
+    /// Any other host, already connected to
+    Pass(TcpStream),
+    /// A host of the proxy's own, served in place
+    Own(OwnListener),
+}
+
+/// Answer a CONNECT by tunnelling to the requested host, or for a host the
+/// client's project overrides, by decrypting the connection and sending its
+/// requests to the override
+async fn connect_response(
     req: Request<Incoming>,
-    target_port: u16,
-) -> Result<Response<BoxBody<Bytes, hyper::Error>>> {
-    let target_addr = format!("127.0.0.1:{}", target_port);
+    ctx: ProxyContext,
+    client: Client,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let Some(authority) = req.uri().authority().cloned() else {
+        return bad_request_response("CONNECT needs a host and port");
+    };
+    if !client.may_tunnel() {
+        return unknown_host_response();
+    }
+
+    let port = authority.port_u16().unwrap_or(443);
+    let own = match own_listener(&ctx, authority.host(), port).await {
+        Ok(own) => own,
+        Err(e) => return error_response(&e.to_string()),
+    };
+    let tunnel_end = match own {
+        // A project host, from a client on another device: served here
+        // rather than at that device's idea of localhost
+        Some(own) => Tunnel::Own(own),
+        None => match override_target(&ctx, req.headers(), authority.host()).await {
+            Some(target) => match ctx.tls_acceptor() {
+                Ok(acceptor) => Tunnel::Intercept(acceptor, target),
+                Err(e) => {
+                    return error_response(&format!(
+                        "Can't decrypt HTTPS for {}: {}",
+                        authority.host(),
+                        e
+                    ))
+                }
+            },
+            None if !client.may_pass_through() => {
+                return forbidden_response(&format!("not a proxy for {}", authority.host()));
+            }
+            None => {
+                let config = ctx.config.borrow().clone();
+                let local = ctx.network.borrow().clone();
+                let host = authority.host();
+                match outbound::connect_remote(&config, Feature::Passthrough, host, port, &local)
+                    .await
+                {
+                    Ok(stream) => Tunnel::Pass(stream),
+                    Err(e) => return outbound_error_response(e),
+                }
+            }
+        },
+    };
+
+    let idle_timeout = tunnel_idle_timeout(&ctx);
+    let upgrade = hyper::upgrade::on(req);
+    let upstream = ctx.upstream.clone();
+    tokio::spawn(async move {
+        let upgraded = match upgrade.await {
+            Ok(upgraded) => TokioIo::new(upgraded),
+            Err(e) => {
+                tracing::debug!("CONNECT to {} failed: {}", authority, e);
+                return;
+            }
+        };
+        match tunnel_end {
+            Tunnel::Intercept(acceptor, target) => match acceptor.accept(upgraded).await {
+                Ok(tls) => {
+                    let client = Client {
+                        https: true,
+                        ..client
+                    };
+                    serve_intercepted(TokioIo::new(tls), upstream, target, client).await;
+                }
+                Err(e) => tracing::debug!("TLS handshake for {} failed: {}", authority, e),
+            },
+            Tunnel::Pass(stream) => {
+                if let Err(e) = tunnel(upgraded, stream, idle_timeout).await {
+                    tracing::debug!("Tunnel to {} closed: {}", authority, e);
+                }
+            }
+            Tunnel::Own(own) => serve_own(upgraded, own, ctx, client).await,
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(empty_body())
+        .unwrap()
+}
+
+/// Serve the decrypted requests of an intercepted HTTPS connection, sending
+/// each to `target`
+async fn serve_intercepted<I>(io: I, upstream: Upstream, target: String, client: Client)
+where
+    I: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+{
+    let service = service_fn(move |mut req: Request<Incoming>| {
+        let upstream = upstream.clone();
+        let target = target.clone();
+        async move {
+            add_forwarded_headers(req.headers_mut(), client);
+            Ok::<_, hyper::Error>(forward_to(&upstream, req, &target).await)
+        }
+    });
+
+    if let Err(e) = http1::Builder::new()
+        .preserve_header_case(true)
+        .serve_connection(io, service)
+        .await
+    {
+        tracing::debug!("Intercepted connection error: {}", e);
+    }
+}
+
+/// Which of the proxy's listeners a tunnel ends at
+pub(crate) enum OwnListener {
+    Http,
+    Https(TlsAcceptor),
+}
+
+/// Which of the proxy's own listeners a tunnel to `host:port` is for, if
+/// `host` is one of its own (under the TLD, or an alias): the HTTPS listener
+/// for port 443 or the HTTPS port, else the HTTP listener
+pub(crate) async fn own_listener(
+    ctx: &ProxyContext,
+    host: &str,
+    port: u16,
+) -> Result<Option<OwnListener>> {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    let (tld, https_port) = {
+        let config = ctx.config.borrow();
+        (config.tld().to_string(), config.https_port)
+    };
+    let own = host == tld
+        || host.ends_with(&format!(".{}", tld))
+        || ctx.aliases.read().await.contains_key(&host);
+    if !own {
+        return Ok(None);
+    }
+    if port == 443 || (https_port > 0 && port == https_port) {
+        if https_port == 0 {
+            anyhow::bail!("HTTPS is disabled (https_port is 0)");
+        }
+        return Ok(Some(OwnListener::Https(ctx.tls_acceptor()?)));
+    }
+    Ok(Some(OwnListener::Http))
+}
 
-    // Connect to target
-    let stream = TcpStream::connect(&target_addr).await?;
-    let io = TokioIo::new(stream);
+/// Serve a tunnel to one of the proxy's own listeners here, rather than over
+/// a connection to it, so its requests keep the client's address. Boxed, as
+/// a CONNECT on it is served by another.
+pub(crate) fn serve_own<I>(
+    io: I,
+    own: OwnListener,
+    ctx: ProxyContext,
+    client: Client,
+) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>>
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    Box::pin(async move {
+        match own {
+            OwnListener::Http => serve_connection(TokioIo::new(io), ctx, client).await,
+            OwnListener::Https(acceptor) => match acceptor.accept(io).await {
+                Ok(tls) => {
+                    let client = Client {
+                        https: true,
+                        ..client
+                    };
+                    serve_connection(TokioIo::new(tls), ctx, client).await;
+                }
+                Err(e) => tracing::debug!("TLS handshake failed: {}", e),
+            },
+        }
+    })
+}
 
-    // Create HTTP connection
-    let (mut sender, conn) = hyper::client::conn::http1::handshake(io).await?;
+/// How long a tunnel may sit idle, if `proxy_idle_timeout_secs` limits it
+pub(crate) fn tunnel_idle_timeout(ctx: &ProxyContext) -> Option<Duration> {
+    let config = ctx.config.borrow();
+    (config.proxy_idle_timeout_secs > 0)
+        .then(|| Duration::from_secs(config.proxy_idle_timeout_secs))
+}
 
-    // Spawn connection handler
+/// Where a forward-proxy client reaches `host`: the override of the project
+/// named in its proxy credentials (`http://<project>@<proxy>`), or without
+/// any, of the first project overriding it
+async fn override_target(ctx: &ProxyContext, headers: &HeaderMap, host: &str) -> Option<String> {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    let table = ctx.options_table.read().await;
+    match proxy_user(headers) {
+        Some(project_name) => table.get(&project_name)?.overrides.get(&host).cloned(),
+        None => table
+            .iter()
+            .filter_map(|(name, options)| Some((name, options.overrides.get(&host)?)))
+            .min_by_key(|(name, _)| *name)
+            .map(|(_, target)| target.clone()),
+    }
+}
+
+/// User name in a request's `Proxy-Authorization: Basic` credentials
+fn proxy_user(headers: &HeaderMap) -> Option<String> {
+    use base64::Engine;
+
+    let value = headers
+        .get(hyper::header::PROXY_AUTHORIZATION)?
+        .to_str()
+        .ok()?;
+    let encoded = value.strip_prefix("Basic ")?.trim();
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .ok()?;
+    let credentials = String::from_utf8(decoded).ok()?;
+    let user = credentials.split(':').next()?;
+    (!user.is_empty()).then(|| user.to_string())
+}
+
+/// Send a request on to `addr` the way an origin server expects it: a path
+/// rather than an absolute URL, and without the headers meant for the proxy
+async fn forward_to(
+    upstream: &Upstream,
+    req: Request<Incoming>,
+    addr: &str,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let (mut parts, body) = req.into_parts();
+    parts.headers.remove(hyper::header::PROXY_AUTHORIZATION);
+    parts.headers.remove("proxy-connection");
+    if let Some(path) = parts.uri.path_and_query().cloned() {
+        parts.uri = hyper::Uri::from(path);
+    }
+
+    match forward_request(upstream, Request::from_parts(parts, body.boxed()), addr).await {
+        Ok(resp) => resp,
+        Err(e) => error_response(&format!("Failed to connect to {}: {}", addr, e)),
+    }
+}
+
+/// Whether a request asks to switch protocols (`Connection: upgrade`)
+fn is_upgrade_request<B>(req: &Request<B>) -> bool {
+    req.headers().contains_key(hyper::header::UPGRADE)
+        && req
+            .headers()
+            .get(hyper::header::CONNECTION)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| {
+                v.split(',')
+                    .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+            })
+}
+
+/// Copy bytes both ways between an upgraded client and backend connection
+async fn bridge_upgrade(
+    client: hyper::upgrade::OnUpgrade,
+    backend: hyper::upgrade::OnUpgrade,
+    idle_timeout: Option<Duration>,
+) {
+    let (client, backend) = match tokio::try_join!(client, backend) {
+        Ok(upgraded) => upgraded,
+        Err(e) => {
+            tracing::debug!("Upgrade failed: {}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = tunnel(TokioIo::new(client), TokioIo::new(backend), idle_timeout).await {
+        tracing::debug!("Upgraded connection closed: {}", e);
+    }
+}
+
+/// Bidirectional copy that gives up after `idle_timeout` without traffic
+pub(crate) async fn tunnel<A, B>(
+    mut a: A,
+    mut b: B,
+    idle_timeout: Option<Duration>,
+) -> std::io::Result<()>
+where
+    A: AsyncRead + AsyncWrite + Unpin,
+    B: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut buf_a = vec![0u8; 16 * 1024];
+    let mut buf_b = vec![0u8; 16 * 1024];
+
+    loop {
+        let read = async {
+            tokio::select! {
+                n = a.read(&mut buf_a) => (true, n),
+                n = b.read(&mut buf_b) => (false, n),
+            }
+        };
+        let (from_a, n) = match idle_timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, read).await {
+                Ok(result) => result,
+                Err(_) => {
+                    tracing::debug!("Closing idle upgraded connection");
+                    return Ok(());
+                }
+            },
+            None => read.await,
+        };
+
+        match (from_a, n?) {
+            (true, 0) => return b.shutdown().await,
+            (false, 0) => return a.shutdown().await,
+            (true, n) => b.write_all(&buf_a[..n]).await?,
+            (false, n) => a.write_all(&buf_b[..n]).await?,
+        }
+    }
+}
+
+/// Buffer a JSON response and validate it against the project's spec in the
+/// background. Compressed bodies are left alone; the backend isn't offered
+/// compression while a project has a spec.
+async fn check_drift(
+    ctx: &ProxyContext,
+    project_name: &str,
+    spec: &std::path::Path,
+    method: &str,
+    path: &str,
+    resp: Response<BoxBody<Bytes, hyper::Error>>,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
+    let is_json = resp
+        .headers()
+        .get(hyper::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    let small = resp
+        .headers()
+        .get(hyper::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .is_none_or(|len| len <= MAX_VALIDATED_BODY);
+    let encoded = resp.headers().contains_key(hyper::header::CONTENT_ENCODING);
+    if !is_json || !small || encoded {
+        return Ok(resp);
+    }
+
+    let (parts, body) = resp.into_parts();
+    let bytes = match buffer_body(body, MAX_VALIDATED_BODY).await? {
+        Buffered::Whole(bytes) => bytes,
+        Buffered::TooLarge(body) => return Ok(Response::from_parts(parts, body)),
+    };
+
+    let drift = ctx.drift.clone();
+    let project_name = project_name.to_string();
+    let spec = spec.to_path_buf();
+    let method = method.to_string();
+    let path = path.to_string();
+    let status = parts.status.as_u16();
+    let body = bytes.clone();
     tokio::spawn(async move {
-        if let Err(e) = conn.with_upgrades().await {
-            tracing::debug!("Backend connection error: {}", e);
+        drift
+            .check(&project_name, &spec, &method, &path, status, &body)
+            .await;
+    });
+
+    Ok(Response::from_parts(parts, full_body(bytes)))
+}
+
+/// Apply a project's rewrites to a text response small enough to buffer.
+/// Compressed bodies and event streams are left alone; the backend isn't
+/// offered compression while a project has rewrites.
+async fn rewrite_response(
+    rewrites: &[Rewrite],
+    method: &str,
+    resp: Response<BoxBody<Bytes, hyper::Error>>,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
+    let headers = resp.headers();
+    let is_text = headers
+        .get(hyper::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| rewrites::is_text(v) && !v.starts_with("text/event-stream"));
+    let small = headers
+        .get(hyper::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .is_none_or(|len| len <= MAX_REWRITTEN_BODY);
+    let has_body = method != "HEAD"
+        && !matches!(
+            resp.status(),
+            StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED
+        );
+    if !is_text || !small || !has_body || headers.contains_key(hyper::header::CONTENT_ENCODING) {
+        return Ok(resp);
+    }
+
+    let (mut parts, body) = resp.into_parts();
+    let bytes = match buffer_body(body, MAX_REWRITTEN_BODY).await? {
+        Buffered::Whole(bytes) => bytes,
+        Buffered::TooLarge(body) => return Ok(Response::from_parts(parts, body)),
+    };
+    let Ok(text) = std::str::from_utf8(&bytes) else {
+        return Ok(Response::from_parts(parts, full_body(bytes)));
+    };
+    let bytes = match rewrites::apply(text, rewrites) {
+        Cow::Borrowed(_) => bytes,
+        Cow::Owned(text) => {
+            // The validators describe the backend's body, not this one
+            parts.headers.remove(hyper::header::ETAG);
+            parts.headers.remove(hyper::header::LAST_MODIFIED);
+            parts
+                .headers
+                .insert(hyper::header::CONTENT_LENGTH, HeaderValue::from(text.len()));
+            Bytes::from(text)
+        }
+    };
+    Ok(Response::from_parts(parts, full_body(bytes)))
+}
+
+/// What [`buffer_body`] read of a body
+enum Buffered {
+    /// All of it
+    Whole(Bytes),
+    /// Too much to buffer: the same body, from its start
+    TooLarge(BoxBody<Bytes, hyper::Error>),
+}
+
+/// Read `body` to its end, unless it runs past `limit` bytes. A body without
+/// a length is only known to be too large once that much has been read, so
+/// what was read is handed back in front of the rest.
+async fn buffer_body(
+    mut body: BoxBody<Bytes, hyper::Error>,
+    limit: u64,
+) -> Result<Buffered, hyper::Error> {
+    let mut read = VecDeque::new();
+    let mut len = 0;
+    while let Some(frame) = body.frame().await {
+        // Trailers are dropped along with the framing
+        let Ok(data) = frame?.into_data() else {
+            continue;
+        };
+        len += data.len() as u64;
+        read.push_back(data);
+        if len > limit {
+            return Ok(Buffered::TooLarge(Replayed { read, rest: body }.boxed()));
+        }
+    }
+    let mut bytes = Vec::with_capacity(len as usize);
+    for data in read {
+        bytes.extend_from_slice(&data);
+    }
+    Ok(Buffered::Whole(Bytes::from(bytes)))
+}
+
+/// A body whose start was already read: that, then whatever is left
+struct Replayed {
+    read: VecDeque<Bytes>,
+    rest: BoxBody<Bytes, hyper::Error>,
+}
+
+impl hyper::body::Body for Replayed {
+    type Data = Bytes;
+    type Error = hyper::Error;
+
+    fn poll_frame(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Result<hyper::body::Frame<Bytes>, hyper::Error>>> {
+        match self.read.pop_front() {
+            Some(data) => std::task::Poll::Ready(Some(Ok(hyper::body::Frame::data(data)))),
+            None => std::pin::Pin::new(&mut self.rest).poll_frame(cx),
         }
+    }
+}
+
+/// Size of a response if it's a main document: HTML answering a GET. Under a
+/// document budget, a streamed document is counted as it passes through and
+/// recorded once it's sent.
+fn measure_document(
+    ctx: &ProxyContext,
+    project_name: &str,
+    options: &ProxyOptions,
+    method: &str,
+    resp: Response<BoxBody<Bytes, hyper::Error>>,
+) -> (Response<BoxBody<Bytes, hyper::Error>>, Option<u64>) {
+    let is_document = method == "GET"
+        && resp.status().is_success()
+        && resp
+            .headers()
+            .get(hyper::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("text/html"));
+    if !is_document {
+        return (resp, None);
+    }
+
+    let length = resp
+        .headers()
+        .get(hyper::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if length.is_some() || options.budgets.document_bytes.is_none() {
+        return (resp, length);
+    }
+
+    let budgets = ctx.budgets.clone();
+    let limits = options.budgets;
+    let project_name = project_name.to_string();
+    let (parts, body) = resp.into_parts();
+    let body = assets::counted(body, move |bytes| {
+        tokio::spawn(async move {
+            budgets.record_document(&project_name, &limits, bytes).await;
+        });
     });
+    (Response::from_parts(parts, body), None)
+}
+
+/// Add the forwarding headers, point `Host` at the backend if the project
+/// asks for it, and inject the active dev auth persona's identity headers,
+/// replacing any sent by the client
+fn apply_request_options(
+    parts: &mut hyper::http::request::Parts,
+    options: &ProxyOptions,
+    client: Client,
+    target_port: u16,
+) {
+    add_forwarded_headers(&mut parts.headers, client);
+    if !options.rewrites.is_empty() || options.openapi_spec.is_some() {
+        // Rewrites and drift checks need bodies they can read
+        parts.headers.remove(hyper::header::ACCEPT_ENCODING);
+    }
+    if options.no_cache {
+        for name in CONDITIONAL_HEADERS {
+            parts.headers.remove(name);
+        }
+    }
+    if options.rewrite_host {
+        if let Ok(host) = HeaderValue::from_str(&options.upstream(target_port)) {
+            parts.headers.insert(hyper::header::HOST, host);
+        }
+    }
+
+    let Some(persona) = options.active_persona() else {
+        return;
+    };
+    for (name, value) in persona.identity_headers() {
+        match (
+            hyper::header::HeaderName::from_bytes(name.as_bytes()),
+            hyper::header::HeaderValue::from_str(&value),
+        ) {
+            (Ok(name), Ok(value)) => {
+                parts.headers.insert(name, value);
+            }
+            _ => tracing::warn!("Skipping invalid persona header {}", name),
+        }
+    }
+}
+
+/// Tell the backend who the client is: `X-Forwarded-For` and `Forwarded` get
+/// this hop appended, while `X-Forwarded-Proto` and `-Host` set by a proxy in
+/// front of this one are kept
+fn add_forwarded_headers(headers: &mut HeaderMap, client: Client) {
+    let proto = if client.https { "https" } else { "http" };
+    let ip = client.addr.ip();
+    let host = headers
+        .get(hyper::header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let append = |headers: &HeaderMap, name: &str, value: String| match headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(previous) => format!("{}, {}", previous, value),
+        None => value,
+    };
+
+    // IPv6 nodes and hosts with a port aren't tokens, so they are quoted
+    let node = match ip {
+        std::net::IpAddr::V4(ip) => ip.to_string(),
+        std::net::IpAddr::V6(ip) => format!("\"[{}]\"", ip),
+    };
+    let mut element = format!("for={};proto={}", node, proto);
+    if let Some(host) = &host {
+        element.push_str(&format!(";host=\"{}\"", host));
+    }
+
+    let mut set = vec![
+        (
+            "x-forwarded-for",
+            append(headers, "x-forwarded-for", ip.to_string()),
+        ),
+        ("forwarded", append(headers, "forwarded", element)),
+    ];
+    if !headers.contains_key("x-forwarded-proto") {
+        set.push(("x-forwarded-proto", proto.to_string()));
+    }
+    if let Some(host) = host.filter(|_| !headers.contains_key("x-forwarded-host")) {
+        set.push(("x-forwarded-host", host));
+    }
+    for (name, value) in set {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(name, value);
+        }
+    }
+}
+
+/// Whether a request is a CORS preflight
+fn is_preflight<B>(req: &Request<B>) -> bool {
+    req.method() == hyper::Method::OPTIONS
+        && req
+            .headers()
+            .contains_key(hyper::header::ACCESS_CONTROL_REQUEST_METHOD)
+}
+
+/// Allow whatever a preflight asks for, from its origin; with credentials
+/// only if it's `trusted`
+fn preflight_response(
+    headers: &HeaderMap,
+    trusted: bool,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    use hyper::header;
+
+    let mut resp = Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header(header::ACCESS_CONTROL_MAX_AGE, "600")
+        .header(
+            header::VARY,
+            "Origin, Access-Control-Request-Method, Access-Control-Request-Headers",
+        )
+        .body(empty_body())
+        .unwrap();
+    let allowed = resp.headers_mut();
+    allow_origin(allowed, headers.get(header::ORIGIN), trusted);
+    if let Some(method) = headers.get(header::ACCESS_CONTROL_REQUEST_METHOD) {
+        allowed.insert(header::ACCESS_CONTROL_ALLOW_METHODS, method.clone());
+    }
+    if let Some(requested) = headers.get(header::ACCESS_CONTROL_REQUEST_HEADERS) {
+        allowed.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, requested.clone());
+    }
+    resp
+}
+
+/// Whether a request is the browser fetching a service worker's script, to
+/// register or update it
+fn is_service_worker_script<B>(req: &Request<B>) -> bool {
+    req.headers()
+        .get("service-worker")
+        .is_some_and(|v| v.as_bytes() == b"script")
+}
+
+/// A service worker that takes over from whatever is registered, empties the
+/// origin's caches, unregisters itself and reloads the open pages
+const SERVICE_WORKER_KILL_SCRIPT: &str = "\
+self.addEventListener('install', () => self.skipWaiting());
+self.addEventListener('activate', (event) => {
+  event.waitUntil((async () => {
+    for (const key of await caches.keys()) {
+      await caches.delete(key);
+    }
+    await self.registration.unregister();
+    for (const client of await self.clients.matchAll({ type: 'window' })) {
+      client.navigate(client.url);
+    }
+  })());
+});
+";
+
+fn service_worker_kill_response() -> Response<BoxBody<Bytes, hyper::Error>> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/javascript")
+        .header("Cache-Control", "no-store")
+        .body(full_body(Bytes::from_static(
+            SERVICE_WORKER_KILL_SCRIPT.as_bytes(),
+        )))
+        .unwrap()
+}
+
+/// Send the client to `location` without caching the redirect, so changing
+/// the project's options takes effect at once
+fn redirect_response(location: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+    Response::builder()
+        .status(StatusCode::TEMPORARY_REDIRECT)
+        .header(hyper::header::LOCATION, location)
+        .header("Cache-Control", "no-store")
+        .body(empty_body())
+        .unwrap()
+}
+
+/// Point a Location at the backend's own address back at the proxy
+fn rewrite_redirect<B>(resp: &mut Response<B>, backend: &str, proxied_origin: &str) {
+    let location = resp
+        .headers()
+        .get(hyper::header::LOCATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|location| redirects::rewrite_location(location, backend, proxied_origin))
+        .and_then(|location| HeaderValue::from_str(&location).ok());
+    if let Some(location) = location {
+        tracing::debug!("Rewrote redirect to {:?}", location);
+        resp.headers_mut().insert(hyper::header::LOCATION, location);
+    }
+}
 
-    // Forward the request
-    let resp = sender.send_request(req).await?;
+/// Allow `origin` with credentials if it's `trusted`, else any origin without
+fn allow_origin(headers: &mut HeaderMap, origin: Option<&HeaderValue>, trusted: bool) {
+    use hyper::header;
 
-    // Convert the response body
+    match origin.filter(|_| trusted) {
+        Some(origin) => {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        None => {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_ORIGIN,
+                HeaderValue::from_static("*"),
+            );
+        }
+    }
+}
+
+/// Apply per-project response header adjustments
+fn apply_response_options<B>(
+    resp: &mut Response<B>,
+    options: &ProxyOptions,
+    origin: Option<&HeaderValue>,
+    trusted: bool,
+) {
+    if options.cors {
+        let headers = resp.headers_mut();
+        allow_origin(headers, origin, trusted);
+        if origin.is_some() {
+            headers.append(hyper::header::VARY, HeaderValue::from_static("Origin"));
+        }
+    }
+    if options.relax_csp {
+        let headers = resp.headers_mut();
+        for name in EMBEDDING_HEADERS {
+            headers.remove(name);
+        }
+        headers.insert(
+            "x-proj-csp",
+            hyper::header::HeaderValue::from_static("relaxed (dev only)"),
+        );
+    }
+    if options.no_cache {
+        let headers = resp.headers_mut();
+        for name in CACHING_HEADERS {
+            headers.remove(name);
+        }
+        headers.insert(
+            hyper::header::CACHE_CONTROL,
+            HeaderValue::from_static("no-store, max-age=0"),
+        );
+    }
+}
+
+/// Whether a request comes from a page the proxy serves, or from no page at
+/// all: not one the browser calls cross-site, and without an `Origin` outside
+/// the TLD, the aliases and loopback
+async fn is_trusted_origin(ctx: &ProxyContext, headers: &HeaderMap, tld: &str) -> bool {
+    let cross_site = headers
+        .get("sec-fetch-site")
+        .is_some_and(|site| site.as_bytes().eq_ignore_ascii_case(b"cross-site"));
+    if cross_site {
+        return false;
+    }
+    let Some(origin) = headers.get(hyper::header::ORIGIN) else {
+        return true;
+    };
+    let host = origin
+        .to_str()
+        .ok()
+        .and_then(|origin| origin.parse::<hyper::Uri>().ok())
+        .and_then(|uri| Some(uri.host()?.trim_end_matches('.').to_ascii_lowercase()));
+    let Some(host) = host else {
+        return false;
+    };
+    let ip = host.trim_start_matches('[').trim_end_matches(']');
+    host == "localhost"
+        || host == tld
+        || host.ends_with(&format!(".{}", tld))
+        || ip
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+        || ctx.aliases.read().await.contains_key(&host)
+}
+
+/// Hostname of a Host header value, without the port, lowercased
+fn hostname(host: &str) -> String {
+    host.split(':').next().unwrap_or("").to_ascii_lowercase()
+}
+
+/// Whether requests for `host` are the proxy's own to answer: those for the
+/// proxy itself and for hosts under the TLD
+fn is_proxy_host(host: &str, tld: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    let ip = host.trim_start_matches('[').trim_end_matches(']');
+    host == "localhost"
+        || host == tld
+        || host.ends_with(&format!(".{}", tld))
+        || ip.parse::<std::net::IpAddr>().is_ok()
+}
+
+/// Extract the project name from a Host header value: the label before the
+/// TLD (see [`naming::project_from_host`]).
+///
+/// Returns `None` for hosts outside the TLD, which only reach a project as a
+/// registered alias, and for bare `localhost`, the TLD itself or IP hosts,
+/// which address the proxy itself.
+fn parse_project_name(host: &str, tld: &str) -> Option<String> {
+    let hostname = hostname(host);
+    let hostname = hostname.trim_end_matches('.');
+    if hostname == "localhost" || hostname == tld || hostname.parse::<std::net::IpAddr>().is_ok() {
+        return None;
+    }
+    naming::project_from_host(hostname, tld).or_else(|| {
+        // A project named before names were hostname labels, e.g. `my_app`
+        let hostname = hostname.to_ascii_lowercase();
+        let subdomains = hostname.strip_suffix(tld)?.strip_suffix('.')?;
+        subdomains
+            .rsplit('.')
+            .next()
+            .filter(|name| !name.is_empty())
+            .map(|name| name.to_string())
+    })
+}
+
+/// Forward a request to the target address
+async fn forward_request(
+    upstream: &Upstream,
+    req: Request<BoxBody<Bytes, hyper::Error>>,
+    target_addr: &str,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>> {
+    let resp = upstream.send(req, target_addr).await?;
     let (parts, body) = resp.into_parts();
-    let body = body.map_err(|e| e).boxed();
+    Ok(Response::from_parts(parts, body.boxed()))
+}
+
+/// Build the PAC script routing `*.<tld>`, the aliases and the overridden
+/// hosts through the proxy
+fn pac_script(config: &Config, hosts: &[String]) -> String {
+    let mut matches = vec![format!(
+        "host === \"{tld}\" || dnsDomainIs(host, \".{tld}\")",
+        tld = config.tld()
+    )];
+    matches.extend(hosts.iter().map(|host| format!("host === \"{}\"", host)));
+    [
+        "function FindProxyForURL(url, host) {".to_string(),
+        format!("  if ({}) {{", matches.join(" || ")),
+        format!("    return \"PROXY {}\";", config.proxy_addr()),
+        "  }".to_string(),
+        "  return \"DIRECT\";".to_string(),
+        "}".to_string(),
+        String::new(),
+    ]
+    .join("\n")
+}
+
+/// Create a PAC file response
+async fn pac_response(
+    ctx: &ProxyContext,
+    config: &Config,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let mut hosts: Vec<String> = ctx.aliases.read().await.keys().cloned().collect();
+    for options in ctx.options_table.read().await.values() {
+        hosts.extend(options.overrides.keys().cloned());
+    }
+    hosts.sort();
+    hosts.dedup();
+    let body = Full::new(Bytes::from(pac_script(config, &hosts)))
+        .map_err(|never| match never {})
+        .boxed();
 
-    Ok(Response::from_parts(parts, body))
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/x-ns-proxy-autoconfig")
+        .body(body)
+        .unwrap()
+}
+
+/// Create a JSON response describing the hostnames handled by the proxy
+async fn hosts_response(
+    ctx: &ProxyContext,
+    config: &Config,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let mut hosts: Vec<String> = {
+        let table = ctx.routing_table.read().await;
+        let aliases = ctx.aliases.read().await;
+        // Aliases of projects nothing is routed for go nowhere yet
+        let routed_aliases = aliases
+            .iter()
+            .filter(|(_, project)| table.contains_key(*project))
+            .map(|(alias, _)| alias.clone());
+        table
+            .keys()
+            .map(|name| config.host(name))
+            .chain(routed_aliases)
+            .collect()
+    };
+    hosts.sort();
+
+    let json = serde_json::json!({
+        "proxy": config.proxy_addr().to_string(),
+        "pac_url": format!("http://{}/proxy.pac", config.proxy_addr()),
+        "domains": [format!("*.{}", config.tld())],
+        "hosts": hosts,
+    });
+    let body = Full::new(Bytes::from(json.to_string()))
+        .map_err(|never| match never {})
+        .boxed();
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(body)
+        .unwrap()
+}
+
+/// Serve proxy metrics in the Prometheus text format
+async fn metrics_response(metrics: &ProxyMetrics) -> Response<BoxBody<Bytes, hyper::Error>> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .body(full_body(Bytes::from(metrics.render().await)))
+        .unwrap()
+}
+
+/// Create a 400 response
+fn bad_request_response(message: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header("Content-Type", "text/plain")
+        .body(full_body(Bytes::from(format!(
+            "Bad Request: {}\n",
+            message
+        ))))
+        .unwrap()
 }
 
 /// Create a 404 response
@@ -145,6 +1832,232 @@ fn not_found_response(message: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
         .unwrap()
 }
 
+/// A 404 for a host that isn't a registered project or alias, saying
+/// nothing about the request
+fn unknown_host_response() -> Response<BoxBody<Bytes, hyper::Error>> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .header("Content-Type", "text/plain")
+        .body(full_body(Bytes::from_static(b"Not Found\n")))
+        .unwrap()
+}
+
+/// Placeholder served while a project's health check hasn't passed; reloads itself
+fn starting_response(
+    project_name: &str,
+    appearance: &Appearance,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let html = project_page(
+        project_name,
+        appearance,
+        "{name} is starting",
+        "{name} is starting up&hellip;",
+        "<p>Waiting for its health check to pass. This page reloads automatically.</p>",
+        Some(1),
+    );
+
+    Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .header("Retry-After", "1")
+        .header("Cache-Control", "no-store")
+        .body(full_body(Bytes::from(html)))
+        .unwrap()
+}
+
+/// Serve a placeholder, or 304 Not Modified if the client has it already
+fn placeholder_response(
+    placeholder: &Placeholder,
+    if_none_match: Option<&str>,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let builder = Response::builder()
+        .header("ETag", placeholder.etag)
+        .header("Cache-Control", "no-cache");
+    if placeholder.is_fresh(if_none_match) {
+        return builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(empty_body())
+            .unwrap();
+    }
+    builder
+        .status(StatusCode::OK)
+        .header("Content-Type", placeholder.content_type)
+        .body(full_body(Bytes::from_static(placeholder.body)))
+        .unwrap()
+}
+
+/// Whether a request comes from a browser loading a page
+fn accepts_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(hyper::header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/html"))
+}
+
+/// Answer to a request shed while its project has too many in flight: a page
+/// that retries by itself for browsers, a plain 503 for everything else
+fn shed_response(
+    project_name: &str,
+    appearance: &Appearance,
+    position: usize,
+    queued: usize,
+    html: bool,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let builder = Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header("Retry-After", "2")
+        .header("Cache-Control", "no-store");
+    if !html {
+        return builder
+            .header("Content-Type", "text/plain")
+            .body(full_body(Bytes::from(format!(
+                "Service Unavailable: {} is warming up, with {} requests queued\n",
+                project_name, queued
+            ))))
+            .unwrap();
+    }
+
+    let html = project_page(
+        project_name,
+        appearance,
+        "{name} is warming up",
+        "{name} is warming up&hellip;",
+        &format!(
+            "<p>It has more requests than it can take right now. You were number {} of {} \
+             in line. This page retries automatically.</p>",
+            position + 1,
+            queued.max(position + 1)
+        ),
+        Some(2),
+    );
+    builder
+        .header("Content-Type", "text/html; charset=utf-8")
+        .body(full_body(Bytes::from(html)))
+        .unwrap()
+}
+
+/// Clear the browser's cache and storage for a project's origin, which also
+/// unregisters its service workers; cookies are kept so logins survive
+fn clear_site_data_response(
+    project_name: &str,
+    appearance: &Appearance,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let html = project_page(
+        project_name,
+        appearance,
+        "{name}: site data cleared",
+        "Cleared {name}'s cache and storage",
+        &format!(
+            "<p>Service workers are unregistered too. Cookies were kept.</p>\
+             <p><a href=\"/\">Back to {}</a></p>",
+            project_name
+        ),
+        None,
+    );
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .header("Clear-Site-Data", "\"cache\", \"storage\"")
+        .header("Cache-Control", "no-store")
+        .body(full_body(Bytes::from(html)))
+        .unwrap()
+}
+
+/// Answer for a project in direct mode, which the proxy doesn't route
+fn direct_response(
+    project_name: &str,
+    appearance: &Appearance,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let html = project_page(
+        project_name,
+        appearance,
+        "{name} isn't routed",
+        "{name} isn't routed through proj",
+        &format!(
+            "<p>It's in direct mode: open it at its own port, which <code>proj {0} info</code>              shows. <code>proj {0} route enable</code> routes it here again.</p>",
+            project_name
+        ),
+        None,
+    );
+
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .header("Cache-Control", "no-store")
+        .body(full_body(Bytes::from(html)))
+        .unwrap()
+}
+
+/// A page of the proxy's about a project, marked with its icon (as the tab's
+/// too) and color. `{name}` in `title` and `heading` stands for the project;
+/// with `refresh`, the page reloads after that many seconds.
+fn project_page(
+    project_name: &str,
+    appearance: &Appearance,
+    title: &str,
+    heading: &str,
+    body: &str,
+    refresh: Option<u32>,
+) -> String {
+    let label = escape_html(&appearance.label(project_name));
+    let mut head = String::from("<meta charset=\"utf-8\">");
+    if let Some(secs) = refresh {
+        head.push_str(&format!(
+            "<meta http-equiv=\"refresh\" content=\"{}\">",
+            secs
+        ));
+    }
+    if let Some(icon) = &appearance.icon {
+        head.push_str(&format!(
+            "<link rel=\"icon\" href=\"data:image/svg+xml,<svg xmlns='http://www.w3.org/2000/svg' \
+             viewBox='0 0 100 100'><text y='.9em' font-size='90'>{}</text></svg>\">",
+            escape_html(icon).replace('#', "%23")
+        ));
+    }
+    let color = appearance
+        .color
+        .as_ref()
+        .map(|color| format!(" style=\"color: {}\"", color))
+        .unwrap_or_default();
+    format!(
+        "<!doctype html>\n<html><head>{head}<title>{title}</title></head>\
+         <body style=\"font-family: system-ui, sans-serif; margin: 4rem; color: #444\">\
+         <h1{color}>{heading}</h1>{body}</body></html>\n",
+        head = head,
+        title = title.replace("{name}", &label),
+        color = color,
+        heading = heading.replace("{name}", &label),
+        body = body
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Create a 403 response
+fn forbidden_response(message: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+    Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .header("Content-Type", "text/plain")
+        .body(full_body(Bytes::from(format!("Forbidden: {}\n", message))))
+        .unwrap()
+}
+
+/// A 403 for a forward-proxy client asking for this machine itself, else a
+/// 502 for an outbound connection that failed
+fn outbound_error_response(e: anyhow::Error) -> Response<BoxBody<Bytes, hyper::Error>> {
+    if !e.is::<outbound::LocalDestination>() {
+        return error_response(&format!("{:#}", e));
+    }
+    forbidden_response(&e.to_string())
+}
+
 /// Create a 502 error response
 fn error_response(message: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
     let body = Full::new(Bytes::from(format!("Bad Gateway: {}\n", message)))
@@ -158,9 +2071,450 @@ fn error_response(message: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
         .unwrap()
 }
 
-#[allow(dead_code)]
+fn full_body(bytes: Bytes) -> BoxBody<Bytes, hyper::Error> {
+    Full::new(bytes).map_err(|never| match never {}).boxed()
+}
+
 fn empty_body() -> BoxBody<Bytes, hyper::Error> {
     Empty::<Bytes>::new()
         .map_err(|never| match never {})
         .boxed()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forwarded_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_static("my-app.localhost:8080"));
+        add_forwarded_headers(
+            &mut headers,
+            Client {
+                addr: "127.0.0.1:50000".parse().unwrap(),
+                https: false,
+                forward: false,
+            },
+        );
+        assert_eq!(headers["x-forwarded-for"], "127.0.0.1");
+        assert_eq!(headers["x-forwarded-proto"], "http");
+        assert_eq!(headers["x-forwarded-host"], "my-app.localhost:8080");
+        assert_eq!(
+            headers["forwarded"],
+            "for=127.0.0.1;proto=http;host=\"my-app.localhost:8080\""
+        );
+
+        // A hop in front keeps its proto and host, and gets appended to
+        headers.insert("x-forwarded-proto", HeaderValue::from_static("https"));
+        add_forwarded_headers(
+            &mut headers,
+            Client {
+                addr: "[::1]:50000".parse().unwrap(),
+                https: false,
+                forward: false,
+            },
+        );
+        assert_eq!(headers["x-forwarded-for"], "127.0.0.1, ::1");
+        assert_eq!(headers["x-forwarded-proto"], "https");
+        assert!(headers["forwarded"]
+            .to_str()
+            .unwrap()
+            .ends_with(", for=\"[::1]\";proto=http;host=\"my-app.localhost:8080\""));
+    }
+
+    #[test]
+    fn test_cors() {
+        let req = Request::builder()
+            .method("OPTIONS")
+            .header("origin", "http://web.localhost:8080")
+            .header("access-control-request-method", "PUT")
+            .header("access-control-request-headers", "content-type")
+            .body(())
+            .unwrap();
+        assert!(is_preflight(&req));
+        let resp = preflight_response(req.headers(), true);
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            resp.headers()["access-control-allow-origin"],
+            "http://web.localhost:8080"
+        );
+        assert_eq!(resp.headers()["access-control-allow-methods"], "PUT");
+        let untrusted = preflight_response(req.headers(), false);
+        assert_eq!(untrusted.headers()["access-control-allow-origin"], "*");
+        assert!(!untrusted
+            .headers()
+            .contains_key("access-control-allow-credentials"));
+        assert_eq!(
+            resp.headers()["access-control-allow-headers"],
+            "content-type"
+        );
+
+        let options = ProxyOptions {
+            cors: true,
+            ..Default::default()
+        };
+        let mut resp = Response::new(());
+        apply_response_options(&mut resp, &options, None, true);
+        assert_eq!(resp.headers()["access-control-allow-origin"], "*");
+        assert!(!resp
+            .headers()
+            .contains_key("access-control-allow-credentials"));
+    }
+
+    #[tokio::test]
+    async fn test_trusted_origin() {
+        let ctx = ProxyContext::for_tests(Config::default());
+        ctx.aliases
+            .write()
+            .await
+            .insert("web.test".to_string(), "web".to_string());
+        let trusted = |origin: Option<&'static str>, site: Option<&'static str>| {
+            let mut headers = HeaderMap::new();
+            if let Some(origin) = origin {
+                headers.insert("origin", HeaderValue::from_static(origin));
+            }
+            if let Some(site) = site {
+                headers.insert("sec-fetch-site", HeaderValue::from_static(site));
+            }
+            let ctx = ctx.clone();
+            async move { is_trusted_origin(&ctx, &headers, "localhost").await }
+        };
+
+        assert!(trusted(None, None).await);
+        assert!(trusted(Some("http://web.localhost:8080"), Some("same-site")).await);
+        assert!(trusted(Some("https://web.test"), None).await);
+        assert!(trusted(Some("http://127.0.0.1:3000"), None).await);
+        assert!(!trusted(Some("https://attacker.example"), None).await);
+        assert!(!trusted(Some("null"), None).await);
+        assert!(!trusted(None, Some("cross-site")).await);
+    }
+
+    #[test]
+    fn test_no_cache() {
+        let options = ProxyOptions {
+            no_cache: true,
+            ..Default::default()
+        };
+        let mut resp = Response::new(());
+        let headers = resp.headers_mut();
+        headers.insert(
+            "cache-control",
+            HeaderValue::from_static("max-age=31536000"),
+        );
+        headers.insert("etag", HeaderValue::from_static("\"abc\""));
+        apply_response_options(&mut resp, &options, None, true);
+        assert_eq!(resp.headers()["cache-control"], "no-store, max-age=0");
+        assert!(!resp.headers().contains_key("etag"));
+
+        let (mut parts, ()) = Request::builder()
+            .header("if-none-match", "\"abc\"")
+            .body(())
+            .unwrap()
+            .into_parts();
+        let client = Client {
+            addr: "127.0.0.1:50000".parse().unwrap(),
+            https: false,
+            forward: false,
+        };
+        apply_request_options(&mut parts, &options, client, 3000);
+        assert!(!parts.headers.contains_key("if-none-match"));
+    }
+
+    #[test]
+    fn test_service_worker_kill_switch() {
+        let req = Request::builder()
+            .header("service-worker", "script")
+            .body(())
+            .unwrap();
+        assert!(is_service_worker_script(&req));
+        assert!(!is_service_worker_script(&Request::new(())));
+        let resp = service_worker_kill_response();
+        assert_eq!(resp.headers()["content-type"], "application/javascript");
+        assert_eq!(resp.headers()["cache-control"], "no-store");
+
+        let resp = clear_site_data_response("web", &Appearance::default());
+        assert_eq!(resp.headers()["clear-site-data"], "\"cache\", \"storage\"");
+    }
+
+    #[test]
+    fn test_project_page_appearance() {
+        let appearance = Appearance {
+            icon: Some("🛒".to_string()),
+            color: Some("#ff8800".to_string()),
+        };
+        let page = project_page("shop", &appearance, "{name}", "Starting {name}", "", None);
+        assert!(page.contains("<title>🛒 shop</title>"));
+        assert!(page.contains("<h1 style=\"color: #ff8800\">Starting 🛒 shop</h1>"));
+        assert!(page.contains("rel=\"icon\""));
+
+        let page = project_page(
+            "<b>",
+            &Appearance::default(),
+            "{name}",
+            "{name}",
+            "",
+            Some(2),
+        );
+        assert!(page.contains("<title>&lt;b&gt;</title>"));
+        assert!(page.contains("content=\"2\""));
+        assert!(!page.contains("rel=\"icon\""));
+    }
+
+    #[test]
+    fn test_parse_project_name() {
+        let project = |host| parse_project_name(host, "localhost");
+        assert_eq!(project("my-app.localhost:8080").as_deref(), Some("my-app"));
+        // Subdomains belong to the project, as their certificates do
+        assert_eq!(project("api.my-app.localhost").as_deref(), Some("my-app"));
+        assert_eq!(project("My-App.localhost").as_deref(), Some("my-app"));
+        assert_eq!(project("my_app.localhost").as_deref(), Some("my_app"));
+        // Foreign hosts pointed here don't pass for a project of that name
+        assert_eq!(project("my-app.lan"), None);
+        assert_eq!(project("my-app.attacker.example"), None);
+        assert_eq!(project("my-app.localhost.attacker.example"), None);
+        assert_eq!(project("localhost:8080"), None);
+        assert_eq!(project("127.0.0.1:8080"), None);
+    }
+
+    #[tokio::test]
+    async fn test_foreign_host() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, addr) = listener.accept().await.unwrap();
+        let ctx = ProxyContext::for_tests(Config::default());
+        ctx.options_table
+            .write()
+            .await
+            .insert("my-app".to_string(), ProxyOptions::default());
+        let client_info = Client {
+            addr,
+            https: false,
+            forward: false,
+        };
+        tokio::spawn(serve_connection(TokioIo::new(stream), ctx, client_info));
+
+        // Named like a registered project, outside the TLD
+        let host = "my-app.attacker.example";
+        let request = format!(
+            "GET / HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+            host
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+        assert!(response.ends_with("\r\n\r\nNot Found\n"), "{}", response);
+    }
+
+    /// The status line of the response to `request` from a client at `peer`
+    /// on the reverse listener
+    async fn reverse_response(peer: &str, request: &str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let ctx = ProxyContext::for_tests(Config::default());
+        let client_info = Client {
+            addr: peer.parse().unwrap(),
+            https: false,
+            forward: false,
+        };
+        tokio::spawn(serve_connection(TokioIo::new(stream), ctx, client_info));
+
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut response = vec![0u8; 1024];
+        let read = client.read(&mut response).await.unwrap();
+        let response = String::from_utf8_lossy(&response[..read]).to_string();
+        response.lines().next().unwrap_or_default().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_reverse_listener_is_no_relay() {
+        let connect = "CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n";
+        let get =
+            "GET http://example.com/ HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n";
+
+        // From the internet: nothing here
+        assert_eq!(
+            reverse_response("203.0.113.5:50000", connect).await,
+            "HTTP/1.1 404 Not Found"
+        );
+        assert_eq!(
+            reverse_response("203.0.113.5:50000", get).await,
+            "HTTP/1.1 404 Not Found"
+        );
+
+        // From the LAN: only own and overridden hosts
+        assert_eq!(
+            reverse_response("192.168.1.5:50000", connect).await,
+            "HTTP/1.1 403 Forbidden"
+        );
+        assert_eq!(
+            reverse_response("192.168.1.5:50000", get).await,
+            "HTTP/1.1 403 Forbidden"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_own_tunnel_keeps_client() {
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let ctx = ProxyContext::for_tests(Config::default());
+        ctx.options_table
+            .write()
+            .await
+            .insert("my-app".to_string(), ProxyOptions::default());
+        let routes = ProjectRoutes {
+            port: Some(backend.local_addr().unwrap().port()),
+            ..Default::default()
+        };
+        ctx.routing_table
+            .write()
+            .await
+            .insert("my-app".to_string(), routes);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let client_info = Client {
+            addr: "192.168.1.5:50000".parse().unwrap(),
+            https: false,
+            forward: false,
+        };
+        tokio::spawn(serve_connection(TokioIo::new(stream), ctx, client_info));
+
+        client
+            .write_all(b"CONNECT my-app.localhost:80 HTTP/1.1\r\nHost: my-app.localhost:80\r\n\r\n")
+            .await
+            .unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(client.read_u8().await.unwrap());
+        }
+        assert!(head.starts_with(b"HTTP/1.1 200 OK\r\n"));
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: my-app.localhost\r\n\r\n")
+            .await
+            .unwrap();
+
+        // Served in place rather than over loopback, so the request is
+        // still the LAN client's
+        let (mut upstream, _) = backend.accept().await.unwrap();
+        let mut request = vec![0u8; 4096];
+        let read = upstream.read(&mut request).await.unwrap();
+        let request = String::from_utf8_lossy(&request[..read]).to_lowercase();
+        assert!(
+            request.contains("x-forwarded-for: 192.168.1.5\r\n"),
+            "{}",
+            request
+        );
+    }
+
+    #[test]
+    fn test_forward_proxy_hosts() {
+        assert!(is_proxy_host("my-app.localhost", "localhost"));
+        assert!(is_proxy_host("localhost", "localhost"));
+        assert!(is_proxy_host("127.0.0.1", "localhost"));
+        assert!(is_proxy_host("[::1]", "localhost"));
+        assert!(is_proxy_host("web.test", "test"));
+        assert!(!is_proxy_host("api.example.com", "localhost"));
+
+        // "my-app:" and "my-app:secret"
+        let mut headers = HeaderMap::new();
+        assert_eq!(proxy_user(&headers), None);
+        headers.insert(
+            "proxy-authorization",
+            HeaderValue::from_static("Basic bXktYXBwOg=="),
+        );
+        assert_eq!(proxy_user(&headers).as_deref(), Some("my-app"));
+        headers.insert(
+            "proxy-authorization",
+            HeaderValue::from_static("Basic bXktYXBwOnNlY3JldA=="),
+        );
+        assert_eq!(proxy_user(&headers).as_deref(), Some("my-app"));
+        headers.insert(
+            "proxy-authorization",
+            HeaderValue::from_static("Bearer bXktYXBwOg=="),
+        );
+        assert_eq!(proxy_user(&headers), None);
+
+        let pac = pac_script(&Config::default(), &["api.example.com".to_string()]);
+        assert!(pac.contains("|| host === \"api.example.com\")"));
+    }
+
+    /// A body sent in `chunks`, without a length
+    fn chunked(chunks: &[&'static str]) -> BoxBody<Bytes, hyper::Error> {
+        let read = chunks
+            .iter()
+            .map(|chunk| Bytes::from_static(chunk.as_bytes()));
+        Replayed {
+            read: read.collect(),
+            rest: Empty::new().map_err(|never| match never {}).boxed(),
+        }
+        .boxed()
+    }
+
+    /// A body that never sends anything, like an idle event stream
+    struct Silent;
+
+    impl hyper::body::Body for Silent {
+        type Data = Bytes;
+        type Error = hyper::Error;
+
+        fn poll_frame(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Option<Result<hyper::body::Frame<Bytes>, hyper::Error>>> {
+            std::task::Poll::Pending
+        }
+    }
+
+    #[tokio::test]
+    async fn test_buffer_body() {
+        let body = chunked(&["abcd", "efgh", "ijkl"]);
+        let Buffered::Whole(bytes) = buffer_body(body, 12).await.unwrap() else {
+            panic!("expected the whole body");
+        };
+        assert_eq!(bytes, "abcdefghijkl");
+
+        // Past the limit, what was read goes out ahead of the rest
+        let body = chunked(&["abcd", "efgh", "ijkl"]);
+        let Buffered::TooLarge(body) = buffer_body(body, 6).await.unwrap() else {
+            panic!("expected the body back");
+        };
+        assert_eq!(body.collect().await.unwrap().to_bytes(), "abcdefghijkl");
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_streams() {
+        let rewrites = [Rewrite {
+            find: "a".to_string(),
+            replace: "b".to_string(),
+            regex: false,
+        }];
+        let resp = Response::builder()
+            .header("content-type", "text/event-stream")
+            .body(Silent.boxed())
+            .unwrap();
+        let resp = tokio::time::timeout(
+            Duration::from_secs(1),
+            rewrite_response(&rewrites, "GET", resp),
+        );
+        assert!(resp.await.expect("event stream was buffered").is_ok());
+
+        let resp = Response::builder()
+            .header("content-type", "text/plain")
+            .body(chunked(&["aaa", "aaa"]))
+            .unwrap();
+        let resp = rewrite_response(&rewrites, "GET", resp).await.unwrap();
+        assert_eq!(
+            resp.into_body().collect().await.unwrap().to_bytes(),
+            "bbbbbb"
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! Per-project FIFO queues of background jobs, run one at a time
+
+use chrono::Utc;
+use proj_common::{QueueJobStatus, QueuedJob};
+use std::collections::{HashMap, VecDeque};
+use uuid::Uuid;
+
+/// Finished jobs remembered per project
+const MAX_HISTORY: usize = 50;
+
+/// Job queues by project name
+#[derive(Default)]
+pub struct QueueTable {
+    queues: HashMap<String, Queue>,
+}
+
+#[derive(Default)]
+struct Queue {
+    pending: VecDeque<QueuedJob>,
+    running: Option<QueuedJob>,
+    /// Finished jobs, oldest first
+    history: VecDeque<QueuedJob>,
+}
+
+impl QueueTable {
+    /// Append a job to a project's queue
+    pub fn push(&mut self, project_name: &str, command: Vec<String>) -> QueuedJob {
+        let job = QueuedJob {
+            id: Uuid::new_v4(),
+            project_name: project_name.to_string(),
+            command,
+            status: QueueJobStatus::Queued,
+            queued_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            process_id: None,
+            exit_code: None,
+        };
+        let queue = self.queues.entry(project_name.to_string()).or_default();
+        queue.pending.push_back(job.clone());
+        job
+    }
+
+    /// Take the next job of a project whose queue is idle, marking it running
+    /// until [`Self::started`] or [`Self::finish`] is called for it
+    pub fn next(&mut self, project_name: &str) -> Option<QueuedJob> {
+        let queue = self.queues.get_mut(project_name)?;
+        if queue.running.is_some() {
+            return None;
+        }
+        let mut job = queue.pending.pop_front()?;
+        job.status = QueueJobStatus::Running;
+        job.started_at = Some(Utc::now());
+        queue.running = Some(job.clone());
+        Some(job)
+    }
+
+    /// Record the process a running job was started as
+    pub fn started(&mut self, project_name: &str, process_id: Uuid) {
+        if let Some(job) = self
+            .queues
+            .get_mut(project_name)
+            .and_then(|q| q.running.as_mut())
+        {
+            job.process_id = Some(process_id);
+        }
+    }
+
+    /// Move a project's running job to its history. Returns false if there was none.
+    pub fn finish(&mut self, project_name: &str, exit_code: Option<i32>) -> bool {
+        let Some(queue) = self.queues.get_mut(project_name) else {
+            return false;
+        };
+        let Some(mut job) = queue.running.take() else {
+            return false;
+        };
+        job.status = if exit_code == Some(0) {
+            QueueJobStatus::Succeeded
+        } else {
+            QueueJobStatus::Failed
+        };
+        job.exit_code = exit_code;
+        job.finished_at = Some(Utc::now());
+        if queue.history.len() >= MAX_HISTORY {
+            queue.history.pop_front();
+        }
+        queue.history.push_back(job);
+        true
+    }
+
+    /// Project whose running job is `process_id`
+    pub fn project_of(&self, process_id: Uuid) -> Option<String> {
+        self.queues
+            .iter()
+            .find(|(_, q)| q.running.as_ref().and_then(|j| j.process_id) == Some(process_id))
+            .map(|(name, _)| name.clone())
+    }
+
+    /// Drop a project's pending jobs, returning how many there were. The
+    /// running job is left alone.
+    pub fn clear(&mut self, project_name: &str) -> usize {
+        let Some(queue) = self.queues.get_mut(project_name) else {
+            return 0;
+        };
+        let cleared = queue.pending.len();
+        for mut job in queue.pending.drain(..) {
+            job.status = QueueJobStatus::Cancelled;
+            job.finished_at = Some(Utc::now());
+            if queue.history.len() >= MAX_HISTORY {
+                queue.history.pop_front();
+            }
+            queue.history.push_back(job);
+        }
+        cleared
+    }
+
+    /// A project's finished, running and pending jobs, in that order
+    pub fn list(&self, project_name: &str) -> Vec<QueuedJob> {
+        let Some(queue) = self.queues.get(project_name) else {
+            return Vec::new();
+        };
+        queue
+            .history
+            .iter()
+            .chain(&queue.running)
+            .chain(&queue.pending)
+            .cloned()
+            .collect()
+    }
+
+    /// Forget a deleted project's queue and history
+    pub fn forget_project(&mut self, project_name: &str) {
+        self.queues.remove(project_name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runs_in_order() {
+        let mut queues = QueueTable::default();
+        let first = queues.push("app", vec!["index".into()]);
+        let second = queues.push("app", vec!["import".into()]);
+        queues.push("app", vec!["dropped".into()]);
+
+        assert_eq!(queues.next("app").unwrap().id, first.id);
+        // One job at a time
+        assert!(queues.next("app").is_none());
+
+        let process_id = Uuid::new_v4();
+        queues.started("app", process_id);
+        assert_eq!(queues.project_of(process_id).as_deref(), Some("app"));
+        assert!(queues.finish("app", Some(1)));
+        assert!(!queues.finish("app", Some(0)));
+
+        assert_eq!(queues.next("app").unwrap().id, second.id);
+        assert_eq!(queues.clear("app"), 1);
+        assert!(queues.finish("app", Some(0)));
+        assert!(queues.next("app").is_none());
+
+        let statuses: Vec<_> = queues.list("app").into_iter().map(|j| j.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                QueueJobStatus::Failed,
+                QueueJobStatus::Cancelled,
+                QueueJobStatus::Succeeded
+            ]
+        );
+    }
+}
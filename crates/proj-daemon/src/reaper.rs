@@ -0,0 +1,96 @@
+//! Periodic cleanup of process records and routes
+//!
+//! Records of exited processes are dropped `exited_retention_mins` after the
+//! exit (run history keeps their summaries). A process recorded as running
+//! whose PID has been gone for two ticks in a row lost its exit monitor, and
+//! its exit is reported now. Routes that don't point where the process
+//! manager would route the project, such as at the port of a process whose
+//! exit was never handled, are recomputed.
+
+use chrono::Utc;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::time::Duration;
+use uuid::Uuid;
+
+use crate::ipc::DaemonState;
+
+/// How often the reaper runs
+const TICK: Duration = Duration::from_secs(60);
+
+/// Reap on every tick
+pub async fn run(state: Arc<DaemonState>) {
+    let mut interval = tokio::time::interval(TICK);
+    // Gone on the previous tick; an exit monitor may just be running late
+    let mut vanished = HashSet::new();
+    loop {
+        interval.tick().await;
+        prune(&state).await;
+        vanished = report_vanished(&state, vanished).await;
+        fix_routes(&state).await;
+    }
+}
+
+/// Drop records of processes that exited longer ago than the retention
+async fn prune(state: &DaemonState) {
+    let retention = state.config.borrow().exited_retention_mins;
+    if retention == 0 {
+        return;
+    }
+    let cutoff = Utc::now() - chrono::Duration::minutes(retention as i64);
+    let pruned = state
+        .processes
+        .call(move |manager| manager.prune_expired(cutoff))
+        .await;
+    if let Ok(pruned @ 1..) = pruned {
+        tracing::info!("Pruned {} exited process records", pruned);
+    }
+}
+
+/// Report exits of processes gone since the last tick, returning those that
+/// are gone now
+async fn report_vanished(state: &DaemonState, previous: HashSet<Uuid>) -> HashSet<Uuid> {
+    state
+        .processes
+        .call(move |manager| {
+            let vanished: HashSet<Uuid> = manager.vanished().into_iter().collect();
+            for &process_id in vanished.intersection(&previous) {
+                tracing::warn!(process_id = %process_id, "Process exited unnoticed");
+                manager.report_vanished(process_id);
+            }
+            vanished
+        })
+        .await
+        .unwrap_or_default()
+}
+
+/// Reroute projects whose route isn't where their processes say it should be
+async fn fix_routes(state: &DaemonState) {
+    let routed: Vec<(String, Option<u16>)> = state
+        .routing_table
+        .read()
+        .await
+        .iter()
+        .map(|(name, routes)| (name.clone(), routes.port))
+        .collect();
+
+    for (name, port) in routed {
+        let project_name = name.clone();
+        let Ok(target) = state
+            .processes
+            .call(move |manager| manager.route_target(&project_name).and_then(|p| p.port))
+            .await
+        else {
+            return;
+        };
+        if target != port {
+            tracing::warn!(
+                project = %name,
+                routed = ?port,
+                target = ?target,
+                "Fixing stale route"
+            );
+            state.reroute(&name).await;
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Redirect normalization - keeps clients on the project's hostname when a
+//! backend redirects to its own address, and makes trailing slashes behave as
+//! they will in production
+
+use hyper::Uri;
+use proj_common::TrailingSlash;
+
+/// Where to redirect a page request to follow a trailing slash mode, if its
+/// path doesn't. Paths to files (a last segment with an extension) and the
+/// proxy's own paths are left alone.
+pub fn trailing_slash(uri: &Uri, mode: TrailingSlash) -> Option<String> {
+    let path = uri.path();
+    if path.starts_with("/__proj/") {
+        return None;
+    }
+    let target = match mode {
+        TrailingSlash::Keep => return None,
+        TrailingSlash::Add => {
+            let last = path.rsplit('/').next().unwrap_or_default();
+            if path.ends_with('/') || last.contains('.') {
+                return None;
+            }
+            format!("{}/", path)
+        }
+        TrailingSlash::Remove => {
+            let trimmed = path.trim_end_matches('/');
+            if trimmed.len() == path.len() || trimmed.is_empty() {
+                return None;
+            }
+            trimmed.to_string()
+        }
+    };
+    Some(match uri.query() {
+        Some(query) => format!("{}?{}", target, query),
+        None => target,
+    })
+}
+
+/// A Location pointing at the backend's own address (`backend`, e.g.
+/// `127.0.0.1:3000`), pointed at `origin` (e.g. `http://my-app.localhost`)
+/// instead. Loopback names for the backend's port count as its address too.
+pub fn rewrite_location(location: &str, backend: &str, origin: &str) -> Option<String> {
+    let uri: Uri = location.parse().ok()?;
+    let authority = uri.authority()?;
+    let (backend_host, backend_port) = backend.rsplit_once(':')?;
+    let port = authority
+        .port_u16()
+        .unwrap_or(if uri.scheme_str() == Some("https") {
+            443
+        } else {
+            80
+        })
+        .to_string();
+    let host = authority
+        .host()
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let is_backend = port == backend_port
+        && (host.eq_ignore_ascii_case(backend_host)
+            || matches!(host, "localhost" | "127.0.0.1" | "::1" | "0.0.0.0"));
+    if !is_backend {
+        return None;
+    }
+    let path = uri.path_and_query().map_or("/", |path| path.as_str());
+    Some(format!("{}{}", origin, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trailing_slash() {
+        let target = |uri: &str, mode| trailing_slash(&uri.parse().unwrap(), mode);
+        assert_eq!(
+            target("/about", TrailingSlash::Add).as_deref(),
+            Some("/about/")
+        );
+        assert_eq!(
+            target("/about?tab=2", TrailingSlash::Add).as_deref(),
+            Some("/about/?tab=2")
+        );
+        assert_eq!(target("/about/", TrailingSlash::Add), None);
+        assert_eq!(target("/app.js", TrailingSlash::Add), None);
+        assert_eq!(
+            target("/about/", TrailingSlash::Remove).as_deref(),
+            Some("/about")
+        );
+        assert_eq!(target("/", TrailingSlash::Remove), None);
+        assert_eq!(target("/about", TrailingSlash::Keep), None);
+        assert_eq!(target("/__proj/metrics", TrailingSlash::Add), None);
+    }
+
+    #[test]
+    fn test_rewrite_location() {
+        let origin = "http://web.localhost:8080";
+        let rewrite = |location| rewrite_location(location, "127.0.0.1:3000", origin);
+        assert_eq!(
+            rewrite("http://127.0.0.1:3000/login?next=%2F").as_deref(),
+            Some("http://web.localhost:8080/login?next=%2F")
+        );
+        assert_eq!(
+            rewrite("http://localhost:3000").as_deref(),
+            Some("http://web.localhost:8080/")
+        );
+        assert_eq!(
+            rewrite("http://[::1]:3000/a").as_deref(),
+            Some("http://web.localhost:8080/a")
+        );
+        assert_eq!(rewrite("http://127.0.0.1:4000/"), None);
+        assert_eq!(rewrite("https://accounts.example.com/"), None);
+        assert_eq!(rewrite("/relative"), None);
+    }
+}
@@ -1,153 +1,790 @@
 //! Project registry - handles project CRUD operations
+//!
+//! Starting only reads the store's index of names and aliases; a project's
+//! settings are parsed the first time it's used, so the daemon comes up at
+//! the same speed with a thousand projects as with one. [`Registry::refresh`] picks up rows
+//! written by another process, like `proj migrate-data`.
 
 use anyhow::{Context, Result};
-use proj_common::{project_dir, projects_dir, Project};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use proj_common::naming::{self, normalize_alias, normalize_tag};
+use proj_common::store::{IndexEntry, Store};
+use proj_common::trash;
+use proj_common::{
+    deps, load_env_profiles, migrate, normalize_color, normalize_icon, normalize_override_target,
+    normalize_route_prefix, project_dir, Appearance, EventKind, Hooks, IpcError, Priority, Project,
+    ProxyOptions, RegistryChange, RegistryChangeKind, Sandbox, Schedule, Service, SmokeCheck,
+    UpstreamAuth,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
 use tokio::fs;
+use tokio::sync::broadcast;
+
+use crate::events::EventBus;
 
 /// Project registry for managing project metadata
 pub struct Registry {
-    projects: HashMap<String, Project>,
+    projects: HashMap<String, Entry>,
+    store: Store,
+    /// Notifies subscribed clients of every saved change
+    changes: broadcast::Sender<RegistryChange>,
+    events: EventBus,
+}
+
+/// A stored project, parsed when it's first needed
+struct Entry {
+    /// When the store last saw it change
+    updated_at: String,
+    /// Its aliases, from the index, so checking a new one reads no project
+    aliases: Vec<String>,
+    /// `None` once it turned out unreadable
+    project: OnceLock<Option<Project>>,
+}
+
+impl Entry {
+    fn new(index: IndexEntry) -> Self {
+        Self {
+            updated_at: index.updated_at,
+            aliases: index.aliases,
+            project: OnceLock::new(),
+        }
+    }
+
+    fn loaded(updated_at: String, project: Project) -> Self {
+        Self {
+            updated_at,
+            aliases: project.aliases.clone(),
+            project: OnceLock::from(Some(project)),
+        }
+    }
+
+    fn get(&self, store: &Store, name: &str) -> Option<&Project> {
+        self.project.get_or_init(|| load(store, name)).as_ref()
+    }
+}
+
+fn load(store: &Store, name: &str) -> Option<Project> {
+    match store.project(name) {
+        Ok(project) => project,
+        Err(e) => {
+            tracing::warn!("Failed to load project '{}': {:#}", name, e);
+            None
+        }
+    }
 }
 
 impl Registry {
-    /// Create a new registry, loading existing projects from disk
-    pub async fn new() -> Result<Self> {
+    /// Create a new registry over the projects in the store
+    pub async fn new(events: EventBus, store: Store) -> Result<Self> {
         let mut registry = Self {
             projects: HashMap::new(),
+            store,
+            changes: broadcast::channel(64).0,
+            events,
         };
-        registry.load_all().await?;
+        registry.load_all()?;
         Ok(registry)
     }
 
-    /// Load all projects from disk
-    async fn load_all(&mut self) -> Result<()> {
-        let projects_path = projects_dir()?;
-
-        if !projects_path.exists() {
-            fs::create_dir_all(&projects_path)
-                .await
-                .context("Failed to create projects directory")?;
-            return Ok(());
+    /// Index the stored projects, migrating those stored in an older format
+    fn load_all(&mut self) -> Result<()> {
+        for upgrade in self.store.upgrade_projects(true)? {
+            match upgrade.result {
+                Ok(migrated) => tracing::info!(
+                    "Migrated project '{}' from version {} to {}",
+                    upgrade.name,
+                    migrated.from,
+                    migrated.to
+                ),
+                Err(e) => tracing::warn!("Project '{}': {}; loading it read-only", upgrade.name, e),
+            }
         }
 
-        let mut entries = fs::read_dir(&projects_path)
-            .await
-            .context("Failed to read projects directory")?;
-
-        while let Some(entry) = entries.next_entry().await? {
-            let path = entry.path();
-            if path.is_dir() {
-                let project_file = path.join("project.json");
-                if project_file.exists() {
-                    match self.load_project(&project_file).await {
-                        Ok(project) => {
-                            self.projects.insert(project.name.clone(), project);
-                        }
-                        Err(e) => {
-                            tracing::warn!("Failed to load project from {:?}: {}", project_file, e);
-                        }
-                    }
-                }
-            }
+        for entry in self.store.project_index()? {
+            self.projects.insert(entry.name.clone(), Entry::new(entry));
         }
 
-        tracing::info!("Loaded {} projects", self.projects.len());
+        tracing::info!("Indexed {} projects", self.projects.len());
         Ok(())
     }
 
-    /// Load a single project from disk
-    async fn load_project(&self, path: &PathBuf) -> Result<Project> {
-        let content = fs::read_to_string(path)
-            .await
-            .context("Failed to read project file")?;
-        let project: Project =
-            serde_json::from_str(&content).context("Failed to parse project file")?;
-        Ok(project)
+    /// Re-read the store's index, dropping projects that were changed or
+    /// removed outside the registry so they're read again. Returns the names
+    /// of those that were added, changed or removed.
+    pub fn refresh(&mut self) -> Result<Vec<String>> {
+        let index = self.store.project_index()?;
+        let mut changed: Vec<String> = self
+            .projects
+            .keys()
+            .filter(|name| !index.iter().any(|entry| &entry.name == *name))
+            .cloned()
+            .collect();
+        for name in &changed {
+            self.projects.remove(name);
+        }
+        for entry in index {
+            if self
+                .projects
+                .get(&entry.name)
+                .is_some_and(|known| known.updated_at == entry.updated_at)
+            {
+                continue;
+            }
+            let name = entry.name.clone();
+            self.projects.insert(name.clone(), Entry::new(entry));
+            changed.push(name);
+        }
+        changed.sort();
+        Ok(changed)
     }
 
-    /// Save a project to disk
-    async fn save_project(&self, project: &Project) -> Result<()> {
-        let dir = project_dir(&project.name)?;
-        fs::create_dir_all(&dir)
-            .await
-            .context("Failed to create project directory")?;
-
-        let project_file = dir.join("project.json");
-        let content =
-            serde_json::to_string_pretty(project).context("Failed to serialize project")?;
-        fs::write(&project_file, content)
-            .await
-            .context("Failed to write project file")?;
+    /// Save a project to the store and make sure its data directory exists
+    async fn save_project(&self, project: &Project) -> Result<String> {
+        let updated_at = self.store.save_project(project)?;
 
+        let dir = project_dir(&project.name)?;
         // Create chrome profile directory
         let chrome_dir = dir.join("chrome");
         fs::create_dir_all(&chrome_dir)
             .await
             .context("Failed to create chrome directory")?;
 
-        Ok(())
+        // Projects are saved before being inserted on creation
+        let kind = if self.projects.contains_key(&project.name) {
+            RegistryChangeKind::Updated
+        } else {
+            RegistryChangeKind::Created
+        };
+        self.notify(kind, &project.name, Some(project.clone()));
+
+        Ok(updated_at)
     }
 
-    /// Create a new project
-    pub async fn create(&mut self, name: String, root_dir: PathBuf) -> Result<Project> {
-        proj_common::validate_project_name(&name)?;
+    /// Receive every change made to the registry from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<RegistryChange> {
+        self.changes.subscribe()
+    }
 
-        if self.projects.contains_key(&name) {
-            anyhow::bail!("Project '{}' already exists", name);
-        }
+    fn notify(&self, kind: RegistryChangeKind, name: &str, project: Option<Project>) {
+        // No subscribers is fine
+        let _ = self.changes.send(RegistryChange {
+            kind,
+            name: name.to_string(),
+            project,
+        });
+    }
 
-        let project = Project::new(name.clone(), root_dir);
-        self.save_project(&project).await?;
-        self.projects.insert(name, project.clone());
+    /// Create a new project, served at `<name>.<tld>`
+    pub async fn create(&mut self, project: Project, tld: &str) -> Result<Project> {
+        naming::project_host(&project.name, tld)?;
 
+        let project = self.insert_new(project).await?;
         tracing::info!("Created project: {}", project.name);
         Ok(project)
     }
 
+    /// Add a project defined elsewhere, e.g. on another machine, checking
+    /// its aliases, services and proxy options as setting them would
+    pub async fn import(&mut self, mut project: Project, tld: &str) -> Result<Project> {
+        naming::project_host(&project.name, tld)?;
+
+        // The port belongs to the other machine's processes
+        project.port = None;
+        let mut aliases = Vec::new();
+        for alias in &project.aliases {
+            let alias = check_alias(alias, tld)?;
+            // Aliases another project here already has stay with it
+            if self.alias_owner(&alias).is_none() && !aliases.contains(&alias) {
+                aliases.push(alias);
+            }
+        }
+        project.aliases = aliases;
+        // Names from before services were hostname labels are kept, as
+        // `set_service` keeps them
+        for (name, service) in &project.services {
+            validate_service(name, service)?;
+        }
+        project.proxy = check_proxy_options(std::mem::take(&mut project.proxy))?;
+        project.revision = 0;
+        migrate::PROJECT.check_writable(project.version)?;
+        project.version = migrate::PROJECT.version();
+        let project = self.insert_new(project).await?;
+        tracing::info!("Imported project: {}", project.name);
+        Ok(project)
+    }
+
+    async fn insert_new(&mut self, project: Project) -> Result<Project> {
+        if self.projects.contains_key(&project.name) {
+            return Err(IpcError::ProjectExists { name: project.name }.into());
+        }
+
+        let updated_at = self.save_project(&project).await?;
+        self.projects.insert(
+            project.name.clone(),
+            Entry::loaded(updated_at, project.clone()),
+        );
+        self.events.emit(&project.name, EventKind::ProjectCreated);
+        Ok(project)
+    }
+
     /// Get a project by name
     pub fn get(&self, name: &str) -> Option<&Project> {
-        self.projects.get(name)
+        self.projects.get(name)?.get(&self.store, name)
     }
 
     /// Get a mutable reference to a project
     #[allow(dead_code)]
     pub fn get_mut(&mut self, name: &str) -> Option<&mut Project> {
-        self.projects.get_mut(name)
+        let entry = self.projects.get_mut(name)?;
+        entry.get(&self.store, name);
+        entry.project.get_mut()?.as_mut()
     }
 
-    /// List all projects
+    /// List all projects, reading any not read yet
     pub fn list(&self) -> Vec<&Project> {
-        self.projects.values().collect()
+        self.projects
+            .iter()
+            .filter_map(|(name, entry)| entry.get(&self.store, name))
+            .collect()
+    }
+
+    /// Delete a project, moving its data directory to the trash unless
+    /// `keep_data` is set
+    pub async fn delete(&mut self, name: &str, keep_data: bool) -> Result<Project> {
+        let project = self
+            .get(name)
+            .ok_or_else(|| project_not_found(name))?
+            .clone();
+        self.store.delete_project(name)?;
+        self.projects.remove(name);
+        // Kept for `proj undo`; should that fail, the data stays where it is
+        let data = if keep_data {
+            vec![]
+        } else {
+            vec![project_dir(name)?]
+        };
+        if let Err(e) = trash::put(trash::Operation::Remove, name, &data, Some(project.clone())) {
+            tracing::warn!("Failed to move {} to the trash: {:#}", name, e);
+        }
+
+        tracing::info!("Deleted project: {}", name);
+        self.notify(RegistryChangeKind::Deleted, name, None);
+        self.events.emit(name, EventKind::ProjectDeleted);
+        Ok(project)
+    }
+
+    /// Fail with [`Conflict`] if a project has moved past `expected_revision`
+    pub fn check_revision(&self, name: &str, expected_revision: Option<u64>) -> Result<()> {
+        let project = self.get(name).ok_or_else(|| project_not_found(name))?;
+        check_revision(project, expected_revision)?;
+        Ok(())
+    }
+
+    /// Apply `edit` to a copy of a project and save it as the next revision
+    async fn update(
+        &mut self,
+        name: &str,
+        expected_revision: Option<u64>,
+        edit: impl FnOnce(&mut Project) -> Result<()>,
+    ) -> Result<Project> {
+        let mut project = self
+            .get(name)
+            .ok_or_else(|| project_not_found(name))?
+            .clone();
+        check_revision(&project, expected_revision)?;
+        // Saving would drop whatever a newer proj added to the file
+        migrate::PROJECT.check_writable(project.version)?;
+
+        edit(&mut project)?;
+        project.revision += 1;
+        let updated_at = self.save_project(&project).await?;
+        self.projects
+            .insert(name.to_string(), Entry::loaded(updated_at, project.clone()));
+        Ok(project)
     }
 
     /// Update a project's port
     pub async fn update_port(&mut self, name: &str, port: Option<u16>) -> Result<()> {
-        {
-            let project = self
-                .projects
-                .get_mut(name)
-                .context(format!("Project '{}' not found", name))?;
+        if self.get(name).is_some_and(|p| p.port == port) {
+            return Ok(());
+        }
+        self.update(name, None, |project| {
             project.port = port;
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Update a project's proxy options
+    pub async fn update_proxy_options(
+        &mut self,
+        name: &str,
+        options: ProxyOptions,
+        expected_revision: Option<u64>,
+    ) -> Result<Project> {
+        let options = check_proxy_options(options)?;
+        self.update(name, expected_revision, |project| {
+            project.proxy = options;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Update a project's maintenance schedule
+    pub async fn update_schedule(
+        &mut self,
+        name: &str,
+        schedule: Option<Schedule>,
+        expected_revision: Option<u64>,
+    ) -> Result<Project> {
+        self.update(name, expected_revision, |project| {
+            project.schedule = schedule;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Replace a project's lifecycle hooks
+    pub async fn update_hooks(
+        &mut self,
+        name: &str,
+        hooks: Hooks,
+        expected_revision: Option<u64>,
+    ) -> Result<Project> {
+        self.update(name, expected_revision, |project| {
+            project.hooks = hooks;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Replace the projects a project depends on, refusing unknown projects
+    /// and cycles
+    pub async fn update_depends_on(
+        &mut self,
+        name: &str,
+        depends_on: Vec<String>,
+        expected_revision: Option<u64>,
+    ) -> Result<Project> {
+        let mut projects: Vec<Project> = self.list().into_iter().cloned().collect();
+        if let Some(project) = projects.iter_mut().find(|p| p.name == name) {
+            project.depends_on = depends_on.clone();
         }
-        // Re-borrow immutably after the mutable borrow is released
-        let project = self
-            .projects
-            .get(name)
-            .context(format!("Project '{}' not found", name))?;
-        self.save_project(project).await?;
+        deps::start_order(&projects, &[name.to_string()])?;
+
+        self.update(name, expected_revision, |project| {
+            project.depends_on = depends_on;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Set the priority a project's processes run at
+    pub async fn update_priority(
+        &mut self,
+        name: &str,
+        priority: Priority,
+        expected_revision: Option<u64>,
+    ) -> Result<Project> {
+        priority.validate()?;
+        self.update(name, expected_revision, |project| {
+            project.priority = priority;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Set the env profiles a project's processes get, all of which must be
+    /// defined in the env profiles file
+    pub async fn update_env_profiles(
+        &mut self,
+        name: &str,
+        profiles: Vec<String>,
+        expected_revision: Option<u64>,
+    ) -> Result<Project> {
+        let defined = load_env_profiles()?;
+        for profile in &profiles {
+            if !defined.contains_key(profile) {
+                anyhow::bail!(
+                    "Env profile '{}' is not defined (add it with: proj profile-env edit)",
+                    profile
+                );
+            }
+        }
+        self.update(name, expected_revision, |project| {
+            project.env_profiles = profiles;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Set the extra hostnames the proxy routes to a project. Names under the
+    /// proxy's TLD are refused, since those already route by project name, as
+    /// are aliases another project has.
+    pub async fn update_aliases(
+        &mut self,
+        name: &str,
+        aliases: Vec<String>,
+        tld: &str,
+        expected_revision: Option<u64>,
+    ) -> Result<Project> {
+        let mut normalized = Vec::new();
+        for alias in aliases {
+            let alias = check_alias(&alias, tld)?;
+            if let Some(other) = self.alias_owner(&alias).filter(|other| *other != name) {
+                anyhow::bail!("'{}' is already an alias of {}", alias, other);
+            }
+            if !normalized.contains(&alias) {
+                normalized.push(alias);
+            }
+        }
+        self.update(name, expected_revision, |project| {
+            project.aliases = normalized;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Set how a project's processes are confined. Extra writable directories
+    /// must be absolute.
+    pub async fn update_sandbox(
+        &mut self,
+        name: &str,
+        sandbox: Sandbox,
+        expected_revision: Option<u64>,
+    ) -> Result<Project> {
+        if let Some(dir) = sandbox.writable.iter().find(|dir| !dir.is_absolute()) {
+            anyhow::bail!("Writable directory must be absolute: {}", dir.display());
+        }
+        self.update(name, expected_revision, |project| {
+            project.sandbox = sandbox;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Set the labels `proj ls` filters and groups a project by
+    pub async fn update_tags(
+        &mut self,
+        name: &str,
+        tags: Vec<String>,
+        expected_revision: Option<u64>,
+    ) -> Result<Project> {
+        let mut normalized = Vec::new();
+        for tag in tags {
+            let tag = normalize_tag(&tag)?;
+            if !normalized.contains(&tag) {
+                normalized.push(tag);
+            }
+        }
+        self.update(name, expected_revision, |project| {
+            project.tags = normalized;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Set the icon and color a project is shown with
+    pub async fn update_appearance(
+        &mut self,
+        name: &str,
+        appearance: Appearance,
+        expected_revision: Option<u64>,
+    ) -> Result<Project> {
+        let appearance = Appearance {
+            icon: appearance.icon.as_deref().map(normalize_icon).transpose()?,
+            color: appearance
+                .color
+                .as_deref()
+                .map(normalize_color)
+                .transpose()?,
+        };
+        self.update(name, expected_revision, |project| {
+            project.appearance = appearance;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Set whether the daemon echoes a project's output, or (with `None`)
+    /// leave it to the config
+    pub async fn update_output_passthrough(
+        &mut self,
+        name: &str,
+        passthrough: Option<bool>,
+        expected_revision: Option<u64>,
+    ) -> Result<Project> {
+        self.update(name, expected_revision, |project| {
+            project.output_passthrough = passthrough;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Set the HTTP checks `proj <name> smoke` runs
+    pub async fn update_smoke_checks(
+        &mut self,
+        name: &str,
+        checks: Vec<SmokeCheck>,
+        expected_revision: Option<u64>,
+    ) -> Result<Project> {
+        for check in &checks {
+            if !check.path.starts_with('/') {
+                anyhow::bail!("Smoke check path must start with '/': {}", check.path);
+            }
+            if !(100..=599).contains(&check.status) {
+                anyhow::bail!("Invalid HTTP status {}", check.status);
+            }
+        }
+        self.update(name, expected_revision, |project| {
+            project.smoke = checks;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Note that a project was just started, remembering the command it was
+    /// run with, if it was a run
+    pub async fn record_start(&mut self, name: &str, command: Option<Vec<String>>) -> Result<()> {
+        self.update(name, None, |project| {
+            project.last_used = Some(chrono::Utc::now());
+            if command.is_some() {
+                project.run_command = command;
+            }
+            Ok(())
+        })
+        .await?;
         Ok(())
     }
 
-    /// Get project count
+    /// Define, replace, or (with `None`) remove a project's service
+    pub async fn set_service(
+        &mut self,
+        project_name: &str,
+        name: &str,
+        service: Option<Service>,
+        expected_revision: Option<u64>,
+    ) -> Result<Project> {
+        self.update(project_name, expected_revision, |project| {
+            match service {
+                Some(service) => {
+                    // Services from before names were hostname labels keep theirs
+                    if !project.services.contains_key(name) {
+                        naming::validate_service_name(name)?;
+                    }
+                    project.services.insert(name.to_string(), service);
+                }
+                None => {
+                    project.services.remove(name).context(format!(
+                        "Service '{}' not found in '{}'",
+                        name, project_name
+                    ))?;
+                }
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Get project count, without reading any
     pub fn count(&self) -> usize {
         self.projects.len()
     }
 
-    /// Find project by port
-    #[allow(dead_code)]
-    pub fn find_by_port(&self, port: u16) -> Option<&Project> {
-        self.projects.values().find(|p| p.port == Some(port))
+    /// Projects read from the store so far
+    #[cfg(test)]
+    pub fn loaded(&self) -> usize {
+        let entries = self.projects.values();
+        entries.filter(|e| e.project.get().is_some()).count()
+    }
+
+    /// The project `alias` routes to, without reading any
+    fn alias_owner(&self, alias: &str) -> Option<&str> {
+        self.projects
+            .iter()
+            .find(|(_, entry)| entry.aliases.iter().any(|a| a == alias))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// A compare-and-set update found the project at a different revision
+#[derive(Debug, thiserror::Error)]
+#[error("Project '{name}' was changed by another client (now at revision {current}, expected {expected}); re-fetch and retry")]
+pub struct Conflict {
+    pub name: String,
+    pub expected: u64,
+    pub current: u64,
+}
+
+/// Normalize an alias, refusing names under the proxy's TLD, since those
+/// already route by project name
+fn check_alias(alias: &str, tld: &str) -> Result<String> {
+    let alias = normalize_alias(alias)?;
+    if alias == tld || alias.ends_with(&format!(".{}", tld)) {
+        anyhow::bail!(
+            "'{}' is under .{}, whose hosts already route by project name",
+            alias,
+            tld
+        );
+    }
+    Ok(alias)
+}
+
+/// Check a service's definition: it needs a command to run
+pub(crate) fn validate_service(name: &str, service: &Service) -> Result<()> {
+    if service.command.is_empty() {
+        return Err(IpcError::InvalidRequest {
+            message: format!("Service '{}' has an empty command", name),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Normalize proxy options' route prefixes and overridden hosts, and check
+/// their rewrites and credentials
+fn check_proxy_options(mut options: ProxyOptions) -> Result<ProxyOptions> {
+    let mut routes = BTreeMap::new();
+    for (prefix, port) in options.routes {
+        if port == 0 {
+            anyhow::bail!("Route {} needs a port", prefix);
+        }
+        routes.insert(normalize_route_prefix(&prefix)?, port);
+    }
+    options.routes = routes;
+    let mut overrides = BTreeMap::new();
+    for (host, target) in options.overrides {
+        overrides.insert(normalize_alias(&host)?, normalize_override_target(&target)?);
+    }
+    options.overrides = overrides;
+    for rewrite in &options.rewrites {
+        rewrite.pattern()?;
+    }
+    let mut auth = BTreeMap::new();
+    for (prefix, scheme) in options.auth {
+        if let UpstreamAuth::Basic { username } = &scheme {
+            if username.is_empty() || username.contains(':') {
+                anyhow::bail!("Invalid basic auth user name '{}'", username);
+            }
+        }
+        auth.insert(normalize_route_prefix(&prefix)?, scheme);
+    }
+    options.auth = auth;
+    options.upstream_host = options
+        .upstream_host
+        .as_deref()
+        .map(naming::normalize_upstream_host)
+        .transpose()?;
+    Ok(options)
+}
+
+fn check_revision(project: &Project, expected_revision: Option<u64>) -> Result<(), Conflict> {
+    match expected_revision {
+        Some(expected) if expected != project.revision => Err(Conflict {
+            name: project.name.clone(),
+            expected,
+            current: project.revision,
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn project_not_found(name: &str) -> anyhow::Error {
+    IpcError::ProjectNotFound {
+        name: name.to_string(),
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_check_revision() {
+        let mut project = Project::new("app".to_string(), PathBuf::from("/tmp/app"));
+        project.revision = 3;
+
+        assert!(check_revision(&project, None).is_ok());
+        assert!(check_revision(&project, Some(3)).is_ok());
+        let conflict = check_revision(&project, Some(2)).unwrap_err();
+        assert_eq!((conflict.expected, conflict.current), (2, 3));
+    }
+
+    #[tokio::test]
+    async fn test_import_checks() {
+        let dir = std::env::temp_dir().join(format!("proj-import-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = Store::open(&dir.join("proj.db")).unwrap();
+        let mut registry = Registry::new(EventBus::new(), store).await.unwrap();
+
+        // An alias under the TLD would take over another project's host
+        let mut project = Project::new("app".to_string(), dir.clone());
+        project.aliases = vec!["Web.localhost.".to_string()];
+        let error = registry.import(project, "localhost").await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "'web.localhost' is under .localhost, whose hosts already route by project name"
+        );
+
+        let mut project = Project::new("app".to_string(), dir.clone());
+        project.aliases = vec!["127.0.0.1".to_string()];
+        assert!(registry.import(project, "localhost").await.is_err());
+
+        let mut project = Project::new("app".to_string(), dir.clone());
+        let service = serde_json::from_str(r#"{"command": []}"#).unwrap();
+        project.services.insert("web".to_string(), service);
+        let error = registry.import(project, "localhost").await.unwrap_err();
+        assert_eq!(error.to_string(), "Service 'web' has an empty command");
+
+        let mut project = Project::new("app".to_string(), dir.clone());
+        project.proxy.upstream_host = Some("devbox:8080".to_string());
+        assert!(registry.import(project, "localhost").await.is_err());
+
+        assert_eq!(registry.count(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lazy_loading() {
+        let dir = std::env::temp_dir().join(format!("proj-registry-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = Store::open(&dir.join("proj.db")).unwrap();
+        for i in 0..1000 {
+            let mut project = Project::new(format!("app-{}", i), dir.clone());
+            project.tags = vec!["client-x".to_string()];
+            project.aliases = vec![format!("app{}.test", i)];
+            store.save_project(&project).unwrap();
+        }
+
+        // How long this takes is measured by the project_index bench
+        let mut registry = Registry::new(EventBus::new(), store.clone()).await.unwrap();
+        assert_eq!(registry.count(), 1000);
+        assert_eq!(registry.loaded(), 0);
+
+        assert_eq!(registry.get("app-7").unwrap().tags, ["client-x"]);
+        assert_eq!(registry.loaded(), 1);
+        // Another project's alias is found in the index, reading only the
+        // project being changed
+        let taken = registry
+            .update_aliases("app-7", vec!["app3.test".to_string()], "localhost", None)
+            .await
+            .unwrap_err();
+        let taken = taken.to_string();
+        assert_eq!(taken, "'app3.test' is already an alias of app-3");
+        assert_eq!(registry.loaded(), 1);
+
+        // Written by another process: read again on refresh
+        let mut project = registry.get("app-7").unwrap().clone();
+        project.tags.clear();
+        store.save_project(&project).unwrap();
+        store.delete_project("app-8").unwrap();
+        assert_eq!(registry.refresh().unwrap(), ["app-7", "app-8"]);
+        assert!(registry.get("app-7").unwrap().tags.is_empty());
+        assert!(registry.get("app-8").is_none());
+        assert_eq!(registry.list().len(), 999);
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
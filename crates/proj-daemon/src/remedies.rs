@@ -0,0 +1,177 @@
+//! Suggestions for a command that couldn't be started because it wasn't
+//! found: a copy in the project's own bin directories, another version of it
+//! on the PATH or under a version manager, or a file in the project root
+//! whose name is close
+
+use proj_common::lint::did_you_mean;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+/// Directories under the project root that package managers install
+/// commands to
+const LOCAL_BINS: &[&str] = &["node_modules/.bin", ".venv/bin", "vendor/bin"];
+
+/// Directories under the home directory that version managers keep each
+/// installed version in, as `<dir>/<version>/<bin>`
+const VERSION_MANAGERS: &[(&str, &str)] = &[
+    (".nvm/versions/node", "bin"),
+    (".local/share/fnm/node-versions", "installation/bin"),
+    (".pyenv/versions", "bin"),
+    (".rbenv/versions", "bin"),
+    (".rustup/toolchains", "bin"),
+];
+
+/// What to try instead of `command`, which wasn't found when run in `dir`
+/// with `path` as its PATH
+pub fn not_found(command: &str, dir: &Path, path: Option<&OsStr>) -> Vec<String> {
+    suggest(command, dir, path, dirs::home_dir().as_deref())
+}
+
+/// `not_found`, looking for version managers' copies under `home`
+fn suggest(command: &str, dir: &Path, path: Option<&OsStr>, home: Option<&Path>) -> Vec<String> {
+    let name = Path::new(command)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(command);
+    let path_dirs: Vec<PathBuf> = path
+        .map(|path| std::env::split_paths(path).collect())
+        .unwrap_or_default();
+    let mut suggestions = Vec::new();
+
+    for bin in LOCAL_BINS {
+        if dir.join(bin).join(name).is_file() {
+            suggestions.push(format!("Did you mean ./{}/{}?", bin, name));
+        }
+    }
+
+    let versions = other_versions(name, &path_dirs);
+    if !versions.is_empty() {
+        suggestions.push(format!("Did you mean {}?", versions.join(" or ")));
+    }
+
+    if let Some(home) = home {
+        for installed in managed_copies(name, home) {
+            suggestions.push(format!(
+                "`{}` is installed at {}, which isn't on the PATH; run it by that path or add its directory to the project's env",
+                name,
+                installed.display()
+            ));
+        }
+    }
+
+    if suggestions.is_empty() {
+        let files = file_names(dir);
+        if let Some(close) = did_you_mean(name, files.iter().map(String::as_str)) {
+            suggestions.push(format!("Did you mean ./{}?", close));
+        }
+    }
+    suggestions
+}
+
+/// Programs on the PATH that are `name` with a different version suffix,
+/// e.g. `python3.12` for `python3.11`, or `python3` for `python`
+fn other_versions(name: &str, path_dirs: &[PathBuf]) -> Vec<String> {
+    let stem = version_stem(name);
+    if stem.is_empty() {
+        return Vec::new();
+    }
+    let mut found: Vec<String> = path_dirs
+        .iter()
+        .flat_map(|dir| file_names(dir))
+        .filter(|candidate| candidate != name && version_stem(candidate) == stem)
+        .filter(|candidate| {
+            let version = &candidate[stem.len()..];
+            version.is_empty() || version.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+        })
+        .collect();
+    found.sort();
+    found.dedup();
+    found.truncate(3);
+    found
+}
+
+/// A program name without its version suffix: `python` for `python3.11`,
+/// `gcc` for `gcc-12`
+fn version_stem(name: &str) -> &str {
+    name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.' || c == '-')
+}
+
+/// Copies of `name` kept by version managers under `home`
+fn managed_copies(name: &str, home: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    for (dir, bin) in VERSION_MANAGERS {
+        let Ok(versions) = std::fs::read_dir(home.join(dir)) else {
+            continue;
+        };
+        let mut copies: Vec<PathBuf> = versions
+            .flatten()
+            .map(|version| version.path().join(bin).join(name))
+            .filter(|copy| copy.is_file())
+            .collect();
+        // The newest version sorts last, most of the time
+        copies.sort();
+        found.extend(copies.pop());
+    }
+    found
+}
+
+/// Names of the files directly in `dir`
+fn file_names(dir: &Path) -> Vec<String> {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.file_type().is_ok_and(|kind| !kind.is_dir()))
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_not_found() {
+        let root = std::env::temp_dir().join(format!("proj-remedies-{}", uuid::Uuid::new_v4()));
+        let project = root.join("app");
+        let bin = root.join("bin");
+        let nvm = root.join(".nvm/versions/node/v20.11.0/bin");
+        std::fs::create_dir_all(project.join("node_modules/.bin")).unwrap();
+        std::fs::create_dir_all(&bin).unwrap();
+        std::fs::create_dir_all(&nvm).unwrap();
+        std::fs::write(nvm.join("node"), "").unwrap();
+        std::fs::write(project.join("node_modules/.bin/vite"), "").unwrap();
+        std::fs::write(project.join("start.sh"), "").unwrap();
+        for name in ["python3", "python3.12", "pythonista"] {
+            std::fs::write(bin.join(name), "").unwrap();
+        }
+        let path = bin.as_os_str();
+        let suggest = |command| suggest(command, &project, Some(path), Some(&root));
+
+        assert_eq!(suggest("vite"), ["Did you mean ./node_modules/.bin/vite?"]);
+        assert_eq!(
+            suggest("python3.11"),
+            ["Did you mean python3 or python3.12?"]
+        );
+        assert_eq!(suggest("./strat.sh"), ["Did you mean ./start.sh?"]);
+        assert_eq!(
+            suggest("node"),
+            [format!(
+                "`node` is installed at {}, which isn't on the PATH; run it by that path or add its directory to the project's env",
+                nvm.join("node").display()
+            )]
+        );
+        assert!(suggest("cargo-nextest").is_empty());
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_version_stem() {
+        assert_eq!(version_stem("python3.11"), "python");
+        assert_eq!(version_stem("gcc-12"), "gcc");
+        assert_eq!(version_stem("node"), "node");
+        assert_eq!(version_stem("7z"), "7z");
+    }
+}
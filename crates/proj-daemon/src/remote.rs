@@ -0,0 +1,174 @@
+//! Remote control over TCP
+//!
+//! With `listen_tcp` set, the daemon also accepts clients on that address,
+//! for a CLI on another machine (`PROJ_HOST`). They speak the same JSON
+//! protocol as local ones, after a first `Authenticate` request carrying the
+//! config's `tcp_token`; without a token nothing is accepted.
+
+use crate::interfaces;
+use crate::ipc::{handle_connection, DaemonState};
+use crate::panics;
+use anyhow::Result;
+use proj_common::{IpcError, IpcRequest, IpcResponse};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Longest `Authenticate` request read from a client not yet authenticated
+const MAX_AUTH_LINE: u64 = 4096;
+
+/// How long a client has to send its `Authenticate` request
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Accept TCP clients at `listen_tcp`, rebinding when it changes or the
+/// network changes under it
+pub async fn run(state: Arc<DaemonState>) {
+    let mut config = state.config.subscribe();
+    let mut network = interfaces::Watch::new(state.network.clone());
+    loop {
+        let addr = config.borrow_and_update().listen_tcp;
+        let listener = match addr {
+            Some(addr) => bind(addr).await,
+            None => None,
+        };
+
+        loop {
+            tokio::select! {
+                accepted = accept(listener.as_ref()) => match accepted {
+                    Ok((stream, peer)) => {
+                        panics::spawn("remote connection", serve(stream, peer, state.clone()));
+                    }
+                    Err(e) => tracing::error!("Remote accept error: {}", e),
+                },
+                changed = config.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                    if config.borrow().listen_tcp != addr {
+                        break;
+                    }
+                }
+                _ = network.rebind_needed(addr.map(|addr| addr.ip()), listener.is_some()) => {
+                    tracing::info!("Network changed; rebinding the remote control");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+async fn bind(addr: SocketAddr) -> Option<TcpListener> {
+    match TcpListener::bind(addr).await {
+        Ok(listener) => {
+            tracing::info!("Remote control listening on {}", addr);
+            Some(listener)
+        }
+        Err(e) => {
+            tracing::error!("Remote control failed to listen on {}: {}", addr, e);
+            None
+        }
+    }
+}
+
+/// Accept the next client, or wait forever without a listener
+async fn accept(listener: Option<&TcpListener>) -> std::io::Result<(TcpStream, SocketAddr)> {
+    match listener {
+        Some(listener) => listener.accept().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Check a client's token, then handle its request like a local one
+async fn serve(stream: TcpStream, peer: SocketAddr, state: Arc<DaemonState>) {
+    let _ = stream.set_nodelay(true);
+    let mut stream = BufReader::new(stream);
+    let token = state.config.borrow().tcp_token.clone();
+    match authenticate(&mut stream, token.as_deref()).await {
+        Ok(true) => {}
+        Ok(false) => {
+            tracing::warn!("Refused remote client {}: wrong or missing token", peer);
+            return;
+        }
+        Err(e) => {
+            tracing::debug!("Remote client {} went away: {}", peer, e);
+            return;
+        }
+    }
+    if let Err(e) = handle_connection(Box::new(stream), state).await {
+        tracing::error!("Remote connection error: {}", e);
+    }
+}
+
+/// Read the client's `Authenticate` request and answer it, returning whether
+/// it may go on. Before that, a client gets [`MAX_AUTH_LINE`] bytes and
+/// [`AUTH_TIMEOUT`] to send it.
+async fn authenticate(stream: &mut BufReader<TcpStream>, token: Option<&str>) -> Result<bool> {
+    let mut line = String::new();
+    let mut limited = (&mut *stream).take(MAX_AUTH_LINE);
+    tokio::time::timeout(AUTH_TIMEOUT, limited.read_line(&mut line))
+        .await
+        .map_err(|_| anyhow::anyhow!("no token within {:?}", AUTH_TIMEOUT))??;
+    let accepted = match (serde_json::from_str(&line), token) {
+        (Ok(IpcRequest::Authenticate { token: given }), Some(token)) => {
+            tokens_match(given.as_bytes(), token.as_bytes())
+        }
+        _ => false,
+    };
+
+    let response = if accepted {
+        IpcResponse::Success { message: None }
+    } else {
+        IpcResponse::Error(IpcError::Unauthorized)
+    };
+    let json = serde_json::to_string(&response)?;
+    stream.write_all(json.as_bytes()).await?;
+    stream.write_all(b"\n").await?;
+    stream.flush().await?;
+    Ok(accepted)
+}
+
+/// Compare tokens in time that doesn't depend on where they differ
+fn tokens_match(given: &[u8], expected: &[u8]) -> bool {
+    if given.len() != expected.len() || expected.is_empty() {
+        return false;
+    }
+    given
+        .iter()
+        .zip(expected)
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match(b"s3cret", b"s3cret"));
+        assert!(!tokens_match(b"s3creT", b"s3cret"));
+        assert!(!tokens_match(b"s3cre", b"s3cret"));
+        assert!(!tokens_match(b"", b""));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_bounds_the_line() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+
+        // A token line that never ends is cut off rather than buffered
+        let writer = tokio::spawn(async move {
+            let chunk = vec![b'a'; 1024];
+            while client.write_all(&chunk).await.is_ok() {}
+        });
+        let mut stream = BufReader::new(stream);
+        assert!(!authenticate(&mut stream, Some("s3cret")).await.unwrap());
+        drop(stream);
+        writer.await.unwrap();
+    }
+}
@@ -0,0 +1,96 @@
+//! Response rewrites - find/replace pairs a project applies to the bodies of
+//! its text responses, for apps with hardcoded absolute URLs and the like
+
+use proj_common::Rewrite;
+use regex::NoExpand;
+use std::borrow::Cow;
+
+/// Largest response body that is buffered for rewriting
+pub const MAX_REWRITTEN_BODY: u64 = 8 * 1024 * 1024;
+
+/// Whether a response of this content type is text the rewrites apply to
+pub fn is_text(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || matches!(
+            essence.as_str(),
+            "application/javascript"
+                | "application/ecmascript"
+                | "application/json"
+                | "application/xml"
+                | "application/manifest+json"
+        )
+}
+
+/// Apply the rewrites to a body in order, skipping any whose pattern no
+/// longer compiles. Borrowed when nothing matched.
+pub fn apply<'a>(text: &'a str, rewrites: &[Rewrite]) -> Cow<'a, str> {
+    let mut text = Cow::Borrowed(text);
+    for rewrite in rewrites {
+        let pattern = match rewrite.pattern() {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                tracing::warn!("Skipping rewrite: {:#}", e);
+                continue;
+            }
+        };
+        let replaced = if rewrite.regex {
+            pattern.replace_all(&text, rewrite.replace.as_str())
+        } else {
+            pattern.replace_all(&text, NoExpand(&rewrite.replace))
+        };
+        if let Cow::Owned(replaced) = replaced {
+            text = Cow::Owned(replaced);
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(find: &str, replace: &str, regex: bool) -> Rewrite {
+        Rewrite {
+            find: find.to_string(),
+            replace: replace.to_string(),
+            regex,
+        }
+    }
+
+    #[test]
+    fn test_is_text() {
+        assert!(is_text("text/html; charset=utf-8"));
+        assert!(is_text("application/javascript"));
+        assert!(is_text("application/ld+json"));
+        assert!(is_text("Application/JSON"));
+        assert!(!is_text("image/png"));
+        assert!(!is_text("application/octet-stream"));
+    }
+
+    #[test]
+    fn test_apply() {
+        let rewrites = [
+            rewrite("https://cdn.example.com", "/assets", false),
+            rewrite(
+                "(?i)<body([^>]*)>",
+                "<body$1><div id=\"banner\">local</div>",
+                true,
+            ),
+            rewrite("$price", "$1", false),
+        ];
+        let html = r#"<BODY class="x"><img src="https://cdn.example.com/a.png">$price</body>"#;
+        assert_eq!(
+            apply(html, &rewrites),
+            r#"<body class="x"><div id="banner">local</div><img src="/assets/a.png">$1</body>"#
+        );
+        assert!(matches!(apply("nothing here", &rewrites), Cow::Borrowed(_)));
+    }
+}
@@ -0,0 +1,93 @@
+//! Where the proxy sends a project's requests
+//!
+//! Each project host routes to the port its process listens on, except for
+//! path prefixes declared in its proxy options (`proj <name> route add`) or
+//! the `[routes]` table of its `proj.toml`, which go to their own ports. The
+//! longest matching prefix wins, matched on whole path segments, so `/api`
+//! takes `/api` and `/api/users` but not `/apiary`.
+
+use proj_common::schema::ProjectFile;
+use proj_common::{normalize_route_prefix, Project};
+use std::collections::BTreeMap;
+
+/// A project's routes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProjectRoutes {
+    /// Port of the project's process, for requests no path route takes
+    pub port: Option<u16>,
+    /// Path prefix → port
+    pub paths: BTreeMap<String, u16>,
+}
+
+impl ProjectRoutes {
+    /// Port a request for `path` goes to
+    pub fn target(&self, path: &str) -> Option<u16> {
+        self.paths
+            .iter()
+            .filter(|(prefix, _)| prefix_matches(prefix, path))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, port)| *port)
+            .or(self.port)
+    }
+
+    /// Whether nothing is routed at all
+    pub fn is_empty(&self) -> bool {
+        self.port.is_none() && self.paths.is_empty()
+    }
+}
+
+pub(crate) fn prefix_matches(prefix: &str, path: &str) -> bool {
+    prefix == "/"
+        || path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// A project's path routes: its `proj.toml` ones, overridden by those in its
+/// proxy options. Unreadable files and bad prefixes are skipped with a warning.
+pub fn declared(project: &Project) -> BTreeMap<String, u16> {
+    let from_file = match ProjectFile::load(&project.root_dir) {
+        Ok(file) => file.map(|file| file.routes).unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Ignoring routes of {}: {:#}", project.name, e);
+            BTreeMap::new()
+        }
+    };
+
+    let mut paths = BTreeMap::new();
+    for (prefix, port) in from_file.into_iter().chain(project.proxy.routes.clone()) {
+        match normalize_route_prefix(&prefix) {
+            Ok(prefix) => {
+                paths.insert(prefix, port);
+            }
+            Err(e) => tracing::warn!("Ignoring route of {}: {}", project.name, e),
+        }
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target() {
+        let mut routes = ProjectRoutes {
+            port: Some(3000),
+            paths: BTreeMap::from([("/api".to_string(), 4000), ("/api/admin".to_string(), 4100)]),
+        };
+        assert_eq!(routes.target("/"), Some(3000));
+        assert_eq!(routes.target("/api"), Some(4000));
+        assert_eq!(routes.target("/api/users"), Some(4000));
+        assert_eq!(routes.target("/api/admin/users"), Some(4100));
+        assert_eq!(routes.target("/apiary"), Some(3000));
+
+        routes.port = None;
+        assert_eq!(routes.target("/index.html"), None);
+        assert_eq!(routes.target("/api"), Some(4000));
+        assert!(!routes.is_empty());
+
+        routes.paths.insert("/".to_string(), 5000);
+        assert_eq!(routes.target("/index.html"), Some(5000));
+    }
+}
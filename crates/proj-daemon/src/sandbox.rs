@@ -0,0 +1,222 @@
+//! Confining spawned processes (`proj <name> sandbox`)
+//!
+//! The command is wrapped in the platform's sandboxing tool: bubblewrap
+//! (`bwrap`) on Linux, which mounts the filesystem read-only except for the
+//! writable directories and can unshare the network namespace, and
+//! `sandbox-exec` on macOS with a generated profile. Without the tool,
+//! sandboxed commands refuse to start rather than run unconfined. In
+//! read-only mode only the temp directory is writable, so a command that
+//! writes anywhere else gets a read-only filesystem or permission error.
+
+use anyhow::Result;
+use proj_common::{Capability, CapabilityStatus, Sandbox, SandboxMode};
+use std::path::{Path, PathBuf};
+
+/// The program and arguments that run `command` confined by `sandbox`, able
+/// to write to `writable` plus the sandbox's own directories
+pub fn wrap(
+    command: &str,
+    args: &[String],
+    sandbox: &Sandbox,
+    writable: &[PathBuf],
+) -> Result<(String, Vec<String>)> {
+    if sandbox.mode == SandboxMode::Off {
+        return Ok((command.to_string(), args.to_vec()));
+    }
+    platform_wrap(
+        command,
+        args,
+        sandbox.mode,
+        &writable_dirs(sandbox, writable),
+    )
+}
+
+/// Whether sandboxed runs can start here, for `proj doctor`
+pub fn capability() -> Capability {
+    const NAME: &str = "sandbox";
+    let tool = if cfg!(target_os = "linux") {
+        Some("bwrap")
+    } else if cfg!(target_os = "macos") {
+        Some("sandbox-exec")
+    } else {
+        None
+    };
+    match tool {
+        Some(tool) if crate::platform::on_path(tool) => {
+            Capability::new(NAME, CapabilityStatus::Ok, tool)
+        }
+        Some(tool) => Capability::new(
+            NAME,
+            CapabilityStatus::Unavailable,
+            format!(
+                "{} isn't installed, so sandboxed runs refuse to start",
+                tool
+            ),
+        ),
+        None => Capability::new(
+            NAME,
+            CapabilityStatus::Unavailable,
+            "not supported on this platform, so sandboxed runs refuse to start",
+        ),
+    }
+}
+
+/// Directories a sandboxed process may write to: the temp directory, plus
+/// `writable` and the sandbox's own unless it is read-only
+fn writable_dirs(sandbox: &Sandbox, writable: &[PathBuf]) -> Vec<PathBuf> {
+    let granted: Vec<PathBuf> = match sandbox.mode {
+        SandboxMode::ReadOnly => Vec::new(),
+        _ => writable.iter().chain(&sandbox.writable).cloned().collect(),
+    };
+
+    let mut dirs: Vec<PathBuf> = granted
+        .into_iter()
+        .chain([std::env::temp_dir()])
+        .filter(|dir| {
+            let exists = dir.is_dir();
+            if !exists {
+                tracing::warn!("Sandbox: skipping missing writable directory {:?}", dir);
+            }
+            exists
+        })
+        // The tools match real paths, not symlinks like macOS's /tmp
+        .map(|dir| dir.canonicalize().unwrap_or(dir))
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+#[cfg(target_os = "linux")]
+fn platform_wrap(
+    command: &str,
+    args: &[String],
+    mode: SandboxMode,
+    writable: &[PathBuf],
+) -> Result<(String, Vec<String>)> {
+    if !crate::platform::on_path("bwrap") {
+        anyhow::bail!("Sandboxing needs bubblewrap; install the bwrap package");
+    }
+    Ok((
+        "bwrap".to_string(),
+        bwrap_args(command, args, mode, writable),
+    ))
+}
+
+#[cfg(target_os = "macos")]
+fn platform_wrap(
+    command: &str,
+    args: &[String],
+    mode: SandboxMode,
+    writable: &[PathBuf],
+) -> Result<(String, Vec<String>)> {
+    let mut wrapped = vec![
+        "-p".to_string(),
+        seatbelt_profile(mode, writable),
+        command.to_string(),
+    ];
+    wrapped.extend(args.iter().cloned());
+    Ok(("sandbox-exec".to_string(), wrapped))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn platform_wrap(
+    _command: &str,
+    _args: &[String],
+    _mode: SandboxMode,
+    _writable: &[PathBuf],
+) -> Result<(String, Vec<String>)> {
+    anyhow::bail!("Sandboxing isn't supported on this platform")
+}
+
+/// bwrap arguments: everything read-only, devices usable, the writable
+/// directories bound over themselves
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn bwrap_args(
+    command: &str,
+    args: &[String],
+    mode: SandboxMode,
+    writable: &[PathBuf],
+) -> Vec<String> {
+    let mut wrapped: Vec<String> = ["--ro-bind", "/", "/", "--dev-bind", "/dev", "/dev"]
+        .map(str::to_string)
+        .to_vec();
+    for dir in writable {
+        let dir = dir.display().to_string();
+        wrapped.extend(["--bind".to_string(), dir.clone(), dir]);
+    }
+    if mode == SandboxMode::Offline {
+        wrapped.push("--unshare-net".to_string());
+    }
+    wrapped.push("--".to_string());
+    wrapped.push(command.to_string());
+    wrapped.extend(args.iter().cloned());
+    wrapped
+}
+
+/// sandbox-exec profile allowing everything but writes outside `writable`
+/// and, when offline, networking other than local sockets
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn seatbelt_profile(mode: SandboxMode, writable: &[PathBuf]) -> String {
+    let quote = |path: &Path| {
+        format!(
+            "\"{}\"",
+            path.display()
+                .to_string()
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+        )
+    };
+    let mut profile = String::from("(version 1)\n(allow default)\n(deny file-write*)\n");
+    profile.push_str("(allow file-write* (subpath \"/dev\")");
+    for dir in writable {
+        profile.push_str(&format!(" (subpath {})", quote(dir)));
+    }
+    profile.push_str(")\n");
+    if mode == SandboxMode::Offline {
+        profile.push_str("(deny network*)\n(allow network* (remote unix-socket))\n");
+    }
+    profile
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrappers() {
+        let args = vec!["install".to_string()];
+        let writable = vec![
+            PathBuf::from("/work/app"),
+            PathBuf::from("/home/me/.proj/projects/app"),
+        ];
+
+        let bwrap = bwrap_args("npm", &args, SandboxMode::Files, &writable);
+        assert_eq!(
+            bwrap.join(" "),
+            "--ro-bind / / --dev-bind /dev /dev --bind /work/app /work/app \
+             --bind /home/me/.proj/projects/app /home/me/.proj/projects/app -- npm install"
+        );
+        let offline = bwrap_args("npm", &args, SandboxMode::Offline, &writable);
+        assert!(offline.contains(&"--unshare-net".to_string()));
+
+        let profile = seatbelt_profile(SandboxMode::Offline, &[PathBuf::from("/work/\"app\"")]);
+        assert!(profile
+            .contains("(allow file-write* (subpath \"/dev\") (subpath \"/work/\\\"app\\\"\"))"));
+        assert!(profile.contains("(deny network*)"));
+        assert!(!seatbelt_profile(SandboxMode::Files, &writable).contains("network"));
+
+        let sandbox = Sandbox::default();
+        assert_eq!(
+            wrap("npm", &args, &sandbox, &writable).unwrap(),
+            ("npm".to_string(), args.clone())
+        );
+
+        let temp = std::env::temp_dir().canonicalize().unwrap();
+        let read_only = Sandbox {
+            mode: SandboxMode::ReadOnly,
+            writable: vec![PathBuf::from("/")],
+        };
+        assert_eq!(writable_dirs(&read_only, &[PathBuf::from("/")]), vec![temp]);
+    }
+}
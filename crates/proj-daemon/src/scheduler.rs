@@ -0,0 +1,120 @@
+//! Maintenance windows - start and stop scheduled projects on time
+
+use chrono::Local;
+use proj_common::{PortPolicy, ResourceLimits, RestartPolicy, DEFAULT_MAX_RESTARTS};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::time::Duration;
+
+use crate::ipc::DaemonState;
+use crate::process::{self, SpawnSpec};
+
+/// How often schedules are re-evaluated
+const TICK: Duration = Duration::from_secs(30);
+
+/// Periodically reconcile scheduled projects with their windows
+pub async fn run(state: Arc<DaemonState>) {
+    let mut interval = tokio::time::interval(TICK);
+    loop {
+        interval.tick().await;
+        reconcile(state.clone()).await;
+    }
+}
+
+/// Start scheduled projects inside their window and stop them outside it
+pub async fn reconcile(state: Arc<DaemonState>) {
+    let now = Local::now();
+    let grace = state.stop_grace();
+    let scheduled: Vec<_> = state
+        .registry
+        .read()
+        .await
+        .list()
+        .into_iter()
+        .filter(|p| p.schedule.is_some())
+        .cloned()
+        .collect();
+
+    for project in scheduled {
+        let Some(schedule) = project.schedule else {
+            continue;
+        };
+        let name = project.name;
+
+        if schedule.should_run(now) {
+            let Some((command, args)) = schedule.command.split_first() else {
+                continue;
+            };
+            let spec = SpawnSpec {
+                command: command.clone(),
+                args: args.to_vec(),
+                working_dir: project.root_dir,
+                restart_policy: RestartPolicy::Never,
+                max_restarts: DEFAULT_MAX_RESTARTS,
+                watch: None,
+                health: None,
+                smoke: Vec::new(),
+                service: None,
+                env: BTreeMap::new(),
+                port: PortPolicy::default(),
+                output: None,
+                job: None,
+                priority: project.priority,
+                env_profiles: project.env_profiles,
+                sandbox: project.sandbox,
+                then: None,
+                otherwise: None,
+                limits: ResourceLimits::default(),
+                named_ports: BTreeMap::new(),
+                profile: None,
+                tty: None,
+                requested: None,
+            };
+            let running = state.processes.list_for_project(&name);
+            if running.iter().any(|p| p.status.is_alive()) {
+                continue;
+            }
+            let prepared = match process::prepare(&name, &spec).await {
+                Ok(prepared) => prepared,
+                Err(e) => {
+                    tracing::warn!("Failed to start scheduled project {}: {}", name, e);
+                    continue;
+                }
+            };
+            // Checked again and started in one call, so a run started
+            // meanwhile isn't doubled
+            let started = state
+                .processes
+                .call_async(move |manager| {
+                    Box::pin(async move {
+                        let running = manager.list_for_project(&name);
+                        if running.iter().any(|p| p.status.is_alive()) {
+                            return None;
+                        }
+                        tracing::info!("Schedule window open, starting {}", name);
+                        let spawned = manager.spawn(name.clone(), spec, prepared).await;
+                        Some((name, spawned))
+                    })
+                })
+                .await;
+            if let Ok(Some((name, Err(e)))) = started {
+                tracing::warn!("Failed to start scheduled project {}: {}", name, e);
+            }
+        } else {
+            let pids = state
+                .processes
+                .call(move |manager| {
+                    let pids = manager.stop_all(Some(&name));
+                    if !pids.is_empty() {
+                        tracing::info!("Outside schedule window, stopped {}", name);
+                    }
+                    pids
+                })
+                .await
+                .unwrap_or_default();
+            for pid in pids {
+                tokio::spawn(process::terminate(pid, grace));
+            }
+        }
+    }
+}
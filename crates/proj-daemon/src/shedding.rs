@@ -0,0 +1,179 @@
+//! Load shedding - a cap on the requests the proxy has in flight to a project
+//!
+//! A dev server still compiling (a cold webpack start, say) answers slowly,
+//! and a page of assets or a few open tabs pile connections onto it until it
+//! falls over. With `proxy.max_in_flight` set, requests past the cap wait in
+//! line for a free slot, in arrival order. One still waiting after
+//! [`QUEUE_WAIT`] is shed: browsers get a page with its place in line that
+//! retries by itself, everything else a 503 with `Retry-After`. A slot is
+//! held until the response headers arrive, so a long download or a WebSocket
+//! doesn't keep others out.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tokio::time::{Duration, Instant};
+
+/// How long a request waits in line before it's shed
+pub const QUEUE_WAIT: Duration = Duration::from_secs(5);
+
+/// Requests to one project
+#[derive(Default)]
+struct Lane {
+    in_flight: usize,
+    /// Tickets of waiting requests, first in line first
+    waiting: VecDeque<u64>,
+    next_ticket: u64,
+    /// Woken when a slot frees up or the line moves
+    moved: Arc<Notify>,
+}
+
+/// What became of a request
+pub enum Admission {
+    /// Forward it, holding the slot until the permit drops
+    Admitted(Permit),
+    /// Waited too long; `position` requests were ahead of it out of
+    /// `queued` waiting
+    Shed { position: usize, queued: usize },
+}
+
+/// A request's slot, given back when dropped
+pub struct Permit {
+    shedder: LoadShedder,
+    project_name: String,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.shedder.lane(&self.project_name, |lane| {
+            lane.in_flight -= 1;
+            lane.moved.notify_waiters();
+        });
+    }
+}
+
+/// In-flight requests per project, shared by every proxy connection
+#[derive(Clone, Default)]
+pub struct LoadShedder {
+    lanes: Arc<Mutex<HashMap<String, Lane>>>,
+}
+
+impl LoadShedder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take one of a project's `limit` slots, waiting up to [`QUEUE_WAIT`]
+    /// for one to free up
+    pub async fn admit(&self, project_name: &str, limit: usize) -> Admission {
+        self.admit_within(project_name, limit, QUEUE_WAIT).await
+    }
+
+    async fn admit_within(&self, project_name: &str, limit: usize, wait: Duration) -> Admission {
+        let deadline = Instant::now() + wait;
+        let mut ticket = None;
+        loop {
+            let moved = self.lane(project_name, |lane| lane.moved.clone());
+            let notified = moved.notified();
+            tokio::pin!(notified);
+            // Registered before looking, so a slot freed meanwhile still wakes it
+            notified.as_mut().enable();
+
+            let admission = self.lane(project_name, |lane| {
+                let first_in_line = match ticket {
+                    None => lane.waiting.is_empty(),
+                    Some(ticket) => lane.waiting.front() == Some(&ticket),
+                };
+                if first_in_line && lane.in_flight < limit.max(1) {
+                    if ticket.is_some() {
+                        lane.waiting.pop_front();
+                        // The next in line may fit too
+                        lane.moved.notify_waiters();
+                    }
+                    lane.in_flight += 1;
+                    return Some(Admission::Admitted(Permit {
+                        shedder: self.clone(),
+                        project_name: project_name.to_string(),
+                    }));
+                }
+                if Instant::now() >= deadline {
+                    let queued = lane.waiting.len();
+                    let position = ticket.and_then(|t| lane.waiting.iter().position(|w| *w == t));
+                    if let Some(position) = position {
+                        lane.waiting.remove(position);
+                        lane.moved.notify_waiters();
+                    }
+                    return Some(Admission::Shed {
+                        position: position.unwrap_or(queued),
+                        queued,
+                    });
+                }
+                if ticket.is_none() {
+                    ticket = Some(lane.next_ticket);
+                    lane.waiting.push_back(lane.next_ticket);
+                    lane.next_ticket += 1;
+                }
+                None
+            });
+            if let Some(admission) = admission {
+                return admission;
+            }
+            let _ = tokio::time::timeout_at(deadline, notified).await;
+        }
+    }
+
+    /// Run `f` on a project's lane, with the lanes locked
+    fn lane<T>(&self, project_name: &str, f: impl FnOnce(&mut Lane) -> T) -> T {
+        let mut lanes = self.lanes.lock().unwrap();
+        f(lanes.entry(project_name.to_string()).or_default())
+    }
+
+    /// Requests in flight to a project
+    #[cfg(test)]
+    fn in_flight(&self, project_name: &str) -> usize {
+        self.lanes
+            .lock()
+            .unwrap()
+            .get(project_name)
+            .map_or(0, |lane| lane.in_flight)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_admit_in_order() {
+        let shedder = LoadShedder::new();
+        let Admission::Admitted(first) = shedder.admit("app", 1).await else {
+            panic!("first request shed");
+        };
+        assert_eq!(shedder.in_flight("app"), 1);
+
+        let waiting = tokio::spawn({
+            let shedder = shedder.clone();
+            async move { matches!(shedder.admit("app", 1).await, Admission::Admitted(_)) }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        drop(first);
+        assert!(waiting.await.unwrap());
+        assert_eq!(shedder.in_flight("app"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_shed_after_wait() {
+        let shedder = LoadShedder::new();
+        let _first = shedder.admit("app", 1).await;
+        let wait = Duration::from_millis(50);
+        match shedder.admit_within("app", 1, wait).await {
+            Admission::Shed { position, queued } => assert_eq!((position, queued), (0, 1)),
+            Admission::Admitted(_) => panic!("admitted over the limit"),
+        }
+        // Other projects have their own slots
+        assert!(matches!(
+            shedder.admit("web", 1).await,
+            Admission::Admitted(_)
+        ));
+    }
+}
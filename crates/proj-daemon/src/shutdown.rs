@@ -0,0 +1,138 @@
+//! Daemon shutdown - applies the exit policy to managed processes
+
+use proj_common::DaemonExitPolicy;
+use std::io::{BufRead, IsTerminal, Write};
+use std::sync::Arc;
+
+use crate::ipc::DaemonState;
+use crate::process;
+
+/// What to do with managed processes as the daemon exits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitAction {
+    StopAll,
+    LeaveRunning,
+}
+
+/// Decide the exit action from the configured policy and an explicit request.
+///
+/// `ask` is only consulted for [`DaemonExitPolicy::Ask`]; it returns `None` when
+/// nobody is there to answer, in which case processes are left running so
+/// nothing is lost (the next daemon re-adopts them).
+pub fn exit_action(
+    policy: DaemonExitPolicy,
+    requested: Option<bool>,
+    ask: impl FnOnce() -> Option<bool>,
+) -> ExitAction {
+    let stop = match (requested, policy) {
+        (Some(stop), _) => stop,
+        (None, DaemonExitPolicy::KillAll) => true,
+        (None, DaemonExitPolicy::LeaveRunning) => false,
+        (None, DaemonExitPolicy::Ask) => ask().unwrap_or(false),
+    };
+    if stop {
+        ExitAction::StopAll
+    } else {
+        ExitAction::LeaveRunning
+    }
+}
+
+/// Apply the exit policy to every managed process
+pub async fn stop_processes(
+    state: &Arc<DaemonState>,
+    policy: DaemonExitPolicy,
+    requested: Option<bool>,
+) {
+    let running = state.processes.running_count();
+    if running == 0 {
+        return;
+    }
+
+    let action = tokio::task::spawn_blocking(move || {
+        exit_action(policy, requested, || prompt_on_terminal(running))
+    })
+    .await
+    .unwrap_or(ExitAction::LeaveRunning);
+
+    if action == ExitAction::LeaveRunning {
+        tracing::info!(
+            "Leaving {} managed processes running for the next daemon to re-adopt",
+            running
+        );
+        return;
+    }
+
+    let pids = state
+        .processes
+        .call(|manager| manager.stop_all(None))
+        .await
+        .unwrap_or_default();
+    let grace = state.stop_grace();
+    tracing::info!("Stopping {} managed processes", pids.len());
+
+    // Each process gets its grace period in parallel
+    let mut tasks = tokio::task::JoinSet::new();
+    for pid in pids {
+        tasks.spawn(process::terminate(pid, grace));
+    }
+    while tasks.join_next().await.is_some() {}
+}
+
+/// Ask on the daemon's terminal (foreground mode only)
+fn prompt_on_terminal(running: usize) -> Option<bool> {
+    let stdin = std::io::stdin();
+    if !stdin.is_terminal() {
+        return None;
+    }
+
+    print!("Stop {} managed process(es)? [Y/n] ", running);
+    std::io::stdout().flush().ok()?;
+    let mut answer = String::new();
+    stdin.lock().read_line(&mut answer).ok()?;
+    Some(!matches!(answer.trim(), "n" | "N" | "no"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_action_modes() {
+        let no_answer = || None;
+        let panics = || -> Option<bool> { panic!("should not ask") };
+
+        // kill-all
+        assert_eq!(
+            exit_action(DaemonExitPolicy::KillAll, None, panics),
+            ExitAction::StopAll
+        );
+        // leave-running-and-readopt
+        assert_eq!(
+            exit_action(DaemonExitPolicy::LeaveRunning, None, panics),
+            ExitAction::LeaveRunning
+        );
+        // ask
+        assert_eq!(
+            exit_action(DaemonExitPolicy::Ask, None, || Some(true)),
+            ExitAction::StopAll
+        );
+        assert_eq!(
+            exit_action(DaemonExitPolicy::Ask, None, || Some(false)),
+            ExitAction::LeaveRunning
+        );
+        assert_eq!(
+            exit_action(DaemonExitPolicy::Ask, None, no_answer),
+            ExitAction::LeaveRunning
+        );
+
+        // An explicit request (e.g. answered by the CLI) overrides the policy
+        assert_eq!(
+            exit_action(DaemonExitPolicy::KillAll, Some(false), panics),
+            ExitAction::LeaveRunning
+        );
+        assert_eq!(
+            exit_action(DaemonExitPolicy::LeaveRunning, Some(true), panics),
+            ExitAction::StopAll
+        );
+    }
+}
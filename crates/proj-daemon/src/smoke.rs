@@ -0,0 +1,131 @@
+//! Smoke checks - quick HTTP checks of a project, sent through the proxy by
+//! `proj <name> smoke` or straight to a service by its health check
+
+use anyhow::{Context, Result};
+use http_body_util::{BodyExt, Empty, Limited};
+use hyper::body::Bytes;
+use hyper::header::{HOST, USER_AGENT};
+use hyper::Request;
+use hyper_util::rt::TokioIo;
+use proj_common::{SmokeCheck, SmokeResult};
+use std::net::SocketAddr;
+use tokio::net::TcpStream;
+use tokio::time::{Duration, Instant};
+
+/// How long a check run by `proj <name> smoke` may take
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most of a response body searched for a check's `contains`
+const MAX_BODY: usize = 4 * 1024 * 1024;
+
+/// Run checks one after another against `addr`, as requests for `host`
+pub async fn run(
+    addr: SocketAddr,
+    host: &str,
+    checks: &[SmokeCheck],
+    timeout: Duration,
+) -> Vec<SmokeResult> {
+    let mut results = Vec::with_capacity(checks.len());
+    for check in checks {
+        results.push(run_one(addr, host, check, timeout).await);
+    }
+    results
+}
+
+async fn run_one(
+    addr: SocketAddr,
+    host: &str,
+    check: &SmokeCheck,
+    timeout: Duration,
+) -> SmokeResult {
+    let started = Instant::now();
+    let response = match tokio::time::timeout(timeout, get(addr, host, &check.path)).await {
+        Ok(response) => response,
+        Err(_) => Err(anyhow::anyhow!(
+            "No response within {}s",
+            timeout.as_secs_f32()
+        )),
+    };
+    let duration_ms = started.elapsed().as_millis() as u64;
+    let (status, failure) = match response {
+        Ok((status, body)) => (Some(status), failure(check, status, &body)),
+        Err(e) => (None, Some(format!("{:#}", e))),
+    };
+    SmokeResult {
+        check: check.clone(),
+        status,
+        duration_ms,
+        failure,
+    }
+}
+
+/// Why a response fails a check, if it does
+fn failure(check: &SmokeCheck, status: u16, body: &[u8]) -> Option<String> {
+    if status != check.status {
+        return Some(format!("Expected {}, got {}", check.status, status));
+    }
+    let text = check.contains.as_deref()?;
+    let found = text.is_empty()
+        || body
+            .windows(text.len())
+            .any(|window| window == text.as_bytes());
+    (!found).then(|| format!("Body doesn't contain {:?}", text))
+}
+
+/// GET `path` over a fresh connection, returning the status and body
+async fn get(addr: SocketAddr, host: &str, path: &str) -> Result<(u16, Bytes)> {
+    let stream = TcpStream::connect(addr)
+        .await
+        .with_context(|| format!("Failed to connect to {}", addr))?;
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .context("HTTP handshake failed")?;
+    tokio::spawn(connection);
+
+    let request = Request::get(path)
+        .header(HOST, host)
+        .header(USER_AGENT, "proj-smoke")
+        .body(Empty::<Bytes>::new())?;
+    let response = sender
+        .send_request(request)
+        .await
+        .context("Request failed")?;
+    let status = response.status().as_u16();
+    let body = Limited::new(response.into_body(), MAX_BODY)
+        .collect()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to read the body: {}", e))?
+        .to_bytes();
+    Ok((status, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(status: u16, contains: Option<&str>) -> SmokeCheck {
+        SmokeCheck {
+            path: "/".to_string(),
+            status,
+            contains: contains.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_failure() {
+        assert_eq!(failure(&check(200, None), 200, b""), None);
+        assert_eq!(
+            failure(&check(200, None), 500, b"").as_deref(),
+            Some("Expected 200, got 500")
+        );
+        assert_eq!(
+            failure(&check(200, Some("Welcome")), 200, b"<h1>Welcome</h1>"),
+            None
+        );
+        assert_eq!(
+            failure(&check(200, Some("Welcome")), 200, b"<h1>Sign in</h1>").as_deref(),
+            Some("Body doesn't contain \"Welcome\"")
+        );
+        assert_eq!(failure(&check(404, Some("")), 404, b""), None);
+    }
+}
@@ -0,0 +1,217 @@
+//! Resource usage sampling - CPU and memory of each managed process tree
+
+use proj_common::ResourceUsage;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::ipc::DaemonState;
+
+/// How often usage is sampled; CPU% is averaged over this window
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Periodically sample every running process tree and record its usage
+pub async fn run(state: Arc<DaemonState>) {
+    // Total CPU time per process at the previous sample
+    let mut previous: HashMap<Uuid, (Instant, Duration)> = HashMap::new();
+    let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let pids: Vec<(Uuid, u32)> = state
+            .processes
+            .list()
+            .into_iter()
+            .filter(|p| p.status.is_alive())
+            .map(|p| (p.id, p.pid))
+            .collect();
+
+        let Ok(samples) = tokio::task::spawn_blocking(move || sample_all(&pids)).await else {
+            continue;
+        };
+
+        let now = Instant::now();
+        let mut usage = Vec::with_capacity(samples.len());
+        let mut current = HashMap::with_capacity(samples.len());
+        for (id, sample) in samples {
+            let cpu_percent = previous
+                .get(&id)
+                .map(|(at, cpu)| cpu_percent(sample.cpu_time.saturating_sub(*cpu), now - *at))
+                .unwrap_or(0.0);
+            current.insert(id, (now, sample.cpu_time));
+            usage.push((
+                id,
+                ResourceUsage {
+                    cpu_percent,
+                    memory_bytes: sample.memory_bytes,
+                },
+            ));
+        }
+        previous = current;
+
+        state
+            .processes
+            .cast(move |manager| {
+                for (id, usage) in usage {
+                    manager.update_usage(id, usage);
+                }
+            })
+            .await;
+    }
+}
+
+/// CPU time and resident memory of a process tree
+#[derive(Debug, Clone, Copy, Default)]
+struct Sample {
+    cpu_time: Duration,
+    memory_bytes: u64,
+}
+
+/// Share of one core used over `elapsed`, as a percentage
+fn cpu_percent(cpu: Duration, elapsed: Duration) -> f32 {
+    if elapsed.is_zero() {
+        return 0.0;
+    }
+    (cpu.as_secs_f64() / elapsed.as_secs_f64() * 100.0) as f32
+}
+
+#[cfg(target_os = "linux")]
+fn sample_all(pids: &[(Uuid, u32)]) -> Vec<(Uuid, Sample)> {
+    // /proc reports CPU time in USER_HZ ticks, which Linux fixes at 100
+    const TICKS_PER_SEC: u64 = 100;
+
+    let Some(children) = crate::ports::children_map() else {
+        return Vec::new();
+    };
+    pids.iter()
+        .map(|&(id, pid)| {
+            let tree =
+                crate::ports::process_tree(pid, |p| children.get(&p).cloned().unwrap_or_default());
+            let mut sample = Sample::default();
+            for pid in tree {
+                if let Some(ticks) = std::fs::read_to_string(format!("/proc/{}/stat", pid))
+                    .ok()
+                    .and_then(|stat| parse_cpu_ticks(&stat))
+                {
+                    sample.cpu_time += Duration::from_millis(ticks * 1000 / TICKS_PER_SEC);
+                }
+                if let Some(rss) = std::fs::read_to_string(format!("/proc/{}/status", pid))
+                    .ok()
+                    .and_then(|status| parse_vm_rss(&status))
+                {
+                    sample.memory_bytes += rss;
+                }
+            }
+            (id, sample)
+        })
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+fn sample_all(pids: &[(Uuid, u32)]) -> Vec<(Uuid, Sample)> {
+    use libproc::libproc::pid_rusage::{pidrusage, RUsageInfoV2};
+    use libproc::processes::{pids_by_type, ProcFilter};
+
+    pids.iter()
+        .map(|&(id, pid)| {
+            let tree = crate::ports::process_tree(pid, |ppid| {
+                pids_by_type(ProcFilter::ByParentProcess { ppid }).unwrap_or_default()
+            });
+            let mut sample = Sample::default();
+            for pid in tree {
+                if let Ok(usage) = pidrusage::<RUsageInfoV2>(pid as i32) {
+                    // Times are in Mach absolute time units, nanoseconds on
+                    // Intel; close enough for a dev dashboard
+                    sample.cpu_time +=
+                        Duration::from_nanos(usage.ri_user_time + usage.ri_system_time);
+                    sample.memory_bytes += usage.ri_resident_size;
+                }
+            }
+            (id, sample)
+        })
+        .collect()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn sample_all(_pids: &[(Uuid, u32)]) -> Vec<(Uuid, Sample)> {
+    Vec::new()
+}
+
+/// File descriptors the daemon has open
+pub fn open_fds() -> Option<u64> {
+    let dir = if cfg!(target_os = "linux") {
+        "/proc/self/fd"
+    } else if cfg!(target_os = "macos") {
+        "/dev/fd"
+    } else {
+        return None;
+    };
+    // Reading the directory opens one more, which isn't counted
+    Some(std::fs::read_dir(dir).ok()?.count().saturating_sub(1) as u64)
+}
+
+/// Resident memory of the daemon itself
+#[cfg(target_os = "linux")]
+pub fn own_memory() -> Option<u64> {
+    parse_vm_rss(&std::fs::read_to_string("/proc/self/status").ok()?)
+}
+
+#[cfg(target_os = "macos")]
+pub fn own_memory() -> Option<u64> {
+    use libproc::libproc::pid_rusage::{pidrusage, RUsageInfoV2};
+    let usage = pidrusage::<RUsageInfoV2>(std::process::id() as i32).ok()?;
+    Some(usage.ri_resident_size)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn own_memory() -> Option<u64> {
+    None
+}
+
+/// utime + stime (fields 14 and 15) from `/proc/<pid>/stat`
+#[cfg(any(target_os = "linux", test))]
+fn parse_cpu_ticks(stat: &str) -> Option<u64> {
+    // The command name is parenthesized and may contain spaces; after it,
+    // fields start at 3 (state)
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some(utime + stime)
+}
+
+/// Resident set size in bytes from `/proc/<pid>/status`
+#[cfg(any(target_os = "linux", test))]
+fn parse_vm_rss(status: &str) -> Option<u64> {
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc_usage() {
+        let stat = "4242 (node server.js) S 1 4242 4242 0 -1 4194560 5000 0 0 0 250 50 0 0 20 0 11 0 1000 1000000 5000";
+        assert_eq!(parse_cpu_ticks(stat), Some(300));
+
+        let status = "Name:\tnode\nVmPeak:\t  900000 kB\nVmRSS:\t   51200 kB\nThreads:\t11\n";
+        assert_eq!(parse_vm_rss(status), Some(51200 * 1024));
+
+        assert_eq!(
+            cpu_percent(Duration::from_millis(500), Duration::from_secs(2)),
+            25.0
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_own_usage() {
+        // Standard streams at least
+        assert!(open_fds().unwrap() >= 3);
+        assert!(own_memory().unwrap() > 0);
+    }
+}
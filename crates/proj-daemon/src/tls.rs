@@ -0,0 +1,272 @@
+//! Local certificate authority and per-project leaf certificates for HTTPS
+
+use anyhow::{Context, Result};
+use chrono::{Datelike, NaiveDate, Utc};
+use proj_common::{naming, Config};
+use rcgen::{
+    date_time_ymd, BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa,
+    Issuer, KeyPair, KeyUsagePurpose,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+use tokio_rustls::rustls::crypto::ring::{default_provider, sign::any_supported_type};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::rustls::ServerConfig;
+
+use crate::proxy::{AliasTable, ProxyOptionsTable};
+
+/// Leaf certificate lifetime (Apple platforms reject anything longer than 825 days)
+const LEAF_VALIDITY_DAYS: u64 = 825;
+
+/// Regenerate stored leaf certificates this long before they expire
+const LEAF_RENEW_AFTER_DAYS: u64 = 800;
+
+/// CA certificate lifetime
+const CA_VALIDITY_DAYS: u64 = 3650;
+
+/// Common name of the local CA, as shown in trust stores
+const CA_COMMON_NAME: &str = "proj Development CA";
+
+/// Build a rustls server config that issues certificates on demand from the local CA
+pub fn server_config(
+    tls_dir: &Path,
+    config: watch::Receiver<Config>,
+    aliases: AliasTable,
+    options: ProxyOptionsTable,
+) -> Result<Arc<ServerConfig>> {
+    let resolver = CertResolver::new(tls_dir, config, aliases, options)?;
+
+    let mut config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .context("Failed to configure TLS protocol versions")?
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(resolver));
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    Ok(Arc::new(config))
+}
+
+/// Resolves SNI hostnames to leaf certificates signed by the local CA
+struct CertResolver {
+    dir: PathBuf,
+    /// Read for the TLD certificates are issued under
+    config: watch::Receiver<Config>,
+    /// Hostname aliases, which get certificates of their own
+    aliases: AliasTable,
+    /// Proxy options, whose hostname overrides get certificates of their
+    /// own when their HTTPS is intercepted
+    options: ProxyOptionsTable,
+    issuer: Issuer<'static, KeyPair>,
+    cache: Mutex<HashMap<String, Arc<CertifiedKey>>>,
+}
+
+impl std::fmt::Debug for CertResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CertResolver")
+            .field("dir", &self.dir)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CertResolver {
+    fn new(
+        dir: &Path,
+        config: watch::Receiver<Config>,
+        aliases: AliasTable,
+        options: ProxyOptionsTable,
+    ) -> Result<Self> {
+        std::fs::create_dir_all(dir).context("Failed to create TLS directory")?;
+        let issuer = load_or_create_ca(dir)?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            config,
+            aliases,
+            options,
+            issuer,
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Load a stored leaf certificate for `name`, issuing a new one if missing or stale
+    fn certified_key(&self, name: &str) -> Result<Arc<CertifiedKey>> {
+        let cert_path = self.dir.join(format!("{}.pem", name));
+        let key_path = self.dir.join(format!("{}-key.pem", name));
+
+        if !is_fresh(&cert_path, &self.dir.join("ca.pem")) || !key_path.exists() {
+            let (cert_pem, key_pem) = self.issue_leaf(name)?;
+            std::fs::write(&cert_path, cert_pem).context("Failed to write certificate")?;
+            write_private(&key_path, &key_pem)?;
+        }
+
+        let cert = CertificateDer::from_pem_file(&cert_path).context("Invalid certificate")?;
+        let key = PrivateKeyDer::from_pem_file(&key_path).context("Invalid private key")?;
+        let signing_key = any_supported_type(&key).context("Unsupported private key")?;
+
+        Ok(Arc::new(CertifiedKey::new(vec![cert], signing_key)))
+    }
+
+    /// Issue a leaf certificate covering `<name>` and its subdomains
+    fn issue_leaf(&self, name: &str) -> Result<(String, String)> {
+        let names = if name == "localhost" {
+            vec!["localhost".to_string()]
+        } else {
+            vec![name.to_string(), format!("*.{}", name)]
+        };
+
+        let mut params = CertificateParams::new(names)?;
+        params.distinguished_name.push(DnType::CommonName, name);
+        params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+        set_validity(&mut params, LEAF_VALIDITY_DAYS);
+
+        let key = KeyPair::generate()?;
+        let cert = params.signed_by(&key, &self.issuer)?;
+        tracing::info!("Issued TLS certificate for {}", name);
+
+        Ok((cert.pem(), key.serialize_pem()))
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let tld = self.config.borrow().tld().to_string();
+        // Handshakes can't wait on the tables; a busy one falls back to localhost
+        let own_name = client_hello
+            .server_name()
+            .map(str::to_ascii_lowercase)
+            .filter(|host| {
+                self.aliases
+                    .try_read()
+                    .is_ok_and(|aliases| aliases.contains_key(host))
+                    || self.options.try_read().is_ok_and(|options| {
+                        options.values().any(|o| o.overrides.contains_key(host))
+                    })
+            });
+        let name = own_name.unwrap_or_else(|| leaf_name(client_hello.server_name(), &tld));
+
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(key) = cache.get(&name) {
+            return Some(key.clone());
+        }
+
+        match self.certified_key(&name) {
+            Ok(key) => {
+                cache.insert(name, key.clone());
+                Some(key)
+            }
+            Err(e) => {
+                tracing::warn!("Failed to load TLS certificate for {}: {}", name, e);
+                None
+            }
+        }
+    }
+}
+
+/// Map an SNI hostname to the name its certificate is issued for
+///
+/// `api.my-app.localhost` and `my-app.localhost` share the `my-app.localhost`
+/// certificate; clients without SNI, or outside `tld`, get a plain `localhost`
+/// certificate.
+fn leaf_name(server_name: Option<&str>, tld: &str) -> String {
+    let project = server_name.and_then(|host| naming::project_from_host(host, tld));
+    match project {
+        Some(project) => format!("{}.{}", project, tld),
+        None => "localhost".to_string(),
+    }
+}
+
+/// Load the CA key from disk, generating and storing a new CA if none exists
+fn load_or_create_ca(dir: &Path) -> Result<Issuer<'static, KeyPair>> {
+    let cert_path = dir.join("ca.pem");
+    let key_path = dir.join("ca-key.pem");
+
+    if cert_path.exists() && key_path.exists() {
+        let key_pem = std::fs::read_to_string(&key_path).context("Failed to read CA key")?;
+        let key = KeyPair::from_pem(&key_pem).context("Invalid CA key")?;
+        return Ok(Issuer::new(ca_params(), key));
+    }
+
+    let mut params = ca_params();
+    set_validity(&mut params, CA_VALIDITY_DAYS);
+    let key = KeyPair::generate()?;
+    let cert = params.self_signed(&key)?;
+
+    write_private(&key_path, &key.serialize_pem())?;
+    std::fs::write(&cert_path, cert.pem()).context("Failed to write CA certificate")?;
+    tracing::info!("Generated local CA at {:?}", cert_path);
+
+    Ok(Issuer::new(params, key))
+}
+
+/// CA parameters; the subject must stay stable so leaves chain to the stored CA
+fn ca_params() -> CertificateParams {
+    let mut params = CertificateParams::default();
+    params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+    params
+        .distinguished_name
+        .push(DnType::CommonName, CA_COMMON_NAME);
+    params
+        .distinguished_name
+        .push(DnType::OrganizationName, "proj");
+    params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+    params
+}
+
+/// Valid from yesterday (to tolerate clock skew) for `days`
+fn set_validity(params: &mut CertificateParams, days: u64) {
+    let today = Utc::now().date_naive();
+    let from = today.pred_opt().unwrap_or(today);
+    let until = today
+        .checked_add_days(chrono::Days::new(days))
+        .unwrap_or(today);
+    let ymd = |date: NaiveDate| date_time_ymd(date.year(), date.month() as u8, date.day() as u8);
+    params.not_before = ymd(from);
+    params.not_after = ymd(until);
+}
+
+/// Whether a stored leaf certificate is newer than the CA and not due for renewal
+fn is_fresh(path: &Path, ca_path: &Path) -> bool {
+    let modified = |p: &Path| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+    let (Some(issued), Some(ca_issued)) = (modified(path), modified(ca_path)) else {
+        return false;
+    };
+    let max_age = Duration::from_secs(LEAF_RENEW_AFTER_DAYS * 24 * 60 * 60);
+    issued >= ca_issued
+        && SystemTime::now()
+            .duration_since(issued)
+            .is_ok_and(|age| age < max_age)
+}
+
+/// Write a private key readable only by the current user
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &str) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .context("Failed to write private key")?;
+    file.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+/// Write a private key; the user profile's ACL already keeps other users out
+#[cfg(windows)]
+fn write_private(path: &Path, contents: &str) -> Result<()> {
+    std::fs::write(path, contents).context("Failed to write private key")
+}
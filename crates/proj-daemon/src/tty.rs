@@ -0,0 +1,222 @@
+//! Pseudo-terminals for interactive runs (`proj <name> run --tty`)
+//!
+//! A run with `tty` starts in a PTY instead of with piped output, so programs
+//! that check isatty or prompt for input behave as in a shell. Its output is
+//! stored line by line like any process's, and also kept as raw bytes for
+//! attached clients: the last [`BACKLOG_BYTES`] are replayed on attach, so a
+//! prompt printed before the client connects isn't lost. Clients send
+//! keystrokes and resizes back; one disconnecting leaves the process running.
+
+use anyhow::{Context, Result};
+use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtySize};
+use proj_common::TerminalSize;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, mpsc, watch};
+
+/// Output replayed to a client that attaches
+pub const BACKLOG_BYTES: usize = 64 * 1024;
+
+/// A running process's terminal
+pub struct Tty {
+    /// Kept open for the terminal's lifetime; also what resizes go to
+    master: Mutex<Box<dyn MasterPty + Send>>,
+    input: Mutex<Box<dyn Write + Send>>,
+    /// Recent output, locked while new output is broadcast so an attaching
+    /// client gets each byte exactly once
+    backlog: Mutex<VecDeque<u8>>,
+    output: broadcast::Sender<Vec<u8>>,
+    /// Exit code once the process is gone (`Some(None)` if it was killed)
+    exited: watch::Sender<Option<Option<i32>>>,
+}
+
+/// A process started in a terminal, and its output as it's read
+pub struct Spawned {
+    pub tty: Arc<Tty>,
+    pub child: Box<dyn Child + Send + Sync>,
+    pub pid: u32,
+    pub output: mpsc::Receiver<Vec<u8>>,
+}
+
+/// Start `program` in a new terminal of `size`
+pub fn spawn(
+    program: &str,
+    args: &[String],
+    working_dir: &Path,
+    env: &BTreeMap<String, String>,
+    size: TerminalSize,
+) -> Result<Spawned> {
+    let pair = native_pty_system()
+        .openpty(pty_size(size))
+        .context("Failed to open a pseudo-terminal")?;
+
+    let mut cmd = CommandBuilder::new(program);
+    cmd.args(args);
+    cmd.cwd(working_dir);
+    // The daemon usually has no terminal of its own to pass on
+    if cmd.get_env("TERM").is_none() {
+        cmd.env("TERM", "xterm-256color");
+    }
+    for (key, value) in env {
+        cmd.env(key, value);
+    }
+    let child = pair.slave.spawn_command(cmd)?;
+    // Only the child holds the terminal open, so reads end when it's gone
+    drop(pair.slave);
+    let pid = child.process_id().context("Failed to get process ID")?;
+
+    let reader = pair.master.try_clone_reader()?;
+    let input = pair.master.take_writer()?;
+    let (output_tx, output) = mpsc::channel(64);
+    std::thread::spawn(move || read_output(reader, output_tx));
+
+    let tty = Arc::new(Tty {
+        master: Mutex::new(pair.master),
+        input: Mutex::new(input),
+        backlog: Mutex::new(VecDeque::new()),
+        output: broadcast::channel(256).0,
+        exited: watch::channel(None).0,
+    });
+    Ok(Spawned {
+        tty,
+        child,
+        pid,
+        output,
+    })
+}
+
+/// Forward what the terminal prints until it's closed; reads block, so this
+/// runs on a thread of its own
+fn read_output(mut reader: Box<dyn Read + Send>, output: mpsc::Sender<Vec<u8>>) {
+    let mut buf = [0; 4096];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) | Err(_) => return,
+            Ok(n) => {
+                if output.blocking_send(buf[..n].to_vec()).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl Tty {
+    /// Record output and send it to attached clients
+    pub fn push(&self, bytes: &[u8]) {
+        let mut backlog = self.backlog.lock().unwrap();
+        backlog.extend(bytes);
+        let excess = backlog.len().saturating_sub(BACKLOG_BYTES);
+        backlog.drain(..excess);
+        let _ = self.output.send(bytes.to_vec());
+    }
+
+    /// Recent output, and a receiver for what follows it
+    pub fn attach(&self) -> (Vec<u8>, broadcast::Receiver<Vec<u8>>) {
+        let backlog = self.backlog.lock().unwrap();
+        (backlog.iter().copied().collect(), self.output.subscribe())
+    }
+
+    /// Type into the terminal
+    pub fn write(&self, bytes: &[u8]) -> std::io::Result<()> {
+        let mut input = self.input.lock().unwrap();
+        input.write_all(bytes)?;
+        input.flush()
+    }
+
+    /// Resize the terminal; the process gets SIGWINCH
+    pub fn resize(&self, size: TerminalSize) -> Result<()> {
+        self.master.lock().unwrap().resize(pty_size(size))
+    }
+
+    /// Tell attached clients the process exited
+    pub fn close(&self, exit_code: Option<i32>) {
+        self.exited.send_replace(Some(exit_code));
+    }
+
+    /// Changes to whether (and how) the process exited
+    pub fn exited(&self) -> watch::Receiver<Option<Option<i32>>> {
+        self.exited.subscribe()
+    }
+}
+
+fn pty_size(size: TerminalSize) -> PtySize {
+    PtySize {
+        rows: size.rows.max(1),
+        cols: size.cols.max(1),
+        pixel_width: 0,
+        pixel_height: 0,
+    }
+}
+
+/// Split terminal output into lines for the log store, keeping an unfinished
+/// one until its end arrives
+#[derive(Default)]
+pub struct Lines {
+    pending: Vec<u8>,
+}
+
+impl Lines {
+    /// The lines `bytes` completes, without line endings
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(bytes);
+        let Some(end) = self.pending.iter().rposition(|&b| b == b'\n') else {
+            return Vec::new();
+        };
+        let complete: Vec<u8> = self.pending.drain(..end).collect();
+        self.pending.remove(0);
+        complete.split(|&b| b == b'\n').map(decode).collect()
+    }
+
+    /// What's left once the terminal closes
+    pub fn finish(self) -> Option<String> {
+        (!self.pending.is_empty()).then(|| decode(&self.pending))
+    }
+}
+
+/// A line as text, without the carriage return terminals end lines with
+fn decode(line: &[u8]) -> String {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    String::from_utf8_lossy(line).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines() {
+        let mut lines = Lines::default();
+        assert!(lines.push(b"Name: ").is_empty());
+        assert_eq!(lines.push(b"app\r\nVersion"), ["Name: app"]);
+        assert_eq!(lines.push(b": 1\r\n\r\nok"), ["Version: 1", ""]);
+        assert_eq!(lines.finish().as_deref(), Some("ok"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_spawn() {
+        let args = [
+            "-c".to_string(),
+            "[ -t 0 ] && read name && echo hi $name".to_string(),
+        ];
+        let mut spawned = spawn(
+            "sh",
+            &args,
+            &std::env::temp_dir(),
+            &BTreeMap::new(),
+            TerminalSize::default(),
+        )
+        .unwrap();
+        spawned.tty.write(b"there\n").unwrap();
+
+        let mut output = Vec::new();
+        while let Some(bytes) = spawned.output.recv().await {
+            output.extend(bytes);
+        }
+        assert!(String::from_utf8_lossy(&output).contains("hi there"));
+        assert!(spawned.child.wait().unwrap().success());
+    }
+}
@@ -0,0 +1,216 @@
+//! Connections from the proxy to backends
+//!
+//! Requests go through a pooled client, so a page's assets reuse a few
+//! keep-alive connections to the backend instead of opening one each. Right
+//! after a dev server restarts its port refuses connections for a moment: a
+//! request without a body whose method is idempotent is sent once more after
+//! a short backoff when it couldn't connect, rather than failing with 502.
+//! Bodies stream through both ways. `upstream_connect_timeout_secs` bounds
+//! connecting and `upstream_read_timeout_secs` the wait for response headers.
+
+use anyhow::{Context, Result};
+use http_body_util::{combinators::BoxBody, BodyExt, Empty};
+use hyper::body::{Body, Bytes, Incoming};
+use hyper::{Request, Response, Uri};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use proj_common::Config;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use tokio::time::Duration;
+
+/// Wait before sending a request again after its connect failed
+const RETRY_BACKOFF: Duration = Duration::from_millis(250);
+
+/// How long an unused pooled connection stays open
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Unused connections kept per backend
+const POOL_MAX_IDLE: usize = 8;
+
+type PooledClient = Client<HttpConnector, BoxBody<Bytes, hyper::Error>>;
+
+/// Pooled connections to every backend, shared by every proxy connection
+#[derive(Clone)]
+pub struct Upstream {
+    /// The client, and the connect timeout it was built with
+    client: Arc<Mutex<(u64, PooledClient)>>,
+    config: watch::Receiver<Config>,
+}
+
+impl Upstream {
+    pub fn new(config: watch::Receiver<Config>) -> Self {
+        let connect_timeout_secs = config.borrow().upstream_connect_timeout_secs;
+        Self {
+            client: Arc::new(Mutex::new((
+                connect_timeout_secs,
+                build_client(connect_timeout_secs),
+            ))),
+            config,
+        }
+    }
+
+    /// Send a request to the backend at `addr` (`host:port`), returning its
+    /// response once the headers are in
+    pub async fn send(
+        &self,
+        req: Request<BoxBody<Bytes, hyper::Error>>,
+        addr: &str,
+    ) -> Result<Response<Incoming>> {
+        let (mut parts, body) = req.into_parts();
+        let path = parts.uri.path_and_query().map_or("/", |path| path.as_str());
+        parts.uri = Uri::try_from(format!("http://{}{}", addr, path))
+            .with_context(|| format!("Invalid backend address {}", addr))?;
+
+        // Without a body, a request that never got through can be sent again
+        let retry = (parts.method.is_idempotent() && body.is_end_stream()).then(|| {
+            let mut again = Request::new(empty_body());
+            *again.method_mut() = parts.method.clone();
+            *again.uri_mut() = parts.uri.clone();
+            *again.version_mut() = parts.version;
+            *again.headers_mut() = parts.headers.clone();
+            again
+        });
+
+        let (client, read_timeout) = self.client();
+        match send(&client, Request::from_parts(parts, body), read_timeout).await {
+            Err(e) if is_connect(&e) => {
+                let Some(again) = retry else {
+                    return Err(e);
+                };
+                tracing::debug!("Connecting to {} failed, retrying: {:#}", addr, e);
+                tokio::time::sleep(RETRY_BACKOFF).await;
+                send(&client, again, read_timeout).await
+            }
+            result => result,
+        }
+    }
+
+    /// The client for the current connect timeout, and the read timeout
+    fn client(&self) -> (PooledClient, Option<Duration>) {
+        let (connect_timeout_secs, read_timeout_secs) = {
+            let config = self.config.borrow();
+            (
+                config.upstream_connect_timeout_secs,
+                config.upstream_read_timeout_secs,
+            )
+        };
+        let mut client = self.client.lock().unwrap();
+        // A changed timeout gets a new pool; the old one closes as it drains
+        if client.0 != connect_timeout_secs {
+            *client = (connect_timeout_secs, build_client(connect_timeout_secs));
+        }
+        (client.1.clone(), timeout(read_timeout_secs))
+    }
+}
+
+fn build_client(connect_timeout_secs: u64) -> PooledClient {
+    let mut connector = HttpConnector::new();
+    connector.set_connect_timeout(timeout(connect_timeout_secs));
+    connector.set_nodelay(true);
+    Client::builder(TokioExecutor::new())
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .pool_max_idle_per_host(POOL_MAX_IDLE)
+        .build(connector)
+}
+
+async fn send(
+    client: &PooledClient,
+    req: Request<BoxBody<Bytes, hyper::Error>>,
+    read_timeout: Option<Duration>,
+) -> Result<Response<Incoming>> {
+    let response = client.request(req);
+    let response = match read_timeout {
+        Some(read_timeout) => tokio::time::timeout(read_timeout, response)
+            .await
+            .map_err(|_| anyhow::anyhow!("No response within {}s", read_timeout.as_secs()))?,
+        None => response.await,
+    };
+    Ok(response?)
+}
+
+/// Whether sending failed before the request reached the backend
+fn is_connect(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<hyper_util::client::legacy::Error>()
+        .is_some_and(|e| e.is_connect())
+}
+
+/// Seconds as a timeout, with 0 as none
+fn timeout(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+fn empty_body() -> BoxBody<Bytes, hyper::Error> {
+    Empty::<Bytes>::new()
+        .map_err(|never| match never {})
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// A backend that answers each connection's first request, then closes it
+    async fn backend(listener: TcpListener) {
+        loop {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                return;
+            };
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).await;
+            let _ = stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok")
+                .await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_refused_connect() {
+        // Free a port, then start listening on it only after the first attempt
+        let port = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let addr = format!("127.0.0.1:{}", port);
+        let upstream = Upstream::new(watch::channel(Config::default()).1);
+        let request = || {
+            Request::get("/")
+                .header("host", "app.localhost")
+                .body(empty_body())
+                .unwrap()
+        };
+
+        let bind = tokio::spawn({
+            let addr = addr.clone();
+            async move {
+                tokio::time::sleep(RETRY_BACKOFF / 2).await;
+                tokio::spawn(backend(TcpListener::bind(addr).await.unwrap()));
+            }
+        });
+        let response = upstream.send(request(), &addr).await.unwrap();
+        assert_eq!(response.status(), 200);
+        bind.await.unwrap();
+
+        // A body can't be sent again, so a refused POST fails right away
+        let closed = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let post = Request::post("/")
+            .body(
+                http_body_util::Full::new(Bytes::from("data"))
+                    .map_err(|never| match never {})
+                    .boxed(),
+            )
+            .unwrap();
+        let error = upstream
+            .send(post, &format!("127.0.0.1:{}", closed))
+            .await
+            .unwrap_err();
+        assert!(is_connect(&error));
+    }
+}
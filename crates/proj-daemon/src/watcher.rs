@@ -0,0 +1,136 @@
+//! File watcher - restarts managed processes when project files change
+
+use anyhow::{Context, Result};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use proj_common::WatchOptions;
+use std::path::{Component, Path};
+use tokio::sync::mpsc;
+use tokio::time::Duration;
+use uuid::Uuid;
+
+use crate::process::ProcessEvent;
+
+/// A running file watcher; dropping it stops watching
+pub struct WatchHandle {
+    _watcher: RecommendedWatcher,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Watch `root_dir` recursively and emit `FilesChanged` after each debounced burst
+pub fn watch(
+    process_id: Uuid,
+    root_dir: &Path,
+    options: &WatchOptions,
+    event_tx: mpsc::Sender<ProcessEvent>,
+) -> Result<WatchHandle> {
+    let (raw_tx, mut raw_rx) = mpsc::unbounded_channel();
+    let ignore = options.ignore.clone();
+    let root = root_dir.to_path_buf();
+
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if let Ok(event) = res {
+            let relevant = event
+                .paths
+                .iter()
+                .any(|path| !is_ignored(path.strip_prefix(&root).unwrap_or(path), &ignore));
+            if relevant {
+                let _ = raw_tx.send(());
+            }
+        }
+    })
+    .context("Failed to create file watcher")?;
+
+    watcher
+        .watch(root_dir, RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {:?}", root_dir))?;
+
+    let debounce = Duration::from_millis(options.debounce_ms);
+    let task = tokio::spawn(async move {
+        while raw_rx.recv().await.is_some() {
+            // Wait for a quiet period so a burst of saves restarts only once
+            loop {
+                match tokio::time::timeout(debounce, raw_rx.recv()).await {
+                    Ok(Some(())) => continue,
+                    Ok(None) => return,
+                    Err(_) => break,
+                }
+            }
+            tracing::debug!("Files changed for process {}", process_id);
+            if event_tx
+                .send(ProcessEvent::FilesChanged { process_id })
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    });
+
+    tracing::info!("Watching {:?} for process {}", root_dir, process_id);
+    Ok(WatchHandle {
+        _watcher: watcher,
+        task,
+    })
+}
+
+/// Check whether any component of a (relative) path matches an ignore glob
+fn is_ignored(path: &Path, ignore: &[String]) -> bool {
+    path.components().any(|component| match component {
+        Component::Normal(name) => {
+            let name = name.to_string_lossy();
+            ignore.iter().any(|pattern| glob_match(pattern, &name))
+        }
+        _ => false,
+    })
+}
+
+/// Minimal glob matching supporting `*` (any run) and `?` (any char)
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let (mut star, mut mark) = (None, 0);
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some(p);
+            mark = n;
+            p += 1;
+        } else if let Some(s) = star {
+            p = s + 1;
+            mark += 1;
+            n = mark;
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_ignored() {
+        let ignore = vec!["node_modules".to_string(), "*.log".to_string()];
+        assert!(is_ignored(
+            Path::new("node_modules/react/index.js"),
+            &ignore
+        ));
+        assert!(is_ignored(Path::new("logs/debug.log"), &ignore));
+        assert!(!is_ignored(Path::new("src/main.rs"), &ignore));
+        assert!(glob_match("*.t?t", "notes.txt"));
+        assert!(!glob_match("*.txt", "notes.md"));
+    }
+}
@@ -0,0 +1,128 @@
+//! The daemon's event stream
+//!
+//! Events arrive as the daemon's `Subscribe` connection writes them. Each has
+//! a name (`route_added`, `process_exited`, ...) and fields depending on it,
+//! as listed under "Event Stream" in the README; the fields are JSON so that
+//! new ones don't break plugins.
+
+use chrono::{DateTime, Utc};
+use proj_common::transport::Connection;
+use proj_common::{EventFilters, IpcResponse};
+use serde_json::{Map, Value};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::{unexpected, Error, Result};
+
+/// Which events to receive; the default is all of them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventFilter {
+    project: Option<String>,
+    events: Vec<String>,
+}
+
+impl EventFilter {
+    /// Only events about this project
+    pub fn project(mut self, name: impl Into<String>) -> Self {
+        self.project = Some(name.into());
+        self
+    }
+
+    /// Events with this name, besides any added before
+    pub fn event(mut self, name: impl Into<String>) -> Self {
+        self.events.push(name.into());
+        self
+    }
+
+    pub(crate) fn into_filters(self) -> EventFilters {
+        EventFilters {
+            project_name: self.project,
+            events: self.events,
+        }
+    }
+}
+
+/// Something that happened in the daemon
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Event {
+    pub timestamp: DateTime<Utc>,
+    /// The project it's about, empty for the daemon itself
+    pub project: String,
+    /// What happened, e.g. `route_added`
+    pub name: String,
+    /// The rest of the event, e.g. `port`
+    pub fields: Map<String, Value>,
+}
+
+impl Event {
+    /// An event as the daemon writes it
+    fn from_json(mut json: Map<String, Value>) -> Result<Self> {
+        let mut take = |key: &str| match json.remove(key) {
+            Some(Value::String(value)) => Ok(value),
+            _ => Err(Error::Protocol(format!("event without {}", key))),
+        };
+        let timestamp = take("timestamp")?
+            .parse()
+            .map_err(|e: chrono::ParseError| Error::Protocol(e.to_string()))?;
+        let project = take("project_name")?;
+        let name = take("event")?;
+        Ok(Self {
+            timestamp,
+            project,
+            name,
+            fields: json,
+        })
+    }
+}
+
+/// Events as they happen, until the daemon stops
+pub struct Events {
+    stream: BufReader<Connection>,
+}
+
+impl Events {
+    pub(crate) fn new(stream: BufReader<Connection>) -> Self {
+        Self { stream }
+    }
+
+    /// The next event, or `None` once the daemon closes the stream
+    pub async fn next(&mut self) -> Result<Option<Event>> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let response: IpcResponse =
+            serde_json::from_str(&line).map_err(|e| Error::Protocol(e.to_string()))?;
+        let IpcResponse::Event(event) = response else {
+            return Err(unexpected(response));
+        };
+        match serde_json::to_value(event).map_err(|e| Error::Protocol(e.to_string()))? {
+            Value::Object(json) => Event::from_json(json).map(Some),
+            _ => Err(Error::Protocol("event isn't an object".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_from_json() {
+        let event = proj_common::Event {
+            timestamp: Utc::now(),
+            project_name: "web".to_string(),
+            kind: proj_common::EventKind::RouteAdded { port: 4000 },
+        };
+        let Value::Object(json) = serde_json::to_value(&event).unwrap() else {
+            panic!("not an object");
+        };
+        let event = Event::from_json(json).unwrap();
+        assert_eq!(
+            (event.project.as_str(), event.name.as_str()),
+            ("web", "route_added")
+        );
+        assert_eq!(event.fields["port"], 4000);
+        assert_eq!(event.fields.len(), 1);
+    }
+}
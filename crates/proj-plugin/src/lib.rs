@@ -0,0 +1,385 @@
+//! Stable API for proj plugins
+//!
+//! A `proj-<name>` executable on `PATH` runs as `proj <name>`. This crate
+//! gives one what it needs to work with the daemon: a [`Client`] that
+//! connects the way `proj` does, the project the user ran it in, the
+//! effective [`Config`], and the daemon's [`events`]. Its types are its own,
+//! versioned with this crate rather than with the binaries, so a plugin
+//! built against 0.1 keeps building as the daemon's internals change.
+//!
+//! ```no_run
+//! # async fn example() -> proj_plugin::Result<()> {
+//! let client = proj_plugin::Client::connect()?;
+//! if let Some(project) = client.current_project().await? {
+//!     println!("{} at {}", project.name, project.url);
+//! }
+//! let mut events = client
+//!     .events(proj_plugin::EventFilter::default().event("route_added"))
+//!     .await?;
+//! while let Some(event) = events.next().await? {
+//!     println!("{} {}", event.project, event.name);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod events;
+
+use proj_common::transport::{Connection, Ipc, Transport};
+use proj_common::{layers, IpcError, IpcRequest, IpcResponse, ProjectFilter};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+pub use events::{Event, EventFilter, Events};
+
+/// Why a call failed
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// Nothing listens where the daemon should be; `proj daemon` starts it
+    #[error("The proj daemon isn't running (start it with `proj daemon`)")]
+    DaemonNotRunning,
+    #[error("Project '{0}' not found")]
+    ProjectNotFound(String),
+    /// The daemon speaks a protocol this version of the crate doesn't
+    #[error(
+        "The daemon speaks protocol {daemon}, this plugin {oldest} to {plugin}; upgrade whichever is older"
+    )]
+    Incompatible {
+        daemon: u32,
+        oldest: u32,
+        plugin: u32,
+    },
+    /// The daemon turned the request down
+    #[error("{0}")]
+    Daemon(String),
+    #[error("Invalid configuration: {0}")]
+    Config(String),
+    #[error("Invalid response from the daemon: {0}")]
+    Protocol(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A project, as plugins see it
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Project {
+    pub name: String,
+    pub root_dir: PathBuf,
+    /// Where the proxy serves it, e.g. `http://my-app.localhost:8080`
+    pub url: String,
+    pub tags: Vec<String>,
+    /// Whether it has a running process
+    pub running: bool,
+}
+
+/// The settings plugins can rely on, resolved like `proj` resolves them
+/// (`~/.proj/config.json`, then `PROJ_<SETTING>` variables)
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Config {
+    pub proxy_port: u16,
+    /// Domain projects are served under
+    pub tld: String,
+    /// Port of the HTTPS listener, 0 when it's off
+    pub https_port: u16,
+    /// The daemon's TCP address, if remote control is on
+    pub listen_tcp: Option<String>,
+    inner: proj_common::Config,
+}
+
+impl Config {
+    /// URL the proxy serves a project at
+    pub fn url(&self, project_name: &str) -> String {
+        self.inner.url(project_name)
+    }
+}
+
+/// The effective configuration
+pub fn config() -> Result<Config> {
+    let config = layers::load(None, &[])
+        .map_err(|e| Error::Config(format!("{:#}", e)))?
+        .config;
+    Ok(Config {
+        proxy_port: config.proxy_port,
+        tld: config.tld.clone(),
+        https_port: config.https_port,
+        listen_tcp: config.listen_tcp.map(|addr| addr.to_string()),
+        inner: config,
+    })
+}
+
+/// Where the daemon listens
+#[derive(Debug, Clone)]
+enum Endpoint {
+    Local(PathBuf),
+    Remote { host: String, token: String },
+}
+
+/// A handle on the daemon. Each call opens its own connection, so a client
+/// can be shared and calls can run at once.
+#[derive(Debug, Clone)]
+pub struct Client {
+    endpoint: Endpoint,
+}
+
+impl Client {
+    /// The daemon `proj` ran this plugin against: `PROJ_HOST` (with the
+    /// configured `tcp_token`) if set, else `PROJ_SOCKET`, else the local
+    /// daemon's socket
+    pub fn connect() -> Result<Self> {
+        let var = |name| {
+            std::env::var(name)
+                .ok()
+                .filter(|value: &String| !value.is_empty())
+        };
+        let endpoint = match var("PROJ_HOST") {
+            Some(host) => {
+                let token = layers::load(None, &[])
+                    .map_err(|e| Error::Config(format!("{:#}", e)))?
+                    .config
+                    .tcp_token
+                    .ok_or_else(|| {
+                        Error::Config(format!("tcp_token is needed for the daemon at {}", host))
+                    })?;
+                Endpoint::Remote { host, token }
+            }
+            None => Endpoint::Local(match var("PROJ_SOCKET") {
+                Some(socket) => PathBuf::from(socket),
+                None => proj_common::socket_path().map_err(|e| Error::Config(e.to_string()))?,
+            }),
+        };
+        Ok(Self { endpoint })
+    }
+
+    /// The daemon listening at a socket (or named pipe)
+    pub fn at(socket: impl AsRef<Path>) -> Self {
+        Self {
+            endpoint: Endpoint::Local(socket.as_ref().to_path_buf()),
+        }
+    }
+
+    /// Check the daemon speaks a protocol this crate can talk to, from
+    /// [`proj_common::MIN_PROTOCOL_VERSION`] up to its own
+    pub async fn check_version(&self) -> Result<()> {
+        let response = self
+            .call(&IpcRequest::Hello {
+                protocol_version: proj_common::PROTOCOL_VERSION,
+                client_version: env!("CARGO_PKG_VERSION").to_string(),
+            })
+            .await;
+        let daemon = match response {
+            Ok(IpcResponse::Hello {
+                protocol_version, ..
+            }) => protocol_version,
+            // Daemons from before the handshake don't know it
+            Ok(_) | Err(Error::Daemon(_)) => 0,
+            Err(e) => return Err(e),
+        };
+        compatible(daemon)
+    }
+
+    /// Every project, by name
+    pub async fn projects(&self) -> Result<Vec<Project>> {
+        let projects = self.list_projects(false).await?;
+        let running: Vec<String> = self
+            .list_projects(true)
+            .await?
+            .into_iter()
+            .map(|project| project.name)
+            .collect();
+        let config = config()?;
+        Ok(projects
+            .into_iter()
+            .map(|project| {
+                let running = running.contains(&project.name);
+                to_project(project, running, &config)
+            })
+            .collect())
+    }
+
+    /// One project
+    pub async fn project(&self, name: &str) -> Result<Project> {
+        self.projects()
+            .await?
+            .into_iter()
+            .find(|project| project.name == name)
+            .ok_or_else(|| Error::ProjectNotFound(name.to_string()))
+    }
+
+    /// The project `proj` ran this plugin in (`PROJ_PROJECT`), else the one
+    /// whose root the current directory is in
+    pub async fn current_project(&self) -> Result<Option<Project>> {
+        let projects = self.projects().await?;
+        if let Ok(name) = std::env::var("PROJ_PROJECT") {
+            if !name.is_empty() {
+                return Ok(projects.into_iter().find(|project| project.name == name));
+            }
+        }
+        let cwd = std::env::current_dir()?;
+        Ok(projects
+            .into_iter()
+            .filter(|project| cwd.starts_with(&project.root_dir))
+            .max_by_key(|project| project.root_dir.components().count()))
+    }
+
+    /// Follow the daemon's events
+    pub async fn events(&self, filter: EventFilter) -> Result<Events> {
+        let request = IpcRequest::Subscribe {
+            filters: filter.into_filters(),
+        };
+        let mut stream = self.send(&request).await?;
+        match read_response(&mut stream).await? {
+            IpcResponse::Success { .. } => Ok(Events::new(stream)),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Send a request as its JSON and return the daemon's answer. This is
+    /// the daemon's wire protocol (see `proj schema ipc-request`), which
+    /// follows its protocol version rather than this crate's semver.
+    pub async fn request(&self, request: serde_json::Value) -> Result<serde_json::Value> {
+        let mut stream = self.open().await?;
+        write_line(&mut stream, &request.to_string()).await?;
+        let line = read_line(&mut stream).await?;
+        serde_json::from_str(&line).map_err(|e| Error::Protocol(e.to_string()))
+    }
+
+    async fn list_projects(&self, running: bool) -> Result<Vec<proj_common::Project>> {
+        let request = IpcRequest::ListProjects {
+            filter: ProjectFilter {
+                running,
+                ..Default::default()
+            },
+        };
+        match self.call(&request).await? {
+            IpcResponse::Projects(projects) => Ok(projects),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    async fn call(&self, request: &IpcRequest) -> Result<IpcResponse> {
+        let mut stream = self.send(request).await?;
+        match read_response(&mut stream).await? {
+            IpcResponse::Error(error) => Err(from_ipc(error)),
+            response => Ok(response),
+        }
+    }
+
+    async fn send(&self, request: &IpcRequest) -> Result<BufReader<Connection>> {
+        let mut stream = self.open().await?;
+        let json = serde_json::to_string(request).map_err(|e| Error::Protocol(e.to_string()))?;
+        write_line(&mut stream, &json).await?;
+        Ok(stream)
+    }
+
+    async fn open(&self) -> Result<BufReader<Connection>> {
+        match &self.endpoint {
+            Endpoint::Local(socket) => {
+                let stream = Ipc::connect(socket)
+                    .await
+                    .map_err(|_| Error::DaemonNotRunning)?;
+                Ok(BufReader::new(Box::new(stream) as Connection))
+            }
+            Endpoint::Remote { host, token } => {
+                let stream = tokio::net::TcpStream::connect(host)
+                    .await
+                    .map_err(|_| Error::DaemonNotRunning)?;
+                stream.set_nodelay(true)?;
+                let mut stream = BufReader::new(Box::new(stream) as Connection);
+                let json = serde_json::to_string(&IpcRequest::Authenticate {
+                    token: token.clone(),
+                })
+                .map_err(|e| Error::Protocol(e.to_string()))?;
+                write_line(&mut stream, &json).await?;
+                match read_response(&mut stream).await? {
+                    IpcResponse::Success { .. } => Ok(stream),
+                    IpcResponse::Error(error) => Err(from_ipc(error)),
+                    response => Err(unexpected(response)),
+                }
+            }
+        }
+    }
+}
+
+async fn write_line(stream: &mut BufReader<Connection>, line: &str) -> Result<()> {
+    let writer = stream.get_mut();
+    writer.write_all(line.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    Ok(())
+}
+
+async fn read_line(stream: &mut BufReader<Connection>) -> Result<String> {
+    let mut line = String::new();
+    if stream.read_line(&mut line).await? == 0 {
+        return Err(Error::Protocol("connection closed".to_string()));
+    }
+    Ok(line)
+}
+
+async fn read_response(stream: &mut BufReader<Connection>) -> Result<IpcResponse> {
+    let line = read_line(stream).await?;
+    serde_json::from_str(&line).map_err(|e| Error::Protocol(e.to_string()))
+}
+
+/// Whether a daemon speaking protocol `daemon` is in the range this crate
+/// can talk to
+fn compatible(daemon: u32) -> Result<()> {
+    let supported = proj_common::MIN_PROTOCOL_VERSION..=proj_common::PROTOCOL_VERSION;
+    if !supported.contains(&daemon) {
+        return Err(Error::Incompatible {
+            daemon,
+            oldest: *supported.start(),
+            plugin: *supported.end(),
+        });
+    }
+    Ok(())
+}
+
+fn unexpected(response: IpcResponse) -> Error {
+    match response {
+        IpcResponse::Error(error) => from_ipc(error),
+        response => Error::Protocol(format!("unexpected {:?}", response)),
+    }
+}
+
+fn from_ipc(error: IpcError) -> Error {
+    match error {
+        IpcError::ProjectNotFound { name } => Error::ProjectNotFound(name),
+        error => Error::Daemon(error.to_string()),
+    }
+}
+
+fn to_project(project: proj_common::Project, running: bool, config: &Config) -> Project {
+    Project {
+        url: config.url(&project.name),
+        name: project.name,
+        root_dir: project.root_dir,
+        tags: project.tags,
+        running,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proj_common::{MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+
+    #[test]
+    fn test_compatible() {
+        for daemon in MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION {
+            assert!(compatible(daemon).is_ok(), "protocol {daemon}");
+        }
+        // Daemons from before the handshake report 0
+        for daemon in [0, MIN_PROTOCOL_VERSION - 1, PROTOCOL_VERSION + 1] {
+            let error = compatible(daemon).unwrap_err();
+            assert!(
+                matches!(error, Error::Incompatible { daemon: d, .. } if d == daemon),
+                "{error}"
+            );
+        }
+    }
+}
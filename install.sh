@@ -34,10 +34,22 @@ detect_platform() {
 
     case "$OS" in
         darwin) OS="apple-darwin" ;;
-        linux) OS="unknown-linux-gnu" ;;
+        linux)
+            # Alpine and other musl distributions can't run glibc builds
+            if ldd --version 2>&1 | grep -qi musl || [ -f /etc/alpine-release ]; then
+                OS="unknown-linux-musl"
+            else
+                OS="unknown-linux-gnu"
+            fi
+            ;;
         *) error "Unsupported OS: $OS" ;;
     esac
 
+    # A shell translated by Rosetta reports x86_64 on Apple silicon
+    if [ "$OS" = "apple-darwin" ] && [ "$(sysctl -n sysctl.proc_translated 2>/dev/null)" = "1" ]; then
+        ARCH="arm64"
+    fi
+
     case "$ARCH" in
         x86_64) ARCH="x86_64" ;;
         arm64|aarch64) ARCH="aarch64" ;;